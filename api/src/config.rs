@@ -3,7 +3,7 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::fs;
 use std::io::{Error, ErrorKind, Result};
@@ -275,6 +275,25 @@ pub struct BackendConfigV2 {
     /// Configuration for local http proxy.
     #[serde(rename = "http-proxy")]
     pub http_proxy: Option<HttpProxyConfig>,
+    /// Configuration for fallback backends to read blobs from when this backend fails.
+    pub fallback: Option<FallbackConfig>,
+    /// Configuration to hedge slow reads with a replica backend.
+    pub hedge: Option<Box<HedgeConfig>>,
+    /// Minimum size in bytes of each read request sent to the backend, zero to disable it.
+    ///
+    /// Smaller reads are extended to an aligned range of this size, and the surplus data is kept
+    /// in memory for following reads of adjacent data, to reduce requests to request-billed
+    /// backends.
+    #[serde(default)]
+    pub min_read_size: u32,
+    /// Timeout in milliseconds for each read request sent to the backend, zero to disable it.
+    ///
+    /// Unlike the connection timeouts of network backends, it bounds the whole request including
+    /// connection setup, so a stalled connection fails the read instead of blocking it forever.
+    #[serde(default)]
+    pub read_timeout_ms: u64,
+    /// Configuration for blobs stored back to back in packed objects.
+    pub packed: Option<PackedConfig>,
 }
 
 impl BackendConfigV2 {
@@ -346,6 +365,22 @@ impl BackendConfigV2 {
             _ => return false,
         }
 
+        if let Some(fallback) = self.fallback.as_ref() {
+            if fallback.backends.iter().any(|b| !b.validate()) {
+                return false;
+            }
+        }
+        if let Some(hedge) = self.hedge.as_ref() {
+            if !hedge.replica.validate() {
+                return false;
+            }
+        }
+        if let Some(packed) = self.packed.as_ref() {
+            if !packed.validate() {
+                return false;
+            }
+        }
+
         true
     }
 
@@ -452,6 +487,91 @@ impl BackendConfigV2 {
     }
 }
 
+/// Configuration information for fallback storage backends.
+///
+/// Reads are served by the first available backend, trying the primary backend first and then
+/// the fallback backends in order. A backend failing with network errors is skipped for
+/// `cooldown_secs` seconds as long as other backends are available.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct FallbackConfig {
+    /// Time in seconds to skip a backend after it fails.
+    #[serde(default = "default_fallback_cooldown")]
+    pub cooldown_secs: u64,
+    /// Configuration for fallback backends serving the same blobs, in order of preference.
+    #[serde(default)]
+    pub backends: Vec<BackendConfigV2>,
+}
+
+/// Configuration information to hedge slow backend reads.
+///
+/// If a read from the primary backend hasn't completed within `delay_ms`, the same range is
+/// requested from the replica backend too, and the first successful response wins.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct HedgeConfig {
+    /// Delay in milliseconds before issuing the hedged read, such as the p95 read latency of the
+    /// primary backend.
+    #[serde(default = "default_hedge_delay")]
+    pub delay_ms: u64,
+    /// Configuration for the replica backend serving the same blobs.
+    pub replica: BackendConfigV2,
+}
+
+/// Configuration information for blobs stored back to back in packed objects.
+///
+/// Several small blobs may be stored in one backend object, and reads of them are mapped to the
+/// containing object. A read reaching the end of a blob also fetches up to `coalesce_size` bytes
+/// of the following blob in the same object by the same request, and keeps the surplus data in
+/// memory for the following blob, so prefetching adjacent blobs doesn't stop at blob boundaries.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PackedConfig {
+    /// Maximum size in bytes of the following blob to fetch together, zero to disable coalescing.
+    #[serde(default = "default_packed_coalesce_size")]
+    pub coalesce_size: u32,
+    /// Packed objects and blobs stored in them.
+    #[serde(default)]
+    pub objects: Vec<PackedObjectConfig>,
+}
+
+impl PackedConfig {
+    /// Validate the packed object configuration.
+    pub fn validate(&self) -> bool {
+        let mut blobs = HashSet::new();
+        for object in self.objects.iter() {
+            if object.object_id.is_empty() || object.blobs.is_empty() {
+                return false;
+            }
+            let mut offsets = HashSet::new();
+            for blob in object.blobs.iter() {
+                if !blobs.insert(blob.blob_id.as_str()) || !offsets.insert(blob.offset) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Configuration information for a packed object containing several blobs.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PackedObjectConfig {
+    /// Id of the backend object containing the blobs.
+    pub object_id: String,
+    /// Blobs stored in the object.
+    #[serde(default)]
+    pub blobs: Vec<PackedBlobConfig>,
+}
+
+/// Configuration information for a blob stored in a packed object.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PackedBlobConfig {
+    /// Id of the blob.
+    pub blob_id: String,
+    /// Offset of the blob data in the object, the blob ends at the start of the following blob
+    /// or at the end of the object.
+    #[serde(default)]
+    pub offset: u64,
+}
+
 /// Configuration information for localdisk storage backend.
 #[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct LocalDiskConfig {
@@ -511,6 +631,12 @@ pub struct OssConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Delays before retrying failed read requests.
+    #[serde(flatten)]
+    pub retry: RetryConfig,
+    /// Maximum size in bytes of a response body accepted from the server, 0 means no limit.
+    #[serde(default)]
+    pub max_response_size: u64,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
@@ -555,6 +681,12 @@ pub struct S3Config {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Delays before retrying failed read requests.
+    #[serde(flatten)]
+    pub retry: RetryConfig,
+    /// Maximum size in bytes of a response body accepted from the server, 0 means no limit.
+    #[serde(default)]
+    pub max_response_size: u64,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
@@ -584,6 +716,12 @@ pub struct HttpProxyConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Delays before retrying failed read requests.
+    #[serde(flatten)]
+    pub retry: RetryConfig,
+    /// Maximum size in bytes of a response body accepted from the server, 0 means no limit.
+    #[serde(default)]
+    pub max_response_size: u64,
     /// Enable HTTP proxy for the read request.
     #[serde(default)]
     pub proxy: ProxyConfig,
@@ -617,6 +755,12 @@ pub struct RegistryConfig {
     /// Retry count when read request failed.
     #[serde(default)]
     pub retry_limit: u8,
+    /// Delays before retrying failed read requests.
+    #[serde(flatten)]
+    pub retry: RetryConfig,
+    /// Maximum size in bytes of a response body accepted from the server, 0 means no limit.
+    #[serde(default)]
+    pub max_response_size: u64,
     /// The field is a bearer token to be sent to registry to authorize registry requests.
     #[serde(default)]
     pub registry_token: Option<String>,
@@ -633,6 +777,33 @@ pub struct RegistryConfig {
     /// Enable mirrors for the read request.
     #[serde(default)]
     pub mirrors: Vec<MirrorConfig>,
+    /// Maximum size in bytes of holes between chunks which may be fetched by one ranged read,
+    /// discarding data in the holes. 0 means only adjacent chunks are fetched together.
+    #[serde(default)]
+    pub range_coalesce_gap: u32,
+}
+
+/// Policy to handle chunk data failing digest validation.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DigestMismatchPolicy {
+    /// Fail the request if data fetched from the storage backend fails validation.
+    #[default]
+    FailFast,
+    /// Fetch data failing validation from the storage backend once more, bypassing the cache.
+    RefetchOnce,
+    /// Log and count the mismatch, and serve data failing validation anyway.
+    LogAndServe,
+}
+
+/// An entry of the prefetch deny list, to skip prefetching a blob or a range of its chunks.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PrefetchDenyEntry {
+    /// Id of the blob to skip.
+    pub blob_id: String,
+    /// Range `[start, end)` of chunk indexes to skip, the whole blob if not specified.
+    #[serde(default)]
+    pub chunk_range: Option<(u32, u32)>,
 }
 
 /// Configuration information for blob cache manager.
@@ -647,9 +818,42 @@ pub struct CacheConfigV2 {
     /// Whether to validate data read from the cache.
     #[serde(default, rename = "validate")]
     pub cache_validate: bool,
+    /// Whether to reject chunks without digest when validating data, instead of skipping
+    /// validation for them.
+    #[serde(default, rename = "validate_strict")]
+    pub cache_validate_strict: bool,
+    /// How to handle chunk data failing digest validation when `validate` is enabled.
+    #[serde(default)]
+    pub digest_mismatch_policy: DigestMismatchPolicy,
+    /// Maximum size in bytes of a backend request merged from contiguous chunks of user IO, zero
+    /// for no limit other than the user IO batch size.
+    #[serde(default)]
+    pub max_user_merge_size: u64,
+    /// Maximum size in bytes of decompressed data of a chunk, to reject chunks claiming a huge
+    /// decompressed size. Zero means four times the chunk size of the blob.
+    #[serde(default)]
+    pub max_decompress_size: u64,
+    /// Size in bytes of the memory tier caching decoded data of hot chunks, zero to disable it.
+    ///
+    /// Only used by the "dummy" cache, to avoid fetching hot chunks from the storage backend
+    /// again and again. The file cache configures its memory tier by
+    /// `file_cache.memory_tier_size`.
+    #[serde(default)]
+    pub memory_tier_size: u64,
     /// Configuration for blob level prefetch.
     #[serde(default)]
     pub prefetch: PrefetchConfigV2,
+    /// Blobs or chunk ranges of blobs which should never be prefetched.
+    ///
+    /// Prefetch requests matching an entry are silently skipped, data is only fetched on demand.
+    #[serde(default)]
+    pub prefetch_deny_list: Vec<PrefetchDenyEntry>,
+    /// Fill data of chunks beyond the end of their blob with zero instead of failing the read.
+    ///
+    /// Such chunks come from corrupted images, reads fail with an error describing the bad chunk
+    /// by default.
+    #[serde(default)]
+    pub zero_out_of_bounds_chunks: bool,
     /// Configuration information for file cache
     #[serde(rename = "filecache")]
     pub file_cache: Option<FileCacheConfig>,
@@ -759,6 +963,99 @@ pub struct FileCacheConfig {
     /// Key for data encryption, a heximal representation of [u8; 32].
     #[serde(default)]
     pub encryption_key: String,
+    /// Number of prefetched chunks to commit to the chunk map together, after syncing chunk data
+    /// to the cache file. Zero means committing each chunk once its data is written.
+    #[serde(default)]
+    pub chunk_map_batch_size: u32,
+    /// Maximum time in milliseconds to defer chunk map updates when batching is enabled.
+    #[serde(default = "default_chunk_map_batch_interval")]
+    pub chunk_map_batch_interval: u64,
+    /// Interval in seconds to sync chunk map files to disk, so states of cached chunks survive
+    /// host crashes. Zero means leaving it to writeback of the kernel.
+    #[serde(default)]
+    pub chunk_map_sync_interval: u64,
+    /// Mark blobs found truncated on the storage backend as degraded, so later reads beyond the
+    /// truncation point fail immediately without accessing the storage backend.
+    #[serde(default)]
+    pub degrade_truncated_blob: bool,
+    /// Assume the working directory is backed by memory, and serve reads from cache files by
+    /// memory mapping instead of `pread()`. It's detected automatically for tmpfs.
+    #[serde(default)]
+    pub memory_backed: bool,
+    /// Serve reads from cache files by memory mapping if they are no bigger than the threshold in
+    /// bytes and read often enough, to avoid a syscall for each read of small and hot blobs. Zero
+    /// to disable it.
+    #[serde(default)]
+    pub mmap_size_threshold: u64,
+    /// Number of reads from a cache file no bigger than `mmap_size_threshold` before memory
+    /// mapping it. Zero maps such cache files when opening them.
+    #[serde(default)]
+    pub mmap_read_threshold: u32,
+    /// Maximum ratio, in percentage, of bytes fetched from the storage backend to bytes actually
+    /// needed when merging or extending requests. Zero means no limit.
+    #[serde(default)]
+    pub read_amplification_limit: u32,
+    /// Switch to read-only passthrough mode, serving data from the storage backend without
+    /// caching, when the working directory becomes read-only instead of failing reads.
+    #[serde(default)]
+    pub readonly_fallback: bool,
+    /// Interval in seconds to probe whether the working directory becomes writable again after
+    /// switching to read-only passthrough mode.
+    #[serde(default = "default_readonly_probe_interval")]
+    pub readonly_probe_interval: u64,
+    /// Size in bytes of the memory tier caching decoded data of hot chunks, zero to disable it.
+    ///
+    /// The memory tier is shared by all blobs managed by the cache. Chunks needing decoding or
+    /// validation are looked up from the memory tier first, then from cache files, and finally
+    /// from the storage backend. Chunks fetched from lower tiers are promoted into the memory
+    /// tier.
+    #[serde(default)]
+    pub memory_tier_size: u64,
+    /// Maximum size in bytes of data cached in cache files, zero means no limit.
+    ///
+    /// Cached chunks get evicted according to `eviction_policy` when exceeding the limit.
+    #[serde(default)]
+    pub max_cache_size: u64,
+    /// Policy to select chunks to evict when exceeding `max_cache_size`, only "lru" for now.
+    #[serde(default = "default_eviction_policy")]
+    pub eviction_policy: String,
+    /// Priority classes of blobs keyed by blob id, zero for blobs not listed.
+    ///
+    /// Queued backend requests for blobs with higher priority are dispatched before those for
+    /// blobs with lower priority.
+    #[serde(default)]
+    pub blob_priorities: HashMap<String, u8>,
+    /// Count accesses to each chunk of blobs, to be retrieved as access heatmaps of blobs.
+    #[serde(default)]
+    pub enable_access_heatmap: bool,
+    /// Minimum number of chunks fetched by one backend request to decompress them in parallel,
+    /// zero to always decompress chunks serially.
+    #[serde(default)]
+    pub parallel_decompress_threshold: u32,
+    /// Number of threads to decompress chunks in parallel.
+    #[serde(default)]
+    pub parallel_decompress_threads: u32,
+    /// Number of chunks to read ahead into the cache when sequential user IO of a blob is
+    /// detected, zero to disable readahead.
+    ///
+    /// Readahead requests are handled by prefetch workers, so they only take effect while
+    /// prefetch is enabled.
+    #[serde(default)]
+    pub readahead_chunks: usize,
+    /// Verify cached chunks when opening cache files.
+    ///
+    /// A checksum of the whole cache file is saved when closing the cache file, and cached chunks
+    /// are only verified one by one if it doesn't match the cache file when opening it again.
+    #[serde(default)]
+    pub verify_on_startup: bool,
+    /// Align uncompressed chunk data in cache files to the boundary in bytes, which must be a
+    /// power of two, and zero means no alignment.
+    ///
+    /// Each chunk is stored in a slot of the chunk size rounded up to the boundary, and writes
+    /// are padded to the boundary, so writes to the underlying storage don't need
+    /// read-modify-write. It doesn't apply to cached compressed data.
+    #[serde(default)]
+    pub cache_block_align: u32,
 }
 
 impl FileCacheConfig {
@@ -870,6 +1167,19 @@ impl RafsConfigV2 {
     }
 }
 
+/// Order to prefetch chunks requested by the filesystem.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrefetchStrategy {
+    /// Prefetch chunks in the order they are stored in the blob.
+    #[default]
+    BlobLayout,
+    /// Prefetch chunks in the order they are requested, such as the order of an access trace.
+    AccessTrace,
+    /// Prefetch chunks needed to boot containers first: metadata, then executables, then data.
+    BootOptimized,
+}
+
 /// Configuration information for blob data prefetching.
 #[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct PrefetchConfigV2 {
@@ -884,9 +1194,36 @@ pub struct PrefetchConfigV2 {
     /// Network bandwidth rate limit in unit of Bytes and Zero means no limit.
     #[serde(default)]
     pub bandwidth_limit: u32,
+    /// Maximum bytes of bandwidth budget accumulated while prefetch is idle, and Zero means
+    /// ten seconds of `bandwidth_limit`.
+    #[serde(default)]
+    pub bandwidth_burst: u32,
+    /// Reduce the prefetch bandwidth by recent user IO throughput, only takes effect when
+    /// `bandwidth_limit` is set.
+    #[serde(default)]
+    pub adaptive_bandwidth: bool,
+    /// Minimum prefetch bandwidth in unit of Bytes when adapting to user IO throughput.
+    #[serde(default)]
+    pub min_bandwidth_limit: u32,
+    /// Seconds to ramp prefetch bandwidth up from a low rate to `bandwidth_limit` after prefetch
+    /// starts, only takes effect when `bandwidth_limit` is set, and Zero means no ramp-up.
+    #[serde(default)]
+    pub ramp_up_secs: u32,
+    /// Maximum bytes of prefetch data requested but not cached yet, and Zero means no limit.
+    #[serde(default)]
+    pub prefetch_inflight_bytes: u32,
     /// Prefetch all data from backend.
     #[serde(default)]
     pub prefetch_all: bool,
+    /// Name of the extended attribute marking files to be prefetched, empty to disable.
+    #[serde(default)]
+    pub hint_xattr: String,
+    /// Path of a recorded chunk access trace to prefetch chunks in access order, empty to disable.
+    #[serde(default)]
+    pub trace_file: String,
+    /// Order to prefetch chunks requested by the filesystem.
+    #[serde(default)]
+    pub strategy: PrefetchStrategy,
 }
 
 /// Configuration information for network proxy.
@@ -925,6 +1262,26 @@ impl Default for ProxyConfig {
     }
 }
 
+/// Configuration for delays before retrying failed requests to storage backends.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct RetryConfig {
+    /// Base delay in milliseconds before retrying a failed read request, doubled for each retry.
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay_ms: u64,
+    /// Maximum delay in milliseconds before retrying a failed read request, 0 means no limit.
+    #[serde(default)]
+    pub retry_max_delay_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            retry_delay_ms: 500,
+            retry_max_delay_ms: 0,
+        }
+    }
+}
+
 /// Configuration for registry mirror.
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct MirrorConfig {
@@ -1195,6 +1552,10 @@ fn default_http_timeout() -> u32 {
     5
 }
 
+fn default_retry_delay() -> u64 {
+    500
+}
+
 fn default_check_interval() -> u64 {
     5
 }
@@ -1207,6 +1568,30 @@ fn default_failure_limit() -> u8 {
     5
 }
 
+fn default_chunk_map_batch_interval() -> u64 {
+    100
+}
+
+fn default_readonly_probe_interval() -> u64 {
+    30
+}
+
+fn default_hedge_delay() -> u64 {
+    100
+}
+
+fn default_fallback_cooldown() -> u64 {
+    30
+}
+
+fn default_packed_coalesce_size() -> u32 {
+    0x100000
+}
+
+fn default_eviction_policy() -> String {
+    "lru".to_string()
+}
+
 fn default_work_dir() -> String {
     ".".to_string()
 }
@@ -1259,6 +1644,11 @@ impl TryFrom<&BackendConfig> for BackendConfigV2 {
             s3: None,
             registry: None,
             http_proxy: None,
+            fallback: None,
+            hedge: None,
+            min_read_size: 0,
+            read_timeout_ms: 0,
+            packed: None,
         };
 
         match value.backend_type.as_str() {
@@ -1317,7 +1707,14 @@ impl TryFrom<&CacheConfig> for CacheConfigV2 {
             cache_type: v.cache_type.clone(),
             cache_compressed: v.cache_compressed,
             cache_validate: v.cache_validate,
+            cache_validate_strict: false,
+            digest_mismatch_policy: DigestMismatchPolicy::default(),
+            max_user_merge_size: 0,
+            max_decompress_size: 0,
+            memory_tier_size: 0,
             prefetch: (&v.prefetch_config).into(),
+            prefetch_deny_list: Vec::new(),
+            zero_out_of_bounds_chunks: false,
             file_cache: None,
             fs_cache: None,
         };
@@ -1456,7 +1853,15 @@ impl From<FsPrefetchControl> for PrefetchConfigV2 {
             threads_count: v.threads_count,
             batch_size: v.batch_size,
             bandwidth_limit: v.bandwidth_limit,
+            bandwidth_burst: 0,
+            adaptive_bandwidth: false,
+            min_bandwidth_limit: 0,
+            ramp_up_secs: 0,
+            prefetch_inflight_bytes: 0,
             prefetch_all: v.prefetch_all,
+            hint_xattr: String::new(),
+            trace_file: String::new(),
+            strategy: PrefetchStrategy::default(),
         }
     }
 }
@@ -1483,7 +1888,15 @@ impl From<&BlobPrefetchConfig> for PrefetchConfigV2 {
             threads_count: v.threads_count,
             batch_size: v.batch_size,
             bandwidth_limit: v.bandwidth_limit,
+            bandwidth_burst: 0,
+            adaptive_bandwidth: false,
+            min_bandwidth_limit: 0,
+            ramp_up_secs: 0,
+            prefetch_inflight_bytes: 0,
             prefetch_all: true,
+            hint_xattr: String::new(),
+            trace_file: String::new(),
+            strategy: PrefetchStrategy::default(),
         }
     }
 }
@@ -1601,6 +2014,71 @@ mod tests {
         assert!(config.get_work_dir().is_err());
     }
 
+    #[test]
+    fn test_file_cache_chunk_map_batch_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.chunk_map_batch_size, 0);
+        assert_eq!(config.chunk_map_batch_interval, 100);
+
+        let config: FileCacheConfig =
+            serde_json::from_str("{\"chunk_map_batch_size\":64,\"chunk_map_batch_interval\":500}")
+                .unwrap();
+        assert_eq!(config.chunk_map_batch_size, 64);
+        assert_eq!(config.chunk_map_batch_interval, 500);
+    }
+
+    #[test]
+    fn test_file_cache_degrade_truncated_blob_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert!(!config.degrade_truncated_blob);
+
+        let config: FileCacheConfig =
+            serde_json::from_str("{\"degrade_truncated_blob\":true}").unwrap();
+        assert!(config.degrade_truncated_blob);
+    }
+
+    #[test]
+    fn test_file_cache_readonly_fallback_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert!(!config.readonly_fallback);
+        assert_eq!(config.readonly_probe_interval, 30);
+
+        let config: FileCacheConfig =
+            serde_json::from_str("{\"readonly_fallback\":true,\"readonly_probe_interval\":5}")
+                .unwrap();
+        assert!(config.readonly_fallback);
+        assert_eq!(config.readonly_probe_interval, 5);
+    }
+
+    #[test]
+    fn test_file_cache_memory_tier_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.memory_tier_size, 0);
+
+        let config: FileCacheConfig =
+            serde_json::from_str("{\"memory_tier_size\":1048576}").unwrap();
+        assert_eq!(config.memory_tier_size, 0x100000);
+    }
+
+    #[test]
+    fn test_file_cache_chunk_map_sync_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.chunk_map_sync_interval, 0);
+
+        let config: FileCacheConfig =
+            serde_json::from_str("{\"chunk_map_sync_interval\":1000}").unwrap();
+        assert_eq!(config.chunk_map_sync_interval, 1000);
+    }
+
+    #[test]
+    fn test_file_cache_block_align_config() {
+        let config: FileCacheConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.cache_block_align, 0);
+
+        let config: FileCacheConfig = serde_json::from_str("{\"cache_block_align\":4096}").unwrap();
+        assert_eq!(config.cache_block_align, 4096);
+    }
+
     #[test]
     fn test_fs_cache_config() {
         let config: FsCacheConfig = serde_json::from_str("{}").unwrap();
@@ -1750,6 +2228,30 @@ mod tests {
         assert!(config.skip_verify);
     }
 
+    #[test]
+    fn test_backend_retry_config() {
+        let content = r#"{
+            "host": "my-registry:5000",
+            "repo": "test/repo",
+            "retry_limit": 3,
+            "retry_delay_ms": 100,
+            "retry_max_delay_ms": 2000
+        }"#;
+        let config: RegistryConfig = serde_json::from_str(content).unwrap();
+        assert_eq!(config.retry_limit, 3);
+        assert_eq!(config.retry.retry_delay_ms, 100);
+        assert_eq!(config.retry.retry_max_delay_ms, 2000);
+
+        let content = r#"{
+            "endpoint": "endpoint",
+            "bucket_name": "bucket_name"
+        }"#;
+        let config: OssConfig = serde_json::from_str(content).unwrap();
+        assert_eq!(config.retry, RetryConfig::default());
+        assert_eq!(config.retry.retry_delay_ms, 500);
+        assert_eq!(config.retry.retry_max_delay_ms, 0);
+    }
+
     #[test]
     fn test_localfs_config() {
         let content = r#"{
@@ -2002,6 +2504,94 @@ mod tests {
         assert_eq!(prefetch.bandwidth_limit, 10000000);
     }
 
+    #[test]
+    fn test_v2_cache_digest_mismatch_policy() {
+        let content = r#"version=2
+        [cache]
+        type = "filecache"
+        digest_mismatch_policy = "refetch_once"
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert_eq!(
+            cache.digest_mismatch_policy,
+            DigestMismatchPolicy::RefetchOnce
+        );
+
+        let config: ConfigV2 = toml::from_str("version=2\n[cache]\ntype = \"filecache\"").unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert_eq!(cache.digest_mismatch_policy, DigestMismatchPolicy::FailFast);
+    }
+
+    #[test]
+    fn test_v2_cache_prefetch_strategy() {
+        let content = r#"version=2
+        [cache]
+        type = "filecache"
+        [cache.prefetch]
+        enable = true
+        strategy = "boot_optimized"
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert_eq!(cache.prefetch.strategy, PrefetchStrategy::BootOptimized);
+
+        let config: ConfigV2 = toml::from_str("version=2\n[cache]\ntype = \"filecache\"").unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert_eq!(cache.prefetch.strategy, PrefetchStrategy::BlobLayout);
+    }
+
+    #[test]
+    fn test_v2_cache_max_user_merge_size() {
+        let content = r#"version=2
+        [cache]
+        type = "filecache"
+        max_user_merge_size = 262144
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert_eq!(cache.max_user_merge_size, 0x40000);
+
+        let config: ConfigV2 = toml::from_str("version=2\n[cache]\ntype = \"filecache\"").unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert_eq!(cache.max_user_merge_size, 0);
+    }
+
+    #[test]
+    fn test_v2_cache_prefetch_deny_list() {
+        let content = r#"version=2
+        [cache]
+        type = "filecache"
+        prefetch_deny_list = [{ blob_id = "blob1" }, { blob_id = "blob2", chunk_range = [1, 3] }]
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert_eq!(cache.prefetch_deny_list.len(), 2);
+        assert_eq!(&cache.prefetch_deny_list[0].blob_id, "blob1");
+        assert!(cache.prefetch_deny_list[0].chunk_range.is_none());
+        assert_eq!(cache.prefetch_deny_list[1].chunk_range, Some((1, 3)));
+
+        let config: ConfigV2 = toml::from_str("version=2\n[cache]\ntype = \"filecache\"").unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert!(cache.prefetch_deny_list.is_empty());
+    }
+
+    #[test]
+    fn test_v2_cache_zero_out_of_bounds_chunks() {
+        let content = r#"version=2
+        [cache]
+        type = "filecache"
+        zero_out_of_bounds_chunks = true
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert!(cache.zero_out_of_bounds_chunks);
+
+        let config: ConfigV2 = toml::from_str("version=2\n[cache]\ntype = \"filecache\"").unwrap();
+        let cache = config.cache.as_ref().unwrap();
+        assert!(!cache.zero_out_of_bounds_chunks);
+    }
+
     #[test]
     fn test_v2_rafs() {
         let content = r#"version=2
@@ -2041,6 +2631,70 @@ mod tests {
         assert!(rafs.prefetch.prefetch_all)
     }
 
+    #[test]
+    fn test_v2_rafs_prefetch_hint_xattr() {
+        let content = r#"version=2
+        [rafs.prefetch]
+        enable = true
+        hint_xattr = "user.nydus.prefetch"
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(&rafs.prefetch.hint_xattr, "user.nydus.prefetch");
+
+        let config: ConfigV2 = toml::from_str("version=2\n[rafs.prefetch]\nenable = true").unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(&rafs.prefetch.hint_xattr, "");
+    }
+
+    #[test]
+    fn test_v2_rafs_prefetch_inflight_bytes() {
+        let content = r#"version=2
+        [rafs.prefetch]
+        enable = true
+        prefetch_inflight_bytes = 4194304
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(rafs.prefetch.prefetch_inflight_bytes, 4194304);
+
+        let config: ConfigV2 = toml::from_str("version=2\n[rafs.prefetch]\nenable = true").unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(rafs.prefetch.prefetch_inflight_bytes, 0);
+    }
+
+    #[test]
+    fn test_v2_rafs_prefetch_trace_file() {
+        let content = r#"version=2
+        [rafs.prefetch]
+        enable = true
+        trace_file = "/var/lib/nydus/trace.json"
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(&rafs.prefetch.trace_file, "/var/lib/nydus/trace.json");
+
+        let config: ConfigV2 = toml::from_str("version=2\n[rafs.prefetch]\nenable = true").unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(&rafs.prefetch.trace_file, "");
+    }
+
+    #[test]
+    fn test_v2_rafs_prefetch_ramp_up() {
+        let content = r#"version=2
+        [rafs.prefetch]
+        enable = true
+        ramp_up_secs = 30
+        "#;
+        let config: ConfigV2 = toml::from_str(content).unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(rafs.prefetch.ramp_up_secs, 30);
+
+        let config: ConfigV2 = toml::from_str("version=2\n[rafs.prefetch]\nenable = true").unwrap();
+        let rafs = config.rafs.as_ref().unwrap();
+        assert_eq!(rafs.prefetch.ramp_up_secs, 0);
+    }
+
     #[test]
     fn test_v2_blob_cache_entry() {
         let content = r#"version=2
@@ -2302,6 +2956,33 @@ mod tests {
         assert!(!cfg.validate());
     }
 
+    #[test]
+    fn test_packed_config() {
+        let content = r#"{
+            "type": "localfs",
+            "localfs": { "dir": "/tmp" },
+            "packed": {
+                "objects": [
+                    {
+                        "object_id": "object",
+                        "blobs": [
+                            { "blob_id": "blob1" },
+                            { "blob_id": "blob2", "offset": 4096 }
+                        ]
+                    }
+                ]
+            }
+        }"#;
+        let mut config: BackendConfigV2 = serde_json::from_str(content).unwrap();
+        assert!(config.validate());
+        let packed = config.packed.as_mut().unwrap();
+        assert_eq!(packed.coalesce_size, 0x100000);
+        assert_eq!(packed.objects[0].blobs[1].offset, 4096);
+
+        packed.objects[0].blobs[1].offset = 0;
+        assert!(!config.validate());
+    }
+
     fn get_config(backend_type: &str) {
         let mut cfg: BackendConfigV2 = BackendConfigV2::default();
         assert!(cfg.get_localdisk_config().is_err());
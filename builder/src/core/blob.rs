@@ -94,7 +94,7 @@ impl Blob {
         Ok(())
     }
 
-    fn finalize_blob_data(
+    pub(crate) fn finalize_blob_data(
         ctx: &BuildContext,
         blob_mgr: &mut BlobManager,
         blob_writer: &mut dyn Artifact,
@@ -104,11 +104,12 @@ impl Blob {
             if let Some((_, blob_ctx)) = blob_mgr.get_current_blob() {
                 let mut batch = batch.lock().unwrap();
                 if !batch.chunk_data_buf_is_empty() {
+                    let data = batch.chunk_data_buf();
                     let (_, compressed_size, _) = Node::write_chunk_data(
-                        &ctx,
                         blob_ctx,
                         blob_writer,
-                        batch.chunk_data_buf(),
+                        data,
+                        ctx.chunk_compressor(data, true),
                     )?;
                     batch.add_context(compressed_size);
                     batch.clear_chunk_data_buf();
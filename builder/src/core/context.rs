@@ -6,8 +6,9 @@
 
 use std::any::Any;
 use std::borrow::Cow;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
+use std::ffi::OsStr;
 use std::fs::{remove_file, rename, File, OpenOptions};
 use std::io::{BufWriter, Cursor, Read, Seek, Write};
 use std::mem::size_of;
@@ -19,6 +20,7 @@ use std::{fmt, fs};
 
 use anyhow::{anyhow, Context, Error, Result};
 use nydus_utils::crypt::{self, Cipher, CipherContext};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tar::{EntryType, Header};
 use vmm_sys_util::tempfile::TempFile;
@@ -31,7 +33,7 @@ use nydus_rafs::metadata::layout::v6::{
 };
 use nydus_rafs::metadata::layout::RafsBlobTable;
 use nydus_rafs::metadata::{Inode, RAFS_DEFAULT_CHUNK_SIZE};
-use nydus_rafs::metadata::{RafsSuperFlags, RafsVersion};
+use nydus_rafs::metadata::{RafsSuper, RafsSuperFlags, RafsVersion};
 use nydus_rafs::RafsIoWrite;
 use nydus_storage::device::{BlobFeatures, BlobInfo};
 use nydus_storage::factory::BlobFactory;
@@ -492,6 +494,8 @@ pub struct BlobContext {
     pub chunk_size: u32,
     /// Whether the blob is from chunk dict.
     pub chunk_source: ChunkSource,
+    /// Generation number recorded in the blob meta for chunks, zero means none.
+    pub chunk_generation: u32,
 
     // SHA256 digest of blob ToC content, including the toc tar header.
     // It's all zero for blobs with inlined-meta.
@@ -550,6 +554,7 @@ impl BlobContext {
             chunk_count: 0,
             chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
             chunk_source: ChunkSource::Build,
+            chunk_generation: 0,
 
             blob_toc_digest: [0u8; 32],
             blob_meta_digest: [0u8; 32],
@@ -769,6 +774,11 @@ impl BlobContext {
             assert_eq!(chunk.index() as usize, self.blob_meta_info.len());
             match &self.blob_meta_info {
                 BlobMetaChunkArray::V1(_) => {
+                    if chunk.is_lz4_block() {
+                        return Err(anyhow!(
+                            "chunk info v1 can't record chunks compressed by lz4_block"
+                        ));
+                    }
                     self.blob_meta_info.add_v1(
                         chunk.compressed_offset(),
                         chunk.compressed_size(),
@@ -789,8 +799,13 @@ impl BlobContext {
                             chunk.uncompressed_size(),
                             chunk.is_compressed(),
                             chunk.is_encrypted(),
+                            chunk.is_lz4_block(),
                             chunk.is_batch(),
-                            0,
+                            if chunk.is_batch() {
+                                0
+                            } else {
+                                self.chunk_generation as u64
+                            },
                         );
                     }
                     self.blob_chunk_digest.push(chunk.id().data);
@@ -928,6 +943,7 @@ impl BlobManager {
             cipher_ctx,
         );
         blob_ctx.set_chunk_size(ctx.chunk_size);
+        blob_ctx.chunk_generation = ctx.chunk_generation;
         blob_ctx.set_meta_info_enabled(
             ctx.fs_version == RafsVersion::V6 && ctx.conversion_type != ConversionType::TarToTarfs,
         );
@@ -1030,6 +1046,97 @@ impl BlobManager {
         self.blobs.get(idx)
     }
 
+    /// Sort blobs by blob id, and return the new index of each blob indexed by its old index.
+    pub fn sort_by_blob_id(&mut self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.blobs.len()).collect();
+        order.sort_by(|a, b| self.blobs[*a].blob_id.cmp(&self.blobs[*b].blob_id));
+        let mut new_index = vec![0; order.len()];
+        for (idx, old_idx) in order.iter().enumerate() {
+            new_index[*old_idx] = idx;
+        }
+
+        let mut blobs: Vec<Option<BlobContext>> = std::mem::take(&mut self.blobs)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.blobs = order.iter().filter_map(|idx| blobs[*idx].take()).collect();
+        if let Some(idx) = self.current_blob_index {
+            self.current_blob_index = Some(new_index[idx as usize] as u32);
+        }
+
+        new_index
+    }
+
+    /// Collapse blobs with identical content into the first one of them, and return the new index
+    /// of each blob indexed by its old index.
+    ///
+    /// Blobs are considered identical if they have the same non-zero blob toc digest or blob meta
+    /// digest, even if their blob ids are different.
+    pub fn dedup_by_digest(&mut self) -> Vec<usize> {
+        let mut toc_digests = HashMap::new();
+        let mut meta_digests = HashMap::new();
+        let mut new_index = Vec::with_capacity(self.blobs.len());
+        let mut blobs = Vec::with_capacity(self.blobs.len());
+
+        for blob in std::mem::take(&mut self.blobs) {
+            let has_toc = blob.blob_toc_digest != [0u8; 32];
+            let has_meta = blob.blob_meta_digest != [0u8; 32];
+            let dup = has_toc
+                .then(|| toc_digests.get(&blob.blob_toc_digest))
+                .flatten()
+                .or_else(|| {
+                    has_meta
+                        .then(|| meta_digests.get(&blob.blob_meta_digest))
+                        .flatten()
+                })
+                .copied();
+            match dup {
+                Some(idx) => new_index.push(idx),
+                None => {
+                    let idx = blobs.len();
+                    if has_toc {
+                        toc_digests.insert(blob.blob_toc_digest, idx);
+                    }
+                    if has_meta {
+                        meta_digests.insert(blob.blob_meta_digest, idx);
+                    }
+                    new_index.push(idx);
+                    blobs.push(blob);
+                }
+            }
+        }
+
+        self.blobs = blobs;
+        if let Some(idx) = self.current_blob_index {
+            self.current_blob_index = Some(new_index[idx as usize] as u32);
+        }
+
+        new_index
+    }
+
+    /// Drop blobs not in `referenced`, and return the new index of each blob indexed by its old
+    /// index, or `None` if the blob has been dropped.
+    pub fn retain_blobs(&mut self, referenced: &HashSet<usize>) -> Vec<Option<usize>> {
+        let mut new_index = Vec::with_capacity(self.blobs.len());
+        let mut blobs = Vec::with_capacity(referenced.len());
+        for (idx, blob) in std::mem::take(&mut self.blobs).into_iter().enumerate() {
+            if referenced.contains(&idx) {
+                new_index.push(Some(blobs.len()));
+                blobs.push(blob);
+            } else {
+                new_index.push(None);
+            }
+        }
+
+        self.blobs = blobs;
+        self.current_blob_index = self
+            .current_blob_index
+            .and_then(|idx| new_index[idx as usize])
+            .map(|idx| idx as u32);
+
+        new_index
+    }
+
     pub fn take_blob(&mut self, idx: usize) -> BlobContext {
         self.blobs.remove(idx)
     }
@@ -1164,7 +1271,7 @@ pub struct BootstrapContext {
     /// This build has a parent bootstrap.
     pub layered: bool,
     /// Cache node index for hardlinks, HashMap<(layer_index, real_inode, dev), Vec<TreeNode>>.
-    pub(crate) inode_map: HashMap<(u16, Inode, u64), Vec<TreeNode>>,
+    pub(crate) inode_map: HashMap<(u32, Inode, u64), Vec<TreeNode>>,
     /// Current position to write in f_bootstrap
     pub(crate) offset: u64,
     pub(crate) writer: Box<dyn RafsIoWrite>,
@@ -1285,6 +1392,11 @@ pub struct BuildContext {
     pub blob_offset: u64,
     /// Blob chunk compress flag.
     pub compressor: compress::Algorithm,
+    /// Select the compressor per chunk by estimating the entropy of chunk data, storing
+    /// incompressible chunks as is and compressing low ratio chunks with lz4_block.
+    pub compressor_auto: bool,
+    /// Entropy in bits per byte above which chunk data is considered incompressible.
+    pub compressor_auto_threshold: f64,
     /// Inode and chunk digest algorithm flag.
     pub digester: digest::Algorithm,
     /// Blob encryption algorithm flag.
@@ -1297,6 +1409,9 @@ pub struct BuildContext {
     pub chunk_size: u32,
     /// Batch chunk data size.
     pub batch_size: u32,
+    /// Generation number recorded in the blob meta for chunks, to invalidate stale cached data
+    /// of blobs rebuilt in place.
+    pub chunk_generation: u32,
     /// Version number of output metadata and data blob.
     pub fs_version: RafsVersion,
     /// Whether any directory/file has extended attributes.
@@ -1327,6 +1442,22 @@ pub struct BuildContext {
 
     /// Whether is chunkdict.
     pub is_chunkdict_generated: bool,
+    /// Keep each source layer's data blob as a distinct OCI layer when merging bootstraps.
+    pub preserve_layers: bool,
+    /// Normalize the merged bootstrap so merging identical sources yields identical bootstraps.
+    pub deterministic_merge: bool,
+    /// Re-chunk data of source layers with a different chunk size when merging bootstraps.
+    pub merge_rechunk: bool,
+    /// Collapse identical data blobs with different blob ids when merging bootstraps.
+    pub merge_dedup_blobs: bool,
+    /// Drop data blobs not referenced by any chunk of the merged tree when merging bootstraps.
+    pub merge_gc_blobs: bool,
+    /// Maximum size of extended attributes of an inode in merged bootstraps, zero means no limit.
+    pub merge_xattr_size_limit: u64,
+    /// Only check and plan merging of bootstraps, without writing the merged bootstrap.
+    pub merge_dry_run: bool,
+    /// Record provenance of merged bootstraps, with builder identity and tool version filled.
+    pub merge_provenance: Option<MergeProvenance>,
 }
 
 impl BuildContext {
@@ -1371,6 +1502,8 @@ impl BuildContext {
             aligned_chunk,
             blob_offset,
             compressor,
+            compressor_auto: false,
+            compressor_auto_threshold: compress::INCOMPRESSIBLE_ENTROPY_THRESHOLD,
             digester,
             cipher,
             explicit_uidgid,
@@ -1378,6 +1511,7 @@ impl BuildContext {
 
             chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
             batch_size: 0,
+            chunk_generation: 0,
             fs_version: RafsVersion::default(),
 
             conversion_type,
@@ -1396,6 +1530,14 @@ impl BuildContext {
             configuration: Arc::new(ConfigV2::default()),
             blob_cache_generator: None,
             is_chunkdict_generated: false,
+            preserve_layers: false,
+            deterministic_merge: false,
+            merge_rechunk: false,
+            merge_dedup_blobs: false,
+            merge_gc_blobs: false,
+            merge_xattr_size_limit: 0,
+            merge_dry_run: false,
+            merge_provenance: None,
         }
     }
 
@@ -1411,6 +1553,32 @@ impl BuildContext {
         self.batch_size = batch_size;
     }
 
+    pub fn set_chunk_generation(&mut self, generation: u32) {
+        self.chunk_generation = generation;
+    }
+
+    pub fn set_compressor_auto(&mut self, auto: bool) {
+        self.compressor_auto = auto;
+    }
+
+    pub fn set_compressor_auto_threshold(&mut self, threshold: f64) {
+        self.compressor_auto_threshold = threshold;
+    }
+
+    /// Select the algorithm to compress a data chunk.
+    ///
+    /// Chunks merged into a batch chunk are compressed together with the blob compressor, so
+    /// `lz4_block` is only selected for standalone chunks.
+    pub fn chunk_compressor(&self, data: &[u8], batch: bool) -> compress::Algorithm {
+        if !self.compressor_auto {
+            return self.compressor;
+        }
+        match compress::select_algorithm(data, self.compressor, self.compressor_auto_threshold) {
+            compress::Algorithm::Lz4Block if batch => self.compressor,
+            algorithm => algorithm,
+        }
+    }
+
     pub fn set_configuration(&mut self, config: Arc<ConfigV2>) {
         self.configuration = config;
     }
@@ -1418,6 +1586,38 @@ impl BuildContext {
     pub fn set_is_chunkdict(&mut self, is_chunkdict: bool) {
         self.is_chunkdict_generated = is_chunkdict;
     }
+
+    pub fn set_preserve_layers(&mut self, preserve_layers: bool) {
+        self.preserve_layers = preserve_layers;
+    }
+
+    pub fn set_deterministic_merge(&mut self, deterministic_merge: bool) {
+        self.deterministic_merge = deterministic_merge;
+    }
+
+    pub fn set_merge_rechunk(&mut self, merge_rechunk: bool) {
+        self.merge_rechunk = merge_rechunk;
+    }
+
+    pub fn set_merge_dedup_blobs(&mut self, merge_dedup_blobs: bool) {
+        self.merge_dedup_blobs = merge_dedup_blobs;
+    }
+
+    pub fn set_merge_gc_blobs(&mut self, merge_gc_blobs: bool) {
+        self.merge_gc_blobs = merge_gc_blobs;
+    }
+
+    pub fn set_merge_xattr_size_limit(&mut self, limit: u64) {
+        self.merge_xattr_size_limit = limit;
+    }
+
+    pub fn set_merge_dry_run(&mut self, merge_dry_run: bool) {
+        self.merge_dry_run = merge_dry_run;
+    }
+
+    pub fn set_merge_provenance(&mut self, builder: &str, tool_version: &str) {
+        self.merge_provenance = Some(MergeProvenance::new(builder, tool_version));
+    }
 }
 
 impl Default for BuildContext {
@@ -1427,6 +1627,8 @@ impl Default for BuildContext {
             aligned_chunk: false,
             blob_offset: 0,
             compressor: compress::Algorithm::default(),
+            compressor_auto: false,
+            compressor_auto_threshold: compress::INCOMPRESSIBLE_ENTROPY_THRESHOLD,
             digester: digest::Algorithm::default(),
             cipher: crypt::Algorithm::None,
             explicit_uidgid: true,
@@ -1434,6 +1636,7 @@ impl Default for BuildContext {
 
             chunk_size: RAFS_DEFAULT_CHUNK_SIZE as u32,
             batch_size: 0,
+            chunk_generation: 0,
             fs_version: RafsVersion::default(),
 
             conversion_type: ConversionType::default(),
@@ -1451,6 +1654,101 @@ impl Default for BuildContext {
             configuration: Arc::new(ConfigV2::default()),
             blob_cache_generator: None,
             is_chunkdict_generated: false,
+            preserve_layers: false,
+            deterministic_merge: false,
+            merge_rechunk: false,
+            merge_dedup_blobs: false,
+            merge_gc_blobs: false,
+            merge_xattr_size_limit: 0,
+            merge_dry_run: false,
+            merge_provenance: None,
+        }
+    }
+}
+
+/// OCI media type for RAFS data blobs.
+pub const MEDIA_TYPE_NYDUS_BLOB: &str = "application/vnd.oci.image.layer.nydus.blob.v1";
+
+/// OCI layer descriptor for a RAFS data blob.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BlobLayerDescriptor {
+    /// Media type of the layer.
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    /// Digest of the layer, in form of `sha256:<blob_id>`.
+    pub digest: String,
+    /// Size of the layer.
+    pub size: u64,
+}
+
+impl BlobLayerDescriptor {
+    /// Create an OCI layer descriptor for a data blob.
+    pub fn new(blob_id: &str, size: u64) -> Self {
+        BlobLayerDescriptor {
+            media_type: MEDIA_TYPE_NYDUS_BLOB.to_string(),
+            digest: format!("sha256:{}", blob_id),
+            size,
+        }
+    }
+}
+
+/// Provenance record of a merged bootstrap, stored in the bootstrap as an extended attribute of
+/// the root inode.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MergeProvenance {
+    /// Identity of the builder which has merged the bootstrap.
+    pub builder: String,
+    /// Version of the tool which has merged the bootstrap.
+    pub tool_version: String,
+    /// Seconds since the Unix epoch when the bootstrap was merged, omitted for deterministic merge.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Digests of source bootstraps in order of merging, in form of `sha256:<hex>`.
+    pub sources: Vec<String>,
+    /// Digest of the chunk dictionary bootstrap, in form of `sha256:<hex>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_dict: Option<String>,
+}
+
+impl MergeProvenance {
+    /// Name of the root inode extended attribute holding the provenance record in JSON.
+    pub const XATTR_NAME: &'static str = "user.nydus.provenance";
+
+    /// Create a provenance record without source information.
+    pub fn new(builder: &str, tool_version: &str) -> Self {
+        MergeProvenance {
+            builder: builder.to_string(),
+            tool_version: tool_version.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Get digest of a file in form of `sha256:<hex>`.
+    pub fn digest_file<P: AsRef<Path>>(path: P) -> Result<String> {
+        let path = path.as_ref();
+        let mut file = File::open(path).with_context(|| format!("open file {:?}", path))?;
+        let digest = digest::RafsDigest::from_reader(&mut file, digest::Algorithm::Sha256)
+            .with_context(|| format!("calculate digest of file {:?}", path))?;
+        Ok(format!("sha256:{}", digest))
+    }
+
+    /// Encode the provenance record as value of the `XATTR_NAME` extended attribute.
+    pub fn to_xattr_value(&self) -> Result<Vec<u8>> {
+        serde_json::to_vec(self).context("serialize provenance record")
+    }
+
+    /// Load the provenance record from the bootstrap at `bootstrap_path`, or None if the
+    /// bootstrap carries no provenance record.
+    pub fn load<P: AsRef<Path>>(bootstrap_path: P) -> Result<Option<Self>> {
+        let path = bootstrap_path.as_ref();
+        let (rs, _) = RafsSuper::load_from_file(path, Arc::new(ConfigV2::default()), false)
+            .with_context(|| format!("load bootstrap {:?}", path))?;
+        let root = rs.get_extended_inode(rs.superblock.root_ino(), false)?;
+        match root.get_xattr(OsStr::new(Self::XATTR_NAME))? {
+            None => Ok(None),
+            Some(value) => serde_json::from_slice(&value)
+                .map(Some)
+                .with_context(|| format!("parse provenance record of bootstrap {:?}", path)),
         }
     }
 }
@@ -1464,6 +1762,8 @@ pub struct BuildOutput {
     pub blob_size: Option<u64>,
     /// File path for the metadata blob.
     pub bootstrap_path: Option<String>,
+    /// OCI layer descriptors for per layer data blobs, one for each merged source layer.
+    pub layers: Vec<BlobLayerDescriptor>,
 }
 
 impl fmt::Display for BuildOutput {
@@ -1501,6 +1801,7 @@ impl BuildOutput {
             blobs,
             blob_size,
             bootstrap_path,
+            layers: Vec::new(),
         })
     }
 }
@@ -1552,6 +1853,11 @@ mod tests {
                 s3: None,
                 registry: None,
                 http_proxy: None,
+                fallback: None,
+                hedge: None,
+                min_read_size: 0,
+                read_timeout_ms: 0,
+                packed: None,
             }),
             id: "id".to_owned(),
             cache: None,
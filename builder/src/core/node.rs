@@ -151,7 +151,7 @@ pub struct Node {
     /// Chunks info list of regular file
     pub chunks: Vec<NodeChunk>,
     /// Layer index where node is located.
-    pub layer_idx: u16,
+    pub layer_idx: u32,
     /// Overlay type for layered build
     pub overlay: Overlay,
 
@@ -194,7 +194,7 @@ impl Display for Node {
 
 impl Node {
     /// Create a new instance of [Node].
-    pub fn new(inode: InodeWrapper, info: NodeInfo, layer_idx: u16) -> Self {
+    pub fn new(inode: InodeWrapper, info: NodeInfo, layer_idx: u32) -> Self {
         Node {
             info: Arc::new(info),
             index: 0,
@@ -443,8 +443,10 @@ impl Node {
                 // Dump current batch chunk if exists, and then add into a new batch chunk.
                 if !batch.chunk_data_buf_is_empty() {
                     // Dump current batch chunk.
+                    let data = batch.chunk_data_buf();
+                    let compressor = ctx.chunk_compressor(data, true);
                     let (_, c_size, _) =
-                        Self::write_chunk_data(ctx, blob_ctx, blob_writer, batch.chunk_data_buf())?;
+                        Self::write_chunk_data(blob_ctx, blob_writer, data, compressor)?;
                     dumped_size = Some(c_size);
                     batch.add_context(c_size);
                     batch.clear_chunk_data_buf();
@@ -469,21 +471,25 @@ impl Node {
                 let mut batch = batch.lock().unwrap();
                 if !batch.chunk_data_buf_is_empty() {
                     // Dump current batch chunk.
+                    let data = batch.chunk_data_buf();
+                    let compressor = ctx.chunk_compressor(data, true);
                     let (_, c_size, _) =
-                        Self::write_chunk_data(ctx, blob_ctx, blob_writer, batch.chunk_data_buf())?;
+                        Self::write_chunk_data(blob_ctx, blob_writer, data, compressor)?;
                     dumped_size = Some(c_size);
                     batch.add_context(c_size);
                     batch.clear_chunk_data_buf();
                 }
             }
 
+            let compressor = ctx.chunk_compressor(chunk_data, false);
             let (pre_c_offset, c_size, is_compressed) =
-                Self::write_chunk_data(ctx, blob_ctx, blob_writer, chunk_data)
+                Self::write_chunk_data(blob_ctx, blob_writer, chunk_data, compressor)
                     .with_context(|| format!("failed to write chunk data {:?}", self.path()))?;
             dumped_size = Some(dumped_size.unwrap_or(0) + c_size);
             chunk.set_compressed_offset(pre_c_offset);
             chunk.set_compressed_size(c_size);
             chunk.set_compressed(is_compressed);
+            chunk.set_lz4_block(is_compressed && compressor != blob_ctx.blob_compressor);
         }
 
         if let Some(blob_cache) = ctx.blob_cache_generator.as_ref() {
@@ -494,13 +500,14 @@ impl Node {
         Ok((chunk_info, dumped_size))
     }
 
+    /// Compress chunk data with `compressor` and write it into the data blob.
     pub fn write_chunk_data(
-        ctx: &BuildContext,
         blob_ctx: &mut BlobContext,
         blob_writer: &mut dyn Artifact,
         chunk_data: &[u8],
+        compressor: compress::Algorithm,
     ) -> Result<(u64, u32, bool)> {
-        let (compressed, is_compressed) = compress::compress(chunk_data, ctx.compressor)
+        let (compressed, is_compressed) = compress::compress(chunk_data, compressor)
             .with_context(|| "failed to compress node file".to_string())?;
         let encrypted = crypt::encrypt_with_context(
             &compressed,
@@ -882,6 +889,15 @@ impl Node {
         self.info = Arc::new(info);
     }
 
+    /// Add or replace an extended attribute with id `key`.
+    pub fn add_xattr(&mut self, key: &OsStr, value: Vec<u8>) -> Result<()> {
+        let mut info = self.info.deref().clone();
+        info.xattrs.add(key.to_os_string(), value)?;
+        self.inode.set_has_xattr(true);
+        self.info = Arc::new(info);
+        Ok(())
+    }
+
     /// Delete an extend attribute with id `key`.
     pub fn remove_xattr(&mut self, key: &OsStr) {
         let mut info = self.info.deref().clone();
@@ -900,6 +916,7 @@ mod tests {
     use nydus_utils::{digest, BufReaderInfo};
     use vmm_sys_util::tempfile::TempFile;
 
+    use crate::core::context::NoopArtifactWriter;
     use crate::{ArtifactWriter, BlobCacheGenerator, HashChunkDict};
 
     use super::*;
@@ -928,6 +945,77 @@ mod tests {
         assert_eq!(chunk.inner.file_offset(), 0x40);
     }
 
+    #[test]
+    fn test_node_write_chunk_data_auto_compressor() {
+        let mut ctx = BuildContext {
+            compressor: compress::Algorithm::Zstd,
+            ..Default::default()
+        };
+        ctx.set_compressor_auto(true);
+        let mut blob_ctx = BlobContext::new(
+            "blob".to_string(),
+            0,
+            BlobFeatures::empty(),
+            ctx.compressor,
+            digest::Algorithm::Sha256,
+            crypt::Algorithm::None,
+            Arc::new(crypt::Algorithm::None.new_cipher().unwrap()),
+            None,
+        );
+        let mut blob_writer = NoopArtifactWriter::default();
+
+        // Pseudo random data, which is not worth compressing.
+        let random: Vec<u8> = (0..0x4000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        let compressor = ctx.chunk_compressor(&random, false);
+        assert_eq!(compressor, compress::Algorithm::None);
+        let (offset, size, compressed) =
+            Node::write_chunk_data(&mut blob_ctx, &mut blob_writer, &random, compressor).unwrap();
+        assert_eq!(offset, 0);
+        assert_eq!(size, 0x4000);
+        assert!(!compressed);
+
+        // Data compressing poorly is compressed by the fast lz4_block, except for batch chunks.
+        let binary = random[..0x400]
+            .iter()
+            .map(|v| v & 0x7f)
+            .collect::<Vec<_>>()
+            .repeat(16);
+        assert_eq!(
+            ctx.chunk_compressor(&binary, true),
+            compress::Algorithm::Zstd
+        );
+        let compressor = ctx.chunk_compressor(&binary, false);
+        assert_eq!(compressor, compress::Algorithm::Lz4Block);
+        let (offset, size, compressed) =
+            Node::write_chunk_data(&mut blob_ctx, &mut blob_writer, &binary, compressor).unwrap();
+        assert_eq!(offset, 0x4000);
+        assert!(size < 0x4000);
+        assert!(compressed);
+
+        let text = "This is a test!\n".repeat(1024);
+        let compressor = ctx.chunk_compressor(text.as_bytes(), false);
+        assert_eq!(compressor, compress::Algorithm::Zstd);
+        let (_, size, compressed) =
+            Node::write_chunk_data(&mut blob_ctx, &mut blob_writer, text.as_bytes(), compressor)
+                .unwrap();
+        assert!(size < 0x4000);
+        assert!(compressed);
+
+        // Any chunk with non-zero entropy is stored uncompressed with a zero threshold.
+        ctx.set_compressor_auto_threshold(0.0);
+        assert_eq!(
+            ctx.chunk_compressor(text.as_bytes(), false),
+            compress::Algorithm::None
+        );
+        ctx.set_compressor_auto(false);
+        assert_eq!(
+            ctx.chunk_compressor(&random, false),
+            compress::Algorithm::Zstd
+        );
+    }
+
     #[test]
     fn test_node_dump_node_data() {
         let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
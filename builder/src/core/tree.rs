@@ -175,16 +175,21 @@ impl Tree {
 
     /// Merge the upper layer tree into the lower layer tree, applying whiteout rules.
     pub fn merge_overaly(&mut self, ctx: &BuildContext, upper: Tree) -> Result<()> {
+        self.merge_overlay_tree(ctx, &upper)?;
+        lazy_drop(upper);
+
+        Ok(())
+    }
+
+    /// Merge the upper layer tree into the lower layer tree, leaving the upper tree to the caller.
+    pub(crate) fn merge_overlay_tree(&mut self, ctx: &BuildContext, upper: &Tree) -> Result<()> {
         assert_eq!(self.name, "/".as_bytes());
         assert_eq!(upper.name, "/".as_bytes());
 
         // Handle the root node.
         upper.lock_node().overlay = Overlay::UpperModification;
         self.node = upper.node.clone();
-        self.merge_children(ctx, &upper)?;
-        lazy_drop(upper);
-
-        Ok(())
+        self.merge_children(ctx, upper)
     }
 
     fn merge_children(&mut self, ctx: &BuildContext, upper: &Tree) -> Result<()> {
@@ -31,7 +31,7 @@ impl FilesystemTreeBuilder {
         ctx: &mut BuildContext,
         bootstrap_ctx: &mut BootstrapContext,
         parent: &TreeNode,
-        layer_idx: u16,
+        layer_idx: u32,
     ) -> Result<Vec<Tree>> {
         let mut result = Vec::new();
         let parent = parent.lock().unwrap();
@@ -94,7 +94,7 @@ impl DirectoryBuilder {
         &mut self,
         ctx: &mut BuildContext,
         bootstrap_ctx: &mut BootstrapContext,
-        layer_idx: u16,
+        layer_idx: u32,
     ) -> Result<Tree> {
         let node = Node::from_fs_object(
             ctx.fs_version,
@@ -127,7 +127,7 @@ impl Builder for DirectoryBuilder {
         blob_mgr: &mut BlobManager,
     ) -> Result<BuildOutput> {
         let mut bootstrap_ctx = bootstrap_mgr.create_ctx()?;
-        let layer_idx = u16::from(bootstrap_ctx.layered);
+        let layer_idx = u32::from(bootstrap_ctx.layered);
         let mut blob_writer: Box<dyn Artifact> = if let Some(blob_stor) = ctx.blob_storage.clone() {
             Box::new(ArtifactWriter::new(blob_stor)?)
         } else {
@@ -195,3 +195,84 @@ impl Builder for DirectoryBuilder {
         BuildOutput::new(blob_mgr, &bootstrap_mgr.bootstrap_storage)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use nydus_api::ConfigV2;
+    use nydus_rafs::metadata::{RafsSuper, RafsVersion};
+    use nydus_utils::{compress, digest};
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::{ArtifactStorage, ConversionType, Features, Prefetch, WhiteoutSpec};
+
+    #[test]
+    fn test_build_with_prefetch_hint_xattr() {
+        let src_dir = TempDir::new().unwrap();
+        let hot = src_dir.as_path().join("hot");
+        let cold = src_dir.as_path().join("cold");
+        fs::write(&hot, vec![0x5au8; 0x3000]).unwrap();
+        fs::write(cold, vec![0xa5u8; 0x3000]).unwrap();
+        if xattr::set(&hot, "user.nydus.prefetch", b"1").is_err() {
+            // User extended attributes are not supported by the underlying filesystem.
+            return;
+        }
+
+        let tmp_dir = TempDir::new().unwrap();
+        let bootstrap_path = tmp_dir.as_path().join("bootstrap");
+        let mut ctx = BuildContext::new(
+            "test".to_string(),
+            true,
+            0,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::DirectoryToRafs,
+            src_dir.as_path().to_path_buf(),
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir(tmp_dir.as_path().to_path_buf())),
+            false,
+            Features::new(),
+            false,
+        );
+        ctx.fs_version = RafsVersion::V6;
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::SingleFile(bootstrap_path.clone())),
+            None,
+        );
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256);
+        DirectoryBuilder::new()
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+
+        let config = Arc::new(ConfigV2::default());
+        let (sb, _) = RafsSuper::load_from_file(&bootstrap_path, config, false).unwrap();
+        let root_ino = sb.superblock.root_ino();
+        let inodes = sb
+            .get_prefetch_hinted_inodes(root_ino, OsStr::new("user.nydus.prefetch"))
+            .unwrap();
+        assert_eq!(inodes.len(), 1);
+        let mut hot_ino = 0;
+        let mut hot_chunks = 0;
+        sb.walk_directory::<&Path>(root_ino, None, &mut |inode, path| {
+            if path == Path::new("/hot") {
+                hot_ino = inode.ino();
+                hot_chunks = inode.get_chunk_count();
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(inodes[0], hot_ino);
+        assert!(hot_chunks > 0);
+
+        let inodes = sb
+            .get_prefetch_hinted_inodes(root_ino, OsStr::new("user.nydus.other"))
+            .unwrap();
+        assert!(inodes.is_empty());
+    }
+}
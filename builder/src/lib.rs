@@ -21,7 +21,7 @@ use nydus_utils::digest::{DigestHasher, RafsDigest};
 use nydus_utils::{compress, digest, root_tracer, timing_tracer};
 use sha2::Digest;
 
-use self::core::node::{Node, NodeInfo};
+use self::core::node::NodeInfo;
 
 pub use self::chunkdict_generator::ChunkdictBlobInfo;
 pub use self::chunkdict_generator::ChunkdictChunkInfo;
@@ -30,16 +30,17 @@ pub use self::compact::BlobCompactor;
 pub use self::core::bootstrap::Bootstrap;
 pub use self::core::chunk_dict::{parse_chunk_dict_arg, ChunkDict, HashChunkDict};
 pub use self::core::context::{
-    ArtifactStorage, ArtifactWriter, BlobCacheGenerator, BlobContext, BlobManager,
-    BootstrapContext, BootstrapManager, BuildContext, BuildOutput, ConversionType,
+    ArtifactStorage, ArtifactWriter, BlobCacheGenerator, BlobContext, BlobLayerDescriptor,
+    BlobManager, BootstrapContext, BootstrapManager, BuildContext, BuildOutput, ConversionType,
+    MergeProvenance, MEDIA_TYPE_NYDUS_BLOB,
 };
 pub use self::core::feature::{Feature, Features};
-pub use self::core::node::{ChunkSource, NodeChunk};
+pub use self::core::node::{ChunkSource, Node, NodeChunk};
 pub use self::core::overlay::{Overlay, WhiteoutSpec};
 pub use self::core::prefetch::{Prefetch, PrefetchPolicy};
 pub use self::core::tree::{MetadataTreeBuilder, Tree, TreeNode};
 pub use self::directory::DirectoryBuilder;
-pub use self::merge::Merger;
+pub use self::merge::{MergeOptions, MergeSource, Merger, TreeRewriter};
 pub use self::stargz::StargzBuilder;
 pub use self::tarball::TarballBuilder;
 
@@ -255,14 +256,14 @@ fn finalize_blob(
 /// Helper for TarballBuilder/StargzBuilder to build the filesystem tree.
 pub struct TarBuilder {
     pub explicit_uidgid: bool,
-    pub layer_idx: u16,
+    pub layer_idx: u32,
     pub version: RafsVersion,
     next_ino: Inode,
 }
 
 impl TarBuilder {
     /// Create a new instance of [TarBuilder].
-    pub fn new(explicit_uidgid: bool, layer_idx: u16, version: RafsVersion) -> Self {
+    pub fn new(explicit_uidgid: bool, layer_idx: u32, version: RafsVersion) -> Self {
         TarBuilder {
             explicit_uidgid,
             layer_idx,
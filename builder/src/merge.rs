@@ -5,21 +5,206 @@
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::path::PathBuf;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use hex::FromHex;
 use nydus_api::ConfigV2;
 use nydus_rafs::metadata::{RafsSuper, RafsVersion};
+use nydus_storage::backend::{BlobBackend, BlobReader};
 use nydus_storage::device::{BlobFeatures, BlobInfo};
-use nydus_utils::crypt;
+use nydus_storage::factory::BlobFactory;
+use nydus_utils::digest::{self, RafsDigest};
+use nydus_utils::{compress, crypt};
 
+use super::core::blob::Blob;
 use super::{
-    ArtifactStorage, BlobContext, BlobManager, Bootstrap, BootstrapContext, BuildContext,
-    BuildOutput, ChunkSource, ConversionType, Overlay, Tree,
+    finalize_blob, ArtifactStorage, ArtifactWriter, BlobContext, BlobLayerDescriptor, BlobManager,
+    Bootstrap, BootstrapContext, BuildContext, BuildOutput, ChunkSource, ConversionType,
+    MergeProvenance, NodeChunk, Overlay, Tree,
 };
 
+/// Reader to fetch file data from chunks in the original data blobs, used to re-chunk files.
+struct ChunkDataReader<'a> {
+    chunks: Vec<NodeChunk>,
+    readers: &'a [Arc<dyn BlobReader>],
+    compressor: compress::Algorithm,
+    buf: Vec<u8>,
+    pos: usize,
+    next: usize,
+}
+
+impl<'a> ChunkDataReader<'a> {
+    fn new(
+        mut chunks: Vec<NodeChunk>,
+        readers: &'a [Arc<dyn BlobReader>],
+        compressor: compress::Algorithm,
+    ) -> Self {
+        chunks.sort_by_key(|c| c.inner.file_offset());
+        ChunkDataReader {
+            chunks,
+            readers,
+            compressor,
+            buf: Vec::new(),
+            pos: 0,
+            next: 0,
+        }
+    }
+
+    fn load_next_chunk(&mut self) -> io::Result<()> {
+        let chunk = &self.chunks[self.next].inner;
+        let reader = self
+            .readers
+            .get(chunk.blob_index() as usize)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid blob index {} of chunk", chunk.blob_index()),
+                )
+            })?;
+        let mut c_buf = vec![0u8; chunk.compressed_size() as usize];
+        let size = reader
+            .read_all(&mut c_buf, chunk.compressed_offset())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+        if size != c_buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "failed to read chunk at offset {:x}",
+                    chunk.compressed_offset()
+                ),
+            ));
+        }
+        if chunk.is_compressed() {
+            let mut d_buf = vec![0u8; chunk.uncompressed_size() as usize];
+            compress::decompress(&c_buf, &mut d_buf, self.compressor)?;
+            self.buf = d_buf;
+        } else {
+            self.buf = c_buf;
+        }
+        self.pos = 0;
+        self.next += 1;
+        Ok(())
+    }
+}
+
+impl Read for ChunkDataReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.buf.len() {
+            if self.next >= self.chunks.len() {
+                return Ok(0);
+            }
+            self.load_next_chunk()?;
+        }
+        let size = std::cmp::min(buf.len(), self.buf.len() - self.pos);
+        buf[..size].copy_from_slice(&self.buf[self.pos..self.pos + size]);
+        self.pos += size;
+        Ok(size)
+    }
+}
+
+/// Callback to rewrite the merged filesystem tree before building the merged RAFS bootstrap.
+pub type TreeRewriter = Box<dyn FnOnce(&mut Tree) -> Result<()>>;
+
+/// Optional arguments of [Merger::merge_sources()](struct.Merger.html#method.merge_sources).
+#[derive(Default)]
+pub struct MergeOptions {
+    /// Digests of data blobs of source layers, in the same order as source bootstraps.
+    pub blob_digests: Option<Vec<String>>,
+    /// Original blob ids of source layers, in the same order as source bootstraps.
+    pub original_blob_ids: Option<Vec<String>>,
+    /// Sizes of data blobs of source layers, in the same order as source bootstraps.
+    pub blob_sizes: Option<Vec<u64>>,
+    /// Digests of blob ToCs of source layers, in the same order as source bootstraps.
+    pub blob_toc_digests: Option<Vec<String>>,
+    /// Sizes of blob ToCs of source layers, in the same order as source bootstraps.
+    pub blob_toc_sizes: Option<Vec<u64>>,
+    /// Chunk dictionary used to build source layers.
+    pub chunk_dict: Option<PathBuf>,
+    /// Configuration to load bootstraps and access data blobs.
+    pub config_v2: Arc<ConfigV2>,
+    /// Callback to add, remove or modify nodes of the merged tree before building the merged
+    /// bootstrap.
+    pub tree_rewriter: Option<TreeRewriter>,
+}
+
+/// A per layer RAFS bootstrap to be merged.
+pub enum MergeSource {
+    /// Bootstrap stored in a file.
+    File(PathBuf),
+    /// Bootstrap held in memory, with `name` standing for the bootstrap file name.
+    ///
+    /// Like the file name of a bootstrap file, `name` is used to derive the blob id of the layer
+    /// when no original blob id is given.
+    Memory { name: String, data: Vec<u8> },
+}
+
+impl MergeSource {
+    fn load(&self, config: Arc<ConfigV2>) -> Result<RafsSuper> {
+        let (rs, _) = match self {
+            MergeSource::File(path) => RafsSuper::load_from_file(path, config, false)?,
+            #[cfg(target_os = "linux")]
+            MergeSource::Memory { data, .. } => RafsSuper::load_from_memory(data, config, false)?,
+            #[cfg(not(target_os = "linux"))]
+            MergeSource::Memory { .. } => bail!("in-memory bootstraps are only supported on Linux"),
+        };
+        Ok(rs)
+    }
+
+    /// Get RAFS version of the bootstrap by peeking at its super block.
+    fn version(&self, config: Arc<ConfigV2>) -> Result<RafsVersion> {
+        let version = match self {
+            MergeSource::File(path) => {
+                File::open(path).and_then(|mut file| RafsVersion::detect(&mut file))
+            }
+            MergeSource::Memory { data, .. } => RafsVersion::detect(&mut data.as_slice()),
+        };
+        match version {
+            Ok(v) => Ok(v),
+            // The bootstrap may be inlined in a data blob, which needs to be loaded as a whole.
+            Err(_) => {
+                let rs = self.load(config)?;
+                RafsVersion::try_from(rs.meta.version).context("failed to get RAFS version number")
+            }
+        }
+    }
+
+    fn blob_id(&self) -> Result<String> {
+        let path = match self {
+            MergeSource::File(path) => path.as_path(),
+            MergeSource::Memory { name, .. } => Path::new(name),
+        };
+        Ok(BlobInfo::get_blob_id_from_meta_path(path)?)
+    }
+
+    fn digest(&self) -> Result<String> {
+        match self {
+            MergeSource::File(path) => MergeProvenance::digest_file(path),
+            MergeSource::Memory { data, .. } => {
+                let digest = RafsDigest::from_buf(data, digest::Algorithm::Sha256);
+                Ok(format!("sha256:{}", digest))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MergeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeSource::File(path) => write!(f, "{:?}", path),
+            MergeSource::Memory { name, .. } => write!(f, "{:?} (in memory)", name),
+        }
+    }
+}
+
 /// Struct to generate the merged RAFS bootstrap for an image from per layer RAFS bootstraps.
 ///
 /// A container image contains one or more layers, a RAFS bootstrap is built for each layer.
@@ -65,11 +250,238 @@ impl Merger {
         })
     }
 
+    /// Check whether data blobs of the source bootstrap `rs` may be re-chunked.
+    fn check_rechunk(rs: &RafsSuper) -> Result<()> {
+        for blob in rs.superblock.get_blob_infos() {
+            if blob.cipher() != crypt::Algorithm::None
+                || blob.has_feature(BlobFeatures::BATCH)
+                || blob.has_feature(BlobFeatures::ZRAN)
+                || blob.has_feature(BlobFeatures::SEPARATE)
+                || blob.has_feature(BlobFeatures::TARFS)
+            {
+                bail!(
+                    "can not re-chunk data blob {} in this format",
+                    blob.blob_id()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-chunk data of regular files in `tree` at `ctx.chunk_size` into a new data blob.
+    ///
+    /// Chunk data is fetched from data blobs of the source bootstrap `rs` by `backend`, and the
+    /// new data blob is saved into `ctx.blob_storage`. Return None if there's no file data.
+    fn rechunk_layer(
+        ctx: &mut BuildContext,
+        rs: &RafsSuper,
+        tree: &Tree,
+        backend: &Arc<dyn BlobBackend + Send + Sync>,
+    ) -> Result<Option<BlobContext>> {
+        let blob_storage = ctx
+            .blob_storage
+            .clone()
+            .ok_or_else(|| anyhow!("re-chunking requires a directory to save data blobs"))?;
+        Self::check_rechunk(rs)?;
+        let compressor = rs.meta.get_compressor();
+        let mut readers = Vec::new();
+        for blob in rs.superblock.get_blob_infos() {
+            let reader = backend.get_reader(&blob.blob_id()).map_err(|e| {
+                anyhow!("failed to get reader for blob {}, {:?}", blob.blob_id(), e)
+            })?;
+            readers.push(reader);
+        }
+
+        let mut blob_mgr = BlobManager::new(ctx.digester);
+        let mut blob_writer = ArtifactWriter::new(blob_storage)?;
+        let mut data_buf = vec![0u8; ctx.chunk_size as usize];
+        let mut visited = HashSet::new();
+        tree.walk_bfs(true, &mut |n| {
+            // Avoid re-chunking a node shared by multiple tree entries twice.
+            if !visited.insert(Rc::as_ptr(&n.node)) {
+                return Ok(());
+            }
+            let mut node = n.lock_node();
+            if !node.is_reg() {
+                return Ok(());
+            }
+            let chunks = std::mem::take(&mut node.chunks);
+            let chunk_count = node.chunk_count(ctx.chunk_size as u64)?;
+            node.inode.set_child_count(chunk_count);
+            let mut reader = ChunkDataReader::new(chunks, &readers, compressor);
+            node.dump_node_data_with_reader(
+                ctx,
+                &mut blob_mgr,
+                &mut blob_writer,
+                Some(&mut reader),
+                &mut data_buf,
+            )
+            .with_context(|| format!("failed to re-chunk file {:?}", node.target()))?;
+            Ok(())
+        })?;
+
+        if blob_mgr.get_current_blob().is_none() {
+            return Ok(None);
+        }
+        Blob::finalize_blob_data(ctx, &mut blob_mgr, &mut blob_writer)?;
+        if let Some((_, blob_ctx)) = blob_mgr.get_current_blob() {
+            Blob::dump_meta_data(ctx, blob_ctx, &mut blob_writer)?;
+        }
+        finalize_blob(ctx, &mut blob_mgr, &mut blob_writer)?;
+
+        Ok(Some(blob_mgr.take_blob(0)))
+    }
+
+    /// Update blob index of chunks in `tree` after reorganizing the blob table, `new_index` is the
+    /// new index of each blob indexed by its old index.
+    fn remap_blob_index(tree: &Tree, new_index: &[usize]) -> Result<()> {
+        let mut visited = HashSet::new();
+        tree.walk_bfs(true, &mut |n| {
+            // Avoid remapping a node shared by multiple tree entries twice.
+            if visited.insert(Rc::as_ptr(&n.node)) {
+                let mut node = n.lock_node();
+                for chunk in &mut node.chunks {
+                    let blob_index = new_index[chunk.inner.blob_index() as usize];
+                    chunk.set_blob_index(blob_index as u32);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Collect indices of blobs referenced by chunks in `tree`.
+    fn referenced_blobs(tree: &Tree) -> Result<HashSet<usize>> {
+        let mut referenced = HashSet::new();
+        tree.walk_bfs(true, &mut |n| {
+            for chunk in n.lock_node().chunks.iter() {
+                referenced.insert(chunk.inner.blob_index() as usize);
+            }
+            Ok(())
+        })?;
+        Ok(referenced)
+    }
+
+    /// Convert inodes and chunks of RAFS v5 in `tree` into RAFS v6 representation.
+    ///
+    /// Data blobs of RAFS v5 lack the chunk information table required by RAFS v6, so chunk
+    /// data must be re-chunked into a new data blob after upgrading.
+    fn upgrade_to_v6(tree: &Tree) -> Result<()> {
+        let mut visited = HashSet::new();
+        tree.walk_bfs(true, &mut |n| {
+            if visited.insert(Rc::as_ptr(&n.node)) {
+                let mut node = n.lock_node();
+                node.inode.upgrade_to_v6();
+                for chunk in &mut node.chunks {
+                    let mut inner = chunk.inner.deref().clone();
+                    inner.upgrade_to_v6();
+                    chunk.inner = Arc::new(inner);
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Get RAFS version of the merged bootstrap, RAFS v5 layers are upgraded to RAFS v6 if any
+    /// layer is RAFS v6.
+    fn target_version(
+        parent_version: Option<RafsVersion>,
+        sources: &[MergeSource],
+        config: Arc<ConfigV2>,
+    ) -> Result<RafsVersion> {
+        if let Some(version) = parent_version {
+            return Ok(version);
+        }
+        for source in sources {
+            let version = source
+                .version(config.clone())
+                .context(format!("detect RAFS version of bootstrap {:?}", source))?;
+            if version == RafsVersion::V6 {
+                return Ok(RafsVersion::V6);
+            }
+        }
+        Ok(RafsVersion::V5)
+    }
+
+    /// Get index of the `layer_idx`th source layer merged onto `parent_layers` parent layers.
+    fn layer_index(parent_layers: usize, layer_idx: usize) -> Result<u32> {
+        parent_layers
+            .checked_add(layer_idx)
+            .and_then(|idx| u32::try_from(idx).ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "too many layers {}, limited to {}",
+                    parent_layers.saturating_add(layer_idx),
+                    u32::MAX
+                )
+            })
+    }
+
+    /// Check that extended attributes of each inode in `tree` take no more than `limit` bytes in
+    /// the RAFS filesystem of version `fs_version`.
+    fn check_xattr_size(tree: &Tree, fs_version: RafsVersion, limit: u64) -> Result<()> {
+        tree.walk_bfs(true, &mut |n| {
+            let node = n.lock_node();
+            let xattrs = &node.info.xattrs;
+            let size = match fs_version {
+                RafsVersion::V5 => xattrs.aligned_size_v5(),
+                RafsVersion::V6 => xattrs.aligned_size_v6(),
+            } as u64;
+            ensure!(
+                size <= limit,
+                "extended attributes of {:?} take {} bytes, exceeding the limit of {} bytes",
+                node.target(),
+                size,
+                limit
+            );
+            Ok(())
+        })
+    }
+
     /// Overlay multiple RAFS filesystems into a merged RAFS filesystem.
     ///
     /// # Arguments
     /// - sources: contains one or more per layer bootstraps in order of lower to higher.
     /// - chunk_dict: contain the chunk dictionary used to build per layer boostrap, or None.
+    ///
+    /// If `ctx.preserve_layers` is set, the data blob of each source layer is guaranteed to be
+    /// kept as a distinct entry in the blob table and an OCI layer descriptor is emitted for it,
+    /// so only the filesystem metadata gets merged.
+    ///
+    /// If `ctx.deterministic_merge` is set, the blob table is sorted by blob id, so merging the
+    /// same sources always generates an identical bootstrap.
+    ///
+    /// If `ctx.merge_rechunk` is set, source layers with a chunk size different from the first
+    /// layer are re-chunked instead of rejected. File data of those layers is fetched by the
+    /// backend configured in `config_v2` and saved as a new data blob into `ctx.blob_storage`,
+    /// and the blob digest/size/toc lists don't apply to the new data blobs.
+    ///
+    /// If `ctx.merge_dedup_blobs` is set, data blobs with identical content but different blob ids,
+    /// detected by matching blob toc digest or blob meta digest, are collapsed into one entry of
+    /// the blob table.
+    ///
+    /// If `ctx.merge_gc_blobs` is set, data blobs not referenced by any chunk of the merged tree,
+    /// for example blobs of lower layers whose files are all overwritten or removed by upper
+    /// layers, are dropped from the blob table after rewriting the tree. Indices of the remaining
+    /// blobs change accordingly, so it's not compatible with `ctx.preserve_layers`.
+    ///
+    /// If `ctx.merge_dry_run` is set, all checks are done and the blob table is built, but neither
+    /// the merged bootstrap nor re-chunked data blobs get written, and the returned `BuildOutput`
+    /// describes the blobs the merged bootstrap would reference. Layers to be re-chunked are only
+    /// checked, and no blobs for them are reported.
+    ///
+    /// RAFS v5 sources may be placed below RAFS v6 sources, and the merged bootstrap is RAFS v6.
+    /// Inodes of the RAFS v5 layers are converted into RAFS v6 representation, and their file data
+    /// is re-chunked into new RAFS v6 data blobs like `ctx.merge_rechunk` does, because RAFS v5
+    /// data blobs lack the chunk information table. RAFS v5 sources above RAFS v6 layers, and
+    /// RAFS v6 sources onto a RAFS v5 parent bootstrap, are rejected.
+    ///
+    /// If `ctx.merge_xattr_size_limit` is set, merging fails if the extended attributes of any
+    /// inode of the merged tree take more space than the limit.
+    ///
+    /// If `ctx.merge_provenance` is set, a provenance record with digests of the source bootstraps
+    /// and the chunk dictionary is stored in the merged bootstrap as an extended attribute of the
+    /// root inode, which may be loaded by `MergeProvenance::load()`. The timestamp is omitted if
+    /// `ctx.deterministic_merge` is set.
     #[allow(clippy::too_many_arguments)]
     pub fn merge(
         ctx: &mut BuildContext,
@@ -84,9 +496,116 @@ impl Merger {
         chunk_dict: Option<PathBuf>,
         config_v2: Arc<ConfigV2>,
     ) -> Result<BuildOutput> {
+        Self::merge_sources(
+            ctx,
+            parent_bootstrap_path,
+            sources.into_iter().map(MergeSource::File).collect(),
+            target,
+            MergeOptions {
+                blob_digests,
+                original_blob_ids,
+                blob_sizes,
+                blob_toc_digests,
+                blob_toc_sizes,
+                chunk_dict,
+                config_v2,
+                tree_rewriter: None,
+            },
+        )
+    }
+
+    /// Append a new layer onto an existing merged bootstrap instead of merging all layers again.
+    ///
+    /// The existing merged bootstrap is loaded as the parent bootstrap, so its blob table is kept
+    /// as is and the data blob of the new layer is appended to it. Appending layers one by one
+    /// generates the same bootstrap as merging all of them at once by `merge()`.
+    ///
+    /// The new layer must have the same chunk size as the merged bootstrap, and options which
+    /// renumber data blobs, `ctx.merge_dedup_blobs`, `ctx.merge_gc_blobs` and
+    /// `ctx.deterministic_merge`, are rejected to keep indices of existing data blobs.
+    #[allow(clippy::too_many_arguments)]
+    pub fn append_layer(
+        ctx: &mut BuildContext,
+        merged_bootstrap_path: PathBuf,
+        layer_bootstrap_path: PathBuf,
+        blob_digest: Option<String>,
+        original_blob_id: Option<String>,
+        blob_size: Option<u64>,
+        target: ArtifactStorage,
+        chunk_dict: Option<PathBuf>,
+        config_v2: Arc<ConfigV2>,
+    ) -> Result<BuildOutput> {
+        ensure!(
+            !ctx.merge_dedup_blobs && !ctx.merge_gc_blobs && !ctx.deterministic_merge,
+            "can't renumber data blobs of the merged bootstrap when appending a layer"
+        );
+        let (merged, _) =
+            RafsSuper::load_from_file(&merged_bootstrap_path, config_v2.clone(), false)
+                .context(format!("load merged bootstrap {:?}", merged_bootstrap_path))?;
+        let (layer, _) = RafsSuper::load_from_file(&layer_bootstrap_path, config_v2.clone(), false)
+            .context(format!("load bootstrap {:?}", layer_bootstrap_path))?;
+        ensure!(
+            merged.meta.chunk_size == layer.meta.chunk_size,
+            "can not append bootstrap {:?} with chunk size {:x} to merged bootstrap with chunk size {:x}",
+            layer_bootstrap_path,
+            layer.meta.chunk_size,
+            merged.meta.chunk_size,
+        );
+        let parent_bootstrap_path = merged_bootstrap_path
+            .to_str()
+            .ok_or_else(|| anyhow!("invalid merged bootstrap path {:?}", merged_bootstrap_path))?
+            .to_string();
+
+        Self::merge(
+            ctx,
+            Some(parent_bootstrap_path),
+            vec![layer_bootstrap_path],
+            blob_digest.map(|v| vec![v]),
+            original_blob_id.map(|v| vec![v]),
+            blob_size.map(|v| vec![v]),
+            None,
+            None,
+            target,
+            chunk_dict,
+            config_v2,
+        )
+    }
+
+    /// Overlay multiple RAFS filesystems, stored in files or held in memory, into a merged RAFS
+    /// filesystem.
+    ///
+    /// It's the same as `merge()`, except that source bootstraps may be held in memory, so they
+    /// needn't be written to files before merging, and optional arguments are passed by
+    /// `options`. If `options.tree_rewriter` is given, it's invoked to add, remove or modify
+    /// nodes of the merged tree before building the merged bootstrap.
+    pub fn merge_sources(
+        ctx: &mut BuildContext,
+        parent_bootstrap_path: Option<String>,
+        sources: Vec<MergeSource>,
+        target: ArtifactStorage,
+        options: MergeOptions,
+    ) -> Result<BuildOutput> {
+        let MergeOptions {
+            blob_digests,
+            original_blob_ids,
+            blob_sizes,
+            blob_toc_digests,
+            blob_toc_sizes,
+            chunk_dict,
+            config_v2,
+            tree_rewriter,
+        } = options;
         if sources.is_empty() {
             bail!("source bootstrap list is empty , at least one bootstrap is required");
         }
+        ensure!(
+            !(ctx.preserve_layers && ctx.merge_dedup_blobs),
+            "can't deduplicate data blobs when preserving layers"
+        );
+        ensure!(
+            !(ctx.preserve_layers && ctx.merge_gc_blobs),
+            "can't drop unreferenced data blobs when preserving layers"
+        );
         if let Some(digests) = blob_digests.as_ref() {
             ensure!(
                 digests.len() == sources.len(),
@@ -129,9 +648,12 @@ impl Merger {
         }
 
         let mut tree: Option<Tree> = None;
+        let mut fs_version = None;
         let mut blob_mgr = BlobManager::new(ctx.digester);
         let mut blob_idx_map = HashMap::new();
         let mut parent_layers = 0;
+        // Index into `blob_mgr` of the data blob owned by each source layer.
+        let mut layer_blobs = Vec::new();
 
         // Load parent bootstrap
         if let Some(parent_bootstrap_path) = &parent_bootstrap_path {
@@ -145,6 +667,10 @@ impl Merger {
                 blob_mgr.add_blob(blob_ctx);
             }
             parent_layers = blobs.len();
+            fs_version = Some(
+                RafsVersion::try_from(rs.meta.version)
+                    .context("failed to get RAFS version number")?,
+            );
             tree = Some(Tree::from_bootstrap(&rs, &mut ())?);
         }
 
@@ -160,17 +686,39 @@ impl Merger {
             }
         }
 
-        let mut fs_version = RafsVersion::V6;
+        let target_version = Self::target_version(fs_version, &sources, config_v2.clone())?;
         let mut chunk_size = None;
+        let mut backend = None;
 
-        for (layer_idx, bootstrap_path) in sources.iter().enumerate() {
-            let (rs, _) = RafsSuper::load_from_file(bootstrap_path, config_v2.clone(), false)
-                .context(format!("load bootstrap {:?}", bootstrap_path))?;
-            config
-                .get_or_insert_with(|| rs.meta.get_config())
-                .check_compatibility(&rs.meta)?;
-            fs_version = RafsVersion::try_from(rs.meta.version)
+        for (layer_idx, source) in sources.iter().enumerate() {
+            let rs = source
+                .load(config_v2.clone())
+                .context(format!("load bootstrap {:?}", source))?;
+            let version = RafsVersion::try_from(rs.meta.version)
                 .context("failed to get RAFS version number")?;
+            // RAFS v5 layers are re-chunked into data blobs of RAFS v6 when upgrading.
+            let upgrade = version != target_version;
+            if upgrade {
+                ensure!(
+                    target_version == RafsVersion::V6,
+                    "can't merge RAFS v6 bootstrap {:?} onto RAFS v5 parent bootstrap",
+                    source
+                );
+                ensure!(
+                    fs_version != Some(RafsVersion::V6),
+                    "can't merge RAFS v5 bootstrap {:?} onto RAFS v6 layers, RAFS v6 can't be downgraded to v5",
+                    source
+                );
+            }
+            let rechunk = upgrade
+                || ctx.merge_rechunk && chunk_size.map_or(false, |size| size != rs.meta.chunk_size);
+            let mut layer_config = *config.get_or_insert_with(|| rs.meta.get_config());
+            if rechunk {
+                layer_config.chunk_size = rs.meta.chunk_size;
+            }
+            layer_config.version = version;
+            layer_config.check_compatibility(&rs.meta)?;
+            fs_version = Some(version);
             ctx.compressor = rs.meta.get_compressor();
             ctx.digester = rs.meta.get_digester();
             // If any RAFS filesystems are encrypted, the merged boostrap will be marked as encrypted.
@@ -187,13 +735,43 @@ impl Merger {
 
             let mut parent_blob_added = false;
             let blobs = &rs.superblock.get_blob_infos();
-            for blob in blobs {
+            let upper = Tree::from_bootstrap(&rs, &mut ())?;
+            if upgrade {
+                Self::upgrade_to_v6(&upper)?;
+            }
+            let mut rechunked_blob_index = None;
+            if rechunk && ctx.merge_dry_run {
+                // Data blobs are not generated in dry-run mode, so drop the original chunks.
+                Self::check_rechunk(&rs).context(format!("re-chunk bootstrap {:?}", source))?;
+                upper.walk_bfs(true, &mut |n| {
+                    n.lock_node().chunks.clear();
+                    Ok(())
+                })?;
+            } else if rechunk {
+                if backend.is_none() {
+                    let backend_config = config_v2.get_backend_config()?;
+                    backend = Some(BlobFactory::new_backend(backend_config, "merger")?);
+                }
+                ctx.fs_version = target_version;
+                ctx.aligned_chunk = target_version == RafsVersion::V6;
+                ctx.chunk_size = *chunk_size.get_or_insert(rs.meta.chunk_size);
+                if let Some(blob_ctx) =
+                    Self::rechunk_layer(ctx, &rs, &upper, backend.as_ref().unwrap())
+                        .context(format!("re-chunk bootstrap {:?}", source))?
+                {
+                    layer_blobs.push(blob_mgr.len());
+                    rechunked_blob_index = Some(blob_mgr.len());
+                    blob_idx_map.insert(blob_ctx.blob_id.clone(), blob_mgr.len());
+                    blob_mgr.add_blob(blob_ctx);
+                }
+            }
+            for blob in blobs.iter().filter(|_| !rechunk) {
                 let mut blob_ctx = BlobContext::from(ctx, &blob, ChunkSource::Parent)?;
                 if let Some(chunk_size) = chunk_size {
                     ensure!(
                         chunk_size == blob_ctx.chunk_size,
                         "can not merge bootstraps with inconsistent chunk size, current bootstrap {:?} with chunk size {:x}, expected {:x}",
-                        bootstrap_path,
+                        source,
                         blob_ctx.chunk_size,
                         chunk_size,
                     );
@@ -222,8 +800,7 @@ impl Merger {
                         {
                             blob_ctx.blob_id = original_id;
                         } else {
-                            blob_ctx.blob_id =
-                                BlobInfo::get_blob_id_from_meta_path(bootstrap_path)?;
+                            blob_ctx.blob_id = source.blob_id()?;
                         }
                     }
                     if let Some(digest) = Self::get_digest_from_list(&blob_digests, layer_idx)? {
@@ -249,16 +826,34 @@ impl Merger {
                     }
                 }
 
-                if let Entry::Vacant(e) = blob_idx_map.entry(blob.blob_id()) {
-                    e.insert(blob_mgr.len());
-                    blob_mgr.add_blob(blob_ctx);
+                let is_layer_blob = !chunk_dict_blobs.contains(&blob.blob_id());
+                match blob_idx_map.entry(blob.blob_id()) {
+                    Entry::Vacant(e) => {
+                        if is_layer_blob {
+                            layer_blobs.push(blob_mgr.len());
+                        }
+                        e.insert(blob_mgr.len());
+                        blob_mgr.add_blob(blob_ctx);
+                    }
+                    Entry::Occupied(_) => {
+                        if is_layer_blob && ctx.preserve_layers {
+                            bail!(
+                                "data blob {} of bootstrap {:?} is shared with another layer, can't preserve it as a separate layer",
+                                blob.blob_id(),
+                                source
+                            );
+                        }
+                    }
                 }
             }
 
-            let upper = Tree::from_bootstrap(&rs, &mut ())?;
             upper.walk_bfs(true, &mut |n| {
                 let mut node = n.lock_node();
                 for chunk in &mut node.chunks {
+                    if let Some(blob_index) = rechunked_blob_index {
+                        chunk.set_blob_index(blob_index as u32);
+                        continue;
+                    }
                     let origin_blob_index = chunk.inner.blob_index() as usize;
                     let blob_ctx = blobs[origin_blob_index].as_ref();
                     if let Some(blob_index) = blob_idx_map.get(&blob_ctx.blob_id()) {
@@ -268,21 +863,15 @@ impl Merger {
                 }
                 // Set node's layer index to distinguish same inode number (from bootstrap)
                 // between different layers.
-                let idx = u16::try_from(layer_idx).context(format!(
-                    "too many layers {}, limited to {}",
-                    layer_idx,
-                    u16::MAX
-                ))?;
-                if parent_layers + idx as usize > u16::MAX as usize {
-                    bail!("too many layers {}, limited to {}", layer_idx, u16::MAX);
-                }
-                node.layer_idx = idx + parent_layers as u16;
+                node.layer_idx = Self::layer_index(parent_layers, layer_idx)?;
                 node.overlay = Overlay::UpperAddition;
                 Ok(())
             })?;
 
             if let Some(tree) = &mut tree {
-                tree.merge_overaly(ctx, upper)?;
+                // Release the upper tree right away instead of lazily, otherwise resources of
+                // all source bootstraps are held when merging many layers.
+                tree.merge_overlay_tree(ctx, &upper)?;
             } else {
                 tree = Some(upper);
             }
@@ -298,30 +887,219 @@ impl Merger {
         }
 
         // Safe to unwrap because there is at least one source bootstrap.
-        let tree = tree.unwrap();
+        let mut tree = tree.unwrap();
+        let fs_version = target_version;
+        if ctx.merge_dedup_blobs {
+            let new_index = blob_mgr.dedup_by_digest();
+            Self::remap_blob_index(&tree, &new_index)?;
+            for idx in layer_blobs.iter_mut() {
+                *idx = new_index[*idx];
+            }
+        }
+        if ctx.deterministic_merge {
+            let new_index = blob_mgr.sort_by_blob_id();
+            Self::remap_blob_index(&tree, &new_index)?;
+            for idx in layer_blobs.iter_mut() {
+                *idx = new_index[*idx];
+            }
+        }
+        if let Some(rewriter) = tree_rewriter {
+            rewriter(&mut tree).context("failed to rewrite merged tree")?;
+        }
+        if ctx.merge_gc_blobs {
+            let referenced = Self::referenced_blobs(&tree)?;
+            let new_index = blob_mgr.retain_blobs(&referenced);
+            // Unreferenced blobs have no chunks to remap.
+            let new_index: Vec<usize> = new_index
+                .iter()
+                .map(|idx| idx.unwrap_or(usize::MAX))
+                .collect();
+            Self::remap_blob_index(&tree, &new_index)?;
+        }
+        // Never inherit a stale provenance record from the parent bootstrap.
+        let provenance_name = OsStr::new(MergeProvenance::XATTR_NAME);
+        tree.lock_node().remove_xattr(provenance_name);
+        if let Some(provenance) = &ctx.merge_provenance {
+            let mut provenance = provenance.clone();
+            for source in sources.iter() {
+                provenance.sources.push(source.digest()?);
+            }
+            if let Some(chunk_dict_path) = &chunk_dict {
+                provenance.chunk_dict = Some(MergeProvenance::digest_file(chunk_dict_path)?);
+            }
+            if !ctx.deterministic_merge {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+                provenance.timestamp = Some(now.as_secs());
+            }
+            tree.lock_node()
+                .add_xattr(provenance_name, provenance.to_xattr_value()?)
+                .context("failed to record provenance of merged bootstrap")?;
+        }
+        if ctx.merge_xattr_size_limit > 0 {
+            Self::check_xattr_size(&tree, fs_version, ctx.merge_xattr_size_limit)?;
+        }
         ctx.fs_version = fs_version;
         if let Some(chunk_size) = chunk_size {
             ctx.chunk_size = chunk_size;
         }
 
-        let mut bootstrap_ctx = BootstrapContext::new(Some(target.clone()), false)?;
+        let storage = (!ctx.merge_dry_run).then(|| target.clone());
+        let mut bootstrap_ctx = BootstrapContext::new(storage, false)?;
         let mut bootstrap = Bootstrap::new(tree)?;
         bootstrap.build(ctx, &mut bootstrap_ctx)?;
         let blob_table = blob_mgr.to_blob_table(ctx)?;
-        let mut bootstrap_storage = Some(target.clone());
-        bootstrap
-            .dump(ctx, &mut bootstrap_storage, &mut bootstrap_ctx, &blob_table)
-            .context(format!("dump bootstrap to {:?}", target.display()))?;
-        BuildOutput::new(&blob_mgr, &bootstrap_storage)
+        let mut bootstrap_storage = None;
+        if !ctx.merge_dry_run {
+            bootstrap_storage = Some(target.clone());
+            bootstrap
+                .dump(ctx, &mut bootstrap_storage, &mut bootstrap_ctx, &blob_table)
+                .context(format!("dump bootstrap to {:?}", target.display()))?;
+        }
+        let mut output = BuildOutput::new(&blob_mgr, &bootstrap_storage)?;
+        if ctx.preserve_layers {
+            output.layers = layer_blobs
+                .iter()
+                .filter_map(|idx| blob_mgr.get_blob(*idx))
+                .map(|b| BlobLayerDescriptor::new(&b.blob_id, b.compressed_blob_size))
+                .collect();
+        }
+        Ok(output)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use nydus_utils::digest;
+    use std::fs;
+    use std::path::Path;
+
+    use nydus_api::{BackendConfigV2, LocalFsConfig};
+    use nydus_storage::RAFS_DEFAULT_CHUNK_SIZE;
+    use nydus_utils::{compress, digest};
+    use sha2::{Digest, Sha256};
+    use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
     use super::*;
+    use crate::{
+        BootstrapManager, Builder, DirectoryBuilder, Features, Node, Prefetch, WhiteoutSpec,
+        MEDIA_TYPE_NYDUS_BLOB,
+    };
+
+    fn build_layer(src_dir: &Path, work_dir: &Path, name: &str) -> PathBuf {
+        build_layer_with_chunk_size(src_dir, work_dir, name, RAFS_DEFAULT_CHUNK_SIZE as u32)
+    }
+
+    fn build_layer_with_chunk_size(
+        src_dir: &Path,
+        work_dir: &Path,
+        name: &str,
+        chunk_size: u32,
+    ) -> PathBuf {
+        build_layer_with(
+            src_dir,
+            work_dir,
+            name,
+            chunk_size,
+            String::new(),
+            Features::new(),
+            RafsVersion::V6,
+        )
+    }
+
+    fn build_layer_with(
+        src_dir: &Path,
+        work_dir: &Path,
+        name: &str,
+        chunk_size: u32,
+        blob_id: String,
+        features: Features,
+        fs_version: RafsVersion,
+    ) -> PathBuf {
+        let bootstrap_path = work_dir.join(name);
+        let mut ctx = BuildContext::new(
+            blob_id,
+            true,
+            0,
+            compress::Algorithm::Zstd,
+            digest::Algorithm::Sha256,
+            true,
+            WhiteoutSpec::Oci,
+            ConversionType::DirectoryToRafs,
+            src_dir.to_path_buf(),
+            Prefetch::default(),
+            Some(ArtifactStorage::FileDir(work_dir.to_path_buf())),
+            false,
+            features,
+            false,
+        );
+        ctx.fs_version = fs_version;
+        ctx.chunk_size = chunk_size;
+        let mut bootstrap_mgr = BootstrapManager::new(
+            Some(ArtifactStorage::SingleFile(bootstrap_path.clone())),
+            None,
+        );
+        let mut blob_mgr = BlobManager::new(digest::Algorithm::Sha256);
+        DirectoryBuilder::new()
+            .build(&mut ctx, &mut bootstrap_mgr, &mut blob_mgr)
+            .unwrap();
+        bootstrap_path
+    }
+
+    fn new_merge_context() -> BuildContext {
+        let mut ctx = BuildContext::default();
+        ctx.configuration.internal.set_blob_accessible(true);
+        ctx.digester = digest::Algorithm::Sha256;
+        ctx
+    }
+
+    fn merge_layers(
+        ctx: &mut BuildContext,
+        sources: Vec<PathBuf>,
+        target: &Path,
+        config: ConfigV2,
+    ) -> Result<BuildOutput> {
+        Merger::merge(
+            ctx,
+            None,
+            sources,
+            None,
+            None,
+            None,
+            None,
+            None,
+            ArtifactStorage::SingleFile(target.to_path_buf()),
+            None,
+            Arc::new(config),
+        )
+    }
+
+    // Read data of file `path` in the merged bootstrap, from data blobs stored in `blob_dirs`.
+    fn read_merged_file(rs: &RafsSuper, tree: &Tree, path: &str, blob_dirs: &[&Path]) -> Vec<u8> {
+        let blobs = rs.superblock.get_blob_infos();
+        let node = tree.get_node(Path::new(path)).unwrap().lock_node();
+        let mut data = Vec::new();
+        for chunk in node.chunks.iter() {
+            let chunk = &chunk.inner;
+            let blob_id = blobs[chunk.blob_index() as usize].blob_id();
+            let blob_path = blob_dirs
+                .iter()
+                .map(|dir| dir.join(&blob_id))
+                .find(|p| p.exists())
+                .unwrap();
+            let blob = fs::read(blob_path).unwrap();
+            let start = chunk.compressed_offset() as usize;
+            let end = start + chunk.compressed_size() as usize;
+            let mut buf = vec![0u8; chunk.uncompressed_size() as usize];
+            if chunk.is_compressed() {
+                compress::decompress(&blob[start..end], &mut buf, rs.meta.get_compressor())
+                    .unwrap();
+            } else {
+                buf.copy_from_slice(&blob[start..end]);
+            }
+            data.extend_from_slice(&buf);
+        }
+        data
+    }
 
     #[test]
     fn test_merger_get_string_from_list() {
@@ -412,4 +1190,760 @@ mod tests {
         println!("BuildOutput: {}", build_output);
         assert_eq!(build_output.blob_size, Some(16));
     }
+
+    #[test]
+    fn test_merger_merge_preserve_layers() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("lower"), vec![0x5au8; 0x3000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("upper"), vec![0xa5u8; 0x3000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        let mut ctx = new_merge_context();
+        ctx.set_preserve_layers(true);
+        let target = work_dir.as_path().join("merged.boot");
+        let output =
+            merge_layers(&mut ctx, vec![lower, upper], &target, ConfigV2::default()).unwrap();
+
+        assert_eq!(output.blobs.len(), 2);
+        assert_eq!(output.layers.len(), 2);
+        assert_ne!(output.layers[0].digest, output.layers[1].digest);
+        for (layer, blob_id) in output.layers.iter().zip(output.blobs.iter()) {
+            assert_eq!(layer.media_type, MEDIA_TYPE_NYDUS_BLOB);
+            assert_eq!(layer.digest, format!("sha256:{}", blob_id));
+            assert!(layer.size > 0);
+        }
+    }
+
+    #[test]
+    fn test_merger_append_layer() {
+        let work_dir = TempDir::new().unwrap();
+        let mut layers = Vec::new();
+        for (idx, name) in ["lower", "middle", "upper"].iter().enumerate() {
+            let src_dir = TempDir::new().unwrap();
+            fs::write(src_dir.as_path().join(name), vec![idx as u8 + 1; 0x3000]).unwrap();
+            fs::write(
+                src_dir.as_path().join("shared"),
+                vec![idx as u8 + 1; 0x1000],
+            )
+            .unwrap();
+            let boot = format!("{}.boot", name);
+            layers.push(build_layer(src_dir.as_path(), work_dir.as_path(), &boot));
+        }
+        let config = Arc::new(ConfigV2::default());
+
+        let full = work_dir.as_path().join("full.boot");
+        let full_output = merge_layers(
+            &mut new_merge_context(),
+            layers.clone(),
+            &full,
+            ConfigV2::default(),
+        )
+        .unwrap();
+
+        let mut merged = work_dir.as_path().join("append0.boot");
+        merge_layers(
+            &mut new_merge_context(),
+            vec![layers[0].clone()],
+            &merged,
+            ConfigV2::default(),
+        )
+        .unwrap();
+        let mut output = None;
+        for (idx, layer) in layers.iter().enumerate().skip(1) {
+            let target = work_dir.as_path().join(format!("append{}.boot", idx));
+            output = Some(
+                Merger::append_layer(
+                    &mut new_merge_context(),
+                    merged,
+                    layer.clone(),
+                    None,
+                    None,
+                    None,
+                    ArtifactStorage::SingleFile(target.clone()),
+                    None,
+                    config.clone(),
+                )
+                .unwrap(),
+            );
+            merged = target;
+        }
+
+        assert_eq!(output.unwrap().blobs, full_output.blobs);
+        assert_eq!(fs::read(&merged).unwrap(), fs::read(&full).unwrap());
+
+        let mut ctx = new_merge_context();
+        ctx.set_deterministic_merge(true);
+        assert!(Merger::append_layer(
+            &mut ctx,
+            merged,
+            layers[0].clone(),
+            None,
+            None,
+            None,
+            ArtifactStorage::SingleFile(work_dir.as_path().join("invalid.boot")),
+            None,
+            config,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_merger_append_layer_chunk_size() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("lower"), vec![0x5au8; 0x3000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("upper"), vec![0xa5u8; 0x3000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer_with_chunk_size(
+            upper_dir.as_path(),
+            work_dir.as_path(),
+            "upper.boot",
+            0x1000,
+        );
+
+        let mut ctx = new_merge_context();
+        let res = Merger::append_layer(
+            &mut ctx,
+            lower,
+            upper,
+            None,
+            None,
+            None,
+            ArtifactStorage::SingleFile(work_dir.as_path().join("merged.boot")),
+            None,
+            Arc::new(ConfigV2::default()),
+        );
+        assert!(res.unwrap_err().to_string().contains("chunk size"));
+    }
+
+    #[test]
+    fn test_merger_merge_preserve_layers_shared_blob() {
+        let mut ctx = BuildContext::default();
+        ctx.configuration.internal.set_blob_accessible(false);
+        ctx.digester = digest::Algorithm::Sha256;
+        ctx.set_preserve_layers(true);
+
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let source_path =
+            PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let tmp_file = TempFile::new().unwrap();
+        let target = ArtifactStorage::SingleFile(tmp_file.as_path().to_path_buf());
+        let res = Merger::merge(
+            &mut ctx,
+            None,
+            vec![source_path.clone(), source_path],
+            None,
+            Some(vec!["blob_id".to_owned(), "blob_id2".to_owned()]),
+            None,
+            None,
+            None,
+            target,
+            None,
+            Arc::new(ConfigV2::new("config_v2")),
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_merger_merge_memory_sources() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("lower"), vec![0x5au8; 0x3000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("upper"), vec![0xa5u8; 0x3000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        let merge = |name: &str, sources: Vec<MergeSource>| {
+            let mut ctx = new_merge_context();
+            // Blob ids are derived from names of the source bootstraps.
+            ctx.configuration.internal.set_blob_accessible(false);
+            ctx.set_deterministic_merge(true);
+            let path = work_dir.as_path().join(name);
+            let output = Merger::merge_sources(
+                &mut ctx,
+                None,
+                sources,
+                ArtifactStorage::SingleFile(path.clone()),
+                MergeOptions::default(),
+            )
+            .unwrap();
+            (output, fs::read(path).unwrap())
+        };
+
+        let (output1, bootstrap1) = merge(
+            "merged1.boot",
+            vec![
+                MergeSource::File(lower.clone()),
+                MergeSource::File(upper.clone()),
+            ],
+        );
+        let (output2, bootstrap2) = merge(
+            "merged2.boot",
+            vec![
+                MergeSource::Memory {
+                    name: "lower.boot".to_string(),
+                    data: fs::read(&lower).unwrap(),
+                },
+                MergeSource::Memory {
+                    name: "upper.boot".to_string(),
+                    data: fs::read(&upper).unwrap(),
+                },
+            ],
+        );
+        assert_eq!(
+            output1.blobs,
+            vec!["lower".to_string(), "upper".to_string()]
+        );
+        assert_eq!(output1.blobs, output2.blobs);
+        assert_eq!(bootstrap1, bootstrap2);
+    }
+
+    #[test]
+    fn test_merger_merge_deterministic() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        let lower_file = lower_dir.as_path().join("lower");
+        fs::write(&lower_file, vec![0x5au8; 0x3000]).unwrap();
+        // Extended attributes of an inode get stored in an unspecified order if they are kept in
+        // a hash map, so merged bootstraps may differ.
+        for idx in 0..8 {
+            let name = format!("user.attr{}", idx);
+            if xattr::set(&lower_file, &name, &[idx as u8; 0x10]).is_err() {
+                // User extended attributes are not supported by the underlying filesystem.
+                return;
+            }
+        }
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("upper"), vec![0xa5u8; 0x3000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        let merge = |name: &str| {
+            let mut ctx = new_merge_context();
+            ctx.set_deterministic_merge(true);
+            let path = work_dir.as_path().join(name);
+            let sources = vec![lower.clone(), upper.clone()];
+            let output = merge_layers(&mut ctx, sources, &path, ConfigV2::default()).unwrap();
+            (output, fs::read(path).unwrap())
+        };
+
+        let (output1, bootstrap1) = merge("merged1.boot");
+        let (output2, bootstrap2) = merge("merged2.boot");
+        assert_eq!(output1.blobs.len(), 2);
+        assert!(output1.blobs[0] < output1.blobs[1]);
+        assert_eq!(output1.blobs, output2.blobs);
+        assert_eq!(
+            digest::RafsDigest::from_buf(&bootstrap1, digest::Algorithm::Sha256),
+            digest::RafsDigest::from_buf(&bootstrap2, digest::Algorithm::Sha256)
+        );
+
+        // Chunks should still reference data blobs of the layers they come from.
+        let config = Arc::new(ConfigV2::default());
+        let get_blob_id = |path: &Path| {
+            let (rs, _) = RafsSuper::load_from_file(path, config.clone(), false).unwrap();
+            rs.superblock.get_blob_infos()[0].blob_id()
+        };
+        let lower_blob_id = get_blob_id(&lower);
+        let upper_blob_id = get_blob_id(&upper);
+        let merged = work_dir.as_path().join("merged1.boot");
+        let (rs, _) = RafsSuper::load_from_file(&merged, config.clone(), false).unwrap();
+        let blobs = rs.superblock.get_blob_infos();
+        let tree = Tree::from_bootstrap(&rs, &mut ()).unwrap();
+        let mut count = 0;
+        tree.walk_bfs(true, &mut |n| {
+            let node = n.lock_node();
+            let expected = match node.name().to_str() {
+                Some("lower") => &lower_blob_id,
+                Some("upper") => &upper_blob_id,
+                _ => return Ok(()),
+            };
+            for chunk in node.chunks.iter() {
+                assert_eq!(
+                    &blobs[chunk.inner.blob_index() as usize].blob_id(),
+                    expected
+                );
+                count += 1;
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_merger_merge_dedup_blobs() {
+        let work_dir = TempDir::new().unwrap();
+        let src_dir = TempDir::new().unwrap();
+        fs::write(src_dir.as_path().join("file"), vec![0x5au8; 0x3000]).unwrap();
+        // Two layers sharing an identical blob, but with different blob ids.
+        let layers: Vec<PathBuf> = ["a", "b"]
+            .iter()
+            .map(|id| {
+                build_layer_with(
+                    src_dir.as_path(),
+                    work_dir.as_path(),
+                    &format!("{}.boot", id),
+                    RAFS_DEFAULT_CHUNK_SIZE as u32,
+                    id.repeat(64),
+                    Features::try_from("blob-toc").unwrap(),
+                    RafsVersion::V6,
+                )
+            })
+            .collect();
+
+        let merge = |dedup: bool| {
+            let mut ctx = new_merge_context();
+            ctx.set_merge_dedup_blobs(dedup);
+            let path = work_dir.as_path().join("merged.boot");
+            let output =
+                merge_layers(&mut ctx, layers.clone(), &path, ConfigV2::default()).unwrap();
+
+            let (rs, _) =
+                RafsSuper::load_from_file(&path, Arc::new(ConfigV2::default()), false).unwrap();
+            assert_eq!(rs.superblock.get_blob_infos().len(), output.blobs.len());
+            let tree = Tree::from_bootstrap(&rs, &mut ()).unwrap();
+            tree.walk_bfs(true, &mut |n| {
+                for chunk in n.lock_node().chunks.iter() {
+                    assert!((chunk.inner.blob_index() as usize) < output.blobs.len());
+                }
+                Ok(())
+            })
+            .unwrap();
+            output.blobs
+        };
+
+        assert_eq!(merge(false).len(), 2);
+        assert_eq!(merge(true), vec!["a".repeat(64)]);
+    }
+
+    #[test]
+    fn test_merger_merge_xattr_size_limit() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        let lower_file = lower_dir.as_path().join("lower");
+        fs::write(&lower_file, vec![0x5au8; 0x3000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        let upper_file = upper_dir.as_path().join("upper");
+        fs::write(&upper_file, vec![0xa5u8; 0x3000]).unwrap();
+        if xattr::set(&lower_file, "user.lower", &[0x1u8; 0x40]).is_err() {
+            // User extended attributes are not supported by the underlying filesystem.
+            return;
+        }
+        xattr::set(&upper_file, "user.upper1", &[0x2u8; 0x80]).unwrap();
+        xattr::set(&upper_file, "user.upper2", &[0x3u8; 0x80]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        let target = work_dir.as_path().join("merged.boot");
+        let merge = |limit: u64| {
+            let mut ctx = new_merge_context();
+            ctx.set_merge_xattr_size_limit(limit);
+            let sources = vec![lower.clone(), upper.clone()];
+            merge_layers(&mut ctx, sources, &target, ConfigV2::default())
+        };
+
+        assert!(merge(0).is_ok());
+        assert!(merge(0x200).is_ok());
+        let err = format!("{:?}", merge(0x100).unwrap_err());
+        assert!(err.contains("/upper"));
+        assert!(err.contains("exceeding the limit of 256 bytes"));
+    }
+
+    #[test]
+    fn test_merger_merge_gc_blobs() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("file"), vec![0x5au8; 0x3000]).unwrap();
+        // The upper layer overwrites all files of the lower layer.
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("file"), vec![0xa5u8; 0x3000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        let merge = |gc: bool| {
+            let mut ctx = new_merge_context();
+            // Blob ids are derived from names of the source bootstraps.
+            ctx.configuration.internal.set_blob_accessible(false);
+            ctx.set_merge_gc_blobs(gc);
+            let path = work_dir.as_path().join("merged.boot");
+            let sources = vec![lower.clone(), upper.clone()];
+            let output = merge_layers(&mut ctx, sources, &path, ConfigV2::default()).unwrap();
+
+            let (rs, _) =
+                RafsSuper::load_from_file(&path, Arc::new(ConfigV2::default()), false).unwrap();
+            let blobs = rs.superblock.get_blob_infos();
+            assert_eq!(blobs.len(), output.blobs.len());
+            let tree = Tree::from_bootstrap(&rs, &mut ()).unwrap();
+            tree.walk_bfs(true, &mut |n| {
+                for chunk in n.lock_node().chunks.iter() {
+                    let blob = &blobs[chunk.inner.blob_index() as usize];
+                    assert_eq!(blob.blob_id(), "upper");
+                }
+                Ok(())
+            })
+            .unwrap();
+            output.blobs
+        };
+
+        assert_eq!(merge(false), vec!["lower".to_string(), "upper".to_string()]);
+        assert_eq!(merge(true), vec!["upper".to_string()]);
+    }
+
+    #[test]
+    fn test_merger_merge_dry_run() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("lower"), vec![0x5au8; 0x50000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("upper"), vec![0xa5u8; 0x50000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+        let other = build_layer_with_chunk_size(
+            upper_dir.as_path(),
+            work_dir.as_path(),
+            "other.boot",
+            0x40000,
+        );
+
+        let blob_dir = TempDir::new().unwrap();
+        let target = work_dir.as_path().join("merged.boot");
+        let merge = |sources: Vec<PathBuf>, rechunk: bool| {
+            let mut ctx = new_merge_context();
+            ctx.blob_storage = Some(ArtifactStorage::FileDir(blob_dir.as_path().to_path_buf()));
+            ctx.set_merge_rechunk(rechunk);
+            ctx.set_merge_dry_run(true);
+            merge_layers(&mut ctx, sources, &target, ConfigV2::default())
+        };
+
+        let output = merge(vec![lower.clone(), upper.clone()], false).unwrap();
+        assert_eq!(output.blobs.len(), 2);
+        assert!(output.bootstrap_path.is_none());
+        assert!(!target.exists());
+
+        // Checks still run in dry-run mode.
+        assert!(merge(vec![lower.clone(), other.clone()], false).is_err());
+        assert!(!target.exists());
+
+        // No data blob is generated for layers to be re-chunked.
+        let output = merge(vec![lower, other], true).unwrap();
+        assert_eq!(output.blobs.len(), 1);
+        assert!(!target.exists());
+        assert_eq!(fs::read_dir(blob_dir.as_path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_merger_merge_provenance() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("lower"), vec![0x5au8; 0x50000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("upper"), vec![0xa5u8; 0x50000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        let target = work_dir.as_path().join("merged.boot");
+        let merge = |provenance: bool, deterministic: bool| {
+            let mut ctx = new_merge_context();
+            ctx.set_deterministic_merge(deterministic);
+            if provenance {
+                ctx.set_merge_provenance("ci@example.com", "v2.2.0");
+            }
+            let sources = vec![upper.clone(), lower.clone()];
+            merge_layers(&mut ctx, sources, &target, ConfigV2::default()).unwrap();
+        };
+
+        // No provenance is recorded unless requested.
+        merge(false, false);
+        assert!(MergeProvenance::load(&target).unwrap().is_none());
+
+        merge(true, false);
+        let provenance = MergeProvenance::load(&target).unwrap().unwrap();
+        assert_eq!(provenance.builder, "ci@example.com");
+        assert_eq!(provenance.tool_version, "v2.2.0");
+        assert!(provenance.timestamp.is_some());
+        assert!(provenance.chunk_dict.is_none());
+        let digest = |path: &Path| {
+            let data = fs::read(path).unwrap();
+            format!("sha256:{}", hex::encode(Sha256::digest(data)))
+        };
+        assert_eq!(provenance.sources, vec![digest(&upper), digest(&lower)]);
+
+        // The timestamp is omitted for deterministic merge.
+        merge(true, true);
+        let provenance = MergeProvenance::load(&target).unwrap().unwrap();
+        assert!(provenance.timestamp.is_none());
+        assert_eq!(provenance.sources, vec![digest(&upper), digest(&lower)]);
+    }
+
+    #[test]
+    fn test_merger_merge_rechunk() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        let lower_data: Vec<u8> = (0..0x50000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(lower_dir.as_path().join("lower"), &lower_data).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        let upper_data: Vec<u8> = (0..0x180123u32).map(|i| (i % 253) as u8).collect();
+        fs::write(upper_dir.as_path().join("upper"), &upper_data).unwrap();
+        let lower = build_layer_with_chunk_size(
+            lower_dir.as_path(),
+            work_dir.as_path(),
+            "lower.boot",
+            0x40000,
+        );
+        let upper = build_layer_with_chunk_size(
+            upper_dir.as_path(),
+            work_dir.as_path(),
+            "upper.boot",
+            0x100000,
+        );
+
+        let merge = |rechunk: bool, blob_dir: &Path| {
+            let mut ctx = new_merge_context();
+            ctx.blob_storage = Some(ArtifactStorage::FileDir(blob_dir.to_path_buf()));
+            ctx.set_merge_rechunk(rechunk);
+            let config = ConfigV2 {
+                backend: Some(BackendConfigV2 {
+                    backend_type: "localfs".to_owned(),
+                    localfs: Some(LocalFsConfig {
+                        dir: work_dir.as_path().to_str().unwrap().to_owned(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let sources = vec![lower.clone(), upper.clone()];
+            merge_layers(&mut ctx, sources, &blob_dir.join("merged.boot"), config)
+        };
+
+        let blob_dir = TempDir::new().unwrap();
+        assert!(merge(false, blob_dir.as_path()).is_err());
+        let output = merge(true, blob_dir.as_path()).unwrap();
+        assert_eq!(output.blobs.len(), 2);
+
+        let merged = blob_dir.as_path().join("merged.boot");
+        let (rs, _) =
+            RafsSuper::load_from_file(&merged, Arc::new(ConfigV2::default()), false).unwrap();
+        assert_eq!(rs.meta.chunk_size, 0x40000);
+        let tree = Tree::from_bootstrap(&rs, &mut ()).unwrap();
+        tree.walk_bfs(true, &mut |n| {
+            for chunk in n.lock_node().chunks.iter() {
+                assert!(chunk.inner.uncompressed_size() <= 0x40000);
+            }
+            Ok(())
+        })
+        .unwrap();
+        let blob_dirs = [work_dir.as_path(), blob_dir.as_path()];
+        assert_eq!(
+            read_merged_file(&rs, &tree, "/lower", &blob_dirs),
+            lower_data
+        );
+        assert_eq!(
+            read_merged_file(&rs, &tree, "/upper", &blob_dirs),
+            upper_data
+        );
+    }
+
+    #[test]
+    fn test_merger_merge_upgrade_v5() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        let lower_data: Vec<u8> = (0..0x1800u32).map(|i| (i % 251) as u8).collect();
+        fs::write(lower_dir.as_path().join("lower"), &lower_data).unwrap();
+        fs::write(lower_dir.as_path().join("file"), b"lower").unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        let upper_data: Vec<u8> = (0..0x2345u32).map(|i| (i % 253) as u8).collect();
+        fs::write(upper_dir.as_path().join("upper"), &upper_data).unwrap();
+        fs::write(upper_dir.as_path().join("file"), b"upper").unwrap();
+        let lower = build_layer_with(
+            lower_dir.as_path(),
+            work_dir.as_path(),
+            "lower.boot",
+            0x1000,
+            String::new(),
+            Features::new(),
+            RafsVersion::V5,
+        );
+        let upper = build_layer_with(
+            upper_dir.as_path(),
+            work_dir.as_path(),
+            "upper.boot",
+            0x1000,
+            String::new(),
+            Features::new(),
+            RafsVersion::V6,
+        );
+
+        let merge = |sources: Vec<PathBuf>, blob_dir: &Path| {
+            let mut ctx = new_merge_context();
+            ctx.blob_storage = Some(ArtifactStorage::FileDir(blob_dir.to_path_buf()));
+            let config = ConfigV2 {
+                backend: Some(BackendConfigV2 {
+                    backend_type: "localfs".to_owned(),
+                    localfs: Some(LocalFsConfig {
+                        dir: work_dir.as_path().to_str().unwrap().to_owned(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            merge_layers(&mut ctx, sources, &blob_dir.join("merged.boot"), config)
+        };
+
+        let blob_dir = TempDir::new().unwrap();
+        assert!(merge(vec![upper.clone(), lower.clone()], blob_dir.as_path()).is_err());
+        let output = merge(vec![lower, upper], blob_dir.as_path()).unwrap();
+        assert_eq!(output.blobs.len(), 2);
+
+        let merged = blob_dir.as_path().join("merged.boot");
+        let (rs, _) =
+            RafsSuper::load_from_file(&merged, Arc::new(ConfigV2::default()), false).unwrap();
+        assert!(rs.meta.is_v6());
+        let tree = Tree::from_bootstrap(&rs, &mut ()).unwrap();
+        let blob_dirs = [work_dir.as_path(), blob_dir.as_path()];
+        let read_file = |path: &str| read_merged_file(&rs, &tree, path, &blob_dirs);
+        assert_eq!(read_file("/lower"), lower_data);
+        assert_eq!(read_file("/upper"), upper_data);
+        assert_eq!(read_file("/file"), b"upper");
+    }
+
+    #[test]
+    fn test_merger_layer_index() {
+        assert_eq!(Merger::layer_index(0, 0).unwrap(), 0);
+        assert_eq!(Merger::layer_index(3, 2).unwrap(), 5);
+        // Layer indexes beyond `u16::MAX` are distinct.
+        let idx = Merger::layer_index(u16::MAX as usize, 1).unwrap();
+        assert_eq!(idx, u16::MAX as u32 + 1);
+        assert_ne!(idx, Merger::layer_index(0, 0).unwrap());
+        assert_eq!(
+            Merger::layer_index(u32::MAX as usize - 1, 1).unwrap(),
+            u32::MAX
+        );
+        assert!(Merger::layer_index(u32::MAX as usize, 1).is_err());
+        assert!(Merger::layer_index(usize::MAX, 1).is_err());
+    }
+
+    // Merging tens of thousands of layers takes a while, layer indexes beyond `u16::MAX` are
+    // covered by `test_merger_layer_index`. Run it explicitly by `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn test_merger_merge_many_layers() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("file"), vec![0x5au8; 0x1000]).unwrap();
+        fs::write(lower_dir.as_path().join("lower"), vec![0x5au8; 0x1000]).unwrap();
+        let middle_dir = TempDir::new().unwrap();
+        fs::write(middle_dir.as_path().join("middle"), b"").unwrap();
+        let wrap_dir = TempDir::new().unwrap();
+        fs::write(wrap_dir.as_path().join("wrap"), vec![0x3cu8; 0x1000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("lower"), vec![0xa5u8; 0x1000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let middle = build_layer(middle_dir.as_path(), work_dir.as_path(), "middle.boot");
+        let wrap = build_layer(wrap_dir.as_path(), work_dir.as_path(), "wrap.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        // More layers than `u16::MAX`, all but three of them share one trivial bootstrap. The
+        // layer index of the `wrap` layer truncated to u16 equals the one of the lowest layer,
+        // and `/wrap` has the same inode number as `/file` in the lowest layer.
+        let layers = 70000;
+        let mut sources = vec![middle; layers];
+        sources[0] = lower.clone();
+        sources[u16::MAX as usize + 1] = wrap.clone();
+        sources[layers - 1] = upper.clone();
+        let merged = work_dir.as_path().join("merged.boot");
+        merge_layers(
+            &mut new_merge_context(),
+            sources,
+            &merged,
+            ConfigV2::default(),
+        )
+        .unwrap();
+
+        let config = Arc::new(ConfigV2::default());
+        let blob_id = |path: &Path| {
+            let (rs, _) = RafsSuper::load_from_file(path, config.clone(), false).unwrap();
+            rs.superblock.get_blob_infos()[0].blob_id()
+        };
+        let (rs, _) = RafsSuper::load_from_file(&merged, config.clone(), false).unwrap();
+        let blobs = rs.superblock.get_blob_infos();
+        assert_eq!(blobs.len(), 3);
+        let tree = Tree::from_bootstrap(&rs, &mut ()).unwrap();
+        assert!(tree.get_node(Path::new("/middle")).is_some());
+        let file_blob = |name: &str| {
+            let node = tree.get_node(Path::new(name)).unwrap().lock_node();
+            assert_eq!(node.chunks.len(), 1);
+            let blob_index = node.chunks[0].inner.blob_index() as usize;
+            (node.inode.ino(), blobs[blob_index].blob_id())
+        };
+
+        // Files from layers with the same truncated layer index are not taken as hardlinks.
+        let (file_ino, file_blob_id) = file_blob("/file");
+        let (wrap_ino, wrap_blob_id) = file_blob("/wrap");
+        assert_ne!(file_ino, wrap_ino);
+        assert_eq!(file_blob_id, blob_id(&lower));
+        assert_eq!(wrap_blob_id, blob_id(&wrap));
+        // The file in the topmost layer should win.
+        assert_eq!(file_blob("/lower").1, blob_id(&upper));
+    }
+
+    #[test]
+    fn test_merger_merge_tree_rewriter() {
+        let work_dir = TempDir::new().unwrap();
+        let lower_dir = TempDir::new().unwrap();
+        fs::write(lower_dir.as_path().join("lower"), vec![0x5au8; 0x3000]).unwrap();
+        let upper_dir = TempDir::new().unwrap();
+        fs::write(upper_dir.as_path().join("upper"), vec![0xa5u8; 0x3000]).unwrap();
+        let lower = build_layer(lower_dir.as_path(), work_dir.as_path(), "lower.boot");
+        let upper = build_layer(upper_dir.as_path(), work_dir.as_path(), "upper.boot");
+
+        let inject_dir = TempDir::new().unwrap();
+        let inject_path = inject_dir.as_path().join("injected");
+        fs::write(&inject_path, b"").unwrap();
+        let source = inject_dir.as_path().to_path_buf();
+        let rewriter: TreeRewriter = Box::new(move |tree: &mut Tree| {
+            let node = Node::from_fs_object(
+                RafsVersion::V6,
+                source,
+                inject_path,
+                Overlay::UpperAddition,
+                RAFS_DEFAULT_CHUNK_SIZE as u32,
+                true,
+                false,
+            )?;
+            tree.insert_child(Tree::new(node));
+            let idx = tree.get_child_idx(b"lower").unwrap();
+            tree.children.remove(idx);
+            Ok(())
+        });
+
+        let mut ctx = new_merge_context();
+        let merged = work_dir.as_path().join("merged.boot");
+        Merger::merge_sources(
+            &mut ctx,
+            None,
+            vec![MergeSource::File(lower), MergeSource::File(upper)],
+            ArtifactStorage::SingleFile(merged.clone()),
+            MergeOptions {
+                tree_rewriter: Some(rewriter),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let (rs, _) =
+            RafsSuper::load_from_file(&merged, Arc::new(ConfigV2::default()), false).unwrap();
+        let tree = Tree::from_bootstrap(&rs, &mut ()).unwrap();
+        let injected = tree.get_node(Path::new("/injected")).unwrap().lock_node();
+        assert!(injected.is_reg());
+        assert_eq!(injected.inode.size(), 0);
+        assert!(tree.get_node(Path::new("/upper")).is_some());
+        assert!(tree.get_node(Path::new("/lower")).is_none());
+    }
 }
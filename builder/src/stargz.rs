@@ -409,7 +409,7 @@ impl StargzBuilder {
         }
     }
 
-    fn build_tree(&mut self, ctx: &mut BuildContext, layer_idx: u16) -> Result<Tree> {
+    fn build_tree(&mut self, ctx: &mut BuildContext, layer_idx: u32) -> Result<Tree> {
         let toc_index = TocIndex::load(&ctx.source_path, 0)?;
         if toc_index.version != 1 {
             bail!("stargz: TOC version {} is unsupported", toc_index.version);
@@ -844,7 +844,7 @@ impl Builder for StargzBuilder {
             Box::<NoopArtifactWriter>::default()
         };
         let mut bootstrap_ctx = bootstrap_mgr.create_ctx()?;
-        let layer_idx = u16::from(bootstrap_ctx.layered);
+        let layer_idx = u32::from(bootstrap_ctx.layered);
 
         // Build filesystem tree from the stargz TOC.
         let tree = timing_tracer!({ self.build_tree(ctx, layer_idx) }, "build_tree")?;
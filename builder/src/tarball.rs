@@ -113,7 +113,7 @@ impl<'a> TarballTreeBuilder<'a> {
         ctx: &'a mut BuildContext,
         blob_mgr: &'a mut BlobManager,
         blob_writer: &'a mut dyn Artifact,
-        layer_idx: u16,
+        layer_idx: u32,
     ) -> Self {
         let builder = TarBuilder::new(ctx.explicit_uidgid, layer_idx, ctx.fs_version);
         Self {
@@ -581,7 +581,7 @@ impl Builder for TarballBuilder {
         blob_mgr: &mut BlobManager,
     ) -> Result<BuildOutput> {
         let mut bootstrap_ctx = bootstrap_mgr.create_ctx()?;
-        let layer_idx = u16::from(bootstrap_ctx.layered);
+        let layer_idx = u32::from(bootstrap_ctx.layered);
         let mut blob_writer: Box<dyn Artifact> = match self.ty {
             ConversionType::EStargzToRafs
             | ConversionType::EStargzToRef
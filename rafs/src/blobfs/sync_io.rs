@@ -51,6 +51,8 @@ impl BlobFs {
             blob_id,
             offset,
             len,
+            merging_size: None,
+            bandwidth_rate: None,
         };
 
         self.state.fetch_range_sync(&[req]).map_err(|e| {
@@ -31,6 +31,7 @@ use fuse_backend_rs::api::BackendFileSystem;
 use nix::unistd::{getegid, geteuid};
 
 use nydus_api::ConfigV2;
+use nydus_storage::access_trace::AccessTrace;
 use nydus_storage::device::{BlobDevice, BlobIoVec, BlobPrefetchRequest};
 use nydus_storage::{RAFS_DEFAULT_CHUNK_SIZE, RAFS_MAX_CHUNK_SIZE};
 use nydus_utils::{
@@ -67,6 +68,8 @@ pub struct Rafs {
     digest_validate: bool,
     fs_prefetch: bool,
     prefetch_all: bool,
+    prefetch_hint_xattr: Option<OsString>,
+    prefetch_trace_file: Option<PathBuf>,
     xattr_enabled: bool,
     user_io_batch_size: u32,
 
@@ -104,6 +107,16 @@ impl Rafs {
             fs_prefetch: rafs_cfg.prefetch.enable,
             user_io_batch_size: rafs_cfg.user_io_batch_size as u32,
             prefetch_all: rafs_cfg.prefetch.prefetch_all,
+            prefetch_hint_xattr: if rafs_cfg.prefetch.hint_xattr.is_empty() {
+                None
+            } else {
+                Some(OsString::from(&rafs_cfg.prefetch.hint_xattr))
+            },
+            prefetch_trace_file: if rafs_cfg.prefetch.trace_file.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(&rafs_cfg.prefetch.trace_file))
+            },
             xattr_enabled: rafs_cfg.enable_xattr,
 
             i_uid: geteuid().into(),
@@ -332,10 +345,47 @@ impl Rafs {
         let sb = self.sb.clone();
         let device = self.device.clone();
         let prefetch_all = self.prefetch_all;
+        let hint_xattr = self.prefetch_hint_xattr.clone();
+        let trace_file = self.prefetch_trace_file.clone();
         let root_ino = self.root_ino();
 
         let _ = std::thread::spawn(move || {
-            Self::do_prefetch(root_ino, reader, prefetch_files, prefetch_all, sb, device);
+            // Chunks in the access trace are prefetched first, in the recorded access order.
+            if let Some(path) = trace_file {
+                Self::prefetch_trace(&path, &sb, &device);
+            }
+            Self::do_prefetch(
+                root_ino,
+                reader,
+                prefetch_files,
+                prefetch_all,
+                hint_xattr,
+                sb,
+                device,
+            );
+        });
+    }
+
+    fn prefetch_trace(path: &Path, sb: &RafsSuper, device: &BlobDevice) {
+        let trace = match AccessTrace::from_file(path) {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("failed to load access trace {}, {}", path.display(), e);
+                return;
+            }
+        };
+        let blob_infos = sb.superblock.get_blob_infos();
+        let io_vecs = trace.to_io_vecs(&blob_infos, |blob_index, chunk_index| {
+            device.get_chunk_info(blob_index, chunk_index)
+        });
+        info!(
+            "prefetch {} chunks by access trace {}",
+            io_vecs.iter().map(|v| v.len()).sum::<usize>(),
+            path.display()
+        );
+        let io_vecs = io_vecs.iter().collect::<Vec<_>>();
+        device.prefetch(&io_vecs, &[]).unwrap_or_else(|e| {
+            warn!("Prefetch error, {:?}", e);
         });
     }
 
@@ -353,6 +403,7 @@ impl Rafs {
         mut reader: RafsIoReader,
         prefetch_files: Option<Vec<PathBuf>>,
         prefetch_all: bool,
+        hint_xattr: Option<OsString>,
         sb: Arc<RafsSuper>,
         device: BlobDevice,
     ) {
@@ -372,6 +423,8 @@ impl Rafs {
                             blob_id: blob.blob_id().to_owned(),
                             offset,
                             len,
+                            merging_size: None,
+                            bandwidth_rate: None,
                         });
                         offset += len;
                     }
@@ -415,6 +468,28 @@ impl Rafs {
 
         let mut ignore_prefetch_all = false;
 
+        // Files marked by the prefetch hint xattr are prefetched in addition to the prefetch list.
+        if let Some(name) = hint_xattr.as_ref() {
+            if !startup_prefetch_all && !inlay_prefetch_all && !prefetch_all {
+                match sb.get_prefetch_hinted_inodes(root_ino, name) {
+                    Ok(inodes) if !inodes.is_empty() => {
+                        info!("prefetch {} files marked by xattr {:?}", inodes.len(), name);
+                        if let Err(e) = sb.prefetch_files(
+                            &device,
+                            &mut reader,
+                            root_ino,
+                            Some(inodes),
+                            &fetcher,
+                        ) {
+                            info!("Failed to prefetch hinted files {:?}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Failed to collect prefetch hinted files, {}", e),
+                }
+            }
+        }
+
         // User specified prefetch files have high priority to be prefetched.
         // Moreover, user specified prefetch files list will override those on-disk prefetch table.
         if !startup_prefetch_all && !inlay_prefetch_all {
@@ -452,6 +527,8 @@ impl Rafs {
                             blob_id: blob.blob_id().to_owned(),
                             offset: pre_offset,
                             len: cmp::min(batch_size, blob_size - pre_offset),
+                            merging_size: None,
+                            bandwidth_rate: None,
                         };
                         device
                             .prefetch(&[], &[req])
@@ -1042,6 +1119,8 @@ mod tests {
             digest_validate: false,
             fs_prefetch: false,
             prefetch_all: false,
+            prefetch_hint_xattr: None,
+            prefetch_trace_file: None,
             xattr_enabled: false,
             user_io_batch_size: 0,
             i_uid: 0,
@@ -24,7 +24,7 @@ use fuse_backend_rs::api::filesystem::Entry;
 use nydus_storage::device::v5::BlobV5ChunkInfo;
 use nydus_storage::device::{BlobChunkFlags, BlobChunkInfo, BlobDevice, BlobInfo};
 use nydus_utils::digest::RafsDigest;
-use nydus_utils::ByteSize;
+use nydus_utils::{compress, ByteSize};
 
 use crate::metadata::inode::RafsInodeFlags;
 use crate::metadata::layout::v5::{
@@ -761,6 +761,12 @@ impl BlobChunkInfo for CachedChunkInfoV5 {
         false
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.flags
+            .contains(BlobChunkFlags::LZ4_BLOCK)
+            .then_some(compress::Algorithm::Lz4Block)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -248,6 +248,27 @@ impl ChunkWrapper {
         }
     }
 
+    /// Check whether the chunk is compressed by lz4_block instead of the blob compressor.
+    pub fn is_lz4_block(&self) -> bool {
+        match self {
+            ChunkWrapper::V5(c) => c.flags.contains(BlobChunkFlags::LZ4_BLOCK),
+            ChunkWrapper::V6(c) => c.flags.contains(BlobChunkFlags::LZ4_BLOCK),
+            ChunkWrapper::Ref(c) => as_blob_v5_chunk_info(c.deref())
+                .flags()
+                .contains(BlobChunkFlags::LZ4_BLOCK),
+        }
+    }
+
+    /// Set flag for whether chunk is compressed by lz4_block instead of the blob compressor.
+    pub fn set_lz4_block(&mut self, lz4_block: bool) {
+        self.ensure_owned();
+        match self {
+            ChunkWrapper::V5(c) => c.flags.set(BlobChunkFlags::LZ4_BLOCK, lz4_block),
+            ChunkWrapper::V6(c) => c.flags.set(BlobChunkFlags::LZ4_BLOCK, lz4_block),
+            ChunkWrapper::Ref(_c) => panic!("unexpected"),
+        }
+    }
+
     /// Check whether the chunk is encrypted or not.
     pub fn is_encrypted(&self) -> bool {
         match self {
@@ -359,6 +380,14 @@ impl ChunkWrapper {
         }
     }
 
+    /// Convert RAFS v5 chunk information into RAFS v6 representation.
+    pub fn upgrade_to_v6(&mut self) {
+        self.ensure_owned();
+        if let ChunkWrapper::V5(c) = self {
+            *self = ChunkWrapper::V6(*c);
+        }
+    }
+
     /// Store the chunk information object into RAFS metadata blob.
     pub fn store(&self, w: &mut dyn RafsIoWrite) -> Result<usize> {
         match self {
@@ -34,7 +34,7 @@ use nydus_storage::device::{
 };
 use nydus_storage::utils::readahead;
 use nydus_utils::filemap::{clone_file, FileMapState};
-use nydus_utils::{digest::RafsDigest, div_round_up, round_up};
+use nydus_utils::{compress, digest::RafsDigest, div_round_up, round_up};
 
 use crate::metadata::layout::v5::RafsV5ChunkInfo;
 use crate::metadata::layout::v6::{
@@ -1461,6 +1461,14 @@ impl BlobChunkInfo for DirectChunkInfoV6 {
             .contains(BlobChunkFlags::ENCYPTED)
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        let state = self.state();
+        self.v5_chunk(&state)
+            .flags
+            .contains(BlobChunkFlags::LZ4_BLOCK)
+            .then_some(compress::Algorithm::Lz4Block)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
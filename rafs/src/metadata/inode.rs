@@ -579,6 +579,14 @@ impl InodeWrapper {
         inode_size + xattrs.aligned_size_v6()
     }
 
+    /// Convert a RAFS v5 inode into RAFS v6 representation.
+    pub fn upgrade_to_v6(&mut self) {
+        self.ensure_owned();
+        if let InodeWrapper::V5(i) = self {
+            *self = InodeWrapper::V6(RafsV6Inode::from(&*i));
+        }
+    }
+
     fn ensure_owned(&mut self) {
         if let Self::Ref(i) = self {
             let i = i.clone();
@@ -739,6 +747,28 @@ impl From<&dyn RafsInodeExt> for RafsV6Inode {
     }
 }
 
+impl From<&RafsV5Inode> for RafsV6Inode {
+    fn from(inode: &RafsV5Inode) -> Self {
+        RafsV6Inode {
+            i_ino: inode.i_ino,
+            i_uid: inode.i_uid,
+            i_gid: inode.i_gid,
+            i_projid: inode.i_projid,
+            i_mode: inode.i_mode,
+            i_size: inode.i_size,
+            i_blocks: inode.i_blocks,
+            i_flags: inode.i_flags,
+            i_nlink: inode.i_nlink,
+            i_child_count: inode.i_child_count,
+            i_name_size: inode.i_name_size,
+            i_symlink_size: inode.i_symlink_size,
+            i_rdev: inode.i_rdev,
+            i_mtime_nsec: inode.i_mtime_nsec,
+            i_mtime: inode.i_mtime,
+        }
+    }
+}
+
 bitflags! {
     /// Rafs v5 inode flags.
     pub struct RafsInodeFlags: u64 {
@@ -228,6 +228,7 @@ impl RafsV5SuperBlock {
         self.s_flags &= !RafsSuperFlags::COMPRESSION_LZ4.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_GZIP.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_ZSTD.bits();
+        self.s_flags &= !RafsSuperFlags::COMPRESSION_BROTLI.bits();
         self.s_flags |= c.bits();
     }
 
@@ -237,6 +238,7 @@ impl RafsV5SuperBlock {
 
         self.s_flags &= !RafsSuperFlags::HASH_BLAKE3.bits();
         self.s_flags &= !RafsSuperFlags::HASH_SHA256.bits();
+        self.s_flags &= !RafsSuperFlags::HASH_XXH64.bits();
         self.s_flags |= c.bits();
     }
 
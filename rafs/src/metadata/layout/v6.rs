@@ -426,7 +426,8 @@ impl RafsV6SuperBlockExt {
         flags &= RafsSuperFlags::COMPRESSION_NONE.bits()
             | RafsSuperFlags::COMPRESSION_LZ4.bits()
             | RafsSuperFlags::COMPRESSION_GZIP.bits()
-            | RafsSuperFlags::COMPRESSION_ZSTD.bits();
+            | RafsSuperFlags::COMPRESSION_ZSTD.bits()
+            | RafsSuperFlags::COMPRESSION_BROTLI.bits();
         if flags.count_ones() != 1 {
             return Err(einval!(format!(
                 "invalid flags {:#x} related to compression algorithm in Rafs v6 extended superblock",
@@ -435,7 +436,9 @@ impl RafsV6SuperBlockExt {
         }
 
         let mut flags = self.flags();
-        flags &= RafsSuperFlags::HASH_BLAKE3.bits() | RafsSuperFlags::HASH_SHA256.bits();
+        flags &= RafsSuperFlags::HASH_BLAKE3.bits()
+            | RafsSuperFlags::HASH_SHA256.bits()
+            | RafsSuperFlags::HASH_XXH64.bits();
         if flags.count_ones() != 1 {
             return Err(einval!(format!(
                 "invalid flags {:#x} related to digest algorithm in Rafs v6 extended superblock",
@@ -535,6 +538,7 @@ impl RafsV6SuperBlockExt {
         self.s_flags &= !RafsSuperFlags::COMPRESSION_LZ4.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_GZIP.bits();
         self.s_flags &= !RafsSuperFlags::COMPRESSION_ZSTD.bits();
+        self.s_flags &= !RafsSuperFlags::COMPRESSION_BROTLI.bits();
         self.s_flags |= c.bits();
     }
 
@@ -565,6 +569,7 @@ impl RafsV6SuperBlockExt {
 
         self.s_flags &= !RafsSuperFlags::HASH_BLAKE3.bits();
         self.s_flags &= !RafsSuperFlags::HASH_SHA256.bits();
+        self.s_flags &= !RafsSuperFlags::HASH_XXH64.bits();
         self.s_flags |= c.bits();
     }
 
@@ -2570,6 +2575,15 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rafs_v6_super_block_ext_brotli() {
+        let mut ext = RafsV6SuperBlockExt::new();
+        ext.set_compressor(compress::Algorithm::GZip);
+        ext.set_compressor(compress::Algorithm::Brotli);
+        assert_eq!(ext.s_flags & RafsSuperFlags::COMPRESSION_GZIP.bits(), 0);
+        assert_ne!(ext.s_flags & RafsSuperFlags::COMPRESSION_BROTLI.bits(), 0);
+    }
+
     #[test]
     fn test_rafs_v6_inode_compact() {
         let mut cpt = RafsV6InodeCompact::new();
@@ -2783,6 +2797,67 @@ mod tests {
         writer.flush().unwrap();
     }
 
+    #[test]
+    fn test_rafs_v6_blob_table_mixed_compressors() {
+        let mut table = RafsV6BlobTable::new();
+        let data = vec![0x5au8; 0x1000];
+        let mut blobs = Vec::new();
+        let mut header = BlobCompressionContextHeader::default();
+        header.set_aligned(true);
+        header.set_ci_compressed_size(size_of::<BlobChunkInfoV1Ondisk>() as u64);
+        header.set_ci_uncompressed_size(size_of::<BlobChunkInfoV1Ondisk>() as u64);
+        for (id, algo) in [
+            ("1", compress::Algorithm::Lz4Block),
+            ("2", compress::Algorithm::GZip),
+        ] {
+            let (compressed, is_compressed) = compress::compress(&data, algo).unwrap();
+            assert!(is_compressed);
+            table.add(
+                id.repeat(BLOB_SHA256_LEN),
+                0,
+                0,
+                0x1000,
+                1,
+                0x1000,
+                compressed.len() as u64,
+                RafsSuperFlags::from(algo) | RafsSuperFlags::HASH_BLAKE3,
+                [0; 32],
+                [0; 32],
+                0,
+                0,
+                false,
+                header,
+                Arc::new(crypt::Algorithm::None.new_cipher().unwrap()),
+                None,
+            );
+            blobs.push(compressed.into_owned());
+        }
+
+        let (mut reader, mut writer) = get_streams();
+        table.store(&mut writer).unwrap();
+        writer.flush().unwrap();
+        let mut loaded = RafsV6BlobTable::new();
+        loaded
+            .load(
+                &mut reader,
+                table.size() as u32,
+                0x1000,
+                RafsSuperFlags::COMPRESSION_GZIP,
+            )
+            .unwrap();
+
+        // Each blob keeps its own compression algorithm, whatever the filesystem default is.
+        let expected = [compress::Algorithm::Lz4Block, compress::Algorithm::GZip];
+        for (idx, algo) in expected.iter().enumerate() {
+            let info = loaded.get(idx as u32).unwrap();
+            assert_eq!(info.compressor(), *algo);
+            let mut buf = vec![0u8; 0x1000];
+            let size = compress::decompress(&blobs[idx], &mut buf, info.compressor()).unwrap();
+            assert_eq!(size, 0x1000);
+            assert_eq!(buf, data);
+        }
+    }
+
     #[test]
     fn test_rafs_v6_xattr_entry() {
         let ent = RafsV6XattrEntry::new();
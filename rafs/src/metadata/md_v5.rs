@@ -249,6 +249,12 @@ impl BlobChunkInfo for V5IoChunk {
         false
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.flags
+            .contains(BlobChunkFlags::LZ4_BLOCK)
+            .then_some(compress::Algorithm::Lz4Block)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
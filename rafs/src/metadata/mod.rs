@@ -6,12 +6,13 @@
 //! Enums, Structs and Traits to access and manage Rafs filesystem metadata.
 
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
 use std::ffi::{OsStr, OsString};
 use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
 use std::fs::OpenOptions;
-use std::io::{Error, ErrorKind, Result};
+use std::io::{Error, ErrorKind, Read, Result};
+use std::mem::size_of;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
@@ -24,15 +25,16 @@ use fuse_backend_rs::abi::fuse_abi::Attr;
 use fuse_backend_rs::api::filesystem::Entry;
 use nydus_api::{ConfigV2, RafsConfigV2};
 use nydus_storage::device::{
-    BlobChunkInfo, BlobDevice, BlobFeatures, BlobInfo, BlobIoMerge, BlobIoVec,
+    BlobChunkInfo, BlobDevice, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoKind, BlobIoMerge,
+    BlobIoVec,
 };
 use nydus_storage::meta::toc::TocEntryList;
 use nydus_utils::digest::{self, RafsDigest};
 use nydus_utils::{compress, crypt};
 use serde::Serialize;
 
-use self::layout::v5::RafsV5PrefetchTable;
-use self::layout::v6::RafsV6PrefetchTable;
+use self::layout::v5::{RafsV5PrefetchTable, RafsV5SuperBlock};
+use self::layout::v6::{RafsV6PrefetchTable, RafsV6SuperBlock, EROFS_SUPER_OFFSET};
 use self::layout::{XattrName, XattrValue, RAFS_SUPER_VERSION_V5, RAFS_SUPER_VERSION_V6};
 use self::noop::NoopSuperBlock;
 use crate::fs::{RAFS_DEFAULT_ATTR_TIMEOUT, RAFS_DEFAULT_ENTRY_TIMEOUT};
@@ -301,6 +303,10 @@ bitflags! {
         const INLINED_CHUNK_DIGEST = 0x0000_0100;
         /// RAFS works in Tarfs mode, which directly uses tar streams as data blobs.
         const TARTFS_MODE = 0x0000_0200;
+        /// Use non-cryptographic xxh64 hash algorithm to calculate digest.
+        const HASH_XXH64 = 0x0000_0400;
+        /// Data chunks are compressed with brotli.
+        const COMPRESSION_BROTLI = 0x0000_0800;
         /// Data chunks are not encrypted.
         const ENCRYPTION_NONE = 0x0100_0000;
         /// Data chunks are encrypted with AES-128-XTS.
@@ -334,6 +340,7 @@ impl From<RafsSuperFlags> for digest::Algorithm {
         match flags {
             x if x.contains(RafsSuperFlags::HASH_BLAKE3) => digest::Algorithm::Blake3,
             x if x.contains(RafsSuperFlags::HASH_SHA256) => digest::Algorithm::Sha256,
+            x if x.contains(RafsSuperFlags::HASH_XXH64) => digest::Algorithm::Xxh64,
             _ => digest::Algorithm::Blake3,
         }
     }
@@ -344,6 +351,7 @@ impl From<digest::Algorithm> for RafsSuperFlags {
         match d {
             digest::Algorithm::Blake3 => RafsSuperFlags::HASH_BLAKE3,
             digest::Algorithm::Sha256 => RafsSuperFlags::HASH_SHA256,
+            digest::Algorithm::Xxh64 => RafsSuperFlags::HASH_XXH64,
         }
     }
 }
@@ -355,6 +363,7 @@ impl From<RafsSuperFlags> for compress::Algorithm {
             x if x.contains(RafsSuperFlags::COMPRESSION_LZ4) => compress::Algorithm::Lz4Block,
             x if x.contains(RafsSuperFlags::COMPRESSION_GZIP) => compress::Algorithm::GZip,
             x if x.contains(RafsSuperFlags::COMPRESSION_ZSTD) => compress::Algorithm::Zstd,
+            x if x.contains(RafsSuperFlags::COMPRESSION_BROTLI) => compress::Algorithm::Brotli,
             _ => compress::Algorithm::Lz4Block,
         }
     }
@@ -367,6 +376,7 @@ impl From<compress::Algorithm> for RafsSuperFlags {
             compress::Algorithm::Lz4Block => RafsSuperFlags::COMPRESSION_LZ4,
             compress::Algorithm::GZip => RafsSuperFlags::COMPRESSION_GZIP,
             compress::Algorithm::Zstd => RafsSuperFlags::COMPRESSION_ZSTD,
+            compress::Algorithm::Brotli => RafsSuperFlags::COMPRESSION_BROTLI,
         }
     }
 }
@@ -455,6 +465,17 @@ impl RafsSuperConfig {
                 meta.get_digester()
             ))
         );
+        // Weak digests from trusted pipelines must not be mixed with others, so data of untrusted
+        // images can't be deduplicated against them.
+        ensure!(
+            self.digester == meta.get_digester()
+                || (self.digester.is_cryptographic() && meta.get_digester().is_cryptographic()),
+            MergeError::InconsistentFilesystem(format!(
+                "can not mix non-cryptographic digest algorithm with others, {} vs {}",
+                self.digester,
+                meta.get_digester()
+            ))
+        );
         let is_tarfs_mode = meta.flags.contains(RafsSuperFlags::TARTFS_MODE);
         ensure!(
             is_tarfs_mode == self.is_tarfs_mode,
@@ -675,6 +696,33 @@ impl RafsVersion {
     pub fn is_v6(&self) -> bool {
         self == &Self::V6
     }
+
+    /// Detect RAFS version from the super block at the beginning of `r`, without loading the
+    /// whole filesystem metadata.
+    pub fn detect<R: Read>(r: &mut R) -> Result<Self> {
+        let mut sb = RafsV5SuperBlock::new();
+        let mut buf = Vec::with_capacity(size_of::<RafsV5SuperBlock>());
+        r.take(size_of::<RafsV5SuperBlock>() as u64)
+            .read_to_end(&mut buf)?;
+        if buf.len() == size_of::<RafsV5SuperBlock>() {
+            sb.as_mut().copy_from_slice(&buf);
+            if sb.is_rafs_v5() {
+                return Ok(RafsVersion::V5);
+            }
+        }
+
+        let mut sb = RafsV6SuperBlock::new();
+        let offset = EROFS_SUPER_OFFSET as usize;
+        if buf.len() >= offset + size_of::<RafsV6SuperBlock>() {
+            sb.as_mut()
+                .copy_from_slice(&buf[offset..offset + size_of::<RafsV6SuperBlock>()]);
+            if sb.is_rafs_v6() {
+                return Ok(RafsVersion::V6);
+            }
+        }
+
+        Err(einval!("invalid RAFS superblock"))
+    }
 }
 
 /// Rafs metadata working mode.
@@ -753,6 +801,44 @@ impl RafsSuper {
         path: P,
         config: Arc<ConfigV2>,
         is_chunk_dict: bool,
+    ) -> Result<(Self, RafsIoReader)> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(false)
+            .open(path.as_ref())?;
+        Self::load_from_reader(Box::new(file), Some(path.as_ref()), config, is_chunk_dict)
+    }
+
+    /// Load Rafs super block from metadata held in memory.
+    ///
+    /// The metadata is copied into an anonymous memory file instead of a file on disk. Unlike
+    /// `load_from_file()`, blob ids can't be fixed up from the metadata file name.
+    #[cfg(target_os = "linux")]
+    pub fn load_from_memory(
+        data: &[u8],
+        config: Arc<ConfigV2>,
+        is_chunk_dict: bool,
+    ) -> Result<(Self, RafsIoReader)> {
+        use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+        use std::fs::File;
+        use std::io::{Seek, Write};
+        use std::os::unix::io::FromRawFd;
+
+        let name = std::ffi::CString::new("rafs-meta").unwrap();
+        let fd = memfd_create(&name, MemFdCreateFlag::MFD_CLOEXEC).map_err(Error::from)?;
+        // Safe because the fd has just been created and is owned by nobody else.
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(data)?;
+        file.rewind()?;
+        Self::load_from_reader(Box::new(file), None, config, is_chunk_dict)
+    }
+
+    // Load Rafs super block from `reader`, with `path` of the metadata file if available.
+    fn load_from_reader(
+        mut reader: RafsIoReader,
+        path: Option<&Path>,
+        config: Arc<ConfigV2>,
+        is_chunk_dict: bool,
     ) -> Result<(Self, RafsIoReader)> {
         let validate_digest = config
             .rafs
@@ -765,17 +851,15 @@ impl RafsSuper {
             ..Default::default()
         };
         rs.meta.is_chunk_dict = is_chunk_dict;
-
-        // open bootstrap file
-        let file = OpenOptions::new()
-            .read(true)
-            .write(false)
-            .open(path.as_ref())?;
-        let mut reader = Box::new(file) as RafsIoReader;
         let mut blob_accessible = config.internal.blob_accessible();
 
         if let Err(e) = rs.load(&mut reader) {
-            let id = BlobInfo::get_blob_id_from_meta_path(path.as_ref())?;
+            // The RAFS meta may be inlined in the data blob named after the metadata file.
+            let path = match path {
+                Some(path) => path,
+                None => return Err(e),
+            };
+            let id = BlobInfo::get_blob_id_from_meta_path(path)?;
             let new_path = match TocEntryList::extract_rafs_meta(&id, config.clone()) {
                 Ok(v) => v,
                 Err(_e) => {
@@ -786,9 +870,9 @@ impl RafsSuper {
             let file = OpenOptions::new().read(true).write(false).open(new_path)?;
             reader = Box::new(file) as RafsIoReader;
             rs.load(&mut reader)?;
-            rs.set_blob_id_from_meta_path(path.as_ref())?;
+            rs.set_blob_id_from_meta_path(path)?;
             blob_accessible = true;
-        } else {
+        } else if let Some(path) = path {
             // Backward compatibility: try to fix blob id for old converters.
             // Old converters extracts bootstraps from data blobs with inlined bootstrap
             // use blob digest as the bootstrap file name. The last blob in the blob table from
@@ -797,7 +881,7 @@ impl RafsSuper {
             for blob in blobs.iter() {
                 // Fix blob id for new images with old converters.
                 if blob.has_feature(BlobFeatures::INLINED_FS_META) {
-                    blob.set_blob_id_from_meta_path(path.as_ref())?;
+                    blob.set_blob_id_from_meta_path(path)?;
                 }
             }
         }
@@ -971,6 +1055,113 @@ impl RafsSuper {
         }
     }
 
+    /// Collect regular files under directory `ino` carrying the prefetch hint extended attribute.
+    ///
+    /// Image authors may mark hot files with extended attribute `name` at build time, so those
+    /// files get prefetched without maintaining a central prefetch list.
+    pub fn get_prefetch_hinted_inodes(&self, ino: Inode, name: &OsStr) -> Result<Vec<Inode>> {
+        let mut inodes = Vec::new();
+        if !self.meta.has_xattr() {
+            return Ok(inodes);
+        }
+
+        self.walk_directory::<PathBuf>(ino, None, &mut |inode, _path| {
+            if inode.is_reg() && inode.has_xattr() && inode.get_xattr(name)?.is_some() {
+                inodes.push(inode.ino());
+            }
+            Ok(())
+        })
+        .map_err(|e| eio!(format!("failed to collect prefetch hinted files, {}", e)))?;
+
+        Ok(inodes)
+    }
+
+    /// Collect chunks of files matching path glob patterns, such as `/usr/bin` or `/lib/*.so`.
+    ///
+    /// Patterns are matched against absolute paths, and files under a matching directory are
+    /// collected too. Chunks shared by multiple files are collected once. Chunks of each blob are
+    /// sorted by offset and split into vectors of at most `merging_size` compressed bytes, so
+    /// each vector may be fetched by one backend request.
+    pub fn collect_glob_chunks(
+        &self,
+        device: &BlobDevice,
+        patterns: &[&str],
+        merging_size: u64,
+    ) -> Result<Vec<BlobIoVec>> {
+        let patterns = patterns
+            .iter()
+            .map(|p| {
+                glob::Pattern::new(p)
+                    .map_err(|e| einval!(format!("invalid path glob {}, {}", p, e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+
+        // Chunks of each blob, indexed by blob index and chunk index.
+        let mut chunks: BTreeMap<u32, BTreeMap<u32, BlobIoDesc>> = BTreeMap::new();
+        let root_ino = self.superblock.root_ino();
+        self.walk_directory::<PathBuf>(root_ino, None, &mut |inode, path| {
+            if !inode.is_reg() || inode.is_empty_size() {
+                return Ok(());
+            }
+            let matched = path
+                .ancestors()
+                .any(|path| patterns.iter().any(|p| p.matches_path_with(path, options)));
+            if matched {
+                for descs in inode.alloc_bio_vecs(device, 0, inode.size() as usize, false)? {
+                    for desc in (0..descs.len()).filter_map(|idx| descs.blob_io_desc(idx)) {
+                        chunks
+                            .entry(desc.blob.blob_index())
+                            .or_default()
+                            .entry(desc.chunkinfo.id())
+                            .or_insert_with(|| desc.clone());
+                    }
+                }
+            }
+            Ok(())
+        })
+        .map_err(|e| eio!(format!("failed to collect chunks of files, {}", e)))?;
+
+        let mut io_vecs = Vec::new();
+        for (_, descs) in chunks {
+            let mut descs = descs.into_values().collect::<Vec<_>>();
+            descs.sort_by_key(|desc| desc.chunkinfo.compressed_offset());
+            let mut io_vec = BlobIoVec::new(descs[0].blob.clone());
+            let mut size = 0;
+            for desc in descs {
+                let chunk_size = desc.chunkinfo.compressed_size() as u64;
+                if size > 0 && size + chunk_size > merging_size {
+                    let blob = desc.blob.clone();
+                    io_vecs.push(std::mem::replace(&mut io_vec, BlobIoVec::new(blob)));
+                    size = 0;
+                }
+                size += chunk_size;
+                io_vec.push(desc);
+            }
+            io_vecs.push(io_vec);
+        }
+
+        Ok(io_vecs)
+    }
+
+    /// Prefetch files matching path glob patterns, returns the number of chunks to prefetch.
+    ///
+    /// See [collect_glob_chunks()](Self::collect_glob_chunks) for the matching rules.
+    pub fn prefetch_globs(
+        &self,
+        device: &BlobDevice,
+        patterns: &[&str],
+        merging_size: u64,
+    ) -> Result<usize> {
+        let io_vecs = self.collect_glob_chunks(device, patterns, merging_size)?;
+        let count = io_vecs.iter().map(|v| v.len()).sum();
+        device.prefetch(&io_vecs.iter().collect::<Vec<_>>(), &[])?;
+        Ok(count)
+    }
+
     #[inline]
     fn prefetch_inode(
         device: &BlobDevice,
@@ -988,8 +1179,15 @@ impl RafsSuper {
             }
         }
 
+        // Executables are needed early to boot containers.
+        let kind = if inode.get_attr().mode & 0o111 != 0 {
+            BlobIoKind::Executable
+        } else {
+            BlobIoKind::Data
+        };
         let descs = inode.alloc_bio_vecs(device, 0, inode.size() as usize, false)?;
-        for desc in descs {
+        for mut desc in descs {
+            desc.set_kind(kind);
             state.append(desc);
             if let Some(desc) = state.get_current_element() {
                 fetcher(desc, false);
@@ -1163,6 +1361,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rafs_compressor_brotli() {
+        assert_eq!(
+            compress::Algorithm::from(RafsSuperFlags::COMPRESSION_BROTLI),
+            compress::Algorithm::Brotli
+        );
+        assert_eq!(
+            RafsSuperFlags::from(compress::Algorithm::Brotli),
+            RafsSuperFlags::COMPRESSION_BROTLI
+        );
+    }
+
     #[test]
     fn test_rafs_digestor() {
         assert_eq!(
@@ -1183,6 +1393,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_rafs_digestor_xxh64() {
+        assert_eq!(
+            digest::Algorithm::from(RafsSuperFlags::HASH_XXH64),
+            digest::Algorithm::Xxh64
+        );
+        assert_eq!(
+            RafsSuperFlags::from(digest::Algorithm::Xxh64),
+            RafsSuperFlags::HASH_XXH64
+        );
+    }
+
     #[test]
     fn test_rafs_crypt_from() {
         assert_eq!(
@@ -1323,4 +1545,116 @@ mod tests {
         assert!(meta1.get_config().check_compatibility(&meta5).is_err());
         assert!(meta1.get_config().check_compatibility(&meta6).is_err());
     }
+
+    #[test]
+    fn test_rafs_super_config_check_digester() {
+        let meta = |hash| {
+            get_meta(
+                1024,
+                true,
+                false,
+                hash,
+                RafsSuperFlags::COMPRESSION_ZSTD,
+                RafsSuperFlags::ENCRYPTION_NONE,
+                RAFS_SUPER_VERSION_V6,
+            )
+        };
+        let blake3 = meta(RafsSuperFlags::HASH_BLAKE3);
+        let sha256 = meta(RafsSuperFlags::HASH_SHA256);
+        let xxh64 = meta(RafsSuperFlags::HASH_XXH64);
+
+        assert!(blake3.get_config().check_compatibility(&sha256).is_ok());
+        assert!(xxh64.get_config().check_compatibility(&xxh64).is_ok());
+        let err = blake3.get_config().check_compatibility(&xxh64).unwrap_err();
+        assert!(err.to_string().contains("non-cryptographic digest"));
+        assert!(xxh64.get_config().check_compatibility(&sha256).is_err());
+    }
+
+    #[test]
+    fn test_detect_rafs_version() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v5.boot");
+        let mut file = std::fs::File::open(path).unwrap();
+        assert_eq!(RafsVersion::detect(&mut file).unwrap(), RafsVersion::V5);
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v6-2.2.boot");
+        let mut file = std::fs::File::open(path).unwrap();
+        assert_eq!(RafsVersion::detect(&mut file).unwrap(), RafsVersion::V6);
+        assert!(RafsVersion::detect(&mut [0u8; 0x2000].as_slice()).is_err());
+        assert!(RafsVersion::detect(&mut [0u8; 0x10].as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_collect_glob_chunks() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let path = PathBuf::from(root_dir).join("../tests/texture/bootstrap/rafs-v5.boot");
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let config = format!(
+            r#"
+            version = 2
+            id = "test"
+            [backend]
+            type = "localfs"
+            [backend.localfs]
+            dir = "{}"
+            [cache]
+            type = "dummycache"
+            [rafs]
+            mode = "direct"
+            "#,
+            tmp_dir.as_path().display()
+        );
+        let config = Arc::new(ConfigV2::from_str(&config).unwrap());
+        let (rs, _) = RafsSuper::load_from_file(&path, config.clone(), false).unwrap();
+        let blob_infos = rs.superblock.get_blob_infos();
+        for blob in blob_infos.iter() {
+            std::fs::File::create(tmp_dir.as_path().join(blob.blob_id())).unwrap();
+        }
+        let device = BlobDevice::new(&config, &blob_infos).unwrap();
+
+        // Expected chunks of all regular files under "/etc".
+        let mut expected = HashSet::new();
+        rs.walk_directory::<PathBuf>(rs.superblock.root_ino(), None, &mut |inode, path| {
+            if path.starts_with("/etc") && inode.is_reg() && !inode.is_empty_size() {
+                for descs in inode.alloc_bio_vecs(&device, 0, inode.size() as usize, false)? {
+                    for idx in 0..descs.len() {
+                        let desc = descs.blob_io_desc(idx).unwrap();
+                        expected.insert((desc.blob.blob_index(), desc.chunkinfo.id()));
+                    }
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert!(!expected.is_empty());
+
+        let merging_size = 0x10000;
+        let io_vecs = rs
+            .collect_glob_chunks(&device, &["/et[c]"], merging_size)
+            .unwrap();
+        let mut collected = HashSet::new();
+        for io_vec in io_vecs.iter() {
+            let mut size = 0;
+            let mut last_offset = 0;
+            for idx in 0..io_vec.len() {
+                let desc = io_vec.blob_io_desc(idx).unwrap();
+                assert_eq!(desc.blob.blob_index(), io_vec.blob_index());
+                assert!(desc.chunkinfo.compressed_offset() >= last_offset);
+                last_offset = desc.chunkinfo.compressed_offset();
+                size += desc.chunkinfo.compressed_size() as u64;
+                // Chunks shared by multiple files are collected once.
+                assert!(collected.insert((desc.blob.blob_index(), desc.chunkinfo.id())));
+            }
+            assert!(io_vec.len() == 1 || size <= merging_size);
+        }
+        assert_eq!(collected, expected);
+
+        let io_vecs = rs
+            .collect_glob_chunks(&device, &["/etc/DIR_COLORS*"], merging_size)
+            .unwrap();
+        let count = io_vecs.iter().map(|v| v.len()).sum::<usize>();
+        assert!(count > 0 && count < expected.len());
+        assert!(rs
+            .collect_glob_chunks(&device, &["/["], merging_size)
+            .is_err());
+    }
 }
@@ -546,6 +546,8 @@ impl FsCacheHandler {
                 blob_id: blob_info.blob_id().to_owned(),
                 offset: pre_offset,
                 len: cmp::min(size, blob_size - pre_offset),
+                merging_size: None,
+                bandwidth_rate: None,
             });
             pre_offset += size;
             if pre_offset >= blob_size {
@@ -30,10 +30,10 @@ use nix::unistd::{getegid, geteuid};
 use nydus::{get_build_time_info, setup_logging};
 use nydus_api::{BuildTimeInfo, ConfigV2, LocalFsConfig};
 use nydus_builder::{
-    parse_chunk_dict_arg, ArtifactStorage, BlobCacheGenerator, BlobCompactor, BlobManager,
-    BootstrapManager, BuildContext, BuildOutput, Builder, ChunkdictBlobInfo, ChunkdictChunkInfo,
-    ConversionType, DirectoryBuilder, Feature, Features, Generator, HashChunkDict, Merger,
-    Prefetch, PrefetchPolicy, StargzBuilder, TarballBuilder, WhiteoutSpec,
+    parse_chunk_dict_arg, ArtifactStorage, BlobCacheGenerator, BlobCompactor, BlobLayerDescriptor,
+    BlobManager, BootstrapManager, BuildContext, BuildOutput, Builder, ChunkdictBlobInfo,
+    ChunkdictChunkInfo, ConversionType, DirectoryBuilder, Feature, Features, Generator,
+    HashChunkDict, Merger, Prefetch, PrefetchPolicy, StargzBuilder, TarballBuilder, WhiteoutSpec,
 };
 use nydus_rafs::metadata::{MergeError, RafsSuper, RafsSuperConfig, RafsVersion};
 use nydus_storage::backend::localfs::LocalFs;
@@ -80,6 +80,10 @@ pub struct OutputSerializer {
     fs_version: String,
     /// Chunk compression algorithm.
     compressor: String,
+    /// OCI layer descriptors for per layer data blobs, only available for
+    /// `merge --preserve-layers`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    layers: Vec<BlobLayerDescriptor>,
 }
 
 impl OutputSerializer {
@@ -110,6 +114,7 @@ impl OutputSerializer {
                 trace,
                 fs_version: fs_version.to_string(),
                 compressor: compressor.to_string(),
+                layers: build_output.layers,
             };
 
             serde_json::to_writer_pretty(w, &output)
@@ -147,6 +152,7 @@ impl OutputSerializer {
                 trace,
                 fs_version: fs_version.to_string(),
                 compressor: compressor.to_string(),
+                layers: Vec::new(),
             };
 
             serde_json::to_writer(w, &output).context("failed to write result to output file")?;
@@ -295,7 +301,30 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("Algorithm to compress data chunks:")
                         .required(false)
                         .default_value("zstd")
-                        .value_parser(["none", "lz4_block", "zstd"]),
+                        .value_parser(["none", "lz4_block", "zstd", "brotli"]),
+                )
+                .arg(
+                    Arg::new("chunk-generation")
+                        .long("chunk-generation")
+                        .help("Generation number recorded for data chunks, bump it when rebuilding a blob in place to invalidate stale cached chunks")
+                        .value_parser(clap::value_parser!(u32))
+                        .required(false)
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("compressor-auto")
+                        .long("compressor-auto")
+                        .help("Select the compressor per data chunk by estimating its entropy: store incompressible chunks as is, compress low ratio chunks with lz4_block and others with the compressor")
+                        .action(ArgAction::SetTrue)
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("compressor-auto-threshold")
+                        .long("compressor-auto-threshold")
+                        .help("Entropy in bits per byte (0 to 8) above which data chunks are considered incompressible with --compressor-auto")
+                        .value_parser(clap::value_parser!(f64))
+                        .requires("compressor-auto")
+                        .required(false),
                 )
                 .arg(
                     Arg::new("digester")
@@ -303,7 +332,7 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("Algorithm to digest data chunks:")
                         .required(false)
                         .default_value("blake3")
-                        .value_parser(["blake3", "sha256"]),
+                        .value_parser(["blake3", "sha256", "xxh64"]),
                 )
                 .arg( arg_config.clone() )
                 .arg(
@@ -483,6 +512,63 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                     .required(false)
                     .help("RAFS blob toc size list separated by comma"),
             )
+            .arg(
+                Arg::new("preserve-layers")
+                    .long("preserve-layers")
+                    .help("Keep data blob of each source layer as a separate OCI layer, only merge the metadata")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("deterministic")
+                    .long("deterministic")
+                    .help("Generate identical merged RAFS metadata for identical sources, by sorting the blob table")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("dry-run")
+                    .long("dry-run")
+                    .help("Check whether the bootstraps can be merged and output the blobs to be referenced, without writing the merged RAFS metadata")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("xattr-size-limit")
+                    .long("xattr-size-limit")
+                    .help("Maximum size in bytes of extended attributes of an inode in the merged RAFS metadata, 0 means no limit")
+                    .value_parser(clap::value_parser!(u64))
+                    .default_value("0")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("dedup-blobs")
+                    .long("dedup-blobs")
+                    .help("Collapse identical data blobs with different blob ids into one entry of the blob table, by matching blob toc or blob meta digests")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("gc-blobs")
+                    .long("gc-blobs")
+                    .help("Drop data blobs not referenced by any file of the merged RAFS metadata from the blob table")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
+            .arg(
+                Arg::new("provenance")
+                    .long("provenance")
+                    .value_name("BUILDER")
+                    .help("Save a provenance record with the builder identity and digests of the source bootstraps in the merged RAFS metadata")
+                    .required(false),
+            )
+            .arg(
+                Arg::new("rechunk")
+                    .long("rechunk")
+                    .help("Re-chunk data of layers with a different chunk size into new data blobs saved in `--blob-dir`, fetching data by the backend in `--config`")
+                    .action(ArgAction::SetTrue)
+                    .required(false),
+            )
             .arg(arg_config.clone())
             .arg(
                 Arg::new("SOURCE")
@@ -653,7 +739,7 @@ fn prepare_cmd_args(bti_string: &'static str) -> App {
                         .help("Algorithm to digest data chunks:")
                         .required(false)
                         .default_value("blake3")
-                        .value_parser(["blake3", "sha256"]),
+                        .value_parser(["blake3", "sha256", "xxh64"]),
                 )
                 .arg(
                     arg_output_json.clone(),
@@ -1093,6 +1179,19 @@ impl Command {
         build_ctx.set_fs_version(version);
         build_ctx.set_chunk_size(chunk_size);
         build_ctx.set_batch_size(batch_size);
+        build_ctx.set_compressor_auto(matches.get_flag("compressor-auto"));
+        if let Some(threshold) = matches.get_one::<f64>("compressor-auto-threshold") {
+            if !(0.0..=8.0).contains(threshold) {
+                bail!(
+                    "invalid --compressor-auto-threshold {}, should be in range [0, 8]",
+                    threshold
+                );
+            }
+            build_ctx.set_compressor_auto_threshold(*threshold);
+        }
+        if let Some(generation) = matches.get_one::<u32>("chunk-generation") {
+            build_ctx.set_chunk_generation(*generation);
+        }
 
         let blob_cache_generator = match blob_cache_storage {
             Some(storage) => Some(BlobCacheGenerator::new(storage)?),
@@ -1141,6 +1240,10 @@ impl Command {
             build_ctx.blob_features.insert(BlobFeatures::BATCH);
             build_ctx.blob_features.insert(BlobFeatures::CHUNK_INFO_V2);
         }
+        // Chunks compressed by lz4_block can only be recorded by chunk info v2.
+        if build_ctx.compressor_auto && !version.is_v5() {
+            build_ctx.blob_features.insert(BlobFeatures::CHUNK_INFO_V2);
+        }
 
         let mut builder: Box<dyn Builder> = match conversion_type {
             ConversionType::DirectoryToRafs => {
@@ -1404,9 +1507,23 @@ impl Command {
             .set_blob_accessible(matches.get_one::<String>("config").is_some());
         let mut ctx = BuildContext {
             prefetch: Self::get_prefetch(matches)?,
+            preserve_layers: matches.get_flag("preserve-layers"),
+            deterministic_merge: matches.get_flag("deterministic"),
+            merge_rechunk: matches.get_flag("rechunk"),
+            merge_dedup_blobs: matches.get_flag("dedup-blobs"),
+            merge_gc_blobs: matches.get_flag("gc-blobs"),
+            merge_dry_run: matches.get_flag("dry-run"),
+            merge_xattr_size_limit: *matches.get_one::<u64>("xattr-size-limit").unwrap(),
+            blob_storage: matches
+                .get_one::<String>("blob-dir")
+                .map(|dir| ArtifactStorage::FileDir(PathBuf::from(dir))),
             ..Default::default()
         };
         ctx.configuration = config.clone();
+        if let Some(builder) = matches.get_one::<String>("provenance") {
+            let version = format!("{}-{}", build_info.package_ver, build_info.git_commit);
+            ctx.set_merge_provenance(builder, &version);
+        }
 
         let parent_bootstrap_path = Self::get_parent_bootstrap(matches)?;
         let meta = RafsSuper::load_from_file(&source_bootstrap_paths[0], config.clone(), false)?
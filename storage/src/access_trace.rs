@@ -0,0 +1,139 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recorded chunk access traces to drive blob data prefetching.
+//!
+//! An access trace is an ordered list of chunks touched by a workload, recorded when building or
+//! profiling an image. Replaying the trace prefetches chunks in the order they were accessed by the
+//! workload instead of the order they are stored in blobs.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Result};
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::device::{BlobChunkInfo, BlobInfo, BlobIoDesc, BlobIoVec};
+
+/// Version of the access trace file format.
+pub const ACCESS_TRACE_VERSION: u32 = 1;
+
+/// A chunk accessed by the workload.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AccessTraceEntry {
+    /// Id of the blob containing the chunk.
+    pub blob_id: String,
+    /// Index of the chunk in the blob.
+    pub chunk_index: u32,
+}
+
+/// An ordered list of chunks accessed by a workload, stored as a JSON file.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct AccessTrace {
+    /// Version of the access trace format.
+    pub version: u32,
+    /// Accessed chunks in access order.
+    pub entries: Vec<AccessTraceEntry>,
+}
+
+impl Default for AccessTrace {
+    fn default() -> Self {
+        AccessTrace {
+            version: ACCESS_TRACE_VERSION,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl AccessTrace {
+    /// Create a new empty `AccessTrace` object.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an access to chunk `chunk_index` of blob `blob_id`.
+    pub fn push(&mut self, blob_id: &str, chunk_index: u32) {
+        self.entries.push(AccessTraceEntry {
+            blob_id: blob_id.to_string(),
+            chunk_index,
+        });
+    }
+
+    /// Load an access trace from a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path.as_ref()).map_err(|e| {
+            eother!(format!(
+                "failed to open access trace file {}, {}",
+                path.as_ref().display(),
+                e
+            ))
+        })?;
+        let trace: AccessTrace = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| einval!(format!("invalid access trace file, {}", e)))?;
+        if trace.version != ACCESS_TRACE_VERSION {
+            return Err(einval!(format!(
+                "unsupported access trace version {}",
+                trace.version
+            )));
+        }
+        Ok(trace)
+    }
+
+    /// Save the access trace into a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)
+            .map_err(|e| eother!(format!("failed to save access trace, {}", e)))
+    }
+
+    /// Convert the access trace into blob IO vectors to prefetch chunks in access order.
+    ///
+    /// Each vector covers chunks of the same blob in ascending offset order, so chunks adjacent
+    /// in both the trace and the blob may be merged into one backend request when prefetching.
+    /// Chunks accessed again, and chunks not found by `get_chunk(blob_index, chunk_index)`, are
+    /// skipped.
+    pub fn to_io_vecs<F>(&self, blobs: &[Arc<BlobInfo>], get_chunk: F) -> Vec<BlobIoVec>
+    where
+        F: Fn(u32, u32) -> Option<Arc<dyn BlobChunkInfo>>,
+    {
+        let mut io_vecs: Vec<BlobIoVec> = Vec::new();
+        let mut visited = HashSet::new();
+        // Blob index and end offset of the last chunk.
+        let mut last: Option<(u32, u64)> = None;
+
+        for entry in self.entries.iter() {
+            let blob = match blobs.iter().find(|b| b.blob_id() == entry.blob_id) {
+                Some(blob) => blob,
+                None => continue,
+            };
+            let blob_index = blob.blob_index();
+            if !visited.insert((blob_index, entry.chunk_index)) {
+                continue;
+            }
+            let chunk = match get_chunk(blob_index, entry.chunk_index) {
+                Some(chunk) => chunk,
+                None => {
+                    warn!(
+                        "access trace: no chunk {} in blob {}",
+                        entry.chunk_index, entry.blob_id
+                    );
+                    continue;
+                }
+            };
+
+            let offset = chunk.compressed_offset();
+            if !matches!(last, Some((idx, end)) if idx == blob_index && offset >= end) {
+                io_vecs.push(BlobIoVec::new(blob.clone()));
+            }
+            last = Some((blob_index, offset + chunk.compressed_size() as u64));
+            let size = chunk.uncompressed_size();
+            let desc = BlobIoDesc::new(blob.clone(), chunk.into(), 0, size, false);
+            io_vecs.last_mut().unwrap().push(desc);
+        }
+
+        io_vecs
+    }
+}
@@ -5,10 +5,12 @@
 //! Help library to manage network connections.
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::io::{Read, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Result, Write};
+use std::os::unix::fs::FileExt;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, AtomicI16, AtomicU64, AtomicU8, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{fmt, thread};
 
@@ -23,9 +25,13 @@ use reqwest::{
     Method, StatusCode, Url,
 };
 
-use nydus_api::{HttpProxyConfig, MirrorConfig, OssConfig, ProxyConfig, RegistryConfig, S3Config};
+use nydus_api::{
+    HttpProxyConfig, MirrorConfig, OssConfig, ProxyConfig, RegistryConfig, RetryConfig, S3Config,
+};
 use url::ParseError;
 
+use crate::backend::{BackendError, RetryPolicy};
+
 const HEADER_AUTHORIZATION: &str = "Authorization";
 
 const RATE_LIMITED_LOG_TIME: u8 = 2;
@@ -39,12 +45,14 @@ thread_local! {
 pub enum ConnectionError {
     Disconnected,
     ErrorWithMsg(String),
+    Status(StatusCode, String),
     Common(reqwest::Error),
     Format(reqwest::Error),
     Url(String, ParseError),
     Scheme(String),
     MirrorHost,
     MirrorPort,
+    ResponseTooLarge(u64, u64),
 }
 
 impl fmt::Display for ConnectionError {
@@ -52,12 +60,37 @@ impl fmt::Display for ConnectionError {
         match self {
             ConnectionError::Disconnected => write!(f, "network connection disconnected"),
             ConnectionError::ErrorWithMsg(s) => write!(f, "network error, {}", s),
+            ConnectionError::Status(c, s) => write!(f, "network error, status {}, {}", c, s),
             ConnectionError::Common(e) => write!(f, "network error, {}", e),
             ConnectionError::Format(e) => write!(f, "{}", e),
             ConnectionError::Url(s, e) => write!(f, "failed to parse URL {}, {}", s, e),
             ConnectionError::Scheme(s) => write!(f, "invalid scheme {}", s),
             ConnectionError::MirrorHost => write!(f, "invalid mirror host"),
             ConnectionError::MirrorPort => write!(f, "invalid mirror port"),
+            ConnectionError::ResponseTooLarge(size, limit) => write!(
+                f,
+                "response size {} exceeds the maximum allowed size {}",
+                size, limit
+            ),
+        }
+    }
+}
+
+impl ConnectionError {
+    /// Check whether the failed request may succeed if retried.
+    ///
+    /// Network failures, request timeouts, throttling and server side errors are transient, but
+    /// requests rejected by the server with other statuses, such as 404, are permanent.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ConnectionError::Status(c, _) => is_transient_status(*c),
+            ConnectionError::ErrorWithMsg(_)
+            | ConnectionError::Url(_, _)
+            | ConnectionError::Scheme(_)
+            | ConnectionError::MirrorHost
+            | ConnectionError::MirrorPort
+            | ConnectionError::ResponseTooLarge(_, _) => false,
+            _ => true,
         }
     }
 }
@@ -74,6 +107,8 @@ pub(crate) struct ConnectionConfig {
     pub timeout: u32,
     pub connect_timeout: u32,
     pub retry_limit: u8,
+    pub retry: RetryConfig,
+    pub max_response_size: u64,
 }
 
 impl Default for ConnectionConfig {
@@ -85,6 +120,18 @@ impl Default for ConnectionConfig {
             timeout: 5,
             connect_timeout: 5,
             retry_limit: 0,
+            retry: RetryConfig::default(),
+            max_response_size: 0,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    /// Get the policy to delay retries of failed read requests.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            delay: Duration::from_millis(self.retry.retry_delay_ms),
+            max_delay: Duration::from_millis(self.retry.retry_max_delay_ms),
         }
     }
 }
@@ -98,6 +145,8 @@ impl From<OssConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            max_response_size: c.max_response_size,
         }
     }
 }
@@ -111,6 +160,8 @@ impl From<S3Config> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            max_response_size: c.max_response_size,
         }
     }
 }
@@ -124,6 +175,8 @@ impl From<RegistryConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            max_response_size: c.max_response_size,
         }
     }
 }
@@ -137,6 +190,8 @@ impl From<HttpProxyConfig> for ConnectionConfig {
             timeout: c.timeout,
             connect_timeout: c.connect_timeout,
             retry_limit: c.retry_limit,
+            retry: c.retry,
+            max_response_size: c.max_response_size,
         }
     }
 }
@@ -250,13 +305,359 @@ pub(crate) fn is_success_status(status: StatusCode) -> bool {
     status >= StatusCode::OK && status < StatusCode::BAD_REQUEST
 }
 
+// Check whether requests failed with `status` may succeed if retried, such as request timeouts,
+// throttling and server side errors.
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
 /// Convert a HTTP `Response` into an `Result<Response>`.
 pub(crate) fn respond(resp: Response, catch_status: bool) -> ConnectionResult<Response> {
     if !catch_status || is_success_status(resp.status()) {
         Ok(resp)
     } else {
+        let status = resp.status();
         let msg = resp.text().map_err(ConnectionError::Format)?;
-        Err(ConnectionError::ErrorWithMsg(msg))
+        if is_transient_status(status) {
+            Err(ConnectionError::Status(status, msg))
+        } else {
+            Err(ConnectionError::ErrorWithMsg(msg))
+        }
+    }
+}
+
+/// Convert an asynchronous HTTP `Response` into an `Result<Response>`.
+#[cfg(feature = "backend-async")]
+pub(crate) async fn respond_async(
+    resp: reqwest::Response,
+    catch_status: bool,
+) -> ConnectionResult<reqwest::Response> {
+    if !catch_status || is_success_status(resp.status()) {
+        Ok(resp)
+    } else {
+        let status = resp.status();
+        let msg = resp.text().await.map_err(ConnectionError::Format)?;
+        if is_transient_status(status) {
+            Err(ConnectionError::Status(status, msg))
+        } else {
+            Err(ConnectionError::ErrorWithMsg(msg))
+        }
+    }
+}
+
+/// Error codes related to copying body of HTTP responses.
+#[derive(Debug)]
+pub(crate) enum CopyError {
+    /// Transfer failed after receiving the specified number of bytes.
+    Transport(usize, reqwest::Error),
+    /// Response body is larger than the buffer of the specified size.
+    Oversized(usize),
+    /// Response body exceeds the maximum response size of the connection.
+    TooLarge(u64),
+}
+
+impl CopyError {
+    /// Convert into a `BackendError`, with transport errors wrapped by `transport`.
+    pub(crate) fn into_backend_error<E, F>(self, transport: F) -> BackendError
+    where
+        E: Into<BackendError>,
+        F: FnOnce(reqwest::Error) -> E,
+    {
+        match self {
+            CopyError::Transport(received, e) => BackendError::interrupted(received, transport(e)),
+            CopyError::Oversized(size) => BackendError::Oversized(size),
+            CopyError::TooLarge(limit) => BackendError::ResponseTooLarge(limit),
+        }
+    }
+}
+
+// Writer to abort copying a response body once it exceeds `limit` bytes, 0 means no limit.
+struct LimitWriter<W> {
+    inner: W,
+    limit: u64,
+    received: u64,
+    exceeded: bool,
+}
+
+impl<W> LimitWriter<W> {
+    fn new(inner: W, limit: u64) -> Self {
+        LimitWriter {
+            inner,
+            limit,
+            received: 0,
+            exceeded: false,
+        }
+    }
+}
+
+impl<W: Write> Write for LimitWriter<W> {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        if self.limit > 0 && self.received + data.len() as u64 > self.limit {
+            self.exceeded = true;
+            return Err(std::io::Error::from(std::io::ErrorKind::WriteZero));
+        }
+        let size = self.inner.write(data)?;
+        self.received += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Writer to fill a buffer after discarding the first `skip` bytes, which remembers whether data
+// got discarded due to buffer overflow.
+struct BufWriter<'a> {
+    buf: &'a mut [u8],
+    skip: u64,
+    pos: usize,
+    overflow: bool,
+}
+
+impl Write for BufWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        if self.skip > 0 {
+            let size = std::cmp::min(self.skip, data.len() as u64);
+            self.skip -= size;
+            return Ok(size as usize);
+        }
+        if !data.is_empty() && self.pos == self.buf.len() {
+            self.overflow = true;
+            return Err(std::io::Error::from(std::io::ErrorKind::WriteZero));
+        }
+        let size = std::cmp::min(data.len(), self.buf.len() - self.pos);
+        self.buf[self.pos..self.pos + size].copy_from_slice(&data[..size]);
+        self.pos += size;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Copy body of the HTTP `Response` into `buf`.
+///
+/// On transport failure, number of bytes already received is returned together with the error,
+/// so the caller may resume the transfer from there. A body larger than `buf`, for example when
+/// the server ignores the `Range` header, is an error instead of being truncated silently, so is
+/// a body exceeding `limit` bytes if `limit` isn't 0.
+pub(crate) fn copy_response(
+    resp: &mut Response,
+    buf: &mut [u8],
+    limit: u64,
+) -> std::result::Result<usize, CopyError> {
+    let content_length = resp.content_length().unwrap_or(0);
+    if limit > 0 && content_length > limit {
+        return Err(CopyError::TooLarge(limit));
+    } else if content_length > buf.len() as u64 {
+        return Err(CopyError::Oversized(buf.len()));
+    }
+    let size = buf.len();
+    let buf_writer = BufWriter {
+        buf,
+        skip: 0,
+        pos: 0,
+        overflow: false,
+    };
+    let mut writer = LimitWriter::new(buf_writer, limit);
+    match resp.copy_to(&mut writer) {
+        Ok(_) => Ok(writer.inner.pos),
+        Err(_) if writer.exceeded => Err(CopyError::TooLarge(limit)),
+        Err(_) if writer.inner.overflow => Err(CopyError::Oversized(size)),
+        Err(e) => Err(CopyError::Transport(writer.inner.pos, e)),
+    }
+}
+
+/// Read body of the response to a request for range [offset, offset + size) asynchronously.
+///
+/// Failures are reported as [copy_response] does. If the server ignored the `Range` header and
+/// responded with the whole object, the requested range is sliced from the body while it streams
+/// by, and the rest of the body is never received.
+#[cfg(feature = "backend-async")]
+pub(crate) async fn read_response_async(
+    mut resp: reqwest::Response,
+    size: usize,
+    offset: u64,
+    limit: u64,
+) -> std::result::Result<Vec<u8>, CopyError> {
+    let whole = resp.status() == StatusCode::OK;
+    if !whole && resp.content_length().unwrap_or(0) > size as u64 {
+        return Err(CopyError::Oversized(size));
+    }
+
+    let mut skip = if whole { offset } else { 0 };
+    let mut received = 0u64;
+    let mut data = Vec::with_capacity(size);
+    while !whole || data.len() < size {
+        let chunk = match resp.chunk().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => return Err(CopyError::Transport(data.len(), e)),
+        };
+        received += chunk.len() as u64;
+        if limit > 0 && received > limit {
+            return Err(CopyError::TooLarge(limit));
+        }
+        let start = std::cmp::min(skip, chunk.len() as u64) as usize;
+        skip -= start as u64;
+        let chunk = &chunk[start..];
+        if !whole && data.len() + chunk.len() > size {
+            return Err(CopyError::Oversized(size));
+        }
+        let len = std::cmp::min(chunk.len(), size - data.len());
+        data.extend_from_slice(&chunk[..len]);
+    }
+
+    Ok(data)
+}
+
+/// Number of responses ignoring the `Range` header before a blob gets saved locally.
+const RANGE_IGNORED_LIMIT: u8 = 3;
+
+/// Fallback for servers intermittently ignoring the `Range` header of requests for a blob.
+///
+/// Some misconfigured servers respond to ranged requests with the whole object from time to time,
+/// so requested data is sliced from the body while it streams by. Once the server has ignored
+/// ranges `RANGE_IGNORED_LIMIT` times, the whole object is saved into an unlinked temporary file
+/// and all following reads of the blob are served from it, avoiding the unreliable ranged
+/// requests without keeping the object in memory.
+#[derive(Debug, Default)]
+pub(crate) struct RangeFallback {
+    ignored: AtomicU8,
+    object: OnceLock<File>,
+}
+
+impl RangeFallback {
+    /// Read data of range [offset, offset + buf.len()) from the whole object saved locally.
+    pub(crate) fn read_cached(&self, buf: &mut [u8], offset: u64) -> Option<usize> {
+        let object = self.object.get()?;
+        let mut pos = 0;
+        while pos < buf.len() {
+            match object.read_at(&mut buf[pos..], offset + pos as u64) {
+                Ok(0) => break,
+                Ok(size) => pos += size,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    // Fall back to ranged requests.
+                    warn!("failed to read blob saved locally, {}", e);
+                    return None;
+                }
+            }
+        }
+        Some(pos)
+    }
+
+    /// Copy data of range [offset, offset + buf.len()) from the response to a ranged request.
+    ///
+    /// Failures are reported as [copy_response] does. Receiving or saving more than `limit` bytes
+    /// of the whole object is aborted if `limit` isn't 0.
+    pub(crate) fn copy_response(
+        &self,
+        resp: &mut Response,
+        buf: &mut [u8],
+        offset: u64,
+        limit: u64,
+    ) -> std::result::Result<usize, CopyError> {
+        // The server may have ignored the `Range` header, so the body starts at offset 0.
+        if resp.status() != StatusCode::OK
+            || offset == 0 && resp.content_length() == Some(buf.len() as u64)
+        {
+            return copy_response(resp, buf, limit);
+        }
+
+        let ignored = self.ignored.fetch_add(1, Ordering::Relaxed) + 1;
+        if ignored >= RANGE_IGNORED_LIMIT && self.object.get().is_none() {
+            match Self::create_object() {
+                Ok(object) => {
+                    let object_writer = ObjectWriter {
+                        object: &object,
+                        buf,
+                        offset,
+                        pos: 0,
+                    };
+                    let mut writer = LimitWriter::new(object_writer, limit);
+                    match resp.copy_to(&mut writer) {
+                        Ok(_) => {}
+                        Err(_) if writer.exceeded => return Err(CopyError::TooLarge(limit)),
+                        Err(e) => return Err(CopyError::Transport(0, e)),
+                    }
+                    let size = writer.inner.pos.saturating_sub(offset);
+                    if self.object.set(object).is_ok() {
+                        warn!(
+                            "server ignored Range header {} times, serve reads from the whole object",
+                            ignored
+                        );
+                    }
+                    return Ok(std::cmp::min(size, buf.len() as u64) as usize);
+                }
+                Err(e) => warn!("failed to create file to save blob, {}", e),
+            }
+        }
+
+        let buf_writer = BufWriter {
+            buf,
+            skip: offset,
+            pos: 0,
+            overflow: false,
+        };
+        let mut writer = LimitWriter::new(buf_writer, limit);
+        match resp.copy_to(&mut writer) {
+            Err(_) if writer.exceeded => Err(CopyError::TooLarge(limit)),
+            // Data after the requested range is never received.
+            Err(_) if writer.inner.overflow => Ok(writer.inner.pos),
+            Ok(_) => Ok(writer.inner.pos),
+            Err(e) => Err(CopyError::Transport(writer.inner.pos, e)),
+        }
+    }
+
+    // Create an unlinked temporary file to save the whole object.
+    fn create_object() -> Result<File> {
+        static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "nydus-blob-{}-{}",
+            std::process::id(),
+            SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        ));
+        let object = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        std::fs::remove_file(&path)?;
+
+        Ok(object)
+    }
+}
+
+// Writer to save the whole object into a file, which also fills a buffer with data at `offset`.
+struct ObjectWriter<'a> {
+    object: &'a File,
+    buf: &'a mut [u8],
+    offset: u64,
+    pos: u64,
+}
+
+impl Write for ObjectWriter<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        let size = self.object.write(data)?;
+        let start = std::cmp::max(self.pos, self.offset);
+        let end = std::cmp::min(self.pos + size as u64, self.offset + self.buf.len() as u64);
+        if start < end {
+            let (from, to) = ((start - self.pos) as usize, (end - self.pos) as usize);
+            let pos = (start - self.offset) as usize;
+            self.buf[pos..pos + to - from].copy_from_slice(&data[from..to]);
+        }
+        self.pos += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
     }
 }
 
@@ -264,11 +665,15 @@ pub(crate) fn respond(resp: Response, catch_status: bool) -> ConnectionResult<Re
 #[derive(Debug)]
 pub(crate) struct Connection {
     client: Client,
+    #[cfg(feature = "backend-async")]
+    async_client: reqwest::Client,
     proxy: Option<Arc<Proxy>>,
     pub mirrors: Vec<Arc<Mirror>>,
     pub shutdown: AtomicBool,
     /// Timestamp of connection's last active request, represents as duration since UNIX_EPOCH in seconds.
     last_active: Arc<AtomicU64>,
+    /// Maximum size of response body accepted from server, 0 means no limit.
+    max_response_size: u64,
 }
 
 #[derive(Debug)]
@@ -345,6 +750,8 @@ impl Connection {
 
         let connection = Arc::new(Connection {
             client,
+            #[cfg(feature = "backend-async")]
+            async_client: Self::build_async_connection(config)?,
             proxy,
             mirrors,
             shutdown: AtomicBool::new(false),
@@ -354,6 +761,7 @@ impl Connection {
                     .unwrap()
                     .as_secs(),
             )),
+            max_response_size: config.max_response_size,
         });
 
         // Start proxy's health checking thread.
@@ -638,6 +1046,48 @@ impl Connection {
         )
     }
 
+    /// Check whether requests may be sent by `call_async()`.
+    ///
+    /// Asynchronous requests are always sent to the original server, so they are unavailable
+    /// if a proxy or mirrors are configured.
+    #[cfg(feature = "backend-async")]
+    pub fn supports_async(&self) -> bool {
+        self.proxy.is_none() && self.mirrors.is_empty()
+    }
+
+    /// Send a request without body to the original server asynchronously.
+    #[cfg(feature = "backend-async")]
+    pub async fn call_async(
+        &self,
+        method: Method,
+        url: &str,
+        headers: &HeaderMap,
+        catch_status: bool,
+    ) -> ConnectionResult<reqwest::Response> {
+        if self.shutdown.load(Ordering::Acquire) {
+            return Err(ConnectionError::Disconnected);
+        }
+        self.last_active.store(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            Ordering::Relaxed,
+        );
+
+        let resp = self
+            .async_client
+            .request(method.clone(), url)
+            .headers(headers.clone())
+            .send()
+            .await
+            .map_err(ConnectionError::Common)?;
+        if method != Method::HEAD {
+            self.check_response_size(resp.content_length())?;
+        }
+        respond_async(resp, catch_status).await
+    }
+
     fn build_connection(proxy: &str, config: &ConnectionConfig) -> Result<Client> {
         let connect_timeout = if config.connect_timeout != 0 {
             Some(Duration::from_secs(config.connect_timeout as u64))
@@ -666,6 +1116,22 @@ impl Connection {
         cb.build().map_err(|e| einval!(e))
     }
 
+    #[cfg(feature = "backend-async")]
+    fn build_async_connection(config: &ConnectionConfig) -> Result<reqwest::Client> {
+        let mut cb = reqwest::Client::builder().redirect(Policy::none());
+        if config.timeout != 0 {
+            cb = cb.timeout(Duration::from_secs(config.timeout as u64));
+        }
+        if config.connect_timeout != 0 {
+            cb = cb.connect_timeout(Duration::from_secs(config.connect_timeout as u64));
+        }
+        if config.skip_verify {
+            cb = cb.danger_accept_invalid_certs(true);
+        }
+
+        cb.build().map_err(|e| einval!(e))
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn call_inner<R: Read + Clone + Send + 'static>(
         &self,
@@ -725,9 +1191,90 @@ impl Connection {
 
         match ret {
             Err(err) => Err(ConnectionError::Common(err)),
-            Ok(resp) => respond(resp, catch_status),
+            Ok(resp) => {
+                // HEAD requests report size of the target resource instead of the response body.
+                if method != Method::HEAD {
+                    self.check_response_size(resp.content_length())?;
+                }
+                respond(resp, catch_status)
+            }
         }
     }
+
+    /// Get the maximum size of response body accepted from server, 0 means no limit.
+    ///
+    /// Response bodies without `Content-Length` are only checked while being received, so readers
+    /// of response bodies should enforce the limit too.
+    pub(crate) fn max_response_size(&self) -> u64 {
+        self.max_response_size
+    }
+
+    /// Reject responses announcing a body larger than the configured limit, so a misbehaving
+    /// server can't stream unbounded data to us.
+    fn check_response_size(&self, content_length: Option<u64>) -> ConnectionResult<()> {
+        if self.max_response_size > 0 {
+            if let Some(size) = content_length {
+                if size > self.max_response_size {
+                    return Err(ConnectionError::ResponseTooLarge(
+                        size,
+                        self.max_response_size,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Start a HTTP server for tests, which serves ranged reads of `blob` at any path after `delay`.
+#[cfg(test)]
+pub(crate) fn start_mock_server(blob: Vec<u8>, delay: Duration) -> std::net::SocketAddr {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let blob = Arc::new(blob);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let blob = blob.clone();
+            thread::spawn(move || {
+                let mut req = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => return,
+                        Ok(size) => req.extend_from_slice(&buf[..size]),
+                    }
+                }
+                let req = String::from_utf8_lossy(&req).to_lowercase();
+                let (status, body) = match req
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| r.split_once('-'))
+                {
+                    Some((start, end)) => {
+                        let start = start.parse::<usize>().unwrap();
+                        let end = std::cmp::min(end.parse::<usize>().unwrap(), blob.len() - 1);
+                        ("206 Partial Content", &blob[start..=end])
+                    }
+                    None => ("200 OK", &blob[..]),
+                };
+                thread::sleep(delay);
+                let _ = stream.write_all(
+                    format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    )
+                    .as_bytes(),
+                );
+                let _ = stream.write_all(body);
+            });
+        }
+    });
+
+    addr
 }
 
 #[cfg(test)]
@@ -789,4 +1336,201 @@ mod tests {
         assert_eq!(config.proxy.url, "");
         assert!(config.mirrors.is_empty());
     }
+
+    #[test]
+    fn test_connection_max_response_size() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        assert_eq!(ConnectionConfig::default().max_response_size, 0);
+
+        // A mock server streaming a response much larger than requested.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            for idx in 0..4 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut req = [0u8; 1024];
+                let _ = stream.read(&mut req);
+                let body = vec![0x5au8; 0x10000];
+                // The last response has no `Content-Length`, its body ends when connection closes.
+                let length = match idx {
+                    3 => String::new(),
+                    _ => format!("Content-Length: {}\r\n", body.len()),
+                };
+                let _ = stream.write_all(
+                    format!("HTTP/1.1 200 OK\r\n{}Connection: close\r\n\r\n", length).as_bytes(),
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+        let url = format!("http://{}/blob", addr);
+
+        let config = ConnectionConfig {
+            max_response_size: 0x1000,
+            ..Default::default()
+        };
+        let connection = Connection::new(&config).unwrap();
+        let ret =
+            connection.call::<&[u8]>(Method::GET, &url, None, None, &mut HeaderMap::new(), true);
+        assert!(matches!(
+            ret,
+            Err(ConnectionError::ResponseTooLarge(0x10000, 0x1000))
+        ));
+
+        let config = ConnectionConfig::default();
+        let connection = Connection::new(&config).unwrap();
+        let mut resp = connection
+            .call::<&[u8]>(Method::GET, &url, None, None, &mut HeaderMap::new(), true)
+            .unwrap();
+        let mut buf = vec![0u8; 0x10000];
+        assert_eq!(copy_response(&mut resp, &mut buf, 0).unwrap(), 0x10000);
+        assert_eq!(buf[0xffff], 0x5a);
+
+        // Bodies larger than the buffer are errors instead of truncated data.
+        for _ in 0..2 {
+            let mut resp = connection
+                .call::<&[u8]>(Method::GET, &url, None, None, &mut HeaderMap::new(), true)
+                .unwrap();
+            let mut buf = vec![0u8; 0x1000];
+            assert!(matches!(
+                copy_response(&mut resp, &mut buf, 0),
+                Err(CopyError::Oversized(0x1000))
+            ));
+        }
+
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_range_fallback() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        let blob: Vec<u8> = (0..0x10000u32).map(|i| (i % 251) as u8).collect();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicU64::new(0));
+        let server_blob = blob.clone();
+        let server_requests = requests.clone();
+        // Every other request ignores the `Range` header and gets the whole object.
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut req = [0u8; 1024];
+                let size = stream.read(&mut req).unwrap();
+                let req = String::from_utf8_lossy(&req[..size]).to_lowercase();
+                let idx = server_requests.fetch_add(1, Ordering::Relaxed);
+                let range = req
+                    .lines()
+                    .find_map(|l| l.strip_prefix("range: bytes="))
+                    .and_then(|r| r.split_once('-'))
+                    .map(|(s, e)| (s.parse::<usize>().unwrap(), e.parse::<usize>().unwrap()));
+                let (status, body) = match range {
+                    Some((start, end)) if idx % 2 == 0 => {
+                        ("206 Partial Content", &server_blob[start..=end])
+                    }
+                    _ => ("200 OK", &server_blob[..]),
+                };
+                let _ = stream.write_all(
+                    format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    )
+                    .as_bytes(),
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+        let url = format!("http://{}/blob", addr);
+
+        let connection = Connection::new(&ConnectionConfig::default()).unwrap();
+        let fallback = RangeFallback::default();
+        let read = |offset: u64, size: usize| {
+            let mut buf = vec![0u8; size];
+            if let Some(size) = fallback.read_cached(&mut buf, offset) {
+                assert_eq!(size, buf.len());
+            } else {
+                let mut headers = HeaderMap::new();
+                let range = format!("bytes={}-{}", offset, offset + size as u64 - 1);
+                headers.insert("Range", range.parse().unwrap());
+                let mut resp = connection
+                    .call::<&[u8]>(Method::GET, &url, None, None, &mut headers, true)
+                    .unwrap();
+                let size = fallback
+                    .copy_response(&mut resp, &mut buf, offset, 0)
+                    .unwrap();
+                assert_eq!(size, buf.len());
+            }
+            assert_eq!(buf, blob[offset as usize..offset as usize + size]);
+        };
+
+        // Ranges are requested until the server has ignored them for several times.
+        for idx in 0..6 {
+            read(0x1000 * idx + 0x10, 0x100);
+        }
+        assert_eq!(requests.load(Ordering::Relaxed), 6);
+        // Then reads are served from the whole object.
+        read(0x8000, 0x1000);
+        read(0xff00, 0x100);
+        assert_eq!(requests.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn test_max_response_size_while_streaming() {
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        // A mock server ignoring the `Range` header and streaming the whole object without
+        // `Content-Length`, so the body size is unknown until connection closes.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut req = [0u8; 1024];
+                let _ = stream.read(&mut req);
+                let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n");
+                let _ = stream.write_all(&[0x5au8; 0x10000]);
+            }
+        });
+        let url = format!("http://{}/blob", addr);
+        let config = ConnectionConfig {
+            max_response_size: 0x4000,
+            ..Default::default()
+        };
+        let connection = Connection::new(&config).unwrap();
+        let limit = connection.max_response_size();
+        let get = || {
+            connection
+                .call::<&[u8]>(Method::GET, &url, None, None, &mut HeaderMap::new(), true)
+                .unwrap()
+        };
+
+        let mut buf = vec![0u8; 0x10000];
+        assert!(matches!(
+            copy_response(&mut get(), &mut buf, limit),
+            Err(CopyError::TooLarge(0x4000))
+        ));
+
+        // Data within the limit is sliced from the whole object.
+        let fallback = RangeFallback::default();
+        let mut buf = vec![0u8; 0x1000];
+        assert_eq!(
+            fallback
+                .copy_response(&mut get(), &mut buf, 0x1000, limit)
+                .unwrap(),
+            0x1000
+        );
+        // Data beyond the limit is never reached, neither by skipping data before it nor by
+        // saving the whole object.
+        for _ in 0..RANGE_IGNORED_LIMIT {
+            assert!(matches!(
+                fallback.copy_response(&mut get(), &mut buf, 0x8000, limit),
+                Err(CopyError::TooLarge(0x4000))
+            ));
+        }
+        assert!(fallback.read_cached(&mut buf, 0).is_none());
+    }
 }
@@ -0,0 +1,161 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bounded pool of threads to issue backend requests in background.
+//!
+//! Backend requests can't be interrupted, so a request abandoned by its caller keeps a thread busy
+//! until it completes. The pool never runs more than `max_threads` requests at a time, and new
+//! requests are rejected instead of queued behind stalled ones once all threads are busy.
+
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct State {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    idle: usize,
+}
+
+#[derive(Default)]
+struct Inner {
+    state: Mutex<State>,
+    // Signaled when a job gets queued.
+    queued: Condvar,
+    // Signaled when a worker becomes idle.
+    idle: Condvar,
+}
+
+/// A bounded pool of threads to run backend requests.
+pub(crate) struct Executor {
+    name: &'static str,
+    max_threads: usize,
+    inner: Arc<Inner>,
+}
+
+impl Executor {
+    /// Create a new instance of `Executor`, whose threads are spawned on demand.
+    pub(crate) fn new(name: &'static str, max_threads: usize) -> Self {
+        Executor {
+            name,
+            max_threads,
+            inner: Arc::new(Inner::default()),
+        }
+    }
+
+    /// Run `job` in background, waiting for at most `wait` if all threads are busy.
+    ///
+    /// Returns false if no thread is available to run the job, and the job gets dropped.
+    pub(crate) fn submit<F>(&self, job: F, wait: Duration) -> bool
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let deadline = Instant::now() + wait;
+        let mut state = self.inner.state.lock().unwrap();
+        loop {
+            if state.jobs.len() < state.idle {
+                break;
+            } else if state.threads < self.max_threads && self.spawn_worker() {
+                state.threads += 1;
+                state.idle += 1;
+                break;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self
+                .inner
+                .idle
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
+        state.jobs.push_back(Box::new(job));
+        self.inner.queued.notify_one();
+        true
+    }
+
+    fn spawn_worker(&self) -> bool {
+        let inner = self.inner.clone();
+        thread::Builder::new()
+            .name(self.name.to_string())
+            .spawn(move || Self::run_worker(inner))
+            .map_err(|e| warn!("failed to spawn thread for backend request, {}", e))
+            .is_ok()
+    }
+
+    fn run_worker(inner: Arc<Inner>) {
+        let mut state = inner.state.lock().unwrap();
+        loop {
+            match state.jobs.pop_front() {
+                Some(job) => {
+                    state.idle -= 1;
+                    drop(state);
+                    // Senders captured by a panicking job get dropped, which wakes up the waiter.
+                    if catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        warn!("backend request panicked");
+                    }
+                    state = inner.state.lock().unwrap();
+                    state.idle += 1;
+                    inner.idle.notify_one();
+                }
+                None => state = inner.queued.wait(state).unwrap(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+
+    use super::*;
+
+    #[test]
+    fn test_executor_bounded() {
+        let executor = Executor::new("test_executor", 2);
+        let (tx, rx) = channel();
+        let (done_tx, done_rx) = channel::<()>();
+        let done_rx = Arc::new(Mutex::new(done_rx));
+        for _ in 0..2 {
+            let tx = tx.clone();
+            let done_rx = done_rx.clone();
+            assert!(executor.submit(
+                move || {
+                    let _ = done_rx.lock().unwrap().recv();
+                    tx.send(()).unwrap();
+                },
+                Duration::ZERO
+            ));
+        }
+        // All threads are busy.
+        assert!(!executor.submit(|| {}, Duration::from_millis(10)));
+
+        // Wait for a thread to become idle.
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            done_tx.send(()).unwrap();
+            done_tx
+        });
+        let tx2 = tx.clone();
+        assert!(executor.submit(move || tx2.send(()).unwrap(), Duration::from_secs(5)));
+        rx.recv().unwrap();
+        rx.recv().unwrap();
+        drop(handle.join().unwrap());
+        rx.recv().unwrap();
+
+        // Panicking jobs don't take threads away.
+        assert!(executor.submit(|| panic!("mock panic"), Duration::ZERO));
+        let tx2 = tx.clone();
+        assert!(executor.submit(move || tx2.send(()).unwrap(), Duration::from_secs(5)));
+        rx.recv().unwrap();
+        assert_eq!(executor.inner.state.lock().unwrap().threads, 2);
+    }
+}
@@ -0,0 +1,335 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to hedge slow reads with a replica backend.
+//!
+//! If a read from the primary backend hasn't completed within the configured delay, or it fails,
+//! the same range is requested from the replica backend too, and the first successful response
+//! wins. Backend requests can't be interrupted, so the losing request runs to completion in
+//! background and its data gets discarded. Requests are issued from a bounded pool of threads,
+//! and reads aren't hedged once all threads are busy.
+
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::executor::Executor;
+use crate::backend::{BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader};
+use crate::utils::alloc_buf;
+
+/// Maximum number of concurrent requests issued for hedged reads.
+const HEDGE_MAX_THREADS: usize = 32;
+
+lazy_static! {
+    static ref HEDGE_EXECUTOR: Executor = Executor::new("nydus_hedged_read", HEDGE_MAX_THREADS);
+}
+
+type HedgedResult = BackendResult<Vec<u8>>;
+
+/// A storage backend hedging slow reads from the primary backend with a replica backend.
+pub struct HedgedBackend {
+    primary: Arc<dyn BlobBackend + Send + Sync>,
+    replica: Arc<dyn BlobBackend + Send + Sync>,
+    delay: Duration,
+}
+
+impl HedgedBackend {
+    /// Create a new instance of `HedgedBackend`.
+    pub fn new(
+        primary: Arc<dyn BlobBackend + Send + Sync>,
+        replica: Arc<dyn BlobBackend + Send + Sync>,
+        delay: Duration,
+    ) -> Self {
+        HedgedBackend {
+            primary,
+            replica,
+            delay,
+        }
+    }
+}
+
+impl BlobBackend for HedgedBackend {
+    fn shutdown(&self) {
+        self.primary.shutdown();
+        self.replica.shutdown();
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.primary.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        Ok(Arc::new(HedgedReader {
+            primary: self.primary.get_reader(blob_id)?,
+            replica: self.replica.get_reader(blob_id)?,
+            delay: self.delay,
+        }))
+    }
+}
+
+struct HedgedReader {
+    primary: Arc<dyn BlobReader>,
+    replica: Arc<dyn BlobReader>,
+    delay: Duration,
+}
+
+impl HedgedReader {
+    // Read the range from `reader` in background, returns false if all threads are busy.
+    fn spawn_read(
+        reader: &Arc<dyn BlobReader>,
+        tx: &Sender<HedgedResult>,
+        size: usize,
+        offset: u64,
+    ) -> bool {
+        let reader = reader.clone();
+        let tx = tx.clone();
+        HEDGE_EXECUTOR.submit(
+            move || {
+                let mut buf = alloc_buf(size);
+                let res = reader.read(&mut buf, offset).map(|size| {
+                    buf.truncate(size);
+                    buf
+                });
+                // The receiver is gone if another request has won.
+                let _ = tx.send(res);
+            },
+            Duration::ZERO,
+        )
+    }
+}
+
+impl BlobReader for HedgedReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.primary.blob_size()
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let (tx, rx) = channel();
+        if !Self::spawn_read(&self.primary, &tx, buf.len(), offset) {
+            return self.primary.read(buf, offset);
+        }
+
+        // Keep the sender until the replica request is issued, so it's dropped before waiting
+        // for the last response and requests exiting without a response can be detected.
+        let mut tx = Some(tx);
+        let mut inflight = 1;
+        loop {
+            let res = match tx.as_ref() {
+                Some(_) => rx.recv_timeout(self.delay),
+                None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match res {
+                Ok(Ok(data)) => {
+                    buf[..data.len()].copy_from_slice(&data);
+                    return Ok(data.len());
+                }
+                Ok(Err(e)) => {
+                    inflight -= 1;
+                    match tx.take() {
+                        Some(tx) => {
+                            warn!("read at 0x{:x} failed, try replica backend, {}", offset, e);
+                            if !Self::spawn_read(&self.replica, &tx, buf.len(), offset) {
+                                return self.replica.read(buf, offset);
+                            }
+                            inflight += 1;
+                        }
+                        None if inflight == 0 => return Err(e),
+                        None => warn!("hedged read at 0x{:x} failed, {}", offset, e),
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    debug!(
+                        "read at 0x{:x} doesn't complete in {}ms, hedge with replica backend",
+                        offset,
+                        self.delay.as_millis()
+                    );
+                    if let Some(tx) = tx.take() {
+                        if Self::spawn_read(&self.replica, &tx, buf.len(), offset) {
+                            inflight += 1;
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(BackendError::Unsupported(
+                        "hedged read requests exited unexpectedly".to_string(),
+                    ))
+                }
+            }
+        }
+    }
+
+    // Retries are handled by the primary and replica readers.
+    fn read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        self.try_read(buf, offset)
+    }
+
+    fn read_url(&self, url: &str, buf: &mut [u8]) -> BackendResult<usize> {
+        self.primary.read_url(url, buf)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.primary.metrics()
+    }
+
+    fn max_coalesce_gap(&self) -> u64 {
+        self.primary.max_coalesce_gap()
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        // Requests may be served by either of the readers, and asynchronous reads are served by
+        // `read()` on the blocking thread pool.
+        self.primary.capabilities() & self.replica.capabilities() & !BackendCaps::SUPPORTS_ASYNC
+    }
+
+    fn stop_prefetch_data(&self) -> BackendResult<()> {
+        self.primary.stop_prefetch_data()?;
+        self.replica.stop_prefetch_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Instant;
+
+    use super::*;
+
+    struct MockBackend {
+        data: u8,
+        latency: Duration,
+        fail: bool,
+        panic: bool,
+        reads: Arc<AtomicUsize>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl MockBackend {
+        fn new(data: u8, latency: u64, fail: bool) -> Arc<Self> {
+            Arc::new(MockBackend {
+                data,
+                latency: Duration::from_millis(latency),
+                fail,
+                panic: false,
+                reads: Arc::new(AtomicUsize::new(0)),
+                metrics: BackendMetrics::new("hedge", "localfs"),
+            })
+        }
+    }
+
+    struct MockReader {
+        data: u8,
+        latency: Duration,
+        fail: bool,
+        panic: bool,
+        reads: Arc<AtomicUsize>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for MockReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(0x1000)
+        }
+
+        fn try_read(&self, buf: &mut [u8], _offset: u64) -> BackendResult<usize> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            thread::sleep(self.latency);
+            if self.panic {
+                panic!("mock panic");
+            }
+            if self.fail {
+                return Err(BackendError::Unsupported("mock failure".to_string()));
+            }
+            buf.fill(self.data);
+            Ok(buf.len())
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    impl BlobBackend for MockBackend {
+        fn shutdown(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn get_reader(&self, _blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+            Ok(Arc::new(MockReader {
+                data: self.data,
+                latency: self.latency,
+                fail: self.fail,
+                panic: self.panic,
+                reads: self.reads.clone(),
+                metrics: self.metrics.clone(),
+            }))
+        }
+    }
+
+    fn read(primary: &Arc<MockBackend>, replica: &Arc<MockBackend>) -> (BackendResult<u8>, u128) {
+        let backend =
+            HedgedBackend::new(primary.clone(), replica.clone(), Duration::from_millis(20));
+        let reader = backend.get_reader("blob").unwrap();
+        let mut buf = vec![0u8; 0x1000];
+        let start = Instant::now();
+        let res = reader.read(&mut buf, 0).map(|size| {
+            assert_eq!(size, buf.len());
+            buf[0]
+        });
+        (res, start.elapsed().as_millis())
+    }
+
+    #[test]
+    fn test_hedged_read() {
+        // The replica wins if the primary is slow.
+        let primary = MockBackend::new(1, 1000, false);
+        let replica = MockBackend::new(2, 0, false);
+        let (res, elapsed) = read(&primary, &replica);
+        assert_eq!(res.unwrap(), 2);
+        assert!(elapsed < 500);
+        assert_eq!(replica.reads.load(Ordering::Relaxed), 1);
+
+        // No hedged read if the primary is fast enough.
+        let primary = MockBackend::new(1, 0, false);
+        let replica = MockBackend::new(2, 0, false);
+        let (res, _) = read(&primary, &replica);
+        assert_eq!(res.unwrap(), 1);
+        assert_eq!(replica.reads.load(Ordering::Relaxed), 0);
+
+        // The replica is tried immediately if the primary fails.
+        let primary = MockBackend::new(1, 0, true);
+        let replica = MockBackend::new(2, 0, false);
+        let (res, _) = read(&primary, &replica);
+        assert_eq!(res.unwrap(), 2);
+
+        // Fail if both fail.
+        let primary = MockBackend::new(1, 0, true);
+        let replica = MockBackend::new(2, 50, true);
+        let (res, _) = read(&primary, &replica);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_hedged_read_panic() {
+        // Fail instead of hanging if requests exit without a response.
+        let primary = Arc::new(MockBackend {
+            data: 1,
+            latency: Duration::ZERO,
+            fail: false,
+            panic: true,
+            reads: Arc::new(AtomicUsize::new(0)),
+            metrics: BackendMetrics::new("hedge", "localfs"),
+        });
+        let replica = primary.clone();
+        let (res, elapsed) = read(&primary, &replica);
+        assert!(res.is_err());
+        assert!(elapsed < 1000);
+        assert_eq!(primary.reads.load(Ordering::Relaxed), 2);
+    }
+}
@@ -14,8 +14,8 @@ use nydus_utils::metrics::BackendMetrics;
 use reqwest;
 use tokio::runtime::Runtime;
 
-use super::connection::{Connection, ConnectionConfig, ConnectionError};
-use super::{BackendError, BackendResult, BlobBackend, BlobReader};
+use super::connection::{copy_response, Connection, ConnectionConfig, ConnectionError, CopyError};
+use super::{BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader};
 use std::path::Path;
 use std::{
     fmt,
@@ -257,17 +257,49 @@ impl BlobReader for HttpProxyReader {
                     .call::<&[u8]>(Method::GET, uri.as_str(), None, None, &mut headers, true)
                     .map_err(HttpProxyError::RemoteRequest)?;
 
-                Ok(resp
-                    .copy_to(&mut buf)
-                    .map_err(HttpProxyError::Transport)
-                    .map(|size| size as usize)?)
+                copy_response(&mut resp, buf, connection.max_response_size())
+                    .map_err(|e| e.into_backend_error(HttpProxyError::Transport))
             }
         }
     }
 
+    fn read_url(&self, url: &str, buf: &mut [u8]) -> BackendResult<usize> {
+        let connection = match &self.client {
+            Client::Local(_) => {
+                return Err(BackendError::Unsupported(format!(
+                    "reading chunk data from {} through local http proxy is not supported",
+                    url
+                )))
+            }
+            Client::Remote(connection) => connection,
+        };
+        let mut headers = HeaderMap::new();
+        let range = range_str_for_header(0, Some(buf.len()));
+        headers.insert(
+            http::header::RANGE,
+            range
+                .as_str()
+                .parse()
+                .map_err(|e| HttpProxyError::ConstructHeader(format!("{}", e)))?,
+        );
+        let mut resp = connection
+            .call::<&[u8]>(Method::GET, url, None, None, &mut headers, true)
+            .map_err(HttpProxyError::RemoteRequest)?;
+
+        copy_response(&mut resp, buf, connection.max_response_size()).map_err(|e| match e {
+            CopyError::Transport(_, e) => HttpProxyError::Transport(e).into(),
+            CopyError::Oversized(size) => BackendError::Oversized(size),
+            CopyError::TooLarge(limit) => BackendError::ResponseTooLarge(limit),
+        })
+    }
+
     fn metrics(&self) -> &nydus_utils::metrics::BackendMetrics {
         &self.metrics
     }
+
+    fn capabilities(&self) -> BackendCaps {
+        BackendCaps::SUPPORTS_RANGE | BackendCaps::SUPPORTS_MULTIPART
+    }
 }
 
 impl HttpProxy {
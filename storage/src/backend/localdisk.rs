@@ -17,7 +17,7 @@ use nix::sys::uio;
 use nydus_api::LocalDiskConfig;
 use nydus_utils::metrics::BackendMetrics;
 
-use crate::backend::{BackendError, BackendResult, BlobBackend, BlobReader};
+use crate::backend::{BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader};
 use crate::utils::{readv, MemSliceCursor};
 
 type LocalDiskResult<T> = std::result::Result<T, LocalDiskError>;
@@ -133,6 +133,10 @@ impl BlobReader for LocalDiskBlob {
     fn metrics(&self) -> &BackendMetrics {
         &self.metrics
     }
+
+    fn capabilities(&self) -> BackendCaps {
+        BackendCaps::SUPPORTS_RANGE | BackendCaps::SUPPORTS_MULTIPART
+    }
 }
 
 /// Storage backend based on local disk.
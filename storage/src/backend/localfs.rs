@@ -18,7 +18,7 @@ use nix::sys::uio;
 use nydus_api::LocalFsConfig;
 use nydus_utils::metrics::BackendMetrics;
 
-use crate::backend::{BackendError, BackendResult, BlobBackend, BlobReader};
+use crate::backend::{BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader};
 use crate::utils::{readv, MemSliceCursor};
 
 type LocalFsResult<T> = std::result::Result<T, LocalFsError>;
@@ -84,6 +84,10 @@ impl BlobReader for LocalFsEntry {
     fn metrics(&self) -> &BackendMetrics {
         &self.metrics
     }
+
+    fn capabilities(&self) -> BackendCaps {
+        BackendCaps::SUPPORTS_RANGE | BackendCaps::SUPPORTS_MULTIPART
+    }
 }
 
 /// Storage backend based on local filesystem.
@@ -0,0 +1,260 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to enforce a minimum size for read requests.
+//!
+//! Object storage services charging per request perform poorly with tiny reads, such as fetching
+//! a small chunk by a dedicated request. Reads smaller than the configured minimum size are
+//! extended to an aligned range of the minimum size, and data of recently fetched ranges is kept
+//! in memory to serve following reads of adjacent data.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::{
+    BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader, RetryPolicy,
+};
+use crate::utils::alloc_buf;
+
+// Number of recently fetched ranges kept in memory for each blob.
+const MIN_READ_CACHED_RANGES: usize = 8;
+
+/// A storage backend issuing read requests no smaller than a minimum size.
+pub struct MinReadBackend {
+    inner: Arc<dyn BlobBackend + Send + Sync>,
+    min_size: u64,
+}
+
+impl MinReadBackend {
+    /// Create a new instance of `MinReadBackend`.
+    pub fn new(inner: Arc<dyn BlobBackend + Send + Sync>, min_size: u64) -> Self {
+        MinReadBackend { inner, min_size }
+    }
+}
+
+impl BlobBackend for MinReadBackend {
+    fn shutdown(&self) {
+        self.inner.shutdown();
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        Ok(Arc::new(MinReadReader {
+            inner: self.inner.get_reader(blob_id)?,
+            min_size: self.min_size,
+            blob_size: OnceLock::new(),
+            ranges: Mutex::new(VecDeque::with_capacity(MIN_READ_CACHED_RANGES)),
+        }))
+    }
+}
+
+struct MinReadReader {
+    inner: Arc<dyn BlobReader>,
+    min_size: u64,
+    blob_size: OnceLock<u64>,
+    // Recently fetched ranges in form of (offset, data), the most recently used one is the last.
+    ranges: Mutex<VecDeque<(u64, Arc<Vec<u8>>)>>,
+}
+
+impl MinReadReader {
+    // Serve the read from recently fetched ranges if they contain the requested data.
+    fn read_cached(&self, buf: &mut [u8], offset: u64) -> Option<usize> {
+        let end = offset.checked_add(buf.len() as u64)?;
+        let mut ranges = self.ranges.lock().unwrap();
+        let idx = ranges
+            .iter()
+            .position(|(start, data)| *start <= offset && end <= *start + data.len() as u64)?;
+        let range = ranges.remove(idx)?;
+        let pos = (offset - range.0) as usize;
+        buf.copy_from_slice(&range.1[pos..pos + buf.len()]);
+        ranges.push_back(range);
+        Some(buf.len())
+    }
+
+    fn cache_range(&self, offset: u64, data: Vec<u8>) {
+        let mut ranges = self.ranges.lock().unwrap();
+        if ranges.len() >= MIN_READ_CACHED_RANGES {
+            ranges.pop_front();
+        }
+        ranges.push_back((offset, Arc::new(data)));
+    }
+
+    fn get_blob_size(&self) -> BackendResult<u64> {
+        if let Some(size) = self.blob_size.get() {
+            return Ok(*size);
+        }
+        let size = self.inner.blob_size()?;
+        Ok(*self.blob_size.get_or_init(|| size))
+    }
+}
+
+impl BlobReader for MinReadReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.get_blob_size()
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let size = buf.len() as u64;
+        if size == 0 || size >= self.min_size {
+            return self.inner.try_read(buf, offset);
+        }
+        if let Some(size) = self.read_cached(buf, offset) {
+            return Ok(size);
+        }
+
+        let blob_size = self.get_blob_size()?;
+        if offset >= blob_size {
+            return self.inner.try_read(buf, offset);
+        }
+        // Fetch the aligned range containing the requested data, which may span two aligned
+        // ranges, and move the range backward if exceeding the end of the blob.
+        let mut start = offset - offset % self.min_size;
+        let mut end = start + self.min_size;
+        if end < offset + size {
+            end += self.min_size;
+        }
+        if end > blob_size {
+            end = blob_size;
+            start = std::cmp::min(start, end.saturating_sub(self.min_size));
+        }
+
+        let mut data = alloc_buf((end - start) as usize);
+        let nr_read = match self.inner.try_read(&mut data, start) {
+            Ok(v) => v,
+            // The received data may not reach the requested range, so just restart it.
+            Err(BackendError::Interrupted(_, e)) => return Err(*e),
+            Err(e) => return Err(e),
+        };
+        data.truncate(nr_read);
+        let pos = (offset - start) as usize;
+        let size = std::cmp::min(buf.len(), data.len().saturating_sub(pos));
+        buf[..size].copy_from_slice(&data[pos..pos + size]);
+        if nr_read > 0 {
+            self.cache_range(start, data);
+        }
+
+        Ok(size)
+    }
+
+    fn read_url(&self, url: &str, buf: &mut [u8]) -> BackendResult<usize> {
+        self.inner.read_url(url, buf)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.inner.retry_limit()
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn max_coalesce_gap(&self) -> u64 {
+        self.inner.max_coalesce_gap()
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        // Asynchronous reads are served by `read()` on the blocking thread pool.
+        self.inner.capabilities() & !BackendCaps::SUPPORTS_ASYNC
+    }
+
+    fn stop_prefetch_data(&self) -> BackendResult<()> {
+        self.inner.stop_prefetch_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockBackend {
+        reads: Arc<Mutex<Vec<(u64, usize)>>>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    struct MockReader {
+        reads: Arc<Mutex<Vec<(u64, usize)>>>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for MockReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(0x38000)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            self.reads.lock().unwrap().push((offset, buf.len()));
+            let size = std::cmp::min(buf.len() as u64, 0x38000u64.saturating_sub(offset));
+            for (idx, v) in buf[..size as usize].iter_mut().enumerate() {
+                *v = ((offset + idx as u64) % 251) as u8;
+            }
+            Ok(size as usize)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    impl BlobBackend for MockBackend {
+        fn shutdown(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn get_reader(&self, _blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+            Ok(Arc::new(MockReader {
+                reads: self.reads.clone(),
+                metrics: self.metrics.clone(),
+            }))
+        }
+    }
+
+    #[test]
+    fn test_min_read_size() {
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let backend = MinReadBackend::new(
+            Arc::new(MockBackend {
+                reads: reads.clone(),
+                metrics: BackendMetrics::new("min_read", "localfs"),
+            }),
+            0x10000,
+        );
+        let reader = backend.get_reader("blob").unwrap();
+        let read = |offset: u64, size: usize| {
+            let mut buf = vec![0u8; size];
+            assert_eq!(reader.read(&mut buf, offset).unwrap(), size);
+            for (idx, v) in buf.iter().enumerate() {
+                assert_eq!(*v, ((offset + idx as u64) % 251) as u8);
+            }
+            reads.lock().unwrap().len()
+        };
+
+        // Tiny reads fetch the aligned range, and adjacent reads are served from memory.
+        assert_eq!(read(0x1000, 200), 1);
+        assert_eq!(read(0x1100, 0x1000), 1);
+        assert_eq!(read(0xff00, 0x100), 1);
+        // A read crossing the aligned boundary fetches two aligned ranges.
+        assert_eq!(read(0x1ff00, 0x200), 2);
+        // The range is moved backward at the end of the blob.
+        assert_eq!(read(0x37f00, 0x100), 3);
+        // Big reads are passed through.
+        assert_eq!(read(0x3000, 0x10000), 4);
+
+        let reads = reads.lock().unwrap();
+        assert_eq!(reads[0], (0, 0x10000));
+        assert_eq!(reads[1], (0x10000, 0x20000));
+        assert_eq!(reads[2], (0x28000, 0x10000));
+        assert!(reads.iter().all(|(_, size)| *size >= 0x10000));
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to fall back to other endpoints serving the same blobs.
+//!
+//! Blobs may be served by several endpoints, such as a read-through cache mirror in front of the
+//! origin registry. Requests are sent to the endpoints in order until one of them succeeds, and
+//! an endpoint failing with network errors is skipped for a cooldown period as long as other
+//! endpoints are available.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::{BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader};
+
+#[derive(Default)]
+struct EndpointHealth {
+    // The endpoint is skipped until the instant if it has failed recently.
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl EndpointHealth {
+    fn is_healthy(&self) -> bool {
+        let mut guard = self.down_until.lock().unwrap();
+        match *guard {
+            Some(until) if until > Instant::now() => false,
+            Some(_) => {
+                *guard = None;
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn mark_down(&self, cooldown: Duration) {
+        *self.down_until.lock().unwrap() = Some(Instant::now() + cooldown);
+    }
+
+    fn mark_up(&self) {
+        *self.down_until.lock().unwrap() = None;
+    }
+}
+
+/// A storage backend reading blobs from the first available one of several endpoints.
+pub struct MirrorBackend {
+    backends: Vec<Arc<dyn BlobBackend + Send + Sync>>,
+    health: Arc<Vec<EndpointHealth>>,
+    cooldown: Duration,
+}
+
+impl MirrorBackend {
+    /// Create a new instance of `MirrorBackend` with endpoints in order of preference.
+    pub fn new(backends: Vec<Arc<dyn BlobBackend + Send + Sync>>, cooldown: Duration) -> Self {
+        assert!(!backends.is_empty());
+        let health = backends.iter().map(|_| EndpointHealth::default()).collect();
+        MirrorBackend {
+            backends,
+            health: Arc::new(health),
+            cooldown,
+        }
+    }
+
+    /// Get health state of the endpoints, an endpoint is unhealthy during the cooldown period
+    /// after failing.
+    pub fn health(&self) -> Vec<bool> {
+        self.health.iter().map(|h| h.is_healthy()).collect()
+    }
+}
+
+impl BlobBackend for MirrorBackend {
+    fn shutdown(&self) {
+        for backend in self.backends.iter() {
+            backend.shutdown();
+        }
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.backends[0].metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        let mut readers = Vec::with_capacity(self.backends.len());
+        let mut last_err = None;
+        for (idx, backend) in self.backends.iter().enumerate() {
+            match backend.get_reader(blob_id) {
+                Ok(reader) => readers.push((idx, reader)),
+                Err(e) => {
+                    warn!(
+                        "failed to get reader for blob {} from endpoint {}, {}",
+                        blob_id, idx, e
+                    );
+                    last_err = Some(e);
+                }
+            }
+        }
+        if readers.is_empty() {
+            return Err(last_err.unwrap());
+        }
+
+        Ok(Arc::new(MirrorReader {
+            readers,
+            health: self.health.clone(),
+            cooldown: self.cooldown,
+            last_good: AtomicUsize::new(0),
+        }))
+    }
+}
+
+struct MirrorReader {
+    // Readers in form of (endpoint index, reader).
+    readers: Vec<(usize, Arc<dyn BlobReader>)>,
+    health: Arc<Vec<EndpointHealth>>,
+    cooldown: Duration,
+    // Position in `readers` of the endpoint which has served the last successful request.
+    last_good: AtomicUsize,
+}
+
+impl MirrorReader {
+    // Get positions of readers to try in order: the last good one first, then other healthy ones
+    // in order, and unhealthy ones at the end.
+    fn candidates(&self) -> Vec<usize> {
+        let last_good = self.last_good.load(Ordering::Relaxed);
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) = (0..self.readers.len())
+            .filter(|pos| *pos != last_good)
+            .partition(|pos| self.health[self.readers[*pos].0].is_healthy());
+        if self.health[self.readers[last_good].0].is_healthy() {
+            healthy.insert(0, last_good);
+        } else {
+            healthy.push(last_good);
+        }
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    fn with_endpoints<T, F>(&self, mut op: F) -> BackendResult<T>
+    where
+        F: FnMut(&dyn BlobReader) -> BackendResult<T>,
+    {
+        let mut last_err = None;
+        for pos in self.candidates() {
+            let (idx, reader) = &self.readers[pos];
+            match op(reader.as_ref()) {
+                Ok(v) => {
+                    self.health[*idx].mark_up();
+                    self.last_good.store(pos, Ordering::Relaxed);
+                    return Ok(v);
+                }
+                Err(e) => {
+                    warn!("request to endpoint {} failed, {}", idx, e);
+                    // The endpoint may just miss the blob if failed with permanent errors.
+                    if e.is_retryable() {
+                        self.health[*idx].mark_down(self.cooldown);
+                    }
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            BackendError::Unsupported("no endpoint available for the blob".to_string())
+        }))
+    }
+}
+
+impl BlobReader for MirrorReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.with_endpoints(|reader| reader.blob_size())
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        self.with_endpoints(|reader| reader.try_read(buf, offset))
+    }
+
+    // Retries are handled by readers of the endpoints.
+    fn read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        self.with_endpoints(|reader| reader.read(buf, offset))
+    }
+
+    fn read_url(&self, url: &str, buf: &mut [u8]) -> BackendResult<usize> {
+        self.with_endpoints(|reader| reader.read_url(url, buf))
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.readers[0].1.metrics()
+    }
+
+    fn max_coalesce_gap(&self) -> u64 {
+        self.readers[0].1.max_coalesce_gap()
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        // Requests may fail over to any of the readers, and asynchronous reads are served by
+        // `read()` on the blocking thread pool.
+        self.readers
+            .iter()
+            .fold(BackendCaps::all(), |caps, (_, r)| caps & r.capabilities())
+            & !BackendCaps::SUPPORTS_ASYNC
+    }
+
+    fn stop_prefetch_data(&self) -> BackendResult<()> {
+        for (_, reader) in self.readers.iter() {
+            reader.stop_prefetch_data()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "backend-localfs"))]
+mod tests {
+    use super::*;
+    use crate::backend::localfs::LocalFsError;
+
+    struct MockBackend {
+        data: u8,
+        down: bool,
+        reads: Arc<AtomicUsize>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl MockBackend {
+        fn new(data: u8, down: bool) -> Arc<Self> {
+            Arc::new(MockBackend {
+                data,
+                down,
+                reads: Arc::new(AtomicUsize::new(0)),
+                metrics: BackendMetrics::new("mirror", "localfs"),
+            })
+        }
+
+        fn reads(&self) -> usize {
+            self.reads.load(Ordering::Relaxed)
+        }
+    }
+
+    struct MockReader {
+        data: u8,
+        down: bool,
+        reads: Arc<AtomicUsize>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for MockReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(0x1000)
+        }
+
+        fn try_read(&self, buf: &mut [u8], _offset: u64) -> BackendResult<usize> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            if self.down {
+                return Err(LocalFsError::ReadBlob("endpoint down".to_string()).into());
+            }
+            buf.fill(self.data);
+            Ok(buf.len())
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    impl BlobBackend for MockBackend {
+        fn shutdown(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn get_reader(&self, _blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+            Ok(Arc::new(MockReader {
+                data: self.data,
+                down: self.down,
+                reads: self.reads.clone(),
+                metrics: self.metrics.clone(),
+            }))
+        }
+    }
+
+    fn read(backend: &MirrorBackend) -> BackendResult<u8> {
+        let reader = backend.get_reader("blob").unwrap();
+        let mut buf = vec![0u8; 0x1000];
+        reader.read(&mut buf, 0).map(|size| {
+            assert_eq!(size, buf.len());
+            buf[0]
+        })
+    }
+
+    #[test]
+    fn test_mirror_backend() {
+        // The primary endpoint serves the reads if it's up.
+        let primary = MockBackend::new(1, false);
+        let origin = MockBackend::new(2, false);
+        let backend = MirrorBackend::new(
+            vec![primary.clone(), origin.clone()],
+            Duration::from_secs(60),
+        );
+        assert_eq!(read(&backend).unwrap(), 1);
+        assert_eq!(origin.reads(), 0);
+        assert_eq!(backend.health(), vec![true, true]);
+
+        // Fall back to the origin if the primary is down, and skip the primary then.
+        let primary = MockBackend::new(1, true);
+        let origin = MockBackend::new(2, false);
+        let backend = MirrorBackend::new(
+            vec![primary.clone(), origin.clone()],
+            Duration::from_secs(60),
+        );
+        assert_eq!(read(&backend).unwrap(), 2);
+        assert_eq!(backend.health(), vec![false, true]);
+        assert_eq!(read(&backend).unwrap(), 2);
+        assert_eq!(primary.reads(), 1);
+        assert_eq!(origin.reads(), 2);
+
+        // Fail if all endpoints are down, but still try them during the cooldown period.
+        let primary = MockBackend::new(1, true);
+        let origin = MockBackend::new(2, true);
+        let backend = MirrorBackend::new(
+            vec![primary.clone(), origin.clone()],
+            Duration::from_secs(60),
+        );
+        assert!(read(&backend).is_err());
+        assert_eq!(backend.health(), vec![false, false]);
+        assert!(read(&backend).is_err());
+        assert_eq!(primary.reads(), 2);
+        assert_eq!(origin.reads(), 2);
+    }
+}
@@ -13,16 +13,25 @@
 //!   The [LocalFs](localfs/struct.LocalFs.html) storage backend supports backend level data
 //!   prefetching, which is to load data into page cache.
 //! - [LocalDisk](localdisk/struct.LocalDisk.html): backend driver to access blobs on local disk.
+//! - [HedgedBackend](hedge/struct.HedgedBackend.html): backend driver to hedge slow reads from
+//!   another backend with a replica backend.
+//! - [MirrorBackend](mirror/struct.MirrorBackend.html): backend driver to fall back to other
+//!   endpoints serving the same blobs.
+//! - [PackedBackend](packed/struct.PackedBackend.html): backend driver to access blobs stored
+//!   back to back in packed objects.
 
 use std::fmt;
+#[cfg(feature = "backend-async")]
+use std::future::Future;
 use std::io::Read;
-use std::{sync::Arc, time::Duration};
+#[cfg(feature = "backend-async")]
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{sync::Arc, thread};
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
-use nydus_utils::{
-    metrics::{BackendMetrics, ERROR_HOLDER},
-    DelayType, Delayer,
-};
+use nydus_utils::metrics::{BackendMetrics, ERROR_HOLDER};
 
 use crate::utils::{alloc_buf, copyv};
 use crate::StorageError;
@@ -34,20 +43,26 @@ use crate::StorageError;
     feature = "backend-http-proxy",
 ))]
 pub mod connection;
+mod executor;
+pub mod hedge;
 #[cfg(feature = "backend-http-proxy")]
 pub mod http_proxy;
 #[cfg(feature = "backend-localdisk")]
 pub mod localdisk;
 #[cfg(feature = "backend-localfs")]
 pub mod localfs;
+pub mod min_read;
+pub mod mirror;
 #[cfg(any(feature = "backend-oss", feature = "backend-s3"))]
 pub mod object_storage;
 #[cfg(feature = "backend-oss")]
 pub mod oss;
+pub mod packed;
 #[cfg(feature = "backend-registry")]
 pub mod registry;
 #[cfg(feature = "backend-s3")]
 pub mod s3;
+pub mod timeout;
 
 /// Error codes related to storage backend operations.
 #[derive(Debug)]
@@ -56,6 +71,14 @@ pub enum BackendError {
     Unsupported(String),
     /// Failed to copy data from/into blob.
     CopyData(StorageError),
+    /// Data transfer interrupted after receiving the specified number of bytes.
+    Interrupted(usize, Box<BackendError>),
+    /// Request not completed within the specified duration.
+    Timeout(Duration),
+    /// Response carries more data than the requested range of the specified size.
+    Oversized(usize),
+    /// Response body exceeds the maximum response size of the specified bytes.
+    ResponseTooLarge(u64),
     #[cfg(feature = "backend-localdisk")]
     /// Error from LocalDisk storage backend.
     LocalDisk(self::localdisk::LocalDiskError),
@@ -78,6 +101,24 @@ impl fmt::Display for BackendError {
         match self {
             BackendError::Unsupported(s) => write!(f, "{}", s),
             BackendError::CopyData(e) => write!(f, "failed to copy data, {}", e),
+            BackendError::Interrupted(size, e) => {
+                write!(
+                    f,
+                    "transfer interrupted after receiving {} bytes, {}",
+                    size, e
+                )
+            }
+            BackendError::Timeout(d) => write!(f, "request timed out after {:?}", d),
+            BackendError::Oversized(size) => {
+                write!(f, "response body larger than requested {} bytes", size)
+            }
+            BackendError::ResponseTooLarge(limit) => {
+                write!(
+                    f,
+                    "response body exceeds the maximum allowed size {}",
+                    limit
+                )
+            }
             #[cfg(feature = "backend-registry")]
             BackendError::Registry(e) => write!(f, "{:?}", e),
             #[cfg(feature = "backend-localfs")]
@@ -92,9 +133,120 @@ impl fmt::Display for BackendError {
     }
 }
 
+impl BackendError {
+    /// Create an error for a data transfer interrupted after receiving `received` bytes.
+    pub fn interrupted<E: Into<BackendError>>(received: usize, err: E) -> Self {
+        if received == 0 {
+            err.into()
+        } else {
+            BackendError::Interrupted(received, Box::new(err.into()))
+        }
+    }
+
+    /// Check whether the failed request may succeed if issued again.
+    ///
+    /// Errors such as timeouts, throttling and server side failures are transient, but invalid
+    /// requests or missing blobs won't be fixed by retrying.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BackendError::Unsupported(_)
+            | BackendError::CopyData(_)
+            | BackendError::Oversized(_)
+            | BackendError::ResponseTooLarge(_) => false,
+            BackendError::Interrupted(_, _) | BackendError::Timeout(_) => true,
+            #[cfg(feature = "backend-registry")]
+            BackendError::Registry(e) => match e {
+                registry::RegistryError::Request(e) => e.is_retryable(),
+                registry::RegistryError::Url(_, _) | registry::RegistryError::Scheme(_) => false,
+                _ => true,
+            },
+            #[cfg(feature = "backend-localfs")]
+            BackendError::LocalFs(e) => matches!(e, localfs::LocalFsError::ReadBlob(_)),
+            #[cfg(any(feature = "backend-oss", feature = "backend-s3"))]
+            BackendError::ObjectStorage(e) => match e {
+                object_storage::ObjectStorageError::Request(e) => e.is_retryable(),
+                object_storage::ObjectStorageError::Auth(_)
+                | object_storage::ObjectStorageError::ConstructHeader(_) => false,
+                _ => true,
+            },
+            #[cfg(feature = "backend-localdisk")]
+            BackendError::LocalDisk(e) => matches!(e, localdisk::LocalDiskError::ReadBlob(_)),
+            #[cfg(feature = "backend-http-proxy")]
+            BackendError::HttpProxy(e) => match e {
+                http_proxy::HttpProxyError::RemoteRequest(e) => e.is_retryable(),
+                http_proxy::HttpProxyError::LocalRequest(_)
+                | http_proxy::HttpProxyError::ReadResponseBody(_)
+                | http_proxy::HttpProxyError::Transport(_) => true,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Policy to delay retries of failed backend requests.
+///
+/// The delay gets doubled for each retry, and a random jitter of up to half the delay is added
+/// to avoid retries from many clients hitting the server at the same time.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Delay before the first retry.
+    pub delay: Duration,
+    /// Maximum delay before a retry, zero means no limit.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            delay: Duration::from_millis(500),
+            max_delay: Duration::ZERO,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Get the delay before the `attempt`-th retry, counting from 0.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let mut delay = self
+            .delay
+            .checked_mul(1u32 << attempt.min(16))
+            .unwrap_or(Duration::MAX);
+        if !self.max_delay.is_zero() {
+            delay = delay.min(self.max_delay);
+        }
+        let millis = delay.as_millis() as u64 / 2;
+        if millis > 0 {
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.subsec_nanos() as u64)
+                .unwrap_or_default();
+            delay = delay.saturating_add(Duration::from_millis(seed % (millis + 1)));
+        }
+        delay
+    }
+}
+
 /// Specialized `Result` for storage backends.
 pub type BackendResult<T> = std::result::Result<T, BackendError>;
 
+/// Future of an asynchronous read from storage backends, resolving to the data read.
+#[cfg(feature = "backend-async")]
+pub type BackendReadFuture = Pin<Box<dyn Future<Output = BackendResult<Vec<u8>>> + Send>>;
+
+bitflags! {
+    /// Capabilities of storage backends, for the cache layer to choose how to access blobs.
+    pub struct BackendCaps: u32 {
+        /// Ranged reads only transfer the requested data, so chunks may be partially fetched.
+        const SUPPORTS_RANGE = 0x0000_0001;
+        /// Several ranges separated by holes may be fetched by one request.
+        const SUPPORTS_MULTIPART = 0x0000_0002;
+        /// `BlobReader::read_async()` doesn't occupy a thread while waiting for backend IO.
+        const SUPPORTS_ASYNC = 0x0000_0004;
+        /// The backend prefetches data by itself, which may be stopped by `stop_prefetch_data()`.
+        const SUPPORTS_PREFETCH = 0x0000_0008;
+    }
+}
+
 /// Trait to read data from a on storage backend.
 pub trait BlobReader: Send + Sync {
     /// Get size of the blob file.
@@ -105,8 +257,23 @@ pub trait BlobReader: Send + Sync {
     /// Try to read data of range [offset, offset + buf.len()) from the blob file, and returns:
     /// - bytes of data read, which may be smaller than buf.len()
     /// - error code if error happens
+    ///
+    /// If the transfer gets interrupted after receiving part of the data,
+    /// `BackendError::Interrupted` should be returned with the number of bytes received, so the
+    /// caller may resume from there.
     fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize>;
 
+    /// Read data of a chunk stored at `url` instead of in the blob file.
+    ///
+    /// Data of range [0, buf.len()) is read from the resource at `url`, for chunks sourced from
+    /// other origins. Storage backends not supporting it just return `BackendError::Unsupported`.
+    fn read_url(&self, url: &str, _buf: &mut [u8]) -> BackendResult<usize> {
+        Err(BackendError::Unsupported(format!(
+            "reading chunk data from {} is not supported",
+            url
+        )))
+    }
+
     /// Read a range of data from the blob file into the provided buffer.
     ///
     /// Read data of range [offset, offset + buf.len()) from the blob file, and returns:
@@ -114,27 +281,55 @@ pub trait BlobReader: Send + Sync {
     /// - error code if error happens
     ///
     /// It will try `BlobBackend::retry_limit()` times at most and return the first successfully
-    /// read data.
+    /// read data, delaying retries according to `BlobReader::retry_policy()`. Errors which can't
+    /// be fixed by retrying, such as a missing blob, are returned immediately. If the transfer
+    /// gets interrupted after receiving part of the data, it resumes from the first byte not
+    /// received yet instead of restarting the whole range. Each resumption counts against the
+    /// retry limit, so a connection dropping over and over can't stall the read forever.
     fn read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
         let mut retry_count = self.retry_limit();
         let begin_time = self.metrics().begin();
 
-        let mut delayer = Delayer::new(DelayType::BackOff, Duration::from_millis(500));
+        let policy = self.retry_policy();
+        let mut attempt = 0;
+        let mut received = 0usize;
 
         loop {
-            match self.try_read(buf, offset) {
+            match self.try_read(&mut buf[received..], offset + received as u64) {
                 Ok(size) => {
                     self.metrics().end(&begin_time, buf.len(), false);
-                    return Ok(size);
+                    return Ok(received + size);
+                }
+                Err(BackendError::Interrupted(size, err)) if retry_count > 0 => {
+                    received += size;
+                    if received >= buf.len() {
+                        self.metrics().end(&begin_time, buf.len(), false);
+                        return Ok(buf.len());
+                    }
+                    warn!(
+                        "Read from backend interrupted: {:?}, resume from offset 0x{:x}, retry count {}",
+                        err,
+                        offset + received as u64,
+                        retry_count
+                    );
+                    retry_count -= 1;
                 }
                 Err(err) => {
-                    if retry_count > 0 {
+                    // Report all data received so far when giving up an interrupted transfer.
+                    let err = match err {
+                        BackendError::Interrupted(size, e) => {
+                            BackendError::Interrupted(received + size, e)
+                        }
+                        e => e,
+                    };
+                    if retry_count > 0 && err.is_retryable() {
                         warn!(
                             "Read from backend failed: {:?}, retry count {}",
                             err, retry_count
                         );
                         retry_count -= 1;
-                        delayer.delay();
+                        thread::sleep(policy.delay(attempt));
+                        attempt += 1;
                     } else {
                         self.metrics().end(&begin_time, buf.len(), true);
                         ERROR_HOLDER
@@ -203,6 +398,115 @@ pub trait BlobReader: Send + Sync {
     fn retry_limit(&self) -> u8 {
         0
     }
+
+    /// Get the policy to delay retries of failed requests.
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy::default()
+    }
+
+    /// Get maximum size of holes between data ranges which may be fetched by one ranged read.
+    ///
+    /// Backends where a round trip costs more than transferring some surplus data may fetch
+    /// ranges close to each other by one request, and the caller discards data in the holes.
+    /// Zero means only adjacent ranges may be fetched together.
+    fn max_coalesce_gap(&self) -> u64 {
+        0
+    }
+
+    /// Get capabilities of the storage backend.
+    ///
+    /// Optimizations depending on them are disabled by default, so backends should advertise
+    /// what they support.
+    fn capabilities(&self) -> BackendCaps {
+        BackendCaps::empty()
+    }
+
+    /// Cancel data prefetching in progress on the storage backend.
+    ///
+    /// It should be idempotent, and the reader should still serve read requests afterwards.
+    fn stop_prefetch_data(&self) -> BackendResult<()> {
+        Ok(())
+    }
+
+    /// Try to read a range of data from the blob file asynchronously.
+    ///
+    /// It's the asynchronous version of `try_read()`, which reads data of range
+    /// [offset, offset + size) without retry and returns the data read. Storage backends
+    /// advertising `BackendCaps::SUPPORTS_ASYNC` must implement it without blocking the thread
+    /// while waiting for backend IO.
+    #[cfg(feature = "backend-async")]
+    fn try_read_async(self: Arc<Self>, _size: usize, _offset: u64) -> BackendReadFuture
+    where
+        Self: 'static,
+    {
+        Box::pin(async {
+            Err(BackendError::Unsupported(
+                "asynchronous read is not supported".to_string(),
+            ))
+        })
+    }
+
+    /// Read a range of data from the blob file asynchronously.
+    ///
+    /// Read data of range [offset, offset + size) from the blob file with the same retry policy
+    /// as `read()`, and the returned data may be smaller than `size`. It must be awaited within a
+    /// tokio runtime. If the storage backend advertises `BackendCaps::SUPPORTS_ASYNC`, requests
+    /// are issued by `try_read_async()` and interrupted transfers are restarted instead of being
+    /// resumed. Otherwise `read()` is run on the blocking thread pool of the runtime.
+    #[cfg(feature = "backend-async")]
+    fn read_async(self: Arc<Self>, size: usize, offset: u64) -> BackendReadFuture
+    where
+        Self: 'static,
+    {
+        if !self.capabilities().contains(BackendCaps::SUPPORTS_ASYNC) {
+            return Box::pin(async move {
+                tokio::task::spawn_blocking(move || {
+                    let mut buf = alloc_buf(size);
+                    let nr_read = self.read(&mut buf, offset)?;
+                    buf.truncate(nr_read);
+                    Ok(buf)
+                })
+                .await
+                .map_err(|e| {
+                    BackendError::Unsupported(format!("asynchronous read failed, {}", e))
+                })?
+            });
+        }
+
+        Box::pin(async move {
+            let mut retry_count = self.retry_limit();
+            let begin_time = self.metrics().begin();
+            let policy = self.retry_policy();
+            let mut attempt = 0;
+
+            loop {
+                match self.clone().try_read_async(size, offset).await {
+                    Ok(data) => {
+                        self.metrics().end(&begin_time, size, false);
+                        return Ok(data);
+                    }
+                    Err(err) if retry_count > 0 && err.is_retryable() => {
+                        warn!(
+                            "Asynchronous read from backend failed: {:?}, retry count {}",
+                            err, retry_count
+                        );
+                        retry_count -= 1;
+                        tokio::time::sleep(policy.delay(attempt)).await;
+                        attempt += 1;
+                    }
+                    Err(err) => {
+                        self.metrics().end(&begin_time, size, true);
+                        ERROR_HOLDER
+                            .lock()
+                            .unwrap()
+                            .push(&format!("{:?}", err))
+                            .unwrap_or_else(|_| error!("Failed when try to hold error"));
+                        return Err(err);
+                    }
+                }
+            }
+        })
+    }
 }
 
 /// Trait to access blob files on backend storages, such as OSS, registry, local fs etc.
@@ -274,3 +578,382 @@ impl Read for BlobBufReader {
         Ok(sz)
     }
 }
+
+/// A `BlobReader` wrapper to serve reads of the blob metadata sidecar from memory.
+///
+/// Blob metadata, such as the chunk information array, chunk digest array and ToC, is stored in
+/// range [`start`, `start` + `size`) of blobs with separate metadata. Instead of fetching those
+/// pieces one by one, the whole range is fetched by a single backend request on first access and
+/// kept resident, all later reads falling into the range are served from memory. Reads out of the
+/// range are forwarded to the backend.
+pub struct BlobSidecarReader {
+    start: u64,
+    size: u64,
+    data: Mutex<Option<Arc<Vec<u8>>>>,
+    reader: Arc<dyn BlobReader>,
+}
+
+impl BlobSidecarReader {
+    /// Create a new instance of `BlobSidecarReader` for metadata of `size` bytes at `start`.
+    pub fn new(reader: Arc<dyn BlobReader>, start: u64, size: u64) -> Self {
+        Self {
+            start,
+            size,
+            data: Mutex::new(None),
+            reader,
+        }
+    }
+
+    fn get_sidecar(&self) -> BackendResult<Arc<Vec<u8>>> {
+        let mut guard = self.data.lock().unwrap();
+        if let Some(data) = guard.as_ref() {
+            return Ok(data.clone());
+        }
+
+        let blob_size = self.reader.blob_size()?;
+        let size = blob_size.checked_sub(self.start).ok_or_else(|| {
+            BackendError::Unsupported(format!(
+                "blob metadata offset 0x{:x} is bigger than blob size 0x{:x}",
+                self.start, blob_size
+            ))
+        })?;
+        let mut buf = alloc_buf(std::cmp::min(size, self.size) as usize);
+        let sz = self.reader.read_all(&mut buf, self.start)?;
+        buf.truncate(sz);
+        let data = Arc::new(buf);
+        *guard = Some(data.clone());
+
+        Ok(data)
+    }
+}
+
+impl BlobReader for BlobSidecarReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.reader.blob_size()
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        if offset < self.start || offset - self.start >= self.size {
+            return self.reader.try_read(buf, offset);
+        }
+
+        let data = self.get_sidecar()?;
+        let pos = offset - self.start;
+        if pos >= data.len() as u64 {
+            return Ok(0);
+        }
+        let pos = pos as usize;
+        let sz = std::cmp::min(buf.len(), data.len() - pos);
+        buf[..sz].copy_from_slice(&data[pos..pos + sz]);
+
+        Ok(sz)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.reader.metrics()
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.reader.retry_limit()
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.reader.retry_policy()
+    }
+
+    fn max_coalesce_gap(&self) -> u64 {
+        self.reader.max_coalesce_gap()
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        // Asynchronous reads are served by `read()` on the blocking thread pool.
+        self.reader.capabilities() & !BackendCaps::SUPPORTS_ASYNC
+    }
+
+    fn stop_prefetch_data(&self) -> BackendResult<()> {
+        self.reader.stop_prefetch_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountingReader {
+        data: Vec<u8>,
+        count: AtomicUsize,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for CountingReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.data.len() as u64)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            let offset = offset as usize;
+            let sz = std::cmp::min(buf.len(), self.data.len().saturating_sub(offset));
+            buf[..sz].copy_from_slice(&self.data[offset..offset + sz]);
+            Ok(sz)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    #[cfg(feature = "backend-async")]
+    #[test]
+    fn test_read_async() {
+        struct SlowReader {
+            metrics: Arc<BackendMetrics>,
+        }
+
+        impl BlobReader for SlowReader {
+            fn blob_size(&self) -> BackendResult<u64> {
+                Ok(0x100000)
+            }
+
+            fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+                thread::sleep(Duration::from_millis(200));
+                for (idx, v) in buf.iter_mut().enumerate() {
+                    *v = ((offset + idx as u64) % 251) as u8;
+                }
+                Ok(buf.len())
+            }
+
+            fn metrics(&self) -> &BackendMetrics {
+                &self.metrics
+            }
+        }
+
+        let reader: Arc<dyn BlobReader> = Arc::new(SlowReader {
+            metrics: BackendMetrics::new("async", "mock"),
+        });
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let start = std::time::Instant::now();
+        let results = rt.block_on(async {
+            let mut handles = Vec::new();
+            for idx in 0..64u64 {
+                let reader = reader.clone();
+                handles.push(tokio::spawn(async move {
+                    (idx, reader.read_async(0x100, idx * 0x1000).await)
+                }));
+            }
+            let mut results = Vec::new();
+            for handle in handles {
+                results.push(handle.await.unwrap());
+            }
+            results
+        });
+
+        // Reads are served concurrently even with a single runtime thread.
+        assert!(start.elapsed() < Duration::from_millis(64 * 200 / 4));
+        assert_eq!(results.len(), 64);
+        for (idx, res) in results {
+            let data = res.unwrap();
+            assert_eq!(data.len(), 0x100);
+            for (pos, v) in data.iter().enumerate() {
+                assert_eq!(*v, ((idx * 0x1000 + pos as u64) % 251) as u8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_blob_sidecar_reader() {
+        let inner = Arc::new(CountingReader {
+            data: (0..0x3000u32).map(|v| v as u8).collect(),
+            count: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("sidecar", "mock"),
+        });
+        let reader = BlobSidecarReader::new(inner.clone(), 0x1000, 0x4000);
+
+        // Chunk information array, chunk digest array and ToC are all fetched by one request.
+        let mut buf = vec![0u8; 0x100];
+        assert_eq!(reader.read_all(&mut buf, 0x1000).unwrap(), 0x100);
+        assert_eq!(buf[1], 1);
+        assert_eq!(reader.read_all(&mut buf, 0x2080).unwrap(), 0x100);
+        assert_eq!(buf[0], 0x80);
+        assert_eq!(reader.read_all(&mut buf, 0x2f80).unwrap(), 0x80);
+        assert_eq!(buf[0x7f], 0xff);
+        assert_eq!(inner.count.load(Ordering::Relaxed), 1);
+
+        // Data out of the sidecar range is forwarded to the backend.
+        assert_eq!(reader.read(&mut buf, 0x10).unwrap(), 0x100);
+        assert_eq!(buf[0], 0x10);
+        assert_eq!(inner.count.load(Ordering::Relaxed), 2);
+
+        // Only the metadata range is kept resident, data after it is forwarded to the backend.
+        let reader = BlobSidecarReader::new(inner.clone(), 0x1000, 0x1000);
+        assert_eq!(reader.read_all(&mut buf, 0x1f80).unwrap(), 0x100);
+        assert_eq!(buf[0x7f], 0xff);
+        assert_eq!(buf[0x80], 0x00);
+        assert_eq!(reader.get_sidecar().unwrap().len(), 0x1000);
+        assert_eq!(inner.count.load(Ordering::Relaxed), 4);
+        assert_eq!(reader.read_all(&mut buf, 0x1000).unwrap(), 0x100);
+        assert_eq!(inner.count.load(Ordering::Relaxed), 4);
+    }
+
+    struct DroppingReader {
+        data: Vec<u8>,
+        drop_after: usize,
+        drops: AtomicUsize,
+        offsets: Mutex<Vec<u64>>,
+        retry_limit: u8,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for DroppingReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.data.len() as u64)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            self.offsets.lock().unwrap().push(offset);
+            let offset = offset as usize;
+            let sz = std::cmp::min(buf.len(), self.data.len().saturating_sub(offset));
+            if sz > self.drop_after && self.drops.load(Ordering::Relaxed) > 0 {
+                // Simulate a connection dropped in the middle of the transfer.
+                self.drops.fetch_sub(1, Ordering::Relaxed);
+                let cnt = self.drop_after;
+                buf[..cnt].copy_from_slice(&self.data[offset..offset + cnt]);
+                return Err(BackendError::interrupted(
+                    cnt,
+                    BackendError::Unsupported("connection reset".to_string()),
+                ));
+            }
+            buf[..sz].copy_from_slice(&self.data[offset..offset + sz]);
+            Ok(sz)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn retry_limit(&self) -> u8 {
+            self.retry_limit
+        }
+    }
+
+    #[test]
+    fn test_blob_reader_resume_interrupted_read() {
+        let reader = DroppingReader {
+            data: (0..0x4000u32).map(|v| (v % 251) as u8).collect(),
+            drop_after: 0x1000,
+            drops: AtomicUsize::new(3),
+            offsets: Mutex::new(Vec::new()),
+            retry_limit: 3,
+            metrics: BackendMetrics::new("resume", "mock"),
+        };
+
+        // Interrupted transfers resume from the first byte not received yet.
+        let mut buf = vec![0u8; 0x3800];
+        assert_eq!(reader.read(&mut buf, 0x400).unwrap(), 0x3800);
+        assert_eq!(buf, reader.data[0x400..0x3c00]);
+        assert_eq!(
+            *reader.offsets.lock().unwrap(),
+            vec![0x400, 0x1400, 0x2400, 0x3400]
+        );
+
+        // Interruptions count against the retry limit, and all data received is reported.
+        let reader = DroppingReader {
+            drop_after: 0x800,
+            drops: AtomicUsize::new(8),
+            offsets: Mutex::new(Vec::new()),
+            ..reader
+        };
+        match reader.read(&mut buf, 0) {
+            Err(BackendError::Interrupted(size, _)) => assert_eq!(size, 0x2000),
+            ret => panic!("unexpected result {:?}", ret),
+        }
+        assert_eq!(reader.offsets.lock().unwrap().len(), 4);
+
+        // Interruption without receiving any data is a plain error.
+        let err = BackendError::interrupted(0, BackendError::Unsupported("reset".to_string()));
+        assert!(matches!(err, BackendError::Unsupported(_)));
+    }
+
+    #[cfg(feature = "backend-registry")]
+    struct FlakyReader {
+        failures: AtomicUsize,
+        status: reqwest::StatusCode,
+        attempts: AtomicUsize,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    #[cfg(feature = "backend-registry")]
+    impl BlobReader for FlakyReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(0x1000)
+        }
+
+        fn try_read(&self, buf: &mut [u8], _offset: u64) -> BackendResult<usize> {
+            self.attempts.fetch_add(1, Ordering::Relaxed);
+            if self.failures.load(Ordering::Relaxed) > 0 {
+                self.failures.fetch_sub(1, Ordering::Relaxed);
+                return Err(BackendError::Registry(registry::RegistryError::Request(
+                    connection::ConnectionError::Status(self.status, "mock".to_string()),
+                )));
+            }
+            buf.fill(0x5a);
+            Ok(buf.len())
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn retry_limit(&self) -> u8 {
+            3
+        }
+
+        fn retry_policy(&self) -> RetryPolicy {
+            RetryPolicy {
+                delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(2),
+            }
+        }
+    }
+
+    #[cfg(feature = "backend-registry")]
+    #[test]
+    fn test_blob_reader_retry_transient_errors() {
+        // Transient errors are retried until the read succeeds.
+        let reader = FlakyReader {
+            failures: AtomicUsize::new(2),
+            status: reqwest::StatusCode::SERVICE_UNAVAILABLE,
+            attempts: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("retry", "mock"),
+        };
+        let mut buf = vec![0u8; 0x100];
+        assert_eq!(reader.read(&mut buf, 0).unwrap(), 0x100);
+        assert!(buf.iter().all(|v| *v == 0x5a));
+        assert_eq!(reader.attempts.load(Ordering::Relaxed), 3);
+
+        // Permanent errors are returned immediately.
+        let reader = FlakyReader {
+            failures: AtomicUsize::new(2),
+            status: reqwest::StatusCode::NOT_FOUND,
+            attempts: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("retry", "mock"),
+        };
+        assert!(reader.read(&mut buf, 0).is_err());
+        assert_eq!(reader.attempts.load(Ordering::Relaxed), 1);
+
+        // The delay grows exponentially with jitter, and is capped by the maximum delay.
+        let policy = RetryPolicy {
+            delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+        };
+        assert!(policy.delay(0) >= Duration::from_millis(100));
+        assert!(policy.delay(0) <= Duration::from_millis(150));
+        assert!(policy.delay(2) >= Duration::from_millis(400));
+        assert!(policy.delay(2) <= Duration::from_millis(600));
+        assert!(policy.delay(10) <= Duration::from_millis(1500));
+        assert!(policy.delay(100) >= Duration::from_millis(1000));
+    }
+}
@@ -16,8 +16,14 @@ use reqwest::Method;
 
 use nydus_utils::metrics::BackendMetrics;
 
-use super::connection::{Connection, ConnectionError};
-use super::{BackendError, BackendResult, BlobBackend, BlobReader};
+#[cfg(feature = "backend-async")]
+use super::connection::read_response_async;
+use super::connection::{Connection, ConnectionError, RangeFallback};
+#[cfg(feature = "backend-async")]
+use super::BackendReadFuture;
+use super::{BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader, RetryPolicy};
+#[cfg(feature = "backend-async")]
+use crate::utils::alloc_buf;
 
 /// Error codes related to object storage backend.
 #[derive(Debug)]
@@ -63,6 +69,8 @@ pub trait ObjectStorageState: Send + Sync + Debug {
     ) -> Result<()>;
 
     fn retry_limit(&self) -> u8;
+
+    fn retry_policy(&self) -> RetryPolicy;
 }
 
 struct ObjectStorageReader<T>
@@ -73,6 +81,7 @@ where
     connection: Arc<Connection>,
     state: Arc<T>,
     metrics: Arc<BackendMetrics>,
+    range_fallback: RangeFallback,
 }
 
 impl<T> BlobReader for ObjectStorageReader<T>
@@ -107,11 +116,78 @@ where
             })?)
     }
 
-    fn try_read(&self, mut buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        if let Some(size) = self.range_fallback.read_cached(buf, offset) {
+            return Ok(size);
+        }
+        let (url, mut headers) = self.range_request(offset, buf.len())?;
+
+        // Safe because the the call() is a synchronous operation.
+        let mut resp = self
+            .connection
+            .call::<&[u8]>(Method::GET, url.as_str(), None, None, &mut headers, true)
+            .map_err(ObjectStorageError::Request)?;
+        self.range_fallback
+            .copy_response(&mut resp, buf, offset, self.connection.max_response_size())
+            .map_err(|e| e.into_backend_error(ObjectStorageError::Transport))
+    }
+
+    #[cfg(feature = "backend-async")]
+    fn try_read_async(self: Arc<Self>, size: usize, offset: u64) -> BackendReadFuture
+    where
+        Self: 'static,
+    {
+        Box::pin(async move {
+            let mut buf = alloc_buf(size);
+            if let Some(size) = self.range_fallback.read_cached(&mut buf, offset) {
+                buf.truncate(size);
+                return Ok(buf);
+            }
+            let (url, headers) = self.range_request(offset, size)?;
+
+            let resp = self
+                .connection
+                .call_async(Method::GET, url.as_str(), &headers, true)
+                .await
+                .map_err(ObjectStorageError::Request)?;
+            read_response_async(resp, size, offset, self.connection.max_response_size())
+                .await
+                .map_err(|e| e.into_backend_error(ObjectStorageError::Transport))
+        })
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        &self.metrics
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.state.retry_limit()
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.state.retry_policy()
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        let caps = BackendCaps::SUPPORTS_RANGE | BackendCaps::SUPPORTS_MULTIPART;
+        #[cfg(feature = "backend-async")]
+        if self.connection.supports_async() {
+            return caps | BackendCaps::SUPPORTS_ASYNC;
+        }
+        caps
+    }
+}
+
+impl<T> ObjectStorageReader<T>
+where
+    T: ObjectStorageState,
+{
+    // Build the signed request for data of range [offset, offset + size) of the blob.
+    fn range_request(&self, offset: u64, size: usize) -> BackendResult<(String, HeaderMap)> {
         let query = &[];
         let (resource, url) = self.state.url(&self.blob_id, query);
         let mut headers = HeaderMap::new();
-        let end_at = offset + buf.len() as u64 - 1;
+        let end_at = offset + size as u64 - 1;
         let range = format!("bytes={}-{}", offset, end_at);
 
         headers.insert(
@@ -125,23 +201,7 @@ where
             .sign(Method::GET, &mut headers, resource.as_str(), url.as_str())
             .map_err(ObjectStorageError::Auth)?;
 
-        // Safe because the the call() is a synchronous operation.
-        let mut resp = self
-            .connection
-            .call::<&[u8]>(Method::GET, url.as_str(), None, None, &mut headers, true)
-            .map_err(ObjectStorageError::Request)?;
-        Ok(resp
-            .copy_to(&mut buf)
-            .map_err(ObjectStorageError::Transport)
-            .map(|size| size as usize)?)
-    }
-
-    fn metrics(&self) -> &BackendMetrics {
-        &self.metrics
-    }
-
-    fn retry_limit(&self) -> u8 {
-        self.state.retry_limit()
+        Ok((url, headers))
     }
 }
 
@@ -197,6 +257,7 @@ where
                 state: self.state.clone(),
                 connection: self.connection.clone(),
                 metrics: metrics.clone(),
+                range_fallback: RangeFallback::default(),
             }))
         } else {
             Err(BackendError::Unsupported(
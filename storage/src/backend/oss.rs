@@ -19,6 +19,7 @@ use nydus_utils::metrics::BackendMetrics;
 
 use crate::backend::connection::{Connection, ConnectionConfig};
 use crate::backend::object_storage::{ObjectStorage, ObjectStorageState};
+use crate::backend::RetryPolicy;
 
 const HEADER_DATE: &str = "Date";
 const HEADER_AUTHORIZATION: &str = "Authorization";
@@ -35,6 +36,7 @@ pub struct OssState {
     endpoint: String,
     bucket_name: String,
     retry_limit: u8,
+    retry_policy: RetryPolicy,
 }
 
 impl OssState {
@@ -116,6 +118,10 @@ impl ObjectStorageState for OssState {
     fn retry_limit(&self) -> u8 {
         self.retry_limit
     }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
 }
 
 /// Storage backend to access data stored in OSS.
@@ -135,6 +141,7 @@ impl Oss {
             access_key_secret: oss_config.access_key_secret.clone(),
             bucket_name: oss_config.bucket_name.clone(),
             retry_limit,
+            retry_policy: con_config.retry_policy(),
         });
         let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
 
@@ -163,6 +170,7 @@ mod tests {
             endpoint: "oss".to_string(),
             bucket_name: "images".to_string(),
             retry_limit: 5,
+            retry_policy: RetryPolicy::default(),
         };
 
         assert_eq!(
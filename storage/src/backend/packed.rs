@@ -0,0 +1,357 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to access blobs stored back to back in packed objects.
+//!
+//! Several small blobs may be stored in one backend object to reduce the number of objects.
+//! Reads of those blobs are mapped to the containing object, and a read reaching the end of a
+//! blob also fetches the head of the following blob in the same request. The surplus data is kept
+//! in memory for the following blob, so prefetching adjacent blobs issues one request across the
+//! blob boundary instead of stopping at it.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use nydus_api::PackedConfig;
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::{
+    BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader, RetryPolicy,
+};
+use crate::utils::alloc_buf;
+
+// Number of coalesced ranges of following blobs kept in memory for each packed object.
+const PACKED_CACHED_RANGES: usize = 8;
+
+struct PackedObject {
+    object_id: String,
+    // Offsets of blobs in the object, in ascending order.
+    offsets: Vec<u64>,
+    reader: OnceLock<Arc<dyn BlobReader>>,
+    size: OnceLock<u64>,
+    // Data of following blobs fetched by coalesced reads, in form of (offset in object, data).
+    ranges: Mutex<VecDeque<(u64, Arc<Vec<u8>>)>>,
+}
+
+/// A storage backend mapping blobs to the packed objects containing them.
+pub struct PackedBackend {
+    inner: Arc<dyn BlobBackend + Send + Sync>,
+    coalesce_size: u64,
+    objects: Vec<Arc<PackedObject>>,
+    // Map blob id to (index in `objects`, index in the object).
+    blobs: HashMap<String, (usize, usize)>,
+}
+
+impl PackedBackend {
+    /// Create a new instance of `PackedBackend`.
+    pub fn new(inner: Arc<dyn BlobBackend + Send + Sync>, config: &PackedConfig) -> Self {
+        let mut objects = Vec::with_capacity(config.objects.len());
+        let mut blobs = HashMap::new();
+        for object in config.objects.iter() {
+            let mut packed = object.blobs.iter().collect::<Vec<_>>();
+            packed.sort_by_key(|b| b.offset);
+            for (idx, blob) in packed.iter().enumerate() {
+                blobs.insert(blob.blob_id.clone(), (objects.len(), idx));
+            }
+            objects.push(Arc::new(PackedObject {
+                object_id: object.object_id.clone(),
+                offsets: packed.iter().map(|b| b.offset).collect(),
+                reader: OnceLock::new(),
+                size: OnceLock::new(),
+                ranges: Mutex::new(VecDeque::with_capacity(PACKED_CACHED_RANGES)),
+            }));
+        }
+
+        PackedBackend {
+            inner,
+            coalesce_size: config.coalesce_size as u64,
+            objects,
+            blobs,
+        }
+    }
+}
+
+impl BlobBackend for PackedBackend {
+    fn shutdown(&self) {
+        self.inner.shutdown();
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        let (object_idx, index) = match self.blobs.get(blob_id) {
+            Some(v) => *v,
+            None => return self.inner.get_reader(blob_id),
+        };
+        // Blobs in the same object share the object reader and the coalesced data.
+        let object = self.objects[object_idx].clone();
+        if object.reader.get().is_none() {
+            let reader = self.inner.get_reader(&object.object_id)?;
+            let _ = object.reader.set(reader);
+        }
+
+        Ok(Arc::new(PackedReader {
+            inner: object.reader.get().unwrap().clone(),
+            object,
+            index,
+            coalesce_size: self.coalesce_size,
+        }))
+    }
+}
+
+struct PackedReader {
+    inner: Arc<dyn BlobReader>,
+    object: Arc<PackedObject>,
+    // Index of the blob in the object.
+    index: usize,
+    coalesce_size: u64,
+}
+
+impl PackedReader {
+    fn object_size(&self) -> BackendResult<u64> {
+        if let Some(size) = self.object.size.get() {
+            return Ok(*size);
+        }
+        let size = self.inner.blob_size()?;
+        Ok(*self.object.size.get_or_init(|| size))
+    }
+
+    // Get range [start, end) of the blob with index `index` in the object.
+    fn blob_range(&self, index: usize) -> BackendResult<(u64, u64)> {
+        let start = self.object.offsets[index];
+        let end = match self.object.offsets.get(index + 1) {
+            Some(end) => *end,
+            None => self.object_size()?,
+        };
+        Ok((start, std::cmp::max(start, end)))
+    }
+
+    // Copy data at `offset` of the object from coalesced ranges, returns size of data copied.
+    fn read_cached(&self, buf: &mut [u8], offset: u64) -> usize {
+        let mut ranges = self.object.ranges.lock().unwrap();
+        let idx = match ranges
+            .iter()
+            .position(|(start, data)| *start <= offset && offset < *start + data.len() as u64)
+        {
+            Some(idx) => idx,
+            None => return 0,
+        };
+        let range = ranges.remove(idx).unwrap();
+        let pos = (offset - range.0) as usize;
+        let size = std::cmp::min(buf.len(), range.1.len() - pos);
+        buf[..size].copy_from_slice(&range.1[pos..pos + size]);
+        ranges.push_back(range);
+        size
+    }
+
+    fn cache_range(&self, offset: u64, data: Vec<u8>) {
+        let mut ranges = self.object.ranges.lock().unwrap();
+        if ranges.len() >= PACKED_CACHED_RANGES {
+            ranges.pop_front();
+        }
+        ranges.push_back((offset, Arc::new(data)));
+    }
+}
+
+impl BlobReader for PackedReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        let (start, end) = self.blob_range(self.index)?;
+        Ok(end - start)
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let (start, end) = self.blob_range(self.index)?;
+        let blob_size = end - start;
+        if offset >= blob_size || buf.is_empty() {
+            return Ok(0);
+        }
+        let size = std::cmp::min(buf.len() as u64, blob_size - offset) as usize;
+        let offset = start + offset;
+        let cached = self.read_cached(&mut buf[..size], offset);
+        if cached >= size {
+            return Ok(size);
+        }
+
+        let buf = &mut buf[cached..size];
+        let offset = offset + cached as u64;
+        let next_size = if self.coalesce_size > 0
+            && offset + buf.len() as u64 == end
+            && self.index + 1 < self.object.offsets.len()
+        {
+            let (next_start, next_end) = self.blob_range(self.index + 1)?;
+            std::cmp::min(self.coalesce_size, next_end - next_start)
+        } else {
+            0
+        };
+        if next_size == 0 {
+            return match self.inner.try_read(buf, offset) {
+                Ok(v) => Ok(cached + v),
+                Err(BackendError::Interrupted(v, e)) => {
+                    Err(BackendError::Interrupted(cached + v, e))
+                }
+                Err(e) => Err(e),
+            };
+        }
+
+        // Fetch the head of the following blob by the same request.
+        let mut data = alloc_buf(buf.len() + next_size as usize);
+        let nr_read = match self.inner.try_read(&mut data, offset) {
+            Ok(v) => v,
+            // The received data may not reach the requested range, so just restart it.
+            Err(BackendError::Interrupted(_, e)) => return Err(*e),
+            Err(e) => return Err(e),
+        };
+        data.truncate(nr_read);
+        let size = std::cmp::min(buf.len(), nr_read);
+        buf[..size].copy_from_slice(&data[..size]);
+        if nr_read > size {
+            self.cache_range(end, data.split_off(size));
+        }
+
+        Ok(cached + size)
+    }
+
+    fn read_url(&self, url: &str, buf: &mut [u8]) -> BackendResult<usize> {
+        self.inner.read_url(url, buf)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.inner.retry_limit()
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn max_coalesce_gap(&self) -> u64 {
+        self.inner.max_coalesce_gap()
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        // Asynchronous reads are served by `read()` on the blocking thread pool.
+        self.inner.capabilities() & !BackendCaps::SUPPORTS_ASYNC
+    }
+
+    fn stop_prefetch_data(&self) -> BackendResult<()> {
+        self.inner.stop_prefetch_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nydus_api::{PackedBlobConfig, PackedObjectConfig};
+
+    type Reads = Arc<Mutex<Vec<(String, u64, usize)>>>;
+
+    struct MockBackend {
+        reads: Reads,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    struct MockReader {
+        id: String,
+        reads: Reads,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for MockReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(0x8000)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            self.reads
+                .lock()
+                .unwrap()
+                .push((self.id.clone(), offset, buf.len()));
+            let size = std::cmp::min(buf.len() as u64, 0x8000u64.saturating_sub(offset));
+            for (idx, v) in buf[..size as usize].iter_mut().enumerate() {
+                *v = ((offset + idx as u64) % 251) as u8;
+            }
+            Ok(size as usize)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    impl BlobBackend for MockBackend {
+        fn shutdown(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+            Ok(Arc::new(MockReader {
+                id: blob_id.to_string(),
+                reads: self.reads.clone(),
+                metrics: self.metrics.clone(),
+            }))
+        }
+    }
+
+    #[test]
+    fn test_packed_blobs_coalesced_read() {
+        let reads = Arc::new(Mutex::new(Vec::new()));
+        let blob = |blob_id: &str, offset| PackedBlobConfig {
+            blob_id: blob_id.to_string(),
+            offset,
+        };
+        let config = PackedConfig {
+            coalesce_size: 0x1000,
+            objects: vec![PackedObjectConfig {
+                object_id: "object".to_string(),
+                blobs: vec![blob("b", 0x4000), blob("a", 0), blob("c", 0x6000)],
+            }],
+        };
+        let backend = PackedBackend::new(
+            Arc::new(MockBackend {
+                reads: reads.clone(),
+                metrics: BackendMetrics::new("packed", "localfs"),
+            }),
+            &config,
+        );
+        let a = backend.get_reader("a").unwrap();
+        let b = backend.get_reader("b").unwrap();
+        let c = backend.get_reader("c").unwrap();
+        assert_eq!(a.blob_size().unwrap(), 0x4000);
+        assert_eq!(b.blob_size().unwrap(), 0x2000);
+        assert_eq!(c.blob_size().unwrap(), 0x2000);
+
+        let read = |reader: &Arc<dyn BlobReader>, base: u64, offset: u64, size: usize| {
+            let mut buf = vec![0u8; size];
+            assert_eq!(reader.read(&mut buf, offset).unwrap(), size);
+            for (idx, v) in buf.iter().enumerate() {
+                assert_eq!(*v, ((base + offset + idx as u64) % 251) as u8);
+            }
+            reads.lock().unwrap().len()
+        };
+
+        // Reading the tail of blob "a" fetches the head of blob "b" by one request.
+        assert_eq!(read(&a, 0, 0x2000, 0x2000), 1);
+        assert_eq!(read(&b, 0x4000, 0, 0x1000), 1);
+        // Only the missing part is fetched if the coalesced data covers the head of the read.
+        assert_eq!(read(&b, 0x4000, 0x800, 0x1000), 2);
+        // Reads not reaching the end of a blob aren't extended.
+        assert_eq!(read(&a, 0, 0, 0x1000), 3);
+        // Blobs not packed are read from the backend directly.
+        let d = backend.get_reader("d").unwrap();
+        assert_eq!(read(&d, 0, 0, 0x1000), 4);
+
+        let reads = reads.lock().unwrap();
+        assert_eq!(reads[0], ("object".to_string(), 0x2000, 0x3000));
+        assert_eq!(reads[1], ("object".to_string(), 0x5000, 0x800));
+        assert_eq!(reads[2], ("object".to_string(), 0, 0x1000));
+        assert_eq!(reads[3], ("d".to_string(), 0, 0x1000));
+    }
+}
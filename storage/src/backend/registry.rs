@@ -23,9 +23,18 @@ use nydus_api::RegistryConfig;
 use nydus_utils::metrics::BackendMetrics;
 
 use crate::backend::connection::{
-    is_success_status, respond, Connection, ConnectionConfig, ConnectionError, ReqBody,
+    is_success_status, respond, Connection, ConnectionConfig, ConnectionError, RangeFallback,
+    ReqBody,
 };
-use crate::backend::{BackendError, BackendResult, BlobBackend, BlobReader};
+#[cfg(feature = "backend-async")]
+use crate::backend::connection::{read_response_async, respond_async};
+#[cfg(feature = "backend-async")]
+use crate::backend::BackendReadFuture;
+use crate::backend::{
+    BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader, RetryPolicy,
+};
+#[cfg(feature = "backend-async")]
+use crate::utils::alloc_buf;
 
 const REGISTRY_CLIENT_ID: &str = "nydus-registry-client";
 const HEADER_AUTHORIZATION: &str = "Authorization";
@@ -182,6 +191,10 @@ struct RegistryState {
     password: String,
     // Retry limit for read operation
     retry_limit: u8,
+    // Policy to delay retries of failed read operation
+    retry_policy: RetryPolicy,
+    // Maximum size of holes between chunks fetched by one ranged read
+    range_coalesce_gap: u64,
     // Scheme specified for blob server
     blob_url_scheme: String,
     // Replace registry redirected url host with the given host
@@ -456,6 +469,11 @@ impl First {
         self.inner.load().call_once(f)
     }
 
+    #[cfg(feature = "backend-async")]
+    fn is_completed(&self) -> bool {
+        self.inner.load().is_completed()
+    }
+
     fn renew(&self) {
         self.inner.store(Arc::new(Once::new()));
     }
@@ -506,6 +524,7 @@ struct RegistryReader {
     state: Arc<RegistryState>,
     metrics: Arc<BackendMetrics>,
     first: First,
+    range_fallback: RangeFallback,
 }
 
 impl RegistryReader {
@@ -628,12 +647,7 @@ impl RegistryReader {
     /// Request:  GET https://raw-blob-storage-host.com/signature=x
     /// Response: status: 200 Ok / 403 Forbidden
     /// If responding 403, we need to repeat step one
-    fn _try_read(
-        &self,
-        mut buf: &mut [u8],
-        offset: u64,
-        allow_retry: bool,
-    ) -> RegistryResult<usize> {
+    fn _try_read(&self, buf: &mut [u8], offset: u64, allow_retry: bool) -> BackendResult<usize> {
         let url = format!("/blobs/sha256:{}", self.blob_id);
         let url = self
             .state
@@ -696,10 +710,10 @@ impl RegistryReader {
                     if e.to_string().contains("self signed certificate") {
                         warn!("try to enable \"skip_verify: true\" option");
                     }
-                    return Err(RegistryError::Request(ConnectionError::Common(e)));
+                    return Err(RegistryError::Request(ConnectionError::Common(e)).into());
                 }
                 Err(e) => {
-                    return Err(e);
+                    return Err(e.into());
                 }
             };
             let status = resp.status();
@@ -707,31 +721,7 @@ impl RegistryReader {
             // Handle redirect request and cache redirect url
             if REDIRECTED_STATUS_CODE.contains(&status) {
                 if let Some(location) = resp.headers().get("location") {
-                    let location = location.to_str().unwrap();
-                    let mut location = Url::parse(location)
-                        .map_err(|e| RegistryError::Url(location.to_string(), e))?;
-                    // Note: Some P2P proxy server supports only scheme specified origin blob server,
-                    // so we need change scheme to `blob_url_scheme` here
-                    if !self.state.blob_url_scheme.is_empty() {
-                        location
-                            .set_scheme(&self.state.blob_url_scheme)
-                            .map_err(|_| {
-                                RegistryError::Scheme(self.state.blob_url_scheme.clone())
-                            })?;
-                    }
-                    if !self.state.blob_redirected_host.is_empty() {
-                        location
-                            .set_host(Some(self.state.blob_redirected_host.as_str()))
-                            .map_err(|e| {
-                                error!(
-                                    "Failed to set blob redirected host to {}: {:?}",
-                                    self.state.blob_redirected_host.as_str(),
-                                    e
-                                );
-                                RegistryError::Url(location.to_string(), e)
-                            })?;
-                        debug!("New redirected location {:?}", location.host_str());
-                    }
+                    let location = self.redirect_location(location)?;
                     let resp_ret = self
                         .connection
                         .call::<&[u8]>(
@@ -751,7 +741,7 @@ impl RegistryReader {
                                 .set(self.blob_id.clone(), location.as_str().to_string())
                         }
                         Err(err) => {
-                            return Err(err);
+                            return Err(err.into());
                         }
                     }
                 };
@@ -760,9 +750,130 @@ impl RegistryReader {
             }
         }
 
-        resp.copy_to(&mut buf)
-            .map_err(RegistryError::Transport)
-            .map(|size| size as usize)
+        self.range_fallback
+            .copy_response(&mut resp, buf, offset, self.connection.max_response_size())
+            .map_err(|e| e.into_backend_error(RegistryError::Transport))
+    }
+
+    // Get the location redirected to, with scheme and host replaced as configured.
+    fn redirect_location(&self, location: &HeaderValue) -> RegistryResult<Url> {
+        let location = location.to_str().unwrap();
+        let mut location =
+            Url::parse(location).map_err(|e| RegistryError::Url(location.to_string(), e))?;
+        // Note: Some P2P proxy server supports only scheme specified origin blob server,
+        // so we need change scheme to `blob_url_scheme` here
+        if !self.state.blob_url_scheme.is_empty() {
+            location
+                .set_scheme(&self.state.blob_url_scheme)
+                .map_err(|_| RegistryError::Scheme(self.state.blob_url_scheme.clone()))?;
+        }
+        if !self.state.blob_redirected_host.is_empty() {
+            location
+                .set_host(Some(self.state.blob_redirected_host.as_str()))
+                .map_err(|e| {
+                    error!(
+                        "Failed to set blob redirected host to {}: {:?}",
+                        self.state.blob_redirected_host.as_str(),
+                        e
+                    );
+                    RegistryError::Url(location.to_string(), e)
+                })?;
+            debug!("New redirected location {:?}", location.host_str());
+        }
+
+        Ok(location)
+    }
+
+    /// Read data from registry server asynchronously.
+    ///
+    /// Requests are sent with the cached redirect url or authorization header. Authentication
+    /// isn't supported here, so the first request of the backend and requests rejected for
+    /// authorization fall back to `try_read()` on the blocking thread pool, which negotiates and
+    /// caches the authorization header for following requests.
+    #[cfg(feature = "backend-async")]
+    async fn _try_read_async(self: Arc<Self>, size: usize, offset: u64) -> BackendResult<Vec<u8>> {
+        let mut headers = HeaderMap::new();
+        let end_at = offset + size as u64 - 1;
+        let range = format!("bytes={}-{}", offset, end_at);
+        headers.insert("Range", range.parse().unwrap());
+
+        let resp = if let Some(cached_redirect) = self.state.cached_redirect.get(&self.blob_id) {
+            let resp = self
+                .connection
+                .call_async(Method::GET, cached_redirect.as_str(), &headers, false)
+                .await
+                .map_err(RegistryError::Request)?;
+            if [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN].contains(&resp.status()) {
+                warn!(
+                    "The redirected link has expired: {}, will retry read",
+                    cached_redirect.as_str()
+                );
+                self.state.cached_redirect.remove(&self.blob_id);
+                return self.read_blocking(size, offset).await;
+            }
+            respond_async(resp, true)
+                .await
+                .map_err(RegistryError::Request)?
+        } else if !self.first.is_completed() {
+            return self.read_blocking(size, offset).await;
+        } else {
+            let url = format!("/blobs/sha256:{}", self.blob_id);
+            let url = self
+                .state
+                .url(url.as_str(), &[])
+                .map_err(|e| RegistryError::Url(url, e))?;
+            let mut auth_headers = headers.clone();
+            let cached_auth = self.state.cached_auth.get();
+            if !cached_auth.is_empty() {
+                auth_headers.insert(
+                    HEADER_AUTHORIZATION,
+                    HeaderValue::from_str(cached_auth.as_str()).unwrap(),
+                );
+            }
+            let resp = self
+                .connection
+                .call_async(Method::GET, url.as_str(), &auth_headers, false)
+                .await
+                .map_err(RegistryError::Request)?;
+            let status = resp.status();
+            if status == StatusCode::UNAUTHORIZED {
+                return self.read_blocking(size, offset).await;
+            }
+
+            match resp.headers().get("location") {
+                Some(location) if REDIRECTED_STATUS_CODE.contains(&status) => {
+                    let location = self.redirect_location(location)?;
+                    let resp = self
+                        .connection
+                        .call_async(Method::GET, location.as_str(), &headers, true)
+                        .await
+                        .map_err(RegistryError::Request)?;
+                    self.state
+                        .cached_redirect
+                        .set(self.blob_id.clone(), location.as_str().to_string());
+                    resp
+                }
+                _ => respond_async(resp, true)
+                    .await
+                    .map_err(RegistryError::Request)?,
+            }
+        };
+
+        read_response_async(resp, size, offset, self.connection.max_response_size())
+            .await
+            .map_err(|e| e.into_backend_error(RegistryError::Transport))
+    }
+
+    #[cfg(feature = "backend-async")]
+    async fn read_blocking(self: Arc<Self>, size: usize, offset: u64) -> BackendResult<Vec<u8>> {
+        tokio::task::spawn_blocking(move || {
+            let mut buf = alloc_buf(size);
+            let nr_read = self.try_read(&mut buf, offset)?;
+            buf.truncate(nr_read);
+            Ok(buf)
+        })
+        .await
+        .map_err(|e| BackendError::Unsupported(format!("asynchronous read failed, {}", e)))?
     }
 }
 
@@ -814,9 +925,25 @@ impl BlobReader for RegistryReader {
     }
 
     fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
-        self.first.handle_force(&mut || -> BackendResult<usize> {
-            self._try_read(buf, offset, true)
-                .map_err(BackendError::Registry)
+        if let Some(size) = self.range_fallback.read_cached(buf, offset) {
+            return Ok(size);
+        }
+        self.first
+            .handle_force(&mut || -> BackendResult<usize> { self._try_read(buf, offset, true) })
+    }
+
+    #[cfg(feature = "backend-async")]
+    fn try_read_async(self: Arc<Self>, size: usize, offset: u64) -> BackendReadFuture
+    where
+        Self: 'static,
+    {
+        Box::pin(async move {
+            let mut buf = alloc_buf(size);
+            if let Some(size) = self.range_fallback.read_cached(&mut buf, offset) {
+                buf.truncate(size);
+                return Ok(buf);
+            }
+            self._try_read_async(size, offset).await
         })
     }
 
@@ -827,6 +954,23 @@ impl BlobReader for RegistryReader {
     fn retry_limit(&self) -> u8 {
         self.state.retry_limit
     }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.state.retry_policy
+    }
+
+    fn max_coalesce_gap(&self) -> u64 {
+        self.state.range_coalesce_gap
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        let caps = BackendCaps::SUPPORTS_RANGE | BackendCaps::SUPPORTS_MULTIPART;
+        #[cfg(feature = "backend-async")]
+        if self.connection.supports_async() {
+            return caps | BackendCaps::SUPPORTS_ASYNC;
+        }
+        caps
+    }
 }
 
 /// Storage backend based on image registry.
@@ -877,6 +1021,8 @@ impl Registry {
             username,
             password,
             retry_limit,
+            retry_policy: con_config.retry_policy(),
+            range_coalesce_gap: config.range_coalesce_gap as u64,
             blob_url_scheme: config.blob_url_scheme.clone(),
             blob_redirected_host: config.blob_redirected_host.clone(),
             cached_auth_using_http_get: HashCache::new(),
@@ -995,6 +1141,7 @@ impl BlobBackend for Registry {
             connection: self.connection.clone(),
             metrics: self.metrics.clone(),
             first: self.first.clone(),
+            range_fallback: RangeFallback::default(),
         }))
     }
 }
@@ -1059,6 +1206,8 @@ mod tests {
             username: "test".to_string(),
             password: "password".to_string(),
             retry_limit: 5,
+            retry_policy: RetryPolicy::default(),
+            range_coalesce_gap: 0,
             blob_url_scheme: "https".to_string(),
             blob_redirected_host: "oss.alibaba-inc.com".to_string(),
             cached_auth_using_http_get: Default::default(),
@@ -1172,4 +1321,57 @@ mod tests {
 
         assert_eq!(*val.load().as_ref(), 2);
     }
+
+    #[cfg(feature = "backend-async")]
+    #[test]
+    fn test_registry_read_async() {
+        use std::time::Instant;
+
+        use crate::backend::connection::start_mock_server;
+
+        let blob: Vec<u8> = (0..0x10000u32).map(|i| (i % 251) as u8).collect();
+        let addr = start_mock_server(blob.clone(), Duration::from_millis(200));
+        let config = RegistryConfig {
+            scheme: "http".to_string(),
+            host: addr.to_string(),
+            repo: "test/repo".to_string(),
+            ..Default::default()
+        };
+        let registry = Registry::new(&config, Some("test-read-async")).unwrap();
+        let reader = registry.get_reader("test").unwrap();
+        assert!(reader.capabilities().contains(BackendCaps::SUPPORTS_ASYNC));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .max_blocking_threads(1)
+            .build()
+            .unwrap();
+        // The first request is sent from the blocking thread pool to negotiate authorization.
+        let data = rt.block_on(reader.clone().read_async(0x100, 0x10)).unwrap();
+        assert_eq!(data, blob[0x10..0x110]);
+
+        // Following requests in flight don't occupy the only blocking thread of the runtime.
+        let start = Instant::now();
+        let results = rt.block_on(async {
+            let mut handles = Vec::new();
+            for idx in 0..16u64 {
+                let reader = reader.clone();
+                handles.push(tokio::spawn(async move {
+                    (idx, reader.read_async(0x100, idx * 0x1000).await)
+                }));
+            }
+            let mut results = Vec::new();
+            for handle in handles {
+                results.push(handle.await.unwrap());
+            }
+            results
+        });
+        assert!(start.elapsed() < Duration::from_millis(16 * 200 / 4));
+        for (idx, res) in results {
+            let offset = idx as usize * 0x1000;
+            assert_eq!(res.unwrap(), blob[offset..offset + 0x100]);
+        }
+
+        registry.shutdown();
+    }
 }
@@ -21,6 +21,7 @@ use time::{format_description, OffsetDateTime};
 
 use crate::backend::connection::{Connection, ConnectionConfig};
 use crate::backend::object_storage::{ObjectStorage, ObjectStorageState};
+use crate::backend::RetryPolicy;
 
 const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
 const HEADER_HOST: &str = "Host";
@@ -38,6 +39,7 @@ pub struct S3State {
     endpoint: String,
     bucket_name: String,
     retry_limit: u8,
+    retry_policy: RetryPolicy,
 }
 
 /// Storage backend to access data stored in S3.
@@ -64,6 +66,7 @@ impl S3 {
             access_key_secret: s3_config.access_key_secret.clone(),
             bucket_name: s3_config.bucket_name.clone(),
             retry_limit,
+            retry_policy: con_config.retry_policy(),
         });
         let metrics = id.map(|i| BackendMetrics::new(i, "oss"));
 
@@ -224,6 +227,10 @@ impl ObjectStorageState for S3State {
     fn retry_limit(&self) -> u8 {
         self.retry_limit
     }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.retry_policy
+    }
 }
 
 // modified based on https://github.com/minio/minio-rs/blob/5fea81d68d381fd2a4c27e4d259f7012de08ab77/src/s3/utils.rs#L52-L56
@@ -269,7 +276,7 @@ mod tests {
 
     use crate::backend::object_storage::ObjectStorageState;
     use crate::backend::s3::S3State;
-    use crate::backend::BlobBackend;
+    use crate::backend::{BlobBackend, RetryPolicy};
 
     use super::S3;
 
@@ -283,6 +290,7 @@ mod tests {
             endpoint: "localhost:9000".to_string(),
             bucket_name: "test-bucket".to_string(),
             retry_limit: 6,
+            retry_policy: RetryPolicy::default(),
         };
         let (resource, url) = state.url("test-object", &["a=b", "c=d"]);
         (state, resource, url)
@@ -334,4 +342,55 @@ mod tests {
         let authorization = headers.get("Authorization").unwrap();
         assert!(re.is_match(authorization.to_str().unwrap()));
     }
+
+    #[cfg(feature = "backend-async")]
+    #[test]
+    fn test_s3_read_async() {
+        use std::time::{Duration, Instant};
+
+        use crate::backend::connection::start_mock_server;
+        use crate::backend::BackendCaps;
+
+        let blob: Vec<u8> = (0..0x10000u32).map(|i| (i % 251) as u8).collect();
+        let addr = start_mock_server(blob.clone(), Duration::from_millis(200));
+        let config = S3Config {
+            scheme: "http".to_string(),
+            endpoint: addr.to_string(),
+            region: "us-east-1".to_string(),
+            bucket_name: "test-bucket".to_string(),
+            ..Default::default()
+        };
+        let s3 = S3::new(&config, Some("test-read-async")).unwrap();
+        let reader = s3.get_reader("test").unwrap();
+        assert!(reader.capabilities().contains(BackendCaps::SUPPORTS_ASYNC));
+
+        // Requests in flight don't occupy the only blocking thread of the runtime.
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .max_blocking_threads(1)
+            .build()
+            .unwrap();
+        let start = Instant::now();
+        let results = rt.block_on(async {
+            let mut handles = Vec::new();
+            for idx in 0..16u64 {
+                let reader = reader.clone();
+                handles.push(tokio::spawn(async move {
+                    (idx, reader.read_async(0x100, idx * 0x1000).await)
+                }));
+            }
+            let mut results = Vec::new();
+            for handle in handles {
+                results.push(handle.await.unwrap());
+            }
+            results
+        });
+        assert!(start.elapsed() < Duration::from_millis(16 * 200 / 4));
+        for (idx, res) in results {
+            let offset = idx as usize * 0x1000;
+            assert_eq!(res.unwrap(), blob[offset..offset + 0x100]);
+        }
+
+        s3.shutdown();
+    }
 }
@@ -0,0 +1,241 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage backend driver to enforce a hard timeout on backend requests.
+//!
+//! Network backends only limit the time to connect and the idle time between packets, so a
+//! request may still hang on a stalled TLS handshake or a slowly trickling response. Each request
+//! is issued from a bounded pool of threads and fails with `BackendError::Timeout` if it hasn't
+//! completed within the configured duration, including the time waiting for an idle thread.
+//! Backend requests can't be interrupted, so the timed out request runs to completion in background
+//! and its result gets discarded.
+
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use nydus_utils::metrics::BackendMetrics;
+
+use crate::backend::executor::Executor;
+use crate::backend::{
+    BackendCaps, BackendError, BackendResult, BlobBackend, BlobReader, RetryPolicy,
+};
+use crate::utils::alloc_buf;
+
+/// Maximum number of concurrent requests issued with a timeout.
+const TIMEOUT_MAX_THREADS: usize = 32;
+
+lazy_static! {
+    static ref TIMEOUT_EXECUTOR: Executor =
+        Executor::new("nydus_timeout_read", TIMEOUT_MAX_THREADS);
+}
+
+/// A storage backend failing requests not completed within a timeout.
+pub struct TimeoutBackend {
+    inner: Arc<dyn BlobBackend + Send + Sync>,
+    timeout: Duration,
+}
+
+impl TimeoutBackend {
+    /// Create a new instance of `TimeoutBackend`.
+    pub fn new(inner: Arc<dyn BlobBackend + Send + Sync>, timeout: Duration) -> Self {
+        TimeoutBackend { inner, timeout }
+    }
+}
+
+impl BlobBackend for TimeoutBackend {
+    fn shutdown(&self) {
+        self.inner.shutdown();
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+        Ok(Arc::new(TimeoutReader {
+            inner: self.inner.get_reader(blob_id)?,
+            timeout: self.timeout,
+        }))
+    }
+}
+
+struct TimeoutReader {
+    inner: Arc<dyn BlobReader>,
+    timeout: Duration,
+}
+
+impl TimeoutReader {
+    // Run `f` with the inner reader in background, and wait for at most `self.timeout`.
+    fn run<T, F>(&self, f: F) -> BackendResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn BlobReader) -> BackendResult<T> + Send + 'static,
+    {
+        let start = Instant::now();
+        let (tx, rx) = channel();
+        let reader = self.inner.clone();
+        let job = move || {
+            // The receiver is gone if the request has timed out.
+            let _ = tx.send(f(reader.as_ref()));
+        };
+        if !TIMEOUT_EXECUTOR.submit(job, self.timeout) {
+            return Err(BackendError::Timeout(self.timeout));
+        }
+
+        match rx.recv_timeout(self.timeout.saturating_sub(start.elapsed())) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) => Err(BackendError::Timeout(self.timeout)),
+            Err(RecvTimeoutError::Disconnected) => Err(BackendError::Unsupported(
+                "read request exited unexpectedly".to_string(),
+            )),
+        }
+    }
+}
+
+impl BlobReader for TimeoutReader {
+    fn blob_size(&self) -> BackendResult<u64> {
+        self.run(|reader| reader.blob_size())
+    }
+
+    fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+        let size = buf.len();
+        let (data, res) = self.run(move |reader| {
+            let mut data = alloc_buf(size);
+            let res = reader.try_read(&mut data, offset);
+            Ok((data, res))
+        })?;
+        let received = match &res {
+            Ok(v) => *v,
+            Err(BackendError::Interrupted(v, _)) => *v,
+            Err(_) => 0,
+        };
+        buf[..received].copy_from_slice(&data[..received]);
+        res
+    }
+
+    fn read_url(&self, url: &str, buf: &mut [u8]) -> BackendResult<usize> {
+        let url = url.to_string();
+        let size = buf.len();
+        let (data, size) = self.run(move |reader| {
+            let mut data = alloc_buf(size);
+            let size = reader.read_url(&url, &mut data)?;
+            Ok((data, size))
+        })?;
+        buf[..size].copy_from_slice(&data[..size]);
+        Ok(size)
+    }
+
+    fn metrics(&self) -> &BackendMetrics {
+        self.inner.metrics()
+    }
+
+    fn retry_limit(&self) -> u8 {
+        self.inner.retry_limit()
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    fn max_coalesce_gap(&self) -> u64 {
+        self.inner.max_coalesce_gap()
+    }
+
+    fn capabilities(&self) -> BackendCaps {
+        // Asynchronous reads are served by `read()` on the blocking thread pool.
+        self.inner.capabilities() & !BackendCaps::SUPPORTS_ASYNC
+    }
+
+    fn stop_prefetch_data(&self) -> BackendResult<()> {
+        self.inner.stop_prefetch_data()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    struct MockBackend {
+        latency: Duration,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    struct MockReader {
+        latency: Duration,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for MockReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(0x1000)
+        }
+
+        fn try_read(&self, buf: &mut [u8], _offset: u64) -> BackendResult<usize> {
+            thread::sleep(self.latency);
+            buf.fill(0x5a);
+            Ok(buf.len())
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn retry_limit(&self) -> u8 {
+            1
+        }
+
+        fn retry_policy(&self) -> RetryPolicy {
+            RetryPolicy {
+                delay: Duration::ZERO,
+                max_delay: Duration::ZERO,
+            }
+        }
+    }
+
+    impl BlobBackend for MockBackend {
+        fn shutdown(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn get_reader(&self, _blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+            Ok(Arc::new(MockReader {
+                latency: self.latency,
+                metrics: self.metrics.clone(),
+            }))
+        }
+    }
+
+    fn new_reader(latency: u64, timeout: u64) -> Arc<dyn BlobReader> {
+        let backend = TimeoutBackend::new(
+            Arc::new(MockBackend {
+                latency: Duration::from_millis(latency),
+                metrics: BackendMetrics::new("timeout", "localfs"),
+            }),
+            Duration::from_millis(timeout),
+        );
+        backend.get_reader("blob").unwrap()
+    }
+
+    #[test]
+    fn test_timeout_read() {
+        let mut buf = vec![0u8; 0x100];
+        let reader = new_reader(10, 1000);
+        assert_eq!(reader.read(&mut buf, 0).unwrap(), 0x100);
+        assert!(buf.iter().all(|v| *v == 0x5a));
+
+        // Both the first attempt and the retry time out, without waiting for the stalled reads.
+        let reader = new_reader(2000, 50);
+        let start = Instant::now();
+        let err = reader.read(&mut buf, 0).unwrap_err();
+        assert!(matches!(err, BackendError::Timeout(_)));
+        assert!(err.is_retryable());
+        assert!(start.elapsed() < Duration::from_millis(1000));
+    }
+}
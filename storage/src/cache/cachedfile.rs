@@ -9,33 +9,50 @@
 //! performance. It may be used by both the userspace `FileCacheMgr` or the `FsCacheMgr` based
 //! on the in-kernel fscache system.
 
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{ErrorKind, Read, Result};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{Error, ErrorKind, Read, Result, Write};
 use std::mem::ManuallyDrop;
+use std::ops::Range;
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
 use nix::sys::uio;
-use nydus_utils::compress::Decoder;
+use nydus_api::{DigestMismatchPolicy, PrefetchStrategy};
 use nydus_utils::crypt::{self, Cipher, CipherContext};
+use nydus_utils::filemap::FileMapState;
 use nydus_utils::metrics::{BlobcacheMetrics, Metric};
-use nydus_utils::{compress, digest, round_up_usize, DelayType, Delayer, FileRangeReader};
+use nydus_utils::{
+    compress, digest, round_up, round_up_usize, DelayType, Delayer, FileRangeReader,
+};
 use tokio::runtime::Runtime;
 
 use crate::backend::BlobReader;
-use crate::cache::state::ChunkMap;
+use crate::cache::checksum::CacheFileChecksum;
+use crate::cache::eviction::BlobEvictionHandle;
+use crate::cache::state::{ChunkGenerationMap, ChunkMap, RangeMap};
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncPrefetchMessage, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobIoMergeState};
+use crate::cache::{
+    is_prefetch_denied, read_out_of_bounds_chunks, BlobCache, BlobCacheStatus, BlobIoMergeState,
+    CacheError, CacheMetrics, PrefetchProgress, ReadStats, VerifyReport,
+};
 use crate::device::{
-    BlobChunkInfo, BlobInfo, BlobIoDesc, BlobIoRange, BlobIoSegment, BlobIoTag, BlobIoVec,
-    BlobObject, BlobPrefetchRequest,
+    BlobChunkInfo, BlobInfo, BlobIoChunk, BlobIoDesc, BlobIoKind, BlobIoRange, BlobIoSegment,
+    BlobIoTag, BlobIoVec, BlobObject, BlobPrefetchRequest,
 };
+use crate::factory::ASYNC_RUNTIME;
+use crate::meta::toc::{TocEntryList, TocLocation};
 use crate::meta::{BlobCompressionContextInfo, BlobMetaChunk};
-use crate::utils::{alloc_buf, copyv, readv, MemSliceCursor};
+use crate::utils::{
+    alloc_buf, copyv, is_zero_chunk_digest, punch_hole, readv, readv_mapped, MemSliceCursor,
+};
 use crate::{StorageError, StorageResult, RAFS_BATCH_SIZE_TO_GAP_SHIFT, RAFS_DEFAULT_CHUNK_SIZE};
 
 const DOWNLOAD_META_RETRY_COUNT: u32 = 5;
@@ -166,141 +183,1404 @@ impl BlobCCI {
         Ok(size)
     }
 
-    fn get_compressed_info(&self, chunk: &Arc<dyn BlobChunkInfo>) -> Result<(u64, u32)> {
-        Ok((
-            self.get_compressed_offset(chunk)?,
-            self.get_compressed_size(chunk)?,
-        ))
+    fn get_compressed_info(&self, chunk: &Arc<dyn BlobChunkInfo>) -> Result<(u64, u32)> {
+        Ok((
+            self.get_compressed_offset(chunk)?,
+            self.get_compressed_size(chunk)?,
+        ))
+    }
+
+    fn get_compressed_end(&self, chunk: &Arc<dyn BlobChunkInfo>) -> Result<u64> {
+        let (offset, size) = self.get_compressed_info(chunk)?;
+        Ok(offset + size as u64)
+    }
+}
+
+/// Helper struct to validate the blob ToC on first access and cache the validated ToC.
+pub(crate) struct BlobTocState {
+    path: String,
+    reader: Arc<dyn BlobReader>,
+    toc: Mutex<Option<Arc<TocEntryList>>>,
+}
+
+impl BlobTocState {
+    pub(crate) fn new(path: String, reader: Arc<dyn BlobReader>) -> Self {
+        BlobTocState {
+            path,
+            reader,
+            toc: Mutex::new(None),
+        }
+    }
+
+    fn get_or_validate(&self, blob_info: &BlobInfo) -> Result<Arc<TocEntryList>> {
+        let mut guard = self.toc.lock().unwrap();
+        if let Some(toc) = guard.as_ref() {
+            return Ok(toc.clone());
+        }
+
+        let blob_size = self
+            .reader
+            .blob_size()
+            .map_err(|e| eio!(format!("failed to get blob size, {}", e)))?;
+        let location = TocLocation::from_blob_info(blob_info, blob_size)?;
+        let toc =
+            TocEntryList::read_and_validate(&self.path, self.reader.as_ref(), &location, blob_size)
+                .map_err(|e| {
+                    error!("blob {}: {}", blob_info.blob_id(), e);
+                    e
+                })?;
+        let toc = Arc::new(toc);
+        *guard = Some(toc.clone());
+
+        Ok(toc)
+    }
+}
+
+// Index of a chunk buffered in a chunk map batch, with offset and size of its data in the cache file.
+type BatchedChunk = (u32, u64, u64);
+
+/// Helper struct to batch chunk map updates for chunks written into the cache file.
+///
+/// Ready state of buffered chunks is committed after syncing data of the cache file, so a chunk
+/// never gets marked as ready before its data is durable. The cache file is synced once per batch
+/// commit. While readers are waiting for chunks, buffered updates are committed immediately so the
+/// readers are not delayed by the batch size or time threshold. Buffered updates are also committed
+/// by a periodic flusher once they exceed the time threshold, and when the cache entry is dropped.
+///
+/// Committed chunks are reported to the cache evictor, so buffered chunks are never evicted before
+/// getting marked as ready.
+pub(crate) struct ChunkMapBatch {
+    batch_size: usize,
+    interval: Duration,
+    eviction: Option<BlobEvictionHandle>,
+    // Buffered chunks, with the time the first one got buffered.
+    state: Mutex<(Vec<BatchedChunk>, Instant)>,
+    waiters: AtomicUsize,
+}
+
+impl ChunkMapBatch {
+    pub(crate) fn new(
+        batch_size: u32,
+        interval: u64,
+        eviction: Option<BlobEvictionHandle>,
+    ) -> Self {
+        ChunkMapBatch {
+            batch_size: batch_size as usize,
+            interval: Duration::from_millis(interval),
+            eviction,
+            state: Mutex::new((Vec::with_capacity(batch_size as usize), Instant::now())),
+            waiters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Buffer the ready state update of a chunk whose data has been written to the cache file,
+    /// and commit buffered updates if the batch size or time threshold is reached, or if someone
+    /// is waiting for chunks.
+    fn add(&self, file: &File, chunk_map: &Arc<dyn ChunkMap>, chunk: &dyn BlobChunkInfo) {
+        let (offset, size) = match self.eviction.as_ref() {
+            Some(eviction) => eviction.locate(chunk),
+            None => (0, 0),
+        };
+        let mut guard = self.state.lock().unwrap();
+        if guard.0.is_empty() {
+            guard.1 = Instant::now();
+        }
+        guard.0.push((chunk.id(), offset, size));
+        if guard.0.len() >= self.batch_size
+            || guard.1.elapsed() >= self.interval
+            || self.waiters.load(Ordering::Acquire) > 0
+        {
+            let pending = std::mem::take(&mut guard.0);
+            drop(guard);
+            self.commit(file, chunk_map, pending);
+        }
+    }
+
+    /// Commit buffered chunk map updates if the time threshold has been reached.
+    fn flush_expired(&self, file: &File, chunk_map: &Arc<dyn ChunkMap>) {
+        let mut guard = self.state.lock().unwrap();
+        if !guard.0.is_empty() && guard.1.elapsed() >= self.interval {
+            let pending = std::mem::take(&mut guard.0);
+            drop(guard);
+            self.commit(file, chunk_map, pending);
+        }
+    }
+
+    /// Commit all buffered chunk map updates.
+    fn flush(&self, file: &File, chunk_map: &Arc<dyn ChunkMap>) {
+        let pending = std::mem::take(&mut self.state.lock().unwrap().0);
+        if !pending.is_empty() {
+            self.commit(file, chunk_map, pending);
+        }
+    }
+
+    /// Commit buffered chunk map updates if any chunk in range [start, start + count) is buffered.
+    fn flush_range(&self, file: &File, chunk_map: &Arc<dyn ChunkMap>, start: u32, count: u32) {
+        let end = start.saturating_add(count);
+        let mut guard = self.state.lock().unwrap();
+        if guard
+            .0
+            .iter()
+            .any(|(idx, _, _)| *idx >= start && *idx < end)
+        {
+            let pending = std::mem::take(&mut guard.0);
+            drop(guard);
+            self.commit(file, chunk_map, pending);
+        }
+    }
+
+    // Run `f` as a waiter, so updates added meanwhile are committed without buffering. Buffered
+    // updates of chunks in range [start, start + count) are committed before running `f`.
+    fn wait<T>(
+        &self,
+        file: &File,
+        chunk_map: &Arc<dyn ChunkMap>,
+        start: u32,
+        count: u32,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        self.waiters.fetch_add(1, Ordering::AcqRel);
+        self.flush_range(file, chunk_map, start, count);
+        let ret = f();
+        self.waiters.fetch_sub(1, Ordering::AcqRel);
+        ret
+    }
+
+    /// Check whether a chunk is ready, and mark it as pending if not.
+    ///
+    /// Buffered updates are only committed if the chunk is not ready yet, so reading ready chunks
+    /// never syncs the cache file.
+    fn check_ready_and_mark_pending(
+        &self,
+        file: &File,
+        chunk_map: &Arc<dyn ChunkMap>,
+        chunk: &dyn BlobChunkInfo,
+    ) -> StorageResult<bool> {
+        if chunk_map
+            .is_ready(chunk)
+            .map_err(StorageError::CacheIndex)?
+        {
+            return Ok(true);
+        }
+        self.wait(file, chunk_map, chunk.id(), 1, || {
+            chunk_map.check_ready_and_mark_pending(chunk)
+        })
+    }
+
+    /// Wait for chunks in range [start, start + count) to get ready.
+    fn wait_for_range_ready(
+        &self,
+        file: &File,
+        chunk_map: &Arc<dyn ChunkMap>,
+        start: u32,
+        count: u32,
+    ) -> Result<bool> {
+        let bitmap = chunk_map
+            .as_range_map()
+            .ok_or_else(|| einval!("invalid chunk_map for batched chunk map updates"))?;
+        self.wait(file, chunk_map, start, count, || {
+            bitmap.wait_for_range_ready(start, count)
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().0.is_empty()
+    }
+
+    fn commit(&self, file: &File, chunk_map: &Arc<dyn ChunkMap>, pending: Vec<BatchedChunk>) {
+        let bitmap = match chunk_map.as_range_map() {
+            Some(v) => v,
+            None => return,
+        };
+        match file.sync_data() {
+            Ok(_) => {
+                for (index, offset, size) in pending {
+                    if let Err(e) = bitmap.set_range_ready_and_clear_pending(index, 1) {
+                        error!("Failed change caching state for chunk {}, {:?}", index, e);
+                    } else if let Some(eviction) = self.eviction.as_ref() {
+                        // Nobody is waiting for the result of batched updates.
+                        if let Err(e) = eviction.on_access_at(index, offset, size) {
+                            warn!("failed to keep chunk {} in cache, {}", index, e);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to sync data of cache file, {}", e);
+                for (index, _, _) in pending {
+                    bitmap.clear_range_pending(index, 1);
+                }
+            }
+        }
+    }
+}
+
+/// Helper struct to track whether the working directory of cache files is writable.
+///
+/// Once writing to cache files fails with `EROFS`, caching is suspended and data is served from
+/// the storage backend directly. Writability of the working directory is probed periodically,
+/// and caching resumes when the filesystem recovers.
+pub(crate) struct CacheWriteState {
+    work_dir: String,
+    probe_interval: Duration,
+    readonly: AtomicBool,
+    last_probe: Mutex<Instant>,
+}
+
+impl CacheWriteState {
+    pub(crate) fn new(work_dir: &str, probe_interval: u64) -> Self {
+        CacheWriteState {
+            work_dir: work_dir.to_string(),
+            probe_interval: Duration::from_secs(probe_interval),
+            readonly: AtomicBool::new(false),
+            last_probe: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Check whether caching is suspended, re-probing the working directory if it's time to.
+    pub(crate) fn is_readonly(&self) -> bool {
+        if !self.readonly.load(Ordering::Acquire) {
+            return false;
+        }
+
+        // Only one thread needs to probe, others keep on serving data in passthrough mode.
+        if let Ok(mut last_probe) = self.last_probe.try_lock() {
+            if last_probe.elapsed() >= self.probe_interval {
+                *last_probe = Instant::now();
+                if self.probe() {
+                    info!(
+                        "cache directory {} becomes writable, resume caching",
+                        self.work_dir
+                    );
+                    self.readonly.store(false, Ordering::Release);
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Check the result of writing to the working directory, and switch to read-only passthrough
+    /// mode if the filesystem becomes read-only.
+    ///
+    /// Returns true if the error is caused by a read-only filesystem.
+    pub(crate) fn check_write_error(&self, err: &Error) -> bool {
+        if err.raw_os_error() != Some(libc::EROFS) {
+            return false;
+        }
+        *self.last_probe.lock().unwrap() = Instant::now();
+        if !self.readonly.swap(true, Ordering::AcqRel) {
+            error!(
+                "cache directory {} becomes read-only, serve data from storage backend without caching",
+                self.work_dir
+            );
+        }
+        true
+    }
+
+    fn probe(&self) -> bool {
+        let path = Path::new(&self.work_dir).join(".nydus_write_probe");
+        let res = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(b"probe").and_then(|_| f.sync_all()));
+        let _ = std::fs::remove_file(&path);
+        res.is_ok()
+    }
+}
+
+/// Helper struct to count accesses to each chunk of a blob, to generate heatmaps of the blob.
+pub(crate) struct ChunkAccessHeatmap {
+    counters: Vec<AtomicU32>,
+}
+
+impl ChunkAccessHeatmap {
+    pub(crate) fn new(chunk_count: u32) -> Self {
+        ChunkAccessHeatmap {
+            counters: (0..chunk_count).map(|_| AtomicU32::new(0)).collect(),
+        }
+    }
+
+    /// Record an access to the chunk with index `index`.
+    fn record(&self, index: u32) {
+        if let Some(counter) = self.counters.get(index as usize) {
+            let _ =
+                counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| v.checked_add(1));
+        }
+    }
+
+    fn snapshot(&self) -> Vec<u32> {
+        self.counters
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    fn reset(&self) {
+        for counter in self.counters.iter() {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Helper struct to read ahead chunks following sequential user IO of a blob.
+///
+/// User IO is sequential if it starts at or right after the last chunk accessed by the previous
+/// user IO, and then the next `window` chunks get fetched into the cache by prefetch workers.
+pub(crate) struct ChunkReadahead {
+    window: u32,
+    // Index of the last chunk accessed by user IO, u64::MAX if unknown.
+    last: AtomicU64,
+    // Index of the first chunk not requested to read ahead yet.
+    next: AtomicU64,
+    // The blob cache object to issue readahead requests, set once it's created.
+    cache: Mutex<Weak<FileCacheEntry>>,
+}
+
+impl ChunkReadahead {
+    pub(crate) fn new(window: u32) -> Self {
+        ChunkReadahead {
+            window,
+            last: AtomicU64::new(u64::MAX),
+            next: AtomicU64::new(0),
+            cache: Mutex::new(Weak::new()),
+        }
+    }
+
+    /// Set the blob cache object to issue readahead requests.
+    pub(crate) fn set_cache(&self, cache: &Arc<FileCacheEntry>) {
+        *self.cache.lock().unwrap() = Arc::downgrade(cache);
+    }
+
+    /// Record user IO accessing chunks [first, last], and return chunks to read ahead if it's
+    /// sequential.
+    fn advance(&self, first: u32, last: u32, chunk_count: u32) -> Option<Range<u32>> {
+        let prev = self.last.swap(last as u64, Ordering::AcqRel);
+        if prev == u64::MAX || (first as u64 != prev && first as u64 != prev + 1) {
+            self.next.store(0, Ordering::Release);
+            return None;
+        }
+
+        let end = std::cmp::min(last as u64 + 1 + self.window as u64, chunk_count as u64);
+        let start = std::cmp::max(last as u64 + 1, self.next.fetch_max(end, Ordering::AcqRel));
+        if start < end {
+            Some(start as u32..end as u32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Helper struct to cache decoded data of hot chunks in memory.
+///
+/// The memory tier avoids decompressing, decrypting or validating chunk data from cache files
+/// again and again for hot chunks. Least recently used chunks get evicted when exceeding the
+/// capacity. Chunks are indexed by chunk index by default, and a tier shared by multiple blobs
+/// may use `(blob_id, chunk_index)` as key instead.
+pub(crate) struct ChunkMemoryTier<K = u32> {
+    capacity: u64,
+    state: Mutex<ChunkMemoryTierState<K>>,
+}
+
+struct ChunkMemoryTierState<K> {
+    chunks: HashMap<K, (Arc<Vec<u8>>, u64)>,
+    lru: BTreeMap<u64, K>,
+    size: u64,
+    seq: u64,
+}
+
+impl<K: Clone + Eq + Hash> ChunkMemoryTier<K> {
+    pub(crate) fn new(capacity: u64) -> Self {
+        ChunkMemoryTier {
+            capacity,
+            state: Mutex::new(ChunkMemoryTierState {
+                chunks: HashMap::new(),
+                lru: BTreeMap::new(),
+                size: 0,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// Get decoded data of the chunk identified by `key`, and mark it as most recently used.
+    pub(crate) fn get(&self, key: &K) -> Option<Arc<Vec<u8>>> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let seq = state.seq + 1;
+        let (data, old_seq) = state.chunks.get_mut(key)?;
+        let old_seq = std::mem::replace(old_seq, seq);
+        let data = data.clone();
+        state.seq = seq;
+        state.lru.remove(&old_seq);
+        state.lru.insert(seq, key.clone());
+
+        Some(data)
+    }
+
+    /// Promote decoded data of the chunk identified by `key` into the memory tier.
+    pub(crate) fn insert(&self, key: K, data: &[u8]) {
+        let size = data.len() as u64;
+        if size > self.capacity {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.chunks.contains_key(&key) {
+            return;
+        }
+        while state.size + size > self.capacity {
+            match state.lru.pop_first() {
+                Some((_, victim)) => {
+                    if let Some((buf, _)) = state.chunks.remove(&victim) {
+                        state.size -= buf.len() as u64;
+                    }
+                }
+                None => break,
+            }
+        }
+        state.seq += 1;
+        let seq = state.seq;
+        state
+            .chunks
+            .insert(key.clone(), (Arc::new(data.to_vec()), seq));
+        state.lru.insert(seq, key);
+        state.size += size;
+    }
+
+    /// Drop data of the chunk identified by `key` from the memory tier.
+    pub(crate) fn remove(&self, key: &K) {
+        let mut state = self.state.lock().unwrap();
+        if let Some((buf, seq)) = state.chunks.remove(key) {
+            state.size -= buf.len() as u64;
+            state.lru.remove(&seq);
+        }
+    }
+
+    /// Drop data of chunks with keys not satisfying `keep` from the memory tier.
+    pub(crate) fn retain<F: Fn(&K) -> bool>(&self, keep: F) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let chunks = &mut state.chunks;
+        let mut size = state.size;
+        state.lru.retain(|_, key| {
+            if keep(key) {
+                return true;
+            }
+            if let Some((buf, _)) = chunks.remove(key) {
+                size -= buf.len() as u64;
+            }
+            false
+        });
+        state.size = size;
+    }
+}
+
+/// Memory tier shared by all blobs of a cache manager, with chunks keyed by blob id and index.
+pub(crate) type SharedMemoryTier = ChunkMemoryTier<(String, u32)>;
+
+/// Chunks of a blob cached in the memory tier shared by all blobs of a cache manager.
+pub(crate) struct BlobMemoryTier {
+    tier: Arc<SharedMemoryTier>,
+    blob_id: String,
+}
+
+impl BlobMemoryTier {
+    pub(crate) fn new(tier: Arc<SharedMemoryTier>, blob_id: String) -> Self {
+        BlobMemoryTier { tier, blob_id }
+    }
+
+    /// Get decoded data of the chunk with index `index`, and mark it as most recently used.
+    pub(crate) fn get(&self, index: &u32) -> Option<Arc<Vec<u8>>> {
+        self.tier.get(&(self.blob_id.clone(), *index))
+    }
+
+    /// Promote decoded data of the chunk with index `index` into the memory tier.
+    pub(crate) fn insert(&self, index: u32, data: &[u8]) {
+        self.tier.insert((self.blob_id.clone(), index), data)
+    }
+
+    /// Drop data of the chunk with index `index` from the memory tier.
+    pub(crate) fn remove(&self, index: &u32) {
+        self.tier.remove(&(self.blob_id.clone(), *index))
+    }
+
+    /// Drop data of all chunks of the blob from the memory tier.
+    pub(crate) fn clear(&self) {
+        self.tier.retain(|(blob_id, _)| *blob_id != self.blob_id)
+    }
+}
+
+/// Memory mapping of a cache file, created once the cache file has been read often enough.
+///
+/// Cache files are sized upfront and the mapping is shared with the file, so it covers chunks
+/// written later and reflects holes punched by eviction and invalidation. Accessing the mapping
+/// beyond the end of the file raises `SIGBUS`, so the file size is checked before each read
+/// through the mapping, and the mapping is dropped once the file gets shrunk, falling back to
+/// reading the file.
+pub(crate) struct CacheFileMap {
+    map: RwLock<Option<Arc<FileMapState>>>,
+    failed: AtomicBool,
+    reads: AtomicU32,
+    read_threshold: u32,
+    size: usize,
+}
+
+impl CacheFileMap {
+    /// Create a mapping of `size` bytes of `file`, established after `read_threshold` reads.
+    pub(crate) fn new(file: &File, blob_id: &str, size: usize, read_threshold: u32) -> Self {
+        let map = CacheFileMap {
+            map: RwLock::new(None),
+            failed: AtomicBool::new(false),
+            reads: AtomicU32::new(0),
+            read_threshold,
+            size,
+        };
+        if read_threshold == 0 {
+            map.init(file, blob_id);
+        }
+        map
+    }
+
+    /// Account a read from the cache file, and get the mapping if it's established and the cache
+    /// file still covers it.
+    pub(crate) fn get(&self, file: &File, blob_id: &str) -> Option<Arc<FileMapState>> {
+        let map = self.map.read().unwrap().clone();
+        if let Some(map) = map {
+            if self.is_file_mappable(file) {
+                return Some(map);
+            }
+            self.unmap(file, blob_id);
+            return None;
+        }
+        if self.failed.load(Ordering::Relaxed)
+            || self.reads.fetch_add(1, Ordering::Relaxed) + 1 < self.read_threshold
+        {
+            return None;
+        }
+        self.init(file, blob_id)
+    }
+
+    /// Check whether the mapping has been established.
+    #[cfg(test)]
+    pub(crate) fn is_mapped(&self) -> bool {
+        self.map.read().unwrap().is_some()
+    }
+
+    // Drop the mapping if the cache file is smaller than the mapping. The cache file will be
+    // mapped again once it's restored and has been read often enough.
+    fn unmap(&self, file: &File, blob_id: &str) {
+        let mut guard = self.map.write().unwrap();
+        if guard.is_some() && !self.is_file_mappable(file) {
+            warn!(
+                "cache file of blob {} is shrunk, drop its memory mapping",
+                blob_id
+            );
+            *guard = None;
+            self.reads.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn is_file_mappable(&self, file: &File) -> bool {
+        file.metadata()
+            .map(|m| m.len() >= self.size as u64)
+            .unwrap_or(false)
+    }
+
+    fn init(&self, file: &File, blob_id: &str) -> Option<Arc<FileMapState>> {
+        let mut guard = self.map.write().unwrap();
+        if let Some(map) = guard.as_ref() {
+            return Some(map.clone());
+        }
+        // Count reads again before retrying if the cache file is too small to be mapped.
+        self.reads.store(0, Ordering::Relaxed);
+        if !self.is_file_mappable(file) {
+            return None;
+        }
+        match file
+            .try_clone()
+            .and_then(|f| FileMapState::new(f, 0, self.size, false))
+        {
+            Ok(map) => {
+                let map = Arc::new(map);
+                *guard = Some(map.clone());
+                Some(map)
+            }
+            Err(e) => {
+                warn!(
+                    "failed to memory map cache file for blob {}, {}",
+                    blob_id, e
+                );
+                self.failed.store(true, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+/// Get offset of uncompressed data of the chunk in the cache file.
+///
+/// Chunks are stored at their uncompressed offsets in the blob, or at the start of slots of
+/// `slot_size` bytes indexed by chunk index if `slot_size` isn't zero.
+pub(crate) fn cache_data_offset(chunk: &dyn BlobChunkInfo, slot_size: u64) -> u64 {
+    match slot_size {
+        0 => chunk.uncompressed_offset(),
+        v => chunk.id() as u64 * v,
+    }
+}
+
+// Pad chunk data with zeros to a multiple of `align` bytes, so writes to the cache file are
+// aligned too.
+fn pad_cache_data(buf: &[u8], align: u64) -> Cow<[u8]> {
+    let size = buf.len() as u64;
+    if align == 0 || size % align == 0 {
+        return Cow::Borrowed(buf);
+    }
+    let mut data = buf.to_vec();
+    data.resize(round_up(size, align) as usize, 0);
+    Cow::Owned(data)
+}
+
+pub(crate) struct FileCacheEntry {
+    pub(crate) blob_id: String,
+    pub(crate) blob_info: Arc<BlobInfo>,
+    pub(crate) cache_cipher_object: Arc<Cipher>,
+    pub(crate) cache_cipher_context: Arc<CipherContext>,
+    pub(crate) chunk_map: Arc<dyn ChunkMap>,
+    // Batch chunk map updates for prefetched chunks, only for `IndexedChunkMap`.
+    pub(crate) chunk_map_batch: Option<ChunkMapBatch>,
+    // Validated ToC of the blob, only for blobs with ToC.
+    pub(crate) blob_toc: Option<BlobTocState>,
+    // Memory mapping of the cache file, to serve reads from memory backed or small and hot cache
+    // files.
+    pub(crate) cache_map: Option<CacheFileMap>,
+    pub(crate) file: Arc<File>,
+    pub(crate) meta: Option<FileCacheMeta>,
+    pub(crate) metrics: Arc<BlobcacheMetrics>,
+    pub(crate) prefetch_state: Arc<AtomicU32>,
+    pub(crate) reader: Arc<dyn BlobReader>,
+    pub(crate) runtime: Arc<Runtime>,
+    pub(crate) workers: Arc<AsyncWorkerMgr>,
+
+    pub(crate) blob_compressed_size: u64,
+    pub(crate) blob_uncompressed_size: u64,
+    // Size of the blob found truncated on storage backend, `u64::MAX` if not truncated.
+    pub(crate) truncated_blob_size: AtomicU64,
+    // Whether to fail reads beyond the end of truncated blobs without accessing the backend.
+    pub(crate) degrade_truncated_blob: bool,
+    // Whether `get_blob_object()` is supported.
+    pub(crate) is_get_blob_object_supported: bool,
+    // Cache raw data from backend instead of decompressed/decrypted plaintext.
+    pub(crate) is_raw_data: bool,
+    // The data in cache file is uncompressed and encrypted.
+    pub(crate) is_cache_encrypted: bool,
+    // Whether direct chunkmap is used.
+    pub(crate) is_direct_chunkmap: bool,
+    // The blob is for an stargz image.
+    pub(crate) is_legacy_stargz: bool,
+    // The blob is for an RAFS filesystem in `TARFS` mode.
+    pub(crate) is_tarfs: bool,
+    // The blob contains batch chunks.
+    pub(crate) is_batch: bool,
+    // The blob is based on ZRan decompression algorithm.
+    pub(crate) is_zran: bool,
+    // Serve all-zero chunks from holes of the cache file instead of fetching and caching them.
+    pub(crate) zero_chunks: bool,
+    // True if direct IO is enabled for the `self.file`, supported for fscache only.
+    pub(crate) dio_enabled: bool,
+    // Data from the file cache should be validated before use.
+    pub(crate) need_validation: bool,
+    // Reject chunks without digest instead of skipping validation for them.
+    pub(crate) strict_validation: bool,
+    // How to handle chunk data failing digest validation.
+    pub(crate) digest_mismatch_policy: DigestMismatchPolicy,
+    // Whether it has been reported that the blob contains chunks without digest.
+    pub(crate) no_digest_reported: AtomicBool,
+    // Amplified user IO request batch size to read data from remote storage backend / local cache.
+    pub(crate) user_io_batch_size: u32,
+    // Maximum size of backend requests merged from user IO, zero for no limit.
+    pub(crate) max_user_merge_size: u64,
+    // Maximum size of decompressed data of a chunk.
+    pub(crate) max_decompress_size: u64,
+    // Boundary to pad writes of uncompressed chunk data to, zero for no padding.
+    pub(crate) cache_block_align: u64,
+    // Size of the slot storing each chunk in the cache file, zero if chunks are stored at their
+    // uncompressed offsets.
+    pub(crate) cache_slot_size: u64,
+    // Maximum percentage of fetched bytes to requested bytes when merging/extending requests.
+    pub(crate) read_amplification_limit: u32,
+    // Switch to read-only passthrough mode when the cache directory becomes read-only.
+    pub(crate) write_state: Option<Arc<CacheWriteState>>,
+    // Memory tier to cache decoded data of hot chunks.
+    pub(crate) memory_tier: Option<BlobMemoryTier>,
+    // Evict cached chunks when cache files exceed the size limit.
+    pub(crate) eviction: Option<BlobEvictionHandle>,
+    // Generations of cached chunks, to invalidate stale chunks of blobs rebuilt in place.
+    pub(crate) chunk_generations: Option<ChunkGenerationMap>,
+    // Priority class of the blob when dispatching queued backend requests.
+    pub(crate) priority: u8,
+    // Count accesses to each chunk for heatmaps of the blob.
+    pub(crate) access_heatmap: Option<ChunkAccessHeatmap>,
+    // Minimum number of chunks fetched together to decompress them in parallel, zero to disable.
+    pub(crate) parallel_decompress_threshold: u32,
+    // Number of threads to decompress chunks in parallel.
+    pub(crate) parallel_decompress_threads: u32,
+    // Read ahead chunks following sequential user IO.
+    pub(crate) readahead: Option<ChunkReadahead>,
+    // Whole-file checksum of the cache file, to skip verifying cached chunks on startup.
+    pub(crate) checksum: Option<Arc<CacheFileChecksum>>,
+    // Counters of chunk hits and misses.
+    pub(crate) cache_metrics: CacheMetrics,
+    pub(crate) prefetch_config: Arc<AsyncPrefetchConfig>,
+    // Maximum size of merged prefetch requests overriding `prefetch_config`, zero if not set.
+    pub(crate) prefetch_merging_size: AtomicU64,
+    // Bytes of prefetch requests queued and handled.
+    pub(crate) prefetch_progress: PrefetchProgress,
+    // Ranges of chunk indexes which should never be prefetched.
+    pub(crate) prefetch_deny: Vec<Range<u32>>,
+    // Fill data of chunks beyond the end of the blob with zero instead of failing reads.
+    pub(crate) zero_out_of_bounds_chunks: bool,
+    // Time of last read in seconds since the UNIX epoch, zero if never read.
+    pub(crate) last_access: AtomicU64,
+}
+
+impl Drop for FileCacheEntry {
+    fn drop(&mut self) {
+        self.flush_chunk_map_batch();
+        if let Some(checksum) = self.checksum.as_ref() {
+            if let Err(e) = checksum.save() {
+                warn!(
+                    "failed to save checksum of cache file of blob {}, {}",
+                    self.blob_id, e
+                );
+            }
+        }
+    }
+}
+
+impl FileCacheEntry {
+    pub(crate) fn get_blob_size(reader: &Arc<dyn BlobReader>, blob_info: &BlobInfo) -> Result<u64> {
+        // Stargz needs blob size information, so hacky!
+        let size = if blob_info.is_legacy_stargz() {
+            reader.blob_size().map_err(|e| einval!(e))?
+        } else {
+            blob_info.compressed_size()
+        };
+
+        Ok(size)
+    }
+
+    // Get the memory mapping to serve a read from the cache file, if it's established.
+    fn mapped_cache_file(&self) -> Option<Arc<FileMapState>> {
+        self.cache_map
+            .as_ref()
+            .and_then(|map| map.get(&self.file, &self.blob_id))
+    }
+
+    fn delay_persist_chunk_data(&self, chunk: Arc<dyn BlobChunkInfo>, buffer: Arc<DataBuffer>) {
+        let delayed_chunk_map = self.chunk_map.clone();
+        let file = self.file.clone();
+        let metrics = self.metrics.clone();
+        let is_raw_data = self.is_raw_data;
+        let is_cache_encrypted = self.is_cache_encrypted;
+        let cipher_object = self.cache_cipher_object.clone();
+        let cipher_context = self.cache_cipher_context.clone();
+        let write_state = self.write_state.clone();
+        let checksum = self.checksum.clone();
+        let eviction = self.eviction.clone();
+        let block_align = self.cache_block_align;
+        let slot_size = self.cache_slot_size;
+
+        metrics.buffered_backend_size.add(buffer.size() as u64);
+        self.runtime.spawn_blocking(move || {
+            metrics.buffered_backend_size.sub(buffer.size() as u64);
+            let mut t_buf;
+            let buf = if !is_raw_data && is_cache_encrypted {
+                let (key, iv) = cipher_context.generate_cipher_meta(&chunk.chunk_id().data);
+                let buf = buffer.slice();
+                t_buf = alloc_buf(round_up_usize(buf.len(), ENCRYPTION_PAGE_SIZE));
+
+                let mut pos = 0;
+                while pos < buf.len() {
+                    let mut s_buf;
+                    // Padding to buffer to 4096 bytes if needed.
+                    let buf = if pos + ENCRYPTION_PAGE_SIZE > buf.len() {
+                        s_buf = buf[pos..].to_vec();
+                        s_buf.resize(ENCRYPTION_PAGE_SIZE, 0);
+                        &s_buf
+                    } else {
+                        &buf[pos..pos + ENCRYPTION_PAGE_SIZE]
+                    };
+
+                    assert_eq!(buf.len(), ENCRYPTION_PAGE_SIZE);
+                    match cipher_object.encrypt(key, Some(&iv), buf) {
+                        Ok(buf2) => {
+                            assert_eq!(buf2.len(), ENCRYPTION_PAGE_SIZE);
+                            t_buf[pos..pos + ENCRYPTION_PAGE_SIZE].copy_from_slice(buf2.as_ref());
+                            pos += ENCRYPTION_PAGE_SIZE;
+                        }
+                        Err(_) => {
+                            Self::_clear_chunk_pending_status(&delayed_chunk_map, chunk.as_ref());
+                            return;
+                        }
+                    }
+                }
+                &t_buf
+            } else {
+                buffer.slice()
+            };
+
+            let offset = if is_raw_data {
+                chunk.compressed_offset()
+            } else {
+                cache_data_offset(chunk.as_ref(), slot_size)
+            };
+            let buf = pad_cache_data(buf, block_align);
+            let res =
+                Self::persist_cached_data(&file, &write_state, checksum.as_deref(), offset, &buf);
+            if let Err(e) = Self::_update_chunk_pending_status(
+                &delayed_chunk_map,
+                &eviction,
+                chunk.as_ref(),
+                res.is_ok(),
+            ) {
+                warn!("failed to keep chunk {} in cache, {}", chunk.id(), e);
+            }
+        });
+    }
+
+    fn persist_chunk_data(&self, chunk: &dyn BlobChunkInfo, buf: &[u8]) -> Result<()> {
+        let offset = cache_data_offset(chunk, self.cache_slot_size);
+        let res = Self::persist_cached_data(
+            &self.file,
+            &self.write_state,
+            self.checksum.as_deref(),
+            offset,
+            &pad_cache_data(buf, self.cache_block_align),
+        );
+        self.update_chunk_pending_status(chunk, res.is_ok())
+    }
+
+    fn persist_chunk_data_batched(
+        &self,
+        batch: &ChunkMapBatch,
+        chunk: &dyn BlobChunkInfo,
+        buf: &[u8],
+    ) {
+        let offset = cache_data_offset(chunk, self.cache_slot_size);
+        match Self::persist_cached_data(
+            &self.file,
+            &self.write_state,
+            self.checksum.as_deref(),
+            offset,
+            &pad_cache_data(buf, self.cache_block_align),
+        ) {
+            Ok(_) => batch.add(&self.file, &self.chunk_map, chunk),
+            Err(_) => self.clear_chunk_pending_status(chunk),
+        }
+    }
+
+    // Check whether a chunk is ready, without being delayed by batched chunk map updates when
+    // waiting for the chunk being fetched by others.
+    fn check_ready_and_mark_pending(&self, chunk: &dyn BlobChunkInfo) -> StorageResult<bool> {
+        match self.chunk_map_batch.as_ref() {
+            Some(batch) => batch.check_ready_and_mark_pending(&self.file, &self.chunk_map, chunk),
+            None => self.chunk_map.check_ready_and_mark_pending(chunk),
+        }
+    }
+
+    // Wait for chunks being fetched by others, without being delayed by batched chunk map updates.
+    fn wait_for_chunks_ready(
+        &self,
+        bitmap: &dyn RangeMap<I = u32>,
+        start: u32,
+        count: u32,
+    ) -> Result<bool> {
+        match self.chunk_map_batch.as_ref() {
+            Some(batch) => batch.wait_for_range_ready(&self.file, &self.chunk_map, start, count),
+            None => bitmap.wait_for_range_ready(start, count),
+        }
+    }
+
+    fn flush_chunk_map_batch(&self) {
+        if let Some(batch) = self.chunk_map_batch.as_ref() {
+            if !batch.is_empty() {
+                batch.flush(&self.file, &self.chunk_map);
+            }
+        }
+    }
+
+    /// Start a task to periodically commit batched chunk map updates exceeding the time threshold,
+    /// so they don't stay uncommitted until the next chunk gets written. The task exits once the
+    /// cache entry is dropped.
+    pub(crate) fn start_chunk_map_batch_flusher(entry: &Arc<Self>) {
+        let interval = match entry.chunk_map_batch.as_ref() {
+            Some(batch) if !batch.interval.is_zero() => batch.interval,
+            _ => return,
+        };
+        let entry = Arc::downgrade(entry);
+        ASYNC_RUNTIME.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let entry = match entry.upgrade() {
+                    Some(v) => v,
+                    None => break,
+                };
+                let _ = tokio::task::spawn_blocking(move || {
+                    if let Some(batch) = entry.chunk_map_batch.as_ref() {
+                        batch.flush_expired(&entry.file, &entry.chunk_map);
+                    }
+                })
+                .await;
+            }
+        });
+    }
+
+    /// Evict a cached chunk by marking it as not ready and releasing its space in the cache file.
+    pub(crate) fn evict_chunk(&self, index: u32, offset: u64, size: u64) -> Result<()> {
+        let range_map = self.chunk_map.as_range_map().ok_or_else(|| enosys!())?;
+        range_map.clear_range_ready(index, 1)?;
+        let old = self.checksum.as_ref().map(|checksum| {
+            CacheFileChecksum::compute(&self.file, offset, size).map_err(|e| {
+                warn!("failed to compute checksum of cache file, {}", e);
+                checksum.invalidate();
+            })
+        });
+        punch_hole(self.file.as_raw_fd(), offset, size)?;
+        if let (Some(checksum), Some(Ok(old))) = (self.checksum.as_ref(), old) {
+            checksum.release(old);
+        }
+        Ok(())
+    }
+
+    /// Invalidate all cached chunks of the blob, so they will be fetched from the backend again.
+    ///
+    /// Space of the cache file is released too if `release_space` is true. Chunks being fetched
+    /// concurrently may still get cached after invalidation.
+    pub(crate) fn invalidate(&self, release_space: bool) -> Result<()> {
+        let range_map = self.chunk_map.as_range_map().ok_or_else(|| {
+            enosys!(format!(
+                "can't invalidate cached chunks of blob {} without index",
+                self.blob_id
+            ))
+        })?;
+        // Commit deferred updates first, otherwise they may mark chunks as ready again.
+        self.flush_chunk_map_batch();
+        let count = self.blob_info.chunk_count();
+        if count > 0 {
+            range_map.clear_range_ready(0, count)?;
+        }
+        if let Some(tier) = self.memory_tier.as_ref() {
+            tier.clear();
+        }
+        if let Some(eviction) = self.eviction.as_ref() {
+            eviction.evictor().forget_blob_chunks(eviction.blob());
+        }
+        if release_space {
+            let size = self.file.metadata()?.len();
+            if size > 0 {
+                punch_hole(self.file.as_raw_fd(), 0, size)?;
+            }
+            if let Some(checksum) = self.checksum.as_ref() {
+                checksum.reset(0);
+            }
+        }
+        info!("invalidate cached chunks of blob {}", self.blob_id);
+        Ok(())
+    }
+
+    // Invalidate cached data of the chunk if it has been cached with a different generation, so
+    // it will be fetched from the backend again.
+    fn check_chunk_generation(
+        &self,
+        generations: &ChunkGenerationMap,
+        chunk: &dyn BlobChunkInfo,
+    ) -> Result<()> {
+        // Chunks from RAFS metadata have no generation, get it from the blob meta then.
+        let mut generation = chunk.generation();
+        if generation == 0 && self.meta.is_some() {
+            if let Some(meta) = self.get_blob_meta_info()? {
+                generation = meta.get_chunk_generation(chunk.id() as usize);
+            }
+        }
+        let cached = generations.get(chunk.id())?;
+        if cached == generation {
+            return Ok(());
+        }
+
+        if self.chunk_map.is_ready(chunk)? {
+            info!(
+                "invalidate chunk {} of blob {} cached with generation {}, current generation {}",
+                chunk.id(),
+                self.blob_id,
+                cached,
+                generation
+            );
+            self.invalidate_chunk(chunk)?;
+        } else if let Some(tier) = self.memory_tier.as_ref() {
+            tier.remove(&chunk.id());
+        }
+        generations.set(chunk.id(), generation)
+    }
+
+    // Drop cached data of the chunk, so it will be fetched from the backend again.
+    fn invalidate_chunk(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        let (offset, size) = if self.is_raw_data {
+            (chunk.compressed_offset(), chunk.compressed_size() as u64)
+        } else {
+            (
+                cache_data_offset(chunk, self.cache_slot_size),
+                chunk.uncompressed_size() as u64,
+            )
+        };
+        self.evict_chunk(chunk.id(), offset, size)?;
+        if let Some(tier) = self.memory_tier.as_ref() {
+            tier.remove(&chunk.id());
+        }
+        Ok(())
+    }
+
+    /// Fetch all chunks not ready yet into the cache file, blocking until they are ready.
+    ///
+    /// Chunks are fetched in batches of the prefetch merging size, and `cancel` is checked
+    /// between batches. Returns the number of bytes fetched from the backend.
+    pub(crate) fn warm_chunks(
+        &self,
+        chunks: &[Arc<dyn BlobChunkInfo>],
+        cancel: &AtomicBool,
+    ) -> Result<u64> {
+        // Assume data from tar file is always ready.
+        if self.is_tarfs {
+            return Ok(0);
+        }
+        if self.write_state.as_ref().map(|s| s.is_readonly()) == Some(true) {
+            return Err(eio!("cache file is read-only, can't warm the blob cache"));
+        }
+
+        let batch_size = self.prefetch_batch_size();
+        let mut fetched = 0;
+        let mut start = 0;
+        while start < chunks.len() {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(Error::from_raw_os_error(libc::ECANCELED));
+            }
+            let mut size = chunks[start].compressed_size() as u64;
+            let mut end = start + 1;
+            while end < chunks.len() && size + chunks[end].compressed_size() as u64 <= batch_size {
+                size += chunks[end].compressed_size() as u64;
+                end += 1;
+            }
+            fetched += self.fetch_chunks(&chunks[start..end])? as u64;
+            start = end;
+        }
+
+        // Failed backend requests are not reported by `fetch_chunks()`.
+        let missing = chunks
+            .iter()
+            .filter(|c| !matches!(self.chunk_map.is_ready(c.as_ref()), Ok(true)))
+            .count();
+        if missing > 0 {
+            return Err(eio!(format!(
+                "failed to fetch {} chunks of blob {}",
+                missing, self.blob_id
+            )));
+        }
+        Ok(fetched)
+    }
+
+    // Fetch chunks not ready yet from the backend into the cache file, returns bytes fetched.
+    fn fetch_chunks(&self, chunks: &[Arc<dyn BlobChunkInfo>]) -> Result<usize> {
+        self.mark_zero_chunks_ready(chunks.iter().map(|c| c.as_ref()));
+        // Chunks failing to be kept in the cache are reported after handling all chunks, so no
+        // chunk is left pending.
+        let mut cached = Ok(());
+        let mut pending = Vec::with_capacity(chunks.len());
+        if !self.chunk_map.is_persist() {
+            let mut d_size = 0;
+            for c in chunks.iter() {
+                self.check_decompress_size(c.as_ref())?;
+                d_size = std::cmp::max(d_size, c.uncompressed_size() as usize);
+            }
+            let mut buf = alloc_buf(d_size);
+
+            for c in chunks.iter() {
+                if let Ok(true) = self.check_ready_and_mark_pending(c.as_ref()) {
+                    // The chunk is ready, so skip it.
+                    continue;
+                }
+
+                // For digested chunk map, we must check whether the cached data is valid because
+                // the digested chunk map cannot persist readiness state.
+                let d_size = c.uncompressed_size() as usize;
+                match self.read_file_cache(c.as_ref(), &mut buf[0..d_size]) {
+                    // The cached data is valid, set the chunk as ready.
+                    Ok(_v) => {
+                        cached = cached.and(self.update_chunk_pending_status(c.as_ref(), true))
+                    }
+                    // The cached data is invalid, queue the chunk for reading from backend.
+                    Err(_e) => pending.push(c.clone()),
+                }
+            }
+        } else {
+            for c in chunks.iter() {
+                if let Ok(true) = self.check_ready_and_mark_pending(c.as_ref()) {
+                    // The chunk is ready, so skip it.
+                    continue;
+                } else {
+                    pending.push(c.clone());
+                }
+            }
+        }
+
+        let mut total_size = 0;
+        let mut start = 0;
+        while start < pending.len() {
+            // Figure out the range with continuous chunk ids, be careful that `end` is inclusive.
+            let mut end = start;
+            while end < pending.len() - 1 && pending[end + 1].id() == pending[end].id() + 1 {
+                end += 1;
+            }
+
+            let (blob_offset, _blob_end, blob_size) = self.get_blob_range(&pending[start..=end])?;
+            match self.read_chunks_from_backend(blob_offset, blob_size, &pending[start..=end], true)
+            {
+                Ok(mut bufs) => {
+                    total_size += blob_size;
+                    if self.is_raw_data {
+                        let res = Self::persist_cached_data(
+                            &self.file,
+                            &self.write_state,
+                            self.checksum.as_deref(),
+                            blob_offset,
+                            bufs.compressed_buf(),
+                        );
+                        for c in pending.iter().take(end + 1).skip(start) {
+                            cached = cached
+                                .and(self.update_chunk_pending_status(c.as_ref(), res.is_ok()));
+                        }
+                    } else {
+                        for idx in start..=end {
+                            let buf = match bufs.next() {
+                                None => return Err(einval!("invalid chunk decompressed status")),
+                                Some(Err(e)) => {
+                                    for chunk in &mut pending[idx..=end] {
+                                        self.clear_chunk_pending_status(chunk.as_ref());
+                                    }
+                                    return Err(e);
+                                }
+                                Some(Ok(v)) => v,
+                            };
+                            cached =
+                                cached.and(self.persist_chunk_data(pending[idx].as_ref(), &buf));
+                        }
+                    }
+                }
+                Err(_e) => {
+                    // Clear the pending flag for all chunks in processing.
+                    for chunk in &mut pending[start..=end] {
+                        self.clear_chunk_pending_status(chunk.as_ref());
+                    }
+                }
+            }
+
+            start = end + 1;
+        }
+
+        cached?;
+        Ok(total_size)
     }
 
-    fn get_compressed_end(&self, chunk: &Arc<dyn BlobChunkInfo>) -> Result<u64> {
-        let (offset, size) = self.get_compressed_info(chunk)?;
-        Ok(offset + size as u64)
+    // Clear readiness of chunks beyond the end of a truncated cache file, for example when the
+    // daemon has been killed before the cache file was synced.
+    pub(crate) fn repair_truncated_cache(&self, file_size: u64) -> Result<()> {
+        let count = self.blob_info.chunk_count();
+        let chunks = (0..count)
+            .map(|idx| self.get_chunk_info(idx))
+            .collect::<Option<Vec<_>>>();
+        let cleared = match chunks {
+            Some(chunks) => self.clear_truncated_chunks(&chunks, file_size)?,
+            None => {
+                // Chunks can't be located without blob meta, so none of them can be trusted.
+                let range_map = self.chunk_map.as_range_map().ok_or_else(|| enosys!())?;
+                range_map.clear_range_ready(0, count)?;
+                count
+            }
+        };
+        if cleared > 0 {
+            warn!(
+                "cache file of blob {} is truncated to 0x{:x}, clear {} chunks beyond it",
+                self.blob_id, file_size, cleared
+            );
+        }
+        Ok(())
     }
-}
-
-pub(crate) struct FileCacheEntry {
-    pub(crate) blob_id: String,
-    pub(crate) blob_info: Arc<BlobInfo>,
-    pub(crate) cache_cipher_object: Arc<Cipher>,
-    pub(crate) cache_cipher_context: Arc<CipherContext>,
-    pub(crate) chunk_map: Arc<dyn ChunkMap>,
-    pub(crate) file: Arc<File>,
-    pub(crate) meta: Option<FileCacheMeta>,
-    pub(crate) metrics: Arc<BlobcacheMetrics>,
-    pub(crate) prefetch_state: Arc<AtomicU32>,
-    pub(crate) reader: Arc<dyn BlobReader>,
-    pub(crate) runtime: Arc<Runtime>,
-    pub(crate) workers: Arc<AsyncWorkerMgr>,
-
-    pub(crate) blob_compressed_size: u64,
-    pub(crate) blob_uncompressed_size: u64,
-    // Whether `get_blob_object()` is supported.
-    pub(crate) is_get_blob_object_supported: bool,
-    // Cache raw data from backend instead of decompressed/decrypted plaintext.
-    pub(crate) is_raw_data: bool,
-    // The data in cache file is uncompressed and encrypted.
-    pub(crate) is_cache_encrypted: bool,
-    // Whether direct chunkmap is used.
-    pub(crate) is_direct_chunkmap: bool,
-    // The blob is for an stargz image.
-    pub(crate) is_legacy_stargz: bool,
-    // The blob is for an RAFS filesystem in `TARFS` mode.
-    pub(crate) is_tarfs: bool,
-    // The blob contains batch chunks.
-    pub(crate) is_batch: bool,
-    // The blob is based on ZRan decompression algorithm.
-    pub(crate) is_zran: bool,
-    // True if direct IO is enabled for the `self.file`, supported for fscache only.
-    pub(crate) dio_enabled: bool,
-    // Data from the file cache should be validated before use.
-    pub(crate) need_validation: bool,
-    // Amplified user IO request batch size to read data from remote storage backend / local cache.
-    pub(crate) user_io_batch_size: u32,
-    pub(crate) prefetch_config: Arc<AsyncPrefetchConfig>,
-}
 
-impl FileCacheEntry {
-    pub(crate) fn get_blob_size(reader: &Arc<dyn BlobReader>, blob_info: &BlobInfo) -> Result<u64> {
-        // Stargz needs blob size information, so hacky!
-        let size = if blob_info.is_legacy_stargz() {
-            reader.blob_size().map_err(|e| einval!(e))?
+    /// Get status of the blob cache.
+    ///
+    /// Data is materialized in the cache file if all chunks are ready, otherwise only allocated
+    /// space of the cache file is counted because data is never written into holes of it.
+    pub(crate) fn status(&self) -> BlobCacheStatus {
+        let total_size = if self.is_raw_data {
+            self.blob_compressed_size
         } else {
-            blob_info.compressed_size()
+            self.blob_uncompressed_size
+        };
+        let all_ready = self
+            .chunk_map
+            .as_range_map()
+            .map_or(false, |m| m.is_range_all_ready());
+        let materialized_size = if all_ready {
+            total_size
+        } else {
+            self.file
+                .metadata()
+                .map(|m| std::cmp::min(m.blocks() * 512, total_size))
+                .unwrap_or(0)
         };
+        let last_access = self.last_access.load(Ordering::Relaxed);
 
-        Ok(size)
+        BlobCacheStatus {
+            blob_id: self.blob_id.clone(),
+            total_size,
+            materialized_size,
+            last_access: (last_access > 0).then_some(last_access),
+        }
     }
 
-    fn delay_persist_chunk_data(&self, chunk: Arc<dyn BlobChunkInfo>, buffer: Arc<DataBuffer>) {
-        let delayed_chunk_map = self.chunk_map.clone();
-        let file = self.file.clone();
-        let metrics = self.metrics.clone();
-        let is_raw_data = self.is_raw_data;
-        let is_cache_encrypted = self.is_cache_encrypted;
-        let cipher_object = self.cache_cipher_object.clone();
-        let cipher_context = self.cache_cipher_context.clone();
-
-        metrics.buffered_backend_size.add(buffer.size() as u64);
-        self.runtime.spawn_blocking(move || {
-            metrics.buffered_backend_size.sub(buffer.size() as u64);
-            let mut t_buf;
-            let buf = if !is_raw_data && is_cache_encrypted {
-                let (key, iv) = cipher_context.generate_cipher_meta(&chunk.chunk_id().data);
-                let buf = buffer.slice();
-                t_buf = alloc_buf(round_up_usize(buf.len(), ENCRYPTION_PAGE_SIZE));
-
-                let mut pos = 0;
-                while pos < buf.len() {
-                    let mut s_buf;
-                    // Padding to buffer to 4096 bytes if needed.
-                    let buf = if pos + ENCRYPTION_PAGE_SIZE > buf.len() {
-                        s_buf = buf[pos..].to_vec();
-                        s_buf.resize(ENCRYPTION_PAGE_SIZE, 0);
-                        &s_buf
-                    } else {
-                        &buf[pos..pos + ENCRYPTION_PAGE_SIZE]
-                    };
-
-                    assert_eq!(buf.len(), ENCRYPTION_PAGE_SIZE);
-                    match cipher_object.encrypt(key, Some(&iv), buf) {
-                        Ok(buf2) => {
-                            assert_eq!(buf2.len(), ENCRYPTION_PAGE_SIZE);
-                            t_buf[pos..pos + ENCRYPTION_PAGE_SIZE].copy_from_slice(buf2.as_ref());
-                            pos += ENCRYPTION_PAGE_SIZE;
-                        }
-                        Err(_) => {
-                            Self::_update_chunk_pending_status(
-                                &delayed_chunk_map,
-                                chunk.as_ref(),
-                                false,
-                            );
-                            return;
-                        }
-                    }
-                }
-                &t_buf
+    /// Clear readiness of chunks with data beyond `file_size` in the cache file.
+    ///
+    /// Returns the number of cleared chunks, which will be fetched from the backend again.
+    pub(crate) fn clear_truncated_chunks(
+        &self,
+        chunks: &[Arc<dyn BlobChunkInfo>],
+        file_size: u64,
+    ) -> Result<u32> {
+        let range_map = self.chunk_map.as_range_map().ok_or_else(|| enosys!())?;
+        let mut cleared = 0;
+        for chunk in chunks {
+            let end = if self.is_raw_data {
+                chunk.compressed_offset() + chunk.compressed_size() as u64
             } else {
-                buffer.slice()
+                cache_data_offset(chunk.as_ref(), self.cache_slot_size)
+                    + chunk.uncompressed_size() as u64
             };
+            if end > file_size && self.chunk_map.is_ready(chunk.as_ref())? {
+                range_map.clear_range_ready(chunk.id(), 1)?;
+                if let Some(tier) = self.memory_tier.as_ref() {
+                    tier.remove(&chunk.id());
+                }
+                cleared += 1;
+            }
+        }
+        Ok(cleared)
+    }
 
-            let offset = if is_raw_data {
-                chunk.compressed_offset()
+    /// Re-validate data of chunks ready in the cache file by their digests.
+    pub(crate) fn verify_chunks(
+        &self,
+        chunks: &[Arc<dyn BlobChunkInfo>],
+        clear_corrupted: bool,
+    ) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        for chunk in chunks {
+            if !self.chunk_map.is_ready(chunk.as_ref())? {
+                continue;
+            }
+            let mut buf = alloc_buf(chunk.uncompressed_size() as usize);
+            let result = self
+                .read_file_cache_data(chunk.as_ref(), &mut buf)
+                .and_then(|_| self.validate_chunk_data(chunk.as_ref(), &buf, true));
+            if let Err(e) = result {
+                warn!(
+                    "cached data of chunk {} of blob {} is corrupted, {}",
+                    chunk.id(),
+                    self.blob_id,
+                    e
+                );
+                report.corrupted += 1;
+                report.corrupted_chunks.push(chunk.id());
+                if clear_corrupted {
+                    self.invalidate_chunk(chunk.as_ref())?;
+                }
             } else {
-                chunk.uncompressed_offset()
-            };
-            let res = Self::persist_cached_data(&file, offset, buf);
-            Self::_update_chunk_pending_status(&delayed_chunk_map, chunk.as_ref(), res.is_ok());
-        });
+                report.good += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Check the whole-file checksum saved when the cache file was closed last time, and verify
+    /// cached chunks one by one if it doesn't match data of the cache file.
+    pub(crate) fn check_cache_file(&self) -> Result<()> {
+        let checksum = match self.checksum.as_ref() {
+            Some(v) => v,
+            None => return Ok(()),
+        };
+        let value = CacheFileChecksum::compute_file(&self.file)?;
+        let saved = checksum.take_saved();
+        checksum.reset(value);
+        if saved == Some(value) {
+            return Ok(());
+        }
+
+        info!(
+            "checksum of cache file of blob {} doesn't match, verify cached chunks",
+            self.blob_id
+        );
+        match self.verify_cache(true) {
+            Ok(report) if report.corrupted > 0 => warn!(
+                "clear {} corrupted chunks of blob {}",
+                report.corrupted, self.blob_id
+            ),
+            Ok(_) => {}
+            Err(e) => warn!(
+                "failed to verify cached chunks of blob {}, {}",
+                self.blob_id, e
+            ),
+        }
+        Ok(())
     }
 
-    fn persist_chunk_data(&self, chunk: &dyn BlobChunkInfo, buf: &[u8]) {
-        let offset = chunk.uncompressed_offset();
-        let res = Self::persist_cached_data(&self.file, offset, buf);
-        self.update_chunk_pending_status(chunk, res.is_ok());
+    fn persist_cached_data(
+        file: &Arc<File>,
+        write_state: &Option<Arc<CacheWriteState>>,
+        checksum: Option<&CacheFileChecksum>,
+        offset: u64,
+        buffer: &[u8],
+    ) -> Result<()> {
+        // Skip writing to the cache file in read-only passthrough mode.
+        if write_state.as_ref().map(|s| s.is_readonly()) == Some(true) {
+            return Err(Error::from_raw_os_error(libc::EROFS));
+        }
+        // Data to be replaced is needed to update the whole-file checksum incrementally.
+        let old = checksum.map(|checksum| {
+            CacheFileChecksum::compute(file, offset, buffer.len() as u64).map_err(|e| {
+                warn!("failed to compute checksum of cache file, {}", e);
+                checksum.invalidate();
+            })
+        });
+        let res = match write_state {
+            Some(state) => Self::write_cached_data(file, offset, buffer).map_err(|e| {
+                state.check_write_error(&e);
+                e
+            }),
+            None => Self::write_cached_data(file, offset, buffer),
+        };
+        if let Some(checksum) = checksum {
+            match (&res, old) {
+                (Ok(_), Some(Ok(old))) => checksum.replace(old, offset, buffer),
+                // Data may be partially written.
+                (Err(_), _) => checksum.invalidate(),
+                _ => {}
+            }
+        }
+        res
     }
 
-    fn persist_cached_data(file: &Arc<File>, offset: u64, buffer: &[u8]) -> Result<()> {
+    fn write_cached_data(file: &Arc<File>, offset: u64, buffer: &[u8]) -> Result<()> {
         let fd = file.as_raw_fd();
 
         let n = loop {
@@ -326,37 +1606,144 @@ impl FileCacheEntry {
         }
     }
 
-    fn update_chunk_pending_status(&self, chunk: &dyn BlobChunkInfo, success: bool) {
-        Self::_update_chunk_pending_status(&self.chunk_map, chunk, success)
+    // Mark all-zero chunks ready without fetching them from the backend. Data is never written
+    // into the cache file for them, so they are read as zeros from holes of the cache file.
+    fn mark_zero_chunks_ready<'a>(&self, chunks: impl Iterator<Item = &'a dyn BlobChunkInfo>) {
+        if !self.zero_chunks || self.write_state.as_ref().map(|s| s.is_readonly()) == Some(true) {
+            return;
+        }
+        let digester = self.blob_digester();
+        for chunk in chunks {
+            if is_zero_chunk_digest(chunk.chunk_id(), chunk.uncompressed_size(), digester)
+                && matches!(self.check_ready_and_mark_pending(chunk), Ok(false))
+            {
+                // Zero chunks take no space in the cache file, so they are not accounted for
+                // eviction.
+                if let Err(e) = self.chunk_map.set_ready_and_clear_pending(chunk) {
+                    warn!("failed to mark zero chunk {} ready, {:?}", chunk.id(), e);
+                    self.chunk_map.clear_pending(chunk);
+                }
+            }
+        }
+    }
+
+    // Mark the chunk as ready if its data has been cached successfully, otherwise clear its
+    // pending state. Errors of keeping the cached chunk within the cache size limit are returned
+    // after updating the state.
+    fn update_chunk_pending_status(&self, chunk: &dyn BlobChunkInfo, success: bool) -> Result<()> {
+        Self::_update_chunk_pending_status(&self.chunk_map, &self.eviction, chunk, success)
     }
 
     fn _update_chunk_pending_status(
         chunk_map: &Arc<dyn ChunkMap>,
+        eviction: &Option<BlobEvictionHandle>,
         chunk: &dyn BlobChunkInfo,
         success: bool,
-    ) {
-        if success {
-            if let Err(e) = chunk_map.set_ready_and_clear_pending(chunk) {
-                error!(
-                    "Failed change caching state for chunk of offset {}, {:?}",
-                    chunk.compressed_offset(),
-                    e
-                )
-            }
-        } else {
+    ) -> Result<()> {
+        if !success {
+            Self::_clear_chunk_pending_status(chunk_map, chunk);
+        } else if let Err(e) = chunk_map.set_ready_and_clear_pending(chunk) {
             error!(
-                "Failed to persist data for chunk at offset {}",
-                chunk.compressed_offset()
-            );
-            chunk_map.clear_pending(chunk);
+                "Failed change caching state for chunk of offset {}, {:?}",
+                chunk.compressed_offset(),
+                e
+            )
+        } else if let Some(eviction) = eviction {
+            // The chunk gets evicted again if the size limit can't be met because of pinned chunks.
+            eviction.on_access(chunk)?;
         }
+        Ok(())
+    }
+
+    // Clear the pending state of a chunk failed to be fetched or cached.
+    fn clear_chunk_pending_status(&self, chunk: &dyn BlobChunkInfo) {
+        Self::_clear_chunk_pending_status(&self.chunk_map, chunk)
+    }
+
+    fn _clear_chunk_pending_status(chunk_map: &Arc<dyn ChunkMap>, chunk: &dyn BlobChunkInfo) {
+        error!(
+            "Failed to persist data for chunk at offset {}",
+            chunk.compressed_offset()
+        );
+        chunk_map.clear_pending(chunk);
     }
 
     fn prefetch_batch_size(&self) -> u64 {
-        if self.prefetch_config.batch_size < 0x2_0000 {
-            0x2_0000
-        } else {
-            self.prefetch_config.batch_size as u64
+        let batch_size = match self.prefetch_merging_size.load(Ordering::Relaxed) {
+            0 => self.prefetch_config.batch_size as u64,
+            v => v,
+        };
+        std::cmp::max(batch_size, 0x2_0000)
+    }
+
+    // Split compressed data range `[offset, offset + len)` into ranges allowed to be prefetched,
+    // excluding chunks denied by `prefetch_deny`.
+    fn prefetch_allowed_ranges(&self, offset: u64, len: u64) -> Vec<(u64, u64)> {
+        let mut ranges = vec![(offset, offset + len)];
+        let chunk_count = self.blob_info.chunk_count();
+        for denied in self.prefetch_deny.iter() {
+            if denied.start == 0 && denied.end >= chunk_count {
+                return Vec::new();
+            }
+            let end = std::cmp::min(denied.end, chunk_count);
+            if denied.start >= end {
+                continue;
+            }
+            // Denied chunks can't be located without blob meta.
+            let (first, last) = match (
+                self.get_chunk_info(denied.start),
+                self.get_chunk_info(end - 1),
+            ) {
+                (Some(first), Some(last)) => (first, last),
+                _ => continue,
+            };
+            let (start, end) = (first.compressed_offset(), last.compressed_end());
+            let mut allowed = Vec::with_capacity(ranges.len() + 1);
+            for (s, e) in ranges {
+                if end <= s || start >= e {
+                    allowed.push((s, e));
+                    continue;
+                }
+                if s < start {
+                    allowed.push((s, start));
+                }
+                if end < e {
+                    allowed.push((end, e));
+                }
+            }
+            ranges = allowed;
+        }
+        ranges
+    }
+
+    // Reorder merged prefetch requests for chunks requested by `bios` according to the prefetch
+    // strategy, they are in the order of the blob layout by default.
+    fn sort_prefetch_requests(&self, bios: &[BlobIoDesc], requests: &mut [BlobIoRange]) {
+        let strategy = self.prefetch_config.strategy;
+        if strategy == PrefetchStrategy::BlobLayout {
+            return;
+        }
+
+        // Position of the first request for each chunk, and the most urgent kind of data in it.
+        let mut chunks: HashMap<u32, (usize, BlobIoKind)> = HashMap::new();
+        for (idx, bio) in bios.iter().enumerate() {
+            let v = chunks.entry(bio.chunkinfo.id()).or_insert((idx, bio.kind));
+            v.1 = std::cmp::min(v.1, bio.kind);
+        }
+        let keys = |req: &BlobIoRange| {
+            req.chunks
+                .iter()
+                .filter_map(|c| chunks.get(&c.id()).copied())
+                .collect::<Vec<_>>()
+        };
+        match strategy {
+            PrefetchStrategy::AccessTrace => {
+                requests.sort_by_cached_key(|req| keys(req).into_iter().map(|v| v.0).min())
+            }
+            PrefetchStrategy::BootOptimized => {
+                requests.sort_by_cached_key(|req| keys(req).into_iter().map(|v| v.1).min())
+            }
+            PrefetchStrategy::BlobLayout => {}
         }
     }
 
@@ -384,6 +1771,39 @@ impl FileCacheEntry {
         }
     }
 
+    // Drop extended chunks which would make the request exceed the read amplification limit.
+    fn limit_extended_chunks(
+        &self,
+        region: &Region,
+        chunks: Vec<Arc<dyn BlobChunkInfo>>,
+    ) -> Result<Vec<Arc<dyn BlobChunkInfo>>> {
+        let size = region.blob_len as u64;
+        let mut limit = u64::MAX;
+        if self.read_amplification_limit != 0 {
+            limit = std::cmp::max(size * self.read_amplification_limit as u64 / 100, size);
+        }
+        if self.max_user_merge_size != 0 {
+            limit = std::cmp::min(limit, std::cmp::max(self.max_user_merge_size, size));
+        }
+        if limit == u64::MAX {
+            return Ok(chunks);
+        }
+
+        let end = region.blob_address + limit;
+        let mut blob_cci = BlobCCI::new();
+        let mut result = Vec::with_capacity(chunks.len());
+        for c in chunks {
+            if c.is_batch() && blob_cci.is_none() {
+                blob_cci.set_meta(self.get_blob_meta_info()?)?;
+            }
+            if blob_cci.get_compressed_end(&c)? <= end {
+                result.push(c);
+            }
+        }
+
+        Ok(result)
+    }
+
     fn strip_ready_chunks(
         &self,
         meta: Arc<BlobCompressionContextInfo>,
@@ -554,6 +1974,10 @@ impl BlobCache for FileCacheEntry {
         self.is_legacy_stargz
     }
 
+    fn max_decompress_size(&self) -> u64 {
+        self.max_decompress_size
+    }
+
     fn is_batch(&self) -> bool {
         self.is_batch
     }
@@ -566,10 +1990,42 @@ impl BlobCache for FileCacheEntry {
         self.need_validation
     }
 
+    fn strict_validation(&self) -> bool {
+        self.strict_validation
+    }
+
+    fn report_no_digest_chunk(&self, chunk: &dyn BlobChunkInfo) {
+        if !self.no_digest_reported.swap(true, Ordering::Relaxed) {
+            warn!(
+                "chunk {} of blob {} has no digest, data of the blob can't be fully validated",
+                chunk.id(),
+                self.blob_id
+            );
+        }
+    }
+
+    fn digest_mismatch_policy(&self) -> DigestMismatchPolicy {
+        self.digest_mismatch_policy
+    }
+
+    fn report_digest_mismatch_served(&self, chunk: &dyn BlobChunkInfo) {
+        self.metrics.digest_mismatch_served.inc();
+        warn!(
+            "data of chunk {} of blob {} doesn't match its digest, serve it anyway",
+            chunk.id(),
+            self.blob_id
+        );
+    }
+
     fn reader(&self) -> &dyn BlobReader {
         &*self.reader
     }
 
+    #[cfg(feature = "backend-async")]
+    fn shared_reader(&self) -> Arc<dyn BlobReader> {
+        self.reader.clone()
+    }
+
     fn get_chunk_map(&self) -> &Arc<dyn ChunkMap> {
         &self.chunk_map
     }
@@ -581,6 +2037,62 @@ impl BlobCache for FileCacheEntry {
             .map(|v| BlobMetaChunk::new(chunk_index as usize, &v.state))
     }
 
+    fn prefetch_progress(&self) -> Option<&PrefetchProgress> {
+        Some(&self.prefetch_progress)
+    }
+
+    fn is_range_cached(&self, bios: &[BlobIoDesc]) -> bool {
+        // Data of tarfs blobs is always available in the local blob file.
+        self.is_tarfs
+            || bios
+                .iter()
+                .all(|bio| matches!(self.chunk_map.is_ready(&bio.chunkinfo), Ok(true)))
+    }
+
+    fn pin_chunks(&self, chunks: &[u32]) -> Result<()> {
+        self.check_chunk_indexes(chunks)?;
+        // Chunks are never evicted if eviction isn't enabled.
+        if let Some(eviction) = self.eviction.as_ref() {
+            eviction.pin_chunks(chunks);
+        }
+        Ok(())
+    }
+
+    fn unpin_chunks(&self, chunks: &[u32]) -> Result<()> {
+        self.check_chunk_indexes(chunks)?;
+        if let Some(eviction) = self.eviction.as_ref() {
+            eviction.unpin_chunks(chunks);
+        }
+        Ok(())
+    }
+
+    fn verify_cache(&self, clear_corrupted: bool) -> Result<VerifyReport> {
+        if self.is_raw_data && (self.is_zran || self.is_batch) {
+            return Err(enosys!("can't verify cached data of ZRan or batch chunks"));
+        }
+        let chunks = (0..self.blob_info.chunk_count())
+            .map(|idx| {
+                self.get_chunk_info(idx)
+                    .ok_or_else(|| einval!("blob meta is needed to verify cached data"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.verify_chunks(&chunks, clear_corrupted)
+    }
+
+    fn get_truncated_blob_size(&self) -> Option<u64> {
+        match self.truncated_blob_size.load(Ordering::Acquire) {
+            u64::MAX => None,
+            v => Some(v),
+        }
+    }
+
+    fn set_truncated_blob_size(&self, blob_size: u64) {
+        if self.degrade_truncated_blob {
+            self.truncated_blob_size
+                .fetch_min(blob_size, Ordering::AcqRel);
+        }
+    }
+
     fn get_blob_object(&self) -> Option<&dyn BlobObject> {
         if self.is_get_blob_object_supported {
             Some(self)
@@ -589,6 +2101,34 @@ impl BlobCache for FileCacheEntry {
         }
     }
 
+    fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    fn access_heatmap(&self) -> Vec<u32> {
+        match self.access_heatmap.as_ref() {
+            Some(heatmap) => heatmap.snapshot(),
+            None => Vec::new(),
+        }
+    }
+
+    fn parallel_decompress(&self) -> (u32, u32) {
+        (
+            self.parallel_decompress_threshold,
+            self.parallel_decompress_threads,
+        )
+    }
+
+    fn reset_access_heatmap(&self) {
+        if let Some(heatmap) = self.access_heatmap.as_ref() {
+            heatmap.reset();
+        }
+    }
+
+    fn metrics(&self) -> CacheMetrics {
+        self.cache_metrics.clone()
+    }
+
     fn start_prefetch(&self) -> StorageResult<()> {
         self.prefetch_state.fetch_add(1, Ordering::Release);
         Ok(())
@@ -611,6 +2151,7 @@ impl BlobCache for FileCacheEntry {
             }
             if val == 0 || val == 1 {
                 self.workers.flush_pending_prefetch_requests(&self.blob_id);
+                self.flush_chunk_map_batch();
                 return Ok(());
             }
         }
@@ -626,137 +2167,144 @@ impl BlobCache for FileCacheEntry {
         prefetches: &[BlobPrefetchRequest],
         bios: &[BlobIoDesc],
     ) -> StorageResult<usize> {
+        // Prefetched data can't be cached in read-only passthrough mode.
+        if self.write_state.as_ref().map(|s| s.is_readonly()) == Some(true) {
+            return Ok(0);
+        }
+
         // Handle blob prefetch request first, it may help performance.
-        for req in prefetches {
-            let msg = AsyncPrefetchMessage::new_blob_prefetch(
-                blob_cache.clone(),
-                req.offset as u64,
-                req.len as u64,
-            );
-            let _ = self.workers.send_prefetch_message(msg);
+        // Account all requests before queueing any, so progress can't reach the total early.
+        let ranges = prefetches
+            .iter()
+            .map(|req| self.prefetch_allowed_ranges(req.offset, req.len))
+            .collect::<Vec<_>>();
+        let blob_size: u64 = ranges.iter().flatten().map(|(s, e)| e - s).sum();
+        self.prefetch_progress.add_total(blob_size);
+        for (req, ranges) in prefetches.iter().zip(ranges) {
+            if let Some(size) = req.merging_size {
+                self.prefetch_merging_size.store(size, Ordering::Relaxed);
+            }
+            if let Some(rate) = req.bandwidth_rate {
+                self.workers.set_blob_bandwidth_limit(&self.blob_id, rate);
+            }
+            for (start, end) in ranges {
+                let msg =
+                    AsyncPrefetchMessage::new_blob_prefetch(blob_cache.clone(), start, end - start);
+                if self.workers.send_prefetch_message(msg).is_err() {
+                    self.prefetch_progress.complete(end - start);
+                }
+            }
         }
 
         // Then handle fs prefetch
         let max_comp_size = self.prefetch_batch_size();
-        let mut bios = bios.to_vec();
-        bios.sort_by_key(|entry| entry.chunkinfo.compressed_offset());
-        self.metrics.prefetch_unmerged_chunks.add(bios.len() as u64);
+        let mut sorted_bios = bios
+            .iter()
+            .filter(|bio| !is_prefetch_denied(&self.prefetch_deny, bio.chunkinfo.id()))
+            .cloned()
+            .collect::<Vec<_>>();
+        sorted_bios.sort_by_key(|entry| entry.chunkinfo.compressed_offset());
+        self.metrics
+            .prefetch_unmerged_chunks
+            .add(sorted_bios.len() as u64);
+        let mut requests = Vec::new();
         BlobIoMergeState::merge_and_issue(
-            &bios,
+            &sorted_bios,
             max_comp_size,
             max_comp_size as u64 >> RAFS_BATCH_SIZE_TO_GAP_SHIFT,
-            |req: BlobIoRange| {
-                let msg = AsyncPrefetchMessage::new_fs_prefetch(blob_cache.clone(), req);
-                let _ = self.workers.send_prefetch_message(msg);
-            },
+            self.read_amplification_limit,
+            |req: BlobIoRange| requests.push(req),
         );
+        self.sort_prefetch_requests(bios, &mut requests);
+        let fs_size: u64 = requests.iter().map(|req| req.blob_size).sum();
+        self.prefetch_progress.add_total(fs_size);
+        for req in requests {
+            let size = req.blob_size;
+            let msg = AsyncPrefetchMessage::new_fs_prefetch(blob_cache.clone(), req);
+            if self.workers.send_prefetch_message(msg).is_err() {
+                self.prefetch_progress.complete(size);
+            }
+        }
 
-        Ok(0)
+        Ok((blob_size + fs_size) as usize)
     }
 
     fn prefetch_range(&self, range: &BlobIoRange) -> Result<usize> {
-        let mut pending = Vec::with_capacity(range.chunks.len());
-        if !self.chunk_map.is_persist() {
-            let mut d_size = 0;
-            for c in range.chunks.iter() {
-                d_size = std::cmp::max(d_size, c.uncompressed_size() as usize);
-            }
-            let mut buf = alloc_buf(d_size);
+        self.fetch_chunks(&range.chunks)
+    }
 
-            for c in range.chunks.iter() {
-                if let Ok(true) = self.chunk_map.check_ready_and_mark_pending(c.as_ref()) {
-                    // The chunk is ready, so skip it.
-                    continue;
-                }
+    fn warm(&self, cancel: &AtomicBool) -> Result<u64> {
+        let chunks = (0..self.blob_info.chunk_count())
+            .map(|idx| {
+                self.get_chunk_info(idx)
+                    .ok_or_else(|| einval!("blob meta is needed to warm the blob cache"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.warm_chunks(&chunks, cancel)
+    }
 
-                // For digested chunk map, we must check whether the cached data is valid because
-                // the digested chunk map cannot persist readiness state.
-                let d_size = c.uncompressed_size() as usize;
-                match self.read_file_cache(c.as_ref(), &mut buf[0..d_size]) {
-                    // The cached data is valid, set the chunk as ready.
-                    Ok(_v) => self.update_chunk_pending_status(c.as_ref(), true),
-                    // The cached data is invalid, queue the chunk for reading from backend.
-                    Err(_e) => pending.push(c.clone()),
-                }
-            }
-        } else {
-            for c in range.chunks.iter() {
-                if let Ok(true) = self.chunk_map.check_ready_and_mark_pending(c.as_ref()) {
-                    // The chunk is ready, so skip it.
-                    continue;
-                } else {
-                    pending.push(c.clone());
-                }
-            }
+    fn read_with_stats(
+        &self,
+        iovec: &mut BlobIoVec,
+        buffers: &[FileVolatileSlice],
+    ) -> Result<ReadStats> {
+        if let Some(stats) = read_out_of_bounds_chunks(
+            self,
+            self.zero_out_of_bounds_chunks,
+            iovec,
+            buffers,
+            |iovec, buffers| self.read_with_stats(iovec, buffers),
+        )? {
+            return Ok(stats);
         }
+        if let Ok(now) = SystemTime::now().duration_since(UNIX_EPOCH) {
+            self.last_access.store(now.as_secs(), Ordering::Relaxed);
+        }
+        self.mark_zero_chunks_ready(
+            iovec
+                .bi_vec
+                .iter()
+                .map(|bio| &bio.chunkinfo as &dyn BlobChunkInfo),
+        );
 
-        let mut total_size = 0;
-        let mut start = 0;
-        while start < pending.len() {
-            // Figure out the range with continuous chunk ids, be careful that `end` is inclusive.
-            let mut end = start;
-            while end < pending.len() - 1 && pending[end + 1].id() == pending[end].id() + 1 {
-                end += 1;
-            }
-
-            let (blob_offset, _blob_end, blob_size) = self.get_blob_range(&pending[start..=end])?;
-            match self.read_chunks_from_backend(blob_offset, blob_size, &pending[start..=end], true)
-            {
-                Ok(mut bufs) => {
-                    total_size += blob_size;
-                    if self.is_raw_data {
-                        let res = Self::persist_cached_data(
-                            &self.file,
-                            blob_offset,
-                            bufs.compressed_buf(),
-                        );
-                        for c in pending.iter().take(end + 1).skip(start) {
-                            self.update_chunk_pending_status(c.as_ref(), res.is_ok());
-                        }
-                    } else {
-                        for idx in start..=end {
-                            let buf = match bufs.next() {
-                                None => return Err(einval!("invalid chunk decompressed status")),
-                                Some(Err(e)) => {
-                                    for chunk in &mut pending[idx..=end] {
-                                        self.update_chunk_pending_status(chunk.as_ref(), false);
-                                    }
-                                    return Err(e);
-                                }
-                                Some(Ok(v)) => v,
-                            };
-                            self.persist_chunk_data(pending[idx].as_ref(), &buf);
-                        }
-                    }
-                }
-                Err(_e) => {
-                    // Clear the pending flag for all chunks in processing.
-                    for chunk in &mut pending[start..=end] {
-                        self.update_chunk_pending_status(chunk.as_ref(), false);
-                    }
+        // Check chunks before reading, to tell data in the cache from data to be fetched.
+        // Persistent chunk maps are lock free, so only query other chunk maps in batch to avoid
+        // taking the lock for each chunk.
+        let ready = if !self.is_tarfs && iovec.len() > 1 && !self.chunk_map.is_persist() {
+            let chunks = iovec
+                .bi_vec
+                .iter()
+                .map(|bio| &bio.chunkinfo as &dyn BlobChunkInfo)
+                .collect::<Vec<_>>();
+            self.chunk_map.are_chunks_ready(&chunks).ok()
+        } else {
+            None
+        };
+        let mut cached_size = 0usize;
+        let mut fetched = HashSet::new();
+        for (idx, bio) in iovec.bi_vec.iter().enumerate() {
+            let is_ready = self.is_tarfs
+                || match ready.as_ref() {
+                    Some(ready) => ready[idx],
+                    None => matches!(self.chunk_map.is_ready(&bio.chunkinfo), Ok(true)),
+                };
+            if is_ready {
+                if bio.user_io {
+                    cached_size += bio.size as usize;
                 }
+            } else {
+                fetched.insert(bio.chunkinfo.id());
             }
-
-            start = end + 1;
         }
 
-        Ok(total_size)
-    }
-
-    fn read(&self, iovec: &mut BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize> {
-        self.metrics.total.inc();
-        self.workers.consume_prefetch_budget(iovec.size());
-
-        if iovec.is_empty() {
-            Ok(0)
-        } else if iovec.len() == 1 {
-            let mut state = FileIoMergeState::new();
-            let mut cursor = MemSliceCursor::new(buffers);
-            let req = BlobIoRange::new(&iovec.bi_vec[0], 1);
-            self.dispatch_one_range(&req, &mut cursor, &mut state)
-        } else {
-            self.read_iter(&mut iovec.bi_vec, buffers)
-        }
+        let total = self.read_iovec(iovec, buffers)?;
+        let from_cache = std::cmp::min(cached_size, total);
+        Ok(ReadStats {
+            total,
+            from_cache,
+            from_backend: total - from_cache,
+            chunks_fetched: fetched.len(),
+        })
     }
 
     fn get_blob_meta_info(&self) -> Result<Option<Arc<BlobCompressionContextInfo>>> {
@@ -863,6 +2411,60 @@ impl BlobObject for FileCacheEntry {
 }
 
 impl FileCacheEntry {
+    fn check_chunk_indexes(&self, chunks: &[u32]) -> Result<()> {
+        let count = self.blob_info.chunk_count();
+        match chunks.iter().find(|idx| **idx >= count) {
+            Some(idx) => Err(einval!(format!(
+                "chunk index {} exceeds chunk count {} of blob {}",
+                idx, count, self.blob_id
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    // Fetch chunks following `iovec` into the cache in background if it's sequential user IO.
+    fn readahead(&self, iovec: &BlobIoVec) {
+        // Requests are dropped by prefetch workers if prefetch isn't active.
+        let readahead = match self.readahead.as_ref() {
+            Some(v) if self.is_prefetch_active() => v,
+            _ => return,
+        };
+        let (first, last) = match (iovec.bi_vec.first(), iovec.bi_vec.last()) {
+            (Some(first), Some(last)) => (first.chunkinfo.id(), last.chunkinfo.id()),
+            _ => return,
+        };
+        let range = match readahead.advance(first, last, self.blob_info.chunk_count()) {
+            Some(v) => v,
+            None => return,
+        };
+        let cache = match readahead.cache.lock().unwrap().upgrade() {
+            Some(v) => v,
+            None => return,
+        };
+
+        let mut bios = Vec::with_capacity(range.len());
+        for idx in range {
+            // Chunks can't be located without blob meta.
+            let chunk = match self.get_chunk_info(idx) {
+                Some(v) => v,
+                None => return,
+            };
+            if !self.chunk_map.is_ready(chunk.as_ref()).unwrap_or(false) {
+                let size = chunk.uncompressed_size();
+                bios.push(BlobIoDesc::new(
+                    self.blob_info.clone(),
+                    BlobIoChunk::from(chunk),
+                    0,
+                    size,
+                    false,
+                ));
+            }
+        }
+        if !bios.is_empty() {
+            let _ = self.prefetch(cache, &[], &bios);
+        }
+    }
+
     fn do_fetch_chunks(&self, chunks: &[Arc<dyn BlobChunkInfo>], prefetch: bool) -> Result<()> {
         // Validate input parameters.
         assert!(!chunks.is_empty());
@@ -891,6 +2493,9 @@ impl FileCacheEntry {
             (start as usize, end as usize)
         };
 
+        // Chunks failing to be kept in the cache are reported after handling all chunks, so no
+        // chunk is left pending.
+        let mut cached = Ok(());
         if start_idx <= end_idx {
             let start_chunk = &chunks[start_idx];
             let end_chunk = &chunks[end_idx];
@@ -915,12 +2520,17 @@ impl FileCacheEntry {
                     if self.is_raw_data {
                         let res = Self::persist_cached_data(
                             &self.file,
+                            &self.write_state,
+                            self.checksum.as_deref(),
                             blob_offset,
                             bufs.compressed_buf(),
                         );
                         for idx in start_idx..=end_idx {
                             if status[idx] {
-                                self.update_chunk_pending_status(chunks[idx].as_ref(), res.is_ok());
+                                cached = cached.and(self.update_chunk_pending_status(
+                                    chunks[idx].as_ref(),
+                                    res.is_ok(),
+                                ));
                             }
                         }
                     } else {
@@ -942,7 +2552,21 @@ impl FileCacheEntry {
                                 if self.dio_enabled {
                                     self.adjust_buffer_for_dio(&mut buf)
                                 }
-                                self.persist_chunk_data(chunks[idx].as_ref(), buf.as_ref());
+                                match self.chunk_map_batch.as_ref() {
+                                    Some(batch) if prefetch => {
+                                        self.persist_chunk_data_batched(
+                                            batch,
+                                            chunks[idx].as_ref(),
+                                            buf.as_ref(),
+                                        );
+                                    }
+                                    _ => {
+                                        cached = cached.and(self.persist_chunk_data(
+                                            chunks[idx].as_ref(),
+                                            buf.as_ref(),
+                                        ));
+                                    }
+                                }
                             }
                         }
                     }
@@ -958,7 +2582,19 @@ impl FileCacheEntry {
             }
         }
 
-        if !bitmap.wait_for_range_ready(chunk_index, count)? {
+        cached?;
+
+        if prefetch && self.chunk_map_batch.is_some() && !self.is_raw_data {
+            // Chunks fetched by ourselves are committed in batch, only wait for the others.
+            for (idx, chunk) in chunks.iter().enumerate() {
+                if !status[idx] && !self.wait_for_chunks_ready(bitmap, chunk.id(), 1)? {
+                    return Err(eio!(format!(
+                        "failed to prefetch data from storage backend for chunk {}",
+                        chunk.id()
+                    )));
+                }
+            }
+        } else if !self.wait_for_chunks_ready(bitmap, chunk_index, count)? {
             if prefetch {
                 return Err(eio!(format!(
                     "failed to prefetch data from storage backend for chunk {}/{}",
@@ -968,21 +2604,22 @@ impl FileCacheEntry {
 
             // if we are in on-demand path, retry for the timeout chunks
             for chunk in chunks {
-                match self.chunk_map.check_ready_and_mark_pending(chunk.as_ref()) {
+                match self.check_ready_and_mark_pending(chunk.as_ref()) {
                     Err(e) => return Err(eio!(format!("do_fetch_chunks failed, {:?}", e))),
                     Ok(true) => {}
                     Ok(false) => {
                         info!("retry for timeout chunk, {}", chunk.id());
+                        self.check_decompress_size(chunk.as_ref())?;
                         let mut buf = alloc_buf(chunk.uncompressed_size() as usize);
                         self.read_chunk_from_backend(chunk.as_ref(), &mut buf)
                             .map_err(|e| {
-                                self.update_chunk_pending_status(chunk.as_ref(), false);
+                                self.clear_chunk_pending_status(chunk.as_ref());
                                 eio!(format!("read_raw_chunk failed, {:?}", e))
                             })?;
                         if self.dio_enabled {
                             self.adjust_buffer_for_dio(&mut buf)
                         }
-                        self.persist_chunk_data(chunk.as_ref(), &buf);
+                        self.persist_chunk_data(chunk.as_ref(), &buf)?;
                     }
                 }
             }
@@ -1001,6 +2638,115 @@ impl FileCacheEntry {
 }
 
 impl FileCacheEntry {
+    // Read data of `iovec` into `buffers`, from the cache or the storage backend.
+    fn read_iovec(&self, iovec: &mut BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize> {
+        self.metrics.total.inc();
+        if let Some(state) = self.blob_toc.as_ref() {
+            state.get_or_validate(&self.blob_info)?;
+        }
+        self.workers.consume_prefetch_budget(iovec.size());
+        if let Some(heatmap) = self.access_heatmap.as_ref() {
+            for bio in iovec.bi_vec.iter() {
+                heatmap.record(bio.chunkinfo.id());
+            }
+        }
+        self.readahead(iovec);
+        if let Some(generations) = self.chunk_generations.as_ref() {
+            for bio in iovec.bi_vec.iter() {
+                self.check_chunk_generation(generations, &bio.chunkinfo)?;
+            }
+        }
+
+        iovec.set_data_compressor(None);
+        if iovec.accept_compressed() {
+            if let Some(size) = self.read_compressed(iovec, buffers)? {
+                iovec.set_data_compressor(Some(self.blob_compressor()));
+                return Ok(size);
+            }
+        }
+
+        if let Some(size) = self.read_cache_contiguous(iovec, buffers)? {
+            return Ok(size);
+        }
+
+        if iovec.is_empty() {
+            Ok(0)
+        } else if iovec.len() == 1 {
+            let mut state = FileIoMergeState::new();
+            let mut cursor = MemSliceCursor::new(buffers);
+            let req = BlobIoRange::new(&iovec.bi_vec[0], 1);
+            self.dispatch_one_range(&req, &mut cursor, &mut state)
+        } else {
+            self.read_iter(&mut iovec.bi_vec, buffers)
+        }
+    }
+
+    // Read data of `iovec` from the file cache into `buffers` by one syscall, bypassing region
+    // merging, if all chunks are ready and contiguous in the cache file. Returns `None` to fall
+    // back to the generic path otherwise.
+    pub(crate) fn read_cache_contiguous(
+        &self,
+        iovec: &BlobIoVec,
+        buffers: &[FileVolatileSlice],
+    ) -> Result<Option<usize>> {
+        if iovec.is_empty()
+            || buffers.is_empty()
+            || self.is_raw_data
+            || self.is_cache_encrypted
+            || self.need_validation()
+        {
+            return Ok(None);
+        }
+
+        let mut prev: Option<&BlobIoDesc> = None;
+        for bio in iovec.bi_vec.iter() {
+            if !bio.user_io {
+                return Ok(None);
+            }
+            if let Some(prev) = prev {
+                let chunk = &prev.chunkinfo;
+                if bio.offset != 0
+                    || prev.offset + prev.size != chunk.uncompressed_size()
+                    || cache_data_offset(chunk, self.cache_slot_size)
+                        + chunk.uncompressed_size() as u64
+                        != cache_data_offset(&bio.chunkinfo, self.cache_slot_size)
+                {
+                    return Ok(None);
+                }
+            }
+            if !matches!(self.chunk_map.is_ready(&bio.chunkinfo), Ok(true)) {
+                return Ok(None);
+            }
+            prev = Some(bio);
+        }
+
+        let size = iovec.size() as usize;
+        if buffers.iter().map(|buf| buf.len()).sum::<usize>() < size {
+            return Ok(None);
+        }
+
+        if let Some(eviction) = self.eviction.as_ref() {
+            for bio in iovec.bi_vec.iter() {
+                eviction.on_access(&bio.chunkinfo)?;
+            }
+        }
+        self.metrics.partial_hits.inc();
+        self.cache_metrics
+            .record_hits(iovec.len() as u64, size as u64);
+
+        let offset = cache_data_offset(&iovec.bi_vec[0].chunkinfo, self.cache_slot_size)
+            + iovec.bi_vec[0].offset as u64;
+        let mut cursor = MemSliceCursor::new(buffers);
+        let mut iov = cursor.consume(size);
+        let size = if let Some(map) = self.mapped_cache_file() {
+            readv_mapped(&map, &mut iov, offset)?
+        } else {
+            readv(self.file.as_raw_fd(), &mut iov, offset)?
+        };
+
+        Ok(Some(size))
+    }
+
     // There are some assumption applied to the `bios` passed to `read_iter()`.
     // - The blob address of chunks in `bios` are continuous.
     // - There is at most one user io request in the `bios`.
@@ -1015,7 +2761,7 @@ impl FileCacheEntry {
             .merge_requests_for_user(bios, self.user_io_batch_size())
             .ok_or_else(|| {
                 for bio in bios.iter() {
-                    self.update_chunk_pending_status(&bio.chunkinfo, false);
+                    self.clear_chunk_pending_status(&bio.chunkinfo);
                 }
                 einval!("Empty bios list")
             })?;
@@ -1029,7 +2775,7 @@ impl FileCacheEntry {
                 .map_err(|e| {
                     for req in requests.iter().skip(idx) {
                         for chunk in req.chunks.iter() {
-                            self.update_chunk_pending_status(chunk.as_ref(), false);
+                            self.clear_chunk_pending_status(chunk.as_ref());
                         }
                     }
                     e
@@ -1051,12 +2797,17 @@ impl FileCacheEntry {
         trace!("dispatch single io range {:?}", req);
         let mut blob_cci = BlobCCI::new();
         for (i, chunk) in req.chunks.iter().enumerate() {
-            let is_ready = match self.chunk_map.check_ready_and_mark_pending(chunk.as_ref()) {
+            let is_ready = match self.check_ready_and_mark_pending(chunk.as_ref()) {
                 Ok(true) => true,
                 Ok(false) => false,
                 Err(StorageError::Timeout) => false, // Retry if waiting for inflight IO timeouts
                 Err(e) => return Err(einval!(e)),
             };
+            if is_ready && req.tags[i].is_user_io() {
+                if let Some(eviction) = self.eviction.as_ref() {
+                    eviction.on_access(chunk.as_ref())?;
+                }
+            }
 
             // Directly read chunk data from file cache into user buffer iff:
             // - the chunk is ready in the file cache
@@ -1070,7 +2821,7 @@ impl FileCacheEntry {
                 if req.tags[i].is_user_io() {
                     state.push(
                         RegionType::CacheFast,
-                        chunk.uncompressed_offset(),
+                        cache_data_offset(chunk.as_ref(), self.cache_slot_size),
                         chunk.uncompressed_size(),
                         req.tags[i].clone(),
                         None,
@@ -1086,7 +2837,7 @@ impl FileCacheEntry {
                 if req.tags[i].is_user_io() {
                     state.push(
                         RegionType::CacheSlow,
-                        chunk.uncompressed_offset(),
+                        cache_data_offset(chunk.as_ref(), self.cache_slot_size),
                         chunk.uncompressed_size(),
                         req.tags[i].clone(),
                         Some(req.chunks[i].clone()),
@@ -1137,7 +2888,13 @@ impl FileCacheEntry {
         let mut iovec = cursor.consume(size);
 
         self.metrics.partial_hits.inc();
-        readv(self.file.as_raw_fd(), &mut iovec, offset)
+        self.cache_metrics
+            .record_hits(region.count as u64, size as u64);
+        if let Some(map) = self.mapped_cache_file() {
+            readv_mapped(&map, &mut iovec, offset)
+        } else {
+            readv(self.file.as_raw_fd(), &mut iovec, offset)
+        }
     }
 
     // Try to read data from blob cache and validate it, fallback to storage backend.
@@ -1200,6 +2957,7 @@ impl FileCacheEntry {
         // Try to extend requests.
         let mut region_hold;
         if let Some(v) = self.extend_pending_chunks(&region.chunks, self.user_io_batch_size())? {
+            let v = self.limit_extended_chunks(region, v)?;
             if v.len() > r.chunks.len() {
                 let mut tag_set = HashSet::new();
                 for (idx, chunk) in region.chunks.iter().enumerate() {
@@ -1248,21 +3006,43 @@ impl FileCacheEntry {
                 }
                 e
             })?;
+        let user_chunks = region.tags.iter().filter(|v| **v).count();
+        self.cache_metrics
+            .record_misses(user_chunks as u64, region.blob_len as u64);
 
         if self.is_raw_data {
-            let res =
-                Self::persist_cached_data(&self.file, region.blob_address, bufs.compressed_buf());
+            let res = Self::persist_cached_data(
+                &self.file,
+                &self.write_state,
+                self.checksum.as_deref(),
+                region.blob_address,
+                bufs.compressed_buf(),
+            );
+            let mut cached = Ok(());
             for chunk in region.chunks.iter() {
-                self.update_chunk_pending_status(chunk.as_ref(), res.is_ok());
+                cached = cached.and(self.update_chunk_pending_status(chunk.as_ref(), res.is_ok()));
             }
             res?;
+            cached?;
         }
 
         let mut chunk_buffers = Vec::with_capacity(region.chunks.len());
         let mut buffer_holder = Vec::with_capacity(region.chunks.len());
         for (i, v) in bufs.enumerate() {
-            let d = Arc::new(DataBuffer::Allocated(v?));
+            let v = match v {
+                Ok(v) => v,
+                Err(e) => {
+                    let chunk = region.chunks[i].as_ref();
+                    let mut buf = alloc_buf(chunk.uncompressed_size() as usize);
+                    self.refetch_chunk(chunk, &mut buf, e)?;
+                    buf
+                }
+            };
+            let d = Arc::new(DataBuffer::Allocated(v));
             if region.tags[i] {
+                if let Some(tier) = self.memory_tier.as_ref() {
+                    tier.insert(region.chunks[i].id(), d.slice());
+                }
                 buffer_holder.push(d.clone());
             }
             if !self.is_raw_data {
@@ -1308,6 +3088,7 @@ impl FileCacheEntry {
         );
 
         let buffer_holder;
+        self.check_decompress_size(chunk.as_ref())?;
         let d_size = chunk.uncompressed_size() as usize;
         let mut d = DataBuffer::Allocated(alloc_buf(d_size));
 
@@ -1317,8 +3098,17 @@ impl FileCacheEntry {
         // - digested or dummy chunk map is used.
         let is_ready = self.chunk_map.is_ready(chunk.as_ref())?;
         let try_cache = is_ready || !self.is_direct_chunkmap;
-        let buffer = if try_cache && self.read_file_cache(chunk.as_ref(), d.mut_slice()).is_ok() {
+        let memory_hit = self.memory_tier.as_ref().and_then(|t| t.get(&chunk.id()));
+        let buffer = if let Some(data) = memory_hit.as_ref() {
+            self.metrics.whole_hits.inc();
+            self.cache_metrics.record_hits(1, size as u64);
+            if !is_ready {
+                self.chunk_map.clear_pending(chunk.as_ref());
+            }
+            data.as_slice()
+        } else if try_cache && self.read_file_cache(chunk.as_ref(), d.mut_slice()).is_ok() {
             self.metrics.whole_hits.inc();
+            self.cache_metrics.record_hits(1, size as u64);
             self.chunk_map.set_ready_and_clear_pending(chunk.as_ref())?;
             trace!(
                 "recover blob cache {} {} offset {} size {}",
@@ -1327,37 +3117,50 @@ impl FileCacheEntry {
                 user_offset,
                 size,
             );
-            &d
+            d.slice()
         } else {
             let c = self
                 .read_chunk_from_backend(chunk.as_ref(), d.mut_slice())
+                .or_else(|e| self.refetch_chunk(chunk.as_ref(), d.mut_slice(), e))
                 .map_err(|e| {
                     self.chunk_map.clear_pending(chunk.as_ref());
                     e
                 })?;
+            self.cache_metrics
+                .record_misses(1, chunk.compressed_size() as u64);
             if self.is_raw_data {
                 match c {
                     Some(v) => {
                         let buf = Arc::new(DataBuffer::Allocated(v));
                         self.delay_persist_chunk_data(chunk.clone(), buf);
-                        &d
+                        d.slice()
+                    }
+                    // Data from the uncompressed fallback copy can't be cached as raw data.
+                    None if chunk.is_compressed() || chunk.is_encrypted() => {
+                        self.chunk_map.clear_pending(chunk.as_ref());
+                        d.slice()
                     }
                     None => {
                         buffer_holder = Arc::new(d.convert_to_owned_buffer());
                         self.delay_persist_chunk_data(chunk.clone(), buffer_holder.clone());
-                        buffer_holder.as_ref()
+                        buffer_holder.slice()
                     }
                 }
             } else {
                 buffer_holder = Arc::new(d.convert_to_owned_buffer());
                 self.delay_persist_chunk_data(chunk.clone(), buffer_holder.clone());
-                buffer_holder.as_ref()
+                buffer_holder.slice()
             }
         };
+        if memory_hit.is_none() {
+            if let Some(tier) = self.memory_tier.as_ref() {
+                tier.insert(chunk.id(), buffer);
+            }
+        }
 
         let dst_buffers = mem_cursor.inner_slice();
         let read_size = copyv(
-            &[buffer.slice()],
+            &[buffer],
             dst_buffers,
             user_offset as usize,
             size as usize,
@@ -1374,7 +3177,95 @@ impl FileCacheEntry {
         Ok(read_size)
     }
 
+    /// Read raw chunk data without decompression for consumers accepting compressed data.
+    ///
+    /// Returns `None` if the request can't be served with compressed data, such as when a blob IO
+    /// doesn't cover a whole chunk, and the caller should fall back to the normal read path.
+    fn read_compressed(
+        &self,
+        iovec: &BlobIoVec,
+        buffers: &[FileVolatileSlice],
+    ) -> Result<Option<usize>> {
+        if iovec.is_empty()
+            || self.blob_compressor() == compress::Algorithm::None
+            || self.blob_cipher() != crypt::Algorithm::None
+            || self.is_zran()
+            || self.is_batch()
+            || self.is_legacy_stargz()
+        {
+            return Ok(None);
+        }
+        let mut total = 0usize;
+        for bio in iovec.bi_vec.iter() {
+            let chunk = &bio.chunkinfo;
+            if bio.offset != 0
+                || bio.size != chunk.uncompressed_size()
+                || chunk.is_encrypted()
+                || chunk.compressor().is_some()
+                || chunk.source_url().is_some()
+            {
+                return Ok(None);
+            }
+            total += chunk.compressed_size() as usize;
+        }
+        if total > buffers.iter().map(|b| b.len()).sum::<usize>() {
+            return Ok(None);
+        }
+
+        let mut bufs = Vec::with_capacity(iovec.len());
+        for bio in iovec.bi_vec.iter() {
+            let chunk = &bio.chunkinfo;
+            let offset = chunk.compressed_offset();
+            let mut buf = alloc_buf(chunk.compressed_size() as usize);
+            if self.is_raw_data && matches!(self.chunk_map.is_ready(chunk), Ok(true)) {
+                FileRangeReader::new(&self.file, offset, buf.len() as u64).read_exact(&mut buf)?;
+                self.cache_metrics.record_hits(1, buf.len() as u64);
+            } else {
+                self.read_raw_chunk(chunk, offset, &mut buf)?;
+                self.cache_metrics.record_misses(1, buf.len() as u64);
+            }
+            bufs.push(buf);
+        }
+
+        let (size, _) = copyv(&bufs, buffers, 0, total, 0, 0).map_err(|e| {
+            error!("failed to copy compressed chunk data to buf: {:?}", e);
+            eio!(e)
+        })?;
+        Ok(Some(size))
+    }
+
+    // Fetch the chunk from the storage backend again if its data fetched from the backend fails
+    // digest validation with `err` and `RefetchOnce` policy is configured, otherwise return `err`.
+    fn refetch_chunk(
+        &self,
+        chunk: &dyn BlobChunkInfo,
+        buffer: &mut [u8],
+        err: Error,
+    ) -> Result<Option<Vec<u8>>> {
+        if self.digest_mismatch_policy != DigestMismatchPolicy::RefetchOnce
+            || !matches!(
+                CacheError::from_io_error(&err),
+                Some(CacheError::DigestMismatch)
+            )
+        {
+            return Err(err);
+        }
+        warn!(
+            "data of chunk {} of blob {} doesn't match its digest, fetch it from backend again",
+            chunk.id(),
+            self.blob_id
+        );
+        self.read_chunk_from_backend(chunk, buffer)
+    }
+
     fn read_file_cache(&self, chunk: &dyn BlobChunkInfo, buffer: &mut [u8]) -> Result<()> {
+        self.read_file_cache_data(chunk, buffer)?;
+        self.validate_chunk_data(chunk, buffer, false)?;
+        Ok(())
+    }
+
+    // Read decoded data of the chunk from the cache file without validation.
+    fn read_file_cache_data(&self, chunk: &dyn BlobChunkInfo, buffer: &mut [u8]) -> Result<()> {
         if self.is_raw_data {
             let offset = chunk.compressed_offset();
             let size = if self.is_legacy_stargz() {
@@ -1382,24 +3273,23 @@ impl FileCacheEntry {
             } else {
                 chunk.compressed_size() as u64
             };
-            let mut reader = FileRangeReader::new(&self.file, offset, size);
             if !chunk.is_compressed() {
-                reader.read_exact(buffer)?;
-            } else if self.blob_compressor() == compress::Algorithm::Lz4Block {
-                let mut buf = alloc_buf(size as usize);
-                reader.read_exact(&mut buf)?;
-                let size = compress::decompress(&buf, buffer, self.blob_compressor())?;
+                FileRangeReader::new(&self.file, offset, size).read_exact(buffer)?;
+            } else {
+                // Stream compressed data from the cache file instead of buffering it.
+                let size = compress::decompress_file(
+                    &self.file,
+                    offset,
+                    size,
+                    buffer,
+                    self.chunk_compressor(chunk),
+                )?;
                 if size != buffer.len() {
-                    return Err(einval!(
-                        "data size decoded by lz4_block doesn't match expected"
-                    ));
+                    return Err(einval!("decompressed data size doesn't match expected"));
                 }
-            } else {
-                let mut decoder = Decoder::new(reader, self.blob_compressor())?;
-                decoder.read_exact(buffer)?;
             }
         } else if self.is_cache_encrypted {
-            let offset = chunk.uncompressed_offset();
+            let offset = cache_data_offset(chunk, self.cache_slot_size);
             let size = chunk.uncompressed_size() as usize;
             let cipher_object = self.cache_cipher_object.clone();
             let cipher_context = self.cache_cipher_context.clone();
@@ -1422,11 +3312,14 @@ impl FileCacheEntry {
                 }
             }
         } else {
-            let offset = chunk.uncompressed_offset();
+            let offset = cache_data_offset(chunk, self.cache_slot_size);
             let size = chunk.uncompressed_size() as u64;
-            FileRangeReader::new(&self.file, offset, size).read_exact(buffer)?;
+            if let Some(map) = self.mapped_cache_file() {
+                buffer.copy_from_slice(map.get_slice::<u8>(offset as usize, size as usize)?);
+            } else {
+                FileRangeReader::new(&self.file, offset, size).read_exact(buffer)?;
+            }
         }
-        self.validate_chunk_data(chunk, buffer, false)?;
         Ok(())
     }
 
@@ -1437,10 +3330,12 @@ impl FileCacheEntry {
     ) -> Option<Vec<BlobIoRange>> {
         let mut requests: Vec<BlobIoRange> = Vec::with_capacity(bios.len());
 
-        BlobIoMergeState::merge_and_issue(
+        BlobIoMergeState::merge_and_issue_with_limit(
             bios,
             max_comp_size,
             max_comp_size >> RAFS_BATCH_SIZE_TO_GAP_SHIFT,
+            self.read_amplification_limit,
+            self.max_user_merge_size,
             |mr: BlobIoRange| {
                 requests.push(mr);
             },
@@ -1917,4 +3812,241 @@ mod tests {
         let c_end = blob_cci.get_compressed_end(&batch_chunk).unwrap();
         assert_eq!(c_end, 0x2000);
     }
+
+    fn mock_chunk(index: u32) -> MockChunkInfo {
+        MockChunkInfo {
+            index,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_chunk_map_batch() {
+        use crate::cache::state::{BlobStateMap, IndexedChunkMap};
+        use vmm_sys_util::tempdir::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob").display().to_string();
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .read(true)
+            .open(&blob_path)
+            .unwrap();
+        let chunk_map: Arc<dyn ChunkMap> = Arc::new(BlobStateMap::from(
+            IndexedChunkMap::new(&blob_path, 8, true).unwrap(),
+        ));
+        let bitmap = chunk_map.as_range_map().unwrap();
+        assert!(bitmap
+            .check_range_ready_and_mark_pending(0, 8)
+            .unwrap()
+            .is_some());
+
+        let batch = ChunkMapBatch::new(4, 60000, None);
+        assert!(batch.is_empty());
+        for idx in 0..3 {
+            batch.add(&file, &chunk_map, &mock_chunk(idx));
+        }
+        assert!(!batch.is_empty());
+        assert!(!bitmap.is_range_ready(0, 1).unwrap());
+        batch.add(&file, &chunk_map, &mock_chunk(3));
+        assert!(batch.is_empty());
+        assert!(bitmap.is_range_ready(0, 4).unwrap());
+
+        batch.add(&file, &chunk_map, &mock_chunk(4));
+        assert!(!bitmap.is_range_ready(4, 1).unwrap());
+        batch.flush(&file, &chunk_map);
+        assert!(batch.is_empty());
+        assert!(bitmap.is_range_ready(4, 1).unwrap());
+        assert!(!bitmap.is_range_ready(5, 1).unwrap());
+
+        // Updates are committed by the periodic flusher once exceeding the time threshold.
+        let batch = ChunkMapBatch::new(4, 50, None);
+        batch.add(&file, &chunk_map, &mock_chunk(5));
+        batch.flush_expired(&file, &chunk_map);
+        assert!(!bitmap.is_range_ready(5, 1).unwrap());
+        std::thread::sleep(Duration::from_millis(60));
+        batch.flush_expired(&file, &chunk_map);
+        assert!(batch.is_empty());
+        assert!(bitmap.is_range_ready(5, 1).unwrap());
+    }
+
+    #[test]
+    fn test_chunk_map_batch_concurrent_readers() {
+        use crate::cache::state::{BlobStateMap, IndexedChunkMap};
+        use crate::cache::SINGLE_INFLIGHT_WAIT_TIMEOUT;
+        use vmm_sys_util::tempdir::TempDir;
+
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob").display().to_string();
+        let file = Arc::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .read(true)
+                .open(&blob_path)
+                .unwrap(),
+        );
+        let chunk_map: Arc<dyn ChunkMap> = Arc::new(BlobStateMap::from(
+            IndexedChunkMap::new(&blob_path, 16, true).unwrap(),
+        ));
+        let bitmap = chunk_map.as_range_map().unwrap();
+        assert!(bitmap
+            .check_range_ready_and_mark_pending(0, 16)
+            .unwrap()
+            .is_some());
+
+        // Readers commit buffered chunks they are waiting for.
+        let batch = Arc::new(ChunkMapBatch::new(8, 60000, None));
+        batch.add(&file, &chunk_map, &mock_chunk(0));
+        assert!(batch.wait_for_range_ready(&file, &chunk_map, 0, 1).unwrap());
+        assert!(batch.is_empty());
+
+        // Readers don't commit buffered chunks they are not waiting for, but chunks written while
+        // they are waiting are committed immediately, without waiting for the batch thresholds.
+        batch.add(&file, &chunk_map, &mock_chunk(1));
+        let start = Instant::now();
+        let readers: Vec<_> = (0..2u32)
+            .map(|idx| {
+                let (batch, file, chunk_map) = (batch.clone(), file.clone(), chunk_map.clone());
+                std::thread::spawn(move || {
+                    batch
+                        .wait_for_range_ready(&file, &chunk_map, idx * 2 + 2, 2)
+                        .unwrap()
+                })
+            })
+            .collect();
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!batch.is_empty());
+        assert!(!bitmap.is_range_ready(1, 1).unwrap());
+        for idx in 2..6 {
+            std::thread::sleep(Duration::from_millis(5));
+            batch.add(&file, &chunk_map, &mock_chunk(idx));
+        }
+        for reader in readers {
+            assert!(reader.join().unwrap());
+        }
+        assert!(start.elapsed() < Duration::from_millis(SINGLE_INFLIGHT_WAIT_TIMEOUT));
+        assert!(bitmap.is_range_ready(1, 5).unwrap());
+
+        // Updates are buffered again once there's no waiter.
+        batch.add(&file, &chunk_map, &mock_chunk(6));
+        assert!(!batch.is_empty());
+        assert!(!bitmap.is_range_ready(6, 1).unwrap());
+
+        // Checking a chunk that lands in the batch while waiting doesn't stall on the batch.
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            index: 7,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let reader = {
+            let (batch, file, chunk_map) = (batch.clone(), file.clone(), chunk_map.clone());
+            let chunk = chunk.clone();
+            std::thread::spawn(move || {
+                batch
+                    .check_ready_and_mark_pending(&file, &chunk_map, chunk.as_ref())
+                    .unwrap()
+            })
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        batch.add(&file, &chunk_map, &mock_chunk(7));
+        assert!(reader.join().unwrap());
+        assert!(start.elapsed() < Duration::from_millis(SINGLE_INFLIGHT_WAIT_TIMEOUT));
+        assert!(bitmap.is_range_ready(6, 2).unwrap());
+
+        // Checking ready chunks never commits buffered updates.
+        assert!(batch
+            .check_ready_and_mark_pending(&file, &chunk_map, chunk.as_ref())
+            .unwrap());
+        batch.add(&file, &chunk_map, &mock_chunk(8));
+        assert!(batch
+            .check_ready_and_mark_pending(&file, &chunk_map, chunk.as_ref())
+            .unwrap());
+        assert!(!batch.is_empty());
+
+        // Buffered chunks are committed when prefetch ends.
+        batch.flush(&file, &chunk_map);
+        assert!(batch.is_empty());
+        assert!(bitmap.is_range_ready(0, 9).unwrap());
+    }
+
+    #[test]
+    fn test_cache_write_state() {
+        use vmm_sys_util::tempdir::TempDir;
+        use vmm_sys_util::tempfile::TempFile;
+
+        let dir = TempDir::new().unwrap();
+        let work_dir = dir.as_path().to_str().unwrap();
+        let file = Arc::new(TempFile::new().unwrap().into_file());
+        let erofs = Error::from_raw_os_error(libc::EROFS);
+
+        let state = Arc::new(CacheWriteState::new(work_dir, 3600));
+        assert!(!state.is_readonly());
+        assert!(!state.check_write_error(&eio!()));
+        assert!(!state.is_readonly());
+        assert!(state.check_write_error(&erofs));
+        assert!(state.is_readonly());
+
+        // Writing to cache files is skipped in read-only passthrough mode.
+        let write_state = Some(state);
+        let err = FileCacheEntry::persist_cached_data(&file, &write_state, None, 0, &[0x5a; 16])
+            .err()
+            .unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+        assert_eq!(file.metadata().unwrap().len(), 0);
+
+        // Caching resumes once the working directory is found writable again.
+        let state = Arc::new(CacheWriteState::new(work_dir, 0));
+        assert!(state.check_write_error(&erofs));
+        assert!(!state.is_readonly());
+        assert!(!dir.as_path().join(".nydus_write_probe").exists());
+        let write_state = Some(state);
+        FileCacheEntry::persist_cached_data(&file, &write_state, None, 0, &[0x5a; 16]).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_chunk_memory_tier() {
+        let tier = ChunkMemoryTier::new(0x3000);
+        tier.insert(0, &[0x0u8; 0x1000]);
+        tier.insert(1, &[0x1u8; 0x1000]);
+        tier.insert(2, &[0x2u8; 0x1000]);
+        // Chunks bigger than the capacity are never cached.
+        tier.insert(3, &[0x3u8; 0x4000]);
+        assert!(tier.get(&3).is_none());
+
+        // Touch chunk 0, so chunk 1 becomes the least recently used one.
+        assert_eq!(tier.get(&0).unwrap()[0], 0x0);
+        tier.insert(4, &[0x4u8; 0x1000]);
+        assert!(tier.get(&1).is_none());
+        assert_eq!(tier.get(&0).unwrap()[0], 0x0);
+        assert_eq!(tier.get(&2).unwrap()[0], 0x2);
+        assert_eq!(tier.get(&4).unwrap()[0], 0x4);
+        assert_eq!(tier.state.lock().unwrap().size, 0x3000);
+    }
+
+    #[test]
+    fn test_blob_memory_tier() {
+        let tier = Arc::new(SharedMemoryTier::new(0x3000));
+        let blob0 = BlobMemoryTier::new(tier.clone(), "blob-0".to_string());
+        let blob1 = BlobMemoryTier::new(tier.clone(), "blob-1".to_string());
+        blob0.insert(0, &[0x0u8; 0x1000]);
+        blob0.insert(1, &[0x1u8; 0x1000]);
+        blob1.insert(0, &[0x2u8; 0x1000]);
+        assert_eq!(blob0.get(&0).unwrap()[0], 0x0);
+        assert_eq!(blob1.get(&0).unwrap()[0], 0x2);
+
+        // Blobs share the capacity, the least recently used chunk of blob-0 gets evicted.
+        blob1.insert(1, &[0x3u8; 0x1000]);
+        assert!(blob0.get(&1).is_none());
+        assert_eq!(blob0.get(&0).unwrap()[0], 0x0);
+
+        // Clearing a blob keeps chunks of other blobs.
+        blob0.clear();
+        assert!(blob0.get(&0).is_none());
+        assert_eq!(blob1.get(&0).unwrap()[0], 0x2);
+        assert_eq!(blob1.get(&1).unwrap()[0], 0x3);
+        assert_eq!(tier.state.lock().unwrap().size, 0x2000);
+    }
 }
@@ -0,0 +1,182 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Whole-file checksum of cache files for quick validation on startup.
+//!
+//! The checksum is the sum of all bytes of a cache file weighted by their positions, so it can be
+//! updated incrementally when a range of the cache file is written or released, no matter in
+//! which order chunks get cached. It's saved into a sidecar file when the cache file is closed,
+//! and compared with the checksum of the cache file when it's opened again, to skip verifying
+//! cached chunks one by one if the cache file hasn't been changed in between.
+
+use std::fs::{self, File};
+use std::io::{ErrorKind, Result};
+use std::os::unix::fs::FileExt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::utils::alloc_buf;
+
+/// Suffix of the sidecar file to save the whole-file checksum of a cache file.
+pub const CACHE_CHECKSUM_FILE_SUFFIX: &str = "checksum";
+
+const CACHE_CHECKSUM_MAGIC: u64 = 0x4e59_4455_5343_4b53;
+const CACHE_CHECKSUM_BLOCK_SIZE: u64 = 0x100000;
+
+/// Incrementally updated checksum of a cache file.
+pub(crate) struct CacheFileChecksum {
+    path: String,
+    value: AtomicU64,
+    // Cleared if the checksum may be out of sync with the cache file, so it won't be saved.
+    valid: AtomicBool,
+}
+
+impl CacheFileChecksum {
+    /// Create a checksum object for a cache file, to be saved into the sidecar file `path`.
+    ///
+    /// The checksum is invalid until [reset()](#method.reset) is called.
+    pub fn new(path: String) -> Self {
+        CacheFileChecksum {
+            path,
+            value: AtomicU64::new(0),
+            valid: AtomicBool::new(false),
+        }
+    }
+
+    /// Compute checksum of data in range `[offset, offset + size)` of `file`.
+    ///
+    /// Data beyond the end of the file is treated as zeros, just like holes of the file.
+    pub fn compute(file: &File, offset: u64, size: u64) -> Result<u64> {
+        let mut buf = alloc_buf(std::cmp::min(size, CACHE_CHECKSUM_BLOCK_SIZE) as usize);
+        let end = offset.saturating_add(size);
+        let mut pos = offset;
+        let mut sum = 0u64;
+        while pos < end {
+            let len = std::cmp::min(end - pos, buf.len() as u64) as usize;
+            let n = match file.read_at(&mut buf[..len], pos) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            sum = sum.wrapping_add(weighted_sum(pos, &buf[..n]));
+            pos += n as u64;
+        }
+        Ok(sum)
+    }
+
+    /// Compute checksum of the whole `file`.
+    pub fn compute_file(file: &File) -> Result<u64> {
+        Self::compute(file, 0, file.metadata()?.len())
+    }
+
+    /// Get the checksum saved into the sidecar file and remove the sidecar file.
+    ///
+    /// The sidecar file is removed so that a stale checksum won't be trusted if the process
+    /// exits abnormally after changing the cache file.
+    pub fn take_saved(&self) -> Option<u64> {
+        let data = fs::read(&self.path).ok()?;
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("failed to remove cache checksum file {}, {}", self.path, e);
+            return None;
+        }
+        if data.len() != 16 {
+            return None;
+        }
+        let magic = u64::from_le_bytes(data[..8].try_into().unwrap());
+        let value = u64::from_le_bytes(data[8..].try_into().unwrap());
+        (magic == CACHE_CHECKSUM_MAGIC).then_some(value)
+    }
+
+    /// Save the checksum into the sidecar file if it's in sync with the cache file.
+    pub fn save(&self) -> Result<()> {
+        if !self.valid.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        let mut data = CACHE_CHECKSUM_MAGIC.to_le_bytes().to_vec();
+        data.extend_from_slice(&self.value().to_le_bytes());
+        fs::write(&self.path, data)
+    }
+
+    /// Get the current checksum value.
+    pub fn value(&self) -> u64 {
+        self.value.load(Ordering::Acquire)
+    }
+
+    /// Set the checksum to `value` and mark it as in sync with the cache file.
+    pub fn reset(&self, value: u64) {
+        self.value.store(value, Ordering::Release);
+        self.valid.store(true, Ordering::Release);
+    }
+
+    /// Mark the checksum as out of sync with the cache file.
+    pub fn invalidate(&self) {
+        self.valid.store(false, Ordering::Release);
+    }
+
+    /// Update the checksum after replacing data with checksum `old` by `buf` at `offset`.
+    pub fn replace(&self, old: u64, offset: u64, buf: &[u8]) {
+        let delta = weighted_sum(offset, buf).wrapping_sub(old);
+        self.value.fetch_add(delta, Ordering::AcqRel);
+    }
+
+    /// Update the checksum after releasing data with checksum `old`.
+    pub fn release(&self, old: u64) {
+        self.value.fetch_sub(old, Ordering::AcqRel);
+    }
+}
+
+// Sum of bytes of `buf` located at `offset`, weighted by pseudo random values of their positions.
+fn weighted_sum(offset: u64, buf: &[u8]) -> u64 {
+    buf.iter().enumerate().fold(0u64, |sum, (idx, v)| {
+        let w = (offset + idx as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        sum.wrapping_add((w ^ (w >> 29)).wrapping_mul(*v as u64))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vmm_sys_util::tempdir::TempDir;
+    use vmm_sys_util::tempfile::TempFile;
+
+    #[test]
+    fn test_cache_file_checksum() {
+        let file = TempFile::new().unwrap().into_file();
+        file.set_len(0x3000).unwrap();
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("blob.checksum");
+        let checksum = CacheFileChecksum::new(path.to_str().unwrap().to_string());
+        checksum.reset(CacheFileChecksum::compute_file(&file).unwrap());
+        assert_eq!(checksum.value(), 0);
+
+        // Updates in any order result in the checksum of the whole file.
+        for (offset, v) in [(0x2000u64, 0x5au8), (0x800, 0xa5), (0x1000, 0x5a)] {
+            let buf = vec![v; 0x1000];
+            let old = CacheFileChecksum::compute(&file, offset, 0x1000).unwrap();
+            file.write_all_at(&buf, offset).unwrap();
+            checksum.replace(old, offset, &buf);
+        }
+        let value = CacheFileChecksum::compute_file(&file).unwrap();
+        assert_ne!(value, 0);
+        assert_eq!(checksum.value(), value);
+
+        // Swapping bytes changes the checksum.
+        assert_ne!(weighted_sum(0x1000, &[1, 2]), weighted_sum(0x1000, &[2, 1]));
+
+        let old = CacheFileChecksum::compute(&file, 0x2000, 0x1000).unwrap();
+        checksum.release(old);
+        file.write_all_at(&[0u8; 0x1000], 0x2000).unwrap();
+        assert_eq!(
+            checksum.value(),
+            CacheFileChecksum::compute_file(&file).unwrap()
+        );
+
+        checksum.save().unwrap();
+        assert_eq!(checksum.take_saved(), Some(checksum.value()));
+        assert_eq!(checksum.take_saved(), None);
+        checksum.invalidate();
+        checksum.save().unwrap();
+        assert_eq!(checksum.take_saved(), None);
+    }
+}
@@ -18,25 +18,82 @@
 //! - Read uncompressed data from local disk and no need to double cache the data.
 //!   The [is_chunk_cached()](../trait.BlobCache.html#tymethod.is_chunk_cached) method always
 //!   return true to enable data prefetching.
-use std::io::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error, ErrorKind, Result};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Condvar, Mutex};
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
 use nydus_api::CacheConfigV2;
 use nydus_utils::crypt::{Algorithm, Cipher, CipherContext};
 use nydus_utils::{compress, digest};
 
-use crate::backend::{BlobBackend, BlobReader};
+use crate::backend::{BackendCaps, BlobBackend, BlobReader};
+use crate::cache::cachedfile::SharedMemoryTier;
 use crate::cache::state::{ChunkMap, NoopChunkMap};
-use crate::cache::{BlobCache, BlobCacheMgr};
+use crate::cache::{
+    max_coalesce_gap, max_decompress_size, read_out_of_bounds_chunks, BlobCache, BlobCacheMgr,
+    BlobCacheStatus, CacheMetrics, ReadStats,
+};
 use crate::device::{
-    BlobChunkInfo, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoVec, BlobPrefetchRequest,
+    BlobChunkInfo, BlobFeatures, BlobInfo, BlobIoDesc, BlobIoRange, BlobIoVec, BlobPrefetchRequest,
 };
 use crate::utils::{alloc_buf, copyv};
 use crate::{StorageError, StorageResult};
 
-struct DummyCache {
+type ChunkFetchResult = std::result::Result<Arc<Vec<u8>>, (ErrorKind, String)>;
+
+/// A backend fetch of a chunk in flight, whose result is shared by concurrent readers of the chunk.
+#[derive(Default)]
+struct ChunkFetch {
+    result: Mutex<Option<ChunkFetchResult>>,
+    cond: Condvar,
+}
+
+impl ChunkFetch {
+    fn wait(&self) -> Result<Arc<Vec<u8>>> {
+        let mut guard = self.result.lock().unwrap();
+        loop {
+            match guard.as_ref() {
+                Some(Ok(data)) => return Ok(data.clone()),
+                Some(Err((kind, msg))) => return Err(Error::new(*kind, msg.clone())),
+                None => guard = self.cond.wait(guard).unwrap(),
+            }
+        }
+    }
+}
+
+/// Guard held by the reader fetching a chunk, to wake up readers waiting for the fetch even if
+/// the fetching reader panics.
+struct ChunkFetchGuard<'a> {
+    inflight: &'a Mutex<HashMap<u32, Arc<ChunkFetch>>>,
+    index: u32,
+    fetch: Arc<ChunkFetch>,
+    done: bool,
+}
+
+impl ChunkFetchGuard<'_> {
+    fn finish<F: FnOnce() -> ChunkFetchResult>(&mut self, result: F) {
+        self.done = true;
+        self.inflight.lock().unwrap().remove(&self.index);
+        // No new reader may join after removing the fetch, so skip the result if nobody waits.
+        if Arc::strong_count(&self.fetch) > 1 {
+            *self.fetch.result.lock().unwrap() = Some(result());
+            self.fetch.cond.notify_all();
+        }
+    }
+}
+
+impl Drop for ChunkFetchGuard<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let msg = "reader fetching the chunk exited unexpectedly".to_string();
+            self.finish(|| Err((ErrorKind::Other, msg)));
+        }
+    }
+}
+
+pub(crate) struct DummyCache {
     blob_id: String,
     blob_info: Arc<BlobInfo>,
     chunk_map: Arc<dyn ChunkMap>,
@@ -45,6 +102,147 @@ struct DummyCache {
     digester: digest::Algorithm,
     is_legacy_stargz: bool,
     need_validation: bool,
+    strict_validation: bool,
+    no_digest_reported: AtomicBool,
+    // Maximum size of decompressed data of a chunk.
+    max_decompress_size: u64,
+    metrics: CacheMetrics,
+    memory_tier: Option<Arc<SharedMemoryTier>>,
+    // Backend fetches in flight, indexed by chunk index.
+    inflight: Mutex<HashMap<u32, Arc<ChunkFetch>>>,
+    // Fill data of chunks beyond the end of the blob with zero instead of failing reads.
+    zero_out_of_bounds_chunks: bool,
+    // Whether blob data prefetching is enabled.
+    prefetch: bool,
+}
+
+impl DummyCache {
+    /// Create a new instance of `DummyCache` to serve data from the storage backend directly.
+    pub(crate) fn new(
+        blob_info: &Arc<BlobInfo>,
+        reader: Arc<dyn BlobReader>,
+        cached: bool,
+        need_validation: bool,
+        strict_validation: bool,
+        metrics: CacheMetrics,
+    ) -> Self {
+        DummyCache {
+            blob_id: blob_info.blob_id(),
+            blob_info: blob_info.clone(),
+            chunk_map: Arc::new(NoopChunkMap::new(cached)),
+            reader,
+            compressor: blob_info.compressor(),
+            digester: blob_info.digester(),
+            is_legacy_stargz: blob_info.is_legacy_stargz(),
+            need_validation,
+            strict_validation,
+            no_digest_reported: AtomicBool::new(false),
+            max_decompress_size: max_decompress_size(0, blob_info),
+            metrics,
+            memory_tier: None,
+            inflight: Mutex::new(HashMap::new()),
+            zero_out_of_bounds_chunks: false,
+            prefetch: false,
+        }
+    }
+
+    /// Set maximum size of decompressed data of a chunk, zero for the default.
+    pub(crate) fn set_max_decompress_size(&mut self, size: u64) {
+        self.max_decompress_size = max_decompress_size(size, &self.blob_info);
+    }
+
+    /// Fill data of chunks beyond the end of the blob with zero instead of failing reads.
+    pub(crate) fn set_zero_out_of_bounds_chunks(&mut self, enable: bool) {
+        self.zero_out_of_bounds_chunks = enable;
+    }
+
+    /// Enable or disable blob data prefetching.
+    pub(crate) fn set_prefetch(&mut self, enable: bool) {
+        self.prefetch = enable;
+    }
+
+    /// Cache decompressed data of hot chunks in `memory_tier`.
+    pub(crate) fn set_memory_tier(&mut self, memory_tier: Arc<SharedMemoryTier>) {
+        self.memory_tier = Some(memory_tier);
+    }
+
+    fn get_from_memory_tier(&self, chunk: &dyn BlobChunkInfo) -> Option<Arc<Vec<u8>>> {
+        let data = self
+            .memory_tier
+            .as_ref()?
+            .get(&(self.blob_id.clone(), chunk.id()))?;
+        self.metrics.record_hits(1, data.len() as u64);
+        Some(data)
+    }
+
+    fn insert_into_memory_tier(&self, chunk: &dyn BlobChunkInfo, data: &[u8]) {
+        if let Some(memory_tier) = self.memory_tier.as_ref() {
+            memory_tier.insert((self.blob_id.clone(), chunk.id()), data);
+        }
+    }
+
+    // Read a chunk from the storage backend into `buf`, concurrent reads of the same chunk share
+    // one backend fetch. Returns whether the chunk has been fetched by this reader.
+    fn read_chunk_shared(&self, chunk: &dyn BlobChunkInfo, buf: &mut [u8]) -> Result<bool> {
+        let (fetch, is_fetcher) = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(&chunk.id()) {
+                Some(fetch) => (fetch.clone(), false),
+                None => {
+                    let fetch = Arc::new(ChunkFetch::default());
+                    inflight.insert(chunk.id(), fetch.clone());
+                    (fetch, true)
+                }
+            }
+        };
+        if !is_fetcher {
+            let data = fetch.wait()?;
+            if data.len() != buf.len() {
+                return Err(eio!("size of shared chunk data doesn't match"));
+            }
+            buf.copy_from_slice(&data);
+            return Ok(false);
+        }
+
+        let mut guard = ChunkFetchGuard {
+            inflight: &self.inflight,
+            index: chunk.id(),
+            fetch,
+            done: false,
+        };
+        match self.read_chunk_from_backend(chunk, buf) {
+            Ok(_) => {
+                guard.finish(|| Ok(Arc::new(buf.to_vec())));
+                Ok(true)
+            }
+            Err(e) => {
+                guard.finish(|| Err((e.kind(), e.to_string())));
+                Err(e)
+            }
+        }
+    }
+
+    // Read a chunk into `buf`, and record the cache miss if it has been fetched from the backend.
+    fn read_chunk_into(&self, chunk: &dyn BlobChunkInfo, buf: &mut [u8]) -> Result<()> {
+        if self.read_chunk_shared(chunk, buf)? {
+            self.metrics
+                .record_misses(1, chunk.compressed_size() as u64);
+            self.insert_into_memory_tier(chunk, buf);
+        }
+        Ok(())
+    }
+
+    // Check whether chunks of `prev` and `next` are adjacent in the blob, or separated by a hole
+    // the backend may coalesce, so they may be fetched from the backend by one request. Chunks
+    // with fallback copies are fetched one by one, so the fallback copy may be used on failure.
+    fn is_mergeable(&self, prev: &BlobIoDesc, next: &BlobIoDesc, max_gap: u64) -> bool {
+        !self.is_legacy_stargz
+            && !prev.chunkinfo.is_batch()
+            && !next.chunkinfo.is_batch()
+            && prev.chunkinfo.uncompressed_fallback().is_none()
+            && next.chunkinfo.uncompressed_fallback().is_none()
+            && prev.is_continuous(next, max_gap)
+    }
 }
 
 impl BlobCache for DummyCache {
@@ -84,14 +282,37 @@ impl BlobCache for DummyCache {
         self.is_legacy_stargz
     }
 
+    fn max_decompress_size(&self) -> u64 {
+        self.max_decompress_size
+    }
+
     fn need_validation(&self) -> bool {
         self.need_validation
     }
 
+    fn strict_validation(&self) -> bool {
+        self.strict_validation
+    }
+
+    fn report_no_digest_chunk(&self, chunk: &dyn BlobChunkInfo) {
+        if !self.no_digest_reported.swap(true, Ordering::Relaxed) {
+            warn!(
+                "chunk {} of blob {} has no digest, data of the blob can't be fully validated",
+                chunk.id(),
+                self.blob_id
+            );
+        }
+    }
+
     fn reader(&self) -> &dyn BlobReader {
         &*self.reader
     }
 
+    #[cfg(feature = "backend-async")]
+    fn shared_reader(&self) -> Arc<dyn BlobReader> {
+        self.reader.clone()
+    }
+
     fn get_chunk_map(&self) -> &Arc<dyn ChunkMap> {
         &self.chunk_map
     }
@@ -100,11 +321,30 @@ impl BlobCache for DummyCache {
         None
     }
 
+    fn metrics(&self) -> CacheMetrics {
+        self.metrics.clone()
+    }
+
     fn start_prefetch(&self) -> StorageResult<()> {
         Ok(())
     }
 
     fn stop_prefetch(&self) -> StorageResult<()> {
+        // Stopping prefetch is best effort, failing to cancel backend prefetching is harmless.
+        if !self.prefetch
+            || !self
+                .reader
+                .capabilities()
+                .contains(BackendCaps::SUPPORTS_PREFETCH)
+        {
+            return Ok(());
+        }
+        if let Err(e) = self.reader.stop_prefetch_data() {
+            warn!(
+                "failed to stop prefetching data for blob {}, {}",
+                self.blob_id, e
+            );
+        }
         Ok(())
     }
 
@@ -121,12 +361,32 @@ impl BlobCache for DummyCache {
         Err(StorageError::Unsupported)
     }
 
-    fn read(&self, iovec: &mut BlobIoVec, bufs: &[FileVolatileSlice]) -> Result<usize> {
+    fn read_with_stats(
+        &self,
+        iovec: &mut BlobIoVec,
+        bufs: &[FileVolatileSlice],
+    ) -> Result<ReadStats> {
+        // Reject chunks with absurd decompressed size first, whether they are in bounds or not.
+        for bio in iovec.bi_vec.iter() {
+            self.check_decompress_size(&bio.chunkinfo)?;
+        }
+        if let Some(stats) = read_out_of_bounds_chunks(
+            self,
+            self.zero_out_of_bounds_chunks,
+            iovec,
+            bufs,
+            |iovec, bufs| self.read_with_stats(iovec, bufs),
+        )? {
+            return Ok(stats);
+        }
+        // Always return decompressed data.
+        iovec.set_data_compressor(None);
         let bios = &iovec.bi_vec;
 
         if iovec.size() == 0 || bios.is_empty() {
             return Err(einval!("parameter `bios` is empty"));
         }
+        let mut stats = ReadStats::default();
 
         let bios_len = bios.len();
         let offset = bios[0].offset;
@@ -134,35 +394,165 @@ impl BlobCache for DummyCache {
         // Use the destination buffer to receive the uncompressed data if possible.
         if bufs.len() == 1 && bios_len == 1 && offset == 0 && bufs[0].len() >= d_size {
             if !bios[0].user_io {
-                return Ok(0);
+                return Ok(stats);
             }
             let buf = unsafe { std::slice::from_raw_parts_mut(bufs[0].as_ptr(), d_size) };
-            self.read_chunk_from_backend(&bios[0].chunkinfo, buf)?;
-            return Ok(buf.len());
-        }
-
-        let mut user_size = 0;
-        let mut buffer_holder: Vec<Vec<u8>> = Vec::with_capacity(bios.len());
-        for bio in bios.iter() {
-            if bio.user_io {
-                let mut d = alloc_buf(bio.chunkinfo.uncompressed_size() as usize);
-                self.read_chunk_from_backend(&bio.chunkinfo, d.as_mut_slice())?;
-                buffer_holder.push(d);
-                // Even a merged IO can hardly reach u32::MAX. So this is safe
-                user_size += bio.size;
+            if let Some(data) = self.get_from_memory_tier(&bios[0].chunkinfo) {
+                buf.copy_from_slice(&data);
+                stats.from_cache = buf.len();
+            } else {
+                self.read_chunk_into(&bios[0].chunkinfo, buf)?;
+                stats.from_backend = buf.len();
+                stats.chunks_fetched = 1;
+            }
+            stats.total = buf.len();
+            return Ok(stats);
+        }
+
+        // Only fetch the requested segment of a chunk, unless the whole chunk is needed to fill
+        // the memory cache.
+        if bios_len == 1
+            && bios[0].user_io
+            && self.memory_tier.is_none()
+            && (bios[0].size as usize) < d_size
+        {
+            let mut d = alloc_buf(bios[0].size as usize);
+            if self.read_chunk_segment_from_backend(&bios[0].chunkinfo, offset, &mut d)? {
+                self.metrics.record_misses(1, d.len() as u64);
+                let (size, _) =
+                    copyv(&[d], bufs, 0, bios[0].size as usize, 0, 0).map_err(|e| eother!(e))?;
+                stats.total = size;
+                stats.from_backend = size;
+                stats.chunks_fetched = 1;
+                return Ok(stats);
             }
         }
 
-        copyv(
-            &buffer_holder,
+        // Even a merged IO can hardly reach u32::MAX. So this is safe
+        let user_size = bios
+            .iter()
+            .filter(|bio| bio.user_io)
+            .fold(0usize, |size, bio| size + bio.size as usize);
+        let mut cursor = IoCursor {
             bufs,
-            offset as usize,
-            user_size as usize,
-            0,
-            0,
-        )
-        .map(|(n, _)| n)
-        .map_err(|e| eother!(e))
+            index: 0,
+            offset: 0,
+            skip: offset as usize,
+            left: user_size,
+        };
+        let max_gap = max_coalesce_gap(self.reader.as_ref());
+        let mut idx = 0;
+        while idx < bios_len {
+            if !bios[idx].user_io {
+                idx += 1;
+                continue;
+            }
+            let left = cursor.left;
+            if let Some(data) = self.get_from_memory_tier(&bios[idx].chunkinfo) {
+                cursor.write(&data)?;
+                stats.from_cache += left - cursor.left;
+                idx += 1;
+                continue;
+            }
+
+            // Coalesce chunks adjacent in the blob, or separated by small holes if the backend
+            // supports it, into one backend request.
+            let mut range = BlobIoRange::new(&bios[idx], bios_len - idx);
+            let mut end = idx + 1;
+            while end < bios_len
+                && bios[end].user_io
+                && self.is_mergeable(&bios[end - 1], &bios[end], max_gap)
+            {
+                range.merge(&bios[end], max_gap);
+                end += 1;
+            }
+
+            if range.chunks.len() == 1 {
+                let chunk = &bios[idx].chunkinfo;
+                let size = chunk.uncompressed_size() as usize;
+                // Decompress the chunk into the destination buffer directly if it's contiguous.
+                if let Some(buf) = cursor.contiguous(size) {
+                    self.read_chunk_into(chunk, buf)?;
+                    cursor.advance(size);
+                } else {
+                    let mut d = alloc_buf(size);
+                    self.read_chunk_into(chunk, &mut d)?;
+                    cursor.write(&d)?;
+                }
+            } else {
+                let state = self.read_chunks_from_backend(
+                    range.blob_offset,
+                    range.blob_size as usize,
+                    &range.chunks,
+                    false,
+                )?;
+                self.metrics
+                    .record_misses(range.chunks.len() as u64, range.blob_size);
+                // Chunks following the first one may have been cached in memory, it's still cheaper
+                // to fetch them by the same backend request than splitting the request.
+                for (chunk, d) in range.chunks.iter().zip(state) {
+                    let d = d?;
+                    self.insert_into_memory_tier(chunk.as_ref(), &d);
+                    cursor.write(&d)?;
+                }
+            }
+            stats.from_backend += left - cursor.left;
+            stats.chunks_fetched += range.chunks.len();
+            idx = end;
+        }
+
+        stats.total = user_size - cursor.left;
+        Ok(stats)
+    }
+}
+
+// Cursor to write data of chunks in order into destination buffers of a request.
+struct IoCursor<'a, 'b> {
+    bufs: &'a [FileVolatileSlice<'b>],
+    index: usize,
+    offset: usize,
+    // Bytes to skip at the start of the first chunk.
+    skip: usize,
+    // Bytes still wanted by the request.
+    left: usize,
+}
+
+impl IoCursor<'_, '_> {
+    // Get the destination buffer for the whole data of a chunk, if it's contiguous and wanted.
+    fn contiguous(&self, size: usize) -> Option<&mut [u8]> {
+        if self.skip != 0 || size > self.left || self.index >= self.bufs.len() {
+            return None;
+        }
+        let buf = &self.bufs[self.index];
+        if buf.len() - self.offset < size {
+            return None;
+        }
+        Some(unsafe { std::slice::from_raw_parts_mut(buf.as_ptr().add(self.offset), size) })
+    }
+
+    fn advance(&mut self, size: usize) {
+        self.left -= size;
+        self.offset += size;
+        if self.offset == self.bufs[self.index].len() {
+            self.index += 1;
+            self.offset = 0;
+        }
+    }
+
+    // Copy the wanted part of chunk data into destination buffers.
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if self.skip > data.len() {
+            return Err(eother!(StorageError::MemOverflow));
+        }
+        let size = std::cmp::min(data.len() - self.skip, self.left);
+        let (copied, (index, offset)) =
+            copyv(&[data], self.bufs, self.skip, size, self.index, self.offset)
+                .map_err(|e| eother!(e))?;
+        self.skip = 0;
+        self.left -= copied;
+        self.index = index;
+        self.offset = offset;
+        Ok(())
     }
 }
 
@@ -176,7 +566,15 @@ pub struct DummyCacheMgr {
     backend: Arc<dyn BlobBackend>,
     cached: bool,
     need_validation: bool,
+    validate_strict: bool,
+    max_decompress_size: u64,
+    zero_out_of_bounds_chunks: bool,
+    prefetch: bool,
     closed: AtomicBool,
+    metrics: Arc<CacheMetrics>,
+    memory_tier: Option<Arc<SharedMemoryTier>>,
+    // Uncompressed sizes of blobs accessed through the manager, indexed by blob id.
+    blobs: Mutex<BTreeMap<String, u64>>,
 }
 
 impl DummyCacheMgr {
@@ -190,7 +588,18 @@ impl DummyCacheMgr {
             backend,
             cached,
             need_validation: config.cache_validate,
+            validate_strict: config.cache_validate_strict,
+            max_decompress_size: config.max_decompress_size,
+            zero_out_of_bounds_chunks: config.zero_out_of_bounds_chunks,
+            prefetch: config.prefetch.enable,
             closed: AtomicBool::new(false),
+            metrics: Arc::new(CacheMetrics::default()),
+            memory_tier: if config.memory_tier_size > 0 {
+                Some(Arc::new(SharedMemoryTier::new(config.memory_tier_size)))
+            } else {
+                None
+            },
+            blobs: Mutex::new(BTreeMap::new()),
         })
     }
 }
@@ -224,20 +633,48 @@ impl BlobCacheMgr for DummyCacheMgr {
 
         let blob_id = blob_info.blob_id();
         let reader = self.backend.get_reader(&blob_id).map_err(|e| eother!(e))?;
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(blob_id, blob_info.uncompressed_size());
 
-        Ok(Arc::new(DummyCache {
-            blob_id,
-            blob_info: blob_info.clone(),
-            chunk_map: Arc::new(NoopChunkMap::new(self.cached)),
+        let mut cache = DummyCache::new(
+            blob_info,
             reader,
-            compressor: blob_info.compressor(),
-            digester: blob_info.digester(),
-            is_legacy_stargz: blob_info.is_legacy_stargz(),
-            need_validation: self.need_validation && !blob_info.is_legacy_stargz(),
-        }))
+            self.cached,
+            self.need_validation && !blob_info.is_legacy_stargz(),
+            self.validate_strict,
+            CacheMetrics::new(Some(self.metrics.clone())),
+        );
+        cache.set_max_decompress_size(self.max_decompress_size);
+        cache.set_zero_out_of_bounds_chunks(self.zero_out_of_bounds_chunks);
+        cache.set_prefetch(self.prefetch);
+        if let Some(memory_tier) = self.memory_tier.as_ref() {
+            cache.set_memory_tier(memory_tier.clone());
+        }
+
+        Ok(Arc::new(cache))
     }
 
     fn check_stat(&self) {}
+
+    fn metrics(&self) -> CacheMetrics {
+        self.metrics.as_ref().clone()
+    }
+
+    // Nothing is cached, so only report blobs and their sizes.
+    fn list_blobs(&self) -> Vec<BlobCacheStatus> {
+        self.blobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(blob_id, size)| BlobCacheStatus {
+                blob_id: blob_id.clone(),
+                total_size: *size,
+                ..Default::default()
+            })
+            .collect()
+    }
 }
 
 impl Drop for DummyCacheMgr {
@@ -254,15 +691,252 @@ mod tests {
     use nydus_utils::metrics::BackendMetrics;
     use vmm_sys_util::tempdir::TempDir;
 
+    use std::any::Any;
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicUsize;
+
+    use nydus_utils::digest::RafsDigest;
+
     use crate::{
-        cache::state::IndexedChunkMap,
-        device::{BlobIoChunk, BlobIoRange},
+        backend::{BackendError, BackendResult},
+        cache::{state::IndexedChunkMap, CacheError},
+        device::{BlobChunkFlags, BlobIoChunk, BlobIoKind, BlobIoRange},
         meta::tests::DummyBlobReader,
         test::{MockBackend, MockChunkInfo},
+        utils::check_digest,
     };
 
     use super::*;
 
+    struct UrlChunkInfo {
+        inner: MockChunkInfo,
+        url: Option<String>,
+    }
+
+    impl BlobChunkInfo for UrlChunkInfo {
+        fn chunk_id(&self) -> &RafsDigest {
+            self.inner.chunk_id()
+        }
+
+        fn id(&self) -> u32 {
+            self.inner.id()
+        }
+
+        fn blob_index(&self) -> u32 {
+            self.inner.blob_index()
+        }
+
+        fn compressed_offset(&self) -> u64 {
+            self.inner.compressed_offset()
+        }
+
+        fn compressed_size(&self) -> u32 {
+            self.inner.compressed_size()
+        }
+
+        fn uncompressed_offset(&self) -> u64 {
+            self.inner.uncompressed_offset()
+        }
+
+        fn uncompressed_size(&self) -> u32 {
+            self.inner.uncompressed_size()
+        }
+
+        fn is_batch(&self) -> bool {
+            false
+        }
+
+        fn is_compressed(&self) -> bool {
+            false
+        }
+
+        fn is_encrypted(&self) -> bool {
+            false
+        }
+
+        fn source_url(&self) -> Option<&str> {
+            self.url.as_deref()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    struct FallbackChunkInfo {
+        inner: MockChunkInfo,
+        fallback: Option<(u64, u32)>,
+    }
+
+    impl BlobChunkInfo for FallbackChunkInfo {
+        fn chunk_id(&self) -> &RafsDigest {
+            self.inner.chunk_id()
+        }
+
+        fn id(&self) -> u32 {
+            self.inner.id()
+        }
+
+        fn blob_index(&self) -> u32 {
+            self.inner.blob_index()
+        }
+
+        fn compressed_offset(&self) -> u64 {
+            self.inner.compressed_offset()
+        }
+
+        fn compressed_size(&self) -> u32 {
+            self.inner.compressed_size()
+        }
+
+        fn uncompressed_offset(&self) -> u64 {
+            self.inner.uncompressed_offset()
+        }
+
+        fn uncompressed_size(&self) -> u32 {
+            self.inner.uncompressed_size()
+        }
+
+        fn is_batch(&self) -> bool {
+            self.inner.is_batch()
+        }
+
+        fn is_compressed(&self) -> bool {
+            self.inner.is_compressed()
+        }
+
+        fn is_encrypted(&self) -> bool {
+            self.inner.is_encrypted()
+        }
+
+        fn uncompressed_fallback(&self) -> Option<(u64, u32)> {
+            self.fallback
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    struct UrlBlobReader {
+        blob: Vec<u8>,
+        sources: HashMap<String, Vec<u8>>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for UrlBlobReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.blob.len() as u64)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            let offset = offset as usize;
+            let size = std::cmp::min(buf.len(), self.blob.len().saturating_sub(offset));
+            buf[..size].copy_from_slice(&self.blob[offset..offset + size]);
+            Ok(size)
+        }
+
+        fn read_url(&self, url: &str, buf: &mut [u8]) -> BackendResult<usize> {
+            let data = self
+                .sources
+                .get(url)
+                .ok_or_else(|| BackendError::Unsupported(format!("{} not found", url)))?;
+            let size = std::cmp::min(buf.len(), data.len());
+            buf[..size].copy_from_slice(&data[..size]);
+            Ok(size)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    struct CountingBlobReader {
+        blob: Vec<u8>,
+        reads: AtomicUsize,
+        bytes: AtomicUsize,
+        stops: AtomicUsize,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for CountingBlobReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.blob.len() as u64)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            let offset = offset as usize;
+            let size = std::cmp::min(buf.len(), self.blob.len().saturating_sub(offset));
+            buf[..size].copy_from_slice(&self.blob[offset..offset + size]);
+            self.bytes.fetch_add(size, Ordering::Relaxed);
+            Ok(size)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn stop_prefetch_data(&self) -> BackendResult<()> {
+            self.stops.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn capabilities(&self) -> BackendCaps {
+            BackendCaps::all()
+        }
+
+        #[cfg(feature = "backend-async")]
+        fn try_read_async(
+            self: Arc<Self>,
+            size: usize,
+            offset: u64,
+        ) -> crate::backend::BackendReadFuture {
+            Box::pin(async move {
+                let mut buf = vec![0u8; size];
+                let size = self.try_read(&mut buf, offset)?;
+                buf.truncate(size);
+                Ok(buf)
+            })
+        }
+    }
+
+    struct SlowBlobReader {
+        blob: Vec<u8>,
+        reads: AtomicUsize,
+        fail: bool,
+        panic: bool,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl BlobReader for SlowBlobReader {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.blob.len() as u64)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            if self.panic {
+                panic!("mock backend panicked");
+            } else if self.fail {
+                return Err(BackendError::Unsupported("mock failure".to_string()));
+            }
+            let offset = offset as usize;
+            let size = std::cmp::min(buf.len(), self.blob.len().saturating_sub(offset));
+            buf[..size].copy_from_slice(&self.blob[offset..offset + size]);
+            Ok(size)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn retry_limit(&self) -> u8 {
+            0
+        }
+    }
+
     #[test]
     fn test_dummy_cache() {
         let info = BlobInfo::new(
@@ -306,6 +980,14 @@ mod tests {
             digester: digest::Algorithm::Blake3,
             is_legacy_stargz: false,
             need_validation: false,
+            strict_validation: false,
+            no_digest_reported: AtomicBool::new(false),
+            max_decompress_size: crate::RAFS_MAX_CHUNK_SIZE,
+            metrics: CacheMetrics::default(),
+            memory_tier: None,
+            inflight: Mutex::new(HashMap::new()),
+            zero_out_of_bounds_chunks: false,
+            prefetch: false,
         };
 
         let cache_unuse = DummyCache {
@@ -317,6 +999,14 @@ mod tests {
             digester: digest::Algorithm::Blake3,
             is_legacy_stargz: false,
             need_validation: false,
+            strict_validation: false,
+            no_digest_reported: AtomicBool::new(false),
+            max_decompress_size: crate::RAFS_MAX_CHUNK_SIZE,
+            metrics: CacheMetrics::default(),
+            memory_tier: None,
+            inflight: Mutex::new(HashMap::new()),
+            zero_out_of_bounds_chunks: false,
+            prefetch: false,
         };
 
         assert!(cache.get_legacy_stargz_size(0, 100).is_ok());
@@ -346,6 +1036,8 @@ mod tests {
             blob_id: "blob-0".to_string(),
             offset: 0,
             len: 10,
+            merging_size: None,
+            bandwidth_rate: None,
         };
         let iovec_arr: &[BlobIoDesc] = &[];
         let reqs = &[reqs];
@@ -365,6 +1057,7 @@ mod tests {
             uncompress_offset: 0,
             file_offset: 0,
             index: 0,
+            generation: 0,
             reserved: 0,
         });
         iovec.push(BlobIoDesc::new(
@@ -391,6 +1084,7 @@ mod tests {
             uncompress_offset: 0,
             file_offset: 0,
             index: 0,
+            generation: 0,
             reserved: 0,
         });
 
@@ -404,6 +1098,7 @@ mod tests {
             uncompress_offset: 0,
             file_offset: 0,
             index: 0,
+            generation: 0,
             reserved: 0,
         });
 
@@ -436,6 +1131,70 @@ mod tests {
         assert_eq!(cache.read(&mut iovec, bufs).unwrap(), 200);
     }
 
+    #[test]
+    fn test_dummy_cache_truncated_blob() {
+        let info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            800,
+            800,
+            8,
+            100,
+            BlobFeatures::empty(),
+        );
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-0");
+        let f = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .read(true)
+            .open(blob_path)
+            .unwrap();
+        // The blob is truncated to 400 bytes after building the image.
+        f.set_len(400).unwrap();
+        let reader: Arc<dyn BlobReader> = Arc::new(DummyBlobReader {
+            metrics: BackendMetrics::new("dummy", "localfs"),
+            file: f,
+        });
+        let cache = DummyCache {
+            blob_id: "blob-0".to_string(),
+            blob_info: Arc::new(info),
+            chunk_map: Arc::new(NoopChunkMap::new(false)),
+            reader,
+            compressor: compress::Algorithm::None,
+            digester: digest::Algorithm::Blake3,
+            is_legacy_stargz: false,
+            need_validation: false,
+            strict_validation: false,
+            no_digest_reported: AtomicBool::new(false),
+            max_decompress_size: crate::RAFS_MAX_CHUNK_SIZE,
+            metrics: CacheMetrics::default(),
+            memory_tier: None,
+            inflight: Mutex::new(HashMap::new()),
+            zero_out_of_bounds_chunks: false,
+            prefetch: false,
+        };
+
+        let chunk = MockChunkInfo {
+            compress_size: 200,
+            uncompress_size: 200,
+            compress_offset: 0,
+            ..Default::default()
+        };
+        let mut buf = vec![0u8; 200];
+        assert!(cache.read_chunk_from_backend(&chunk, &mut buf).is_ok());
+
+        let chunk = MockChunkInfo {
+            compress_size: 200,
+            uncompress_size: 200,
+            compress_offset: 300,
+            ..Default::default()
+        };
+        let err = cache.read_chunk_from_backend(&chunk, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(err.to_string().contains("truncated"));
+    }
+
     #[test]
     fn test_dummy_cache_mgr() {
         let content = r#"version=2
@@ -469,4 +1228,1560 @@ mod tests {
         assert!(mgr.closed.load(Ordering::Acquire));
         drop(mgr);
     }
+
+    #[cfg(feature = "backend-localfs")]
+    #[test]
+    fn test_dummy_cache_mgr_localfs() {
+        use crate::backend::localfs::LocalFs;
+        use nydus_api::LocalFsConfig;
+
+        let mut blob = Vec::new();
+        let mut chunks = Vec::new();
+        for idx in 0..4u32 {
+            let data = vec![idx as u8 + 1; 0x1000];
+            let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: idx as u64 * 0x1000,
+                index: idx,
+                ..Default::default()
+            });
+            blob.extend_from_slice(&compressed);
+            chunks.push(chunk);
+        }
+        let tmp_dir = TempDir::new().unwrap();
+        std::fs::write(tmp_dir.as_path().join("blob-0"), &blob).unwrap();
+
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            blob.len() as u64,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+
+        let backend = LocalFs::new(
+            &LocalFsConfig {
+                dir: tmp_dir.as_path().to_str().unwrap().to_string(),
+                ..Default::default()
+            },
+            Some("blob-0"),
+        )
+        .unwrap();
+        let config = CacheConfigV2 {
+            cache_validate: true,
+            ..Default::default()
+        };
+        let mgr = DummyCacheMgr::new(&config, Arc::new(backend), true).unwrap();
+        let cache = mgr.get_blob_cache(&info).unwrap();
+        assert_eq!(cache.blob_compressed_size().unwrap(), blob.len() as u64);
+        let status = mgr.list_blobs();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].blob_id, "blob-0");
+        assert_eq!(status[0].total_size, 0x4000);
+        assert_eq!(status[0].materialized_size, 0);
+
+        // Chunks are read from the blob file `<dir>/<blob_id>` and validated by digest.
+        for chunk in chunks.iter() {
+            let mut iovec = BlobIoVec::new(info.clone());
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk.clone()),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x1000);
+            assert!(buf.iter().all(|v| *v == chunk.id() as u8 + 1));
+            assert!(check_digest(
+                &buf,
+                chunk.chunk_id(),
+                digest::Algorithm::Blake3
+            ));
+        }
+
+        // Chunks beyond the end of the blob file can't be read.
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            flags: BlobChunkFlags::COMPRESSED,
+            compress_size: 0x100,
+            uncompress_size: 0x1000,
+            compress_offset: blob.len() as u64,
+            uncompress_offset: 0x4000,
+            index: 4,
+            ..Default::default()
+        });
+        let mut iovec = BlobIoVec::new(info.clone());
+        iovec.push(BlobIoDesc::new(
+            info.clone(),
+            BlobIoChunk::from(chunk),
+            0,
+            0x1000,
+            true,
+        ));
+        let mut buf = vec![0u8; 0x1000];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        assert!(cache.read(&mut iovec, &[slice]).is_err());
+        mgr.destroy();
+    }
+
+    #[test]
+    fn test_dummy_cache_chunk_source_url() {
+        let mut sources = HashMap::new();
+        sources.insert("http://origin-a/chunk".to_string(), vec![0xaau8; 100]);
+        sources.insert("http://origin-b/chunk".to_string(), vec![0xbbu8; 100]);
+        let reader: Arc<dyn BlobReader> = Arc::new(UrlBlobReader {
+            blob: vec![0x11u8; 300],
+            sources,
+            metrics: BackendMetrics::new("dummy", "http-proxy"),
+        });
+        let info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            300,
+            300,
+            8,
+            3,
+            BlobFeatures::empty(),
+        );
+        let cache = DummyCache {
+            blob_id: "blob-0".to_string(),
+            blob_info: Arc::new(info),
+            chunk_map: Arc::new(NoopChunkMap::new(false)),
+            reader,
+            compressor: compress::Algorithm::None,
+            digester: digest::Algorithm::Blake3,
+            is_legacy_stargz: false,
+            need_validation: false,
+            strict_validation: false,
+            no_digest_reported: AtomicBool::new(false),
+            max_decompress_size: crate::RAFS_MAX_CHUNK_SIZE,
+            metrics: CacheMetrics::default(),
+            memory_tier: None,
+            inflight: Mutex::new(HashMap::new()),
+            zero_out_of_bounds_chunks: false,
+            prefetch: false,
+        };
+
+        let new_chunk = |idx: u32, url: Option<&str>| -> Arc<dyn BlobChunkInfo> {
+            Arc::new(UrlChunkInfo {
+                inner: MockChunkInfo {
+                    compress_size: 100,
+                    uncompress_size: 100,
+                    compress_offset: idx as u64 * 100,
+                    uncompress_offset: idx as u64 * 100,
+                    index: idx,
+                    ..Default::default()
+                },
+                url: url.map(|v| v.to_string()),
+            })
+        };
+        let chunks = vec![
+            new_chunk(0, None),
+            new_chunk(1, Some("http://origin-a/chunk")),
+            new_chunk(2, Some("http://origin-b/chunk")),
+        ];
+
+        let mut buf = vec![0u8; 100];
+        for (chunk, val) in chunks.iter().zip([0x11u8, 0xaa, 0xbb]) {
+            cache
+                .read_chunk_from_backend(chunk.as_ref(), &mut buf)
+                .unwrap();
+            assert!(buf.iter().all(|v| *v == val));
+        }
+
+        let state = cache
+            .read_chunks_from_backend(0, 300, &chunks, false)
+            .unwrap();
+        let bufs = state.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(bufs.len(), 3);
+        for (buf, val) in bufs.iter().zip([0x11u8, 0xaa, 0xbb]) {
+            assert!(buf.iter().all(|v| *v == val));
+        }
+
+        let chunk = new_chunk(1, Some("http://origin-c/chunk"));
+        assert!(cache
+            .read_chunk_from_backend(chunk.as_ref(), &mut buf)
+            .is_err());
+
+        let desc = |chunk: &Arc<dyn BlobChunkInfo>| BlobIoDesc {
+            blob: cache.blob_info.clone(),
+            chunkinfo: chunk.clone().into(),
+            offset: 0,
+            size: 100,
+            user_io: true,
+            kind: BlobIoKind::default(),
+        };
+        assert!(!desc(&chunks[0]).is_continuous(&desc(&chunks[1]), 0x1000));
+        assert!(!desc(&chunks[1]).is_continuous(&desc(&chunks[2]), 0x1000));
+    }
+
+    #[test]
+    fn test_dummy_cache_zstd_chunk() {
+        let data = vec![0x5au8; 0x2000];
+        let (compressed, is_compressed) = compress::compress(&data, compress::Algorithm::Zstd)
+            .map(|(v, c)| (v.to_vec(), c))
+            .unwrap();
+        assert!(is_compressed);
+        let mut blob = vec![0x11u8; 0x100];
+        blob.extend_from_slice(&compressed);
+        blob.extend_from_slice(&[0x22u8; 0x100]);
+        let blob_size = blob.len() as u64;
+        let reader: Arc<dyn BlobReader> = Arc::new(UrlBlobReader {
+            blob,
+            sources: HashMap::new(),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x2000,
+            blob_size,
+            0x2000,
+            1,
+            BlobFeatures::empty(),
+        );
+        let new_cache = |is_legacy_stargz: bool| DummyCache {
+            blob_id: "blob-0".to_string(),
+            blob_info: Arc::new(info.clone()),
+            chunk_map: Arc::new(NoopChunkMap::new(false)),
+            reader: reader.clone(),
+            compressor: compress::Algorithm::Zstd,
+            digester: digest::Algorithm::Blake3,
+            is_legacy_stargz,
+            need_validation: true,
+            strict_validation: false,
+            no_digest_reported: AtomicBool::new(false),
+            max_decompress_size: crate::RAFS_MAX_CHUNK_SIZE,
+            metrics: CacheMetrics::default(),
+            memory_tier: None,
+            inflight: Mutex::new(HashMap::new()),
+            zero_out_of_bounds_chunks: false,
+            prefetch: false,
+        };
+        let new_chunk = |compress_size: u32| -> Arc<dyn BlobChunkInfo> {
+            Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size,
+                uncompress_size: 0x2000,
+                compress_offset: 0x100,
+                ..Default::default()
+            })
+        };
+
+        // Round trip with exact compressed size, validated by chunk digest.
+        let cache = new_cache(false);
+        let chunks = vec![new_chunk(compressed.len() as u32)];
+        let state = cache
+            .read_chunks_from_backend(0x100, compressed.len(), &chunks, false)
+            .unwrap();
+        let bufs = state.collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(bufs.len(), 1);
+        assert_eq!(bufs[0], data);
+        let mut buf = vec![0u8; 0x2000];
+        cache
+            .read_chunk_from_backend(chunks[0].as_ref(), &mut buf)
+            .unwrap();
+        assert_eq!(buf, data);
+
+        // The compressed size is rounded up beyond the tail of the blob, so the size to fetch
+        // gets estimated and limited by the blob size, and data following the chunk is ignored.
+        let cache = new_cache(true);
+        let chunk = new_chunk(compressed.len() as u32 + 0x1000);
+        let size = cache.get_legacy_stargz_size(0x100, 0x2000).unwrap();
+        assert_eq!(size, compressed.len() + 0x100);
+        let mut buf = vec![0u8; 0x2000];
+        cache
+            .read_chunk_from_backend(chunk.as_ref(), &mut buf)
+            .unwrap();
+        assert!(check_digest(
+            &buf,
+            chunk.chunk_id(),
+            digest::Algorithm::Blake3
+        ));
+    }
+
+    #[test]
+    fn test_dummy_cache_merge_adjacent_chunks() {
+        let mut blob = Vec::new();
+        let mut chunks = Vec::new();
+        for idx in 0..4u32 {
+            let data = vec![idx as u8 + 1; 0x1000];
+            let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: idx as u64 * 0x1000,
+                index: idx,
+                ..Default::default()
+            });
+            blob.extend_from_slice(&compressed);
+            chunks.push(chunk);
+        }
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            blob.len() as u64,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let reader = Arc::new(CountingBlobReader {
+            blob,
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let cache = DummyCache::new(
+            &info,
+            reader.clone(),
+            false,
+            true,
+            false,
+            CacheMetrics::default(),
+        );
+
+        let read = |chunks: &[&Arc<dyn BlobChunkInfo>]| {
+            let mut iovec = BlobIoVec::new(info.clone());
+            for chunk in chunks {
+                iovec.push(BlobIoDesc::new(
+                    info.clone(),
+                    BlobIoChunk::from((*chunk).clone()),
+                    0,
+                    0x1000,
+                    true,
+                ));
+            }
+            let mut buf = vec![0u8; chunks.len() * 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            reader.reads.store(0, Ordering::Relaxed);
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), buf.len());
+            for (idx, chunk) in chunks.iter().enumerate() {
+                let expected = chunk.id() as u8 + 1;
+                assert!(buf[idx * 0x1000..(idx + 1) * 0x1000]
+                    .iter()
+                    .all(|v| *v == expected));
+            }
+            reader.reads.load(Ordering::Relaxed)
+        };
+
+        // A run of adjacent chunks is fetched by one backend request.
+        assert_eq!(read(&[&chunks[0], &chunks[1], &chunks[2], &chunks[3]]), 1);
+        // Chunks not adjacent in the blob are fetched separately.
+        assert_eq!(read(&[&chunks[0], &chunks[2], &chunks[3]]), 2);
+    }
+
+    #[test]
+    fn test_dummy_cache_validate_xxh64_digest() {
+        let data = vec![0x5au8; 0x1000];
+        let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            compressed.len() as u64,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Xxh64);
+        let info = Arc::new(info);
+        let reader = Arc::new(CountingBlobReader {
+            blob: compressed.to_vec(),
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let cache = DummyCache::new(&info, reader, false, true, false, CacheMetrics::default());
+
+        let read = |block_id: RafsDigest| {
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id,
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                ..Default::default()
+            });
+            let mut iovec = BlobIoVec::new(info.clone());
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            cache.read(&mut iovec, &[slice])
+        };
+
+        assert_eq!(
+            read(RafsDigest::from_buf(&data, digest::Algorithm::Xxh64)).unwrap(),
+            0x1000
+        );
+        // Digests calculated by other algorithms don't match.
+        assert!(read(RafsDigest::from_buf(&data, digest::Algorithm::Blake3)).is_err());
+    }
+
+    #[test]
+    fn test_dummy_cache_coalesce_chunks_with_holes() {
+        struct CoalescingBlobReader {
+            inner: CountingBlobReader,
+            max_gap: u64,
+        }
+
+        impl BlobReader for CoalescingBlobReader {
+            fn blob_size(&self) -> BackendResult<u64> {
+                self.inner.blob_size()
+            }
+
+            fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+                self.inner.try_read(buf, offset)
+            }
+
+            fn metrics(&self) -> &BackendMetrics {
+                self.inner.metrics()
+            }
+
+            fn max_coalesce_gap(&self) -> u64 {
+                self.max_gap
+            }
+
+            fn capabilities(&self) -> BackendCaps {
+                self.inner.capabilities()
+            }
+        }
+
+        // Chunks are separated by holes of 0x40 bytes.
+        let mut blob = Vec::new();
+        let mut chunks = Vec::new();
+        for idx in 0..4u32 {
+            blob.extend_from_slice(&[0xffu8; 0x40]);
+            let data = vec![idx as u8 + 1; 0x1000];
+            let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: idx as u64 * 0x1000,
+                index: idx,
+                ..Default::default()
+            });
+            blob.extend_from_slice(&compressed);
+            chunks.push(chunk);
+        }
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            blob.len() as u64,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+
+        let read = |max_gap: u64, chunks: &[&Arc<dyn BlobChunkInfo>]| {
+            let reader = Arc::new(CoalescingBlobReader {
+                inner: CountingBlobReader {
+                    blob: blob.clone(),
+                    reads: AtomicUsize::new(0),
+                    bytes: AtomicUsize::new(0),
+                    stops: AtomicUsize::new(0),
+                    metrics: BackendMetrics::new("dummy", "localfs"),
+                },
+                max_gap,
+            });
+            let cache = DummyCache::new(
+                &info,
+                reader.clone(),
+                false,
+                true,
+                false,
+                CacheMetrics::default(),
+            );
+            let mut iovec = BlobIoVec::new(info.clone());
+            for chunk in chunks {
+                iovec.push(BlobIoDesc::new(
+                    info.clone(),
+                    BlobIoChunk::from((*chunk).clone()),
+                    0,
+                    0x1000,
+                    true,
+                ));
+            }
+            let mut buf = vec![0u8; chunks.len() * 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), buf.len());
+            for (idx, chunk) in chunks.iter().enumerate() {
+                let expected = chunk.id() as u8 + 1;
+                assert!(buf[idx * 0x1000..(idx + 1) * 0x1000]
+                    .iter()
+                    .all(|v| *v == expected));
+            }
+            (
+                reader.inner.reads.load(Ordering::Relaxed),
+                reader.inner.bytes.load(Ordering::Relaxed),
+            )
+        };
+
+        let all = [&chunks[0], &chunks[1], &chunks[2], &chunks[3]];
+        let data_size: usize = chunks.iter().map(|c| c.compressed_size() as usize).sum();
+        // Without coalescing, each chunk is fetched by its own request.
+        assert_eq!(read(0, &all), (4, data_size));
+        // Holes no bigger than the gap are fetched and discarded, saving round trips.
+        assert_eq!(read(0x40, &all), (1, data_size + 3 * 0x40));
+        // Holes bigger than the gap still split requests.
+        assert_eq!(read(0x3f, &all).0, 4);
+        let (reads, _) = read(0x40, &[&chunks[0], &chunks[2], &chunks[3]]);
+        assert_eq!(reads, 2);
+    }
+
+    #[cfg(feature = "backend-async")]
+    #[test]
+    fn test_dummy_cache_read_chunks_async() {
+        let mut blob = Vec::new();
+        let mut chunks = Vec::new();
+        for idx in 0..4u32 {
+            let data = vec![idx as u8 + 1; 0x1000];
+            let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: idx as u64 * 0x1000,
+                index: idx,
+                ..Default::default()
+            });
+            blob.extend_from_slice(&compressed);
+            chunks.push(chunk);
+        }
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            blob.len() as u64,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let blob_size = blob.len();
+        let reader = Arc::new(CountingBlobReader {
+            blob,
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let cache: Arc<dyn BlobCache> = Arc::new(DummyCache::new(
+            &info,
+            reader.clone(),
+            false,
+            true,
+            false,
+            CacheMetrics::default(),
+        ));
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let bufs = rt
+            .block_on(crate::cache::read_chunks_async(
+                cache.clone(),
+                0,
+                blob_size,
+                chunks.clone(),
+            ))
+            .unwrap();
+        assert_eq!(reader.reads.load(Ordering::Relaxed), 1);
+        assert_eq!(bufs.len(), 4);
+        for (idx, buf) in bufs.iter().enumerate() {
+            assert!(buf.iter().all(|v| *v == idx as u8 + 1));
+        }
+
+        // Chunks must cover the requested range.
+        let res = rt.block_on(crate::cache::read_chunks_async(
+            cache,
+            0,
+            blob_size,
+            chunks[1..].to_vec(),
+        ));
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_dummy_cache_read_into_slices() {
+        let mut blob = Vec::new();
+        let mut chunks = Vec::new();
+        for idx in 0..4u32 {
+            let data = vec![idx as u8 + 1; 0x1000];
+            let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: idx as u64 * 0x1000,
+                index: idx,
+                ..Default::default()
+            });
+            blob.extend_from_slice(&compressed);
+            chunks.push(chunk);
+        }
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            blob.len() as u64,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let reader = Arc::new(CountingBlobReader {
+            blob,
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let cache = DummyCache::new(&info, reader, false, true, false, CacheMetrics::default());
+
+        // Read chunks not adjacent in the blob into destination slices of `sizes`, returns the
+        // data read and bytes of buffers allocated.
+        let read = |sizes: &[usize]| {
+            let mut iovec = BlobIoVec::new(info.clone());
+            for chunk in [&chunks[0], &chunks[2]] {
+                iovec.push(BlobIoDesc::new(
+                    info.clone(),
+                    BlobIoChunk::from(chunk.clone()),
+                    0,
+                    0x1000,
+                    true,
+                ));
+            }
+            let mut bufs = sizes
+                .iter()
+                .map(|size| vec![0u8; *size])
+                .collect::<Vec<_>>();
+            let slices = bufs
+                .iter_mut()
+                .map(|buf| unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) })
+                .collect::<Vec<_>>();
+            let allocated = crate::utils::ALLOCATED_BYTES.with(|v| v.get());
+            assert_eq!(cache.read(&mut iovec, &slices).unwrap(), 0x2000);
+            let allocated = crate::utils::ALLOCATED_BYTES.with(|v| v.get()) - allocated;
+            (bufs.concat(), allocated)
+        };
+
+        // Chunks are decompressed into contiguous destination slices directly, and copied into
+        // fragmented ones.
+        let (direct, direct_allocated) = read(&[0x2000]);
+        let (copied, copy_allocated) = read(&[0x800, 0x1000, 0x800]);
+        assert_eq!(direct, copied);
+        assert!(direct[..0x1000].iter().all(|v| *v == 1));
+        assert!(direct[0x1000..].iter().all(|v| *v == 3));
+        assert_eq!(copy_allocated - direct_allocated, 0x2000);
+        // Only the chunk not contiguous in the destination is copied.
+        let (partial, partial_allocated) = read(&[0x800, 0x1800]);
+        assert_eq!(partial, direct);
+        assert_eq!(partial_allocated - direct_allocated, 0x1000);
+    }
+
+    #[test]
+    fn test_dummy_cache_read_deduplicated_chunks() {
+        let mut blob = Vec::new();
+        let mut stored = Vec::new();
+        for idx in 0..2u32 {
+            let data = vec![idx as u8 + 1; 0x1000];
+            let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+            let size = compressed.len() as u32;
+            blob.extend_from_slice(&compressed);
+            stored.push((data, blob.len() as u64 - size as u64, size));
+        }
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x2000,
+            blob.len() as u64,
+            0x1000,
+            2,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let reader = Arc::new(CountingBlobReader {
+            blob,
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let cache = DummyCache::new(&info, reader, false, true, false, CacheMetrics::default());
+
+        // Identical chunks of a file refer to the single copy stored in the blob, so their chunk
+        // info objects share the same compressed offset.
+        let order = [0usize, 1, 0, 0, 1];
+        let mut iovec = BlobIoVec::new(info.clone());
+        for (pos, idx) in order.iter().enumerate() {
+            let (data, offset, size) = &stored[*idx];
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: *size,
+                uncompress_size: 0x1000,
+                compress_offset: *offset,
+                uncompress_offset: *idx as u64 * 0x1000,
+                file_offset: pos as u64 * 0x1000,
+                index: *idx as u32,
+                ..Default::default()
+            });
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk),
+                0,
+                0x1000,
+                true,
+            ));
+        }
+        let mut buf = vec![0u8; 0x5000];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x5000);
+        for (pos, idx) in order.iter().enumerate() {
+            assert_eq!(buf[pos * 0x1000..(pos + 1) * 0x1000], stored[*idx].0);
+        }
+    }
+
+    #[test]
+    fn test_dummy_cache_memory_tier() {
+        let mut blob = Vec::new();
+        let mut chunks = Vec::new();
+        for idx in 0..2u32 {
+            let data = vec![idx as u8 + 1; 0x1000];
+            let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: idx as u64 * 0x1000,
+                index: idx,
+                ..Default::default()
+            });
+            blob.extend_from_slice(&compressed);
+            chunks.push(chunk);
+        }
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x2000,
+            blob.len() as u64,
+            0x1000,
+            2,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let reader = Arc::new(CountingBlobReader {
+            blob,
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let mut cache = DummyCache::new(
+            &info,
+            reader.clone(),
+            false,
+            true,
+            false,
+            CacheMetrics::default(),
+        );
+        // Room for only one chunk.
+        cache.set_memory_tier(Arc::new(SharedMemoryTier::new(0x1000)));
+
+        let read = |chunks: &[&Arc<dyn BlobChunkInfo>]| {
+            let mut iovec = BlobIoVec::new(info.clone());
+            for chunk in chunks {
+                iovec.push(BlobIoDesc::new(
+                    info.clone(),
+                    BlobIoChunk::from((*chunk).clone()),
+                    0,
+                    0x1000,
+                    true,
+                ));
+            }
+            let mut buf = vec![0u8; chunks.len() * 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            reader.reads.store(0, Ordering::Relaxed);
+            let stats = cache.read_with_stats(&mut iovec, &[slice]).unwrap();
+            assert_eq!(stats.total, buf.len());
+            assert_eq!(stats.from_cache + stats.from_backend, stats.total);
+            for (idx, chunk) in chunks.iter().enumerate() {
+                let expected = chunk.id() as u8 + 1;
+                assert!(buf[idx * 0x1000..(idx + 1) * 0x1000]
+                    .iter()
+                    .all(|v| *v == expected));
+            }
+            (
+                reader.reads.load(Ordering::Relaxed),
+                stats.from_cache,
+                stats.chunks_fetched,
+            )
+        };
+
+        assert_eq!(read(&[&chunks[0]]), (1, 0, 1));
+        // The second read of the chunk is served from memory.
+        assert_eq!(read(&[&chunks[0]]), (0, 0x1000, 0));
+        assert_eq!(cache.metrics().chunk_hits(), 1);
+        // Chunk 0 comes from memory, chunk 1 from the backend and evicts chunk 0.
+        assert_eq!(read(&[&chunks[0], &chunks[1]]), (1, 0x1000, 1));
+        assert_eq!(read(&[&chunks[1]]), (0, 0x1000, 0));
+        assert_eq!(read(&[&chunks[0]]), (1, 0, 1));
+    }
+
+    #[test]
+    fn test_dummy_cache_validate_digest() {
+        let data = vec![0x5au8; 0x1000];
+        let validate = |digester: digest::Algorithm, chunk_digester: digest::Algorithm| {
+            let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+                block_id: RafsDigest::from_buf(&data, chunk_digester),
+                compress_size: 0x1000,
+                uncompress_size: 0x1000,
+                ..Default::default()
+            });
+            let mut info = BlobInfo::new(
+                0,
+                "blob-0".to_string(),
+                0x1000,
+                0x1000,
+                0x1000,
+                1,
+                BlobFeatures::empty(),
+            );
+            info.set_digester(digester);
+            let info = Arc::new(info);
+            let reader = Arc::new(CountingBlobReader {
+                blob: data.clone(),
+                reads: AtomicUsize::new(0),
+                bytes: AtomicUsize::new(0),
+                stops: AtomicUsize::new(0),
+                metrics: BackendMetrics::new("dummy", "localfs"),
+            });
+            let cache = DummyCache::new(&info, reader, false, true, false, CacheMetrics::default());
+            let mut iovec = BlobIoVec::new(info.clone());
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            cache.read(&mut iovec, &[slice]).is_ok() && buf == data
+        };
+
+        assert!(validate(
+            digest::Algorithm::Blake3,
+            digest::Algorithm::Blake3
+        ));
+        assert!(validate(
+            digest::Algorithm::Sha256,
+            digest::Algorithm::Sha256
+        ));
+        assert!(!validate(
+            digest::Algorithm::Blake3,
+            digest::Algorithm::Sha256
+        ));
+        assert!(!validate(
+            digest::Algorithm::Sha256,
+            digest::Algorithm::Blake3
+        ));
+    }
+
+    #[test]
+    fn test_dummy_cache_no_digest_chunk() {
+        let data = vec![0x5au8; 0x1000];
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            block_id: RafsDigest::default(),
+            compress_size: 0x1000,
+            uncompress_size: 0x1000,
+            ..Default::default()
+        });
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            0x1000,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        let read = |strict: bool| {
+            let reader = Arc::new(CountingBlobReader {
+                blob: data.clone(),
+                reads: AtomicUsize::new(0),
+                bytes: AtomicUsize::new(0),
+                stops: AtomicUsize::new(0),
+                metrics: BackendMetrics::new("dummy", "localfs"),
+            });
+            let cache =
+                DummyCache::new(&info, reader, false, true, strict, CacheMetrics::default());
+            let mut iovec = BlobIoVec::new(info.clone());
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk.clone()),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            let res = cache.read(&mut iovec, &[slice]);
+            assert!(cache.no_digest_reported.load(Ordering::Relaxed) == res.is_ok());
+            res.map(|_| buf)
+        };
+
+        // Validation is skipped for chunks without digest in lenient mode.
+        assert_eq!(read(false).unwrap(), data);
+        // Chunks without digest are rejected in strict mode.
+        let err = read(true).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("has no digest"));
+    }
+
+    #[test]
+    fn test_dummy_cache_stop_prefetch() {
+        let data = vec![0x5au8; 0x1000];
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            compress_size: 0x1000,
+            uncompress_size: 0x1000,
+            ..Default::default()
+        });
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            0x1000,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        let reader = Arc::new(CountingBlobReader {
+            blob: data.clone(),
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let mut cache = DummyCache::new(
+            &info,
+            reader.clone(),
+            false,
+            false,
+            false,
+            CacheMetrics::default(),
+        );
+
+        // Nothing to stop if prefetch is disabled.
+        cache.stop_prefetch().unwrap();
+        assert_eq!(reader.stops.load(Ordering::Relaxed), 0);
+
+        // Stopping prefetch is idempotent and forwarded to the storage backend.
+        cache.set_prefetch(true);
+        cache.stop_prefetch().unwrap();
+        cache.stop_prefetch().unwrap();
+        assert_eq!(reader.stops.load(Ordering::Relaxed), 2);
+
+        // The stopped reader still serves read requests.
+        let mut iovec = BlobIoVec::new(info.clone());
+        iovec.push(BlobIoDesc::new(
+            info.clone(),
+            BlobIoChunk::from(chunk),
+            0,
+            0x1000,
+            true,
+        ));
+        let mut buf = vec![0u8; 0x1000];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x1000);
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn test_dummy_cache_single_flight() {
+        let data = vec![0x5au8; 0x1000];
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            compress_size: 0x1000,
+            uncompress_size: 0x1000,
+            ..Default::default()
+        });
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            0x1000,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        // Read the cold chunk by 100 threads concurrently, returns number of successful reads and
+        // number of backend reads.
+        let read = |fail: bool, panic: bool| {
+            let reader = Arc::new(SlowBlobReader {
+                blob: data.clone(),
+                reads: AtomicUsize::new(0),
+                fail,
+                panic,
+                metrics: BackendMetrics::new("dummy", "localfs"),
+            });
+            let cache = DummyCache::new(
+                &info,
+                reader.clone(),
+                false,
+                false,
+                false,
+                CacheMetrics::default(),
+            );
+            let succeeded = std::thread::scope(|scope| {
+                let threads: Vec<_> = (0..100)
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut iovec = BlobIoVec::new(info.clone());
+                            iovec.push(BlobIoDesc::new(
+                                info.clone(),
+                                BlobIoChunk::from(chunk.clone()),
+                                0,
+                                0x1000,
+                                true,
+                            ));
+                            let mut buf = vec![0u8; 0x1000];
+                            let slice = unsafe {
+                                FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len())
+                            };
+                            let res = cache.read(&mut iovec, &[slice]);
+                            res.is_ok() && buf == data
+                        })
+                    })
+                    .collect();
+                threads
+                    .into_iter()
+                    .map(|t| t.join())
+                    .filter(|t| matches!(t, Ok(true)))
+                    .count()
+            });
+            assert!(cache.inflight.lock().unwrap().is_empty());
+            (succeeded, reader.reads.load(Ordering::Relaxed))
+        };
+
+        assert_eq!(read(false, false), (100, 1));
+        // Errors and panics of the fetching thread are propagated to all waiting threads.
+        assert_eq!(read(true, false), (0, 1));
+        assert_eq!(read(false, true), (0, 1));
+    }
+
+    #[test]
+    fn test_dummy_cache_read_chunk_segment() {
+        let data: Vec<u8> = (0..0x4000u32).map(|i| (i % 251) as u8).collect();
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+            compress_size: 0x4000,
+            uncompress_size: 0x4000,
+            ..Default::default()
+        });
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            0x4000,
+            0x4000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        let read = |need_validation: bool| {
+            let reader = Arc::new(CountingBlobReader {
+                blob: data.clone(),
+                reads: AtomicUsize::new(0),
+                bytes: AtomicUsize::new(0),
+                stops: AtomicUsize::new(0),
+                metrics: BackendMetrics::new("dummy", "localfs"),
+            });
+            let cache = DummyCache::new(
+                &info,
+                reader.clone(),
+                false,
+                need_validation,
+                false,
+                CacheMetrics::default(),
+            );
+            let mut iovec = BlobIoVec::new(info.clone());
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk.clone()),
+                0x1100,
+                0x200,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x200];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x200);
+            assert_eq!(buf, data[0x1100..0x1300]);
+            reader.bytes.load(Ordering::Relaxed)
+        };
+
+        // Only the requested segment is fetched from the backend.
+        assert_eq!(read(false), 0x200);
+        // The whole chunk is needed to validate data.
+        assert_eq!(read(true), 0x4000);
+    }
+
+    #[test]
+    fn test_dummy_cache_backend_capabilities() {
+        struct CapsBlobReader {
+            inner: CountingBlobReader,
+            caps: BackendCaps,
+        }
+
+        impl BlobReader for CapsBlobReader {
+            fn blob_size(&self) -> BackendResult<u64> {
+                self.inner.blob_size()
+            }
+
+            fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+                self.inner.try_read(buf, offset)
+            }
+
+            fn metrics(&self) -> &BackendMetrics {
+                self.inner.metrics()
+            }
+
+            fn max_coalesce_gap(&self) -> u64 {
+                0x40
+            }
+
+            fn stop_prefetch_data(&self) -> BackendResult<()> {
+                self.inner.stop_prefetch_data()
+            }
+
+            fn capabilities(&self) -> BackendCaps {
+                self.caps
+            }
+        }
+
+        let data: Vec<u8> = (0..0x4000u32).map(|i| (i % 251) as u8).collect();
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+            compress_size: 0x4000,
+            uncompress_size: 0x4000,
+            ..Default::default()
+        });
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            0x4000,
+            0x4000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        // Returns bytes fetched to read a chunk segment, and number of forwarded prefetch stops.
+        let run = |caps: BackendCaps| {
+            let reader = Arc::new(CapsBlobReader {
+                inner: CountingBlobReader {
+                    blob: data.clone(),
+                    reads: AtomicUsize::new(0),
+                    bytes: AtomicUsize::new(0),
+                    stops: AtomicUsize::new(0),
+                    metrics: BackendMetrics::new("dummy", "localfs"),
+                },
+                caps,
+            });
+            let mut cache = DummyCache::new(
+                &info,
+                reader.clone(),
+                false,
+                false,
+                false,
+                CacheMetrics::default(),
+            );
+            cache.set_prefetch(true);
+            let mut iovec = BlobIoVec::new(info.clone());
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk.clone()),
+                0x1100,
+                0x200,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x200];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x200);
+            assert_eq!(buf, data[0x1100..0x1300]);
+            cache.stop_prefetch().unwrap();
+            assert_eq!(
+                max_coalesce_gap(reader.as_ref()),
+                if caps.contains(BackendCaps::SUPPORTS_MULTIPART) {
+                    0x40
+                } else {
+                    0
+                }
+            );
+            (
+                reader.inner.bytes.load(Ordering::Relaxed),
+                reader.inner.stops.load(Ordering::Relaxed),
+            )
+        };
+
+        // Conservatively fetch whole chunks and skip optional backend features by default.
+        assert_eq!(run(BackendCaps::empty()), (0x4000, 0));
+        assert_eq!(run(BackendCaps::SUPPORTS_RANGE), (0x200, 0));
+        assert_eq!(run(BackendCaps::SUPPORTS_PREFETCH), (0x4000, 1));
+        assert_eq!(run(BackendCaps::SUPPORTS_MULTIPART), (0x4000, 0));
+    }
+
+    #[test]
+    fn test_dummy_cache_max_decompress_size() {
+        let data = vec![0x5au8; 0x1000];
+        // A chunk claiming an absurd decompressed size, bigger than four times the chunk size.
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            compress_size: 0x1000,
+            uncompress_size: 0xffff_f000,
+            flags: BlobChunkFlags::COMPRESSED,
+            ..Default::default()
+        });
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            0x1000,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        ));
+        let reader = Arc::new(CountingBlobReader {
+            blob: data,
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let mut cache = DummyCache::new(
+            &info,
+            reader.clone(),
+            false,
+            false,
+            false,
+            CacheMetrics::default(),
+        );
+        assert_eq!(cache.max_decompress_size(), 0x4000);
+
+        let mut iovec = BlobIoVec::new(info.clone());
+        iovec.push(BlobIoDesc::new(
+            info.clone(),
+            BlobIoChunk::from(chunk.clone()),
+            0,
+            0x1000,
+            true,
+        ));
+        let mut buf = vec![0u8; 0x1000];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        let err = cache.read(&mut iovec, &[slice]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        let err = cache
+            .read_chunk_from_backend(chunk.as_ref(), &mut buf)
+            .unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+        assert_eq!(reader.reads.load(Ordering::Relaxed), 0);
+
+        // The configured limit is capped by the maximum chunk size.
+        cache.set_max_decompress_size(u64::MAX);
+        assert_eq!(cache.max_decompress_size(), crate::RAFS_MAX_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn test_dummy_cache_chunk_fallback() {
+        let data = vec![0x5au8; 0x1000];
+        let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+        // Corrupted primary copy followed by the uncompressed fallback copy.
+        let mut blob = vec![0xffu8; compressed.len()];
+        blob.extend_from_slice(&data);
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            blob.len() as u64,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let new_chunk = |fallback: Option<(u64, u32)>| -> Arc<dyn BlobChunkInfo> {
+            Arc::new(FallbackChunkInfo {
+                inner: MockChunkInfo {
+                    block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                    flags: BlobChunkFlags::COMPRESSED,
+                    compress_size: compressed.len() as u32,
+                    uncompress_size: 0x1000,
+                    ..Default::default()
+                },
+                fallback,
+            })
+        };
+        let reader = Arc::new(CountingBlobReader {
+            blob,
+            reads: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+            stops: AtomicUsize::new(0),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let cache = DummyCache::new(&info, reader, false, true, false, CacheMetrics::default());
+        let mut buf = vec![0u8; 0x1000];
+
+        // The corrupted primary copy is rejected without fallback copy.
+        let chunk = new_chunk(None);
+        assert!(cache
+            .read_chunk_from_backend(chunk.as_ref(), &mut buf)
+            .is_err());
+
+        // The fallback copy is used when failing to decompress the primary copy.
+        let chunk = new_chunk(Some((compressed.len() as u64, 0x1000)));
+        assert!(cache
+            .read_chunk_from_backend(chunk.as_ref(), &mut buf)
+            .unwrap()
+            .is_none());
+        assert_eq!(buf, data);
+
+        // The fallback copy is validated against the chunk digest too.
+        let chunk = new_chunk(Some((0, 0x1000)));
+        assert!(cache
+            .read_chunk_from_backend(chunk.as_ref(), &mut buf)
+            .is_err());
+    }
+
+    #[test]
+    fn test_dummy_cache_errors() {
+        let data = vec![0x5au8; 0x1000];
+        let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+        let c_size = compressed.len() as u32;
+        let mut blob = compressed.to_vec();
+        blob.extend_from_slice(&vec![0xffu8; c_size as usize]);
+        let mut sources = HashMap::new();
+        sources.insert("http://origin/chunk".to_string(), vec![0x5au8; 0x800]);
+        let reader = Arc::new(UrlBlobReader {
+            blob,
+            sources,
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            c_size as u64 * 2,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        let info = Arc::new(info);
+        let cache = DummyCache::new(&info, reader, false, true, false, CacheMetrics::default());
+        let new_chunk = |digest: &[u8], offset: u64| MockChunkInfo {
+            block_id: RafsDigest::from_buf(digest, digest::Algorithm::Blake3),
+            flags: BlobChunkFlags::COMPRESSED,
+            compress_size: c_size,
+            uncompress_size: 0x1000,
+            compress_offset: offset,
+            ..Default::default()
+        };
+        let mut buf = vec![0u8; 0x1000];
+        let read = |chunk: &dyn BlobChunkInfo, buf: &mut [u8]| {
+            let err = cache.read_chunk_from_backend(chunk, buf).unwrap_err();
+            assert!(CacheError::from_io_error(&err).is_some());
+            err
+        };
+
+        let err = read(&new_chunk(&[0u8; 0x1000], 0), &mut buf);
+        assert!(matches!(
+            CacheError::from_io_error(&err),
+            Some(CacheError::DigestMismatch)
+        ));
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("digest value doesn't match"));
+
+        let err = read(&new_chunk(&data, c_size as u64), &mut buf);
+        assert!(matches!(
+            CacheError::from_io_error(&err),
+            Some(CacheError::Decompress(_))
+        ));
+        assert!(err.to_string().contains("failed to decompress"));
+
+        let chunk = UrlChunkInfo {
+            inner: MockChunkInfo {
+                compress_size: 0x1000,
+                uncompress_size: 0x1000,
+                ..Default::default()
+            },
+            url: Some("http://origin/chunk".to_string()),
+        };
+        let err = read(&chunk, &mut buf);
+        assert!(matches!(
+            CacheError::from_io_error(&err),
+            Some(CacheError::BackendShortRead {
+                expected: _,
+                got: 0x800
+            })
+        ));
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        let chunk = new_chunk(&data, 0);
+        let err = cache
+            .validate_chunk_data(&chunk, &buf[..0x800], true)
+            .unwrap_err();
+        assert!(matches!(
+            CacheError::from_io_error(&err),
+            Some(CacheError::SizeMismatch {
+                expected: 0x1000,
+                got: 0x800
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "data size doesn't match, expect 0x1000, got 0x800"
+        );
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_dummy_cache_mixed_compressors() {
+        // Blobs of an image may be compressed by different algorithms.
+        let data = vec![0x5au8; 0x2000];
+        let caches = [compress::Algorithm::Brotli, compress::Algorithm::Zstd]
+            .iter()
+            .map(|compressor| {
+                let (compressed, is_compressed) = compress::compress(&data, *compressor)
+                    .map(|(v, c)| (v.to_vec(), c))
+                    .unwrap();
+                assert!(is_compressed);
+                let mut info = BlobInfo::new(
+                    0,
+                    "blob-0".to_string(),
+                    0x2000,
+                    compressed.len() as u64,
+                    0x2000,
+                    1,
+                    BlobFeatures::empty(),
+                );
+                info.set_compressor(*compressor);
+                let chunk = MockChunkInfo {
+                    block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+                    flags: BlobChunkFlags::COMPRESSED,
+                    compress_size: compressed.len() as u32,
+                    uncompress_size: 0x2000,
+                    ..Default::default()
+                };
+                let reader: Arc<dyn BlobReader> = Arc::new(UrlBlobReader {
+                    blob: compressed,
+                    sources: HashMap::new(),
+                    metrics: BackendMetrics::new("dummy", "localfs"),
+                });
+                let cache = DummyCache::new(
+                    &Arc::new(info),
+                    reader,
+                    false,
+                    true,
+                    false,
+                    CacheMetrics::default(),
+                );
+                (cache, chunk)
+            })
+            .collect::<Vec<_>>();
+
+        for (cache, chunk) in caches.iter().chain(caches.iter().rev()) {
+            let mut buf = vec![0u8; 0x2000];
+            cache.read_chunk_from_backend(chunk, &mut buf).unwrap();
+            assert_eq!(buf, data);
+        }
+    }
+
+    #[test]
+    fn test_dummy_cache_lz4_block_chunk() {
+        // Chunks may be compressed by lz4_block instead of the blob compressor.
+        let data = vec![0x5au8; 0x2000];
+        let (compressed, is_compressed) = compress::compress(&data, compress::Algorithm::Lz4Block)
+            .map(|(v, c)| (v.to_vec(), c))
+            .unwrap();
+        assert!(is_compressed);
+        let chunk = MockChunkInfo {
+            block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+            flags: BlobChunkFlags::COMPRESSED | BlobChunkFlags::LZ4_BLOCK,
+            compress_size: compressed.len() as u32,
+            uncompress_size: 0x2000,
+            ..Default::default()
+        };
+        let reader: Arc<dyn BlobReader> = Arc::new(UrlBlobReader {
+            blob: compressed,
+            sources: HashMap::new(),
+            metrics: BackendMetrics::new("dummy", "localfs"),
+        });
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x2000,
+            chunk.compress_size as u64,
+            0x2000,
+            1,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        let cache = DummyCache::new(
+            &Arc::new(info),
+            reader,
+            false,
+            true,
+            false,
+            CacheMetrics::default(),
+        );
+        assert_eq!(cache.blob_compressor(), compress::Algorithm::Zstd);
+        let mut buf = vec![0u8; 0x2000];
+        cache.read_chunk_from_backend(&chunk, &mut buf).unwrap();
+        assert_eq!(buf, data);
+    }
 }
@@ -0,0 +1,392 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Policies to evict cached chunks when cache files exceed the configured size limit.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Error, Result};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::cache::cachedfile::{cache_data_offset, FileCacheEntry};
+use crate::device::BlobChunkInfo;
+
+/// Identifier of a chunk cached by a blob cache manager.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ChunkId {
+    /// Index of the blob cache object managed by the blob cache manager.
+    pub blob: u32,
+    /// Index of the chunk in the blob.
+    pub chunk: u32,
+}
+
+/// Trait to select cached chunks to evict when cache files exceed the size limit.
+pub trait EvictionPolicy: Send + Sync {
+    /// Record an access to a cached chunk, which occupies `size` bytes in cache files.
+    fn on_access(&self, id: ChunkId, size: u64);
+
+    /// Select chunks to evict for releasing at least `bytes_needed` bytes, skipping chunks in
+    /// `pinned`.
+    ///
+    /// The selected chunks are not tracked by the policy anymore.
+    fn select_victims(&self, bytes_needed: u64, pinned: &HashSet<ChunkId>) -> Vec<ChunkId>;
+
+    /// Stop tracking all chunks of the blob cache object with index `blob`.
+    fn remove_blob(&self, blob: u32);
+}
+
+/// An [EvictionPolicy](trait.EvictionPolicy.html) evicting least recently used chunks first.
+#[derive(Default)]
+pub struct LruEvictionPolicy {
+    state: Mutex<LruState>,
+}
+
+#[derive(Default)]
+struct LruState {
+    // Map chunk to its (access sequence, size).
+    chunks: HashMap<ChunkId, (u64, u64)>,
+    lru: BTreeMap<u64, ChunkId>,
+    seq: u64,
+}
+
+impl LruEvictionPolicy {
+    /// Create a new instance of `LruEvictionPolicy`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl EvictionPolicy for LruEvictionPolicy {
+    fn on_access(&self, id: ChunkId, size: u64) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        state.seq += 1;
+        if let Some((old_seq, _)) = state.chunks.insert(id, (state.seq, size)) {
+            state.lru.remove(&old_seq);
+        }
+        state.lru.insert(state.seq, id);
+    }
+
+    fn select_victims(&self, bytes_needed: u64, pinned: &HashSet<ChunkId>) -> Vec<ChunkId> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let mut victims = Vec::new();
+        let mut released = 0;
+
+        for (_, id) in state.lru.iter() {
+            if released >= bytes_needed {
+                break;
+            }
+            if pinned.contains(id) {
+                continue;
+            }
+            if let Some((_, size)) = state.chunks.get(id) {
+                released += size;
+            }
+            victims.push(*id);
+        }
+        for id in victims.iter() {
+            if let Some((seq, _)) = state.chunks.remove(id) {
+                state.lru.remove(&seq);
+            }
+        }
+
+        victims
+    }
+
+    fn remove_blob(&self, blob: u32) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let lru = &mut state.lru;
+        state.chunks.retain(|id, (seq, _)| {
+            if id.blob == blob {
+                lru.remove(seq);
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// Create an [EvictionPolicy](trait.EvictionPolicy.html) object by name.
+pub(crate) fn new_eviction_policy(name: &str) -> Result<Box<dyn EvictionPolicy>> {
+    match name {
+        "" | "lru" => Ok(Box::new(LruEvictionPolicy::new())),
+        _ => Err(einval!(format!("unknown cache eviction policy {}", name))),
+    }
+}
+
+/// Keep cache files of a blob cache manager under the size limit by evicting cached chunks.
+///
+/// Only chunks accessed or cached after the manager gets started are tracked, so data cached by
+/// previous instances doesn't count until being accessed again.
+pub(crate) struct CacheEvictor {
+    policy: Box<dyn EvictionPolicy>,
+    max_size: u64,
+    state: Mutex<CacheEvictorState>,
+}
+
+#[derive(Default)]
+struct CacheEvictorState {
+    blobs: HashMap<u32, Weak<FileCacheEntry>>,
+    // Map cached chunk to its (offset, size) in the cache file.
+    chunks: HashMap<ChunkId, (u64, u64)>,
+    // Chunks never to be evicted, which still count against the size limit.
+    pinned: HashSet<ChunkId>,
+    next_blob: u32,
+    used: u64,
+}
+
+impl CacheEvictor {
+    pub(crate) fn new(policy: Box<dyn EvictionPolicy>, max_size: u64) -> Self {
+        CacheEvictor {
+            policy,
+            max_size,
+            state: Mutex::new(CacheEvictorState::default()),
+        }
+    }
+
+    /// Allocate an index for a new blob cache object.
+    pub(crate) fn alloc_blob(&self) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        state.next_blob += 1;
+        state.next_blob
+    }
+
+    /// Register the blob cache object to evict chunks from.
+    pub(crate) fn register_blob(&self, blob: u32, entry: &Arc<FileCacheEntry>) {
+        let mut state = self.state.lock().unwrap();
+        state.blobs.insert(blob, Arc::downgrade(entry));
+    }
+
+    /// Stop tracking chunks of a blob cache object which has been released.
+    pub(crate) fn remove_blob(&self, blob: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.blobs.remove(&blob);
+        state.pinned.retain(|id| id.blob != blob);
+        drop(state);
+        self.forget_blob_chunks(blob);
+    }
+
+    /// Mark chunks of the blob cache object with index `blob` as non-evictable.
+    pub(crate) fn pin_chunks(&self, blob: u32, chunks: &[u32]) {
+        let mut state = self.state.lock().unwrap();
+        for chunk in chunks {
+            state.pinned.insert(ChunkId {
+                blob,
+                chunk: *chunk,
+            });
+        }
+    }
+
+    /// Mark chunks of the blob cache object with index `blob` as evictable again.
+    pub(crate) fn unpin_chunks(&self, blob: u32, chunks: &[u32]) {
+        let mut state = self.state.lock().unwrap();
+        for chunk in chunks {
+            state.pinned.remove(&ChunkId {
+                blob,
+                chunk: *chunk,
+            });
+        }
+    }
+
+    /// Stop tracking chunks of a blob cache object whose cached data has been dropped.
+    pub(crate) fn forget_blob_chunks(&self, blob: u32) {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let used = &mut state.used;
+        state.chunks.retain(|id, (_, size)| {
+            if id.blob == blob {
+                *used -= *size;
+                false
+            } else {
+                true
+            }
+        });
+        self.policy.remove_blob(blob);
+    }
+
+    /// Record an access to a chunk cached at [offset, offset + size) of the cache file, and
+    /// evict chunks if exceeding the size limit.
+    ///
+    /// `ENOSPC` is returned if a newly cached chunk doesn't fit in the size limit because all the
+    /// remaining chunks are pinned, and the new chunk gets evicted instead.
+    pub(crate) fn on_access(&self, id: ChunkId, offset: u64, size: u64) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        if state.chunks.contains_key(&id) {
+            self.policy.on_access(id, size);
+            return Ok(());
+        }
+
+        state.used += size;
+        while state.used > self.max_size {
+            let victims = self
+                .policy
+                .select_victims(state.used - self.max_size, &state.pinned);
+            if victims.is_empty() {
+                warn!(
+                    "cache size {} exceeds limit {} with pinned chunks",
+                    state.used, self.max_size
+                );
+                state.used -= size;
+                Self::evict(state, id, offset, size);
+                return Err(Error::from_raw_os_error(libc::ENOSPC));
+            }
+            for victim in victims {
+                let (offset, size) = match state.chunks.remove(&victim) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                state.used -= size;
+                Self::evict(state, victim, offset, size);
+            }
+        }
+        state.chunks.insert(id, (offset, size));
+        self.policy.on_access(id, size);
+
+        Ok(())
+    }
+
+    fn evict(state: &CacheEvictorState, id: ChunkId, offset: u64, size: u64) {
+        if let Some(entry) = state.blobs.get(&id.blob).and_then(|b| b.upgrade()) {
+            if let Err(e) = entry.evict_chunk(id.chunk, offset, size) {
+                warn!(
+                    "failed to evict chunk {} of blob {}, {}",
+                    id.chunk, entry.blob_id, e
+                );
+            }
+        }
+    }
+}
+
+/// Handle for a blob cache object to report accesses to cached chunks.
+#[derive(Clone)]
+pub(crate) struct BlobEvictionHandle {
+    evictor: Arc<CacheEvictor>,
+    blob: u32,
+    is_raw_data: bool,
+    cache_slot_size: u64,
+}
+
+impl BlobEvictionHandle {
+    pub(crate) fn new(evictor: Arc<CacheEvictor>, is_raw_data: bool, cache_slot_size: u64) -> Self {
+        let blob = evictor.alloc_blob();
+        BlobEvictionHandle {
+            evictor,
+            blob,
+            is_raw_data,
+            cache_slot_size,
+        }
+    }
+
+    pub(crate) fn blob(&self) -> u32 {
+        self.blob
+    }
+
+    pub(crate) fn evictor(&self) -> &Arc<CacheEvictor> {
+        &self.evictor
+    }
+
+    /// Record an access to the cached chunk.
+    pub(crate) fn on_access(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        let (offset, size) = self.locate(chunk);
+        self.on_access_at(chunk.id(), offset, size)
+    }
+
+    /// Record an access to the chunk with index `chunk` cached at [offset, offset + size).
+    pub(crate) fn on_access_at(&self, chunk: u32, offset: u64, size: u64) -> Result<()> {
+        let id = ChunkId {
+            blob: self.blob,
+            chunk,
+        };
+        self.evictor.on_access(id, offset, size)
+    }
+
+    /// Get the (offset, size) of data of the chunk in the cache file.
+    pub(crate) fn locate(&self, chunk: &dyn BlobChunkInfo) -> (u64, u64) {
+        if self.is_raw_data {
+            (chunk.compressed_offset(), chunk.compressed_size() as u64)
+        } else {
+            (
+                cache_data_offset(chunk, self.cache_slot_size),
+                chunk.uncompressed_size() as u64,
+            )
+        }
+    }
+
+    /// Mark chunks of the blob as non-evictable.
+    pub(crate) fn pin_chunks(&self, chunks: &[u32]) {
+        self.evictor.pin_chunks(self.blob, chunks)
+    }
+
+    /// Mark chunks of the blob as evictable again.
+    pub(crate) fn unpin_chunks(&self, chunks: &[u32]) {
+        self.evictor.unpin_chunks(self.blob, chunks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction_policy() {
+        let policy = LruEvictionPolicy::new();
+        let id = |blob, chunk| ChunkId { blob, chunk };
+
+        policy.on_access(id(1, 0), 0x1000);
+        policy.on_access(id(1, 1), 0x1000);
+        policy.on_access(id(2, 0), 0x2000);
+        policy.on_access(id(1, 0), 0x1000);
+
+        let none = HashSet::new();
+        assert_eq!(policy.select_victims(0x800, &none), vec![id(1, 1)]);
+        assert_eq!(
+            policy.select_victims(0x2800, &none),
+            vec![id(2, 0), id(1, 0)]
+        );
+        assert!(policy.select_victims(0x1000, &none).is_empty());
+
+        policy.on_access(id(1, 0), 0x1000);
+        policy.on_access(id(2, 0), 0x1000);
+        policy.remove_blob(1);
+        assert_eq!(policy.select_victims(u64::MAX, &none), vec![id(2, 0)]);
+
+        assert!(new_eviction_policy("lru").is_ok());
+        assert!(new_eviction_policy("fifo").is_err());
+    }
+
+    #[test]
+    fn test_evict_pinned_chunks() {
+        let policy = LruEvictionPolicy::new();
+        let id = |blob, chunk| ChunkId { blob, chunk };
+        policy.on_access(id(1, 0), 0x1000);
+        policy.on_access(id(1, 1), 0x1000);
+        policy.on_access(id(1, 2), 0x1000);
+        let pinned = HashSet::from([id(1, 0)]);
+        assert_eq!(
+            policy.select_victims(0x1800, &pinned),
+            vec![id(1, 1), id(1, 2)]
+        );
+        assert!(policy.select_victims(0x1000, &pinned).is_empty());
+
+        let evictor = CacheEvictor::new(Box::new(LruEvictionPolicy::new()), 0x2000);
+        evictor.pin_chunks(1, &[0, 1, 2]);
+        evictor.on_access(id(1, 0), 0, 0x1000).unwrap();
+        evictor.on_access(id(1, 1), 0x1000, 0x1000).unwrap();
+        // The budget is exceeded but all cached chunks are pinned, so the new chunk isn't kept.
+        let err = evictor.on_access(id(1, 2), 0x2000, 0x1000).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSPC));
+        assert!(!evictor.state.lock().unwrap().chunks.contains_key(&id(1, 2)));
+
+        // Chunk 1 gets evicted once it's unpinned.
+        evictor.unpin_chunks(1, &[1]);
+        evictor.on_access(id(1, 2), 0x2000, 0x1000).unwrap();
+        let state = evictor.state.lock().unwrap();
+        assert_eq!(state.used, 0x2000);
+        assert!(state.chunks.contains_key(&id(1, 0)));
+        assert!(!state.chunks.contains_key(&id(1, 1)));
+    }
+}
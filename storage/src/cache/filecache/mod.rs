@@ -5,24 +5,36 @@
 
 use std::collections::HashMap;
 use std::fs::OpenOptions;
-use std::io::Result;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::io::{ErrorKind, Result};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
 
 use tokio::runtime::Runtime;
 
-use nydus_api::CacheConfigV2;
+use nydus_api::{CacheConfigV2, DigestMismatchPolicy, PrefetchDenyEntry};
 use nydus_utils::crypt;
 use nydus_utils::metrics::BlobcacheMetrics;
+use nydus_utils::round_up;
 
 use crate::backend::BlobBackend;
-use crate::cache::cachedfile::{FileCacheEntry, FileCacheMeta};
+use crate::cache::cachedfile::{
+    BlobMemoryTier, BlobTocState, CacheFileMap, CacheWriteState, ChunkAccessHeatmap, ChunkMapBatch,
+    ChunkReadahead, FileCacheEntry, FileCacheMeta, SharedMemoryTier,
+};
+use crate::cache::checksum::{CacheFileChecksum, CACHE_CHECKSUM_FILE_SUFFIX};
+use crate::cache::dummycache::DummyCache;
+use crate::cache::eviction::{new_eviction_policy, BlobEvictionHandle, CacheEvictor};
 use crate::cache::state::{
-    BlobStateMap, ChunkMap, DigestedChunkMap, IndexedChunkMap, NoopChunkMap,
+    BlobStateMap, ChunkGenerationMap, ChunkMap, DigestedChunkMap, IndexedChunkMap, NoopChunkMap,
 };
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobCacheMgr};
+use crate::cache::{
+    max_decompress_size, prefetch_deny_ranges, BlobCache, BlobCacheMgr, BlobCacheStatus,
+    CacheMetrics, PrefetchProgress,
+};
 use crate::device::{BlobFeatures, BlobInfo};
+use crate::meta::BLOB_TOC_FILE_SUFFIX;
+use crate::utils::is_memory_backed_file;
 
 pub const BLOB_RAW_FILE_SUFFIX: &str = ".blob.raw";
 pub const BLOB_DATA_FILE_SUFFIX: &str = ".blob.data";
@@ -39,6 +51,8 @@ pub struct FileCacheMgr {
     worker_mgr: Arc<AsyncWorkerMgr>,
     work_dir: String,
     validate: bool,
+    validate_strict: bool,
+    digest_mismatch_policy: DigestMismatchPolicy,
     disable_indexed_map: bool,
     cache_raw_data: bool,
     cache_encrypted: bool,
@@ -46,6 +60,29 @@ pub struct FileCacheMgr {
     cache_encryption_key: String,
     closed: Arc<AtomicBool>,
     user_io_batch_size: u32,
+    max_user_merge_size: u64,
+    max_decompress_size: u64,
+    chunk_map_batch_size: u32,
+    chunk_map_batch_interval: u64,
+    chunk_map_sync_interval: u64,
+    degrade_truncated_blob: bool,
+    memory_backed: bool,
+    mmap_size_threshold: u64,
+    mmap_read_threshold: u32,
+    read_amplification_limit: u32,
+    write_state: Option<Arc<CacheWriteState>>,
+    memory_tier: Option<Arc<SharedMemoryTier>>,
+    evictor: Option<Arc<CacheEvictor>>,
+    blob_priorities: HashMap<String, u8>,
+    access_heatmap: bool,
+    parallel_decompress_threshold: u32,
+    parallel_decompress_threads: u32,
+    readahead_chunks: usize,
+    verify_on_startup: bool,
+    cache_block_align: u64,
+    prefetch_deny_list: Vec<PrefetchDenyEntry>,
+    zero_out_of_bounds_chunks: bool,
+    cache_metrics: Arc<CacheMetrics>,
 }
 
 impl FileCacheMgr {
@@ -62,6 +99,19 @@ impl FileCacheMgr {
         let metrics = BlobcacheMetrics::new(id, work_dir);
         let prefetch_config: Arc<AsyncPrefetchConfig> = Arc::new((&config.prefetch).into());
         let worker_mgr = AsyncWorkerMgr::new(metrics.clone(), prefetch_config.clone())?;
+        let cache_block_align = blob_cfg.cache_block_align as u64;
+        if cache_block_align != 0 && !cache_block_align.is_power_of_two() {
+            return Err(einval!(format!(
+                "cache block alignment {} is not a power of two",
+                cache_block_align
+            )));
+        }
+        let evictor = if blob_cfg.max_cache_size > 0 {
+            let policy = new_eviction_policy(&blob_cfg.eviction_policy)?;
+            Some(Arc::new(CacheEvictor::new(policy, blob_cfg.max_cache_size)))
+        } else {
+            None
+        };
 
         Ok(FileCacheMgr {
             blobs: Arc::new(RwLock::new(HashMap::new())),
@@ -73,12 +123,48 @@ impl FileCacheMgr {
             work_dir: work_dir.to_owned(),
             disable_indexed_map: blob_cfg.disable_indexed_map,
             validate: config.cache_validate,
+            validate_strict: config.cache_validate_strict,
+            digest_mismatch_policy: config.digest_mismatch_policy,
             cache_raw_data: config.cache_compressed,
             cache_encrypted: blob_cfg.enable_encryption,
             cache_convergent_encryption: blob_cfg.enable_convergent_encryption,
             cache_encryption_key: blob_cfg.encryption_key.clone(),
             closed: Arc::new(AtomicBool::new(false)),
             user_io_batch_size,
+            max_user_merge_size: config.max_user_merge_size,
+            max_decompress_size: config.max_decompress_size,
+            chunk_map_batch_size: blob_cfg.chunk_map_batch_size,
+            chunk_map_batch_interval: blob_cfg.chunk_map_batch_interval,
+            chunk_map_sync_interval: blob_cfg.chunk_map_sync_interval,
+            degrade_truncated_blob: blob_cfg.degrade_truncated_blob,
+            memory_backed: blob_cfg.memory_backed,
+            mmap_size_threshold: blob_cfg.mmap_size_threshold,
+            mmap_read_threshold: blob_cfg.mmap_read_threshold,
+            read_amplification_limit: blob_cfg.read_amplification_limit,
+            write_state: if blob_cfg.readonly_fallback {
+                Some(Arc::new(CacheWriteState::new(
+                    work_dir,
+                    blob_cfg.readonly_probe_interval,
+                )))
+            } else {
+                None
+            },
+            memory_tier: if blob_cfg.memory_tier_size > 0 {
+                Some(Arc::new(SharedMemoryTier::new(blob_cfg.memory_tier_size)))
+            } else {
+                None
+            },
+            evictor,
+            blob_priorities: blob_cfg.blob_priorities.clone(),
+            access_heatmap: blob_cfg.enable_access_heatmap,
+            parallel_decompress_threshold: blob_cfg.parallel_decompress_threshold,
+            parallel_decompress_threads: blob_cfg.parallel_decompress_threads,
+            readahead_chunks: blob_cfg.readahead_chunks,
+            verify_on_startup: blob_cfg.verify_on_startup,
+            cache_block_align,
+            prefetch_deny_list: config.prefetch_deny_list.clone(),
+            zero_out_of_bounds_chunks: config.zero_out_of_bounds_chunks,
+            cache_metrics: Arc::new(CacheMetrics::default()),
         })
     }
 
@@ -102,20 +188,79 @@ impl FileCacheMgr {
             self.worker_mgr.clone(),
         )?;
         let entry = Arc::new(entry);
+        entry.check_cache_file()?;
         let mut guard = self.blobs.write().unwrap();
         if let Some(entry) = guard.get(&blob.blob_id()) {
             Ok(entry.clone())
         } else {
             let blob_id = blob.blob_id();
             guard.insert(blob_id.clone(), entry.clone());
+            if let Some(eviction) = entry.eviction.as_ref() {
+                eviction.evictor().register_blob(eviction.blob(), &entry);
+            }
+            if let Some(readahead) = entry.readahead.as_ref() {
+                readahead.set_cache(&entry);
+            }
+            FileCacheEntry::start_chunk_map_batch_flusher(&entry);
             self.metrics
                 .underlying_files
                 .lock()
                 .unwrap()
-                .insert(blob_id + BLOB_DATA_FILE_SUFFIX);
+                .insert(blob_id + &self.data_file_suffix());
             Ok(entry)
         }
     }
+
+    // Get suffix of the cache file for uncompressed chunk data, which differs by data layout.
+    fn data_file_suffix(&self) -> String {
+        if self.cache_raw_data || self.cache_block_align == 0 {
+            BLOB_DATA_FILE_SUFFIX.to_string()
+        } else {
+            format!("{}.align{}", BLOB_DATA_FILE_SUFFIX, self.cache_block_align)
+        }
+    }
+
+    // Get size of the slot to store a chunk in the cache file, zero if chunks are stored at
+    // their uncompressed offsets.
+    fn cache_slot_size(&self, blob_info: &BlobInfo) -> u64 {
+        if self.cache_raw_data || self.cache_block_align == 0 {
+            0
+        } else {
+            round_up(blob_info.chunk_size() as u64, self.cache_block_align)
+        }
+    }
+
+    // Get expected size of the cache file of the blob.
+    fn cached_file_size(&self, blob_info: &BlobInfo) -> u64 {
+        if self.cache_raw_data {
+            blob_info.compressed_data_size()
+        } else {
+            match self.cache_slot_size(blob_info) {
+                0 => blob_info.uncompressed_size(),
+                v => blob_info.chunk_count() as u64 * v,
+            }
+        }
+    }
+
+    // Create a passthrough cache object serving data from the storage backend directly, used
+    // when cache files can't be created because the working directory is read-only.
+    fn create_passthrough_cache(&self, blob: &Arc<BlobInfo>) -> Result<Arc<dyn BlobCache>> {
+        let blob_id = blob.blob_id();
+        let reader = self
+            .backend
+            .get_reader(&blob_id)
+            .map_err(|e| eio!(format!("failed to get reader for blob {}, {}", blob_id, e)))?;
+        warn!("serve blob {} in read-only passthrough mode", blob_id);
+
+        Ok(Arc::new(DummyCache::new(
+            blob,
+            reader,
+            false,
+            self.validate && !blob.is_legacy_stargz(),
+            self.validate_strict,
+            CacheMetrics::new(Some(self.cache_metrics.clone())),
+        )))
+    }
 }
 
 impl BlobCacheMgr for FileCacheMgr {
@@ -150,6 +295,9 @@ impl BlobCacheMgr for FileCacheMgr {
             let mut guard = self.blobs.write().unwrap();
             if let Some(entry) = guard.get(key) {
                 if Arc::strong_count(entry) == 1 {
+                    if let Some(eviction) = entry.eviction.as_ref() {
+                        eviction.evictor().remove_blob(eviction.blob());
+                    }
                     guard.remove(key);
                 }
             }
@@ -163,11 +311,45 @@ impl BlobCacheMgr for FileCacheMgr {
     }
 
     fn get_blob_cache(&self, blob_info: &Arc<BlobInfo>) -> Result<Arc<dyn BlobCache>> {
-        self.get_or_create_cache_entry(blob_info)
-            .map(|v| v as Arc<dyn BlobCache>)
+        match self.get_or_create_cache_entry(blob_info) {
+            Ok(entry) => Ok(entry as Arc<dyn BlobCache>),
+            // `DummyCache` doesn't support ZRan based blobs.
+            Err(e) if blob_info.has_feature(BlobFeatures::ZRAN) => Err(e),
+            Err(e) => match self.write_state.as_ref() {
+                Some(state) if state.check_write_error(&e) => {
+                    self.create_passthrough_cache(blob_info)
+                }
+                _ => Err(e),
+            },
+        }
     }
 
     fn check_stat(&self) {}
+
+    fn metrics(&self) -> CacheMetrics {
+        self.cache_metrics.as_ref().clone()
+    }
+
+    fn invalidate_blob(&self, blob_id: &str, punch_hole: bool) -> Result<()> {
+        // Don't block accesses to other blobs while invalidating.
+        let entry = self.blobs.read().unwrap().get(blob_id).cloned();
+        match entry {
+            Some(entry) => entry.invalidate(punch_hole),
+            None => Err(enoent!(format!("blob {} is not cached", blob_id))),
+        }
+    }
+
+    fn list_blobs(&self) -> Vec<BlobCacheStatus> {
+        let mut blobs: Vec<BlobCacheStatus> = self
+            .blobs
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| entry.status())
+            .collect();
+        blobs.sort_by(|a, b| a.blob_id.cmp(&b.blob_id));
+        blobs
+    }
 }
 
 impl Drop for FileCacheMgr {
@@ -212,7 +394,15 @@ impl FileCacheEntry {
         let blob_compressed_size = Self::get_blob_size(&reader, &blob_info)?;
         let blob_uncompressed_size = blob_info.uncompressed_size();
         let is_legacy_stargz = blob_info.is_legacy_stargz();
+        let blob_toc = if !is_tarfs && blob_info.blob_toc_size() != 0 {
+            let path = format!("{}/{}.{}", mgr.work_dir, blob_id, BLOB_TOC_FILE_SUFFIX);
+            Some(BlobTocState::new(path, blob_meta_reader.clone()))
+        } else {
+            None
+        };
 
+        // Size of the cache file if it's smaller than expected, chunks beyond it must be cleared.
+        let mut truncated_size = None;
         let (
             file,
             meta,
@@ -241,23 +431,22 @@ impl FileCacheEntry {
                 && !is_legacy_stargz;
             // Set cache file to its expected size.
             let suffix = if mgr.cache_raw_data {
-                BLOB_RAW_FILE_SUFFIX
+                BLOB_RAW_FILE_SUFFIX.to_string()
             } else {
-                BLOB_DATA_FILE_SUFFIX
+                mgr.data_file_suffix()
             };
-            let blob_data_file_path = blob_file_path.clone() + suffix;
+            let blob_data_file_path = blob_file_path.clone() + &suffix;
             let file = OpenOptions::new()
                 .create(true)
                 .write(true)
                 .read(true)
                 .open(blob_data_file_path)?;
             let file_size = file.metadata()?.len();
-            let cached_file_size = if mgr.cache_raw_data {
-                blob_info.compressed_data_size()
-            } else {
-                blob_info.uncompressed_size()
-            };
+            let cached_file_size = mgr.cached_file_size(&blob_info);
             if file_size == 0 || file_size < cached_file_size {
+                if is_direct_chunkmap && blob_info.meta_ci_is_valid() {
+                    truncated_size = Some(file_size);
+                }
                 file.set_len(cached_file_size)?;
             } else if cached_file_size != 0 && file_size != cached_file_size {
                 let msg = format!(
@@ -292,6 +481,14 @@ impl FileCacheEntry {
             )
         };
 
+        let checksum = (mgr.verify_on_startup && !is_tarfs).then(|| {
+            let path = format!(
+                "{}/{}.{}",
+                mgr.work_dir, blob_id, CACHE_CHECKSUM_FILE_SUFFIX
+            );
+            Arc::new(CacheFileChecksum::new(path))
+        });
+
         let (cache_cipher_object, cache_cipher_context) = if mgr.cache_encrypted {
             let key = hex::decode(mgr.cache_encryption_key.clone())
                 .map_err(|_e| einval!("invalid cache file encryption key"))?;
@@ -317,12 +514,100 @@ impl FileCacheEntry {
             is_batch,
             is_zran,
         );
-        Ok(FileCacheEntry {
+        let cache_slot_size = if is_tarfs {
+            0
+        } else {
+            mgr.cache_slot_size(&blob_info)
+        };
+        let cached_data_size = mgr.cached_file_size(&blob_info);
+        // Serve reads from memory mapping for plaintext cache files stored in memory, or small
+        // cache files once they have been read often enough.
+        let cache_map =
+            if is_tarfs || mgr.cache_raw_data || mgr.cache_encrypted || cached_data_size == 0 {
+                None
+            } else if mgr.memory_backed || is_memory_backed_file(&file) {
+                Some(CacheFileMap::new(
+                    &file,
+                    &blob_id,
+                    cached_data_size as usize,
+                    0,
+                ))
+            } else if cached_data_size <= mgr.mmap_size_threshold {
+                Some(CacheFileMap::new(
+                    &file,
+                    &blob_id,
+                    cached_data_size as usize,
+                    mgr.mmap_read_threshold,
+                ))
+            } else {
+                None
+            };
+        let memory_tier = match mgr.memory_tier.as_ref() {
+            Some(tier) if !is_tarfs => Some(BlobMemoryTier::new(tier.clone(), blob_id.clone())),
+            _ => None,
+        };
+        // Evicting chunks is only supported for blobs with chunk data tracked by IndexedChunkMap.
+        let eviction = match mgr.evictor.as_ref() {
+            Some(evictor)
+                if is_direct_chunkmap
+                    && !is_tarfs
+                    && !is_batch
+                    && !is_zran
+                    && !is_legacy_stargz =>
+            {
+                Some(BlobEvictionHandle::new(
+                    evictor.clone(),
+                    mgr.cache_raw_data,
+                    cache_slot_size,
+                ))
+            }
+            _ => None,
+        };
+        // Invalidating stale chunks requires the same support as evicting chunks.
+        let chunk_generations =
+            if is_direct_chunkmap && !is_tarfs && !is_batch && !is_zran && !is_legacy_stargz {
+                let blob_file_path = format!("{}/{}", mgr.work_dir, blob_id);
+                Some(ChunkGenerationMap::new(&blob_file_path)?)
+            } else {
+                None
+            };
+        // Batching chunk map updates only makes sense for persistent IndexedChunkMap.
+        let chunk_map_batch = if mgr.chunk_map_batch_size > 0
+            && is_direct_chunkmap
+            && !is_tarfs
+            && !mgr.cache_raw_data
+        {
+            Some(ChunkMapBatch::new(
+                mgr.chunk_map_batch_size,
+                mgr.chunk_map_batch_interval,
+                eviction.clone(),
+            ))
+        } else {
+            None
+        };
+
+        let priority = mgr
+            .blob_priorities
+            .get(&blob_id)
+            .copied()
+            .unwrap_or_default();
+        let access_heatmap = if mgr.access_heatmap {
+            Some(ChunkAccessHeatmap::new(blob_info.chunk_count()))
+        } else {
+            None
+        };
+
+        let max_decompress_size = max_decompress_size(mgr.max_decompress_size, &blob_info);
+        let prefetch_deny = prefetch_deny_ranges(&mgr.prefetch_deny_list, &blob_id);
+        let entry = FileCacheEntry {
             blob_id,
             blob_info,
             cache_cipher_object,
             cache_cipher_context,
             chunk_map,
+            chunk_map_batch,
+            blob_toc,
+            cache_map,
             file: Arc::new(file),
             meta,
             metrics: mgr.metrics.clone(),
@@ -333,6 +618,8 @@ impl FileCacheEntry {
 
             blob_compressed_size,
             blob_uncompressed_size,
+            truncated_blob_size: AtomicU64::new(u64::MAX),
+            degrade_truncated_blob: mgr.degrade_truncated_blob,
             is_get_blob_object_supported,
             is_raw_data: mgr.cache_raw_data,
             is_cache_encrypted: mgr.cache_encrypted,
@@ -341,11 +628,46 @@ impl FileCacheEntry {
             is_tarfs,
             is_batch,
             is_zran,
+            zero_chunks: !mgr.cache_raw_data && !mgr.cache_encrypted && !is_tarfs,
             dio_enabled: false,
             need_validation,
+            strict_validation: mgr.validate_strict,
+            digest_mismatch_policy: mgr.digest_mismatch_policy,
+            no_digest_reported: AtomicBool::new(false),
             user_io_batch_size: mgr.user_io_batch_size,
+            max_user_merge_size: mgr.max_user_merge_size,
+            max_decompress_size,
+            cache_block_align: if cache_slot_size == 0 {
+                0
+            } else {
+                mgr.cache_block_align
+            },
+            cache_slot_size,
+            read_amplification_limit: mgr.read_amplification_limit,
+            write_state: mgr.write_state.clone(),
+            memory_tier,
+            eviction,
+            chunk_generations,
+            priority,
+            access_heatmap,
+            parallel_decompress_threshold: mgr.parallel_decompress_threshold,
+            parallel_decompress_threads: mgr.parallel_decompress_threads,
+            readahead: (mgr.readahead_chunks > 0)
+                .then(|| ChunkReadahead::new(mgr.readahead_chunks.min(u32::MAX as usize) as u32)),
+            checksum,
+            cache_metrics: CacheMetrics::new(Some(mgr.cache_metrics.clone())),
             prefetch_config,
-        })
+            prefetch_merging_size: AtomicU64::new(0),
+            prefetch_progress: PrefetchProgress::default(),
+            prefetch_deny,
+            zero_out_of_bounds_chunks: mgr.zero_out_of_bounds_chunks,
+            last_access: AtomicU64::new(0),
+        };
+        if let Some(file_size) = truncated_size {
+            entry.repair_truncated_cache(file_size)?;
+        }
+
+        Ok(entry)
     }
 
     fn create_chunk_map(
@@ -364,11 +686,33 @@ impl FileCacheEntry {
             direct_chunkmap = false;
             Arc::new(BlobStateMap::from(DigestedChunkMap::new()))
         } else {
-            Arc::new(BlobStateMap::from(IndexedChunkMap::new(
-                &format!("{}{}", blob_file, BLOB_DATA_FILE_SUFFIX),
-                blob_info.chunk_count(),
-                true,
-            )?))
+            let map_path = format!("{}{}", blob_file, mgr.data_file_suffix());
+            // Cached chunks are lost if the cache file is missing or has been truncated, so states
+            // recorded by the chunk map can't be trusted anymore. Chunks can be located with blob
+            // meta, so only chunks beyond the end of the cache file are cleared after loading it.
+            let suffix = if mgr.cache_raw_data {
+                BLOB_RAW_FILE_SUFFIX.to_string()
+            } else {
+                mgr.data_file_suffix()
+            };
+            let cached_file_size = mgr.cached_file_size(blob_info);
+            let file_size = match std::fs::metadata(format!("{}{}", blob_file, suffix)) {
+                Ok(md) => md.len(),
+                Err(e) if e.kind() == ErrorKind::NotFound => 0,
+                Err(e) => return Err(e),
+            };
+            if file_size < cached_file_size
+                && !blob_info.meta_ci_is_valid()
+                && IndexedChunkMap::discard(&map_path)?
+            {
+                warn!(
+                    "cache file of blob {} is smaller than expected, discard its chunk map",
+                    blob_info.blob_id()
+                );
+            }
+            let mut chunk_map = IndexedChunkMap::new(&map_path, blob_info.chunk_count(), true)?;
+            chunk_map.set_sync_interval(mgr.chunk_map_sync_interval);
+            Arc::new(BlobStateMap::from(chunk_map))
         };
 
         Ok((chunk_map, direct_chunkmap))
@@ -377,10 +721,247 @@ impl FileCacheEntry {
 
 #[cfg(test)]
 pub mod blob_cache_tests {
-    use nydus_api::FileCacheConfig;
+    use std::os::unix::fs::{FileExt, MetadataExt};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::sync::{Mutex, MutexGuard};
+    use std::time::{Duration, Instant};
+
+    use fuse_backend_rs::file_buf::FileVolatileSlice;
+    use nydus_api::{FileCacheConfig, PrefetchStrategy};
+    use nydus_utils::digest::RafsDigest;
+    use nydus_utils::metrics::{BackendMetrics, Metric};
+    use nydus_utils::{compress, digest};
     use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
+    use super::*;
+    use crate::access_trace::AccessTrace;
+    use crate::backend::{BackendResult, BlobReader};
+    use crate::cache::{CacheError, ReadStats};
+    use crate::device::{
+        BlobChunkFlags, BlobChunkInfo, BlobIoChunk, BlobIoDesc, BlobIoKind, BlobIoRange, BlobIoVec,
+        BlobObject, BlobPrefetchRequest,
+    };
+    use crate::meta::{BlobCompressionContextHeader, BlobMetaChunkArray};
+    use crate::test::MockChunkInfo;
+
+    // A storage backend serving data of `blob` for all blobs, and recording backend reads.
+    #[derive(Clone)]
+    struct MockBackend {
+        blob_id: String,
+        blob: Arc<Vec<u8>>,
+        // Delay of reading data at each offset, in milliseconds.
+        delays: Arc<HashMap<u64, u64>>,
+        reads: Arc<AtomicUsize>,
+        // Backend reads in form of (blob id, offset, size).
+        requests: Arc<Mutex<Vec<(String, u64, usize)>>>,
+        // Number of backend reads in flight, and the peak of it.
+        active: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+        // Number of following backend reads returning corrupted data.
+        corrupt_reads: Arc<AtomicUsize>,
+        metrics: Arc<BackendMetrics>,
+    }
+
+    impl MockBackend {
+        fn new(id: &str, blob: Vec<u8>) -> Self {
+            MockBackend {
+                blob_id: String::new(),
+                blob: Arc::new(blob),
+                delays: Arc::new(HashMap::new()),
+                reads: Arc::new(AtomicUsize::new(0)),
+                requests: Arc::new(Mutex::new(Vec::new())),
+                active: Arc::new(AtomicUsize::new(0)),
+                peak: Arc::new(AtomicUsize::new(0)),
+                corrupt_reads: Arc::new(AtomicUsize::new(0)),
+                metrics: BackendMetrics::new(id, "localfs"),
+            }
+        }
+
+        fn with_delays(mut self, delays: HashMap<u64, u64>) -> Self {
+            self.delays = Arc::new(delays);
+            self
+        }
+
+        fn reads(&self) -> usize {
+            self.reads.load(Ordering::Relaxed)
+        }
+
+        fn requests(&self) -> MutexGuard<Vec<(String, u64, usize)>> {
+            self.requests.lock().unwrap()
+        }
+
+        fn peak(&self) -> usize {
+            self.peak.load(Ordering::SeqCst)
+        }
+
+        fn corrupt_next_reads(&self, count: usize) {
+            self.corrupt_reads.store(count, Ordering::Relaxed);
+        }
+    }
+
+    impl BlobReader for MockBackend {
+        fn blob_size(&self) -> BackendResult<u64> {
+            Ok(self.blob.len() as u64)
+        }
+
+        fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+            self.requests()
+                .push((self.blob_id.clone(), offset, buf.len()));
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(active, Ordering::SeqCst);
+            let delay = self.delays.get(&offset).copied().unwrap_or_default();
+            std::thread::sleep(Duration::from_millis(delay));
+            let offset = offset as usize;
+            let size = std::cmp::min(buf.len(), self.blob.len().saturating_sub(offset));
+            buf[..size].copy_from_slice(&self.blob[offset..offset + size]);
+            let corrupt =
+                self.corrupt_reads
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| v.checked_sub(1));
+            if corrupt.is_ok() && size > 0 {
+                buf[0] = !buf[0];
+            }
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            Ok(size)
+        }
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+    }
+
+    impl BlobBackend for MockBackend {
+        fn shutdown(&self) {}
+
+        fn metrics(&self) -> &BackendMetrics {
+            &self.metrics
+        }
+
+        fn get_reader(&self, blob_id: &str) -> BackendResult<Arc<dyn BlobReader>> {
+            let mut reader = self.clone();
+            reader.blob_id = blob_id.to_string();
+            Ok(Arc::new(reader))
+        }
+    }
+
+    // Data of `count` chunks of `size` bytes, each filled with its chunk index plus one.
+    fn new_blob_data(count: u8, size: usize) -> Vec<u8> {
+        (0..count).flat_map(|i| vec![i + 1; size]).collect()
+    }
+
+    // Uncompressed chunks of `size` bytes, stored one after another in the blob.
+    fn new_chunks(count: u32, size: u32) -> Vec<Arc<dyn BlobChunkInfo>> {
+        (0..count)
+            .map(|i| {
+                Arc::new(MockChunkInfo {
+                    compress_size: size,
+                    uncompress_size: size,
+                    compress_offset: i as u64 * size as u64,
+                    uncompress_offset: i as u64 * size as u64,
+                    index: i,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>
+            })
+            .collect()
+    }
+
+    // A blob of `count` uncompressed chunks of `size` bytes.
+    fn new_blob_info(id: &str, count: u32, size: u32) -> Arc<BlobInfo> {
+        let blob_size = count as u64 * size as u64;
+        Arc::new(BlobInfo::new(
+            0,
+            id.to_string(),
+            blob_size,
+            blob_size,
+            size,
+            count,
+            BlobFeatures::empty(),
+        ))
+    }
+
+    // Create a file cache manager fetching data from `backend`, with a temporary working
+    // directory.
+    fn new_cache_mgr(
+        config: CacheConfigV2,
+        backend: Arc<dyn BlobBackend>,
+    ) -> (TempDir, FileCacheMgr) {
+        let tmp_dir = TempDir::new().unwrap();
+        let mgr = new_cache_mgr_in(tmp_dir.as_path(), config, backend).unwrap();
+        (tmp_dir, mgr)
+    }
+
+    // Create a file cache manager fetching data from `backend`, caching data in `work_dir`.
+    fn new_cache_mgr_in(
+        work_dir: &Path,
+        mut config: CacheConfigV2,
+        backend: Arc<dyn BlobBackend>,
+    ) -> Result<FileCacheMgr> {
+        config.cache_type = "filecache".to_string();
+        config
+            .file_cache
+            .get_or_insert_with(Default::default)
+            .work_dir = work_dir.to_str().unwrap().to_string();
+        let runtime = Arc::new(
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(1)
+                .build()
+                .unwrap(),
+        );
+        FileCacheMgr::new(&config, backend, runtime, "test", 0x1000)
+    }
+
+    // Get the blob cache object of the blob, and its file cache entry.
+    fn get_cache_entry(
+        mgr: &FileCacheMgr,
+        info: &Arc<BlobInfo>,
+    ) -> (Arc<dyn BlobCache>, Arc<FileCacheEntry>) {
+        let cache = mgr.get_blob_cache(info).unwrap();
+        (cache, mgr.get(info).unwrap())
+    }
+
+    // Read data of whole `chunks` of the blob through `cache`.
+    fn read_chunks(
+        cache: &dyn BlobCache,
+        info: &Arc<BlobInfo>,
+        chunks: &[Arc<dyn BlobChunkInfo>],
+    ) -> Result<Vec<u8>> {
+        let mut iovec = BlobIoVec::new(info.clone());
+        for chunk in chunks.iter() {
+            let size = chunk.uncompressed_size();
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                chunk.clone().into(),
+                0,
+                size,
+                true,
+            ));
+        }
+        let mut buf = vec![0u8; iovec.size() as usize];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        let size = cache.read(&mut iovec, &[slice])?;
+        buf.truncate(size);
+        Ok(buf)
+    }
+
+    // Wait for a chunk fetched from the backend to be persisted into the cache file.
+    fn wait_for_ready(cache: &dyn BlobCache, chunk: &dyn BlobChunkInfo) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !cache.get_chunk_map().is_ready(chunk).unwrap() {
+            assert!(Instant::now() < deadline, "chunk isn't persisted in time");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    // Wait at most five seconds for `cond` to become true, for background prefetch to make progress.
+    fn wait_until(cond: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !cond() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
     #[test]
     fn test_blob_cache_config() {
         // new blob cache
@@ -405,6 +986,1902 @@ pub mod blob_cache_tests {
         assert!(blob_config.get_work_dir().is_err());
     }
 
+    #[test]
+    fn test_memory_tier_precedence() {
+        let data = vec![0x5au8; 0x1000];
+        let (compressed, _) = compress::compress(&data, compress::Algorithm::Zstd).unwrap();
+        let compressed = compressed.to_vec();
+        let compressed_size = compressed.len();
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x1000,
+            compressed_size as u64,
+            0x1000,
+            1,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Zstd);
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = vec![Arc::new(MockChunkInfo {
+            block_id: RafsDigest::from_buf(&data, digest::Algorithm::Blake3),
+            flags: BlobChunkFlags::COMPRESSED,
+            compress_size: compressed_size as u32,
+            uncompress_size: 0x1000,
+            ..Default::default()
+        })];
+
+        let tmp_dir = TempDir::new().unwrap();
+        let backend = Arc::new(MockBackend::new("memory-tier", compressed));
+        let new_cache = |memory_tier_size: u64| {
+            let config = CacheConfigV2 {
+                cache_compressed: true,
+                cache_validate: true,
+                file_cache: Some(FileCacheConfig {
+                    memory_tier_size,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let mgr = new_cache_mgr_in(tmp_dir.as_path(), config, backend.clone()).unwrap();
+            mgr.get_blob_cache(&info).unwrap()
+        };
+
+        // The chunk is fetched from the backend, cached on disk and promoted into memory.
+        let cache = new_cache(0x10000);
+        assert_eq!(read_chunks(cache.as_ref(), &info, &chunks).unwrap(), data);
+        assert_eq!(backend.reads(), 1);
+
+        // Corrupt data on disk, the memory tier is consulted before the cache file.
+        let raw_file = tmp_dir
+            .as_path()
+            .join(format!("blob-0{}", BLOB_RAW_FILE_SUFFIX));
+        std::fs::write(raw_file, vec![0xffu8; compressed_size]).unwrap();
+        assert_eq!(read_chunks(cache.as_ref(), &info, &chunks).unwrap(), data);
+        assert_eq!(backend.reads(), 1);
+
+        // Without the memory tier, the corrupted cache file is skipped in favor of the backend.
+        let cache = new_cache(0);
+        assert_eq!(read_chunks(cache.as_ref(), &info, &chunks).unwrap(), data);
+        assert_eq!(backend.reads(), 2);
+    }
+
+    #[test]
+    fn test_evict_chunks_exceeding_max_cache_size() {
+        let blob = new_blob_data(4, 0x1000);
+        let info = new_blob_info("blob-0", 4, 0x1000);
+        let chunks = new_chunks(4, 0x1000);
+        let backend = Arc::new(MockBackend::new("eviction", blob.clone()));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            file_cache: Some(FileCacheConfig {
+                max_cache_size: 0x2000,
+                eviction_policy: "lru".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let read = |idx: usize| {
+            let buf = read_chunks(cache.as_ref(), &info, &chunks[idx..=idx]).unwrap();
+            assert_eq!(buf, blob[idx * 0x1000..(idx + 1) * 0x1000]);
+        };
+        let is_ready = |idx: usize| entry.chunk_map.is_ready(chunks[idx].as_ref()).unwrap();
+
+        read(0);
+        read(1);
+        assert_eq!(backend.reads(), 2);
+        // Access chunk 0 again, so chunk 1 becomes the least recently used one.
+        read(0);
+        assert_eq!(backend.reads(), 2);
+
+        // Writing chunk 2 exceeds the limit and evicts chunk 1.
+        read(2);
+        assert_eq!(backend.reads(), 3);
+        assert!(is_ready(0));
+        assert!(!is_ready(1));
+        assert!(is_ready(2));
+        let raw_file = format!("{}/blob-0{}", mgr.work_dir, BLOB_RAW_FILE_SUFFIX);
+        let cached = std::fs::read(raw_file).unwrap();
+        assert!(cached[0x1000..0x2000].iter().all(|v| *v == 0));
+        assert_eq!(cached[0x2000..0x3000], blob[0x2000..0x3000]);
+
+        // Evicted chunks are fetched from the backend again, evicting chunk 0 this time.
+        read(1);
+        assert_eq!(backend.reads(), 4);
+        assert!(!is_ready(0));
+        assert!(is_ready(1));
+        assert!(is_ready(2));
+
+        // Pinned chunks survive even as the least recently used ones.
+        cache.pin_chunks(&[1]).unwrap();
+        read(3);
+        read(0);
+        assert_eq!(backend.reads(), 6);
+        assert!(is_ready(0));
+        assert!(is_ready(1));
+        assert!(!is_ready(2));
+        assert!(!is_ready(3));
+        assert!(cache.pin_chunks(&[4]).is_err());
+
+        cache.unpin_chunks(&[1]).unwrap();
+        read(2);
+        assert!(!is_ready(1));
+        assert!(is_ready(2));
+    }
+
+    #[test]
+    fn test_evict_with_pinned_chunks_exceeding_limit() {
+        let blob = new_blob_data(3, 0x1000);
+        let info = new_blob_info("blob-0", 3, 0x1000);
+        let chunks = new_chunks(3, 0x1000);
+        let backend = Arc::new(MockBackend::new("eviction", blob.clone()));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            file_cache: Some(FileCacheConfig {
+                max_cache_size: 0x2000,
+                eviction_policy: "lru".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let is_ready = |idx: usize| entry.chunk_map.is_ready(chunks[idx].as_ref()).unwrap();
+
+        cache.pin_chunks(&[0, 1]).unwrap();
+        read_chunks(cache.as_ref(), &info, &chunks[0..2]).unwrap();
+        assert!(is_ready(0));
+        assert!(is_ready(1));
+
+        // Chunks not fitting in the size limit because of pinned chunks are not kept.
+        let err = read_chunks(cache.as_ref(), &info, &chunks[2..3]).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSPC));
+        let cancel = AtomicBool::new(false);
+        let err = entry.warm_chunks(&chunks[2..3], &cancel).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ENOSPC));
+        assert!(is_ready(0));
+        assert!(is_ready(1));
+        assert!(!is_ready(2));
+
+        cache.unpin_chunks(&[1]).unwrap();
+        let buf = read_chunks(cache.as_ref(), &info, &chunks[2..3]).unwrap();
+        assert_eq!(buf, blob[0x2000..0x3000]);
+        assert!(is_ready(0));
+        assert!(!is_ready(1));
+        assert!(is_ready(2));
+    }
+
+    #[test]
+    fn test_evict_batched_chunks() {
+        let blob = new_blob_data(4, 0x1000);
+        let info = new_blob_info("blob-0", 4, 0x1000);
+        let chunks = new_chunks(4, 0x1000);
+        let backend = Arc::new(MockBackend::new("eviction", blob.clone()));
+        let config = CacheConfigV2 {
+            file_cache: Some(FileCacheConfig {
+                max_cache_size: 0x2000,
+                eviction_policy: "lru".to_string(),
+                chunk_map_batch_size: 2,
+                chunk_map_batch_interval: 60000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let prefetch = |idx: usize| {
+            let bio = BlobIoDesc::new(info.clone(), chunks[idx].clone().into(), 0, 0x1000, false);
+            entry.prefetch_chunks(&BlobIoRange::new(&bio, 1)).unwrap();
+        };
+        let is_ready = |idx: usize| entry.chunk_map.is_ready(chunks[idx].as_ref()).unwrap();
+
+        // Buffered chunks are accounted by the evictor once committed.
+        prefetch(0);
+        assert!(!is_ready(0));
+        prefetch(1);
+        assert!(is_ready(0));
+        assert!(is_ready(1));
+        prefetch(2);
+        assert!(!is_ready(2));
+        prefetch(3);
+        assert!(!is_ready(0));
+        assert!(!is_ready(1));
+        assert!(is_ready(2));
+        assert!(is_ready(3));
+        assert_eq!(backend.reads(), 4);
+
+        let buf = read_chunks(cache.as_ref(), &info, &chunks[2..4]).unwrap();
+        assert_eq!(buf, blob[0x2000..0x4000]);
+        assert_eq!(backend.reads(), 4);
+    }
+
+    #[test]
+    fn test_verify_cache() {
+        let blob = new_blob_data(3, 0x1000);
+        let info = new_blob_info("blob-0", 3, 0x1000);
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = (0..3u32)
+            .map(|i| {
+                let range = i as usize * 0x1000..(i as usize + 1) * 0x1000;
+                Arc::new(MockChunkInfo {
+                    block_id: RafsDigest::from_buf(&blob[range], digest::Algorithm::Blake3),
+                    compress_size: 0x1000,
+                    uncompress_size: 0x1000,
+                    compress_offset: i as u64 * 0x1000,
+                    uncompress_offset: i as u64 * 0x1000,
+                    index: i,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>
+            })
+            .collect();
+
+        let backend = Arc::new(MockBackend::new("verify", blob.clone()));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let read = |idx: usize| {
+            let buf = read_chunks(cache.as_ref(), &info, &chunks[idx..=idx]).unwrap();
+            assert_eq!(buf, blob[idx * 0x1000..(idx + 1) * 0x1000]);
+        };
+        read(0);
+        read(1);
+        assert_eq!(backend.reads(), 2);
+
+        // Tear data of chunk 1 in the cache file behind the chunk map.
+        let cache_file = format!("{}/blob-0{}", mgr.work_dir, BLOB_RAW_FILE_SUFFIX);
+        let file = OpenOptions::new().write(true).open(cache_file).unwrap();
+        file.write_all_at(&[0xffu8; 0x100], 0x1800).unwrap();
+
+        // Chunk 2 isn't ready, so it's not verified.
+        let report = entry.verify_chunks(&chunks, false).unwrap();
+        assert_eq!(report.good, 1);
+        assert_eq!(report.corrupted, 1);
+        assert_eq!(report.corrupted_chunks, vec![1]);
+        assert!(entry.chunk_map.is_ready(chunks[1].as_ref()).unwrap());
+
+        let report = entry.verify_chunks(&chunks, true).unwrap();
+        assert_eq!(report.corrupted_chunks, vec![1]);
+        assert!(entry.chunk_map.is_ready(chunks[0].as_ref()).unwrap());
+        assert!(!entry.chunk_map.is_ready(chunks[1].as_ref()).unwrap());
+
+        // The corrupted chunk is fetched from the backend again.
+        read(1);
+        assert_eq!(backend.reads(), 3);
+        let report = entry.verify_chunks(&chunks, true).unwrap();
+        assert_eq!(report.good, 2);
+        assert_eq!(report.corrupted, 0);
+
+        // Chunks can't be enumerated without blob meta.
+        assert!(cache.verify_cache(false).is_err());
+    }
+
+    #[test]
+    fn test_digest_mismatch_policy() {
+        let blob = vec![0x5au8; 0x1000];
+        let info = new_blob_info("blob-0", 1, 0x1000);
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = vec![Arc::new(MockChunkInfo {
+            block_id: RafsDigest::from_buf(&blob, digest::Algorithm::Blake3),
+            compress_size: 0x1000,
+            uncompress_size: 0x1000,
+            ..Default::default()
+        })];
+
+        // Cache the chunk, corrupt it in the cache file, and make the next backend read corrupted.
+        let setup = |policy: DigestMismatchPolicy| {
+            let backend = Arc::new(MockBackend::new("digest", blob.clone()));
+            let config = CacheConfigV2 {
+                cache_validate: true,
+                digest_mismatch_policy: policy,
+                ..Default::default()
+            };
+            let (tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+            let (cache, entry) = get_cache_entry(&mgr, &info);
+            let cancel = AtomicBool::new(false);
+            assert_eq!(entry.warm_chunks(&chunks, &cancel).unwrap(), 0x1000);
+            assert_eq!(backend.reads(), 1);
+
+            let cache_file = format!("{}/blob-0{}", mgr.work_dir, BLOB_DATA_FILE_SUFFIX);
+            let file = OpenOptions::new().write(true).open(cache_file).unwrap();
+            file.write_all_at(&[0xffu8; 0x100], 0x800).unwrap();
+            backend.corrupt_next_reads(1);
+            (tmp_dir, mgr, cache, backend)
+        };
+
+        // The corrupted cache entry is dropped, and the corrupted backend data fails the request.
+        let (_tmp_dir, _mgr, cache, backend) = setup(DigestMismatchPolicy::FailFast);
+        assert!(read_chunks(cache.as_ref(), &info, &chunks).is_err());
+        assert_eq!(backend.reads(), 2);
+
+        // The chunk is fetched from the backend once more after the corrupted backend read.
+        let (_tmp_dir, _mgr, cache, backend) = setup(DigestMismatchPolicy::RefetchOnce);
+        assert_eq!(read_chunks(cache.as_ref(), &info, &chunks).unwrap(), blob);
+        assert_eq!(backend.reads(), 3);
+
+        // The corrupted cache entry is served without touching the backend.
+        let (_tmp_dir, mgr, cache, backend) = setup(DigestMismatchPolicy::LogAndServe);
+        let buf = read_chunks(cache.as_ref(), &info, &chunks).unwrap();
+        assert_eq!(buf[0x800..0x900], [0xffu8; 0x100]);
+        assert_eq!(backend.reads(), 1);
+        assert_eq!(mgr.metrics.digest_mismatch_served.count(), 1);
+    }
+
+    #[test]
+    fn test_read_tarfs_blob() {
+        let foo: Vec<u8> = (0..0x3000u32).map(|v| (v % 251) as u8).collect();
+        let bar = vec![0xa5u8; 0x800];
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in [("foo", &foo), ("bar", &bar)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, data.as_slice())
+                .unwrap();
+        }
+        let tar = builder.into_inner().unwrap();
+
+        // Chunks of file data are located in the tar stream directly, right after their headers.
+        let mut ranges = Vec::new();
+        let mut archive = tar::Archive::new(tar.as_slice());
+        for entry in archive.entries().unwrap() {
+            let entry = entry.unwrap();
+            let offset = entry.raw_file_position();
+            let size = entry.header().size().unwrap();
+            ranges.extend(
+                (0..size)
+                    .step_by(0x1000)
+                    .map(|pos| (offset + pos, std::cmp::min(size - pos, 0x1000) as u32)),
+            );
+        }
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = ranges
+            .iter()
+            .enumerate()
+            .map(|(i, (offset, size))| {
+                Arc::new(MockChunkInfo {
+                    compress_size: *size,
+                    uncompress_size: *size,
+                    compress_offset: *offset,
+                    uncompress_offset: *offset,
+                    index: i as u32,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>
+            })
+            .collect();
+        assert_eq!(chunks.len(), 4);
+
+        let tmp_dir = TempDir::new().unwrap();
+        std::fs::write(tmp_dir.as_path().join("blob-0"), &tar).unwrap();
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            tar.len() as u64,
+            tar.len() as u64,
+            0x1000,
+            chunks.len() as u32,
+            BlobFeatures::CAP_TAR_TOC | BlobFeatures::TARFS,
+        ));
+        let backend = Arc::new(MockBackend::new("tarfs", Vec::new()));
+        let config = CacheConfigV2 {
+            cache_validate: true,
+            ..Default::default()
+        };
+        let mgr = new_cache_mgr_in(tmp_dir.as_path(), config, backend.clone()).unwrap();
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        for chunk in chunks.iter() {
+            assert!(entry.chunk_map.is_ready(chunk.as_ref()).unwrap());
+        }
+
+        // Prefetch is a no-op since data of the tar file is always ready.
+        let prefetch = BlobPrefetchRequest {
+            blob_id: "blob-0".to_string(),
+            offset: 0,
+            len: tar.len() as u64,
+            merging_size: None,
+            bandwidth_rate: None,
+        };
+        cache.start_prefetch().unwrap();
+        cache.prefetch(cache.clone(), &[prefetch], &[]).unwrap();
+
+        // Read file "foo" across chunk boundaries, and file "bar" in whole.
+        let read = |chunk_range: std::ops::Range<usize>, offset: u32, size: usize| {
+            let mut iovec = BlobIoVec::new(info.clone());
+            let mut pos = offset;
+            let mut left = size as u32;
+            for chunk in chunks[chunk_range].iter() {
+                let len = std::cmp::min(chunk.uncompressed_size() - pos, left);
+                iovec.push(BlobIoDesc::new(
+                    info.clone(),
+                    chunk.clone().into(),
+                    pos,
+                    len,
+                    true,
+                ));
+                left -= len;
+                pos = 0;
+            }
+            let mut buf = vec![0u8; size];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), size);
+            buf
+        };
+        assert_eq!(read(0..3, 0, 0x3000), foo);
+        assert_eq!(read(1..3, 0x800, 0x1000), foo[0x1800..0x2800]);
+        assert_eq!(read(3..4, 0, 0x800), bar);
+        assert_eq!(backend.reads(), 0);
+        cache.stop_prefetch().unwrap();
+    }
+
+    #[test]
+    fn test_read_with_stats() {
+        let blob = new_blob_data(4, 0x1000);
+        let info = new_blob_info("blob-0", 4, 0x1000);
+        let chunks = new_chunks(4, 0x1000);
+        let backend = Arc::new(MockBackend::new("stats", blob.clone()));
+        let (_tmp_dir, mgr) = new_cache_mgr(CacheConfigV2::default(), backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let cancel = AtomicBool::new(false);
+        entry
+            .warm_chunks(&[chunks[0].clone(), chunks[2].clone()], &cancel)
+            .unwrap();
+
+        // Read the second half of chunk 0 through the first half of chunk 3.
+        let mut iovec = BlobIoVec::new(info.clone());
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let (offset, size) = match idx {
+                0 => (0x800, 0x800),
+                3 => (0, 0x800),
+                _ => (0, 0x1000),
+            };
+            iovec.push(BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunk.clone()),
+                offset,
+                size,
+                true,
+            ));
+        }
+        let mut buf = vec![0u8; 0x3000];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        let fetched = backend.reads();
+        let stats = cache.read_with_stats(&mut iovec, &[slice]).unwrap();
+        assert_eq!(buf, blob[0x800..0x3800]);
+        assert_eq!(
+            stats,
+            ReadStats {
+                total: 0x3000,
+                from_cache: 0x1800,
+                from_backend: 0x1800,
+                chunks_fetched: 2,
+            }
+        );
+        assert!(backend.reads() > fetched);
+
+        // Wait for fetched chunks to be persisted into the cache file.
+        for idx in [1, 3] {
+            wait_for_ready(cache.as_ref(), chunks[idx].as_ref());
+        }
+
+        // All chunks are served from the cache now.
+        let mut buf = vec![0u8; 0x3000];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        let fetched = backend.reads();
+        let stats = cache.read_with_stats(&mut iovec, &[slice]).unwrap();
+        assert_eq!(stats.from_cache, 0x3000);
+        assert_eq!(stats.chunks_fetched, 0);
+        assert_eq!(backend.reads(), fetched);
+    }
+
+    #[test]
+    fn test_read_zero_chunks() {
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x4000,
+            0x4000,
+            0x1000,
+            4,
+            BlobFeatures::empty(),
+        );
+        info.set_digester(digest::Algorithm::Blake3);
+        let info = Arc::new(info);
+        let zero_digest = RafsDigest::from_buf(&[0u8; 0x1000], digest::Algorithm::Blake3);
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = (0..4u32)
+            .map(|i| {
+                Arc::new(MockChunkInfo {
+                    block_id: zero_digest,
+                    compress_size: 0x1000,
+                    uncompress_size: 0x1000,
+                    compress_offset: i as u64 * 0x1000,
+                    uncompress_offset: i as u64 * 0x1000,
+                    index: i,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>
+            })
+            .collect();
+
+        // Zero chunks are never fetched, so the backend returns garbage.
+        let backend = Arc::new(MockBackend::new("zero", vec![0xffu8; 0x4000]));
+        let config = CacheConfigV2 {
+            cache_validate: true,
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let mut iovec = BlobIoVec::new(info.clone());
+        for chunk in chunks.iter() {
+            let chunk = chunk.clone().into();
+            iovec.push(BlobIoDesc::new(info.clone(), chunk, 0, 0x1000, true));
+        }
+        let mut buf = vec![0x5au8; 0x4000];
+        let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(cache.read(&mut iovec, &[slice]).unwrap(), 0x4000);
+        assert!(buf.iter().all(|v| *v == 0));
+        assert_eq!(backend.reads(), 0);
+
+        // Zero chunks are ready without taking space in the cache file.
+        for chunk in chunks.iter() {
+            assert!(entry.chunk_map.is_ready(chunk.as_ref()).unwrap());
+        }
+        let path = format!("{}/blob-0{}", mgr.work_dir, BLOB_DATA_FILE_SUFFIX);
+        assert_eq!(std::fs::metadata(path).unwrap().blocks(), 0);
+    }
+
+    #[test]
+    fn test_cache_block_align() {
+        // Chunks of 0x1800, 0x1000 and 0x1a00 bytes stored in slots of 0x2000 bytes.
+        let sizes = [0x1800u32, 0x1000, 0x1a00];
+        let mut blob = Vec::new();
+        let mut chunks: Vec<Arc<dyn BlobChunkInfo>> = Vec::new();
+        for (i, size) in sizes.iter().enumerate() {
+            chunks.push(Arc::new(MockChunkInfo {
+                compress_size: *size,
+                uncompress_size: *size,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: blob.len() as u64,
+                index: i as u32,
+                ..Default::default()
+            }));
+            blob.extend(std::iter::repeat(i as u8 + 1).take(*size as usize));
+        }
+        let info = Arc::new(BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            blob.len() as u64,
+            blob.len() as u64,
+            0x2000,
+            3,
+            BlobFeatures::empty(),
+        ));
+
+        let tmp_dir = TempDir::new().unwrap();
+        let backend = Arc::new(MockBackend::new("align", blob.clone()));
+        let mut config = CacheConfigV2 {
+            file_cache: Some(FileCacheConfig {
+                cache_block_align: 0x3000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(new_cache_mgr_in(tmp_dir.as_path(), config.clone(), backend.clone()).is_err());
+        config.file_cache.as_mut().unwrap().cache_block_align = 0x1000;
+        let mgr = new_cache_mgr_in(tmp_dir.as_path(), config, backend.clone()).unwrap();
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        entry.warm_chunks(&chunks, &AtomicBool::new(false)).unwrap();
+        let fetched = backend.reads();
+
+        // Chunk data starts at aligned offsets, and is padded with zeros.
+        let data_file = format!("{}/blob-0{}.align4096", mgr.work_dir, BLOB_DATA_FILE_SUFFIX);
+        let cached = std::fs::read(data_file).unwrap();
+        assert_eq!(cached.len(), 0x6000);
+        let mut pos = 0;
+        for (i, size) in sizes.iter().enumerate() {
+            let slot = &cached[i * 0x2000..(i + 1) * 0x2000];
+            assert_eq!(slot[..*size as usize], blob[pos..pos + *size as usize]);
+            assert!(slot[*size as usize..].iter().all(|v| *v == 0));
+            pos += *size as usize;
+        }
+
+        // Reads across chunks return data from the padded layout.
+        assert_eq!(read_chunks(cache.as_ref(), &info, &chunks).unwrap(), blob);
+        assert_eq!(backend.reads(), fetched);
+    }
+
+    #[test]
+    fn test_is_range_cached() {
+        let info = new_blob_info("blob-0", 4, 0x1000);
+        let chunks = new_chunks(4, 0x1000);
+        let backend = Arc::new(MockBackend::new("range", new_blob_data(4, 0x1000)));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let bios = |range: std::ops::Range<usize>| {
+            range
+                .map(|idx| {
+                    BlobIoDesc::new(info.clone(), chunks[idx].clone().into(), 0, 0x1000, true)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(!cache.is_range_cached(&bios(0..4)));
+        entry
+            .warm_chunks(&chunks[..2], &AtomicBool::new(false))
+            .unwrap();
+        assert_eq!(backend.reads(), 1);
+        // Fully cached, partially cached and uncached ranges.
+        assert!(cache.is_range_cached(&bios(0..2)));
+        assert!(cache.is_range_cached(&bios(1..2)));
+        assert!(!cache.is_range_cached(&bios(1..3)));
+        assert!(!cache.is_range_cached(&bios(2..4)));
+        assert!(cache.is_range_cached(&[]));
+        // Checking the range doesn't fetch data from the backend.
+        assert_eq!(backend.reads(), 1);
+    }
+
+    #[test]
+    fn test_list_blobs() {
+        let chunks = new_chunks(8, 0x10000);
+        let backend = Arc::new(MockBackend::new("list", new_blob_data(8, 0x10000)));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend);
+        assert!(mgr.list_blobs().is_empty());
+
+        let infos = ["blob-1", "blob-0"]
+            .iter()
+            .map(|id| new_blob_info(id, 8, 0x10000))
+            .collect::<Vec<_>>();
+        for info in infos.iter() {
+            mgr.get_blob_cache(info).unwrap();
+        }
+        let cancel = AtomicBool::new(false);
+        // Materialize two chunks of "blob-1" and all chunks of "blob-0".
+        let entry = mgr.get(&infos[0]).unwrap();
+        entry
+            .warm_chunks(&[chunks[0].clone(), chunks[5].clone()], &cancel)
+            .unwrap();
+        let entry = mgr.get(&infos[1]).unwrap();
+        entry.warm_chunks(&chunks, &cancel).unwrap();
+        read_chunks(entry.as_ref(), &infos[1], &chunks[..1]).unwrap();
+
+        let status = mgr.list_blobs();
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[0].blob_id, "blob-0");
+        assert_eq!(status[0].total_size, 0x80000);
+        assert_eq!(status[0].materialized_size, 0x80000);
+        assert!(status[0].last_access.is_some());
+        assert_eq!(status[1].blob_id, "blob-1");
+        assert_eq!(status[1].total_size, 0x80000);
+        assert_eq!(status[1].materialized_size, 0x20000);
+        assert!(status[1].last_access.is_none());
+    }
+
+    #[test]
+    fn test_warm_chunks() {
+        let info = new_blob_info("blob-0", 8, 0x10000);
+        let chunks = new_chunks(8, 0x10000);
+        let backend = Arc::new(MockBackend::new("warm", new_blob_data(8, 0x10000)));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            prefetch: nydus_api::PrefetchConfigV2 {
+                batch_size: 0x40000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+
+        let cancel = AtomicBool::new(true);
+        let err = entry.warm_chunks(&chunks, &cancel).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ECANCELED));
+        assert_eq!(backend.reads(), 0);
+
+        // Chunks are fetched by requests of the merging size.
+        cancel.store(false, Ordering::Relaxed);
+        assert_eq!(entry.warm_chunks(&chunks, &cancel).unwrap(), 0x80000);
+        assert_eq!(backend.reads(), 2);
+        for chunk in chunks.iter() {
+            assert!(entry.chunk_map.is_ready(chunk.as_ref()).unwrap());
+        }
+
+        // Ready chunks aren't fetched again.
+        assert_eq!(entry.warm_chunks(&chunks, &cancel).unwrap(), 0);
+        assert_eq!(backend.reads(), 2);
+        // Chunks can't be enumerated without blob meta.
+        assert!(cache.warm(&cancel).is_err());
+    }
+
+    #[test]
+    fn test_read_out_of_bounds_chunk() {
+        let blob = new_blob_data(2, 0x1000);
+        let info = new_blob_info("blob-0", 2, 0x1000);
+        // The second chunk claims to be beyond the end of the blob.
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = [0u64, 0x2000]
+            .iter()
+            .enumerate()
+            .map(|(i, offset)| {
+                Arc::new(MockChunkInfo {
+                    compress_size: 0x1000,
+                    uncompress_size: 0x1000,
+                    compress_offset: *offset,
+                    uncompress_offset: *offset,
+                    index: i as u32,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>
+            })
+            .collect();
+
+        for zero_fill in [false, true] {
+            let backend = Arc::new(MockBackend::new("bounds", blob.clone()));
+            let config = CacheConfigV2 {
+                zero_out_of_bounds_chunks: zero_fill,
+                ..Default::default()
+            };
+            let (_tmp_dir, mgr) = new_cache_mgr(config, backend);
+            let cache = mgr.get_blob_cache(&info).unwrap();
+
+            let mut iovec = BlobIoVec::new(info.clone());
+            for chunk in chunks.iter() {
+                let chunk = chunk.clone().into();
+                iovec.push(BlobIoDesc::new(info.clone(), chunk, 0, 0x1000, true));
+            }
+            let mut buf = vec![0xffu8; 0x2000];
+            let slices = [unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), 0x2000) }];
+            let res = cache.read(&mut iovec, &slices);
+            if zero_fill {
+                assert_eq!(res.unwrap(), 0x2000);
+                assert_eq!(&buf[..0x1000], &blob[..0x1000]);
+                assert!(buf[0x1000..].iter().all(|v| *v == 0));
+            } else {
+                let err = res.unwrap_err();
+                assert!(matches!(
+                    CacheError::from_io_error(&err),
+                    Some(CacheError::ChunkOutOfBounds {
+                        chunk_index: 1,
+                        compressed: false,
+                        offset: 0x2000,
+                        blob_size: 0x2000,
+                        ..
+                    })
+                ));
+                assert!(err.to_string().contains("blob-0"));
+                assert!(buf.iter().all(|v| *v == 0xff));
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_mmap_cache() {
+        let blob = new_blob_data(2, 0x1000);
+        let info = new_blob_info("blob-0", 2, 0x1000);
+        let chunks = new_chunks(2, 0x1000);
+        let backend = Arc::new(MockBackend::new("mmap", blob.clone()));
+        let config = CacheConfigV2 {
+            file_cache: Some(FileCacheConfig {
+                mmap_size_threshold: 0x2000,
+                mmap_read_threshold: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let map = entry.cache_map.as_ref().unwrap();
+        // Cache files in memory are mapped when opening them.
+        let in_memory = is_memory_backed_file(&entry.file);
+        assert_eq!(map.is_mapped(), in_memory);
+
+        let read = |idx: usize| read_chunks(cache.as_ref(), &info, &chunks[idx..=idx]).unwrap();
+
+        // Chunks fetched from the backend don't count as reads from the cache file.
+        for idx in 0..2 {
+            assert_eq!(read(idx), &blob[idx * 0x1000..(idx + 1) * 0x1000]);
+        }
+        assert_eq!(map.is_mapped(), in_memory);
+        let fetched = backend.reads();
+
+        // The cache file gets mapped once read often enough, and chunks written into the cache
+        // file before mapping it are visible through the mapping.
+        for idx in 0..2 {
+            assert_eq!(read(idx), &blob[idx * 0x1000..(idx + 1) * 0x1000]);
+        }
+        assert!(map.is_mapped());
+        for idx in 0..2 {
+            assert_eq!(read(idx), &blob[idx * 0x1000..(idx + 1) * 0x1000]);
+        }
+        assert_eq!(backend.reads(), fetched);
+
+        // Accessing the mapping beyond the end of a shrunk cache file would raise `SIGBUS`, so the
+        // mapping is dropped before reading through it, and reads fall back to the file.
+        entry.file.set_len(0x1000).unwrap();
+        assert!(map.is_mapped());
+        assert_eq!(read(0), &blob[..0x1000]);
+        assert!(!map.is_mapped());
+        entry.evict_chunk(1, 0x1000, 0x1000).unwrap();
+        assert_eq!(read(1), &blob[0x1000..]);
+        assert!(!map.is_mapped());
+
+        // The cache file is mapped again once it's restored.
+        entry.file.set_len(0x2000).unwrap();
+        for idx in 0..2 {
+            assert_eq!(read(idx), &blob[idx * 0x1000..(idx + 1) * 0x1000]);
+        }
+        assert!(map.is_mapped());
+        let fetched = backend.reads();
+
+        // Invalidated chunks are fetched again instead of reading holes through the mapping.
+        entry.invalidate(true).unwrap();
+        assert_eq!(read(1), &blob[0x1000..]);
+        assert!(backend.reads() > fetched);
+
+        // Cache files bigger than the threshold are never mapped.
+        let info = new_blob_info("blob-1", 3, 0x1000);
+        mgr.get_blob_cache(&info).unwrap();
+        assert!(mgr.get(&info).unwrap().cache_map.is_none());
+    }
+
+    #[test]
+    fn test_read_cache_contiguous() {
+        let blob = new_blob_data(4, 0x1000);
+        let info = new_blob_info("blob-0", 4, 0x1000);
+        let chunks = new_chunks(4, 0x1000);
+        let backend = Arc::new(MockBackend::new("contiguous", blob.clone()));
+        let (_tmp_dir, mgr) = new_cache_mgr(CacheConfigV2::default(), backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let cancel = AtomicBool::new(false);
+        entry.warm_chunks(&chunks, &cancel).unwrap();
+        let fetched = backend.reads();
+
+        // Read [0x1800, 0x3400) of the blob, spanning three chunks, into two adjacent buffers.
+        let mut iovec = BlobIoVec::new(info.clone());
+        for (idx, offset, size) in [(1, 0x800, 0x800), (2, 0, 0x1000), (3, 0, 0x400)] {
+            let chunk = chunks[idx].clone().into();
+            iovec.push(BlobIoDesc::new(info.clone(), chunk, offset, size, true));
+        }
+        let mut buf = vec![0u8; 0x2000];
+        let slices = unsafe {
+            [
+                FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), 0x1000),
+                FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr().add(0x1000), 0x1000),
+            ]
+        };
+        assert_eq!(
+            entry.read_cache_contiguous(&iovec, &slices).unwrap(),
+            Some(0x1c00)
+        );
+        assert_eq!(&buf[..0x1c00], &blob[0x1800..0x3400]);
+        buf.fill(0);
+        assert_eq!(cache.read(&mut iovec, &slices).unwrap(), 0x1c00);
+        assert_eq!(&buf[..0x1c00], &blob[0x1800..0x3400]);
+        assert_eq!(backend.reads(), fetched);
+
+        // Buffers not contiguous in memory are filled by one syscall too.
+        buf.fill(0);
+        let mut other = vec![0u8; 0x1000];
+        let slices = unsafe {
+            [
+                FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), 0x1000),
+                FileVolatileSlice::from_raw_ptr(other.as_mut_ptr(), 0x1000),
+            ]
+        };
+        assert_eq!(
+            entry.read_cache_contiguous(&iovec, &slices).unwrap(),
+            Some(0x1c00)
+        );
+        assert_eq!(&buf[..0x1000], &blob[0x1800..0x2800]);
+        assert_eq!(&other[..0xc00], &blob[0x2800..0x3400]);
+
+        // Buffers too small for the request fall back to the generic path.
+        let slices = unsafe { [FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), 0x1000)] };
+        assert_eq!(entry.read_cache_contiguous(&iovec, &slices).unwrap(), None);
+        assert_eq!(backend.reads(), fetched);
+    }
+
+    #[test]
+    fn test_invalidate_blob() {
+        let blob = new_blob_data(2, 0x1000);
+        let chunks = new_chunks(2, 0x1000);
+        let backend = Arc::new(MockBackend::new("invalidate", blob.clone()));
+        let (_tmp_dir, mgr) = new_cache_mgr(CacheConfigV2::default(), backend.clone());
+        let infos: Vec<Arc<BlobInfo>> = (0..2u32)
+            .map(|idx| new_blob_info(&format!("blob-{}", idx), 2, 0x1000))
+            .collect();
+        let cancel = AtomicBool::new(false);
+        for info in infos.iter() {
+            let (_, entry) = get_cache_entry(&mgr, info);
+            entry.warm_chunks(&chunks, &cancel).unwrap();
+        }
+        let read = |info: &Arc<BlobInfo>| {
+            let cache = mgr.get_blob_cache(info).unwrap();
+            assert_eq!(read_chunks(cache.as_ref(), info, &chunks).unwrap(), blob);
+        };
+
+        let fetched = backend.reads();
+        read(&infos[0]);
+        assert_eq!(backend.reads(), fetched);
+
+        mgr.invalidate_blob("blob-0", true).unwrap();
+        let entry = mgr.get(&infos[0]).unwrap();
+        assert!(!entry.chunk_map.is_ready(chunks[0].as_ref()).unwrap());
+        assert_eq!(entry.file.metadata().unwrap().blocks(), 0);
+        // The invalidated blob is fetched from the backend again, but not the other blob.
+        read(&infos[1]);
+        assert_eq!(backend.reads(), fetched);
+        read(&infos[0]);
+        assert!(backend.reads() > fetched);
+
+        assert!(mgr.invalidate_blob("blob-2", false).is_err());
+    }
+
+    #[test]
+    fn test_invalidate_stale_chunk_generation() {
+        let info = new_blob_info("blob-0", 2, 0x1000);
+        let new_chunk = |index: u32, generation: u32| -> Arc<dyn BlobChunkInfo> {
+            Arc::new(MockChunkInfo {
+                compress_size: 0x1000,
+                uncompress_size: 0x1000,
+                compress_offset: index as u64 * 0x1000,
+                uncompress_offset: index as u64 * 0x1000,
+                index,
+                generation,
+                ..Default::default()
+            })
+        };
+
+        let backend = Arc::new(MockBackend::new("generation", new_blob_data(2, 0x1000)));
+        let (_tmp_dir, mgr) = new_cache_mgr(CacheConfigV2::default(), backend.clone());
+        let cache = mgr.get_blob_cache(&info).unwrap();
+        let read = |chunk: Arc<dyn BlobChunkInfo>| {
+            let buf = read_chunks(cache.as_ref(), &info, &[chunk.clone()]).unwrap();
+            assert_eq!(buf.len(), 0x1000);
+            assert!(buf.iter().all(|v| *v == chunk.id() as u8 + 1));
+            wait_for_ready(cache.as_ref(), chunk.as_ref());
+            backend.reads()
+        };
+
+        // Chunks without generation are cached as usual.
+        assert_eq!(read(new_chunk(0, 0)), 1);
+        assert_eq!(read(new_chunk(0, 0)), 1);
+        let gen_file = format!("{}/blob-0.chunk_gen", mgr.work_dir);
+        assert!(!std::path::Path::new(&gen_file).exists());
+
+        // The chunk is fetched again once its generation advances.
+        assert_eq!(read(new_chunk(0, 1)), 2);
+        assert_eq!(read(new_chunk(0, 1)), 2);
+        assert_eq!(read(new_chunk(1, 0)), 3);
+        assert_eq!(read(new_chunk(0, 2)), 4);
+        assert_eq!(read(new_chunk(1, 0)), 4);
+    }
+
+    #[test]
+    fn test_chunk_map_survives_restart() {
+        let info = new_blob_info("blob-0", 2, 0x1000);
+        let chunks = new_chunks(2, 0x1000);
+        let tmp_dir = TempDir::new().unwrap();
+        let backend = Arc::new(MockBackend::new("restart", new_blob_data(2, 0x1000)));
+        let config = CacheConfigV2 {
+            file_cache: Some(FileCacheConfig {
+                chunk_map_sync_interval: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // Simulate restarting nydusd by creating a new cache manager on the same working directory.
+        let start = || {
+            let mgr = new_cache_mgr_in(tmp_dir.as_path(), config.clone(), backend.clone());
+            mgr.unwrap().get_blob_cache(&info).unwrap()
+        };
+        let read = |cache: &Arc<dyn BlobCache>, idx: usize| {
+            let buf = read_chunks(cache.as_ref(), &info, &chunks[idx..=idx]).unwrap();
+            assert_eq!(buf.len(), 0x1000);
+            assert!(buf.iter().all(|v| *v == idx as u8 + 1));
+            wait_for_ready(cache.as_ref(), chunks[idx].as_ref());
+            backend.reads()
+        };
+
+        let cache = start();
+        assert_eq!(read(&cache, 0), 1);
+
+        // Cached chunks are served from the cache file after restarting.
+        let cache = start();
+        assert!(cache.get_chunk_map().is_ready(chunks[0].as_ref()).unwrap());
+        assert!(!cache.get_chunk_map().is_ready(chunks[1].as_ref()).unwrap());
+        assert_eq!(read(&cache, 0), 1);
+        assert_eq!(read(&cache, 1), 2);
+
+        // The chunk map is discarded if the cache file has been truncated.
+        let data_file = tmp_dir
+            .as_path()
+            .join(format!("blob-0{}", BLOB_DATA_FILE_SUFFIX));
+        OpenOptions::new()
+            .write(true)
+            .open(data_file)
+            .unwrap()
+            .set_len(0x1000)
+            .unwrap();
+        let cache = start();
+        assert!(!cache.get_chunk_map().is_ready(chunks[0].as_ref()).unwrap());
+        assert_eq!(read(&cache, 0), 3);
+    }
+
+    #[test]
+    fn test_verify_on_startup() {
+        // Blob data of 2 chunks, followed by the chunk information table and its header.
+        let mut blob = new_blob_data(2, 0x1000);
+        let mut ci = BlobMetaChunkArray::new_v1();
+        for idx in 0..2u64 {
+            ci.add_v1(idx * 0x1000, 0x1000, idx * 0x1000, 0x1000);
+        }
+        let ci_size = ci.as_byte_slice().len() as u64;
+        let mut header = BlobCompressionContextHeader::default();
+        header.set_ci_compressor(compress::Algorithm::None);
+        header.set_ci_entries(2);
+        header.set_ci_compressed_offset(0x2000);
+        header.set_ci_compressed_size(ci_size);
+        header.set_ci_uncompressed_size(ci_size);
+        header.set_aligned(true);
+        blob.extend_from_slice(ci.as_byte_slice());
+        blob.extend_from_slice(header.as_bytes());
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            0x2000,
+            0x2000,
+            0x1000,
+            2,
+            BlobFeatures::ALIGNED,
+        );
+        info.set_blob_meta_info(0x2000, ci_size, ci_size, compress::Algorithm::None as u32);
+        let info = Arc::new(info);
+
+        let tmp_dir = TempDir::new().unwrap();
+        let backend = Arc::new(MockBackend::new("startup", blob));
+        // Chunks have no digest, so they are all reported as corrupted by the per-chunk verify
+        // in strict mode, which tells whether the slow path is taken.
+        let config = CacheConfigV2 {
+            cache_validate_strict: true,
+            file_cache: Some(FileCacheConfig {
+                chunk_map_sync_interval: 1,
+                verify_on_startup: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        // Simulate restarting nydusd by creating a new cache manager on the same working directory.
+        let start = || {
+            let mgr = new_cache_mgr_in(tmp_dir.as_path(), config.clone(), backend.clone());
+            let mgr = mgr.unwrap();
+            mgr.get_blob_cache(&info).unwrap();
+            mgr
+        };
+        let ready_chunks = |mgr: &FileCacheMgr| {
+            let entry = mgr.get(&info).unwrap();
+            (0..2)
+                .filter(|idx| {
+                    let chunk = entry.get_chunk_info(*idx).unwrap();
+                    entry.chunk_map.is_ready(chunk.as_ref()).unwrap()
+                })
+                .count()
+        };
+        let checksum_file = tmp_dir
+            .as_path()
+            .join(format!("blob-0.{}", CACHE_CHECKSUM_FILE_SUFFIX));
+
+        let mgr = start();
+        let entry = mgr.get(&info).unwrap();
+        let chunks: Vec<_> = (0..2)
+            .map(|idx| entry.get_chunk_info(idx).unwrap())
+            .collect();
+        let cancel = AtomicBool::new(false);
+        assert_eq!(entry.warm_chunks(&chunks, &cancel).unwrap(), 0x2000);
+        assert_eq!(ready_chunks(&mgr), 2);
+        drop(entry);
+        drop(mgr);
+        assert!(checksum_file.exists());
+
+        // The checksum matches the cache file, so cached chunks aren't verified.
+        let mgr = start();
+        assert!(!checksum_file.exists());
+        assert_eq!(ready_chunks(&mgr), 2);
+        drop(mgr);
+
+        // The checksum mismatches the corrupted cache file, so cached chunks are verified.
+        let cache_file = tmp_dir
+            .as_path()
+            .join(format!("blob-0{}", BLOB_DATA_FILE_SUFFIX));
+        let file = OpenOptions::new().write(true).open(cache_file).unwrap();
+        file.write_all_at(&[0xffu8; 0x100], 0x800).unwrap();
+        let mgr = start();
+        assert_eq!(ready_chunks(&mgr), 0);
+    }
+
+    #[test]
+    fn test_clear_truncated_chunks() {
+        let blob = new_blob_data(2, 0x1000);
+        let info = new_blob_info("blob-0", 2, 0x1000);
+        let chunks = new_chunks(2, 0x1000);
+        let backend = Arc::new(MockBackend::new("truncate", blob.clone()));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let read = |idx: usize| {
+            let buf = read_chunks(cache.as_ref(), &info, &chunks[idx..=idx]).unwrap();
+            assert_eq!(buf, blob[idx * 0x1000..(idx + 1) * 0x1000]);
+            backend.reads()
+        };
+        assert_eq!(read(0), 1);
+        assert_eq!(read(1), 2);
+
+        // Only chunks beyond the end of the truncated cache file are cleared.
+        let cache_file = format!("{}/blob-0{}", mgr.work_dir, BLOB_RAW_FILE_SUFFIX);
+        let file = OpenOptions::new().write(true).open(cache_file).unwrap();
+        file.set_len(0x1000).unwrap();
+        assert_eq!(entry.clear_truncated_chunks(&chunks, 0x1000).unwrap(), 1);
+        assert!(entry.chunk_map.is_ready(chunks[0].as_ref()).unwrap());
+        assert!(!entry.chunk_map.is_ready(chunks[1].as_ref()).unwrap());
+
+        assert_eq!(read(0), 2);
+        assert_eq!(read(1), 3);
+        assert_eq!(entry.clear_truncated_chunks(&chunks, 0x2000).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_blob_priority_dispatch() {
+        let backend = Arc::new(MockBackend::new("priority", vec![0x5a; 0x1000]));
+        let mut blob_priorities = HashMap::new();
+        blob_priorities.insert("blob-high".to_string(), 10);
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            prefetch: nydus_api::PrefetchConfigV2 {
+                enable: true,
+                threads_count: 1,
+                batch_size: 0x1000,
+                ..Default::default()
+            },
+            file_cache: Some(FileCacheConfig {
+                blob_priorities,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let chunks = new_chunks(1, 0x1000);
+
+        // Queue prefetch requests before starting workers to simulate backend contention.
+        let mut caches = Vec::new();
+        for id in ["blob-low1", "blob-low2", "blob-high"] {
+            let info = new_blob_info(id, 1, 0x1000);
+            let cache = mgr.get_blob_cache(&info).unwrap();
+            let bio = BlobIoDesc::new(info, chunks[0].clone().into(), 0, 0x1000, false);
+            cache.start_prefetch().unwrap();
+            cache.prefetch(cache.clone(), &[], &[bio]).unwrap();
+            caches.push(cache);
+        }
+        assert_eq!(caches[2].priority(), 10);
+        mgr.init().unwrap();
+
+        wait_until(|| backend.requests().len() >= 3);
+        let reads = backend.requests();
+        let blobs = reads
+            .iter()
+            .map(|(id, _, _)| id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(blobs, vec!["blob-high", "blob-low1", "blob-low2"]);
+        for cache in caches {
+            cache.stop_prefetch().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_prefetch_deny_list() {
+        let backend = Arc::new(MockBackend::new("deny", vec![0x5a; 0x1000]));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            prefetch: nydus_api::PrefetchConfigV2 {
+                enable: true,
+                threads_count: 1,
+                batch_size: 0x1000,
+                ..Default::default()
+            },
+            prefetch_deny_list: vec![nydus_api::PrefetchDenyEntry {
+                blob_id: "blob-denied".to_string(),
+                chunk_range: None,
+            }],
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        mgr.init().unwrap();
+        let chunks = new_chunks(1, 0x1000);
+
+        let mut caches = Vec::new();
+        for (id, size) in [("blob-denied", 0), ("blob-allowed", 0x1000)] {
+            let info = new_blob_info(id, 1, 0x1000);
+            let cache = mgr.get_blob_cache(&info).unwrap();
+            let bio = BlobIoDesc::new(info, chunks[0].clone().into(), 0, 0x1000, false);
+            let req = BlobPrefetchRequest {
+                blob_id: id.to_string(),
+                offset: 0,
+                len: 0x1000,
+                merging_size: None,
+                bandwidth_rate: None,
+            };
+            cache.start_prefetch().unwrap();
+            assert_eq!(
+                cache.prefetch(cache.clone(), &[req], &[bio]).unwrap(),
+                size * 2
+            );
+            caches.push(cache);
+        }
+
+        wait_until(|| {
+            caches
+                .iter()
+                .all(|c| c.prefetch_progress().unwrap().is_done())
+        });
+        let reads = backend.requests();
+        assert!(!reads.is_empty());
+        assert!(reads.iter().all(|(id, _, _)| id == "blob-allowed"));
+        for cache in caches {
+            cache.stop_prefetch().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_blob_prefetch_override() {
+        let backend = Arc::new(MockBackend::new("override", vec![0x5a; 0x80000]));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            prefetch: nydus_api::PrefetchConfigV2 {
+                enable: true,
+                threads_count: 1,
+                batch_size: 0x40000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let chunks = new_chunks(8, 0x10000);
+
+        // Prefetch a metadata blob by big requests and a data blob by small requests.
+        let mut caches = Vec::new();
+        for (id, merging_size) in [("blob-meta", 0x80000), ("blob-data", 0x20000)] {
+            let info = new_blob_info(id, 8, 0x10000);
+            let bios = chunks
+                .iter()
+                .map(|chunk| BlobIoDesc::new(info.clone(), chunk.clone().into(), 0, 0x10000, false))
+                .collect::<Vec<_>>();
+            let req = BlobPrefetchRequest {
+                blob_id: id.to_string(),
+                offset: 0,
+                len: 0,
+                merging_size: Some(merging_size),
+                bandwidth_rate: Some(0),
+            };
+            let cache = mgr.get_blob_cache(&info).unwrap();
+            cache.start_prefetch().unwrap();
+            cache.prefetch(cache.clone(), &[req], &bios).unwrap();
+            caches.push(cache);
+        }
+        mgr.init().unwrap();
+
+        wait_until(|| backend.requests().len() >= 5);
+        let reads = backend.requests();
+        let sizes = |blob_id: &str| {
+            reads
+                .iter()
+                .filter(|(id, _, _)| id == blob_id)
+                .map(|(_, _, size)| *size)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes("blob-meta"), vec![0x80000]);
+        assert_eq!(sizes("blob-data"), vec![0x20000; 4]);
+        for cache in caches {
+            cache.stop_prefetch().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_prefetch_concurrency_limit() {
+        let delays = (0..4u64).map(|idx| (idx * 0x20000, 20)).collect();
+        let backend =
+            Arc::new(MockBackend::new("concurrency", vec![0x5a; 0x80000]).with_delays(delays));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            prefetch: nydus_api::PrefetchConfigV2 {
+                enable: true,
+                threads_count: 2,
+                batch_size: 0x10000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let mut caches = Vec::new();
+        // Prefetch requests of several blobs are queued, instead of being issued at once.
+        for blob_index in 0..4u32 {
+            let info = Arc::new(BlobInfo::new(
+                blob_index,
+                format!("blob-{}", blob_index),
+                0x40000,
+                0x40000,
+                0x10000,
+                4,
+                BlobFeatures::empty(),
+            ));
+            let mut iovec = BlobIoVec::new(info.clone());
+            for idx in 0..4u32 {
+                let chunk = Arc::new(MockChunkInfo {
+                    compress_size: 0x10000,
+                    uncompress_size: 0x10000,
+                    compress_offset: idx as u64 * 0x20000,
+                    uncompress_offset: idx as u64 * 0x20000,
+                    index: idx,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>;
+                iovec.push(BlobIoDesc::new(
+                    info.clone(),
+                    chunk.into(),
+                    0,
+                    0x10000,
+                    false,
+                ));
+            }
+            let cache = mgr.get_blob_cache(&info).unwrap();
+            cache.start_prefetch().unwrap();
+            cache.prefetch(cache.clone(), &[], &iovec.bi_vec).unwrap();
+            caches.push(cache);
+        }
+        mgr.init().unwrap();
+
+        wait_until(|| {
+            caches.iter().all(|c| {
+                let (completed, total) = c.prefetch_progress().unwrap().get();
+                completed == total
+            })
+        });
+        assert_eq!(backend.reads(), 16);
+        assert!(backend.peak() <= 2);
+    }
+
+    #[test]
+    fn test_readahead_sequential_reads() {
+        // Blob data of 16 chunks, followed by the chunk information table and its header. Chunks
+        // are big enough to avoid extending user IO and prefetch requests.
+        const CHUNK_SIZE: usize = 0x40000;
+        let mut blob = new_blob_data(16, CHUNK_SIZE);
+        let mut ci = BlobMetaChunkArray::new_v1();
+        for idx in 0..16u64 {
+            let offset = idx * CHUNK_SIZE as u64;
+            ci.add_v1(offset, CHUNK_SIZE as u32, offset, CHUNK_SIZE as u32);
+        }
+        let ci_size = ci.as_byte_slice().len() as u64;
+        let mut header = BlobCompressionContextHeader::default();
+        header.set_ci_compressor(compress::Algorithm::None);
+        header.set_ci_entries(16);
+        header.set_ci_compressed_offset(0x400000);
+        header.set_ci_compressed_size(ci_size);
+        header.set_ci_uncompressed_size(ci_size);
+        header.set_aligned(true);
+        blob.extend_from_slice(ci.as_byte_slice());
+        blob.extend_from_slice(header.as_bytes());
+        let mut info = BlobInfo::new(
+            0,
+            "readahead".to_string(),
+            0x400000,
+            0x400000,
+            CHUNK_SIZE as u32,
+            16,
+            BlobFeatures::ALIGNED,
+        );
+        info.set_blob_meta_info(0x400000, ci_size, ci_size, compress::Algorithm::None as u32);
+        let info = Arc::new(info);
+
+        let backend = Arc::new(MockBackend::new("readahead", blob.clone()));
+        let config = CacheConfigV2 {
+            prefetch: nydus_api::PrefetchConfigV2 {
+                enable: true,
+                threads_count: 1,
+                ..Default::default()
+            },
+            file_cache: Some(FileCacheConfig {
+                readahead_chunks: 4,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend);
+        mgr.init().unwrap();
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        cache.start_prefetch().unwrap();
+        let chunks: Vec<Arc<dyn BlobChunkInfo>> = (0..16u32)
+            .map(|idx| cache.get_chunk_info(idx).unwrap())
+            .collect();
+        let read = |idx: usize| {
+            let buf = read_chunks(cache.as_ref(), &info, &chunks[idx..=idx]).unwrap();
+            assert_eq!(buf, blob[idx * CHUNK_SIZE..(idx + 1) * CHUNK_SIZE]);
+        };
+        let is_ready = |idx: usize| entry.chunk_map.is_ready(chunks[idx].as_ref()).unwrap();
+
+        // The first read isn't known to be sequential yet.
+        read(0);
+        assert_eq!(cache.prefetch_progress().unwrap().get().1, 0);
+        // The second read continues the first one, so chunks 2 to 5 are read ahead.
+        read(1);
+        wait_until(|| (2..6).all(is_ready));
+        assert!((2..6).all(is_ready));
+        assert!(!is_ready(6));
+        assert_eq!(
+            cache.prefetch_progress().unwrap().get().1,
+            4 * CHUNK_SIZE as u64
+        );
+
+        // Random reads don't trigger readahead.
+        read(9);
+        assert_eq!(
+            cache.prefetch_progress().unwrap().get().1,
+            4 * CHUNK_SIZE as u64
+        );
+        assert!(!is_ready(10));
+    }
+
+    #[test]
+    fn test_latency_stats() {
+        // Reading the last chunk is much slower than reading others.
+        let mut delays: HashMap<u64, u64> = (0..9u64).map(|i| (i * 0x1000, 2)).collect();
+        delays.insert(0x9000, 100);
+        let backend = Arc::new(MockBackend::new("latency", vec![0x5a; 0xa000]).with_delays(delays));
+        let (_tmp_dir, mgr) = new_cache_mgr(CacheConfigV2::default(), backend);
+        let info = new_blob_info("blob-0", 10, 0x1000);
+        let cache = mgr.get_blob_cache(&info).unwrap();
+        assert_eq!(mgr.latency_stats().count, 0);
+
+        for chunk in new_chunks(10, 0x1000).iter() {
+            let buf = read_chunks(cache.as_ref(), &info, &[chunk.clone()]).unwrap();
+            assert_eq!(buf.len(), 0x1000);
+        }
+
+        let stats = mgr.latency_stats();
+        assert_eq!(stats.count, 10);
+        assert!(stats.p50 >= 2_000 && stats.p50 < 100_000);
+        assert!(stats.p90 >= 2_000 && stats.p90 < 100_000);
+        assert!(stats.p99 >= 100_000);
+        assert!(stats.max >= 100_000 && stats.max >= stats.p99);
+    }
+
+    #[test]
+    fn test_prefetch_progress() {
+        let backend = Arc::new(MockBackend::new("progress", vec![0x5a; 0x80000]));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            prefetch: nydus_api::PrefetchConfigV2 {
+                enable: true,
+                threads_count: 1,
+                batch_size: 0x20000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let info = new_blob_info("blob-0", 8, 0x10000);
+        let bios = new_chunks(8, 0x10000)
+            .into_iter()
+            .map(|chunk| BlobIoDesc::new(info.clone(), chunk.into(), 0, 0x10000, false))
+            .collect::<Vec<_>>();
+
+        let cache = mgr.get_blob_cache(&info).unwrap();
+        let progress = || cache.prefetch_progress().unwrap().get();
+        let notified = Arc::new(AtomicUsize::new(0));
+        let counter = notified.clone();
+        cache
+            .prefetch_progress()
+            .unwrap()
+            .notify_done(Box::new(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }));
+        assert_eq!(progress(), (0, 0));
+
+        cache.start_prefetch().unwrap();
+        cache.prefetch(cache.clone(), &[], &bios).unwrap();
+        assert_eq!(progress(), (0, 0x80000));
+        mgr.init().unwrap();
+
+        let last = std::cell::Cell::new(0);
+        wait_until(|| {
+            let (completed, total) = progress();
+            assert_eq!(total, 0x80000);
+            assert!(completed >= last.get() && completed <= total);
+            last.set(completed);
+            completed == total
+        });
+        assert_eq!(last.get(), 0x80000);
+        assert_eq!(backend.requests().len(), 4);
+        assert_eq!(notified.load(Ordering::Relaxed), 1);
+
+        // Callbacks registered after completion are invoked immediately, and only once.
+        let counter = notified.clone();
+        cache
+            .prefetch_progress()
+            .unwrap()
+            .notify_done(Box::new(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }));
+        assert_eq!(notified.load(Ordering::Relaxed), 2);
+        cache.stop_prefetch().unwrap();
+        assert_eq!(notified.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_prefetch_access_trace() {
+        let backend = Arc::new(MockBackend::new("trace", vec![0x5a; 0x80000]));
+        let config = CacheConfigV2 {
+            cache_compressed: true,
+            prefetch: nydus_api::PrefetchConfigV2 {
+                enable: true,
+                threads_count: 1,
+                batch_size: 0x30000,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+        let info = new_blob_info("blob-0", 8, 0x10000);
+        let chunks = new_chunks(8, 0x10000);
+
+        let mut trace = AccessTrace::new();
+        for idx in [4, 5, 6, 0, 1, 4, 7, 2, 9] {
+            trace.push("blob-0", idx);
+        }
+        trace.push("blob-1", 3);
+        let trace_file = TempFile::new().unwrap();
+        trace.save(trace_file.as_path()).unwrap();
+        let trace = AccessTrace::from_file(trace_file.as_path()).unwrap();
+        let io_vecs = trace.to_io_vecs(&[info.clone()], |_, idx| chunks.get(idx as usize).cloned());
+        let indices = io_vecs
+            .iter()
+            .map(|v| {
+                v.bi_vec
+                    .iter()
+                    .map(|d| d.chunkinfo.id())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![vec![4, 5, 6], vec![0, 1, 7], vec![2]]);
+
+        let cache = mgr.get_blob_cache(&info).unwrap();
+        cache.start_prefetch().unwrap();
+        for io_vec in io_vecs.iter() {
+            cache.prefetch(cache.clone(), &[], &io_vec.bi_vec).unwrap();
+        }
+        mgr.init().unwrap();
+
+        wait_until(|| backend.requests().len() >= 4);
+        // Chunks adjacent in both the trace and the blob are merged, in the order of the trace.
+        let reads = backend
+            .requests()
+            .iter()
+            .map(|(_, offset, size)| (*offset, *size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reads,
+            vec![
+                (0x40000, 0x30000),
+                (0, 0x20000),
+                (0x70000, 0x10000),
+                (0x20000, 0x10000)
+            ]
+        );
+        cache.stop_prefetch().unwrap();
+    }
+
+    #[test]
+    fn test_prefetch_strategy() {
+        let info = new_blob_info("blob-0", 6, 0x20000);
+        let chunks = new_chunks(6, 0x20000);
+        // Chunks in the requested order, with kinds of file data they belong to.
+        let bios = [
+            (4, BlobIoKind::Data),
+            (1, BlobIoKind::Executable),
+            (0, BlobIoKind::Data),
+            (5, BlobIoKind::Metadata),
+            (2, BlobIoKind::Metadata),
+            (3, BlobIoKind::Executable),
+        ]
+        .iter()
+        .map(|(idx, kind)| {
+            let mut bio = BlobIoDesc::new(
+                info.clone(),
+                BlobIoChunk::from(chunks[*idx].clone()),
+                0,
+                0x20000,
+                false,
+            );
+            bio.kind = *kind;
+            bio
+        })
+        .collect::<Vec<_>>();
+
+        // Returns indices of chunks in the order they are prefetched from the backend.
+        let prefetch = |strategy: PrefetchStrategy| {
+            let backend = Arc::new(MockBackend::new("strategy", vec![0x5a; 0xc0000]));
+            let config = CacheConfigV2 {
+                cache_compressed: true,
+                prefetch: nydus_api::PrefetchConfigV2 {
+                    enable: true,
+                    threads_count: 1,
+                    batch_size: 0x20000,
+                    strategy,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+            let cache = mgr.get_blob_cache(&info).unwrap();
+            cache.start_prefetch().unwrap();
+            cache.prefetch(cache.clone(), &[], &bios).unwrap();
+            mgr.init().unwrap();
+
+            wait_until(|| backend.requests().len() >= 6);
+            cache.stop_prefetch().unwrap();
+            let reads = backend.requests();
+            reads
+                .iter()
+                .map(|(_, offset, _)| offset / 0x20000)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            prefetch(PrefetchStrategy::BlobLayout),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            prefetch(PrefetchStrategy::AccessTrace),
+            vec![4, 1, 0, 5, 2, 3]
+        );
+        // Metadata chunks are prefetched first, then executables, then data.
+        assert_eq!(
+            prefetch(PrefetchStrategy::BootOptimized),
+            vec![2, 5, 1, 3, 0, 4]
+        );
+    }
+
+    #[test]
+    fn test_access_heatmap() {
+        let info = new_blob_info("blob-0", 4, 0x1000);
+        let chunks = new_chunks(4, 0x1000);
+        let backend = Arc::new(MockBackend::new("heatmap", new_blob_data(4, 0x1000)));
+        let config = CacheConfigV2 {
+            file_cache: Some(FileCacheConfig {
+                enable_access_heatmap: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let (_tmp_dir, mgr) = new_cache_mgr(config, backend);
+        let cache = mgr.get_blob_cache(&info).unwrap();
+        let read = |idx: usize| {
+            let buf = read_chunks(cache.as_ref(), &info, &chunks[idx..=idx]).unwrap();
+            assert_eq!(buf.len(), 0x1000);
+        };
+
+        assert_eq!(cache.access_heatmap(), vec![0, 0, 0, 0]);
+        read(1);
+        read(3);
+        read(1);
+        assert_eq!(cache.access_heatmap(), vec![0, 2, 0, 1]);
+        cache.reset_access_heatmap();
+        assert_eq!(cache.access_heatmap(), vec![0, 0, 0, 0]);
+        read(0);
+        assert_eq!(cache.access_heatmap(), vec![1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_cache_metrics() {
+        let chunks = new_chunks(4, 0x1000);
+        let backend = Arc::new(MockBackend::new("cache-metrics", new_blob_data(4, 0x1000)));
+        let (_tmp_dir, mgr) = new_cache_mgr(CacheConfigV2::default(), backend);
+        let read = |info: &Arc<BlobInfo>, idx: usize| {
+            let cache = mgr.get_blob_cache(info).unwrap();
+            let buf = read_chunks(cache.as_ref(), info, &chunks[idx..=idx]).unwrap();
+            assert_eq!(buf.len(), 0x1000);
+            assert!(buf.iter().all(|v| *v == idx as u8 + 1));
+            wait_for_ready(cache.as_ref(), chunks[idx].as_ref());
+            cache.metrics()
+        };
+
+        let info0 = new_blob_info("blob-0", 4, 0x1000);
+        let info1 = new_blob_info("blob-1", 4, 0x1000);
+        let metrics = read(&info0, 1);
+        assert_eq!(metrics.chunk_hits(), 0);
+        assert_eq!(metrics.chunk_misses(), 1);
+        assert_eq!(metrics.backend_bytes(), 0x1000);
+        assert_eq!(metrics.cached_bytes(), 0);
+        read(&info0, 1);
+        read(&info0, 2);
+        let metrics = read(&info0, 1);
+        assert_eq!(metrics.chunk_hits(), 2);
+        assert_eq!(metrics.chunk_misses(), 2);
+        assert_eq!(metrics.backend_bytes(), 0x2000);
+        assert_eq!(metrics.cached_bytes(), 0x2000);
+
+        let metrics = read(&info1, 3);
+        assert_eq!(metrics.chunk_hits(), 0);
+        assert_eq!(metrics.chunk_misses(), 1);
+
+        // Counters are aggregated from all blob cache objects.
+        let metrics = mgr.metrics();
+        assert_eq!(metrics.chunk_hits(), 2);
+        assert_eq!(metrics.chunk_misses(), 3);
+        assert_eq!(metrics.backend_bytes(), 0x3000);
+        assert_eq!(metrics.cached_bytes(), 0x2000);
+    }
+
+    // A blob of `count` lz4 compressed chunks of 0x1000 bytes, with data of each chunk generated
+    // by `data(index)`.
+    fn new_compressed_blob(
+        count: u32,
+        data: impl Fn(u32) -> Vec<u8>,
+    ) -> (Arc<BlobInfo>, Vec<u8>, Vec<Arc<dyn BlobChunkInfo>>) {
+        let mut blob = Vec::new();
+        let mut chunks: Vec<Arc<dyn BlobChunkInfo>> = Vec::new();
+        for i in 0..count {
+            let data = data(i);
+            let (compressed, is_compressed) =
+                compress::compress(&data, compress::Algorithm::Lz4Block).unwrap();
+            assert!(is_compressed);
+            chunks.push(Arc::new(MockChunkInfo {
+                flags: BlobChunkFlags::COMPRESSED,
+                compress_size: compressed.len() as u32,
+                uncompress_size: 0x1000,
+                compress_offset: blob.len() as u64,
+                uncompress_offset: i as u64 * 0x1000,
+                index: i,
+                ..Default::default()
+            }));
+            blob.extend_from_slice(&compressed);
+        }
+        let mut info = BlobInfo::new(
+            0,
+            "blob-0".to_string(),
+            count as u64 * 0x1000,
+            blob.len() as u64,
+            0x1000,
+            count,
+            BlobFeatures::empty(),
+        );
+        info.set_compressor(compress::Algorithm::Lz4Block);
+        (Arc::new(info), blob, chunks)
+    }
+
+    #[test]
+    fn test_parallel_decompress() {
+        let data = |i: u32| -> Vec<u8> {
+            (0..0x1000u32)
+                .map(|v| ((v / (i + 1)) % 251) as u8)
+                .collect()
+        };
+        let (info, blob, chunks) = new_compressed_blob(64, data);
+        let expected: Vec<Vec<u8>> = (0..64).map(data).collect();
+
+        let decompress = |threshold: u32, threads: u32| {
+            let backend = Arc::new(MockBackend::new("parallel", blob.clone()));
+            let config = CacheConfigV2 {
+                file_cache: Some(FileCacheConfig {
+                    parallel_decompress_threshold: threshold,
+                    parallel_decompress_threads: threads,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+            let (_tmp_dir, mgr) = new_cache_mgr(config, backend);
+            let (_, entry) = get_cache_entry(&mgr, &info);
+            assert_eq!(entry.parallel_decompress(), (threshold, threads));
+            entry
+                .read_chunks_from_backend(0, blob.len(), &chunks, false)
+                .unwrap()
+                .collect::<Result<Vec<_>>>()
+                .unwrap()
+        };
+
+        let serial = decompress(0, 0);
+        assert_eq!(serial, expected);
+        assert_eq!(decompress(16, 4), serial);
+        assert_eq!(decompress(16, 5), serial);
+        // Fall back to the serial path when there are fewer chunks than the threshold.
+        assert_eq!(decompress(128, 4), serial);
+    }
+
+    #[test]
+    fn test_read_accept_compressed() {
+        let (info, blob, chunks) = new_compressed_blob(2, |i| vec![i as u8 + 1; 0x1000]);
+        let backend = Arc::new(MockBackend::new("compressed", blob.clone()));
+        let (_tmp_dir, mgr) = new_cache_mgr(CacheConfigV2::default(), backend);
+        let (cache, entry) = get_cache_entry(&mgr, &info);
+        let read = |offset: u32, size: u32| {
+            let mut iovec = BlobIoVec::new(info.clone());
+            for chunk in chunks.iter() {
+                iovec.push(BlobIoDesc::new(
+                    info.clone(),
+                    chunk.clone().into(),
+                    offset,
+                    size,
+                    true,
+                ));
+            }
+            iovec.set_accept_compressed(true);
+            let mut buf = vec![0u8; 0x2000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            let size = cache.read(&mut iovec, &[slice]).unwrap();
+            buf.truncate(size);
+            (buf, iovec.data_compressor())
+        };
+
+        // Whole chunks are returned as is, without decompression.
+        let (data, compressor) = read(0, 0x1000);
+        assert_eq!(compressor, Some(compress::Algorithm::Lz4Block));
+        assert_eq!(data, blob);
+        let chunk_map = entry.get_chunk_map();
+        assert!(!chunk_map.is_ready(chunks[0].as_ref()).unwrap());
+        assert!(!chunk_map.is_ready(chunks[1].as_ref()).unwrap());
+
+        // Partial chunks fall back to the normal read path.
+        let (data, compressor) = read(0x800, 0x800);
+        assert_eq!(compressor, None);
+        assert_eq!(data.len(), 0x1000);
+        assert!(data[..0x800].iter().all(|v| *v == 1));
+        assert!(data[0x800..].iter().all(|v| *v == 2));
+    }
+
+    #[test]
+    fn test_compressed_cache_saves_space() {
+        let (info, blob, chunks) = new_compressed_blob(4, |i| vec![i as u8 + 1; 0x1000]);
+
+        // Read all chunks twice and return size of the cache file.
+        let read_all = |cache_compressed: bool| {
+            let backend = Arc::new(MockBackend::new("compressed", blob.clone()));
+            let config = CacheConfigV2 {
+                cache_compressed,
+                ..Default::default()
+            };
+            let (_tmp_dir, mgr) = new_cache_mgr(config, backend.clone());
+            let cache = mgr.get_blob_cache(&info).unwrap();
+            for _ in 0..2 {
+                for (idx, chunk) in chunks.iter().enumerate() {
+                    let buf = read_chunks(cache.as_ref(), &info, &[chunk.clone()]).unwrap();
+                    assert_eq!(buf.len(), 0x1000);
+                    assert!(buf.iter().all(|v| *v == idx as u8 + 1));
+                }
+            }
+            let suffix = if cache_compressed {
+                // Compressed data is persisted synchronously, and decompressed on each read.
+                assert_eq!(backend.reads(), 4);
+                BLOB_RAW_FILE_SUFFIX
+            } else {
+                BLOB_DATA_FILE_SUFFIX
+            };
+            std::fs::metadata(format!("{}/blob-0{}", mgr.work_dir, suffix))
+                .unwrap()
+                .len()
+        };
+
+        assert_eq!(read_all(false), 0x4000);
+        assert_eq!(read_all(true), blob.len() as u64);
+        assert!((blob.len() as u64) < 0x4000);
+    }
+
     /*
        #[test]
        fn test_add() {
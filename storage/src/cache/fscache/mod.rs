@@ -6,20 +6,24 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{Error, Result};
 use std::os::unix::io::AsRawFd;
-use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, Ordering};
 use std::sync::{Arc, RwLock};
 
-use nydus_api::CacheConfigV2;
+use nydus_api::{CacheConfigV2, DigestMismatchPolicy, PrefetchDenyEntry};
 use nydus_utils::metrics::BlobcacheMetrics;
 use tokio::runtime::Runtime;
 
 use crate::backend::BlobBackend;
-use crate::cache::cachedfile::{FileCacheEntry, FileCacheMeta};
+use crate::cache::cachedfile::{BlobTocState, FileCacheEntry, FileCacheMeta};
 use crate::cache::state::{BlobStateMap, IndexedChunkMap, RangeMap};
 use crate::cache::worker::{AsyncPrefetchConfig, AsyncWorkerMgr};
-use crate::cache::{BlobCache, BlobCacheMgr};
+use crate::cache::{
+    max_decompress_size, prefetch_deny_ranges, BlobCache, BlobCacheMgr, BlobCacheStatus,
+    CacheMetrics, PrefetchProgress,
+};
 use crate::device::{BlobFeatures, BlobInfo, BlobObject};
 use crate::factory::BLOB_FACTORY;
+use crate::meta::BLOB_TOC_FILE_SUFFIX;
 
 use crate::cache::filecache::BLOB_DATA_FILE_SUFFIX;
 
@@ -37,9 +41,16 @@ pub struct FsCacheMgr {
     worker_mgr: Arc<AsyncWorkerMgr>,
     work_dir: String,
     need_validation: bool,
+    validate_strict: bool,
+    digest_mismatch_policy: DigestMismatchPolicy,
     blobs_check_count: Arc<AtomicU8>,
     closed: Arc<AtomicBool>,
     user_io_batch_size: u32,
+    max_user_merge_size: u64,
+    max_decompress_size: u64,
+    prefetch_deny_list: Vec<PrefetchDenyEntry>,
+    zero_out_of_bounds_chunks: bool,
+    cache_metrics: Arc<CacheMetrics>,
 }
 
 impl FsCacheMgr {
@@ -72,9 +83,16 @@ impl FsCacheMgr {
             worker_mgr: Arc::new(worker_mgr),
             work_dir: work_dir.to_owned(),
             need_validation: config.cache_validate,
+            validate_strict: config.cache_validate_strict,
+            digest_mismatch_policy: config.digest_mismatch_policy,
             blobs_check_count: Arc::new(AtomicU8::new(0)),
             closed: Arc::new(AtomicBool::new(false)),
             user_io_batch_size,
+            max_user_merge_size: config.max_user_merge_size,
+            max_decompress_size: config.max_decompress_size,
+            prefetch_deny_list: config.prefetch_deny_list.clone(),
+            zero_out_of_bounds_chunks: config.zero_out_of_bounds_chunks,
+            cache_metrics: Arc::new(CacheMetrics::default()),
         })
     }
 
@@ -186,6 +204,22 @@ impl BlobCacheMgr for FsCacheMgr {
             self.blobs_check_count.store(0, Ordering::Release);
         }
     }
+
+    fn metrics(&self) -> CacheMetrics {
+        self.cache_metrics.as_ref().clone()
+    }
+
+    fn list_blobs(&self) -> Vec<BlobCacheStatus> {
+        let mut blobs: Vec<BlobCacheStatus> = self
+            .blobs
+            .read()
+            .unwrap()
+            .values()
+            .map(|entry| entry.status())
+            .collect();
+        blobs.sort_by(|a, b| a.blob_id.cmp(&b.blob_id));
+        blobs
+    }
 }
 
 impl Drop for FsCacheMgr {
@@ -244,6 +278,12 @@ impl FileCacheEntry {
             && !blob_info.is_legacy_stargz()
             && blob_info.has_feature(BlobFeatures::INLINED_CHUNK_DIGEST);
         let blob_file_path = format!("{}/{}", mgr.work_dir, blob_meta_id);
+        let blob_toc = if blob_info.blob_toc_size() != 0 {
+            let path = format!("{}.{}", blob_file_path, BLOB_TOC_FILE_SUFFIX);
+            Some(BlobTocState::new(path, blob_meta_reader.clone()))
+        } else {
+            None
+        };
         let meta = if blob_info.meta_ci_is_valid() {
             FileCacheMeta::new(
                 blob_file_path.clone(),
@@ -272,6 +312,9 @@ impl FileCacheEntry {
             cache_cipher_object: Default::default(),
             cache_cipher_context: Default::default(),
             chunk_map,
+            chunk_map_batch: None,
+            blob_toc,
+            cache_map: None,
             file,
             meta: Some(meta),
             metrics: mgr.metrics.clone(),
@@ -282,6 +325,8 @@ impl FileCacheEntry {
 
             blob_compressed_size,
             blob_uncompressed_size: blob_info.uncompressed_size(),
+            truncated_blob_size: AtomicU64::new(u64::MAX),
+            degrade_truncated_blob: false,
             is_get_blob_object_supported: true,
             is_raw_data: false,
             is_direct_chunkmap: true,
@@ -290,10 +335,36 @@ impl FileCacheEntry {
             is_tarfs,
             is_batch,
             is_zran,
+            // Holes in cache files trigger on-demand reads from the fscache driver again.
+            zero_chunks: false,
             dio_enabled: true,
             need_validation,
+            strict_validation: mgr.validate_strict,
+            digest_mismatch_policy: mgr.digest_mismatch_policy,
+            no_digest_reported: AtomicBool::new(false),
             user_io_batch_size: mgr.user_io_batch_size,
+            max_user_merge_size: mgr.max_user_merge_size,
+            max_decompress_size: max_decompress_size(mgr.max_decompress_size, &blob_info),
+            cache_block_align: 0,
+            cache_slot_size: 0,
+            read_amplification_limit: 0,
+            write_state: None,
+            memory_tier: None,
+            eviction: None,
+            priority: 0,
+            access_heatmap: None,
+            chunk_generations: None,
+            parallel_decompress_threshold: 0,
+            parallel_decompress_threads: 0,
+            readahead: None,
+            checksum: None,
+            cache_metrics: CacheMetrics::new(Some(mgr.cache_metrics.clone())),
             prefetch_config,
+            prefetch_merging_size: AtomicU64::new(0),
+            prefetch_progress: PrefetchProgress::default(),
+            prefetch_deny: prefetch_deny_ranges(&mgr.prefetch_deny_list, &blob_info.blob_id()),
+            zero_out_of_bounds_chunks: mgr.zero_out_of_bounds_chunks,
+            last_access: AtomicU64::new(0),
         })
     }
 
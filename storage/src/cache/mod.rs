@@ -17,16 +17,22 @@
 //!   configuration.
 
 use std::cmp;
-use std::io::Result;
-use std::sync::Arc;
+use std::fmt::{Display, Formatter};
+use std::io::{Error, ErrorKind, Result};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use fuse_backend_rs::file_buf::FileVolatileSlice;
+use nydus_api::{DigestMismatchPolicy, PrefetchDenyEntry};
 use nydus_utils::compress::zlib_random::ZranDecoder;
 use nydus_utils::crypt::{self, Cipher, CipherContext};
+use nydus_utils::digest::RafsDigest;
+use nydus_utils::metrics::LatencyStats;
 use nydus_utils::{compress, digest};
 
-use crate::backend::{BlobBackend, BlobReader};
+use crate::backend::{BackendCaps, BlobBackend, BlobReader};
 use crate::cache::state::ChunkMap;
 use crate::device::{
     BlobChunkInfo, BlobInfo, BlobIoDesc, BlobIoRange, BlobIoVec, BlobObject, BlobPrefetchRequest,
@@ -36,9 +42,11 @@ use crate::utils::{alloc_buf, check_digest};
 use crate::{StorageResult, RAFS_MAX_CHUNK_SIZE};
 
 mod cachedfile;
+mod checksum;
 #[cfg(feature = "dedup")]
 mod dedup;
 mod dummycache;
+mod eviction;
 mod filecache;
 #[cfg(target_os = "linux")]
 mod fscache;
@@ -47,6 +55,7 @@ mod worker;
 pub mod state;
 
 pub use dummycache::DummyCacheMgr;
+pub use eviction::{ChunkId, EvictionPolicy, LruEvictionPolicy};
 pub use filecache::FileCacheMgr;
 #[cfg(target_os = "linux")]
 pub use fscache::FsCacheMgr;
@@ -54,10 +63,284 @@ pub use fscache::FsCacheMgr;
 /// Timeout in milli-seconds to retrieve blob data from backend storage.
 pub const SINGLE_INFLIGHT_WAIT_TIMEOUT: u64 = 2000;
 
+/// Result of re-validating chunks ready in a blob cache by their digests.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Number of chunks passing validation.
+    pub good: u32,
+    /// Number of chunks failing validation.
+    pub corrupted: u32,
+    /// Indices of chunks failing validation.
+    pub corrupted_chunks: Vec<u32>,
+}
+
+/// Statistics about where data of a read request is served from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReadStats {
+    /// Total bytes of data read.
+    pub total: usize,
+    /// Bytes of data served from the cache.
+    pub from_cache: usize,
+    /// Bytes of data fetched from the storage backend.
+    pub from_backend: usize,
+    /// Number of chunks fetched from the storage backend.
+    pub chunks_fetched: usize,
+}
+
+/// Status of a blob managed by a blob cache manager.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlobCacheStatus {
+    /// Id of the blob.
+    pub blob_id: String,
+    /// Size of blob data to be cached.
+    pub total_size: u64,
+    /// Bytes of blob data materialized in the cache.
+    pub materialized_size: u64,
+    /// Time of last read from the blob in seconds since the UNIX epoch, `None` if unknown.
+    pub last_access: Option<u64>,
+}
+
+/// Callback invoked once background prefetch of a blob has completed.
+pub type PrefetchNotifier = Box<dyn FnOnce() + Send>;
+
+/// Progress of background data prefetch of a blob.
+///
+/// Bytes of prefetch requests are added to the total when queued, and to the completed amount
+/// when handled by prefetch workers. Requests dropped because prefetch has been stopped are never
+/// completed.
+#[derive(Default)]
+pub struct PrefetchProgress {
+    completed: AtomicU64,
+    total: AtomicU64,
+    notifiers: Mutex<Vec<PrefetchNotifier>>,
+}
+
+impl PrefetchProgress {
+    /// Get prefetch progress in form of (completed bytes, total bytes).
+    pub fn get(&self) -> (u64, u64) {
+        // Load `completed` first so it never exceeds the total.
+        let completed = self.completed.load(Ordering::Acquire);
+        (completed, self.total.load(Ordering::Acquire))
+    }
+
+    /// Check whether all queued prefetch requests have been handled.
+    pub fn is_done(&self) -> bool {
+        let (completed, total) = self.get();
+        total > 0 && completed >= total
+    }
+
+    /// Register a callback to be invoked once all queued prefetch requests have been handled.
+    ///
+    /// The callback is invoked immediately if prefetch has already completed.
+    pub fn notify_done(&self, notifier: PrefetchNotifier) {
+        let mut notifiers = self.notifiers.lock().unwrap();
+        if self.is_done() {
+            drop(notifiers);
+            notifier();
+        } else {
+            notifiers.push(notifier);
+        }
+    }
+
+    /// Add `size` bytes of queued prefetch requests.
+    pub(crate) fn add_total(&self, size: u64) {
+        self.total.fetch_add(size, Ordering::AcqRel);
+    }
+
+    /// Add `size` bytes of handled prefetch requests, and invoke notifiers once all are handled.
+    pub(crate) fn complete(&self, size: u64) {
+        self.completed.fetch_add(size, Ordering::AcqRel);
+        if self.is_done() {
+            let notifiers = std::mem::take(&mut *self.notifiers.lock().unwrap());
+            for notifier in notifiers {
+                notifier();
+            }
+        }
+    }
+}
+
+/// Counters of chunk hits and misses of blob cache objects.
+///
+/// Counters of a blob cache object are also accumulated into its parent, which is owned by the
+/// blob cache manager, so the manager reports aggregated counters of all its blob cache objects.
+/// Cloning a `CacheMetrics` object takes a snapshot of the counters without the parent.
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    chunk_hits: AtomicU64,
+    chunk_misses: AtomicU64,
+    cached_bytes: AtomicU64,
+    backend_bytes: AtomicU64,
+    parent: Option<Arc<CacheMetrics>>,
+}
+
+impl CacheMetrics {
+    /// Create a new instance of `CacheMetrics`, accumulating counters into `parent` too.
+    pub fn new(parent: Option<Arc<CacheMetrics>>) -> Self {
+        CacheMetrics {
+            parent,
+            ..Default::default()
+        }
+    }
+
+    /// Get number of chunks served from the cache.
+    pub fn chunk_hits(&self) -> u64 {
+        self.chunk_hits.load(Ordering::Relaxed)
+    }
+
+    /// Get number of chunks fetched from the storage backend.
+    pub fn chunk_misses(&self) -> u64 {
+        self.chunk_misses.load(Ordering::Relaxed)
+    }
+
+    /// Get number of bytes served from the cache.
+    pub fn cached_bytes(&self) -> u64 {
+        self.cached_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Get number of bytes fetched from the storage backend.
+    pub fn backend_bytes(&self) -> u64 {
+        self.backend_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Record `chunks` chunks hit in the cache, with `bytes` bytes served from the cache.
+    pub(crate) fn record_hits(&self, chunks: u64, bytes: u64) {
+        self.chunk_hits.fetch_add(chunks, Ordering::Relaxed);
+        self.cached_bytes.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(parent) = self.parent.as_ref() {
+            parent.record_hits(chunks, bytes);
+        }
+    }
+
+    /// Record `chunks` chunks missed in the cache, with `bytes` bytes fetched from the backend.
+    pub(crate) fn record_misses(&self, chunks: u64, bytes: u64) {
+        self.chunk_misses.fetch_add(chunks, Ordering::Relaxed);
+        self.backend_bytes.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(parent) = self.parent.as_ref() {
+            parent.record_misses(chunks, bytes);
+        }
+    }
+}
+
+impl Clone for CacheMetrics {
+    fn clone(&self) -> Self {
+        CacheMetrics {
+            chunk_hits: AtomicU64::new(self.chunk_hits()),
+            chunk_misses: AtomicU64::new(self.chunk_misses()),
+            cached_bytes: AtomicU64::new(self.cached_bytes()),
+            backend_bytes: AtomicU64::new(self.backend_bytes()),
+            parent: None,
+        }
+    }
+}
+
+/// Create an error for reading range [.., `end`) from a blob truncated to `blob_size` bytes.
+pub fn blob_truncated_error(blob_id: &str, blob_size: u64, end: u64) -> Error {
+    Error::new(
+        ErrorKind::UnexpectedEof,
+        format!(
+            "blob {} is truncated, size 0x{:x} is less than 0x{:x}",
+            blob_id, blob_size, end
+        ),
+    )
+}
+
+/// Errors of reading and decoding chunk data in the cache layer.
+///
+/// A `CacheError` is converted into [std::io::Error] at the `BlobCache` boundary, and it's still
+/// available from the converted error by [CacheError::from_io_error()].
+#[derive(Debug)]
+pub enum CacheError {
+    /// Digest of chunk data doesn't match the chunk digest.
+    DigestMismatch,
+    /// Size of chunk data doesn't match the expected size.
+    SizeMismatch { expected: usize, got: usize },
+    /// The storage backend returns less data than requested.
+    BackendShortRead { expected: usize, got: usize },
+    /// Failed to decompress chunk data.
+    Decompress(Error),
+    /// Chunk data is beyond the end of the blob.
+    ChunkOutOfBounds {
+        blob_id: String,
+        chunk_index: u32,
+        compressed: bool,
+        offset: u64,
+        size: u32,
+        blob_size: u64,
+    },
+}
+
+impl CacheError {
+    /// Get the `CacheError` object wrapped by an [std::io::Error] object.
+    pub fn from_io_error(err: &Error) -> Option<&CacheError> {
+        err.get_ref().and_then(|e| e.downcast_ref::<CacheError>())
+    }
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::DigestMismatch => write!(f, "data digest value doesn't match"),
+            CacheError::SizeMismatch { expected, got } => write!(
+                f,
+                "data size doesn't match, expect 0x{:x}, got 0x{:x}",
+                expected, got
+            ),
+            CacheError::BackendShortRead { expected, got } => write!(
+                f,
+                "storage backend returns less data than requested, expect 0x{:x}, got 0x{:x}",
+                expected, got
+            ),
+            CacheError::Decompress(e) => write!(f, "failed to decompress chunk data, {}", e),
+            CacheError::ChunkOutOfBounds {
+                blob_id,
+                chunk_index,
+                compressed,
+                offset,
+                size,
+                blob_size,
+            } => write!(
+                f,
+                "chunk {} of blob {} at {} offset 0x{:x} size 0x{:x} is beyond blob size 0x{:x}",
+                chunk_index,
+                blob_id,
+                if *compressed {
+                    "compressed"
+                } else {
+                    "uncompressed"
+                },
+                offset,
+                size,
+                blob_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CacheError::Decompress(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<CacheError> for Error {
+    fn from(err: CacheError) -> Self {
+        let kind = match err {
+            CacheError::BackendShortRead { .. } => ErrorKind::UnexpectedEof,
+            _ => ErrorKind::InvalidData,
+        };
+        Error::new(kind, err)
+    }
+}
+
 struct BlobIoMergeState<'a, F: FnMut(BlobIoRange)> {
     cb: F,
     // size of compressed data
     size: u32,
+    // size of compressed data excluding gaps between chunks
+    useful_size: u32,
     bios: Vec<&'a BlobIoDesc>,
 }
 
@@ -69,6 +352,7 @@ impl<'a, F: FnMut(BlobIoRange)> BlobIoMergeState<'a, F> {
         BlobIoMergeState {
             cb,
             size,
+            useful_size: size,
             bios: vec![bio],
         }
     }
@@ -94,9 +378,40 @@ impl<'a, F: FnMut(BlobIoRange)> BlobIoMergeState<'a, F> {
         };
         assert!(self.size.checked_add(size).is_some());
         self.size += size;
+        self.useful_size += bio.chunkinfo.compressed_size();
         self.bios.push(bio);
     }
 
+    /// Check whether merging `bio` would fetch more than `limit` percent of the useful data.
+    fn exceeds_amplification(&self, bio: &BlobIoDesc, limit: u32) -> bool {
+        if limit == 0 || self.bios.is_empty() || bio.chunkinfo.is_batch() {
+            return false;
+        }
+        let last = &self.bios[self.bios.len() - 1].chunkinfo;
+        if last.is_batch() {
+            return false;
+        }
+        let prev = last.compressed_offset() + last.compressed_size() as u64;
+        let gap = bio.chunkinfo.compressed_offset().saturating_sub(prev);
+        let size = self.size as u64 + gap + bio.chunkinfo.compressed_size() as u64;
+        let useful = self.useful_size as u64 + bio.chunkinfo.compressed_size() as u64;
+        size * 100 > useful * limit as u64
+    }
+
+    /// Check whether merging `bio` would make the pending request bigger than `limit` bytes.
+    fn exceeds_size(&self, bio: &BlobIoDesc, limit: u64) -> bool {
+        if limit == 0 || self.bios.is_empty() || bio.chunkinfo.is_batch() {
+            return false;
+        }
+        let last = &self.bios[self.bios.len() - 1].chunkinfo;
+        if last.is_batch() {
+            return false;
+        }
+        let prev = last.compressed_offset() + last.compressed_size() as u64;
+        let gap = bio.chunkinfo.compressed_offset().saturating_sub(prev);
+        self.size as u64 + gap + bio.chunkinfo.compressed_size() as u64 > limit
+    }
+
     /// Issue all pending io descriptors.
     #[inline]
     pub fn issue(&mut self, max_gap: u64) {
@@ -109,12 +424,38 @@ impl<'a, F: FnMut(BlobIoRange)> BlobIoMergeState<'a, F> {
 
             self.bios.truncate(0);
             self.size = 0;
+            self.useful_size = 0;
         }
     }
 
     /// Merge adjacent chunks into bigger request with compressed size no bigger than `max_size`
     /// and issue all blob IO descriptors.
-    pub fn merge_and_issue(bios: &[BlobIoDesc], max_comp_size: u64, max_gap: u64, op: F) {
+    ///
+    /// Chunks won't be merged across a gap if the merged request would fetch more than
+    /// `max_amplification` percent of the requested data. Zero `max_amplification` means no limit.
+    pub fn merge_and_issue(
+        bios: &[BlobIoDesc],
+        max_comp_size: u64,
+        max_gap: u64,
+        max_amplification: u32,
+        op: F,
+    ) {
+        Self::merge_and_issue_with_limit(bios, max_comp_size, max_gap, max_amplification, 0, op)
+    }
+
+    /// Merge adjacent chunks like [merge_and_issue()](#method.merge_and_issue), but never build
+    /// a request bigger than `max_size` bytes.
+    ///
+    /// Chunks which would make the pending request exceed `max_size` start a new request. Zero
+    /// `max_size` means no limit.
+    pub fn merge_and_issue_with_limit(
+        bios: &[BlobIoDesc],
+        max_comp_size: u64,
+        max_gap: u64,
+        max_amplification: u32,
+        max_size: u64,
+        op: F,
+    ) {
         if !bios.is_empty() {
             let mut index = 1;
             let mut state = BlobIoMergeState::new(&bios[0], op);
@@ -124,6 +465,8 @@ impl<'a, F: FnMut(BlobIoRange)> BlobIoMergeState<'a, F> {
                 // or the accumulated compressed data size is big enough.
                 if !bios[index - 1].is_continuous(cur_bio, max_gap)
                     || state.size() as u64 >= max_comp_size
+                    || state.exceeds_amplification(cur_bio, max_amplification)
+                    || state.exceeds_size(cur_bio, max_size)
                 {
                     state.issue(max_gap);
                 }
@@ -167,7 +510,72 @@ pub trait BlobCache: Send + Sync {
     /// Check whether the cache object is for an stargz image with legacy chunk format.
     fn is_legacy_stargz(&self) -> bool;
 
-    /// Get maximum size of gzip compressed data.
+    /// Get maximum size of decompressed data of a chunk.
+    fn max_decompress_size(&self) -> u64 {
+        RAFS_MAX_CHUNK_SIZE
+    }
+
+    /// Reject `chunk` if its decompressed size exceeds the limit, before allocating buffers for it.
+    fn check_decompress_size(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        let size = chunk.uncompressed_size() as u64;
+        let limit = self.max_decompress_size();
+        if size > limit {
+            Err(einval!(format!(
+                "decompressed size 0x{:x} of chunk {} in blob {} exceeds limit 0x{:x}",
+                size,
+                chunk.id(),
+                self.blob_id(),
+                limit
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reject `chunk` if its data is beyond the end of the blob.
+    ///
+    /// Compressed data isn't checked for blobs whose chunks share compressed data or have no exact
+    /// compressed size, and for chunks served from other sources.
+    fn check_chunk_bounds(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
+        let blob_size = self.blob_uncompressed_size().unwrap_or(0);
+        let end = chunk.uncompressed_offset() + chunk.uncompressed_size() as u64;
+        if blob_size > 0 && end > blob_size {
+            return Err(CacheError::ChunkOutOfBounds {
+                blob_id: self.blob_id().to_string(),
+                chunk_index: chunk.id(),
+                compressed: false,
+                offset: chunk.uncompressed_offset(),
+                size: chunk.uncompressed_size(),
+                blob_size,
+            }
+            .into());
+        }
+
+        if self.is_zran()
+            || self.is_batch()
+            || self.is_legacy_stargz()
+            || chunk.is_batch()
+            || chunk.source_url().is_some()
+        {
+            return Ok(());
+        }
+        let blob_size = self.blob_compressed_size().unwrap_or(0);
+        if blob_size > 0 && chunk.compressed_end() > blob_size {
+            return Err(CacheError::ChunkOutOfBounds {
+                blob_id: self.blob_id().to_string(),
+                chunk_index: chunk.id(),
+                compressed: true,
+                offset: chunk.compressed_offset(),
+                size: chunk.compressed_size(),
+                blob_size,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Get maximum size of compressed data for chunks without exact compressed size.
     fn get_legacy_stargz_size(&self, offset: u64, uncomp_size: usize) -> Result<usize> {
         let blob_size = self.blob_compressed_size()?;
         let max_size = blob_size.checked_sub(offset).ok_or_else(|| {
@@ -177,10 +585,8 @@ pub trait BlobCache: Send + Sync {
             ))
         })?;
         let max_size = cmp::min(max_size, usize::MAX as u64) as usize;
-        Ok(compress::compute_compressed_gzip_size(
-            uncomp_size,
-            max_size,
-        ))
+        let size = compress::compute_compressed_size(uncomp_size, self.blob_compressor());
+        Ok(cmp::min(size, max_size))
     }
 
     /// Check whether the blob is ZRan based.
@@ -196,20 +602,151 @@ pub trait BlobCache: Send + Sync {
     /// Check whether need to validate the data chunk by digest value.
     fn need_validation(&self) -> bool;
 
+    /// Check whether to reject chunks without digest when validating chunk data.
+    ///
+    /// Validation is skipped for chunks without digest if not in strict mode.
+    fn strict_validation(&self) -> bool {
+        false
+    }
+
+    /// Report that validation is skipped for a chunk without digest.
+    fn report_no_digest_chunk(&self, _chunk: &dyn BlobChunkInfo) {}
+
+    /// Get the policy to handle chunk data failing digest validation.
+    fn digest_mismatch_policy(&self) -> DigestMismatchPolicy {
+        DigestMismatchPolicy::FailFast
+    }
+
+    /// Report that data of a chunk is served even though it fails digest validation.
+    fn report_digest_mismatch_served(&self, _chunk: &dyn BlobChunkInfo) {}
+
     /// Get the [BlobReader](../backend/trait.BlobReader.html) to read data from storage backend.
     fn reader(&self) -> &dyn BlobReader;
 
+    /// Get the shared [BlobReader](../backend/trait.BlobReader.html) object to read data from
+    /// storage backend asynchronously.
+    #[cfg(feature = "backend-async")]
+    fn shared_reader(&self) -> Arc<dyn BlobReader>;
+
     /// Get the underlying `ChunkMap` object.
     fn get_chunk_map(&self) -> &Arc<dyn ChunkMap>;
 
     /// Get the `BlobChunkInfo` object corresponding to `chunk_index`.
     fn get_chunk_info(&self, chunk_index: u32) -> Option<Arc<dyn BlobChunkInfo>>;
 
+    /// Get size of the blob if it has been marked as truncated on the storage backend.
+    fn get_truncated_blob_size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Mark the blob as truncated on the storage backend.
+    fn set_truncated_blob_size(&self, _blob_size: u64) {}
+
+    /// Check whether range [`offset`, `offset` + `size`) is beyond the end of a truncated blob.
+    ///
+    /// It's called when failing to read data from the storage backend. The blob size is refreshed
+    /// from the storage backend to tell a truncated blob, which retrying won't fix, apart from
+    /// transient failures.
+    fn check_blob_truncated(&self, offset: u64, size: usize) -> Option<Error> {
+        let end = offset + size as u64;
+        match self.reader().blob_size() {
+            Ok(blob_size) if blob_size < end => {
+                warn!(
+                    "blob {} is truncated on storage backend, size 0x{:x}, expect 0x{:x}",
+                    self.blob_id(),
+                    blob_size,
+                    end
+                );
+                self.set_truncated_blob_size(blob_size);
+                Some(blob_truncated_error(self.blob_id(), blob_size, end))
+            }
+            _ => None,
+        }
+    }
+
+    /// Fail requests beyond the end of a blob which has been marked as truncated.
+    fn check_truncated_range(&self, offset: u64, size: usize) -> Result<()> {
+        if let Some(blob_size) = self.get_truncated_blob_size() {
+            let end = offset + size as u64;
+            if blob_size < end {
+                return Err(blob_truncated_error(self.blob_id(), blob_size, end));
+            }
+        }
+        Ok(())
+    }
+
     /// Get a `BlobObject` instance to directly access uncompressed blob file.
     fn get_blob_object(&self) -> Option<&dyn BlobObject> {
         None
     }
 
+    /// Get priority class of the blob, queued backend requests of blobs with higher priority are
+    /// dispatched first.
+    fn priority(&self) -> u8 {
+        0
+    }
+
+    /// Get the minimum number of chunks fetched by one backend request to decompress them in
+    /// parallel, and the number of threads to decompress them. Zero threshold disables it.
+    fn parallel_decompress(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    /// Get access counters of chunks indexed by chunk index, accumulated since the last reset.
+    ///
+    /// An empty vector is returned if access heatmap is disabled.
+    fn access_heatmap(&self) -> Vec<u32> {
+        Vec::new()
+    }
+
+    /// Reset access counters of chunks.
+    fn reset_access_heatmap(&self) {}
+
+    /// Get a snapshot of cache hit/miss counters of the blob cache object.
+    fn metrics(&self) -> CacheMetrics {
+        CacheMetrics::default()
+    }
+
+    /// Re-validate data of all chunks ready in the cache by their digests.
+    ///
+    /// Corrupted chunks are marked as not ready if `clear_corrupted` is true, so they will be
+    /// fetched from the storage backend again.
+    fn verify_cache(&self, _clear_corrupted: bool) -> Result<VerifyReport> {
+        Err(enosys!(
+            "the blob cache doesn't support verifying cached data"
+        ))
+    }
+
+    /// Check whether all chunks covered by `bios` are ready in the cache, so reading them won't
+    /// access the storage backend.
+    ///
+    /// It's much cheaper than a speculative read, but chunks may still get evicted before being
+    /// read.
+    fn is_range_cached(&self, bios: &[BlobIoDesc]) -> bool {
+        let chunk_map = self.get_chunk_map();
+        bios.iter()
+            .all(|bio| matches!(chunk_map.is_ready(&bio.chunkinfo), Ok(true)))
+    }
+
+    /// Mark chunks with index in `chunks` as non-evictable.
+    ///
+    /// Pinned chunks still count against the cache size limit, but they are never selected to
+    /// evict when exceeding the limit. Chunks which don't fit in the limit because of pinned
+    /// chunks are not kept in the cache, and reads caching them fail with `ENOSPC`.
+    fn pin_chunks(&self, _chunks: &[u32]) -> Result<()> {
+        Err(enosys!("the blob cache doesn't support pinning chunks"))
+    }
+
+    /// Mark chunks with index in `chunks` as evictable again.
+    fn unpin_chunks(&self, _chunks: &[u32]) -> Result<()> {
+        Err(enosys!("the blob cache doesn't support pinning chunks"))
+    }
+
+    /// Get progress of background data prefetch, `None` if prefetch isn't supported.
+    fn prefetch_progress(&self) -> Option<&PrefetchProgress> {
+        None
+    }
+
     /// Enable prefetching blob data in background.
     ///
     /// It should be paired with stop_prefetch().
@@ -224,6 +761,9 @@ pub trait BlobCache: Send + Sync {
     fn is_prefetch_active(&self) -> bool;
 
     /// Start to prefetch requested data in background.
+    ///
+    /// Return number of bytes queued for prefetching, requests denied by the prefetch deny list
+    /// are skipped and not counted.
     fn prefetch(
         &self,
         cache: Arc<dyn BlobCache>,
@@ -236,8 +776,28 @@ pub trait BlobCache: Send + Sync {
         Err(enosys!("doesn't support prefetch_range()"))
     }
 
+    /// Fetch all data of the blob into the cache, blocking until done.
+    ///
+    /// Unlike prefetch, it fails if any chunk can't be cached, and it may be cancelled by setting
+    /// `cancel`, then `ECANCELED` is returned. Returns the number of bytes fetched from the
+    /// backend.
+    fn warm(&self, _cancel: &AtomicBool) -> Result<u64> {
+        Err(enosys!("doesn't support warm()"))
+    }
+
     /// Read chunk data described by the blob Io descriptors from the blob cache into the buffer.
-    fn read(&self, iovec: &mut BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize>;
+    fn read(&self, iovec: &mut BlobIoVec, buffers: &[FileVolatileSlice]) -> Result<usize> {
+        self.read_with_stats(iovec, buffers)
+            .map(|stats| stats.total)
+    }
+
+    /// Read chunk data like `read()`, and report how much data is served from the cache and how
+    /// much is fetched from the storage backend.
+    fn read_with_stats(
+        &self,
+        iovec: &mut BlobIoVec,
+        buffers: &[FileVolatileSlice],
+    ) -> Result<ReadStats>;
 
     /// Read multiple chunks from the blob cache in batch mode.
     ///
@@ -247,6 +807,12 @@ pub trait BlobCache: Send + Sync {
     /// Function `read_chunks_from_backend()` returns one buffer containing decompressed chunk data
     /// for each entry in the `chunks` array in corresponding order.
     ///
+    /// Holes between chunks no bigger than `max_coalesce_gap()` are allowed, and data in the holes
+    /// is fetched from the backend and then discarded.
+    ///
+    /// The requirements on `chunks` are checked in debug builds or if data validation is enabled,
+    /// except for ZRan and batch blobs whose chunks share compressed data.
+    ///
     /// This method returns success only if all requested data are successfully fetched.
     fn read_chunks_from_backend<'a, 'b>(
         &'a self,
@@ -258,18 +824,51 @@ pub trait BlobCache: Send + Sync {
     where
         Self: Sized,
     {
-        // Read requested data from the backend by altogether.
+        if (cfg!(debug_assertions) || self.need_validation()) && !self.is_zran() && !self.is_batch()
+        {
+            let max_gap = max_coalesce_gap(self.reader());
+            check_chunks_range(blob_offset, blob_size, chunks, max_gap)?;
+        }
+        for c in chunks.iter().filter(|c| !c.is_batch()) {
+            self.check_decompress_size(c.as_ref())?;
+        }
+
         let mut c_buf = alloc_buf(blob_size);
         let start = Instant::now();
-        let nr_read = self
-            .reader()
-            .read(c_buf.as_mut_slice(), blob_offset)
-            .map_err(|e| eio!(e))?;
-        if nr_read != blob_size {
-            return Err(eio!(format!(
-                "request for {} bytes but got {} bytes",
-                blob_size, nr_read
-            )));
+        if chunks.iter().any(|c| c.source_url().is_some()) {
+            // Route each chunk to its own source instead of reading the range altogether.
+            for c in chunks {
+                let pos = c
+                    .compressed_offset()
+                    .checked_sub(blob_offset)
+                    .filter(|pos| *pos + c.compressed_size() as u64 <= blob_size as u64)
+                    .ok_or_else(|| einval!("chunk is out of the requested range"))?
+                    as usize;
+                let end = pos + c.compressed_size() as usize;
+                self.read_raw_chunk(c.as_ref(), c.compressed_offset(), &mut c_buf[pos..end])?;
+            }
+        } else {
+            self.check_truncated_range(blob_offset, blob_size)?;
+
+            // Read requested data from the backend by altogether.
+            let nr_read = record_read_latency(self.reader(), || {
+                self.reader().read(c_buf.as_mut_slice(), blob_offset)
+            })
+            .map_err(|e| {
+                self.check_blob_truncated(blob_offset, blob_size)
+                    .unwrap_or_else(|| eio!(e))
+            })?;
+            if nr_read != blob_size {
+                return Err(self
+                    .check_blob_truncated(blob_offset, blob_size)
+                    .unwrap_or_else(|| {
+                        CacheError::BackendShortRead {
+                            expected: blob_size,
+                            got: nr_read,
+                        }
+                        .into()
+                    }));
+            }
         }
         let duration = Instant::now().duration_since(start).as_millis();
         debug!(
@@ -300,11 +899,10 @@ pub trait BlobCache: Send + Sync {
 
         if self.is_zran() || self.is_batch() {
             return Err(enosys!("read_chunk_from_backend"));
-        } else if !chunk.is_compressed() && !chunk.is_encrypted() {
-            let size = self.reader().read(buffer, offset).map_err(|e| eio!(e))?;
-            if size != buffer.len() {
-                return Err(eio!("storage backend returns less data than requested"));
-            }
+        }
+        self.check_decompress_size(chunk)?;
+        if !chunk.is_compressed() && !chunk.is_encrypted() {
+            self.read_raw_chunk(chunk, offset, buffer)?;
         } else {
             let c_size = if self.is_legacy_stargz() {
                 self.get_legacy_stargz_size(offset, buffer.len())?
@@ -312,20 +910,17 @@ pub trait BlobCache: Send + Sync {
                 chunk.compressed_size() as usize
             };
             let mut raw_buffer = alloc_buf(c_size);
-            let size = self
-                .reader()
-                .read(raw_buffer.as_mut_slice(), offset)
-                .map_err(|e| eio!(e))?;
-            if size != raw_buffer.len() {
-                return Err(eio!("storage backend returns less data than requested"));
-            }
-            let decrypted_buffer = crypt::decrypt_with_context(
+            self.read_raw_chunk(chunk, offset, raw_buffer.as_mut_slice())?;
+            let res = crypt::decrypt_with_context(
                 &raw_buffer,
                 &self.blob_cipher_object(),
                 &self.blob_cipher_context(),
                 chunk.is_encrypted(),
-            )?;
-            self.decompress_chunk_data(&decrypted_buffer, buffer, chunk.is_compressed())?;
+            )
+            .and_then(|buf| self.decompress_chunk_data(&buf, buffer, self.chunk_compressor(chunk)));
+            if let Err(e) = res {
+                return self.read_chunk_fallback(chunk, buffer, e).map(|_| None);
+            }
             c_buf = Some(raw_buffer);
         }
 
@@ -337,35 +932,175 @@ pub trait BlobCache: Send + Sync {
             chunk.compressed_offset(),
             duration
         );
-        self.validate_chunk_data(chunk, buffer, false)
+        if let Err(e) = self.validate_chunk_data(chunk, buffer, false) {
+            warn!("failed to read data from backend, {}", e);
+            return self.read_chunk_fallback(chunk, buffer, e).map(|_| None);
+        }
+
+        Ok(c_buf)
+    }
+
+    /// Read a segment of a chunk directly from the storage backend.
+    ///
+    /// Only data at `offset` of the uncompressed chunk is fetched into `buffer`, to avoid read
+    /// amplification when a small segment of a big chunk is requested. Chunk data can't be
+    /// decompressed or validated without the whole chunk, so nothing is read and `Ok(false)` is
+    /// returned if the chunk is compressed or encrypted, or data validation is enabled. It's also
+    /// the case if the backend doesn't support efficient ranged reads.
+    fn read_chunk_segment_from_backend(
+        &self,
+        chunk: &dyn BlobChunkInfo,
+        offset: u32,
+        buffer: &mut [u8],
+    ) -> Result<bool> {
+        if self.is_zran()
+            || self.is_batch()
+            || self.is_legacy_stargz()
+            || self.need_validation()
+            || chunk.is_compressed()
+            || chunk.is_encrypted()
+            || chunk.source_url().is_some()
+            || !self
+                .reader()
+                .capabilities()
+                .contains(BackendCaps::SUPPORTS_RANGE)
+        {
+            return Ok(false);
+        }
+        if offset as u64 + buffer.len() as u64 > chunk.uncompressed_size() as u64 {
+            return Err(einval!(format!(
+                "segment 0x{:x}/0x{:x} exceeds chunk size 0x{:x}",
+                offset,
+                buffer.len(),
+                chunk.uncompressed_size()
+            )));
+        }
+
+        self.read_raw_chunk(chunk, chunk.compressed_offset() + offset as u64, buffer)?;
+        Ok(true)
+    }
+
+    /// Read the uncompressed fallback copy of a chunk from the storage backend into `buffer`.
+    ///
+    /// It's called when failing to decode or validate the primary copy of the chunk with `err`,
+    /// which is returned if the chunk has no fallback copy. Data of the fallback copy is always
+    /// validated against the chunk digest.
+    fn read_chunk_fallback(
+        &self,
+        chunk: &dyn BlobChunkInfo,
+        buffer: &mut [u8],
+        err: Error,
+    ) -> Result<()> {
+        let (offset, size) = match chunk.uncompressed_fallback() {
+            Some(v) => v,
+            None => return Err(err),
+        };
+        warn!(
+            "failed to read chunk {} of blob {}, {}, try fallback copy at 0x{:x}",
+            chunk.id(),
+            self.blob_id(),
+            err,
+            offset
+        );
+        if size as usize != buffer.len() {
+            return Err(CacheError::SizeMismatch {
+                expected: buffer.len(),
+                got: size as usize,
+            }
+            .into());
+        }
+
+        self.check_truncated_range(offset, buffer.len())?;
+        let nr_read = record_read_latency(self.reader(), || self.reader().read(buffer, offset))
             .map_err(|e| {
-                warn!("failed to read data from backend, {}", e);
-                e
+                self.check_blob_truncated(offset, buffer.len())
+                    .unwrap_or_else(|| eio!(e))
             })?;
+        if nr_read != buffer.len() {
+            return Err(CacheError::BackendShortRead {
+                expected: buffer.len(),
+                got: nr_read,
+            }
+            .into());
+        }
+        self.validate_chunk_data(chunk, buffer, true)?;
 
-        Ok(c_buf)
+        Ok(())
     }
 
-    /// Decompress chunk data.
+    /// Read raw data of a chunk from the storage backend into `buf`.
+    ///
+    /// Data is read from the source URL of the chunk if available, otherwise from `offset` of
+    /// the blob.
+    fn read_raw_chunk(&self, chunk: &dyn BlobChunkInfo, offset: u64, buf: &mut [u8]) -> Result<()> {
+        if let Some(url) = chunk.source_url() {
+            let size = record_read_latency(self.reader(), || self.reader().read_url(url, buf))
+                .map_err(|e| {
+                    Error::new(
+                        ErrorKind::Other,
+                        format!("failed to read chunk data from {}, {}", url, e),
+                    )
+                })?;
+            if size != buf.len() {
+                warn!("chunk source {} returns less data than requested", url);
+                return Err(CacheError::BackendShortRead {
+                    expected: buf.len(),
+                    got: size,
+                }
+                .into());
+            }
+            return Ok(());
+        }
+
+        self.check_truncated_range(offset, buf.len())?;
+        let size = record_read_latency(self.reader(), || self.reader().read(buf, offset)).map_err(
+            |e| {
+                self.check_blob_truncated(offset, buf.len())
+                    .unwrap_or_else(|| eio!(e))
+            },
+        )?;
+        if size != buf.len() {
+            return Err(self
+                .check_blob_truncated(offset, buf.len())
+                .unwrap_or_else(|| {
+                    CacheError::BackendShortRead {
+                        expected: buf.len(),
+                        got: size,
+                    }
+                    .into()
+                }));
+        }
+
+        Ok(())
+    }
+
+    /// Get the algorithm to decompress data of the chunk, `None` if the chunk isn't compressed.
+    fn chunk_compressor(&self, chunk: &dyn BlobChunkInfo) -> compress::Algorithm {
+        if !chunk.is_compressed() {
+            compress::Algorithm::None
+        } else {
+            chunk.compressor().unwrap_or_else(|| self.blob_compressor())
+        }
+    }
+
+    /// Decompress chunk data with `compressor`.
     fn decompress_chunk_data(
         &self,
         raw_buffer: &[u8],
         buffer: &mut [u8],
-        is_compressed: bool,
+        compressor: compress::Algorithm,
     ) -> Result<()> {
-        if is_compressed {
-            let compressor = self.blob_compressor();
+        if !compressor.is_none() {
             let ret = compress::decompress(raw_buffer, buffer, compressor).map_err(|e| {
                 error!("failed to decompress chunk: {}", e);
-                e
+                CacheError::Decompress(e)
             })?;
             if ret != buffer.len() {
-                return Err(einval!(format!(
-                    "size of decompressed data doesn't match expected, {} vs {}, raw_buffer: {}",
-                    ret,
-                    buffer.len(),
-                    raw_buffer.len()
-                )));
+                return Err(CacheError::SizeMismatch {
+                    expected: buffer.len(),
+                    got: ret,
+                }
+                .into());
             }
         } else if raw_buffer.as_ptr() != buffer.as_ptr() {
             // raw_chunk and chunk may point to the same buffer, so only copy data when needed.
@@ -383,15 +1118,37 @@ pub trait BlobCache: Send + Sync {
     ) -> Result<usize> {
         let d_size = chunk.uncompressed_size() as usize;
         if buffer.len() != d_size {
-            Err(eio!("uncompressed size and buffer size doesn't match"))
-        } else if (self.need_validation() || force_validation)
-            && !self.is_legacy_stargz()
-            && !check_digest(buffer, chunk.chunk_id(), self.blob_digester())
-        {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "data digest value doesn't match",
-            ))
+            Err(CacheError::SizeMismatch {
+                expected: d_size,
+                got: buffer.len(),
+            }
+            .into())
+        } else if !(self.need_validation() || force_validation) || self.is_legacy_stargz() {
+            Ok(d_size)
+        } else if chunk.chunk_id() == &RafsDigest::default() {
+            // Chunks built without digest can't be validated.
+            if self.strict_validation() {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "chunk {} of blob {} has no digest, can't validate data in strict mode",
+                        chunk.id(),
+                        self.blob_id()
+                    ),
+                ))
+            } else {
+                self.report_no_digest_chunk(chunk);
+                Ok(d_size)
+            }
+        } else if !check_digest(buffer, chunk.chunk_id(), self.blob_digester()) {
+            if !force_validation
+                && self.digest_mismatch_policy() == DigestMismatchPolicy::LogAndServe
+            {
+                self.report_digest_mismatch_served(chunk);
+                Ok(d_size)
+            } else {
+                Err(CacheError::DigestMismatch.into())
+            }
         } else {
             Ok(d_size)
         }
@@ -412,6 +1169,8 @@ pub struct ChunkDecompressState<'a, 'b> {
     chunks: Vec<&'b dyn BlobChunkInfo>,
     c_buf: Vec<u8>,
     d_buf: Vec<u8>,
+    // Chunk data decompressed in parallel, in reverse order of chunks.
+    decoded: Option<Vec<Result<Vec<u8>>>>,
 }
 
 impl<'a, 'b> ChunkDecompressState<'a, 'b> {
@@ -430,6 +1189,7 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
             chunks,
             c_buf,
             d_buf: Vec::new(),
+            decoded: None,
         }
     }
 
@@ -466,9 +1226,15 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
             meta.state.is_encrypted(),
         )?;
         let mut output = alloc_buf(d_size as usize);
+        // Batch chunks are always compressed by the blob compressor.
+        let compressor = if c_size != d_size {
+            self.cache.blob_compressor()
+        } else {
+            compress::Algorithm::None
+        };
 
         self.cache
-            .decompress_chunk_data(&decrypted_buffer, &mut output, c_size != d_size)?;
+            .decompress_chunk_data(&decrypted_buffer, &mut output, compressor)?;
 
         if output.len() != d_size as usize {
             return Err(einval!(format!(
@@ -569,34 +1335,46 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
     }
 
     fn next_buf(&mut self, chunk: &dyn BlobChunkInfo) -> Result<Vec<u8>> {
+        Self::decode_chunk(self.cache, self.blob_offset, &self.c_buf, chunk)
+    }
+
+    fn decode_chunk(
+        cache: &dyn BlobCache,
+        blob_offset: u64,
+        c_buf: &[u8],
+        chunk: &dyn BlobChunkInfo,
+    ) -> Result<Vec<u8>> {
         let c_offset = chunk.compressed_offset();
         let c_size = chunk.compressed_size();
         let d_size = chunk.uncompressed_size() as usize;
-        if c_offset < self.blob_offset
-            || c_offset - self.blob_offset > usize::MAX as u64
+        if c_offset < blob_offset
+            || c_offset - blob_offset > usize::MAX as u64
             || c_offset.checked_add(c_size as u64).is_none()
-            || c_offset + c_size as u64 > self.blob_offset + self.c_buf.len() as u64
-            || d_size as u64 > RAFS_MAX_CHUNK_SIZE
+            || c_offset + c_size as u64 > blob_offset + c_buf.len() as u64
+            || d_size as u64 > cache.max_decompress_size()
         {
             let msg = format!(
                 "invalid chunk info: c_offset 0x{:x}, c_size 0x{:x}, d_size 0x{:x}, blob_offset 0x{:x}",
-                c_offset, c_size, d_size, self.blob_offset
+                c_offset, c_size, d_size, blob_offset
             );
             return Err(eio!(msg));
         }
 
-        let offset_merged = (c_offset - self.blob_offset) as usize;
+        let offset_merged = (c_offset - blob_offset) as usize;
         let end_merged = offset_merged + c_size as usize;
         let decrypted_buffer = crypt::decrypt_with_context(
-            &self.c_buf[offset_merged..end_merged],
-            &self.cache.blob_cipher_object(),
-            &self.cache.blob_cipher_context(),
+            &c_buf[offset_merged..end_merged],
+            &cache.blob_cipher_object(),
+            &cache.blob_cipher_context(),
             chunk.is_encrypted(),
         )?;
         let mut buffer = alloc_buf(d_size);
-        self.cache
-            .decompress_chunk_data(&decrypted_buffer, &mut buffer, chunk.is_compressed())?;
-        self.cache
+        cache.decompress_chunk_data(
+            &decrypted_buffer,
+            &mut buffer,
+            cache.chunk_compressor(chunk),
+        )?;
+        cache
             .validate_chunk_data(chunk, &buffer, false)
             .map_err(|e| {
                 warn!("failed to read data from backend, {}", e);
@@ -605,6 +1383,36 @@ impl<'a, 'b> ChunkDecompressState<'a, 'b> {
         Ok(buffer)
     }
 
+    /// Decompress all chunks by `threads` threads, preserving the order of chunks.
+    fn decompress_parallel(&mut self, threads: usize) -> Vec<Result<Vec<u8>>> {
+        let cache = self.cache;
+        let blob_offset = self.blob_offset;
+        let c_buf = &self.c_buf;
+        let group_size = (self.chunks.len() + threads - 1) / threads;
+
+        std::thread::scope(|scope| {
+            let handles = self
+                .chunks
+                .chunks(group_size)
+                .map(|group| {
+                    scope.spawn(move || {
+                        group
+                            .iter()
+                            .map(|c| Self::decode_chunk(cache, blob_offset, c_buf, *c))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .flat_map(|h| match h.join() {
+                    Ok(v) => v,
+                    Err(_) => vec![Err(eio!("chunk decompression thread panicked"))],
+                })
+                .collect()
+        })
+    }
+
     /// Get an immutable reference to the compressed data buffer.
     pub fn compressed_buf(&self) -> &[u8] {
         &self.c_buf
@@ -620,8 +1428,19 @@ impl<'a, 'b> Iterator for ChunkDecompressState<'a, 'b> {
         }
 
         let cache = self.cache;
+        if self.chunk_idx == 0 && !cache.is_batch() && !cache.is_zran() {
+            let (threshold, threads) = cache.parallel_decompress();
+            if threshold > 0 && threads > 1 && self.chunks.len() >= threshold as usize {
+                let mut decoded = self.decompress_parallel(threads as usize);
+                decoded.reverse();
+                self.decoded = Some(decoded);
+            }
+        }
         let chunk = self.chunks[self.chunk_idx];
         self.chunk_idx += 1;
+        if let Some(decoded) = self.decoded.as_mut() {
+            return decoded.pop();
+        }
         let res = if cache.is_batch() {
             self.next_batch(chunk)
         } else if cache.is_zran() {
@@ -657,15 +1476,334 @@ pub(crate) trait BlobCacheMgr: Send + Sync {
 
     /// Check the blob cache data status, if data all ready stop prefetch workers.
     fn check_stat(&self);
+
+    /// Get a snapshot of cache hit/miss counters aggregated from all blob cache objects.
+    fn metrics(&self) -> CacheMetrics {
+        CacheMetrics::default()
+    }
+
+    /// Get distribution of chunk data read latency of the storage backend, in microseconds.
+    ///
+    /// Only the backend requests are timed, excluding decompression and validation of the data.
+    fn latency_stats(&self) -> LatencyStats {
+        self.backend().metrics().read_latency_stats()
+    }
+
+    /// Invalidate all cached chunks of the blob with id `blob_id`, so they will be fetched from
+    /// the storage backend again on next access.
+    ///
+    /// It's used when a blob gets re-pushed under the same id. Space of the cached data is also
+    /// released from the cache file if `punch_hole` is true.
+    fn invalidate_blob(&self, _blob_id: &str, _punch_hole: bool) -> Result<()> {
+        Err(enosys!(
+            "the blob cache manager doesn't support invalidating cached blobs"
+        ))
+    }
+
+    /// Get status of all blobs known to the blob cache manager, sorted by blob id.
+    fn list_blobs(&self) -> Vec<BlobCacheStatus> {
+        Vec::new()
+    }
+}
+
+/// Get maximum size of holes between chunks which may be fetched from `reader` by one request.
+///
+/// It's `BlobReader::max_coalesce_gap()` if the backend supports fetching ranges separated by
+/// holes by one request, otherwise only adjacent chunks may be fetched together.
+pub(crate) fn max_coalesce_gap(reader: &dyn BlobReader) -> u64 {
+    if reader
+        .capabilities()
+        .contains(BackendCaps::SUPPORTS_MULTIPART)
+    {
+        reader.max_coalesce_gap()
+    } else {
+        0
+    }
+}
+
+// Default maximum size of decompressed chunk data, in multiples of the chunk size of the blob.
+const DEFAULT_DECOMPRESS_SIZE_RATIO: u64 = 4;
+
+/// Get maximum size of decompressed data of chunks in `blob_info`.
+///
+/// It's `configured` if not zero, otherwise a multiple of the chunk size of the blob, and never
+/// bigger than `RAFS_MAX_CHUNK_SIZE`.
+pub(crate) fn max_decompress_size(configured: u64, blob_info: &BlobInfo) -> u64 {
+    let size = if configured > 0 {
+        configured
+    } else {
+        blob_info.chunk_size() as u64 * DEFAULT_DECOMPRESS_SIZE_RATIO
+    };
+    if size == 0 {
+        RAFS_MAX_CHUNK_SIZE
+    } else {
+        cmp::min(size, RAFS_MAX_CHUNK_SIZE)
+    }
+}
+
+/// Check chunks of `iovec` against the end of the blob before reading them.
+///
+/// Return `None` if all chunks are within the blob. Otherwise fail the read, or fill data of the
+/// out of bounds chunks with zero and read other chunks by `read` if `zero_fill` is set.
+pub(crate) fn read_out_of_bounds_chunks(
+    cache: &dyn BlobCache,
+    zero_fill: bool,
+    iovec: &mut BlobIoVec,
+    buffers: &[FileVolatileSlice],
+    read: impl Fn(&mut BlobIoVec, &[FileVolatileSlice]) -> Result<ReadStats>,
+) -> Result<Option<ReadStats>> {
+    // Don't allocate memory for the common case to keep the fast path of reads.
+    let mut found = false;
+    for bio in iovec.bi_vec.iter() {
+        if let Err(e) = cache.check_chunk_bounds(&bio.chunkinfo) {
+            if !zero_fill {
+                return Err(e);
+            }
+            warn!("storage: {}, fill it with zero", e);
+            found = true;
+        }
+    }
+    if !found {
+        return Ok(None);
+    }
+
+    let bad = iovec
+        .bi_vec
+        .iter()
+        .map(|bio| cache.check_chunk_bounds(&bio.chunkinfo).is_err())
+        .collect::<Vec<_>>();
+
+    // Data of chunks returned by `read` can't be interleaved with zero-filled data if compressed.
+    iovec.set_data_compressor(None);
+    let mut stats = ReadStats::default();
+    let mut pos = 0;
+    let mut idx = 0;
+    while idx < iovec.len() {
+        let bio = &iovec.bi_vec[idx];
+        if bad[idx] {
+            if bio.user_io {
+                let size = bio.size as usize;
+                for buf in sub_buffers(buffers, pos, size) {
+                    // Safe because `buf` is within `buffers`.
+                    unsafe { std::ptr::write_bytes(buf.as_ptr(), 0, buf.len()) };
+                }
+                stats.total += size;
+                pos += size;
+            }
+            idx += 1;
+            continue;
+        }
+
+        let mut sub_iovec = BlobIoVec::new(bio.blob.clone());
+        let mut size = 0;
+        while idx < iovec.len() && !bad[idx] {
+            let bio = &iovec.bi_vec[idx];
+            if bio.user_io {
+                size += bio.size as usize;
+            }
+            sub_iovec.push(bio.clone());
+            idx += 1;
+        }
+        // Amplified reads of chunks beyond the user request are just dropped.
+        if size > 0 {
+            let sub_stats = read(&mut sub_iovec, &sub_buffers(buffers, pos, size))?;
+            stats.total += sub_stats.total;
+            stats.from_cache += sub_stats.from_cache;
+            stats.from_backend += sub_stats.from_backend;
+            stats.chunks_fetched += sub_stats.chunks_fetched;
+            pos += size;
+        }
+    }
+
+    Ok(Some(stats))
+}
+
+// Get slices covering range [`offset`, `offset` + `size`) of `buffers`.
+fn sub_buffers<'a>(
+    buffers: &[FileVolatileSlice<'a>],
+    mut offset: usize,
+    mut size: usize,
+) -> Vec<FileVolatileSlice<'a>> {
+    let mut slices = Vec::new();
+    for buf in buffers {
+        if size == 0 {
+            break;
+        }
+        if offset >= buf.len() {
+            offset -= buf.len();
+            continue;
+        }
+        let len = cmp::min(buf.len() - offset, size);
+        // Safe because the range is within `buf`.
+        slices.push(unsafe { FileVolatileSlice::from_raw_ptr(buf.as_ptr().add(offset), len) });
+        offset = 0;
+        size -= len;
+    }
+    slices
+}
+
+/// Get ranges of chunk indexes of blob `blob_id` which should never be prefetched.
+///
+/// An entry without chunk range denies all chunks of the blob.
+pub(crate) fn prefetch_deny_ranges(list: &[PrefetchDenyEntry], blob_id: &str) -> Vec<Range<u32>> {
+    list.iter()
+        .filter(|entry| entry.blob_id == blob_id)
+        .map(|entry| match entry.chunk_range {
+            Some((start, end)) => start..end,
+            None => 0..u32::MAX,
+        })
+        .collect()
+}
+
+/// Check whether prefetching chunk `chunk_index` is denied by `ranges`.
+pub(crate) fn is_prefetch_denied(ranges: &[Range<u32>], chunk_index: u32) -> bool {
+    ranges.iter().any(|range| range.contains(&chunk_index))
+}
+
+// Run the backend request `f` issued by `reader`, and record its latency.
+fn record_read_latency<T>(reader: &dyn BlobReader, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let res = f();
+    reader.metrics().record_read_latency(start.elapsed());
+    res
+}
+
+/// Read multiple chunks from the storage backend asynchronously.
+///
+/// It's the asynchronous version of `BlobCache::read_chunks_from_backend()`, so callers may await
+/// backend IO instead of blocking a thread for each request. Chunks in `chunks` must cover the
+/// continuous range [`blob_offset`..`blob_offset` + `blob_size`], and decompressed data of each
+/// chunk is returned in corresponding order. Chunks are decompressed on the blocking thread pool
+/// of the tokio runtime.
+///
+/// ZRan and batch blobs, and chunks sourced from other origins, are not supported yet.
+#[cfg(feature = "backend-async")]
+pub async fn read_chunks_async(
+    cache: Arc<dyn BlobCache>,
+    blob_offset: u64,
+    blob_size: usize,
+    chunks: Vec<Arc<dyn BlobChunkInfo>>,
+) -> Result<Vec<Vec<u8>>> {
+    if cache.is_zran() || cache.is_batch() || chunks.iter().any(|c| c.source_url().is_some()) {
+        return Err(enosys!("asynchronous read is not supported for the blob"));
+    }
+    check_chunks_range(
+        blob_offset,
+        blob_size,
+        &chunks,
+        max_coalesce_gap(cache.reader()),
+    )?;
+    cache.check_truncated_range(blob_offset, blob_size)?;
+
+    let reader = cache.shared_reader();
+    let start = Instant::now();
+    let res = reader.clone().read_async(blob_size, blob_offset).await;
+    reader.metrics().record_read_latency(start.elapsed());
+    let c_buf = res.map_err(|e| {
+        cache
+            .check_blob_truncated(blob_offset, blob_size)
+            .unwrap_or_else(|| eio!(e))
+    })?;
+    if c_buf.len() != blob_size {
+        return Err(cache
+            .check_blob_truncated(blob_offset, blob_size)
+            .unwrap_or_else(|| {
+                CacheError::BackendShortRead {
+                    expected: blob_size,
+                    got: c_buf.len(),
+                }
+                .into()
+            }));
+    }
+
+    tokio::task::spawn_blocking(move || {
+        let chunks = chunks.iter().map(|v| v.as_ref()).collect();
+        ChunkDecompressState::new(blob_offset, cache.as_ref(), chunks, c_buf).collect()
+    })
+    .await
+    .map_err(|e| eio!(format!("failed to decompress chunks, {}", e)))?
+}
+
+// Check that `chunks` are sorted and continuous, with holes between chunks no bigger than
+// `max_gap`, and exactly cover the blob range [blob_offset, blob_offset + blob_size).
+fn check_chunks_range(
+    blob_offset: u64,
+    blob_size: usize,
+    chunks: &[Arc<dyn BlobChunkInfo>],
+    max_gap: u64,
+) -> Result<()> {
+    let mut end = blob_offset;
+    for (idx, chunk) in chunks.iter().enumerate() {
+        let offset = chunk.compressed_offset();
+        let order = match offset.cmp(&end) {
+            cmp::Ordering::Equal => None,
+            cmp::Ordering::Less => Some("out of order"),
+            cmp::Ordering::Greater if idx > 0 && offset - end <= max_gap => None,
+            cmp::Ordering::Greater => Some("not continuous"),
+        };
+        if let Some(order) = order {
+            return Err(einval!(format!(
+                "chunk {} at 0x{:x} is {}, expect chunk at 0x{:x}",
+                chunk.id(),
+                offset,
+                order,
+                end
+            )));
+        }
+        end = chunk.compressed_end();
+    }
+    if end - blob_offset != blob_size as u64 {
+        return Err(einval!(format!(
+            "chunks cover 0x{:x} bytes but 0x{:x} bytes are requested at 0x{:x}",
+            end - blob_offset,
+            blob_size,
+            blob_offset
+        )));
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::device::{BlobChunkFlags, BlobFeatures};
+    use crate::device::{BlobChunkFlags, BlobFeatures, BlobIoKind};
     use crate::test::MockChunkInfo;
 
     use super::*;
 
+    #[test]
+    fn test_check_chunks_range() {
+        let chunk = |index: u32, offset: u64, size: u32| {
+            Arc::new(MockChunkInfo {
+                compress_size: size,
+                uncompress_size: size,
+                compress_offset: offset,
+                index,
+                ..Default::default()
+            }) as Arc<dyn BlobChunkInfo>
+        };
+
+        let chunks = vec![chunk(0, 0x100, 0x100), chunk(1, 0x200, 0x80)];
+        check_chunks_range(0x100, 0x180, &chunks, 0).unwrap();
+        // The span doesn't match the requested range.
+        assert!(check_chunks_range(0x100, 0x200, &chunks, 0).is_err());
+        assert!(check_chunks_range(0x80, 0x200, &chunks, 0).is_err());
+        assert!(check_chunks_range(0x80, 0x200, &chunks, 0x100).is_err());
+
+        // There's a gap between chunks.
+        let chunks = vec![chunk(0, 0x100, 0x100), chunk(1, 0x280, 0x80)];
+        let err = check_chunks_range(0x100, 0x200, &chunks, 0).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        // Holes no bigger than the coalescing gap are allowed.
+        check_chunks_range(0x100, 0x200, &chunks, 0x80).unwrap();
+        assert!(check_chunks_range(0x100, 0x200, &chunks, 0x7f).is_err());
+
+        // Chunks are out of order.
+        let chunks = vec![chunk(1, 0x200, 0x80), chunk(0, 0x100, 0x100)];
+        let err = check_chunks_range(0x100, 0x180, &chunks, 0).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
     #[test]
     fn test_io_merge_state_new() {
         let blob_info = Arc::new(BlobInfo::new(
@@ -687,6 +1825,7 @@ mod tests {
             uncompress_offset: 0,
             file_offset: 0,
             index: 0,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
         let chunk2 = Arc::new(MockChunkInfo {
@@ -699,6 +1838,7 @@ mod tests {
             uncompress_offset: 0x1000,
             file_offset: 0x1000,
             index: 1,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
         let chunk3 = Arc::new(MockChunkInfo {
@@ -711,6 +1851,7 @@ mod tests {
             uncompress_offset: 0x1000,
             file_offset: 0x1000,
             index: 1,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
 
@@ -721,6 +1862,7 @@ mod tests {
             offset: 0,
             size: 0x1000,
             user_io: true,
+            kind: BlobIoKind::default(),
         };
         let mut state = BlobIoMergeState::new(&desc1, cb);
         assert_eq!(state.size(), 0x800);
@@ -732,6 +1874,7 @@ mod tests {
             offset: 0,
             size: 0x1000,
             user_io: true,
+            kind: BlobIoKind::default(),
         };
         state.push(&desc2);
         assert_eq!(state.size, 0x1000);
@@ -747,6 +1890,7 @@ mod tests {
             offset: 0,
             size: 0x1000,
             user_io: true,
+            kind: BlobIoKind::default(),
         };
         state.push(&desc3);
         assert_eq!(state.size, 0x800);
@@ -761,6 +1905,7 @@ mod tests {
             &[desc1.clone(), desc2.clone(), desc3.clone()],
             0x4000,
             0x0,
+            0,
             |_v| count += 1,
         );
         assert_eq!(count, 1);
@@ -770,12 +1915,13 @@ mod tests {
             &[desc1.clone(), desc2.clone(), desc3.clone()],
             0x1000,
             0x0,
+            0,
             |_v| count += 1,
         );
         assert_eq!(count, 2);
 
         let mut count = 0;
-        BlobIoMergeState::merge_and_issue(&[desc1.clone(), desc3.clone()], 0x4000, 0x0, |_v| {
+        BlobIoMergeState::merge_and_issue(&[desc1.clone(), desc3.clone()], 0x4000, 0x0, 0, |_v| {
             count += 1
         });
         assert_eq!(count, 2);
@@ -783,4 +1929,113 @@ mod tests {
         assert!(desc1.is_continuous(&desc2, 0));
         assert!(!desc1.is_continuous(&desc3, 0));
     }
+
+    #[test]
+    fn test_io_merge_amplification_limit() {
+        let blob_info = Arc::new(BlobInfo::new(
+            1,
+            "test1".to_owned(),
+            0x200000,
+            0x100000,
+            0x100000,
+            512,
+            BlobFeatures::_V5_NO_EXT_BLOB_TABLE,
+        ));
+        let new_desc = |index: u32, compress_offset: u64| BlobIoDesc {
+            blob: blob_info.clone(),
+            chunkinfo: (Arc::new(MockChunkInfo {
+                block_id: Default::default(),
+                blob_index: 1,
+                flags: BlobChunkFlags::empty(),
+                compress_size: 0x800,
+                uncompress_size: 0x1000,
+                compress_offset,
+                uncompress_offset: index as u64 * 0x1000,
+                file_offset: 0,
+                index,
+                generation: 0,
+                reserved: 0,
+            }) as Arc<dyn BlobChunkInfo>)
+                .into(),
+            offset: 0,
+            size: 0x1000,
+            user_io: true,
+            kind: BlobIoKind::default(),
+        };
+        // A gap of 0x800 bytes between the first and second chunk.
+        let bios = [new_desc(0, 0), new_desc(1, 0x1000), new_desc(2, 0x1800)];
+
+        let mut ranges = Vec::new();
+        BlobIoMergeState::merge_and_issue(&bios, 0x10000, 0x1000, 0, |r| ranges.push(r));
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].blob_size, 0x2000);
+
+        // Fetching 0x1800 bytes for 0x1000 useful bytes is within the limit of 150%.
+        let mut ranges = Vec::new();
+        BlobIoMergeState::merge_and_issue(&bios, 0x10000, 0x1000, 150, |r| ranges.push(r));
+        assert_eq!(ranges.len(), 1);
+
+        let mut ranges = Vec::new();
+        BlobIoMergeState::merge_and_issue(&bios, 0x10000, 0x1000, 120, |r| ranges.push(r));
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(ranges[0].chunks.len(), 1);
+        assert_eq!(ranges[0].blob_size, 0x800);
+        assert_eq!(ranges[1].chunks.len(), 2);
+        assert_eq!(ranges[1].blob_offset, 0x1000);
+        assert_eq!(ranges[1].blob_size, 0x1000);
+    }
+
+    #[test]
+    fn test_merge_bios_with_size_limit() {
+        let blob_info = Arc::new(BlobInfo::new(
+            1,
+            "test1".to_owned(),
+            0x200000,
+            0x100000,
+            0x100000,
+            512,
+            BlobFeatures::_V5_NO_EXT_BLOB_TABLE,
+        ));
+        let bios = (0..10u32)
+            .map(|index| BlobIoDesc {
+                blob: blob_info.clone(),
+                chunkinfo: (Arc::new(MockChunkInfo {
+                    blob_index: 1,
+                    compress_size: 0x800,
+                    uncompress_size: 0x1000,
+                    compress_offset: index as u64 * 0x800,
+                    uncompress_offset: index as u64 * 0x1000,
+                    index,
+                    ..Default::default()
+                }) as Arc<dyn BlobChunkInfo>)
+                    .into(),
+                offset: 0,
+                size: 0x1000,
+                user_io: true,
+                kind: BlobIoKind::default(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut ranges = Vec::new();
+        BlobIoMergeState::merge_and_issue_with_limit(&bios, 0x10000, 0x1000, 0, 0, |r| {
+            ranges.push(r)
+        });
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].blob_size, 0x5000);
+
+        // A contiguous run of 0x5000 bytes is split at the boundary of 0x2000 bytes.
+        let mut ranges = Vec::new();
+        BlobIoMergeState::merge_and_issue_with_limit(&bios, 0x10000, 0x1000, 0, 0x2000, |r| {
+            ranges.push(r)
+        });
+        assert_eq!(ranges.len(), 3);
+        for (idx, range) in ranges.iter().enumerate() {
+            assert_eq!(range.blob_offset, idx as u64 * 0x2000);
+            assert!(range.blob_size <= 0x2000);
+        }
+        assert_eq!(ranges[0].chunks.len(), 4);
+        assert_eq!(ranges[1].chunks.len(), 4);
+        assert_eq!(ranges[2].chunks.len(), 2);
+        assert_eq!(ranges[2].blob_size, 0x1000);
+    }
 }
@@ -42,7 +42,15 @@ impl DigestedChunkMap {
 
 impl ChunkMap for DigestedChunkMap {
     fn is_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<bool> {
-        Ok(self.cache.read().unwrap().contains(chunk.chunk_id()))
+        self.are_chunks_ready(&[chunk]).map(|ready| ready[0])
+    }
+
+    fn are_chunks_ready(&self, chunks: &[&dyn BlobChunkInfo]) -> Result<Vec<bool>> {
+        let guard = self.cache.read().unwrap();
+        Ok(chunks
+            .iter()
+            .map(|chunk| guard.contains(chunk.chunk_id()))
+            .collect())
     }
 
     fn set_ready_and_clear_pending(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
@@ -0,0 +1,112 @@
+// Copyright (C) 2023 Alibaba Cloud. All rights reserved.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Result};
+use std::os::unix::fs::FileExt;
+use std::sync::{Mutex, OnceLock};
+
+/// The name suffix of blob chunk generation file, named $blob_id.chunk_gen.
+const FILE_SUFFIX: &str = "chunk_gen";
+
+/// Generation numbers of cached chunks, to detect chunks changed by rebuilding the blob in place.
+///
+/// Generations are persisted in the file `$blob_id.chunk_gen` as an array of little-endian `u32`,
+/// indexed by chunk index. The file is only created when a chunk with non-zero generation gets
+/// cached, so nothing changes for blobs without chunk generations.
+pub(crate) struct ChunkGenerationMap {
+    path: String,
+    file: OnceLock<File>,
+    create_lock: Mutex<()>,
+}
+
+impl ChunkGenerationMap {
+    /// Create a new instance of `ChunkGenerationMap` for the blob cache file at `blob_path`.
+    pub(crate) fn new(blob_path: &str) -> Result<Self> {
+        let path = format!("{}.{}", blob_path, FILE_SUFFIX);
+        let file = OnceLock::new();
+        match OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(f) => {
+                let _ = file.set(f);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(ChunkGenerationMap {
+            path,
+            file,
+            create_lock: Mutex::new(()),
+        })
+    }
+
+    /// Get generation of the cached chunk with index `index`.
+    pub(crate) fn get(&self, index: u32) -> Result<u32> {
+        let file = match self.file.get() {
+            Some(f) => f,
+            None => return Ok(0),
+        };
+        let mut buf = [0u8; 4];
+        let mut pos = 0;
+        while pos < buf.len() {
+            match file.read_at(&mut buf[pos..], index as u64 * 4 + pos as u64) {
+                Ok(0) => break,
+                Ok(n) => pos += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    /// Set generation of the cached chunk with index `index`.
+    pub(crate) fn set(&self, index: u32, generation: u32) -> Result<()> {
+        let file = match self.file.get() {
+            Some(f) => f,
+            None if generation == 0 => return Ok(()),
+            None => {
+                let _guard = self.create_lock.lock().unwrap();
+                if self.file.get().is_none() {
+                    let f = OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .open(&self.path)?;
+                    let _ = self.file.set(f);
+                }
+                self.file.get().unwrap()
+            }
+        };
+
+        file.write_all_at(&generation.to_le_bytes(), index as u64 * 4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_chunk_generation_map() {
+        let tmp_dir = TempDir::new().unwrap();
+        let blob_path = tmp_dir.as_path().join("blob-0");
+        let blob_path = blob_path.to_str().unwrap();
+        let map_path = format!("{}.{}", blob_path, FILE_SUFFIX);
+
+        let map = ChunkGenerationMap::new(blob_path).unwrap();
+        assert_eq!(map.get(3).unwrap(), 0);
+        map.set(3, 0).unwrap();
+        assert!(!std::path::Path::new(&map_path).exists());
+        map.set(3, 2).unwrap();
+        assert_eq!(map.get(3).unwrap(), 2);
+        assert_eq!(map.get(1).unwrap(), 0);
+        assert_eq!(map.get(100).unwrap(), 0);
+
+        // Generations are persisted across instances.
+        let map = ChunkGenerationMap::new(blob_path).unwrap();
+        assert_eq!(map.get(3).unwrap(), 2);
+    }
+}
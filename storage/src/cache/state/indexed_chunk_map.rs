@@ -8,7 +8,7 @@
 //! This module provides a chunk state tracking driver based on a bitmap file. There's a state bit
 //! in the bitmap file for each chunk, and atomic operations are used to manipulate the bitmap.
 //! So it supports concurrent downloading.
-use std::io::Result;
+use std::io::{ErrorKind, Result};
 
 use crate::cache::state::persist_map::PersistMap;
 use crate::cache::state::{ChunkIndexGetter, ChunkMap, RangeMap};
@@ -39,6 +39,23 @@ impl IndexedChunkMap {
 
         PersistMap::open(&filename, chunk_count, true, persist).map(|map| IndexedChunkMap { map })
     }
+
+    /// Sync the bitmap file to disk at most every `interval` seconds when chunk states change,
+    /// zero means leaving it to writeback of the kernel.
+    pub fn set_sync_interval(&mut self, interval: u64) {
+        self.map.set_sync_interval(interval);
+    }
+
+    /// Remove the bitmap file of the blob to discard states of cached chunks, returns whether the
+    /// file existed.
+    pub fn discard(blob_path: &str) -> Result<bool> {
+        let filename = format!("{}.{}", blob_path, FILE_SUFFIX);
+        match std::fs::remove_file(filename) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 impl ChunkMap for IndexedChunkMap {
@@ -51,6 +68,19 @@ impl ChunkMap for IndexedChunkMap {
         }
     }
 
+    fn are_chunks_ready(&self, chunks: &[&dyn BlobChunkInfo]) -> Result<Vec<bool>> {
+        if self.is_range_all_ready() {
+            return Ok(vec![true; chunks.len()]);
+        }
+        chunks
+            .iter()
+            .map(|chunk| {
+                let index = self.map.validate_index(chunk.id())?;
+                Ok(self.map.is_chunk_ready(index).0)
+            })
+            .collect()
+    }
+
     fn set_ready_and_clear_pending(&self, chunk: &dyn BlobChunkInfo) -> Result<()> {
         self.map.set_chunk_ready(chunk.id())
     }
@@ -121,6 +151,16 @@ impl RangeMap for IndexedChunkMap {
             self.map.set_chunk_ready(index)?;
         }
 
+        Ok(())
+    }
+    fn clear_range_ready(&self, start_index: u32, count: u32) -> Result<()> {
+        let count = std::cmp::min(count, u32::MAX - start_index);
+        let end = start_index + count;
+
+        for index in start_index..end {
+            self.map.clear_chunk_ready(index)?;
+        }
+
         Ok(())
     }
 }
@@ -335,4 +375,34 @@ mod tests {
         map.set_ready_and_clear_pending(chunk.as_base()).unwrap();
         assert!(map.is_ready(chunk.as_base()).unwrap());
     }
+
+    #[test]
+    fn test_indexed_reload_and_discard() {
+        let dir = TempDir::new().unwrap();
+        let blob_path = dir.as_path().join("blob-1");
+        let blob_path = blob_path.as_os_str().to_str().unwrap().to_string();
+        let chunk = |index| MockChunkInfo {
+            index,
+            ..Default::default()
+        };
+
+        let mut map = IndexedChunkMap::new(&blob_path, 8, true).unwrap();
+        map.set_sync_interval(1);
+        map.set_ready_and_clear_pending(&chunk(1)).unwrap();
+        map.set_ready_and_clear_pending(&chunk(5)).unwrap();
+        drop(map);
+
+        // States of cached chunks are loaded after restarting.
+        let map = IndexedChunkMap::new(&blob_path, 8, true).unwrap();
+        assert!(map.is_ready(&chunk(1)).unwrap());
+        assert!(map.is_ready(&chunk(5)).unwrap());
+        assert!(!map.is_ready(&chunk(0)).unwrap());
+        assert_eq!(map.map.not_ready_count.load(Ordering::Acquire), 6);
+        drop(map);
+
+        assert!(IndexedChunkMap::discard(&blob_path).unwrap());
+        assert!(!IndexedChunkMap::discard(&blob_path).unwrap());
+        let map = IndexedChunkMap::new(&blob_path, 8, true).unwrap();
+        assert!(!map.is_ready(&chunk(1)).unwrap());
+    }
 }
@@ -40,12 +40,14 @@ use crate::StorageResult;
 
 pub use blob_state_map::BlobStateMap;
 pub use digested_chunk_map::DigestedChunkMap;
+pub(crate) use generation_map::ChunkGenerationMap;
 pub use indexed_chunk_map::IndexedChunkMap;
 pub use noop_chunk_map::NoopChunkMap;
 pub use range_map::BlobRangeMap;
 
 mod blob_state_map;
 mod digested_chunk_map;
+mod generation_map;
 mod indexed_chunk_map;
 mod noop_chunk_map;
 mod persist_map;
@@ -56,6 +58,13 @@ pub trait ChunkMap: Any + Send + Sync {
     /// Check whether the chunk is ready for use.
     fn is_ready(&self, chunk: &dyn BlobChunkInfo) -> Result<bool>;
 
+    /// Check whether each chunk in `chunks` is ready for use.
+    ///
+    /// Implementations protected by locks should check all chunks with the lock acquired once.
+    fn are_chunks_ready(&self, chunks: &[&dyn BlobChunkInfo]) -> Result<Vec<bool>> {
+        chunks.iter().map(|chunk| self.is_ready(*chunk)).collect()
+    }
+
     /// Check whether the chunk is pending for downloading.
     fn is_pending(&self, _chunk: &dyn BlobChunkInfo) -> Result<bool> {
         Ok(false)
@@ -146,6 +155,11 @@ pub trait RangeMap: Send + Sync {
     /// Clear the pending state for all chunks or data in the range.
     fn clear_range_pending(&self, _start: Self::I, _count: Self::I) {}
 
+    /// Mark all chunks or data in the range as not ready, so they will be fetched again.
+    fn clear_range_ready(&self, _start: Self::I, _count: Self::I) -> Result<()> {
+        Err(enosys!())
+    }
+
     /// Wait for all chunks or data in the range to be ready until timeout.
     fn wait_for_range_ready(&self, _start: Self::I, _count: Self::I) -> Result<bool> {
         Err(enosys!())
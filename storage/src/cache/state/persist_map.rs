@@ -6,7 +6,8 @@
 use std::fs::{File, OpenOptions};
 use std::io::{Result, Write};
 use std::os::unix::io::AsRawFd;
-use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use nydus_utils::div_round_up;
 use nydus_utils::filemap::{clone_file, FileMapState};
@@ -45,6 +46,10 @@ pub(crate) struct PersistMap {
     pub count: u32,
     pub not_ready_count: AtomicU32,
     filemap: FileMapState,
+    // Interval in seconds to sync the bitmap to disk, zero to leave it to the kernel.
+    sync_interval: u64,
+    // Seconds since the Unix epoch when the bitmap was synced last time.
+    last_sync: AtomicU64,
 }
 
 impl PersistMap {
@@ -151,9 +156,41 @@ impl PersistMap {
             count: chunk_count,
             not_ready_count: AtomicU32::new(not_ready_count),
             filemap,
+            sync_interval: 0,
+            last_sync: AtomicU64::new(Self::now()),
         })
     }
 
+    /// Sync the bitmap to disk at most every `interval` seconds when chunk states change.
+    pub fn set_sync_interval(&mut self, interval: u64) {
+        self.sync_interval = interval;
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+
+    fn sync_if_due(&self) {
+        if self.sync_interval == 0 {
+            return;
+        }
+        let now = Self::now();
+        let last = self.last_sync.load(Ordering::Relaxed);
+        if now >= last + self.sync_interval
+            && self
+                .last_sync
+                .compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        {
+            if let Err(e) = self.filemap.sync_data() {
+                warn!("failed to sync blob chunk_map file, {}", e);
+            }
+        }
+    }
+
     fn write_header(file: &mut File, size: u64) -> Result<()> {
         let header = Header {
             magic: MAGIC1,
@@ -238,6 +275,8 @@ impl PersistMap {
             if self.write_u8(index, current) {
                 if self.not_ready_count.fetch_sub(1, Ordering::AcqRel) == 1 {
                     self.mark_all_ready();
+                } else {
+                    self.sync_if_due();
                 }
                 break;
             }
@@ -246,6 +285,20 @@ impl PersistMap {
         Ok(())
     }
 
+    pub fn clear_chunk_ready(&self, index: u32) -> Result<()> {
+        let index = self.validate_index(index)?;
+        let mask = Self::index_to_mask(index);
+        let start = HEADER_SIZE + (index as usize >> 3);
+        let atomic_value = self.filemap.get_ref::<AtomicU8>(start).unwrap();
+
+        if atomic_value.fetch_and(!mask, Ordering::AcqRel) & mask == mask {
+            self.not_ready_count.fetch_add(1, Ordering::AcqRel);
+            self.sync_if_due();
+        }
+
+        Ok(())
+    }
+
     fn mark_all_ready(&self) {
         if self.filemap.sync_data().is_ok() {
             /*
@@ -3,18 +3,22 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+#[cfg(feature = "prefetch-rate-limit")]
+use std::collections::{HashMap, VecDeque};
 use std::io::Result;
 use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Once};
 use std::thread;
 use std::time::{Duration, SystemTime};
+#[cfg(feature = "prefetch-rate-limit")]
+use std::{sync::Mutex, time::Instant};
 
-use nydus_api::PrefetchConfigV2;
+use nydus_api::{PrefetchConfigV2, PrefetchStrategy};
 use nydus_utils::async_helper::with_runtime;
 use nydus_utils::metrics::{BlobcacheMetrics, Metric};
 use nydus_utils::mpmc::Channel;
 use tokio::runtime::Runtime;
-use tokio::sync::Semaphore;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use crate::cache::{BlobCache, BlobIoRange};
 use crate::factory::ASYNC_RUNTIME;
@@ -30,6 +34,22 @@ pub(crate) struct AsyncPrefetchConfig {
     /// Network bandwidth for prefetch, in unit of Bytes and Zero means no rate limit is set.
     #[allow(unused)]
     pub bandwidth_limit: u32,
+    /// Burst capacity of the prefetch bandwidth in unit of Bytes, and Zero means the default.
+    #[allow(unused)]
+    pub bandwidth_burst: u32,
+    /// Reduce prefetch bandwidth by recent user IO throughput.
+    #[allow(unused)]
+    pub adaptive_bandwidth: bool,
+    /// Minimum prefetch bandwidth when adapting to user IO throughput, in unit of Bytes.
+    #[allow(unused)]
+    pub min_bandwidth_limit: u32,
+    /// Warm-up window to ramp prefetch bandwidth up to `bandwidth_limit`, Zero means no ramp-up.
+    #[allow(unused)]
+    pub ramp_up: Duration,
+    /// Maximum bytes of prefetch data in flight, and Zero means no limit.
+    pub inflight_bytes: u32,
+    /// Order to prefetch chunks requested by the filesystem.
+    pub strategy: PrefetchStrategy,
 }
 
 impl From<&PrefetchConfigV2> for AsyncPrefetchConfig {
@@ -39,8 +59,160 @@ impl From<&PrefetchConfigV2> for AsyncPrefetchConfig {
             threads_count: p.threads_count,
             batch_size: p.batch_size,
             bandwidth_limit: p.bandwidth_limit,
+            bandwidth_burst: p.bandwidth_burst,
+            adaptive_bandwidth: p.adaptive_bandwidth,
+            min_bandwidth_limit: p.min_bandwidth_limit,
+            ramp_up: Duration::from_secs(p.ramp_up_secs as u64),
+            inflight_bytes: p.prefetch_inflight_bytes,
+            strategy: p.strategy,
+        }
+    }
+}
+
+/// Sliding window to measure user IO throughput for adaptive prefetch bandwidth.
+#[cfg(feature = "prefetch-rate-limit")]
+const ADAPTIVE_BANDWIDTH_WINDOW: Duration = Duration::from_secs(1);
+/// Granularity to merge user IO samples within the sliding window.
+#[cfg(feature = "prefetch-rate-limit")]
+const ADAPTIVE_BANDWIDTH_SLOT: Duration = Duration::from_millis(10);
+
+/// Helper to adapt prefetch bandwidth to recent user IO throughput.
+///
+/// The effective prefetch bandwidth is the configured bandwidth minus user IO throughput over
+/// the sliding window, but no less than the configured floor.
+#[cfg(feature = "prefetch-rate-limit")]
+pub(crate) struct AdaptiveBandwidth {
+    max_rate: u64,
+    min_rate: u64,
+    window: Duration,
+    // User IO samples of (time, bytes) in the sliding window.
+    samples: Mutex<VecDeque<(Instant, u64)>>,
+}
+
+#[cfg(feature = "prefetch-rate-limit")]
+impl AdaptiveBandwidth {
+    pub(crate) fn new(max_rate: u64, min_rate: u64, window: Duration) -> Self {
+        AdaptiveBandwidth {
+            max_rate,
+            min_rate: std::cmp::min(min_rate, max_rate),
+            window,
+            samples: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Record `size` bytes of user IO happened at `now`.
+    pub(crate) fn record(&self, now: Instant, size: u64) {
+        let mut samples = self.samples.lock().unwrap();
+        match samples.back_mut() {
+            Some((time, bytes))
+                if now.saturating_duration_since(*time) < ADAPTIVE_BANDWIDTH_SLOT =>
+            {
+                *bytes += size
+            }
+            _ => samples.push_back((now, size)),
+        }
+        self.expire(&mut samples, now);
+    }
+
+    /// Get the effective prefetch bandwidth at `now`, in unit of Bytes per second.
+    pub(crate) fn rate(&self, now: Instant) -> u64 {
+        let mut samples = self.samples.lock().unwrap();
+        self.expire(&mut samples, now);
+        let bytes = samples.iter().map(|(_, v)| *v).sum::<u64>();
+        let user_rate = bytes as u128 * 1000 / std::cmp::max(self.window.as_millis(), 1);
+        let rate = (self.max_rate as u128).saturating_sub(user_rate) as u64;
+        std::cmp::max(rate, self.min_rate)
+    }
+
+    /// Scale `size` bytes of prefetch data to tokens of a rate limiter refilled at the
+    /// configured bandwidth, so the prefetch throughput matches the effective bandwidth.
+    pub(crate) fn scale(&self, now: Instant, size: u64) -> u64 {
+        let rate = std::cmp::max(self.rate(now), 1);
+        let tokens = size as u128 * self.max_rate as u128 / rate as u128;
+        std::cmp::min(tokens, u64::MAX as u128) as u64
+    }
+
+    fn expire(&self, samples: &mut VecDeque<(Instant, u64)>, now: Instant) {
+        while let Some((time, _)) = samples.front() {
+            if now.saturating_duration_since(*time) >= self.window {
+                samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// The ramp-up starts at `1 / 2^RAMP_UP_STEPS` of the prefetch bandwidth.
+#[cfg(feature = "prefetch-rate-limit")]
+const RAMP_UP_STEPS: u32 = 6;
+/// Backend errors within the interval after a back-off don't back off the ramp-up again.
+#[cfg(feature = "prefetch-rate-limit")]
+const RAMP_UP_BACKOFF_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Helper to ramp prefetch bandwidth up when prefetch starts.
+///
+/// The effective prefetch bandwidth starts low and doubles every `window / RAMP_UP_STEPS` until
+/// it reaches the configured bandwidth, so containers started together don't saturate the
+/// backend at once. A backend error halves the effective bandwidth and the ramp-up continues
+/// from there.
+#[cfg(feature = "prefetch-rate-limit")]
+pub(crate) struct PrefetchRampUp {
+    max_rate: u64,
+    window: Duration,
+    // Virtual start time of the ramp-up and time of the last back-off.
+    state: Mutex<(Instant, Option<Instant>)>,
+}
+
+#[cfg(feature = "prefetch-rate-limit")]
+impl PrefetchRampUp {
+    pub(crate) fn new(max_rate: u64, window: Duration, now: Instant) -> Self {
+        PrefetchRampUp {
+            max_rate,
+            window,
+            state: Mutex::new((now, None)),
         }
     }
+
+    /// Restart the ramp-up at `now`.
+    pub(crate) fn restart(&self, now: Instant) {
+        *self.state.lock().unwrap() = (now, None);
+    }
+
+    /// Get the effective prefetch bandwidth at `now`, in unit of Bytes per second.
+    pub(crate) fn rate(&self, now: Instant) -> u64 {
+        let start = self.state.lock().unwrap().0;
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed >= self.window {
+            return self.max_rate;
+        }
+        let progress = elapsed.as_secs_f64() / self.window.as_secs_f64();
+        let initial = std::cmp::max(self.max_rate >> RAMP_UP_STEPS, 1) as f64;
+        let rate = initial * (progress * RAMP_UP_STEPS as f64).exp2();
+        std::cmp::min(rate as u64, self.max_rate)
+    }
+
+    /// Record a backend error happened at `now`, which halves the effective bandwidth.
+    pub(crate) fn record_error(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(last) = state.1 {
+            if now.saturating_duration_since(last) < RAMP_UP_BACKOFF_INTERVAL {
+                return;
+            }
+        }
+        let elapsed = std::cmp::min(now.saturating_duration_since(state.0), self.window);
+        let elapsed = elapsed.saturating_sub(self.window / RAMP_UP_STEPS);
+        state.0 = now.checked_sub(elapsed).unwrap_or(now);
+        state.1 = Some(now);
+    }
+
+    /// Scale `size` bytes of prefetch data to tokens of a rate limiter refilled at the
+    /// configured bandwidth, so the prefetch throughput matches the effective bandwidth.
+    pub(crate) fn scale(&self, now: Instant, size: u64) -> u64 {
+        let rate = std::cmp::max(self.rate(now), 1);
+        let tokens = size as u128 * self.max_rate as u128 / rate as u128;
+        std::cmp::min(tokens, u64::MAX as u128) as u64
+    }
 }
 
 /// Asynchronous service request message.
@@ -66,6 +238,15 @@ impl AsyncPrefetchMessage {
     pub fn new_blob_prefetch(blob_cache: Arc<dyn BlobCache>, offset: u64, size: u64) -> Self {
         AsyncPrefetchMessage::BlobPrefetch(blob_cache, offset, size, SystemTime::now())
     }
+
+    /// Get priority class of the message, messages with higher priority are handled first.
+    fn priority(&self) -> u8 {
+        match self {
+            AsyncPrefetchMessage::BlobPrefetch(blob_cache, _, _, _) => blob_cache.priority(),
+            AsyncPrefetchMessage::FsPrefetch(blob_cache, _, _) => blob_cache.priority(),
+            _ => 0,
+        }
+    }
 }
 
 /// An asynchronous task manager for data prefetching
@@ -80,6 +261,8 @@ pub(crate) struct AsyncWorkerMgr {
     retry_times: AtomicI32,
 
     prefetch_sema: Arc<Semaphore>,
+    // Budget of prefetch data bytes in flight, in addition to the request count limit.
+    prefetch_inflight_budget: Option<Arc<Semaphore>>,
     prefetch_channel: Arc<Channel<AsyncPrefetchMessage>>,
     prefetch_config: Arc<AsyncPrefetchConfig>,
     #[allow(unused)]
@@ -88,6 +271,13 @@ pub(crate) struct AsyncWorkerMgr {
     prefetch_consumed: AtomicUsize,
     #[cfg(feature = "prefetch-rate-limit")]
     prefetch_limiter: Option<Arc<leaky_bucket::RateLimiter>>,
+    #[cfg(feature = "prefetch-rate-limit")]
+    prefetch_adaptive: Option<AdaptiveBandwidth>,
+    #[cfg(feature = "prefetch-rate-limit")]
+    prefetch_ramp_up: Option<PrefetchRampUp>,
+    // Rate limiters of blobs overriding the prefetch bandwidth, `None` for unlimited bandwidth.
+    #[cfg(feature = "prefetch-rate-limit")]
+    prefetch_blob_limiters: Mutex<HashMap<String, Option<Arc<leaky_bucket::RateLimiter>>>>,
 }
 
 impl AsyncWorkerMgr {
@@ -96,19 +286,31 @@ impl AsyncWorkerMgr {
         metrics: Arc<BlobcacheMetrics>,
         prefetch_config: Arc<AsyncPrefetchConfig>,
     ) -> Result<Self> {
+        #[cfg(feature = "prefetch-rate-limit")]
+        let mut prefetch_adaptive = None;
+        #[cfg(feature = "prefetch-rate-limit")]
+        let mut prefetch_ramp_up = None;
         #[cfg(feature = "prefetch-rate-limit")]
         let prefetch_limiter = match prefetch_config.bandwidth_limit {
             0 => None,
             v => {
-                // If the given value is less than maximum blob chunk size, it exceeds burst size of the
-                // limiter ending up with throttling all throughput, so ensure bandwidth is bigger than
-                // the maximum chunk size.
-                let limit = std::cmp::max(crate::RAFS_MAX_CHUNK_SIZE as usize, v as usize);
-                let limiter = leaky_bucket::RateLimiter::builder()
-                    .initial(limit)
-                    .refill(limit / 10)
-                    .interval(Duration::from_millis(100))
-                    .build();
+                let limiter = Self::new_rate_limiter(v, prefetch_config.bandwidth_burst);
+                // The limiter is refilled every 100ms.
+                let limit = limiter.refill() * 10;
+                if prefetch_config.adaptive_bandwidth {
+                    prefetch_adaptive = Some(AdaptiveBandwidth::new(
+                        limit as u64,
+                        prefetch_config.min_bandwidth_limit as u64,
+                        ADAPTIVE_BANDWIDTH_WINDOW,
+                    ));
+                }
+                if !prefetch_config.ramp_up.is_zero() {
+                    prefetch_ramp_up = Some(PrefetchRampUp::new(
+                        limit as u64,
+                        prefetch_config.ramp_up,
+                        Instant::now(),
+                    ));
+                }
                 Some(Arc::new(limiter))
             }
         };
@@ -123,6 +325,10 @@ impl AsyncWorkerMgr {
             retry_times: AtomicI32::new(32),
 
             prefetch_sema: Arc::new(Semaphore::new(0)),
+            prefetch_inflight_budget: match prefetch_config.inflight_bytes {
+                0 => None,
+                v => Some(Arc::new(Semaphore::new(v as usize))),
+            },
             prefetch_channel: Arc::new(Channel::new()),
             prefetch_config,
             prefetch_delayed: AtomicU64::new(0),
@@ -130,9 +336,67 @@ impl AsyncWorkerMgr {
             prefetch_consumed: AtomicUsize::new(0),
             #[cfg(feature = "prefetch-rate-limit")]
             prefetch_limiter,
+            #[cfg(feature = "prefetch-rate-limit")]
+            prefetch_adaptive,
+            #[cfg(feature = "prefetch-rate-limit")]
+            prefetch_ramp_up,
+            #[cfg(feature = "prefetch-rate-limit")]
+            prefetch_blob_limiters: Mutex::new(HashMap::new()),
         })
     }
 
+    #[cfg(feature = "prefetch-rate-limit")]
+    fn new_rate_limiter(bandwidth: u32, burst: u32) -> leaky_bucket::RateLimiter {
+        // If the given value is less than maximum blob chunk size, it exceeds burst size of the
+        // limiter ending up with throttling all throughput, so ensure bandwidth is bigger than
+        // the maximum chunk size.
+        let limit = std::cmp::max(crate::RAFS_MAX_CHUNK_SIZE as usize, bandwidth as usize);
+        let mut builder = leaky_bucket::RateLimiter::builder();
+        builder
+            .initial(limit)
+            .refill(limit / 10)
+            .interval(Duration::from_millis(100));
+        if burst > 0 {
+            // The bucket must hold at least one refill, otherwise tokens get lost.
+            builder.max(std::cmp::max(burst as usize, limit / 10));
+        }
+        builder.build()
+    }
+
+    // Get the maximum tokens acquired from `limiter` for a prefetch request, which is twenty
+    // seconds of the bandwidth no matter what the burst capacity is.
+    #[cfg(feature = "prefetch-rate-limit")]
+    fn max_rate_limit_tokens(limiter: &leaky_bucket::RateLimiter) -> usize {
+        limiter.refill().saturating_mul(200)
+    }
+
+    /// Override network bandwidth for prefetching data of the blob `blob_id`, in unit of Bytes
+    /// and Zero means no rate limit.
+    pub fn set_blob_bandwidth_limit(&self, blob_id: &str, _bandwidth: u32) {
+        #[cfg(feature = "prefetch-rate-limit")]
+        {
+            let limiter = match _bandwidth {
+                0 => None,
+                v => Some(Arc::new(Self::new_rate_limiter(
+                    v,
+                    self.prefetch_config.bandwidth_burst,
+                ))),
+            };
+            let mut limiters = self.prefetch_blob_limiters.lock().unwrap();
+            // Keep the existing limiter, otherwise the bandwidth budget gets reset.
+            let same = match limiters.get(blob_id) {
+                Some(Some(l)) => limiter.as_ref().map(|v| v.refill()) == Some(l.refill()),
+                Some(None) => limiter.is_none(),
+                None => false,
+            };
+            if !same {
+                limiters.insert(blob_id.to_string(), limiter);
+            }
+        }
+        #[cfg(not(feature = "prefetch-rate-limit"))]
+        let _ = blob_id;
+    }
+
     /// Create working threads and start the event loop.
     pub fn start(mgr: Arc<AsyncWorkerMgr>) -> Result<()> {
         if mgr.prefetch_config.enable {
@@ -168,7 +432,10 @@ impl AsyncWorkerMgr {
             Err(msg)
         } else {
             self.prefetch_inflight.fetch_add(1, Ordering::Relaxed);
-            self.prefetch_channel.send(msg)
+            match msg.priority() {
+                0 => self.prefetch_channel.send(msg),
+                p => self.prefetch_channel.send_ahead(msg, |m| m.priority() < p),
+            }
         }
     }
 
@@ -188,6 +455,12 @@ impl AsyncWorkerMgr {
 
     /// Consume network bandwidth budget for prefetching.
     pub fn consume_prefetch_budget(&self, size: u64) {
+        #[cfg(feature = "prefetch-rate-limit")]
+        if let Some(adaptive) = self.prefetch_adaptive.as_ref() {
+            // User IO slows down prefetch by reducing the effective bandwidth instead.
+            adaptive.record(Instant::now(), size);
+            return;
+        }
         if self.prefetch_inflight.load(Ordering::Relaxed) > 0 {
             self.prefetch_consumed
                 .fetch_add(size as usize, Ordering::AcqRel);
@@ -240,6 +513,11 @@ impl AsyncWorkerMgr {
             mgr.metrics
                 .prefetch_begin_time_millis
                 .set(now.subsec_millis() as u64);
+
+            #[cfg(feature = "prefetch-rate-limit")]
+            if let Some(ramp_up) = mgr.prefetch_ramp_up.as_ref() {
+                ramp_up.restart(Instant::now());
+            }
         });
 
         // Max 1 active requests per thread.
@@ -255,6 +533,7 @@ impl AsyncWorkerMgr {
                         .await
                         .unwrap();
                     if blob_cache.is_prefetch_active() {
+                        let budget = mgr2.acquire_inflight_budget(size).await;
                         rt.spawn_blocking(move || {
                             let _ = Self::handle_blob_prefetch_request(
                                 mgr2.clone(),
@@ -263,6 +542,7 @@ impl AsyncWorkerMgr {
                                 size,
                                 begin_time,
                             );
+                            drop(budget);
                             drop(token);
                         });
                     }
@@ -273,6 +553,7 @@ impl AsyncWorkerMgr {
                         .unwrap();
 
                     if blob_cache.is_prefetch_active() {
+                        let budget = mgr2.acquire_inflight_budget(req.blob_size).await;
                         rt.spawn_blocking(move || {
                             let _ = Self::handle_fs_prefetch_request(
                                 mgr2.clone(),
@@ -280,6 +561,7 @@ impl AsyncWorkerMgr {
                                 req,
                                 begin_time,
                             );
+                            drop(budget);
                             drop(token)
                         });
                     }
@@ -294,7 +576,32 @@ impl AsyncWorkerMgr {
         }
     }
 
+    /// Reserve in-flight byte budget for a prefetch request of `size` bytes.
+    ///
+    /// A request bigger than the whole budget is clamped to the budget, so it still gets
+    /// dispatched once all other requests have completed.
+    async fn acquire_inflight_budget(&self, size: u64) -> Option<OwnedSemaphorePermit> {
+        let budget = self.prefetch_inflight_budget.as_ref()?;
+        let size = std::cmp::min(size, self.prefetch_config.inflight_bytes as u64) as u32;
+        if size == 0 {
+            return None;
+        }
+        Semaphore::acquire_many_owned(budget.clone(), size)
+            .await
+            .ok()
+    }
+
     async fn handle_prefetch_rate_limit(&self, _msg: &AsyncPrefetchMessage) {
+        #[cfg(feature = "prefetch-rate-limit")]
+        if let Some(limiter) = self.get_blob_limiter(_msg) {
+            if let Some((limiter, size)) = limiter {
+                let size = self.ramp_up_tokens(size);
+                let max = Self::max_rate_limit_tokens(&limiter);
+                limiter.acquire(std::cmp::min(size as usize, max)).await;
+            }
+            return;
+        }
+
         #[cfg(feature = "prefetch-rate-limit")]
         // Allocate network bandwidth budget
         if let Some(limiter) = &self.prefetch_limiter {
@@ -320,8 +627,12 @@ impl AsyncWorkerMgr {
             if size > 0 {
                 let size = (self.prefetch_consumed.swap(0, Ordering::AcqRel))
                     .saturating_add(size as usize);
-                let max = limiter.max();
-                let size = std::cmp::min(size, max.saturating_add(max));
+                let size = match self.prefetch_adaptive.as_ref() {
+                    Some(adaptive) => adaptive.scale(Instant::now(), size as u64) as usize,
+                    None => size,
+                };
+                let size = self.ramp_up_tokens(size as u64) as usize;
+                let size = std::cmp::min(size, Self::max_rate_limit_tokens(limiter));
                 let cap = limiter.balance();
                 if cap < size {
                     self.prefetch_delayed.fetch_add(1, Ordering::Relaxed);
@@ -331,6 +642,44 @@ impl AsyncWorkerMgr {
         }
     }
 
+    // Scale `size` bytes of prefetch data to rate limiter tokens during the ramp-up.
+    #[cfg(feature = "prefetch-rate-limit")]
+    fn ramp_up_tokens(&self, size: u64) -> u64 {
+        match self.prefetch_ramp_up.as_ref() {
+            Some(ramp_up) => ramp_up.scale(Instant::now(), size),
+            None => size,
+        }
+    }
+
+    // Slow down the prefetch ramp-up when the storage backend fails prefetch requests.
+    fn record_prefetch_error(&self) {
+        #[cfg(feature = "prefetch-rate-limit")]
+        if let Some(ramp_up) = self.prefetch_ramp_up.as_ref() {
+            ramp_up.record_error(Instant::now());
+        }
+    }
+
+    // Get the rate limiter overriding the prefetch bandwidth of the blob of `msg`, and size of the
+    // data to prefetch. Returns `None` if the global bandwidth applies.
+    #[cfg(feature = "prefetch-rate-limit")]
+    #[allow(clippy::type_complexity)]
+    fn get_blob_limiter(
+        &self,
+        msg: &AsyncPrefetchMessage,
+    ) -> Option<Option<(Arc<leaky_bucket::RateLimiter>, u64)>> {
+        let (blob_cache, size) = match msg {
+            AsyncPrefetchMessage::BlobPrefetch(blob_cache, _offset, size, _) => (blob_cache, *size),
+            AsyncPrefetchMessage::FsPrefetch(blob_cache, req, _) => (blob_cache, req.blob_size),
+            _ => return None,
+        };
+        let limiters = self.prefetch_blob_limiters.lock().unwrap();
+        let limiter = limiters.get(blob_cache.blob_id())?;
+        if size == 0 || !blob_cache.is_prefetch_active() {
+            return Some(None);
+        }
+        Some(limiter.clone().map(|l| (l, size)))
+    }
+
     fn handle_blob_prefetch_request(
         mgr: Arc<AsyncWorkerMgr>,
         cache: Arc<dyn BlobCache>,
@@ -355,10 +704,14 @@ impl AsyncWorkerMgr {
         metrics.prefetch_requests_count.inc();
         metrics.prefetch_data_amount.add(size);
 
+        let mut retrying = false;
         if let Some(obj) = cache.get_blob_object() {
             if let Err(_e) = obj.fetch_range_compressed(offset, size, true) {
+                mgr.record_prefetch_error();
                 if mgr.retry_times.load(Ordering::Relaxed) > 0 {
                     mgr.retry_times.fetch_sub(1, Ordering::Relaxed);
+                    retrying = true;
+                    let cache = cache.clone();
                     ASYNC_RUNTIME.spawn(async move {
                         tokio::time::sleep(Duration::from_secs(1)).await;
                         let msg =
@@ -370,6 +723,12 @@ impl AsyncWorkerMgr {
         } else {
             warn!("prefetch blob range is not supported");
         }
+        // The retried request is accounted as completed when it's handled again.
+        if !retrying {
+            if let Some(progress) = cache.prefetch_progress() {
+                progress.complete(size);
+            }
+        }
 
         metrics.calculate_prefetch_metrics(begin_time);
 
@@ -405,11 +764,18 @@ impl AsyncWorkerMgr {
         mgr.metrics.prefetch_requests_count.inc();
         mgr.metrics.prefetch_data_amount.add(blob_size);
 
-        if let Some(obj) = cache.get_blob_object() {
-            obj.prefetch_chunks(&req)?;
+        let result = if let Some(obj) = cache.get_blob_object() {
+            obj.prefetch_chunks(&req)
         } else {
-            cache.prefetch_range(&req)?;
+            cache.prefetch_range(&req).map(|_| ())
+        };
+        if let Some(progress) = cache.prefetch_progress() {
+            progress.complete(blob_size);
         }
+        if result.is_err() {
+            mgr.record_prefetch_error();
+        }
+        result?;
 
         mgr.metrics.calculate_prefetch_metrics(begin_time);
 
@@ -439,6 +805,12 @@ mod tests {
             threads_count: 2,
             batch_size: 0x100000,
             bandwidth_limit: 0x100000,
+            bandwidth_burst: 0,
+            adaptive_bandwidth: false,
+            min_bandwidth_limit: 0,
+            ramp_up: Duration::ZERO,
+            inflight_bytes: 0,
+            strategy: PrefetchStrategy::default(),
         });
 
         let mgr = Arc::new(AsyncWorkerMgr::new(metrics, config).unwrap());
@@ -479,6 +851,12 @@ mod tests {
             threads_count: 4,
             batch_size: 0x1000000,
             bandwidth_limit: 0x1000000,
+            bandwidth_burst: 0,
+            adaptive_bandwidth: false,
+            min_bandwidth_limit: 0,
+            ramp_up: Duration::ZERO,
+            inflight_bytes: 0,
+            strategy: PrefetchStrategy::default(),
         });
 
         let mgr = Arc::new(AsyncWorkerMgr::new(metrics, config).unwrap());
@@ -518,4 +896,167 @@ mod tests {
         mgr.stop();
         assert_eq!(mgr.workers.load(Ordering::Acquire), 0);
     }
+
+    #[cfg(feature = "prefetch-rate-limit")]
+    #[test]
+    fn test_worker_mgr_rate_limiter_shared() {
+        let tmpdir = TempDir::new().unwrap();
+        let metrics = BlobcacheMetrics::new("test1", tmpdir.as_path().to_str().unwrap());
+        let config = Arc::new(AsyncPrefetchConfig {
+            enable: true,
+            threads_count: 4,
+            batch_size: 0x1000000,
+            bandwidth_limit: 0x2000000,
+            bandwidth_burst: 0x1000000,
+            adaptive_bandwidth: false,
+            min_bandwidth_limit: 0,
+            ramp_up: Duration::ZERO,
+            inflight_bytes: 0,
+            strategy: PrefetchStrategy::default(),
+        });
+        let mgr = Arc::new(AsyncWorkerMgr::new(metrics, config).unwrap());
+        AsyncWorkerMgr::start(mgr.clone()).unwrap();
+        let limiter = mgr.prefetch_limiter.clone().unwrap();
+        assert_eq!(limiter.max(), 0x1000000);
+
+        // All workers draw from the same bucket, so the aggregate throughput is capped by the
+        // bandwidth after the burst budget is drained.
+        let start = Instant::now();
+        for _ in 0..12 {
+            assert!(mgr
+                .send_prefetch_message(AsyncPrefetchMessage::RateLimiter(0x400000))
+                .is_ok());
+        }
+        while mgr.prefetch_inflight.load(Ordering::Acquire) > 0 {
+            assert!(
+                start.elapsed() < Duration::from_secs(4),
+                "prefetch isn't done in time"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(900), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(4), "{:?}", elapsed);
+
+        // Budget accumulated while idle is bounded by the burst capacity.
+        thread::sleep(Duration::from_secs(1));
+        assert!(limiter.balance() <= 0x1000000);
+
+        mgr.stop();
+        assert_eq!(mgr.workers.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn test_worker_mgr_inflight_budget() {
+        let tmpdir = TempDir::new().unwrap();
+        let metrics = BlobcacheMetrics::new("test1", tmpdir.as_path().to_str().unwrap());
+        let config = Arc::new(AsyncPrefetchConfig {
+            enable: true,
+            threads_count: 4,
+            batch_size: 0x100000,
+            bandwidth_limit: 0,
+            bandwidth_burst: 0,
+            adaptive_bandwidth: false,
+            min_bandwidth_limit: 0,
+            ramp_up: Duration::ZERO,
+            inflight_bytes: 0x2800,
+            strategy: PrefetchStrategy::default(),
+        });
+        let mgr = Arc::new(AsyncWorkerMgr::new(metrics, config).unwrap());
+        let budget = mgr.prefetch_inflight_budget.clone().unwrap();
+        let inflight = || 0x2800 - budget.available_permits();
+
+        ASYNC_RUNTIME.block_on(async {
+            let p1 = mgr.acquire_inflight_budget(0x1000).await.unwrap();
+            let p2 = mgr.acquire_inflight_budget(0x1000).await.unwrap();
+            assert_eq!(inflight(), 0x2000);
+
+            // Dispatching another request would exceed the budget, so wait for completion.
+            let pending = tokio::time::timeout(
+                Duration::from_millis(100),
+                mgr.acquire_inflight_budget(0x1000),
+            )
+            .await;
+            assert!(pending.is_err());
+            assert!(inflight() <= 0x2800);
+
+            drop(p1);
+            let p3 = mgr.acquire_inflight_budget(0x1000).await.unwrap();
+            assert_eq!(inflight(), 0x2000);
+            drop(p2);
+            drop(p3);
+
+            // Requests bigger than the budget are clamped to the budget.
+            let p4 = mgr.acquire_inflight_budget(0x10000).await.unwrap();
+            assert_eq!(inflight(), 0x2800);
+            drop(p4);
+            assert_eq!(inflight(), 0);
+            assert!(mgr.acquire_inflight_budget(0).await.is_none());
+        });
+    }
+
+    #[cfg(feature = "prefetch-rate-limit")]
+    #[test]
+    fn test_adaptive_bandwidth() {
+        let adaptive = AdaptiveBandwidth::new(0x100000, 0x10000, Duration::from_secs(1));
+        let start = Instant::now();
+        assert_eq!(adaptive.rate(start), 0x100000);
+        assert_eq!(adaptive.scale(start, 0x1000), 0x1000);
+
+        // Bursts of user IO reduce the effective prefetch bandwidth.
+        for i in 0..4 {
+            adaptive.record(start + Duration::from_millis(i * 100), 0x20000);
+        }
+        let now = start + Duration::from_millis(400);
+        assert_eq!(adaptive.rate(now), 0x80000);
+        assert_eq!(adaptive.scale(now, 0x1000), 0x2000);
+
+        // The effective bandwidth never drops below the floor.
+        adaptive.record(now, 0x1000000);
+        assert_eq!(adaptive.rate(now), 0x10000);
+        assert_eq!(adaptive.scale(now, 0x1000), 0x10000);
+
+        // Bandwidth recovers after user IO samples leave the sliding window.
+        assert_eq!(adaptive.rate(now + Duration::from_millis(999)), 0x10000);
+        assert_eq!(adaptive.rate(now + Duration::from_secs(1)), 0x100000);
+    }
+
+    #[cfg(feature = "prefetch-rate-limit")]
+    #[test]
+    fn test_prefetch_ramp_up() {
+        let start = Instant::now();
+        let ramp_up = PrefetchRampUp::new(0x1000000, Duration::from_secs(60), start);
+        assert_eq!(ramp_up.rate(start), 0x40000);
+        assert_eq!(ramp_up.scale(start, 0x1000), 0x40000);
+
+        // The issued rate increases monotonically during the warm-up window.
+        let mut last = 0;
+        for secs in 0..=70 {
+            let rate = ramp_up.rate(start + Duration::from_secs(secs));
+            assert!(rate >= last, "{} < {} at {}s", rate, last, secs);
+            assert!(rate <= 0x1000000);
+            last = rate;
+        }
+        assert_eq!(ramp_up.rate(start + Duration::from_secs(10)), 0x80000);
+        assert_eq!(ramp_up.rate(start + Duration::from_secs(30)), 0x200000);
+        assert_eq!(ramp_up.rate(start + Duration::from_secs(60)), 0x1000000);
+        assert_eq!(
+            ramp_up.scale(start + Duration::from_secs(60), 0x1000),
+            0x1000
+        );
+
+        // A backend error halves the rate, and errors in a row back off only once.
+        let now = start + Duration::from_secs(70);
+        ramp_up.record_error(now);
+        ramp_up.record_error(now + Duration::from_millis(500));
+        assert_eq!(ramp_up.rate(now), 0x800000);
+        let later = now + Duration::from_secs(1);
+        let rate = ramp_up.rate(later);
+        ramp_up.record_error(later);
+        assert!(ramp_up.rate(later).abs_diff(rate / 2) <= 1);
+        assert_eq!(ramp_up.rate(now + Duration::from_secs(21)), 0x1000000);
+
+        ramp_up.restart(now);
+        assert_eq!(ramp_up.rate(now), 0x40000);
+    }
 }
@@ -29,6 +29,7 @@ use std::io::{self, Error};
 use std::ops::Deref;
 use std::os::unix::io::AsRawFd;
 use std::path::Path;
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 
 use arc_swap::ArcSwap;
@@ -41,7 +42,7 @@ use nydus_utils::compress;
 use nydus_utils::crypt::{self, Cipher, CipherContext};
 use nydus_utils::digest::{self, RafsDigest};
 
-use crate::cache::BlobCache;
+use crate::cache::{BlobCache, PrefetchNotifier};
 use crate::factory::BLOB_FACTORY;
 
 pub(crate) const BLOB_FEATURE_INCOMPAT_MASK: u32 = 0x0000_ffff;
@@ -415,6 +416,8 @@ impl BlobInfo {
             compress::Algorithm::GZip
         } else if self.meta_ci_compressor == compress::Algorithm::Zstd as u32 {
             compress::Algorithm::Zstd
+        } else if self.meta_ci_compressor == compress::Algorithm::Brotli as u32 {
+            compress::Algorithm::Brotli
         } else {
             compress::Algorithm::None
         }
@@ -597,6 +600,8 @@ bitflags! {
         const ENCYPTED = 0x0000_0004;
         /// Chunk data is merged into a batch chunk.
         const BATCH = 0x0000_0008;
+        /// Chunk data is compressed by lz4_block instead of the blob compressor.
+        const LZ4_BLOCK = 0x0000_0010;
     }
 }
 
@@ -664,6 +669,35 @@ pub trait BlobChunkInfo: Any + Sync + Send {
     /// Check whether the chunk is encrypted or not.
     fn is_encrypted(&self) -> bool;
 
+    /// Get the algorithm compressing the chunk, if it's not the compressor of the blob.
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        None
+    }
+
+    /// Get URL of the resource hosting data of the chunk, if the chunk is not stored in the blob.
+    ///
+    /// Data of such chunks is fetched from the URL instead of the compressed data blob, with
+    /// `compressed_size()` bytes starting from the beginning of the resource.
+    fn source_url(&self) -> Option<&str> {
+        None
+    }
+
+    /// Get (offset, size) of the uncompressed fallback copy of the chunk in the blob, if any.
+    ///
+    /// The fallback copy is used when failing to decompress or validate the primary copy.
+    fn uncompressed_fallback(&self) -> Option<(u64, u32)> {
+        None
+    }
+
+    /// Get generation number of the chunk, which gets bumped when the blob is rebuilt in place
+    /// with the chunk changed.
+    ///
+    /// Cached data of the chunk is stale if cached with a different generation. Chunks without
+    /// generation information just return zero.
+    fn generation(&self) -> u32 {
+        0
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -721,11 +755,39 @@ impl BlobChunkInfo for BlobIoChunk {
         self.0.is_encrypted()
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.0.compressor()
+    }
+
+    fn source_url(&self) -> Option<&str> {
+        self.0.source_url()
+    }
+
+    fn uncompressed_fallback(&self) -> Option<(u64, u32)> {
+        self.0.uncompressed_fallback()
+    }
+
+    fn generation(&self) -> u32 {
+        self.0.generation()
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
 
+/// Kind of file data accessed by a blob IO operation, in the order to prefetch for container boot.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum BlobIoKind {
+    /// Data needed to look up and open files.
+    Metadata,
+    /// Data of executable files.
+    Executable,
+    /// Data of other files.
+    #[default]
+    Data,
+}
+
 /// Blob IO descriptor, containing information for a continuous IO range within a chunk.
 #[derive(Clone)]
 pub struct BlobIoDesc {
@@ -742,6 +804,8 @@ pub struct BlobIoDesc {
     /// It might be initiated by user io amplification. With this flag, lower device
     /// layer may choose how to prioritize the IO operation.
     pub(crate) user_io: bool,
+    /// Kind of file data accessed, to prioritize prefetching.
+    pub(crate) kind: BlobIoKind,
 }
 
 impl BlobIoDesc {
@@ -759,6 +823,7 @@ impl BlobIoDesc {
             offset,
             size,
             user_io,
+            kind: BlobIoKind::default(),
         }
     }
 
@@ -767,6 +832,11 @@ impl BlobIoDesc {
         let prev_end = self.chunkinfo.compressed_offset() + self.chunkinfo.compressed_size() as u64;
         let next_offset = next.chunkinfo.compressed_offset();
 
+        // Chunks fetched from their own source URL can't be merged with other chunks.
+        if self.chunkinfo.source_url().is_some() || next.chunkinfo.source_url().is_some() {
+            return false;
+        }
+
         if self.chunkinfo.is_batch() || next.chunkinfo.is_batch() {
             // Batch chunk can only be compared by uncompressed info.
             return next.chunkinfo.uncompressed_offset() - self.chunkinfo.uncompressed_end()
@@ -794,6 +864,7 @@ impl Debug for BlobIoDesc {
             .field("file_offset", &self.offset)
             .field("size", &self.size)
             .field("user", &self.user_io)
+            .field("kind", &self.kind)
             .finish()
     }
 }
@@ -806,6 +877,10 @@ pub struct BlobIoVec {
     bi_size: u64,
     /// Array of blob IOs, these IOs should be executed sequentially.
     pub(crate) bi_vec: Vec<BlobIoDesc>,
+    /// Whether the consumer accepts compressed chunk data.
+    bi_accept_compressed: bool,
+    /// Compression algorithm of data returned by the last read, `None` if decompressed.
+    bi_data_compressor: Option<compress::Algorithm>,
 }
 
 impl BlobIoVec {
@@ -815,6 +890,8 @@ impl BlobIoVec {
             bi_blob,
             bi_size: 0,
             bi_vec: Vec::with_capacity(128),
+            bi_accept_compressed: false,
+            bi_data_compressor: None,
         }
     }
 
@@ -879,6 +956,37 @@ impl BlobIoVec {
     pub fn has_same_blob(&self, desc: &BlobIoVec) -> bool {
         self.bi_blob.blob_index() == desc.bi_blob.blob_index()
     }
+
+    /// Set whether the consumer accepts compressed chunk data.
+    ///
+    /// If accepted, the blob cache may return raw chunk data without decompressing it, when each
+    /// blob IO covers a whole chunk. Then chunk data is stored back to back in the buffers, and
+    /// each chunk occupies `compressed_size()` bytes. Data of chunks without the compressed flag
+    /// is returned as is.
+    pub fn set_accept_compressed(&mut self, accept: bool) {
+        self.bi_accept_compressed = accept;
+    }
+
+    /// Check whether the consumer accepts compressed chunk data.
+    pub fn accept_compressed(&self) -> bool {
+        self.bi_accept_compressed
+    }
+
+    /// Get compression algorithm of data returned by the last read, `None` if decompressed.
+    pub fn data_compressor(&self) -> Option<compress::Algorithm> {
+        self.bi_data_compressor
+    }
+
+    pub(crate) fn set_data_compressor(&mut self, compressor: Option<compress::Algorithm>) {
+        self.bi_data_compressor = compressor;
+    }
+
+    /// Set kind of file data accessed by all blob IOs in the vector.
+    pub fn set_kind(&mut self, kind: BlobIoKind) {
+        for desc in self.bi_vec.iter_mut() {
+            desc.kind = kind;
+        }
+    }
 }
 
 impl Debug for BlobIoVec {
@@ -1062,6 +1170,12 @@ pub struct BlobPrefetchRequest {
     pub offset: u64,
     /// Size of data to prefetch.
     pub len: u64,
+    /// Maximum size of merged backend requests to prefetch data of the blob, overriding the
+    /// `batch_size` of the prefetch configuration if set.
+    pub merging_size: Option<u64>,
+    /// Network bandwidth to prefetch data of the blob, in unit of Bytes, overriding the
+    /// `bandwidth_limit` of the prefetch configuration if set. Zero means no rate limit.
+    pub bandwidth_rate: Option<u32>,
 }
 
 /// Trait to provide direct access to underlying uncompressed blob file.
@@ -1230,6 +1344,42 @@ impl BlobDevice {
         }
     }
 
+    /// Get progress of background data prefetch of a blob, in form of (completed, total) bytes.
+    pub fn prefetch_progress(&self, blob_id: &str) -> Option<(u64, u64)> {
+        let blob = self.get_blob_by_id(blob_id)?;
+        blob.prefetch_progress().map(|p| p.get())
+    }
+
+    /// Register a callback to be invoked once background data prefetch of a blob has completed.
+    ///
+    /// It should be registered before starting prefetch, otherwise the callback is invoked when
+    /// requests queued afterwards have been handled.
+    pub fn notify_prefetch_done(
+        &self,
+        blob_id: &str,
+        notifier: PrefetchNotifier,
+    ) -> io::Result<()> {
+        let blob = self
+            .get_blob_by_id(blob_id)
+            .ok_or_else(|| enoent!(format!("blob {} not found", blob_id)))?;
+        let progress = blob
+            .prefetch_progress()
+            .ok_or_else(|| enosys!("the blob cache doesn't support prefetch progress"))?;
+        progress.notify_done(notifier);
+        Ok(())
+    }
+
+    /// Fetch all data of a blob into the cache, blocking until done.
+    ///
+    /// Returns the number of bytes fetched from the backend, or `ECANCELED` if `cancel` gets set.
+    pub fn warm_blob(&self, blob_info: &BlobInfo, cancel: &AtomicBool) -> io::Result<u64> {
+        let blob_id = blob_info.blob_id();
+        let blob = self
+            .get_blob_by_id(&blob_id)
+            .ok_or_else(|| enoent!(format!("blob {} not found", blob_id)))?;
+        blob.warm(cancel)
+    }
+
     /// fetch specified blob data in a synchronous way.
     pub fn fetch_range_synchronous(&self, prefetches: &[BlobPrefetchRequest]) -> io::Result<()> {
         for req in prefetches {
@@ -1442,6 +1592,7 @@ mod tests {
             uncompress_offset: 0x2000,
             file_offset: 0,
             index: 3,
+            generation: 0,
             reserved: 0,
         });
         let iochunk: BlobIoChunk = chunk.clone().into();
@@ -1475,6 +1626,7 @@ mod tests {
             uncompress_offset: 0,
             file_offset: 0,
             index: 0,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
         let chunk2 = Arc::new(MockChunkInfo {
@@ -1487,6 +1639,7 @@ mod tests {
             uncompress_offset: 0x1000,
             file_offset: 0x1000,
             index: 1,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
         let chunk3 = Arc::new(MockChunkInfo {
@@ -1499,6 +1652,7 @@ mod tests {
             uncompress_offset: 0x3000,
             file_offset: 0x3000,
             index: 1,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
 
@@ -1508,6 +1662,7 @@ mod tests {
             offset: 0,
             size: 0x1000,
             user_io: true,
+            kind: BlobIoKind::default(),
         };
         let desc2 = BlobIoDesc {
             blob: blob_info.clone(),
@@ -1515,6 +1670,7 @@ mod tests {
             offset: 0,
             size: 0x1000,
             user_io: true,
+            kind: BlobIoKind::default(),
         };
         let desc3 = BlobIoDesc {
             blob: blob_info,
@@ -1522,6 +1678,7 @@ mod tests {
             offset: 0,
             size: 0x1000,
             user_io: true,
+            kind: BlobIoKind::default(),
         };
 
         assert!(desc1.is_continuous(&desc2, 0x0));
@@ -1561,6 +1718,7 @@ mod tests {
             uncompress_offset: 0,
             file_offset: 0,
             index: 0,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
         let mut iovec = BlobIoVec::new(blob1.clone());
@@ -1585,6 +1743,7 @@ mod tests {
             uncompress_offset: 0x1000,
             file_offset: 0x1000,
             index: 1,
+            generation: 0,
             reserved: 0,
         }) as Arc<dyn BlobChunkInfo>;
         let mut iovec2 = BlobIoVec::new(blob2.clone());
@@ -1624,6 +1783,7 @@ mod tests {
                 uncompress_offset: 2 * chunk_idx as u64 * chunk_size as u64,
                 file_offset: 2 * chunk_idx as u64 * chunk_size as u64,
                 index: chunk_idx as u32,
+                generation: 0,
                 reserved: 0,
             }) as Arc<dyn BlobChunkInfo>;
             let desc = BlobIoDesc::new(large_blob.clone(), BlobIoChunk(chunk), 0, chunk_size, true);
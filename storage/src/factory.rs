@@ -17,22 +17,27 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use lazy_static::lazy_static;
-use nydus_api::{default_user_io_batch_size, BackendConfigV2, ConfigV2};
+use nydus_api::{default_user_io_batch_size, BackendConfigV2, CacheConfigV2, ConfigV2};
 use tokio::runtime::{Builder, Runtime};
 use tokio::time;
 
+use crate::backend::hedge::HedgedBackend;
 #[cfg(feature = "backend-http-proxy")]
 use crate::backend::http_proxy;
 #[cfg(feature = "backend-localdisk")]
 use crate::backend::localdisk;
 #[cfg(feature = "backend-localfs")]
 use crate::backend::localfs;
+use crate::backend::min_read::MinReadBackend;
+use crate::backend::mirror::MirrorBackend;
 #[cfg(feature = "backend-oss")]
 use crate::backend::oss;
+use crate::backend::packed::PackedBackend;
 #[cfg(feature = "backend-registry")]
 use crate::backend::registry;
 #[cfg(feature = "backend-s3")]
 use crate::backend::s3;
+use crate::backend::timeout::TimeoutBackend;
 use crate::backend::BlobBackend;
 use crate::cache::{BlobCache, BlobCacheMgr, DummyCacheMgr, FileCacheMgr};
 use crate::device::BlobInfo;
@@ -77,9 +82,30 @@ lazy_static::lazy_static! {
     pub static ref BLOB_FACTORY: BlobFactory = BlobFactory::new();
 }
 
+// Key of the blob cache manager for chunk dictionary blobs shared by images, images share the
+// manager only if they have identical storage backend and cache configuration.
+#[derive(Eq, PartialEq)]
+struct DictBlobMgrKey {
+    backend: BackendConfigV2,
+    cache: CacheConfigV2,
+}
+
+#[allow(clippy::derived_hash_with_manual_eq)]
+impl Hash for DictBlobMgrKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.backend.backend_type.hash(state);
+        self.cache.cache_type.hash(state);
+        if let Some(c) = self.cache.file_cache.as_ref() {
+            c.work_dir.hash(state);
+        }
+    }
+}
+
 /// Factory to create blob cache for blob objects.
 pub struct BlobFactory {
     mgrs: Mutex<HashMap<BlobCacheMgrKey, Arc<dyn BlobCacheMgr>>>,
+    // Blob cache managers of chunk dictionary blobs, not owned by any image.
+    dict_mgrs: Mutex<HashMap<DictBlobMgrKey, Arc<dyn BlobCacheMgr>>>,
     mgr_checker_active: AtomicBool,
 }
 
@@ -88,6 +114,7 @@ impl BlobFactory {
     pub fn new() -> Self {
         BlobFactory {
             mgrs: Mutex::new(HashMap::new()),
+            dict_mgrs: Mutex::new(HashMap::new()),
             mgr_checker_active: AtomicBool::new(false),
         }
     }
@@ -110,6 +137,10 @@ impl BlobFactory {
     }
 
     /// Create a blob cache object for a blob with specified configuration.
+    ///
+    /// Chunk dictionary blobs are managed by a blob cache manager shared by all images with the
+    /// same storage backend and cache configuration, so chunks deduplicated by the dictionary are
+    /// fetched and cached only once no matter which image reads them.
     pub fn new_blob_cache(
         &self,
         config: &Arc<ConfigV2>,
@@ -117,10 +148,12 @@ impl BlobFactory {
     ) -> IOResult<Arc<dyn BlobCache>> {
         let backend_cfg = config.get_backend_config()?;
         let cache_cfg = config.get_cache_config()?;
-        let user_io_batch_size = config
-            .get_rafs_config()
-            .map_or_else(|_| default_user_io_batch_size(), |v| v.user_io_batch_size)
-            as u32;
+        if blob_info.is_chunkdict_generated()
+            && matches!(cache_cfg.cache_type.as_str(), "blobcache" | "filecache")
+        {
+            return self.new_dict_blob_cache(config, blob_info);
+        }
+
         let key = BlobCacheMgrKey {
             config: config.clone(),
         };
@@ -129,6 +162,48 @@ impl BlobFactory {
         if let Some(mgr) = guard.get(&key) {
             return mgr.get_blob_cache(blob_info);
         }
+        let mgr = Self::new_cache_mgr(config, backend_cfg, cache_cfg, blob_info, &config.id)?;
+        let mgr = guard.entry(key).or_insert_with(|| mgr);
+
+        mgr.get_blob_cache(blob_info)
+    }
+
+    // Chunk dictionary blobs are referenced by many images, so they are managed by a dedicated
+    // blob cache manager instead of the manager of the first image referencing them, which may
+    // go away while other images still read the dictionary blobs.
+    fn new_dict_blob_cache(
+        &self,
+        config: &Arc<ConfigV2>,
+        blob_info: &Arc<BlobInfo>,
+    ) -> IOResult<Arc<dyn BlobCache>> {
+        let backend_cfg = config.get_backend_config()?;
+        let cache_cfg = config.get_cache_config()?;
+        let key = DictBlobMgrKey {
+            backend: backend_cfg.clone(),
+            cache: cache_cfg.clone(),
+        };
+        let mut guard = self.dict_mgrs.lock().unwrap();
+        if let Some(mgr) = guard.get(&key) {
+            return mgr.get_blob_cache(blob_info);
+        }
+        let id = format!("chunkdict:{}", cache_cfg.get_filecache_config()?.work_dir);
+        let mgr = Self::new_cache_mgr(config, backend_cfg, cache_cfg, blob_info, &id)?;
+        let mgr = guard.entry(key).or_insert_with(|| mgr);
+
+        mgr.get_blob_cache(blob_info)
+    }
+
+    fn new_cache_mgr(
+        config: &ConfigV2,
+        backend_cfg: &BackendConfigV2,
+        cache_cfg: &CacheConfigV2,
+        blob_info: &BlobInfo,
+        id: &str,
+    ) -> IOResult<Arc<dyn BlobCacheMgr>> {
+        let user_io_batch_size = config
+            .get_rafs_config()
+            .map_or_else(|_| default_user_io_batch_size(), |v| v.user_io_batch_size)
+            as u32;
         let backend = Self::new_backend(backend_cfg, &blob_info.blob_id())?;
         let mgr = match cache_cfg.cache_type.as_str() {
             "blobcache" | "filecache" => {
@@ -136,7 +211,7 @@ impl BlobFactory {
                     cache_cfg,
                     backend,
                     ASYNC_RUNTIME.clone(),
-                    &config.id,
+                    id,
                     user_io_batch_size,
                 )?;
                 mgr.init()?;
@@ -148,7 +223,7 @@ impl BlobFactory {
                     cache_cfg,
                     backend,
                     ASYNC_RUNTIME.clone(),
-                    &config.id,
+                    id,
                     user_io_batch_size,
                 )?;
                 mgr.init()?;
@@ -161,9 +236,7 @@ impl BlobFactory {
             }
         };
 
-        let mgr = guard.entry(key).or_insert_with(|| mgr);
-
-        mgr.get_blob_cache(blob_info)
+        Ok(mgr)
     }
 
     /// Garbage-collect unused blob cache managers and blob caches.
@@ -199,6 +272,12 @@ impl BlobFactory {
                 guard.remove(&key);
             }
         }
+
+        // Blob caches of chunk dictionary blobs are released once no image references them.
+        self.dict_mgrs
+            .lock()
+            .unwrap()
+            .retain(|_, mgr| !mgr.gc(None));
     }
 
     /// Create a storage backend for the blob with id `blob_id`.
@@ -206,6 +285,45 @@ impl BlobFactory {
     pub fn new_backend(
         config: &BackendConfigV2,
         blob_id: &str,
+    ) -> IOResult<Arc<dyn BlobBackend + Send + Sync>> {
+        let mut backend = Self::new_primary_backend(config, blob_id)?;
+        if config.read_timeout_ms > 0 {
+            backend = Arc::new(TimeoutBackend::new(
+                backend,
+                Duration::from_millis(config.read_timeout_ms),
+            ));
+        }
+        if let Some(fallback) = config.fallback.as_ref() {
+            let mut backends = vec![backend];
+            for config in fallback.backends.iter() {
+                backends.push(Self::new_backend(config, blob_id)?);
+            }
+            backend = Arc::new(MirrorBackend::new(
+                backends,
+                Duration::from_secs(fallback.cooldown_secs),
+            ));
+        }
+        if let Some(hedge) = config.hedge.as_ref() {
+            let replica = Self::new_backend(&hedge.replica, blob_id)?;
+            backend = Arc::new(HedgedBackend::new(
+                backend,
+                replica,
+                Duration::from_millis(hedge.delay_ms),
+            ));
+        }
+        if config.min_read_size > 0 {
+            backend = Arc::new(MinReadBackend::new(backend, config.min_read_size as u64));
+        }
+        if let Some(packed) = config.packed.as_ref() {
+            backend = Arc::new(PackedBackend::new(backend, packed));
+        }
+        Ok(backend)
+    }
+
+    #[allow(unused_variables)]
+    fn new_primary_backend(
+        config: &BackendConfigV2,
+        blob_id: &str,
     ) -> IOResult<Arc<dyn BlobBackend + Send + Sync>> {
         match config.backend_type.as_str() {
             #[cfg(feature = "backend-oss")]
@@ -250,6 +368,10 @@ impl BlobFactory {
         for (_key, mgr) in mgrs.iter() {
             mgr.check_stat();
         }
+        let mgrs = self.dict_mgrs.lock().unwrap();
+        for (_key, mgr) in mgrs.iter() {
+            mgr.check_stat();
+        }
     }
 }
 
@@ -258,3 +380,117 @@ impl Default for BlobFactory {
         Self::new()
     }
 }
+
+#[cfg(all(test, feature = "backend-localfs"))]
+mod tests {
+    use super::*;
+    use crate::device::{BlobChunkInfo, BlobFeatures, BlobIoDesc, BlobIoVec};
+    use crate::test::MockChunkInfo;
+    use fuse_backend_rs::file_buf::FileVolatileSlice;
+    use std::str::FromStr;
+    use vmm_sys_util::tempdir::TempDir;
+
+    #[test]
+    fn test_share_dict_blob_cache() {
+        let blob_dir = TempDir::new().unwrap();
+        let work_dir = TempDir::new().unwrap();
+        let blob: Vec<u8> = (0..2u8).flat_map(|i| vec![i + 1; 0x1000]).collect();
+        for id in ["dict-blob", "data-blob"] {
+            std::fs::write(blob_dir.as_path().join(id), &blob).unwrap();
+        }
+        let new_config = |id: &str, blob_dir: &str| {
+            let content = format!(
+                r#"
+                version = 2
+                id = "{}"
+                [backend]
+                type = "localfs"
+                [backend.localfs]
+                dir = "{}"
+                [cache]
+                type = "filecache"
+                compressed = true
+                [cache.filecache]
+                work_dir = "{}"
+                "#,
+                id,
+                blob_dir,
+                work_dir.as_path().display()
+            );
+            Arc::new(ConfigV2::from_str(&content).unwrap())
+        };
+        let new_blob = |id: &str, dict: bool| {
+            let mut info = BlobInfo::new(
+                0,
+                id.to_string(),
+                0x2000,
+                0x2000,
+                0x1000,
+                2,
+                BlobFeatures::empty(),
+            );
+            info.set_chunkdict_generated(dict);
+            Arc::new(info)
+        };
+
+        let factory = BlobFactory::new();
+        let dir = blob_dir.as_path().display().to_string();
+        let (config1, config2) = (new_config("image-1", &dir), new_config("image-2", &dir));
+        let dict = new_blob("dict-blob", true);
+        let data = new_blob("data-blob", false);
+        let dict1 = factory.new_blob_cache(&config1, &dict).unwrap();
+        let dict2 = factory.new_blob_cache(&config2, &dict).unwrap();
+        assert!(Arc::ptr_eq(&dict1, &dict2));
+        let data1 = factory.new_blob_cache(&config1, &data).unwrap();
+        let data2 = factory.new_blob_cache(&config2, &data).unwrap();
+        assert!(!Arc::ptr_eq(&data1, &data2));
+        // Images with different storage backends never share dict blob caches.
+        let other_dir = TempDir::new().unwrap();
+        std::fs::write(other_dir.as_path().join("dict-blob"), &blob).unwrap();
+        let config3 = new_config("image-3", &other_dir.as_path().display().to_string());
+        let dict3 = factory.new_blob_cache(&config3, &dict).unwrap();
+        assert!(!Arc::ptr_eq(&dict1, &dict3));
+        assert_eq!(factory.dict_mgrs.lock().unwrap().len(), 2);
+        drop(dict3);
+        factory.gc(None);
+        assert_eq!(factory.dict_mgrs.lock().unwrap().len(), 1);
+
+        // Dict chunks read by the first image are served from the cache for the second one.
+        let chunk: Arc<dyn BlobChunkInfo> = Arc::new(MockChunkInfo {
+            compress_size: 0x1000,
+            uncompress_size: 0x1000,
+            index: 0,
+            ..Default::default()
+        });
+        let read = |cache: &Arc<dyn BlobCache>| {
+            let mut iovec = BlobIoVec::new(dict.clone());
+            iovec.push(BlobIoDesc::new(
+                dict.clone(),
+                chunk.clone().into(),
+                0,
+                0x1000,
+                true,
+            ));
+            let mut buf = vec![0u8; 0x1000];
+            let slice = unsafe { FileVolatileSlice::from_raw_ptr(buf.as_mut_ptr(), buf.len()) };
+            let stats = cache.read_with_stats(&mut iovec, &[slice]).unwrap();
+            assert_eq!(buf, blob[..0x1000]);
+            stats.chunks_fetched
+        };
+        assert_eq!(read(&dict1), 1);
+        assert_eq!(read(&dict2), 0);
+
+        // Releasing the first image keeps the dict blob cache alive for the second one.
+        drop((dict1, data1));
+        factory.gc(Some((&config1, "data-blob")));
+        assert_eq!(factory.dict_mgrs.lock().unwrap().len(), 1);
+        assert_eq!(read(&dict2), 0);
+        let dict3 = factory.new_blob_cache(&config2, &dict).unwrap();
+        assert!(Arc::ptr_eq(&dict2, &dict3));
+
+        drop((dict2, dict3, data2));
+        factory.gc(None);
+        assert!(factory.dict_mgrs.lock().unwrap().is_empty());
+        assert!(factory.mgrs.lock().unwrap().is_empty());
+    }
+}
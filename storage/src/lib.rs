@@ -46,6 +46,7 @@ extern crate nydus_api;
 
 use std::fmt::{Display, Formatter};
 
+pub mod access_trace;
 pub mod backend;
 pub mod cache;
 pub mod device;
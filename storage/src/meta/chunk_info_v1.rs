@@ -150,6 +150,7 @@ mod tests {
 
     use nydus_utils::compress;
     use nydus_utils::metrics::BackendMetrics;
+    use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
     use super::*;
@@ -473,4 +474,93 @@ mod tests {
 
         assert_eq!(&buffer[0..uncompressed_size], data);
     }
+
+    #[test]
+    fn test_load_compressed_chunk_table() {
+        let temp = TempFile::new().unwrap();
+        let mut w = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(temp.as_path())
+            .unwrap();
+        let r = OpenOptions::new()
+            .read(true)
+            .write(false)
+            .open(temp.as_path())
+            .unwrap();
+
+        let chunk_count = 256u32;
+        let chunks: Vec<BlobChunkInfoV1Ondisk> = (0..chunk_count as u64)
+            .map(|i| {
+                let mut chunk = BlobChunkInfoV1Ondisk::default();
+                chunk.set_compressed_offset(i * 0x800);
+                chunk.set_compressed_size(0x800);
+                chunk.set_uncompressed_offset(i * 0x1000);
+                chunk.set_uncompressed_size(0x1000);
+                chunk
+            })
+            .collect();
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                chunks.as_ptr() as *const u8,
+                chunks.len() * std::mem::size_of::<BlobChunkInfoV1Ondisk>(),
+            )
+        };
+        let (buf, compressed) = compress::compress(data, compress::Algorithm::Lz4Block).unwrap();
+        assert!(compressed);
+        assert!(buf.len() < data.len());
+
+        let mut header = BlobCompressionContextHeader::default();
+        header.set_ci_compressor(compress::Algorithm::Lz4Block);
+        header.set_ci_entries(chunk_count);
+        header.set_ci_compressed_offset(0);
+        header.set_ci_compressed_size(buf.len() as u64);
+        header.set_ci_uncompressed_size(data.len() as u64);
+        w.write_all(&buf).unwrap();
+        w.write_all(header.as_bytes()).unwrap();
+
+        let mut blob_info = BlobInfo::new(
+            0,
+            "dummy".to_string(),
+            chunk_count as u64 * 0x1000,
+            chunk_count as u64 * 0x800,
+            0x1000,
+            chunk_count,
+            BlobFeatures::default(),
+        );
+        blob_info.set_blob_meta_info(
+            0,
+            buf.len() as u64,
+            data.len() as u64,
+            compress::Algorithm::Lz4Block as u32,
+        );
+
+        let check = |info: &BlobCompressionContextInfo| {
+            assert_eq!(info.get_chunk_count(), chunk_count as usize);
+            let chunks = info.get_chunks_uncompressed(0x1800, 0x1000, 0).unwrap();
+            assert_eq!(chunks.len(), 2);
+            for (idx, chunk) in chunks.iter().enumerate() {
+                assert_eq!(chunk.id(), idx as u32 + 1);
+                assert_eq!(chunk.compressed_offset(), (idx as u64 + 1) * 0x800);
+                assert_eq!(chunk.compressed_size(), 0x800);
+                assert_eq!(chunk.uncompressed_offset(), (idx as u64 + 1) * 0x1000);
+                assert_eq!(chunk.uncompressed_size(), 0x1000);
+            }
+        };
+
+        let tmp_dir = TempDir::new().unwrap();
+        let blob_path = tmp_dir.as_path().join("dummy");
+        let blob_path = blob_path.to_str().unwrap();
+        let reader: Arc<dyn BlobReader> = Arc::new(DummyBlobReader {
+            metrics: BackendMetrics::new("dummy", "localfs"),
+            file: r,
+        });
+        let info =
+            BlobCompressionContextInfo::new(blob_path, &blob_info, Some(&reader), false).unwrap();
+        check(&info);
+
+        // The decompressed chunk table is cached and reused without the backend.
+        let info = BlobCompressionContextInfo::new(blob_path, &blob_info, None, false).unwrap();
+        check(&info);
+    }
 }
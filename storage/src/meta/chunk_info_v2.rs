@@ -18,7 +18,8 @@ const CHUNK_V2_FLAG_COMPRESSED: u64 = 0x1 << 56;
 const CHUNK_V2_FLAG_ZRAN: u64 = 0x2 << 56;
 const CHUNK_V2_FLAG_BATCH: u64 = 0x4 << 56;
 const CHUNK_V2_FLAG_ENCRYPTED: u64 = 0x8 << 56;
-const CHUNK_V2_FLAG_VALID: u64 = 0xf << 56;
+const CHUNK_V2_FLAG_LZ4_BLOCK: u64 = 0x10 << 56;
+const CHUNK_V2_FLAG_VALID: u64 = 0x1f << 56;
 
 /// Chunk compression information on disk format V2.
 #[repr(C, packed)]
@@ -49,6 +50,14 @@ impl BlobChunkInfoV2Ondisk {
         }
     }
 
+    pub(crate) fn set_lz4_block(&mut self, lz4_block: bool) {
+        if lz4_block {
+            self.uncomp_info |= u64::to_le(CHUNK_V2_FLAG_LZ4_BLOCK);
+        } else {
+            self.uncomp_info &= u64::to_le(!CHUNK_V2_FLAG_LZ4_BLOCK);
+        }
+    }
+
     pub(crate) fn set_zran(&mut self, zran: bool) {
         if zran {
             self.uncomp_info |= u64::to_le(CHUNK_V2_FLAG_ZRAN);
@@ -161,6 +170,10 @@ impl BlobMetaChunkInfo for BlobChunkInfoV2Ondisk {
         u64::from_le(self.uncomp_info) & CHUNK_V2_FLAG_COMPRESSED != 0
     }
 
+    fn is_lz4_block(&self) -> bool {
+        u64::from_le(self.uncomp_info) & CHUNK_V2_FLAG_LZ4_BLOCK != 0
+    }
+
     fn is_zran(&self) -> bool {
         u64::from_le(self.uncomp_info) & CHUNK_V2_FLAG_ZRAN != 0
     }
@@ -381,6 +394,11 @@ mod tests {
         chunk.set_encrypted(true);
         assert!(chunk.is_encrypted());
 
+        chunk.set_lz4_block(true);
+        assert!(chunk.is_lz4_block());
+        chunk.set_lz4_block(false);
+        assert!(!chunk.is_lz4_block());
+
         let before = chunk.uncomp_info;
         chunk.set_batch(true);
         chunk.set_batch(false);
@@ -39,7 +39,7 @@ use nydus_utils::digest::{DigestData, RafsDigest};
 use nydus_utils::filemap::FileMapState;
 use nydus_utils::{compress, crypt};
 
-use crate::backend::BlobReader;
+use crate::backend::{BlobReader, BlobSidecarReader};
 use crate::device::v5::BlobV5ChunkInfo;
 use crate::device::{BlobChunkFlags, BlobChunkInfo, BlobFeatures, BlobInfo};
 use crate::meta::toc::{TocEntryList, TocLocation};
@@ -67,13 +67,15 @@ const BLOB_CCT_V1_MAX_SIZE: u64 = RAFS_MAX_CHUNK_SIZE * 16;
 const BLOB_CCT_V2_MAX_SIZE: u64 = RAFS_MAX_CHUNK_SIZE * 24;
 //const BLOB_CCT_V1_RESERVED_SIZE: u64 = BLOB_METADATA_HEADER_SIZE - 44;
 const BLOB_CCT_V2_RESERVED_SIZE: u64 = BLOB_CCT_HEADER_SIZE - 64;
+/// Size of the tar header following each metadata section of blobs with separate metadata.
+const BLOB_TAR_HEADER_SIZE: u64 = size_of::<tar::Header>() as u64;
 
 /// File suffix for blob meta file.
 const BLOB_CCT_FILE_SUFFIX: &str = "blob.meta";
 /// File suffix for blob chunk digests.
 const BLOB_DIGEST_FILE_SUFFIX: &str = "blob.digest";
 /// File suffix for blob ToC.
-const BLOB_TOC_FILE_SUFFIX: &str = "blob.toc";
+pub(crate) const BLOB_TOC_FILE_SUFFIX: &str = "blob.toc";
 
 /// On disk format for blob compression context table header.
 ///
@@ -147,6 +149,8 @@ impl BlobCompressionContextHeader {
             compress::Algorithm::GZip
         } else if self.s_ci_compressor == compress::Algorithm::Zstd as u32 {
             compress::Algorithm::Zstd
+        } else if self.s_ci_compressor == compress::Algorithm::Brotli as u32 {
+            compress::Algorithm::Brotli
         } else {
             compress::Algorithm::None
         }
@@ -402,6 +406,8 @@ impl BlobCompressionContextInfo {
             return Err(einval!("invalid chunk count in blob meta header"));
         }
 
+        let mut sidecar = None;
+
         let uncompressed_size = blob_info.meta_ci_uncompressed_size() as usize;
         let meta_path = format!("{}.{}", blob_path, BLOB_CCT_FILE_SUFFIX);
         trace!(
@@ -443,9 +449,10 @@ impl BlobCompressionContextInfo {
             filemap.get_mut::<BlobCompressionContextHeader>(aligned_uncompressed_size as usize)?;
         if !Self::validate_header(blob_info, header)? {
             if let Some(reader) = reader {
+                let reader = Self::get_meta_reader(blob_info, reader, &mut sidecar)?;
                 let buffer =
                     unsafe { std::slice::from_raw_parts_mut(base as *mut u8, expected_size) };
-                Self::read_metadata(blob_info, reader, buffer)?;
+                Self::read_metadata(blob_info, &reader, buffer)?;
                 if !Self::validate_header(blob_info, header)? {
                     return Err(enoent!(format!("double check blob_info still invalid",)));
                 }
@@ -520,22 +527,13 @@ impl BlobCompressionContextInfo {
         if load_chunk_digest && blob_info.has_feature(BlobFeatures::INLINED_CHUNK_DIGEST) {
             let digest_path = PathBuf::from(format!("{}.{}", blob_path, BLOB_DIGEST_FILE_SUFFIX));
             if let Some(reader) = reader {
+                let reader = Self::get_meta_reader(blob_info, reader, &mut sidecar)?;
                 let toc_path = format!("{}.{}", blob_path, BLOB_TOC_FILE_SUFFIX);
                 let location = if blob_info.blob_toc_size() != 0 {
                     let blob_size = reader
                         .blob_size()
                         .map_err(|_e| eio!("failed to get blob size"))?;
-                    let offset = blob_size - blob_info.blob_toc_size() as u64;
-                    let mut location = TocLocation::new(offset, blob_info.blob_toc_size() as u64);
-                    let digest = blob_info.blob_toc_digest();
-                    for c in digest {
-                        if *c != 0 {
-                            location.validate_digest = true;
-                            location.digest.data = *digest;
-                            break;
-                        }
-                    }
-                    location
+                    TocLocation::from_blob_info(blob_info, blob_size)?
                 } else {
                     TocLocation::default()
                 };
@@ -685,6 +683,15 @@ impl BlobCompressionContextInfo {
         self.state.get_chunk_digest(chunk_index)
     }
 
+    /// Get generation of the chunk at `chunk_index`, zero if not available.
+    pub fn get_chunk_generation(&self, chunk_index: usize) -> u32 {
+        if chunk_index < self.state.chunk_info_array.len() {
+            self.state.chunk_info_array.generation(chunk_index)
+        } else {
+            0
+        }
+    }
+
     /// Get `BlobChunkInfo` object for the chunk at `chunk_index`.
     pub fn get_chunk_info(&self, chunk_index: usize) -> Arc<dyn BlobChunkInfo> {
         BlobMetaChunk::new(chunk_index, &self.state)
@@ -734,6 +741,55 @@ impl BlobCompressionContextInfo {
         self.state.get_zran_context(zran_index as usize)
     }
 
+    /// Get a reader to fetch blob metadata from the backend.
+    ///
+    /// Metadata of blobs with separate metadata and ToC is fetched by one request, which covers
+    /// range `[meta_ci_offset, toc_offset + toc_size)` of the blob, that is the chunk information
+    /// array and its header, the chunk digest array, the ToC and everything in between.
+    fn get_meta_reader(
+        blob_info: &BlobInfo,
+        reader: &Arc<dyn BlobReader>,
+        sidecar: &mut Option<Arc<dyn BlobReader>>,
+    ) -> Result<Arc<dyn BlobReader>> {
+        if !blob_info.has_feature(BlobFeatures::SEPARATE) || blob_info.blob_toc_size() == 0 {
+            return Ok(reader.clone());
+        }
+        if let Some(sidecar) = sidecar {
+            return Ok(sidecar.clone());
+        }
+
+        let blob_size = reader
+            .blob_size()
+            .map_err(|_e| eio!("failed to get blob size"))?;
+        let toc = TocLocation::from_blob_info(blob_info, blob_size)?;
+        let start = blob_info.meta_ci_offset();
+        let mut size =
+            blob_info.meta_ci_compressed_size() + BLOB_CCT_HEADER_SIZE + BLOB_TAR_HEADER_SIZE;
+        if blob_info.has_feature(BlobFeatures::INLINED_CHUNK_DIGEST) {
+            size += blob_info.chunk_count() as u64 * size_of::<DigestData>() as u64;
+            size += BLOB_TAR_HEADER_SIZE;
+        }
+        if start
+            .checked_add(size)
+            .map(|end| end > toc.offset)
+            .unwrap_or(true)
+        {
+            return Err(einval!(format!(
+                "blob metadata at 0x{:x} overlaps with blob ToC at 0x{:x}",
+                start, toc.offset
+            )));
+        }
+
+        let reader: Arc<dyn BlobReader> = Arc::new(BlobSidecarReader::new(
+            reader.clone(),
+            start,
+            toc.offset + toc.size - start,
+        ));
+        *sidecar = Some(reader.clone());
+
+        Ok(reader)
+    }
+
     fn read_metadata(
         blob_info: &BlobInfo,
         reader: &Arc<dyn BlobReader>,
@@ -1171,6 +1227,7 @@ impl BlobMetaChunkArray {
         uncompressed_size: u32,
         compressed: bool,
         encrypted: bool,
+        lz4_block: bool,
         is_batch: bool,
         data: u64,
     ) {
@@ -1183,6 +1240,7 @@ impl BlobMetaChunkArray {
                 meta.set_uncompressed_size(uncompressed_size);
                 meta.set_compressed(compressed);
                 meta.set_encrypted(encrypted);
+                meta.set_lz4_block(lz4_block);
                 meta.set_batch(is_batch);
                 meta.set_data(data);
                 v.push(meta);
@@ -1370,6 +1428,20 @@ impl BlobMetaChunkArray {
         }
     }
 
+    fn is_lz4_block(&self, index: usize) -> bool {
+        match self {
+            BlobMetaChunkArray::V1(v) => v[index].is_lz4_block(),
+            BlobMetaChunkArray::V2(v) => v[index].is_lz4_block(),
+        }
+    }
+
+    fn generation(&self, index: usize) -> u32 {
+        match self {
+            BlobMetaChunkArray::V1(v) => v[index].get_generation(),
+            BlobMetaChunkArray::V2(v) => v[index].get_generation(),
+        }
+    }
+
     fn _get_chunk_index_nocheck<T: BlobMetaChunkInfo>(
         state: &BlobCompressionContext,
         chunks: &[T],
@@ -1868,6 +1940,17 @@ impl BlobChunkInfo for BlobMetaChunk {
         self.meta.chunk_info_array.is_encrypted(self.chunk_index)
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.meta
+            .chunk_info_array
+            .is_lz4_block(self.chunk_index)
+            .then_some(compress::Algorithm::Lz4Block)
+    }
+
+    fn generation(&self) -> u32 {
+        self.meta.chunk_info_array.generation(self.chunk_index)
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
@@ -1888,6 +1971,9 @@ impl BlobV5ChunkInfo for BlobMetaChunk {
         if self.is_compressed() {
             flags |= BlobChunkFlags::COMPRESSED;
         }
+        if self.compressor().is_some() {
+            flags |= BlobChunkFlags::LZ4_BLOCK;
+        }
         flags
     }
 
@@ -1946,6 +2032,11 @@ pub trait BlobMetaChunkInfo {
     /// compressed.
     fn is_compressed(&self) -> bool;
 
+    /// Check whether the chunk is compressed by lz4_block instead of the blob compressor.
+    fn is_lz4_block(&self) -> bool {
+        false
+    }
+
     /// Check whether the chunk has associated Batch context data.
     fn is_batch(&self) -> bool;
 
@@ -1967,6 +2058,16 @@ pub trait BlobMetaChunkInfo {
     /// Get data associated with the entry. V2 only, V1 just returns zero.
     fn get_data(&self) -> u64;
 
+    /// Get generation of the chunk, stored in associated data of V2 chunks without ZRan or Batch
+    /// context. Zero means no generation information.
+    fn get_generation(&self) -> u32 {
+        if self.is_zran() || self.is_batch() {
+            0
+        } else {
+            self.get_data() as u32
+        }
+    }
+
     /// Check whether the chunk compression information is valid or not.
     fn validate(&self, state: &BlobCompressionContext) -> Result<()>;
 }
@@ -2449,4 +2550,166 @@ pub(crate) mod tests {
         let chunk_ids: Vec<_> = chunks.iter().map(|c| c.id()).collect();
         assert_eq!(chunk_ids, vec![0, 1, 2]);
     }
+
+    #[test]
+    fn test_load_separate_blob_meta_by_one_request() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use vmm_sys_util::tempdir::TempDir;
+
+        struct CountingReader {
+            data: Vec<u8>,
+            requests: AtomicUsize,
+            bytes: AtomicUsize,
+            metrics: Arc<BackendMetrics>,
+        }
+
+        impl BlobReader for CountingReader {
+            fn blob_size(&self) -> BackendResult<u64> {
+                Ok(self.data.len() as u64)
+            }
+
+            fn try_read(&self, buf: &mut [u8], offset: u64) -> BackendResult<usize> {
+                let offset = offset as usize;
+                let sz = std::cmp::min(buf.len(), self.data.len().saturating_sub(offset));
+                buf[..sz].copy_from_slice(&self.data[offset..offset + sz]);
+                self.requests.fetch_add(1, Ordering::Relaxed);
+                self.bytes.fetch_add(sz, Ordering::Relaxed);
+                Ok(sz)
+            }
+
+            fn metrics(&self) -> &BackendMetrics {
+                &self.metrics
+            }
+        }
+
+        // Build a blob with separate metadata: chunk data, chunk information array and its
+        // header, chunk digest array, an optional gap for other data and then the ToC.
+        let chunk_count = 256u32;
+        let new_blob = |gap: usize| {
+            let chunks: Vec<BlobChunkInfoV1Ondisk> = (0..chunk_count as u64)
+                .map(|i| {
+                    let mut chunk = BlobChunkInfoV1Ondisk::default();
+                    chunk.set_compressed_offset(i * 0x10);
+                    chunk.set_compressed_size(0x10);
+                    chunk.set_uncompressed_offset(i * 0x1000);
+                    chunk.set_uncompressed_size(0x1000);
+                    chunk
+                })
+                .collect();
+            let ci = unsafe {
+                std::slice::from_raw_parts(
+                    chunks.as_ptr() as *const u8,
+                    chunks.len() * size_of::<BlobChunkInfoV1Ondisk>(),
+                )
+            };
+            let (compressed, _) = compress::compress(ci, compress::Algorithm::Lz4Block).unwrap();
+            let mut data = vec![0u8; chunk_count as usize * 0x10];
+            let ci_offset = data.len() as u64;
+            let mut header = BlobCompressionContextHeader::default();
+            header.set_ci_compressor(compress::Algorithm::Lz4Block);
+            header.set_ci_entries(chunk_count);
+            header.set_ci_compressed_offset(ci_offset);
+            header.set_ci_compressed_size(compressed.len() as u64);
+            header.set_ci_uncompressed_size(ci.len() as u64);
+            data.extend_from_slice(&compressed);
+            data.extend_from_slice(header.as_bytes());
+            data.extend_from_slice(&[0u8; BLOB_TAR_HEADER_SIZE as usize]);
+
+            let digests = vec![0x5au8; chunk_count as usize * 32];
+            let digest_offset = data.len() as u64;
+            data.extend_from_slice(&digests);
+            data.extend_from_slice(&[0u8; BLOB_TAR_HEADER_SIZE as usize]);
+            data.extend_from_slice(&vec![0u8; gap]);
+
+            let mut toc = TocEntryList::new();
+            toc.add(
+                toc::TOC_ENTRY_BLOB_DIGEST,
+                compress::Algorithm::None,
+                RafsDigest::from_buf(&digests, digest::Algorithm::Sha256),
+                digest_offset,
+                digests.len() as u64,
+                digests.len() as u64,
+            )
+            .unwrap();
+            let mut tar_header = tar::Header::new_gnu();
+            tar_header.set_path(toc::TOC_ENTRY_BLOB_TOC).unwrap();
+            tar_header.set_entry_type(tar::EntryType::Regular);
+            tar_header.set_size(toc.as_bytes().len() as u64);
+            tar_header.set_cksum();
+            data.extend_from_slice(toc.as_bytes());
+            data.extend_from_slice(tar_header.as_bytes());
+
+            let mut blob_info = BlobInfo::new(
+                0,
+                "separate".to_string(),
+                chunk_count as u64 * 0x1000,
+                ci_offset,
+                0x1000,
+                chunk_count,
+                BlobFeatures::SEPARATE | BlobFeatures::INLINED_CHUNK_DIGEST,
+            );
+            blob_info.set_blob_meta_info(
+                ci_offset,
+                compressed.len() as u64,
+                ci.len() as u64,
+                compress::Algorithm::Lz4Block as u32,
+            );
+            blob_info.set_blob_toc_size(toc.as_bytes().len() as u32 + BLOB_TAR_HEADER_SIZE as u32);
+            let reader = Arc::new(CountingReader {
+                data,
+                requests: AtomicUsize::new(0),
+                bytes: AtomicUsize::new(0),
+                metrics: BackendMetrics::new("separate", "mock"),
+            });
+            (blob_info, reader)
+        };
+
+        // All metadata is fetched from the backend by a single request.
+        let tmp_dir = TempDir::new().unwrap();
+        let blob_path = tmp_dir.as_path().join("separate");
+        let blob_path = blob_path.to_str().unwrap();
+        let (blob_info, reader) = new_blob(0);
+        let backend: Arc<dyn BlobReader> = reader.clone();
+        let info =
+            BlobCompressionContextInfo::new(blob_path, &blob_info, Some(&backend), true).unwrap();
+        assert_eq!(info.get_chunk_count(), chunk_count as usize);
+        assert_eq!(reader.requests.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            reader.bytes.load(Ordering::Relaxed) as u64,
+            reader.data.len() as u64 - blob_info.meta_ci_offset()
+        );
+
+        // Cached metadata is reused without touching the backend.
+        let info =
+            BlobCompressionContextInfo::new(blob_path, &blob_info, Some(&backend), true).unwrap();
+        assert_eq!(info.get_chunk_count(), chunk_count as usize);
+        assert_eq!(reader.requests.load(Ordering::Relaxed), 1);
+
+        // Data between the chunk digest array and the ToC is fetched by the same request.
+        let tmp_dir = TempDir::new().unwrap();
+        let blob_path = tmp_dir.as_path().join("separate");
+        let blob_path = blob_path.to_str().unwrap();
+        let (blob_info, reader) = new_blob(0x100000);
+        let backend: Arc<dyn BlobReader> = reader.clone();
+        let info =
+            BlobCompressionContextInfo::new(blob_path, &blob_info, Some(&backend), true).unwrap();
+        assert_eq!(info.get_chunk_count(), chunk_count as usize);
+        assert_eq!(reader.requests.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            reader.bytes.load(Ordering::Relaxed) as u64,
+            reader.data.len() as u64 - blob_info.meta_ci_offset()
+        );
+
+        // Metadata overlapping with the ToC is rejected.
+        let tmp_dir = TempDir::new().unwrap();
+        let blob_path = tmp_dir.as_path().join("separate");
+        let blob_path = blob_path.to_str().unwrap();
+        let (mut blob_info, reader) = new_blob(0);
+        blob_info.set_blob_toc_size(blob_info.blob_toc_size() + 0x1000);
+        let backend: Arc<dyn BlobReader> = reader.clone();
+        assert!(
+            BlobCompressionContextInfo::new(blob_path, &blob_info, Some(&backend), true).is_err()
+        );
+        assert_eq!(reader.requests.load(Ordering::Relaxed), 0);
+    }
 }
@@ -19,6 +19,7 @@ use serde::Serialize;
 use tar::{EntryType, Header};
 
 use crate::backend::{BlobBufReader, BlobReader};
+use crate::device::BlobInfo;
 use crate::factory::BlobFactory;
 use crate::utils::alloc_buf;
 
@@ -417,6 +418,80 @@ impl TocEntryList {
         }
     }
 
+    /// Read a [TocEntryList] from cache file or storage backend, and validate its content.
+    ///
+    /// Any failure is reported as an invalid ToC error, so a corrupted ToC gets caught before
+    /// it's used to locate data in the blob.
+    pub fn read_and_validate<P: AsRef<Path>>(
+        path: P,
+        reader: &dyn BlobReader,
+        location: &TocLocation,
+        blob_size: u64,
+    ) -> Result<Self> {
+        let toc = Self::read_from_cache_file(path.as_ref(), reader, location)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("blob ToC invalid, {}", e)))?;
+        if let Err(e) = toc.validate(blob_size) {
+            // Don't keep the corrupted ToC in the cache file.
+            let _ = fs::remove_file(path.as_ref());
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("blob ToC invalid, {}", e),
+            ));
+        }
+
+        Ok(toc)
+    }
+
+    /// Validate consistency of ToC entries against size of the blob.
+    ///
+    /// ToC entries should be ordered by offset, and all of them should be located before the ToC.
+    pub fn validate(&self, blob_size: u64) -> Result<()> {
+        let toc_offset = blob_size.checked_sub(self.toc_size as u64).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "ToC size 0x{:x} is bigger than blob size 0x{:x}",
+                    self.toc_size, blob_size
+                ),
+            )
+        })?;
+        let mut last_offset = 0;
+        for entry in self.entries.iter() {
+            let name = entry.name()?;
+            entry.compressor()?;
+            let end = entry
+                .compressed_offset
+                .checked_add(entry.compressed_size)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid size of ToC entry {}", name),
+                    )
+                })?;
+            if entry.compressed_offset < last_offset {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "ToC entry {} at 0x{:x} is before previous entry at 0x{:x}",
+                        name, entry.compressed_offset, last_offset
+                    ),
+                ));
+            }
+            if end > toc_offset {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "ToC entry {} ending at 0x{:x} exceeds ToC offset 0x{:x}",
+                        name, end, toc_offset
+                    ),
+                ));
+            }
+            last_offset = entry.compressed_offset;
+        }
+
+        Ok(())
+    }
+
     fn read_toc_header(reader: &dyn BlobReader, location: &TocLocation) -> Result<(Vec<u8>, u64)> {
         location.validate()?;
         let (offset, size) = if location.auto_detect {
@@ -735,6 +810,24 @@ impl TocLocation {
         }
     }
 
+    /// Create a [TocLocation] object for the ToC recorded in the blob information.
+    pub fn from_blob_info(blob_info: &BlobInfo, blob_size: u64) -> Result<Self> {
+        if blob_info.blob_toc_size() == 0 {
+            return Ok(TocLocation::default());
+        }
+        let offset = blob_size
+            .checked_sub(blob_info.blob_toc_size() as u64)
+            .ok_or_else(|| einval!("blob ToC size is bigger than blob size"))?;
+        let mut location = TocLocation::new(offset, blob_info.blob_toc_size() as u64);
+        let digest = blob_info.blob_toc_digest();
+        if digest.iter().any(|c| *c != 0) {
+            location.validate_digest = true;
+            location.digest.data = *digest;
+        }
+
+        Ok(location)
+    }
+
     fn validate(&self) -> Result<()> {
         if !self.auto_detect && (!(512..=0x10000).contains(&self.size) || self.size % 128 != 0) {
             return Err(eother!(format!("invalid size {} of blob ToC", self.size)));
@@ -749,6 +842,7 @@ mod tests {
     use super::*;
     use crate::factory::BlobFactory;
     use nydus_api::{BackendConfigV2, LocalFsConfig};
+    use vmm_sys_util::tempdir::TempDir;
     use vmm_sys_util::tempfile::TempFile;
 
     #[test]
@@ -774,6 +868,11 @@ mod tests {
             registry: None,
             s3: None,
             http_proxy: None,
+            fallback: None,
+            hedge: None,
+            min_read_size: 0,
+            read_timeout_ms: 0,
+            packed: None,
         };
         let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
         let blob = blob_mgr.get_reader(id).unwrap();
@@ -834,6 +933,11 @@ mod tests {
             registry: None,
             s3: None,
             http_proxy: None,
+            fallback: None,
+            hedge: None,
+            min_read_size: 0,
+            read_timeout_ms: 0,
+            packed: None,
             localdisk: None,
         };
         let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
@@ -870,6 +974,11 @@ mod tests {
             s3: None,
             localdisk: None,
             http_proxy: None,
+            fallback: None,
+            hedge: None,
+            min_read_size: 0,
+            read_timeout_ms: 0,
+            packed: None,
         };
         let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
         let blob = blob_mgr.get_reader(id).unwrap();
@@ -894,6 +1003,65 @@ mod tests {
         assert_eq!(path.metadata().unwrap().len(), 20480);
     }
 
+    #[test]
+    fn test_read_and_validate_toc() {
+        let root_dir = &std::env::var("CARGO_MANIFEST_DIR").expect("$CARGO_MANIFEST_DIR");
+        let id = "2fa78cad554b75ac91a4a125ed148d0ddeb25efa4aaa8bd80e5dc292690a4dca";
+        let src = Path::new(root_dir).join("../tests/texture/toc").join(id);
+        let dir = TempDir::new().unwrap();
+        let mut data = fs::read(src).unwrap();
+        let blob_size = data.len() as u64;
+        // Corrupt offset of the second ToC entry, which starts at 9010 + 128.
+        data[9010 + 128 + 56..9010 + 128 + 64].copy_from_slice(&0x10000u64.to_le_bytes());
+        fs::write(dir.as_path().join(id), &data).unwrap();
+        let config = BackendConfigV2 {
+            backend_type: "localfs".to_string(),
+            localfs: Some(LocalFsConfig {
+                blob_file: "".to_string(),
+                dir: dir.as_path().to_str().unwrap().to_string(),
+                alt_dirs: vec![],
+            }),
+            oss: None,
+            registry: None,
+            s3: None,
+            localdisk: None,
+            http_proxy: None,
+            fallback: None,
+            hedge: None,
+            min_read_size: 0,
+            read_timeout_ms: 0,
+            packed: None,
+        };
+        let blob_mgr = BlobFactory::new_backend(&config, id).unwrap();
+        let blob = blob_mgr.get_reader(id).unwrap();
+
+        let location = TocLocation::new(9010, 1024);
+        let list =
+            TocEntryList::read_from_blob::<fs::File>(blob.as_ref(), None, &location).unwrap();
+        assert!(list.validate(blob_size).is_err());
+        let toc_path = dir.as_path().join("toc");
+        let err = TocEntryList::read_and_validate(&toc_path, blob.as_ref(), &location, blob_size)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("blob ToC invalid"));
+
+        let location = TocLocation::with_digest(9010, 1024, RafsDigest::default());
+        let err = TocEntryList::read_and_validate(&toc_path, blob.as_ref(), &location, blob_size)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("blob ToC invalid"));
+
+        let orig = fs::read(Path::new(root_dir).join("../tests/texture/toc").join(id)).unwrap();
+        fs::write(dir.as_path().join(id), orig).unwrap();
+        let location = TocLocation::new(9010, 1024);
+        let list = TocEntryList::read_and_validate(&toc_path, blob.as_ref(), &location, blob_size)
+            .unwrap();
+        assert_eq!(list.entries.len(), 4);
+        assert!(list.validate(list.toc_size() as u64 + 0x1000).is_err());
+        assert!(list.validate(0x100).is_err());
+    }
+
     #[test]
     fn test_toc_entry_flags() {
         let flags = TocEntryFlags::try_from(compress::Algorithm::None).unwrap();
@@ -5,6 +5,7 @@
 
 use std::sync::Arc;
 
+use nydus_utils::compress;
 use nydus_utils::digest::RafsDigest;
 use nydus_utils::metrics::BackendMetrics;
 
@@ -66,6 +67,7 @@ pub(crate) struct MockChunkInfo {
     pub uncompress_offset: u64,
     pub file_offset: u64,
     pub index: u32,
+    pub generation: u32,
     #[allow(unused)]
     pub reserved: u32,
 }
@@ -97,6 +99,16 @@ impl BlobChunkInfo for MockChunkInfo {
         false
     }
 
+    fn compressor(&self) -> Option<compress::Algorithm> {
+        self.flags
+            .contains(BlobChunkFlags::LZ4_BLOCK)
+            .then_some(compress::Algorithm::Lz4Block)
+    }
+
+    fn generation(&self) -> u32 {
+        self.generation
+    }
+
     fn as_any(&self) -> &dyn Any {
         self
     }
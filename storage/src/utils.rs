@@ -5,15 +5,21 @@
 //! Utility helpers to support the storage subsystem.
 use std::alloc::{alloc, Layout};
 use std::cmp::{self, min};
+use std::collections::HashMap;
+use std::fs::File;
 use std::io::{ErrorKind, IoSliceMut, Result};
 use std::os::unix::io::RawFd;
 use std::slice::from_raw_parts_mut;
+use std::sync::Mutex;
 
 use fuse_backend_rs::abi::fuse_abi::off64_t;
 use fuse_backend_rs::file_buf::FileVolatileSlice;
+use lazy_static::lazy_static;
 #[cfg(target_os = "macos")]
 use libc::{fcntl, radvisory};
+use nix::sys::statfs::{fstatfs, TMPFS_MAGIC};
 use nix::sys::uio::preadv;
+use nydus_utils::filemap::FileMapState;
 use nydus_utils::{
     digest::{self, RafsDigest},
     round_down_4k,
@@ -34,6 +40,27 @@ pub fn readv(fd: RawFd, iovec: &mut [IoSliceMut], offset: u64) -> Result<usize>
     }
 }
 
+/// Read from a memory mapped file region into `iovec`, as `readv()` does with a file descriptor.
+pub fn readv_mapped(map: &FileMapState, iovec: &mut [IoSliceMut], offset: u64) -> Result<usize> {
+    let size = iovec.iter().map(|v| v.len()).sum();
+    let src = map.get_slice::<u8>(offset as usize, size)?;
+    let mut pos = 0;
+    for v in iovec.iter_mut() {
+        let len = v.len();
+        v.copy_from_slice(&src[pos..pos + len]);
+        pos += len;
+    }
+    Ok(size)
+}
+
+/// Check whether the file is stored in memory, such as on tmpfs.
+pub fn is_memory_backed_file(file: &File) -> bool {
+    match fstatfs(file) {
+        Ok(st) => st.filesystem_type() == TMPFS_MAGIC,
+        Err(_) => false,
+    }
+}
+
 /// Copy from buffer slice to another buffer slice.
 ///
 /// `offset` is where to start copy in the first buffer of source slice.
@@ -221,9 +248,40 @@ pub fn readahead(fd: libc::c_int, mut offset: u64, end: u64) {
     }
 }
 
+/// Deallocate space of the range [offset, offset + len) in the file without changing file size.
+#[cfg(target_os = "linux")]
+pub fn punch_hole(fd: libc::c_int, offset: u64, len: u64) -> Result<()> {
+    let ret = unsafe {
+        libc::fallocate(
+            fd,
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if ret < 0 {
+        Err(last_error!())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn punch_hole(_fd: libc::c_int, _offset: u64, _len: u64) -> Result<()> {
+    Err(enosys!())
+}
+
+#[cfg(test)]
+thread_local! {
+    // Bytes of buffers allocated by `alloc_buf()` in the current thread.
+    pub(crate) static ALLOCATED_BYTES: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
 /// A customized buf allocator that avoids zeroing
 pub fn alloc_buf(size: usize) -> Vec<u8> {
     assert!(size < isize::MAX as usize);
+    #[cfg(test)]
+    ALLOCATED_BYTES.with(|v| v.set(v.get() + size));
     let layout = Layout::from_size_align(size, 0x1000)
         .unwrap()
         .pad_to_align();
@@ -236,10 +294,63 @@ pub fn check_digest(data: &[u8], digest: &RafsDigest, digester: digest::Algorith
     digest == &RafsDigest::from_buf(data, digester)
 }
 
+lazy_static! {
+    // Digests of all-zero chunks, indexed by chunk size and digest algorithm.
+    static ref ZERO_CHUNK_DIGESTS: Mutex<HashMap<(u32, digest::Algorithm), RafsDigest>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Check whether `digest` is the digest of an all-zero chunk of `size` bytes.
+pub fn is_zero_chunk_digest(digest: &RafsDigest, size: u32, digester: digest::Algorithm) -> bool {
+    let mut digests = ZERO_CHUNK_DIGESTS.lock().unwrap();
+    let zero_digest = digests
+        .entry((size, digester))
+        .or_insert_with(|| RafsDigest::from_buf(&vec![0u8; size as usize], digester));
+    digest == zero_digest
+}
+
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
+
+    use vmm_sys_util::tempfile::TempFile;
+
     use super::*;
 
+    #[test]
+    fn test_readv_mapped() {
+        let tmp_file = TempFile::new().unwrap();
+        let mut file = tmp_file.into_file();
+        let data: Vec<u8> = (0..0x2000u32).map(|v| v as u8).collect();
+        file.write_all(&data).unwrap();
+        let map = FileMapState::new(file, 0, data.len(), false).unwrap();
+
+        let mut buf1 = vec![0u8; 0x10];
+        let mut buf2 = vec![0u8; 0x100];
+        let mut iovec = vec![IoSliceMut::new(&mut buf1), IoSliceMut::new(&mut buf2)];
+        assert_eq!(readv_mapped(&map, &mut iovec, 0x1000).unwrap(), 0x110);
+        assert_eq!(buf1, data[0x1000..0x1010]);
+        assert_eq!(buf2, data[0x1010..0x1110]);
+
+        let mut buf3 = vec![0u8; 0x10];
+        let mut iovec = vec![IoSliceMut::new(&mut buf3)];
+        assert!(readv_mapped(&map, &mut iovec, 0x1ff8).is_err());
+    }
+
+    #[test]
+    fn test_is_memory_backed_file() {
+        let file = File::open("/proc").unwrap();
+        assert!(!is_memory_backed_file(&file));
+        let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+        if mounts
+            .lines()
+            .any(|l| l.starts_with("tmpfs /dev/shm tmpfs "))
+        {
+            let file = File::open("/dev/shm").unwrap();
+            assert!(is_memory_backed_file(&file));
+        }
+    }
+
     #[test]
     fn test_copyv() {
         let mut dst_buf1 = vec![0x0u8; 4];
@@ -6,6 +6,11 @@ use std::io::Result;
 use libc::c_char;
 use lz4_sys::{LZ4_compressBound, LZ4_compress_default, LZ4_decompress_safe};
 
+// Same as `LZ4_COMPRESSBOUND()`, the maximum size of compressed data in the worst case.
+pub(super) fn lz4_compress_bound(size: usize) -> usize {
+    size + size / 255 + 16
+}
+
 pub(super) fn lz4_compress(src: &[u8]) -> Result<Vec<u8>> {
     // 0 iff src too large
     let compress_bound: i32 = unsafe { LZ4_compressBound(src.len() as i32) };
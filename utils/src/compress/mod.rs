@@ -5,16 +5,35 @@
 use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::fmt;
+use std::fs::File;
 use std::io::{BufReader, Error, Read, Result, Write};
 use std::str::FromStr;
 
 mod lz4_standard;
 use self::lz4_standard::*;
+use crate::FileRangeReader;
 
 #[cfg(feature = "zran")]
 pub mod zlib_random;
 
 const COMPRESSION_MINIMUM_RATIO: usize = 100;
+// Maximum number of bytes sampled to estimate entropy of a data chunk.
+const ENTROPY_SAMPLE_SIZE: usize = 0x4000;
+// Maximum number of bytes sampled to estimate compression ratio of data.
+const RATIO_SAMPLE_SIZE: usize = 0x10000;
+/// Entropy (bits per byte) above which data is considered already compressed.
+pub const INCOMPRESSIBLE_ENTROPY_THRESHOLD: f64 = 7.5;
+/// Entropy (bits per byte) above which data compresses poorly, so a fast algorithm is preferred.
+pub const LOW_RATIO_ENTROPY_THRESHOLD: f64 = 6.0;
+// Size of internal buffers of brotli encoders and decoders.
+#[cfg(feature = "brotli")]
+const BROTLI_BUFFER_SIZE: usize = 0x1000;
+// Brotli compression quality, level 11 is too slow to build images.
+#[cfg(feature = "brotli")]
+const BROTLI_QUALITY: u32 = 9;
+// Brotli sliding window of 4MB, big enough for any chunk.
+#[cfg(feature = "brotli")]
+const BROTLI_WINDOW_BITS: u32 = 22;
 
 /// Supported compression algorithms.
 #[repr(u32)]
@@ -25,6 +44,7 @@ pub enum Algorithm {
     Lz4Block = 1,
     GZip = 2,
     Zstd = 3,
+    Brotli = 4,
 }
 
 impl fmt::Display for Algorithm {
@@ -42,6 +62,7 @@ impl FromStr for Algorithm {
             "lz4_block" => Ok(Self::Lz4Block),
             "gzip" => Ok(Self::GZip),
             "zstd" => Ok(Self::Zstd),
+            "brotli" => Ok(Self::Brotli),
             _ => Err(einval!("compression algorithm should be none or lz4_block")),
         }
     }
@@ -59,6 +80,8 @@ impl TryFrom<u32> for Algorithm {
             Ok(Algorithm::GZip)
         } else if value == Algorithm::Zstd as u32 {
             Ok(Algorithm::Zstd)
+        } else if value == Algorithm::Brotli as u32 {
+            Ok(Algorithm::Brotli)
         } else {
             Err(())
         }
@@ -77,6 +100,8 @@ impl TryFrom<u64> for Algorithm {
             Ok(Algorithm::GZip)
         } else if value == Algorithm::Zstd as u64 {
             Ok(Algorithm::Zstd)
+        } else if value == Algorithm::Brotli as u64 {
+            Ok(Algorithm::Brotli)
         } else {
             Err(())
         }
@@ -107,6 +132,7 @@ pub fn compress(src: &[u8], algorithm: Algorithm) -> Result<(Cow<[u8]>, bool)> {
             gz.finish()?
         }
         Algorithm::Zstd => zstd_compress(src)?,
+        Algorithm::Brotli => brotli_compress(src)?,
     };
 
     // Abandon compressed data when compression ratio greater than COMPRESSION_MINIMUM_RATIO
@@ -119,8 +145,7 @@ pub fn compress(src: &[u8], algorithm: Algorithm) -> Result<(Cow<[u8]>, bool)> {
     }
 }
 
-/// Decompress a source slice or file stream into destination slice, with provided compression algorithm.
-/// Use the file as decompress source if provided.
+/// Decompress a source slice into destination slice, with provided compression algorithm.
 pub fn decompress(src: &[u8], dst: &mut [u8], algorithm: Algorithm) -> Result<usize> {
     match algorithm {
         Algorithm::None => {
@@ -130,20 +155,53 @@ pub fn decompress(src: &[u8], dst: &mut [u8], algorithm: Algorithm) -> Result<us
         }
         Algorithm::Lz4Block => lz4_decompress(src, dst),
         Algorithm::GZip => {
-            let mut gz = flate2::bufread::GzDecoder::new(src);
+            // Chunk data may consist of multiple concatenated gzip members.
+            let mut gz = flate2::bufread::MultiGzDecoder::new(src);
             gz.read_exact(dst)?;
             Ok(dst.len())
         }
-        Algorithm::Zstd => zstd::bulk::decompress_to_buffer(src, dst),
+        Algorithm::Zstd => {
+            // The compressed size may be estimated, so ignore data following the first frame.
+            let src = match zstd::zstd_safe::find_frame_compressed_size(src) {
+                Ok(size) if size < src.len() => &src[..size],
+                _ => src,
+            };
+            zstd::bulk::decompress_to_buffer(src, dst)
+        }
+        Algorithm::Brotli => brotli_decompress(src, dst),
+    }
+}
+
+/// Decompress `size` bytes of compressed data at `offset` of `file` into destination slice.
+///
+/// Compressed data is streamed from the file instead of being read into memory altogether, except
+/// for lz4_block, which can only be decompressed as a whole block.
+pub fn decompress_file(
+    file: &File,
+    offset: u64,
+    size: u64,
+    dst: &mut [u8],
+    algorithm: Algorithm,
+) -> Result<usize> {
+    let mut reader = FileRangeReader::new(file, offset, size);
+    if algorithm == Algorithm::Lz4Block {
+        let mut src = vec![0u8; size as usize];
+        reader.read_exact(&mut src)?;
+        decompress(&src, dst, algorithm)
+    } else {
+        Decoder::new(reader, algorithm)?.read_exact(dst)?;
+        Ok(dst.len())
     }
 }
 
 #[allow(clippy::large_enum_variant)]
-/// Stream decoder for gzip/lz4/zstd.
+/// Stream decoder for gzip/lz4/zstd/brotli.
 pub enum Decoder<'a, R: Read> {
     None(R),
     Gzip(flate2::bufread::MultiGzDecoder<BufReader<R>>),
     Zstd(zstd::stream::Decoder<'a, BufReader<R>>),
+    #[cfg(feature = "brotli")]
+    Brotli(brotli::Decompressor<R>),
 }
 
 impl<'a, R: Read> Decoder<'a, R> {
@@ -156,6 +214,12 @@ impl<'a, R: Read> Decoder<'a, R> {
             }
             Algorithm::Lz4Block => panic!("Decoder doesn't support lz4_block"),
             Algorithm::Zstd => Decoder::Zstd(zstd::stream::Decoder::new(reader)?),
+            #[cfg(feature = "brotli")]
+            Algorithm::Brotli => {
+                Decoder::Brotli(brotli::Decompressor::new(reader, BROTLI_BUFFER_SIZE))
+            }
+            #[cfg(not(feature = "brotli"))]
+            Algorithm::Brotli => return Err(brotli_unsupported()),
         };
         Ok(decoder)
     }
@@ -167,6 +231,8 @@ impl<'a, R: Read> Read for Decoder<'a, R> {
             Decoder::None(r) => r.read(buf),
             Decoder::Gzip(r) => r.read(buf),
             Decoder::Zstd(r) => r.read(buf),
+            #[cfg(feature = "brotli")]
+            Decoder::Brotli(r) => r.read(buf),
         }
     }
 }
@@ -191,6 +257,9 @@ impl<R: Read> Read for ZlibDecoder<R> {
     }
 }
 
+// Maximum size of header and footer of a gzip member, including the bgzip extra field.
+const GZIP_MEMBER_OVERHEAD: usize = 10 + 8 + 2 + 6;
+
 /// Estimate the maximum compressed data size from uncompressed data size.
 ///
 /// Gzip is special that it doesn't carry compress_size. We need to read the maximum possible size
@@ -240,15 +309,160 @@ pub fn compute_compressed_gzip_size(size: usize, max_size: usize) -> usize {
     std::cmp::min(size, max_size)
 }
 
+/// Estimate the maximum size of gzip data made up of concatenated members.
+///
+/// Tools like pigz and bgzip concatenate multiple gzip members, each member carrying its own
+/// header and footer, and bgzip adds a 6-byte extra field to the header. It reserves space for
+/// one member every 16K block on top of `compute_compressed_gzip_size()`, so data of concatenated
+/// members is read as a whole.
+///
+/// It's only used when reading chunk data. The stargz builder keeps using
+/// `compute_compressed_gzip_size()`, because the estimate ends up in RAFS metadata.
+pub fn compute_compressed_gzip_members_size(size: usize, max_size: usize) -> usize {
+    let blocks = size / (16 << 10);
+    let size = size + 5 + blocks * 5 + (blocks + 1) * GZIP_MEMBER_OVERHEAD + 128;
+
+    std::cmp::min(size, max_size)
+}
+
+/// Estimate the maximum size of zstd compressed data for `size` bytes of uncompressed data.
+pub fn compute_compressed_zstd_size(size: usize, max_size: usize) -> usize {
+    std::cmp::min(zstd::zstd_safe::compress_bound(size), max_size)
+}
+
+/// Estimate the maximum size of data compressed by `algorithm` for `size` bytes of uncompressed
+/// data, which is enough for buffers to hold the compressed data.
+pub fn compute_compressed_size(size: usize, algorithm: Algorithm) -> usize {
+    match algorithm {
+        Algorithm::None => size,
+        Algorithm::Lz4Block => lz4_compress_bound(size),
+        Algorithm::GZip => compute_compressed_gzip_members_size(size, usize::MAX),
+        Algorithm::Zstd => compute_compressed_zstd_size(size, usize::MAX),
+        Algorithm::Brotli => brotli_compress_bound(size),
+    }
+}
+
+/// Estimate the compression ratio, size of compressed data divided by size of original data, of
+/// data compressed by `algorithm`.
+///
+/// Only the leading `RATIO_SAMPLE_SIZE` bytes of `sample` are compressed to keep the estimation
+/// cheap, and the ratio is extrapolated to the whole data. The ratio never exceeds 1.0 because
+/// data not shrinking after compression is stored as is.
+pub fn estimate_ratio(sample: &[u8], algorithm: Algorithm) -> f64 {
+    let sample = &sample[..std::cmp::min(sample.len(), RATIO_SAMPLE_SIZE)];
+    if sample.is_empty() || algorithm.is_none() {
+        return 1.0;
+    }
+
+    match compress(sample, algorithm) {
+        Ok((data, true)) => data.len() as f64 / sample.len() as f64,
+        Ok((_, false)) => 1.0,
+        Err(e) => {
+            warn!("failed to compress data sample with {}, {}", algorithm, e);
+            1.0
+        }
+    }
+}
+
+/// Estimate the Shannon entropy, in bits per byte, of a data slice.
+///
+/// Only the leading `ENTROPY_SAMPLE_SIZE` bytes are sampled to keep the estimation cheap.
+pub fn estimate_entropy(data: &[u8]) -> f64 {
+    let sample = &data[..std::cmp::min(data.len(), ENTROPY_SAMPLE_SIZE)];
+    if sample.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0u32; 256];
+    for b in sample {
+        counts[*b as usize] += 1;
+    }
+    let total = sample.len() as f64;
+    counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Select the compression algorithm for a data chunk based on its estimated entropy.
+///
+/// Data with entropy above `threshold` is most likely already compressed or encrypted, so it's
+/// stored as is. Data with entropy above `LOW_RATIO_ENTROPY_THRESHOLD` compresses poorly, so the
+/// fast `Lz4Block` algorithm is used, otherwise the high ratio `algorithm` is used.
+pub fn select_algorithm(data: &[u8], algorithm: Algorithm, threshold: f64) -> Algorithm {
+    let entropy = estimate_entropy(data);
+    if algorithm.is_none() || entropy > threshold {
+        Algorithm::None
+    } else if entropy > LOW_RATIO_ENTROPY_THRESHOLD {
+        Algorithm::Lz4Block
+    } else {
+        algorithm
+    }
+}
+
 fn zstd_compress(src: &[u8]) -> Result<Vec<u8>> {
     zstd::bulk::compress(src, zstd::DEFAULT_COMPRESSION_LEVEL)
 }
 
+// Same as BrotliEncoderMaxCompressedSize(), uncompressible data is stored in meta-blocks of 16K
+// with 4 bytes of header each, plus the stream header and the last empty meta-block.
+fn brotli_compress_bound(size: usize) -> usize {
+    if size == 0 {
+        2
+    } else {
+        size + 2 + 4 * (size >> 14) + 3 + 1
+    }
+}
+
+#[cfg(feature = "brotli")]
+fn brotli_compress(src: &[u8]) -> Result<Vec<u8>> {
+    // The window size is encoded in the stream header, so decoders don't need to know it.
+    let mut encoder = brotli::CompressorWriter::new(
+        Vec::with_capacity(brotli_compress_bound(src.len())),
+        BROTLI_BUFFER_SIZE,
+        BROTLI_QUALITY,
+        BROTLI_WINDOW_BITS,
+    );
+    encoder.write_all(src)?;
+    encoder.flush()?;
+    Ok(encoder.into_inner())
+}
+
+#[cfg(feature = "brotli")]
+fn brotli_decompress(src: &[u8], dst: &mut [u8]) -> Result<usize> {
+    // Data following the end of the brotli stream is never read.
+    brotli::Decompressor::new(src, BROTLI_BUFFER_SIZE).read_exact(dst)?;
+    Ok(dst.len())
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli_compress(_src: &[u8]) -> Result<Vec<u8>> {
+    Err(brotli_unsupported())
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli_decompress(_src: &[u8], _dst: &mut [u8]) -> Result<usize> {
+    Err(brotli_unsupported())
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli_unsupported() -> Error {
+    Error::new(
+        std::io::ErrorKind::Unsupported,
+        "brotli support is not enabled, rebuild with the `brotli` feature",
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::fs::OpenOptions;
     use std::io::{Seek, SeekFrom};
+    use std::os::unix::fs::FileExt;
     use std::path::Path;
     use vmm_sys_util::tempfile::TempFile;
 
@@ -276,6 +490,113 @@ mod tests {
         assert_eq!(buf, decompressed);
     }
 
+    #[test]
+    fn test_decompress_gzip_members() {
+        let buf: Vec<u8> = (0..0x10000u32)
+            .map(|i| ((i / 3) ^ (i >> 8)) as u8)
+            .collect();
+        let (first, _) = compress(&buf[..0x6000], Algorithm::GZip).unwrap();
+        let (second, _) = compress(&buf[0x6000..], Algorithm::GZip).unwrap();
+        let mut compressed = [first.as_ref(), second.as_ref()].concat();
+        let size = compressed.len();
+        assert!(compute_compressed_gzip_members_size(buf.len(), usize::MAX) >= size);
+
+        // The estimated size may cover data following the chunk.
+        compressed.extend_from_slice(&[0x5au8; 0x1000]);
+        let max_size = compute_compressed_gzip_members_size(buf.len(), compressed.len());
+        let mut decompressed = vec![0u8; buf.len()];
+        let sz = decompress(&compressed[..max_size], &mut decompressed, Algorithm::GZip).unwrap();
+        assert_eq!(sz, buf.len());
+        assert_eq!(decompressed, buf);
+
+        let mut tmp_file = TempFile::new().unwrap().into_file();
+        tmp_file.write_all(&compressed).unwrap();
+        let mut decompressed = vec![0u8; buf.len()];
+        decompress_file(
+            &tmp_file,
+            0,
+            size as u64,
+            &mut decompressed,
+            Algorithm::GZip,
+        )
+        .unwrap();
+        assert_eq!(decompressed, buf);
+    }
+
+    #[test]
+    fn test_decompress_file() {
+        // A large chunk of compressible but not uniform data.
+        let buf: Vec<u8> = (0..0x80_0000u32)
+            .map(|i| ((i / 7) ^ (i >> 12)) as u8)
+            .collect();
+        for algorithm in [
+            Algorithm::None,
+            Algorithm::Lz4Block,
+            Algorithm::GZip,
+            Algorithm::Zstd,
+        ] {
+            let (compressed, _) = compress(&buf, algorithm).unwrap();
+            let mut expected = vec![0u8; buf.len()];
+            decompress(&compressed, &mut expected, algorithm).unwrap();
+            assert_eq!(expected, buf);
+
+            let file = TempFile::new().unwrap().into_file();
+            file.write_all_at(&compressed, 0x1000).unwrap();
+            file.write_all_at(&[0xffu8; 0x1000], 0x1000 + compressed.len() as u64)
+                .unwrap();
+            let mut decompressed = vec![0u8; buf.len()];
+            let size = decompress_file(
+                &file,
+                0x1000,
+                compressed.len() as u64,
+                &mut decompressed,
+                algorithm,
+            )
+            .unwrap();
+            assert_eq!(size, buf.len());
+            assert!(decompressed == expected, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn test_select_algorithm_by_entropy() {
+        assert_eq!(estimate_entropy(&[]), 0.0);
+        assert_eq!(estimate_entropy(&[0x5u8; 4096]), 0.0);
+
+        let text = "The quick brown fox jumps over the lazy dog.\n".repeat(128);
+        assert!(estimate_entropy(text.as_bytes()) < LOW_RATIO_ENTROPY_THRESHOLD);
+        assert_eq!(
+            select_algorithm(
+                text.as_bytes(),
+                Algorithm::Zstd,
+                INCOMPRESSIBLE_ENTROPY_THRESHOLD
+            ),
+            Algorithm::Zstd
+        );
+
+        // Bytes with 7 bits of entropy.
+        let binary: Vec<u8> = (0..0x4000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8 & 0x7f)
+            .collect();
+        assert_eq!(
+            select_algorithm(&binary, Algorithm::Zstd, INCOMPRESSIBLE_ENTROPY_THRESHOLD),
+            Algorithm::Lz4Block
+        );
+        assert_eq!(
+            select_algorithm(&binary, Algorithm::None, INCOMPRESSIBLE_ENTROPY_THRESHOLD),
+            Algorithm::None
+        );
+
+        let random: Vec<u8> = (0..0x4000u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        assert!(estimate_entropy(&random) > INCOMPRESSIBLE_ENTROPY_THRESHOLD);
+        assert_eq!(
+            select_algorithm(&random, Algorithm::Zstd, INCOMPRESSIBLE_ENTROPY_THRESHOLD),
+            Algorithm::None
+        );
+    }
+
     #[test]
     fn test_compress_algorithm_none() {
         let buf = [
@@ -299,6 +620,62 @@ mod tests {
         assert_eq!(buf, decompressed);
     }
 
+    #[test]
+    fn test_compress_algorithm_zstd_estimated_size() {
+        let buf = vec![0x2u8; 4097];
+        let mut decompressed = vec![0; buf.len()];
+        let (compressed, _) = compress(&buf, Algorithm::Zstd).unwrap();
+        let size = compute_compressed_zstd_size(buf.len(), usize::MAX);
+        assert!(size >= compressed.len());
+        assert_eq!(compute_compressed_zstd_size(buf.len(), 16), 16);
+
+        // Data following the compressed frame should be ignored.
+        let mut padded = compressed.to_vec();
+        padded.resize(size, 0x5a);
+        let sz = decompress(&padded, decompressed.as_mut_slice(), Algorithm::Zstd).unwrap();
+        assert_eq!(sz, 4097);
+        assert_eq!(buf, decompressed);
+    }
+
+    #[test]
+    fn test_compress_estimated_size_and_ratio() {
+        let text = b"nydus image service compresses chunks of container images. "
+            .iter()
+            .cycle()
+            .take(0x30000)
+            .copied()
+            .collect::<Vec<u8>>();
+        let mut seed = 0x2545f491u32;
+        let random = (0..0x30000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect::<Vec<u8>>();
+
+        for algorithm in [
+            Algorithm::None,
+            Algorithm::Lz4Block,
+            Algorithm::GZip,
+            Algorithm::Zstd,
+        ] {
+            for data in [&text, &random, &vec![0u8; 0x30000], &vec![0x5au8; 1]] {
+                let (compressed, _) = compress(data, algorithm).unwrap();
+                assert!(compute_compressed_size(data.len(), algorithm) >= compressed.len());
+
+                let ratio = estimate_ratio(data, algorithm);
+                assert!(ratio > 0.0 && ratio <= 1.0);
+                assert!((ratio * data.len() as f64).ceil() as usize >= compressed.len());
+            }
+            assert_eq!(estimate_ratio(&[], algorithm), 1.0);
+        }
+        assert_eq!(estimate_ratio(&text, Algorithm::None), 1.0);
+        assert!(estimate_ratio(&text, Algorithm::Zstd) < 0.1);
+        assert_eq!(estimate_ratio(&random, Algorithm::Zstd), 1.0);
+    }
+
     #[test]
     fn test_compress_algorithm_lz4() {
         let buf = [
@@ -593,4 +970,81 @@ mod tests {
         assert!(!Algorithm::GZip.is_none());
         assert!(!Algorithm::Zstd.is_none());
     }
+
+    #[test]
+    fn test_algorithm_brotli() {
+        assert_eq!(Algorithm::from_str("brotli").unwrap(), Algorithm::Brotli);
+        assert_eq!(
+            Algorithm::try_from(Algorithm::Brotli as u32).unwrap(),
+            Algorithm::Brotli
+        );
+        assert_eq!(
+            Algorithm::try_from(Algorithm::Brotli as u64).unwrap(),
+            Algorithm::Brotli
+        );
+        assert_eq!(compute_compressed_size(0, Algorithm::Brotli), 2);
+        assert_eq!(
+            compute_compressed_size(0x8000, Algorithm::Brotli),
+            0x8000 + 14
+        );
+    }
+
+    #[cfg(not(feature = "brotli"))]
+    #[test]
+    fn test_compress_algorithm_brotli_disabled() {
+        let buf = vec![0x2u8; 4096];
+        let err = compress(&buf, Algorithm::Brotli).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        let mut decompressed = vec![0u8; 4096];
+        assert!(decompress(&buf, &mut decompressed, Algorithm::Brotli).is_err());
+        assert!(Decoder::new(buf.as_slice(), Algorithm::Brotli).is_err());
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_compress_algorithm_brotli() {
+        for size in [1usize, 2, 16, 4095, 4096, 4097, 0x10_0000] {
+            let buf: Vec<u8> = (0..size).map(|i| ((i / 7) ^ (i >> 9)) as u8).collect();
+            let (compressed, is_compressed) = compress(&buf, Algorithm::Brotli).unwrap();
+            assert!(compressed.len() <= compute_compressed_size(size, Algorithm::Brotli));
+            let algorithm = if is_compressed {
+                Algorithm::Brotli
+            } else {
+                Algorithm::None
+            };
+            let mut decompressed = vec![0u8; size];
+            let sz = decompress(&compressed, &mut decompressed, algorithm).unwrap();
+            assert_eq!(sz, size);
+            assert_eq!(buf, decompressed);
+        }
+
+        // Text compresses well, and data following the brotli stream is ignored.
+        let buf = "nydus image service ".repeat(0x200).into_bytes();
+        let (compressed, is_compressed) = compress(&buf, Algorithm::Brotli).unwrap();
+        assert!(is_compressed);
+        assert!(compressed.len() < buf.len() / 10);
+        let mut data = compressed.to_vec();
+        data.extend_from_slice(&[0xffu8; 0x100]);
+        let mut decompressed = vec![0u8; buf.len()];
+        decompress(&data, &mut decompressed, Algorithm::Brotli).unwrap();
+        assert_eq!(buf, decompressed);
+
+        // Streamed from files.
+        let mut tmp_file = TempFile::new().unwrap().into_file();
+        tmp_file.write_all(&data).unwrap();
+        let mut decompressed = vec![0u8; buf.len()];
+        decompress_file(
+            &tmp_file,
+            0,
+            compressed.len() as u64,
+            &mut decompressed,
+            Algorithm::Brotli,
+        )
+        .unwrap();
+        assert_eq!(buf, decompressed);
+
+        // Corrupted data is rejected.
+        let mut decompressed = vec![0u8; buf.len()];
+        assert!(decompress(&[0xffu8; 0x100], &mut decompressed, Algorithm::Brotli).is_err());
+    }
 }
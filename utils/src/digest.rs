@@ -3,7 +3,12 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-//! Fast message digest algorithms for Rafs and Nydus, including Blake3 and SHA256.
+//! Fast message digest algorithms for Rafs and Nydus, including Blake3, SHA256 and XXH64.
+//!
+//! XXH64 is a non-cryptographic hash, it only protects data against accidental corruption and
+//! should only be used for images from trusted build pipelines. Its 8-byte digest value is stored
+//! in big endian in the first 8 bytes of `RafsDigest`, and the remaining bytes are zero, so all
+//! digest values share the same on-disk layout.
 
 use std::convert::TryFrom;
 use std::fmt;
@@ -25,6 +30,14 @@ pub enum Algorithm {
     #[default]
     Blake3 = 0,
     Sha256 = 1,
+    Xxh64 = 2,
+}
+
+impl Algorithm {
+    /// Check whether the algorithm is a cryptographic hash function.
+    pub fn is_cryptographic(&self) -> bool {
+        *self != Algorithm::Xxh64
+    }
 }
 
 impl fmt::Display for Algorithm {
@@ -40,7 +53,10 @@ impl FromStr for Algorithm {
         match s {
             "blake3" => Ok(Self::Blake3),
             "sha256" => Ok(Self::Sha256),
-            _ => Err(einval!("digest algorithm should be blake3 or sha256")),
+            "xxh64" => Ok(Self::Xxh64),
+            _ => Err(einval!(
+                "digest algorithm should be blake3, sha256 or xxh64"
+            )),
         }
     }
 }
@@ -53,6 +69,8 @@ impl TryFrom<u32> for Algorithm {
             Ok(Algorithm::Sha256)
         } else if value == Algorithm::Blake3 as u32 {
             Ok(Algorithm::Blake3)
+        } else if value == Algorithm::Xxh64 as u32 {
+            Ok(Algorithm::Xxh64)
         } else {
             Err(())
         }
@@ -67,6 +85,8 @@ impl TryFrom<u64> for Algorithm {
             Ok(Algorithm::Sha256)
         } else if value == Algorithm::Blake3 as u64 {
             Ok(Algorithm::Blake3)
+        } else if value == Algorithm::Xxh64 as u64 {
+            Ok(Algorithm::Xxh64)
         } else {
             Err(())
         }
@@ -95,6 +115,7 @@ pub trait DigestHasher {
 pub enum RafsDigestHasher {
     Blake3(Box<blake3::Hasher>),
     Sha256(Sha256),
+    Xxh64(Xxh64),
 }
 
 impl DigestHasher for RafsDigestHasher {
@@ -106,6 +127,9 @@ impl DigestHasher for RafsDigestHasher {
             RafsDigestHasher::Sha256(hasher) => {
                 hasher.update(buf);
             }
+            RafsDigestHasher::Xxh64(hasher) => {
+                hasher.digest_update(buf);
+            }
         }
     }
 
@@ -113,6 +137,7 @@ impl DigestHasher for RafsDigestHasher {
         let data = match self {
             RafsDigestHasher::Blake3(hasher) => hasher.finalize().into(),
             RafsDigestHasher::Sha256(hasher) => hasher.finalize().into(),
+            RafsDigestHasher::Xxh64(hasher) => return hasher.digest_finalize(),
         };
 
         RafsDigest { data }
@@ -143,6 +168,45 @@ impl DigestHasher for Sha256 {
     }
 }
 
+/// Streaming hasher for the XXH64 algorithm with seed 0.
+#[derive(Clone, Default)]
+pub struct Xxh64(xxhash_rust::xxh64::Xxh64);
+
+impl Xxh64 {
+    /// Create a new instance of `Xxh64`.
+    pub fn new() -> Self {
+        Xxh64(xxhash_rust::xxh64::Xxh64::new(0))
+    }
+
+    /// Feed data into the hasher.
+    pub fn update(&mut self, input: &[u8]) {
+        self.0.update(input);
+    }
+
+    /// Get the 64-bit hash value of data fed so far.
+    pub fn finish(&self) -> u64 {
+        self.0.digest()
+    }
+}
+
+impl fmt::Debug for Xxh64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Xxh64").finish_non_exhaustive()
+    }
+}
+
+impl DigestHasher for Xxh64 {
+    fn digest_update(&mut self, buf: &[u8]) {
+        self.update(buf);
+    }
+
+    fn digest_finalize(self) -> RafsDigest {
+        let mut data = DigestData::default();
+        data[..8].copy_from_slice(&self.finish().to_be_bytes());
+        RafsDigest { data }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Default, Ord, PartialOrd)]
 pub struct RafsDigest {
@@ -158,6 +222,11 @@ impl RafsDigest {
                 hasher.update(buf);
                 hasher.finalize().into()
             }
+            Algorithm::Xxh64 => {
+                let mut hasher = Xxh64::new();
+                hasher.update(buf);
+                return hasher.digest_finalize();
+            }
         };
 
         RafsDigest { data }
@@ -192,6 +261,7 @@ impl RafsDigest {
         match algorithm {
             Algorithm::Blake3 => RafsDigestHasher::Blake3(Box::new(blake3::Hasher::new())),
             Algorithm::Sha256 => RafsDigestHasher::Sha256(Sha256::new()),
+            Algorithm::Xxh64 => RafsDigestHasher::Xxh64(Xxh64::new()),
         }
     }
 }
@@ -297,6 +367,32 @@ mod test {
         assert!(Algorithm::try_from(0xffff_abcd as u64).is_err());
     }
 
+    #[test]
+    fn test_xxh64() {
+        let hash = |buf: &[u8]| {
+            let mut hasher = Xxh64::new();
+            hasher.update(buf);
+            hasher.finish()
+        };
+        assert_eq!(hash(b""), 0xef46_db37_51d8_e999);
+        assert_eq!(hash(b"abc"), 0x44bc_2cf5_ad77_0999);
+        let text = b"The quick brown fox jumps over the lazy dog";
+        assert_eq!(hash(text), 0x0b24_2d36_1fda_71bc);
+
+        // Feeding data in pieces doesn't change the result.
+        let data: Vec<u8> = (0..1000u32).map(|v| (v % 251) as u8).collect();
+        let mut hasher = RafsDigest::hasher(Algorithm::Xxh64);
+        for piece in data.chunks(13) {
+            hasher.digest_update(piece);
+        }
+        let digest = hasher.digest_finalize();
+        assert_eq!(digest, RafsDigest::from_buf(&data, Algorithm::Xxh64));
+        assert_eq!(&digest.data[..8], &hash(&data).to_be_bytes());
+        assert!(digest.data[8..].iter().all(|v| *v == 0));
+        assert!(!Algorithm::Xxh64.is_cryptographic());
+        assert_eq!(Algorithm::from_str("xxh64").unwrap(), Algorithm::Xxh64);
+    }
+
     #[test]
     fn test_spec_hasher_new() {
         let text = b"The quick brown fox jumps ";
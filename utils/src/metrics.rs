@@ -606,6 +606,106 @@ impl Metric for BasicMetric {
     }
 }
 
+// Each power-of-2 range of latency values is split into 8 buckets, so values are tracked with
+// 12.5% precision. Values smaller than 16 are tracked exactly.
+const LATENCY_HISTOGRAM_SUB_BUCKETS: usize = 8;
+const LATENCY_HISTOGRAM_BUCKETS: usize = 16 + (64 - 4) * LATENCY_HISTOGRAM_SUB_BUCKETS;
+
+fn latency_histogram_index(value: u64) -> usize {
+    if value < 16 {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros() as usize;
+    let sub = (value >> (exp - 3)) as usize - LATENCY_HISTOGRAM_SUB_BUCKETS;
+    16 + (exp - 4) * LATENCY_HISTOGRAM_SUB_BUCKETS + sub
+}
+
+// Get the highest value falling into the bucket with index `index`.
+fn latency_histogram_value(index: usize) -> u64 {
+    if index < 16 {
+        return index as u64;
+    }
+    let exp = (index - 16) / LATENCY_HISTOGRAM_SUB_BUCKETS + 4;
+    let sub = ((index - 16) % LATENCY_HISTOGRAM_SUB_BUCKETS + LATENCY_HISTOGRAM_SUB_BUCKETS) as u64;
+    (sub << (exp - 3)) | ((1 << (exp - 3)) - 1)
+}
+
+/// Distribution of latency values, with buckets of exponentially growing sizes.
+#[derive(Debug)]
+pub struct LatencyHistogram {
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: (0..LATENCY_HISTOGRAM_BUCKETS)
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            count: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Record a latency value.
+    pub fn record(&self, value: u64) {
+        self.buckets[latency_histogram_index(value)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(value, Ordering::Relaxed);
+    }
+
+    /// Get the value below which `percentile` percent of recorded values fall.
+    ///
+    /// The highest value of the bucket containing the percentile is returned, limited by the
+    /// maximum recorded value.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        let count = self.count.load(Ordering::Relaxed);
+        if count == 0 {
+            return 0;
+        }
+        let rank = std::cmp::max(1, (count as f64 * percentile / 100.0).ceil() as u64);
+        let max = self.max.load(Ordering::Relaxed);
+        let mut total = 0;
+        for (idx, bucket) in self.buckets.iter().enumerate() {
+            total += bucket.load(Ordering::Relaxed);
+            if total >= rank {
+                return std::cmp::min(latency_histogram_value(idx), max);
+            }
+        }
+        max
+    }
+
+    /// Get a snapshot of the latency distribution.
+    pub fn stats(&self) -> LatencyStats {
+        LatencyStats {
+            count: self.count.load(Ordering::Relaxed),
+            p50: self.percentile(50.0),
+            p90: self.percentile(90.0),
+            p99: self.percentile(99.0),
+            max: self.max.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Snapshot of a latency distribution.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct LatencyStats {
+    /// Number of recorded values.
+    pub count: u64,
+    /// 50th percentile of recorded values.
+    pub p50: u64,
+    /// 90th percentile of recorded values.
+    pub p90: u64,
+    /// 99th percentile of recorded values.
+    pub p99: u64,
+    /// Maximum recorded value.
+    pub max: u64,
+}
+
 /// Metrics for storage backends.
 #[derive(Default, Serialize, Debug)]
 pub struct BackendMetrics {
@@ -626,6 +726,9 @@ pub struct BackendMetrics {
     read_count_block_size_dist: [BasicMetric; BLOCK_READ_SIZES_MAX],
     // Categorize metrics as per their latency and request size
     read_latency_sizes_dist: [[BasicMetric; READ_LATENCY_RANGE_MAX]; BLOCK_READ_SIZES_MAX],
+    // Distribution of chunk data read latency in unit of microsecond, recorded by blob caches.
+    #[serde(skip_serializing)]
+    read_latency_micros_hist: LatencyHistogram,
 }
 
 impl BackendMetrics {
@@ -680,6 +783,17 @@ impl BackendMetrics {
         }
     }
 
+    /// Record latency of reading chunk data from the backend.
+    pub fn record_read_latency(&self, duration: Duration) {
+        self.read_latency_micros_hist
+            .record(saturating_duration_micros(&duration));
+    }
+
+    /// Get distribution of chunk data read latency in unit of microsecond.
+    pub fn read_latency_stats(&self) -> LatencyStats {
+        self.read_latency_micros_hist.stats()
+    }
+
     fn export_metrics(&self) -> IoStatsResult<String> {
         serde_json::to_string(self).map_err(MetricsError::Serialize)
     }
@@ -751,6 +865,8 @@ pub struct BlobcacheMetrics {
     // The time milliseconds part when nydusd ends prefetching
     pub prefetch_end_time_millis: BasicMetric,
     pub buffered_backend_size: BasicMetric,
+    // Number of chunks served to users even though their data fails digest validation.
+    pub digest_mismatch_served: BasicMetric,
     pub data_all_ready: AtomicBool,
 }
 
@@ -876,6 +992,33 @@ mod tests {
         assert_eq!(latency_micros_range_index(3_000_000), 7);
     }
 
+    #[test]
+    fn test_latency_histogram() {
+        for value in [0u64, 15, 16, 17, 100, 1_000_000, u64::MAX] {
+            let idx = latency_histogram_index(value);
+            assert!(idx < LATENCY_HISTOGRAM_BUCKETS);
+            assert!(latency_histogram_value(idx) >= value);
+            assert!(idx == 0 || latency_histogram_value(idx - 1) < value);
+        }
+
+        let hist = LatencyHistogram::default();
+        assert_eq!(hist.stats(), LatencyStats::default());
+        for _ in 0..90 {
+            hist.record(1_000);
+        }
+        for _ in 0..9 {
+            hist.record(20_000);
+        }
+        hist.record(300_000);
+        let stats = hist.stats();
+        assert_eq!(stats.count, 100);
+        assert!(stats.p50 >= 1_000 && stats.p50 < 1_125);
+        assert!(stats.p90 >= 1_000 && stats.p90 < 1_125);
+        assert!(stats.p99 >= 20_000 && stats.p99 < 22_500);
+        assert_eq!(stats.max, 300_000);
+        assert_eq!(hist.percentile(100.0), 300_000);
+    }
+
     #[test]
     fn test_inode_stats() {
         let stat = InodeIoStats::default();
@@ -54,6 +54,28 @@ impl<T> Channel<T> {
         }
     }
 
+    /// Send a message to the channel, ahead of the first pending message matching the predicator.
+    ///
+    /// Pending messages keep their order, so it may serve as a priority queue by matching pending
+    /// messages with lower priority.
+    pub fn send_ahead<F>(&self, msg: T, f: F) -> std::result::Result<(), T>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        if self.closed.load(Ordering::Acquire) {
+            Err(msg)
+        } else {
+            let mut requests = self.requests.lock().unwrap();
+            match requests.iter().position(f) {
+                Some(idx) => requests.insert(idx, msg),
+                None => requests.push_back(msg),
+            }
+            drop(requests);
+            self.notifier.notify_one();
+            Ok(())
+        }
+    }
+
     /// Try to receive a message from the channel.
     pub fn try_recv(&self) -> Option<T> {
         self.requests.lock().unwrap().pop_front()
@@ -123,6 +145,25 @@ mod tests {
         channel.send(2u32).unwrap_err();
     }
 
+    #[test]
+    fn test_send_ahead() {
+        let channel = Channel::new();
+
+        channel.send(1u32).unwrap();
+        channel.send(2u32).unwrap();
+        channel.send_ahead(5u32, |v| *v < 5).unwrap();
+        channel.send_ahead(4u32, |v| *v < 4).unwrap();
+        channel.send_ahead(6u32, |v| *v > 6).unwrap();
+        assert_eq!(channel.try_recv().unwrap(), 5);
+        assert_eq!(channel.try_recv().unwrap(), 4);
+        assert_eq!(channel.try_recv().unwrap(), 1);
+        assert_eq!(channel.try_recv().unwrap(), 2);
+        assert_eq!(channel.try_recv().unwrap(), 6);
+
+        channel.close();
+        channel.send_ahead(2u32, |_| true).unwrap_err();
+    }
+
     #[test]
     fn test_flush_channel() {
         let channel = Channel::new();